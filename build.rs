@@ -1,5 +1,26 @@
-#[cfg(windows)]
 fn main() {
+    git_hash();
+    windows_resources();
+}
+
+/// 把当前 git 提交写入 `BPB_GIT_HASH`，显示在“关于”面板中；不在 git 仓库中时跳过
+fn git_hash() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--short=10", "HEAD"])
+        .output();
+    if let Ok(output) = output
+        && output.status.success()
+    {
+        let hash = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=BPB_GIT_HASH={}", hash.trim());
+    }
+}
+
+#[cfg(windows)]
+fn windows_resources() {
     if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
         return;
     }
@@ -13,4 +34,4 @@ fn main() {
 }
 
 #[cfg(not(windows))]
-fn main() {}
+fn windows_resources() {}
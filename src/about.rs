@@ -0,0 +1,70 @@
+//! “关于”面板：工具版本、构建信息与运行环境，反馈问题时可以一次复制全部内容
+
+use std::path::PathBuf;
+
+use crate::onboarding::Onboarding;
+use crate::profiles::Profiles;
+use crate::{config, crash, dirs, platform, steam};
+
+/// 构建时由 build.rs 写入的 git 提交；不在 git 仓库中构建时没有
+const GIT_HASH: Option<&str> = option_env!("BPB_GIT_HASH");
+
+/// 依次显示的（名称, 内容）
+pub fn collect() -> Vec<(&'static str, String)> {
+    let features: Vec<&str> = [
+        ("cli", cfg!(feature = "cli")),
+        ("gui", cfg!(feature = "gui")),
+        ("online", cfg!(feature = "online")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+
+    let steam_roots = steam::steam_roots();
+    let steam_roots = if steam_roots.is_empty() {
+        "未找到".to_string()
+    } else {
+        steam_roots
+            .iter()
+            .map(|root| root.display().to_string())
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+
+    vec![
+        ("版本", env!("CARGO_PKG_VERSION").to_string()),
+        ("提交", GIT_HASH.unwrap_or("未知").to_string()),
+        ("功能", features.join(", ")),
+        (
+            "平台",
+            format!("{} {}", platform::name(), std::env::consts::ARCH),
+        ),
+        (
+            "便携模式",
+            if config::is_portable() { "是" } else { "否" }.to_string(),
+        ),
+        ("Steam 目录", steam_roots),
+        ("设置文件", display(config::config_path())),
+        ("使用须知记录", display(Onboarding::path())),
+        ("安装配置", display(Profiles::path())),
+        ("崩溃日志", display(crash::crash_log_path())),
+        ("数据目录", display(dirs::data_dir())),
+        ("缓存目录", display(dirs::cache_dir())),
+    ]
+}
+
+/// 复制到剪贴板的纯文本
+pub fn to_text(items: &[(&'static str, String)]) -> String {
+    items
+        .iter()
+        .map(|(name, value)| format!("{}: {}\n", name, value))
+        .collect()
+}
+
+fn display(path: Option<PathBuf>) -> String {
+    match path {
+        Some(path) if path.exists() => path.display().to_string(),
+        Some(path) => format!("{}（不存在）", path.display()),
+        None => "未知".to_string(),
+    }
+}
@@ -0,0 +1,44 @@
+//! 只读打开用于比较的 PCK：本地文件，或在启用 `online` 特性时通过 HTTP Range 请求访问的远程文件
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// 远程读取时每次请求的最小字节数
+#[cfg(feature = "online")]
+const REMOTE_READ_AHEAD: usize = 256 * 1024;
+
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub fn is_remote(location: &Path) -> bool {
+    location
+        .to_str()
+        .is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+pub fn open(location: &Path) -> Result<Box<dyn ReadSeek>> {
+    if is_remote(location) {
+        return open_remote(&location.to_string_lossy());
+    }
+    let file =
+        File::open(location).with_context(|| format!("无法打开文件: {}", location.display()))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+#[cfg(feature = "online")]
+fn open_remote(url: &str) -> Result<Box<dyn ReadSeek>> {
+    let reader = crate::remote::HttpRangeReader::open(url)?;
+    Ok(Box::new(BufReader::with_capacity(
+        REMOTE_READ_AHEAD,
+        reader,
+    )))
+}
+
+#[cfg(not(feature = "online"))]
+fn open_remote(url: &str) -> Result<Box<dyn ReadSeek>> {
+    anyhow::bail!("读取远程 PCK 需要启用 online 特性编译: {}", url)
+}
@@ -0,0 +1,145 @@
+//! 调试构建专用的 GUI 脚本模式，用于界面冒烟测试。
+//! 设置 `BPB_ENHANCE_GUI_SCRIPT` 指向脚本文件后，窗口启动时按行执行脚本，结果写到 `BPB_ENHANCE_GUI_RESULTS`（未设置时输出到 stdout）。
+//!
+//! 脚本语法（`#` 开头为注释）：
+//! - `set-path <路径>`：填写游戏路径
+//! - `click apply`：点击“应用”
+//! - `expect success|error [文本]`：检查最近一次结果的类型及是否包含文本
+//! - `quit`：退出，有 expect 失败时退出码为 1
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+const SCRIPT_ENV: &str = "BPB_ENHANCE_GUI_SCRIPT";
+const RESULTS_ENV: &str = "BPB_ENHANCE_GUI_RESULTS";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    SetPath(String),
+    ClickApply,
+    Expect(OutcomeKind, Option<String>),
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutcomeKind {
+    Success,
+    Error,
+}
+
+impl OutcomeKind {
+    fn label(self) -> &'static str {
+        match self {
+            OutcomeKind::Success => "success",
+            OutcomeKind::Error => "error",
+        }
+    }
+}
+
+/// 一次脚本运行的状态
+#[derive(Debug)]
+pub struct Automation {
+    steps: Vec<Step>,
+    results: Option<PathBuf>,
+    last_outcome: Option<(OutcomeKind, String)>,
+    failed: bool,
+}
+
+impl Automation {
+    /// 读取环境变量指定的脚本；未设置时返回 None
+    pub fn from_env() -> Option<Result<Self>> {
+        if !cfg!(debug_assertions) {
+            return None;
+        }
+        let script = std::env::var_os(SCRIPT_ENV)?;
+        Some(
+            std::fs::read_to_string(&script)
+                .with_context(|| format!("无法读取 GUI 脚本: {}", PathBuf::from(&script).display()))
+                .and_then(|content| parse_script(&content))
+                .map(|steps| Self {
+                    steps,
+                    results: std::env::var_os(RESULTS_ENV).map(PathBuf::from),
+                    last_outcome: None,
+                    failed: false,
+                }),
+        )
+    }
+
+    pub fn take_steps(&mut self) -> Vec<Step> {
+        std::mem::take(&mut self.steps)
+    }
+
+    /// 记录界面上出现的通知或对话框
+    pub fn record_outcome(&mut self, kind: OutcomeKind, message: &str) {
+        self.log(&format!("{}\t{}", kind.label(), message.replace('\n', " ")));
+        self.last_outcome = Some((kind, message.to_string()));
+    }
+
+    pub fn check(&mut self, kind: OutcomeKind, text: Option<&str>) {
+        let passed = self.last_outcome.as_ref().is_some_and(|(last, message)| {
+            *last == kind && text.is_none_or(|t| message.contains(t))
+        });
+        if !passed {
+            self.failed = true;
+            self.log(&format!(
+                "FAIL\texpect {} {}",
+                kind.label(),
+                text.unwrap_or_default()
+            ));
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        i32::from(self.failed)
+    }
+
+    fn log(&self, line: &str) {
+        let written = self.results.as_ref().is_some_and(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .is_ok()
+        });
+        if !written {
+            println!("{}", line);
+        }
+    }
+}
+
+fn parse_script(content: &str) -> Result<Vec<Step>> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| parse_step(line).with_context(|| format!("第 {} 行", number)))
+        .collect()
+}
+
+fn parse_step(line: &str) -> Result<Step> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+
+    Ok(match command {
+        "set-path" => Step::SetPath(rest.to_string()),
+        "click" if rest == "apply" => Step::ClickApply,
+        "click" => bail!("未知的按钮: {}", rest),
+        "expect" => {
+            let (kind, text) = rest.split_once(' ').unwrap_or((rest, ""));
+            let kind = match kind {
+                "success" => OutcomeKind::Success,
+                "error" => OutcomeKind::Error,
+                other => return Err(anyhow!("expect 只支持 success 或 error: {}", other)),
+            };
+            let text = text.trim();
+            Step::Expect(kind, (!text.is_empty()).then(|| text.to_string()))
+        }
+        "quit" => Step::Quit,
+        other => bail!("未知的命令: {}", other),
+    })
+}
@@ -0,0 +1,464 @@
+//! 修改前的 PCK 备份：每次修改前把 PCK 复制为 `<文件名>.bak-<时间>`（UTC，如
+//! `BackpackBattles.pck.bak-20261015-054200`），默认放在 PCK 所在目录，可以用设置中的
+//! `[backup] dir` 改放到其他位置。只保留最近的 `[backup] keep` 份（默认 3 份），
+//! 最新的一份即最近一次修改之前的状态。旧版本留下的 `<文件名>.bak` 视为最早的一份，不会被自动删除。
+//!
+//! 每份备份旁的 `.md5` 文件记录备份的大小与 MD5，以及可选的标签（如 [`PRE_EXISTING_LABEL`]）。
+//! 恢复前先核对它并抽查备份中的 entry，备份损坏时拒绝恢复，避免用残缺的“原版”覆盖能正常运行的 PCK。
+//!
+//! 设置了 `[backup] compression_level` 时备份以 gzip 压缩写入，文件名多一个 `.gz`，
+//! 校验文件记录的是压缩后的文件；恢复时先解压到临时文件，抽查解压出的 PCK 后再替换。
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::config::BackupConfig;
+use crate::{dirs, journal, pck, pending, provenance};
+
+/// 恢复前抽查的 entry 比例
+const SPOT_CHECK_PERCENT: f64 = 10.0;
+/// 未设置 `[backup] keep` 时保留的份数
+const DEFAULT_KEEP: usize = 3;
+/// 修改前检测到其他工具的修改时（见 [`crate::foreign`]）给备份加的标签：备份中不是原版
+pub const PRE_EXISTING_LABEL: &str = "pre-existing modifications";
+/// 压缩备份的扩展名
+const COMPRESSED_SUFFIX: &str = ".gz";
+
+/// 旧版本使用的固定备份路径 `<文件名>.bak`
+fn legacy_path(pck: &Path) -> PathBuf {
+    let mut name = pck.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    pck.with_file_name(name)
+}
+
+fn backup_dir(pck: &Path, config: &BackupConfig) -> PathBuf {
+    match &config.dir {
+        Some(dir) if dir.is_relative() => dirs::data_dir().unwrap_or_default().join(dir),
+        Some(dir) => dir.clone(),
+        None => pck.parent().map(Path::to_path_buf).unwrap_or_default(),
+    }
+}
+
+/// 备份的校验文件路径
+fn digest_path(backup: &Path) -> PathBuf {
+    let mut path = backup.to_path_buf().into_os_string();
+    path.push(".md5");
+    PathBuf::from(path)
+}
+
+/// 备份名中的时间部分排序用的键：`20261015-054200`，同一秒内的后续备份为 `20261015-054200-2`；
+/// 不是备份的文件（校验文件、未完成的临时文件）返回 None
+fn stamp_key(stamp: &str) -> Option<(&str, u32)> {
+    let stamp = stamp.strip_suffix(COMPRESSED_SUFFIX).unwrap_or(stamp);
+    let (time, counter) = match stamp.get(15..)? {
+        "" => (stamp, 1),
+        rest => (&stamp[..15], rest.strip_prefix('-')?.parse().ok()?),
+    };
+    let valid = time
+        .bytes()
+        .enumerate()
+        .all(|(i, b)| if i == 8 { b == b'-' } else { b.is_ascii_digit() });
+    valid.then_some((time, counter))
+}
+
+/// `pck` 的全部备份，从旧到新
+pub fn list(pck: &Path, config: &BackupConfig) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}.bak-", pck.file_name().unwrap_or_default().to_string_lossy());
+    let dir = backup_dir(pck, config);
+    let mut stamped = Vec::new();
+    match fs::read_dir(&dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry.with_context(|| format!("无法读取目录: {}", dir.display()))?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if let Some(stamp) = name.strip_prefix(&prefix)
+                    && let Some((time, counter)) = stamp_key(stamp)
+                {
+                    stamped.push(((time.to_string(), counter), entry.path()));
+                }
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("无法读取目录: {}", dir.display()));
+        }
+    }
+    stamped.sort();
+
+    let legacy = legacy_path(pck);
+    Ok(legacy
+        .is_file()
+        .then_some(legacy)
+        .into_iter()
+        .chain(stamped.into_iter().map(|(_, path)| path))
+        .collect())
+}
+
+/// 是否是以 gzip 压缩的备份
+fn is_compressed(backup: &Path) -> bool {
+    backup
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(COMPRESSED_SUFFIX))
+}
+
+/// 创建备份时记录的标签
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub fn label(backup: &Path) -> Option<String> {
+    let recorded = fs::read_to_string(digest_path(backup)).ok()?;
+    let label = recorded.lines().nth(1)?.trim();
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+/// 最近一次修改之前的备份
+pub fn latest(pck: &Path, config: &BackupConfig) -> Result<Option<PathBuf>> {
+    Ok(list(pck, config)?.pop())
+}
+
+/// 备份 `pck`，返回备份路径；先写临时文件再改名，复制中断时不会留下残缺的备份。
+/// 成功后删除超出保留份数的旧备份
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub fn create(pck: &Path, config: &BackupConfig) -> Result<PathBuf> {
+    create_labeled(pck, config, None)
+}
+
+/// 与 [`create`] 相同，`label` 记在校验文件中，列出备份时显示
+pub fn create_labeled(pck: &Path, config: &BackupConfig, label: Option<&str>) -> Result<PathBuf> {
+    let dir = backup_dir(pck, config);
+    if !dir.as_os_str().is_empty() {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("无法创建备份目录: {}", dir.display()))?;
+    }
+    let name = pck.file_name().unwrap_or_default().to_string_lossy();
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    // 2026-10-15 05:42:00 UTC -> 20261015-054200
+    let stamp: String = provenance::format_timestamp(secs)
+        .trim_end_matches(" UTC")
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_ascii_digit() => Some(c),
+            _ => None,
+        })
+        .collect();
+    let suffix = match config.compression_level {
+        Some(_) => COMPRESSED_SUFFIX,
+        None => "",
+    };
+    let mut backup = dir.join(format!("{}.bak-{}{}", name, stamp, suffix));
+    let mut counter = 1;
+    while backup.exists() {
+        counter += 1;
+        backup = dir.join(format!("{}.bak-{}-{}{}", name, stamp, counter, suffix));
+    }
+
+    let mut partial = backup.clone().into_os_string();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let copied = match config.compression_level {
+        Some(level) => compress(pck, &partial, level),
+        None => fs::copy(pck, &partial).map(drop),
+    };
+    copied.with_context(|| {
+        let _ = fs::remove_file(&partial);
+        format!("无法备份 {} 到 {}", pck.display(), partial.display())
+    })?;
+    let (size, digest) = file_digest(&partial).inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })?;
+    // 先写校验文件再改名：之后中断时只会留下没有备份的校验文件，不会出现没有校验文件的新备份
+    let digest_file = digest_path(&backup);
+    let label = label.map(|label| format!("{}\n", label)).unwrap_or_default();
+    fs::write(&digest_file, format!("{} {}\n{}", digest, size, label))
+        .with_context(|| format!("无法写入备份校验文件: {}", digest_file.display()))?;
+    fs::rename(&partial, &backup).with_context(|| {
+        let _ = fs::remove_file(&partial);
+        let _ = fs::remove_file(&digest_file);
+        format!("无法写入备份: {}", backup.display())
+    })?;
+
+    prune(pck, config)?;
+    Ok(backup)
+}
+
+/// 删除超出保留份数的旧备份；旧版本的 `.bak` 不计入也不删除
+fn prune(pck: &Path, config: &BackupConfig) -> Result<()> {
+    let keep = config.keep.unwrap_or(DEFAULT_KEEP);
+    let legacy = legacy_path(pck);
+    let stamped: Vec<PathBuf> = list(pck, config)?
+        .into_iter()
+        .filter(|path| *path != legacy)
+        .collect();
+    for old in &stamped[..stamped.len().saturating_sub(keep)] {
+        fs::remove_file(old).with_context(|| format!("无法删除旧备份: {}", old.display()))?;
+        let _ = fs::remove_file(digest_path(old));
+    }
+    Ok(())
+}
+
+/// 检查备份是否完好：与记录的大小和 MD5 一致、文件头与文件表可读、抽查的 entry 数据无误。
+/// 没有校验文件的旧备份只做后两项检查；压缩的备份以完整解压一遍代替后两项
+pub fn verify(backup: &Path) -> Result<()> {
+    check_digest(backup)?;
+    if is_compressed(backup) {
+        // gzip 自带 CRC32，解压不出错即内容完好
+        let file =
+            File::open(backup).with_context(|| format!("无法打开备份: {}", backup.display()))?;
+        io::copy(&mut GzDecoder::new(BufReader::new(file)), &mut io::sink())
+            .context("备份已损坏：无法解压")?;
+        return Ok(());
+    }
+    check_contents(backup)
+}
+
+/// 与校验文件记录的大小和 MD5 核对；没有校验文件时跳过
+fn check_digest(backup: &Path) -> Result<()> {
+    if !backup.is_file() {
+        bail!("找不到备份: {}", backup.display());
+    }
+
+    let digest_file = digest_path(backup);
+    match fs::read_to_string(&digest_file) {
+        Ok(recorded) => {
+            let (expected_digest, expected_size) = recorded
+                .lines()
+                .next()
+                .and_then(|line| line.split_once(' '))
+                .and_then(|(digest, size)| Some((digest, size.trim().parse::<u64>().ok()?)))
+                .with_context(|| format!("备份校验文件格式无效: {}", digest_file.display()))?;
+            let (size, digest) = file_digest(backup)?;
+            if size != expected_size {
+                bail!(
+                    "备份已损坏：大小为 {} 字节，备份时为 {} 字节",
+                    size,
+                    expected_size
+                );
+            }
+            if digest != expected_digest {
+                bail!("备份已损坏：MD5 与备份时记录的不一致");
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("无法读取备份校验文件: {}", digest_file.display()));
+        }
+    }
+    Ok(())
+}
+
+/// 文件头与文件表可读、抽查的 entry 数据无误
+fn check_contents(backup: &Path) -> Result<()> {
+    let file = File::open(backup).with_context(|| format!("无法打开备份: {}", backup.display()))?;
+    pck::read_entries(&mut BufReader::new(file), None, pck::TableLimits::DEFAULT)
+        .context("备份已损坏：无法读取文件表")?;
+
+    let options = pck::VerifyOptions {
+        sample_percent: SPOT_CHECK_PERCENT,
+        jobs: 1,
+        fail_fast: true,
+        ..pck::VerifyOptions::default()
+    };
+    let report = pck::verify_pck(backup, &options).context("备份已损坏：无法抽查文件数据")?;
+    if let Some(failure) = report.failures.first() {
+        bail!("备份已损坏：{} {}", failure.path, failure.reason);
+    }
+    Ok(())
+}
+
+/// 用最近一次的备份覆盖 `pck`，返回使用的备份
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub fn restore(pck: &Path, config: &BackupConfig) -> Result<PathBuf> {
+    let backup = latest(pck, config)?
+        .with_context(|| format!("找不到 {} 的备份", pck.display()))?;
+    restore_from(pck, &backup)?;
+    Ok(backup)
+}
+
+/// 用指定的备份覆盖 `pck`；先校验备份，损坏时不做任何修改
+pub fn restore_from(pck: &Path, backup: &Path) -> Result<()> {
+    let refuse = || format!("拒绝用 {} 恢复", backup.display());
+    let mut partial = pck.to_path_buf().into_os_string();
+    partial.push(".restore.partial");
+    let partial = PathBuf::from(partial);
+
+    if is_compressed(backup) {
+        check_digest(backup).with_context(refuse)?;
+        decompress(backup, &partial)
+            .and_then(|()| check_contents(&partial))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&partial);
+            })
+            .with_context(refuse)?;
+    } else {
+        verify(backup).with_context(refuse)?;
+        fs::copy(backup, &partial)
+            .with_context(|| format!("无法复制备份到 {}", partial.display()))?;
+    }
+    fs::rename(&partial, pck).with_context(|| {
+        let _ = fs::remove_file(&partial);
+        format!("无法覆盖 {}，请确认游戏已关闭", pck.display())
+    })?;
+    // 恢复后的 PCK 不再对应暂存或中断时的状态，未提交的暂存修改与回滚日志随之作废
+    let _ = fs::remove_file(pending::journal_path(pck));
+    let _ = fs::remove_file(journal::journal_path(pck));
+    Ok(())
+}
+
+/// 以 gzip 级别 `level` 把 `source` 压缩写入 `target`
+fn compress(source: &Path, target: &Path, level: u32) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(source)?);
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(target)?),
+        Compression::new(level),
+    );
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?.flush()
+}
+
+/// 把压缩的备份解压到 `target`
+fn decompress(backup: &Path, target: &Path) -> Result<()> {
+    let file = File::open(backup).with_context(|| format!("无法打开备份: {}", backup.display()))?;
+    let mut writer = BufWriter::new(
+        File::create(target).with_context(|| format!("无法写入: {}", target.display()))?,
+    );
+    io::copy(&mut GzDecoder::new(BufReader::new(file)), &mut writer)
+        .context("备份已损坏：无法解压")?;
+    writer
+        .flush()
+        .with_context(|| format!("无法写入: {}", target.display()))
+}
+
+/// 文件大小与十六进制 MD5
+fn file_digest(path: &Path) -> Result<(u64, String)> {
+    let file = File::open(path).with_context(|| format!("无法打开: {}", path.display()))?;
+    let (md5, size) =
+        pck::digest_reader(file).with_context(|| format!("无法读取: {}", path.display()))?;
+    Ok((size, format!("{:x}", md5::Digest(md5))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_most_recent_timestamped_backups() {
+        let dir = std::env::temp_dir().join(format!("bpb_backup_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        fs::write(legacy_path(&pck), b"legacy").unwrap();
+        let config = BackupConfig {
+            dir: Some(dir.join("backups")),
+            keep: Some(2),
+            ..BackupConfig::default()
+        };
+
+        let mut created = Vec::new();
+        for data in ["first", "second", "third"] {
+            fs::write(&pck, data).unwrap();
+            created.push(create(&pck, &config).unwrap());
+        }
+        let name = created[0].file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("Game.pck.bak-"), "{}", name);
+        assert!(created[0].starts_with(dir.join("backups")));
+
+        // 最早的一份已删除，旧版本的 .bak 保留并排在最前
+        assert!(!created[0].exists());
+        assert_eq!(
+            list(&pck, &config).unwrap(),
+            [legacy_path(&pck), created[1].clone(), created[2].clone()]
+        );
+        let latest = latest(&pck, &config).unwrap().unwrap();
+        assert_eq!(fs::read(latest).unwrap(), b"third");
+        // 两份备份与各自的校验文件
+        assert_eq!(fs::read_dir(dir.join("backups")).unwrap().count(), 4);
+
+        assert_eq!(stamp_key("20261015-054200"), Some(("20261015-054200", 1)));
+        assert_eq!(stamp_key("20261015-054200-12"), Some(("20261015-054200", 12)));
+        assert_eq!(stamp_key("20261015-054200.md5"), None);
+        assert_eq!(stamp_key("20261015-054200.partial"), None);
+        assert_eq!(stamp_key("20261015-054200.gz"), Some(("20261015-054200", 1)));
+        assert_eq!(stamp_key("20261015-054200.gz.md5"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_restore_a_damaged_backup() {
+        let dir = std::env::temp_dir().join(format!("bpb_restore_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        let source = dir.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a.txt"), b"original").unwrap();
+        pck::pack_directory(&source, &pck, &pck::PackOptions::default()).unwrap();
+        let original = fs::read(&pck).unwrap();
+
+        let config = BackupConfig::default();
+        let backup = create_labeled(&pck, &config, Some(PRE_EXISTING_LABEL)).unwrap();
+        assert_eq!(label(&backup).as_deref(), Some(PRE_EXISTING_LABEL));
+        fs::write(&pck, b"patched").unwrap();
+        assert_eq!(restore(&pck, &config).unwrap(), backup);
+        assert_eq!(fs::read(&pck).unwrap(), original);
+
+        // 翻转备份末尾的一个字节：MD5 不再匹配，PCK 保持不变
+        fs::write(&pck, b"patched").unwrap();
+        let mut damaged = original.clone();
+        *damaged.last_mut().unwrap() ^= 0xff;
+        fs::write(&backup, &damaged).unwrap();
+        let err = restore(&pck, &config).unwrap_err();
+        assert!(format!("{:#}", err).contains("备份已损坏"));
+        assert_eq!(fs::read(&pck).unwrap(), b"patched");
+
+        // 没有校验文件时仍会抽查 entry 数据
+        fs::remove_file(digest_path(&backup)).unwrap();
+        assert!(verify(&backup).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compressed_backups_restore_transparently() {
+        let dir = std::env::temp_dir().join(format!("bpb_backup_gz_{}", std::process::id()));
+        let source = dir.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a.txt"), "compressible ".repeat(1000)).unwrap();
+        let pck = dir.join("Game.pck");
+        pck::pack_directory(&source, &pck, &pck::PackOptions::default()).unwrap();
+        let original = fs::read(&pck).unwrap();
+
+        let config = BackupConfig {
+            compression_level: Some(6),
+            ..BackupConfig::default()
+        };
+        let backup = create(&pck, &config).unwrap();
+        assert!(is_compressed(&backup));
+        assert!(fs::metadata(&backup).unwrap().len() < original.len() as u64 / 4);
+        assert_eq!(latest(&pck, &config).unwrap(), Some(backup.clone()));
+        verify(&backup).unwrap();
+
+        fs::write(&pck, b"patched").unwrap();
+        assert_eq!(restore(&pck, &config).unwrap(), backup);
+        assert_eq!(fs::read(&pck).unwrap(), original);
+
+        // 截断的压缩备份在解压时被发现，PCK 保持不变
+        fs::write(&pck, b"patched").unwrap();
+        fs::remove_file(digest_path(&backup)).unwrap();
+        let compressed = fs::read(&backup).unwrap();
+        fs::write(&backup, &compressed[..compressed.len() / 2]).unwrap();
+        assert!(verify(&backup).is_err());
+        assert!(restore(&pck, &config).is_err());
+        assert_eq!(fs::read(&pck).unwrap(), b"patched");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
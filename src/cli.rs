@@ -0,0 +1,2258 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use bpb_enhance::pck::DiagnosticsSink;
+use bpb_enhance::pool::{CancelToken, Pool};
+
+use crate::manifest::OptionSelection;
+use crate::mapping::{self, PathMapping};
+use crate::progress::json_string;
+use crate::provenance::{self, ProvenanceStore};
+use crate::rebase::RebaseStatus;
+use crate::report::{EntryChange, EntryDigest};
+use crate::res_type::{self, ResType};
+use crate::{
+    backup, compression, config, depot, diff, explain, foreign, import_pairs, journal, lint, merge,
+    overlay, pck, pending, platform, progress, rebase, scaffold, stress, transfer, tweak,
+    tweak_pack, zip,
+};
+#[cfg(feature = "mount")]
+use crate::mount;
+
+#[derive(Debug, Parser)]
+#[command(name = "bpb_enhance")]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    apply: ApplyArgs,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Keep all tool data next to the executable (same as a portable.flag file there)"
+    )]
+    portable: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Disable the entry-count and path-length safety limits for legitimately huge PCKs"
+    )]
+    force_limits_off: bool,
+
+    #[arg(
+        short,
+        long,
+        visible_alias = "threads",
+        global = true,
+        value_name = "N|auto",
+        help = "Threads used by parallel work such as hashing and verification (default: `threads` in bpb_enhance.toml, auto = logical cores)"
+    )]
+    jobs: Option<config::Threads>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "HEX",
+        help = "AES-256 key the game was exported with (64 hex characters), needed for encrypted Godot 4 PCKs"
+    )]
+    encryption_key: Option<pck::EncryptionKey>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value = "text",
+        help = "Also report progress as JSON event lines on stderr (json)"
+    )]
+    progress: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PIPE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = progress::DEFAULT_PIPE,
+        help = "Also write progress events to a named pipe created by a frontend (default: \\\\.\\pipe\\bpb_enhance)"
+    )]
+    progress_pipe: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Apply the assets in replace.toml to a PCK file (default when no subcommand is given)
+    Apply(ApplyArgs),
+    /// Discard changes staged with apply --stage, truncating the data they appended
+    Abort(PendingArgs),
+    /// Estimate how much large uncompressed entries would shrink if stored compressed
+    AnalyzeCompression(AnalyzeCompressionArgs),
+    /// Check whether the installed game still matches what Steam downloaded, before patching
+    CheckInstall(CheckInstallArgs),
+    /// Activate changes staged with apply --stage by writing their header and entry table
+    Commit(PendingArgs),
+    /// Reclaim space left behind by replaced and deleted entries
+    Compact(CompactArgs),
+    /// Compare two PCK files entry by entry, optionally exporting the difference as a mod
+    Diff(DiffArgs),
+    /// Split a PCK file into checksummed chunks and a manifest for moving it to another PC
+    ExportTransfer(ExportTransferArgs),
+    /// Write every entry of a PCK file to a .zip archive, keeping their res:// layout
+    ExportZip(ExportZipArgs),
+    /// Write entries of a PCK file to a folder, keeping their res:// layout
+    Extract(ExtractArgs),
+    /// Verify chunks written by export-transfer and reassemble them into a PCK file
+    ImportTransfer(ImportTransferArgs),
+    /// Write the files of a .zip into a PCK file, mapping each ZIP path to res://<path>
+    ImportZip(ImportZipArgs),
+    /// List the entries of a PCK file
+    List(ListArgs),
+    /// Validate a replace.toml manifest without touching any PCK
+    LintManifest(LintManifestArgs),
+    /// Apply every entry of a mod's PCK on top of a game PCK
+    Merge(MergeArgs),
+    /// Mount a PCK as a read-only folder to browse res:// with ordinary tools (Linux)
+    #[cfg(feature = "mount")]
+    Mount(MountArgs),
+    /// Scaffold a new mod folder with replace.toml, README and example rules
+    NewMod(NewModArgs),
+    /// Build a new PCK from a folder, optionally embedded into an executable
+    Pack(PackArgs),
+    /// Port a mod built for one game version onto another
+    Rebase(RebaseArgs),
+    /// Rename entries inside a PCK according to a mapping file
+    Remap(RemapArgs),
+    /// Detect and fix structural problems in a PCK file
+    Repair(RepairArgs),
+    /// Restore a PCK file from its latest backup after checking the backup is intact
+    Restore(RestoreArgs),
+    /// Soak-test the write path with randomized cycles on a scratch copy of a PCK (maintainers)
+    #[command(hide = true)]
+    Stress(StressArgs),
+    /// Write tweak_pack.toml (version and MD5 of every file) for the asset pack the GUI applies
+    TweakPack(TweakPackArgs),
+    /// Recompute entry MD5s and compare them with the PCK's file table
+    Verify(VerifyArgs),
+}
+
+#[derive(Debug, Args)]
+struct ApplyArgs {
+    #[arg(short, long, required = true, help = "Path to the PCK file")]
+    pck: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Path to the assets folder containing replace.toml"
+    )]
+    assets: Option<String>,
+
+    #[arg(
+        long,
+        help = "Write a patch report after applying (.html for HTML, otherwise Markdown)"
+    )]
+    report: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Backend::InPlace,
+        help = "How to apply the mod"
+    )]
+    backend: Backend,
+
+    #[arg(
+        long,
+        default_value_t = overlay::DEFAULT_PRIORITY,
+        help = "Load order of the override pack; higher loads later and wins (overlay backend)"
+    )]
+    priority: u16,
+
+    #[arg(
+        long,
+        value_name = "CSV",
+        help = "Mapping of obfuscated paths to readable names used in replace.toml"
+    )]
+    map: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "ZIP",
+        help = "If the apply fails, write a diagnostics bundle for bug reports to this file"
+    )]
+    explain: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "warn",
+        help = "Re-hash entries moved out of the way of the growing table and warn about, or repair, stale MD5s (default: [io] verify_moves)"
+    )]
+    verify_moves: Option<VerifyMoves>,
+
+    #[arg(
+        long,
+        help = "Only replace the contents of existing entries; refuse mods that add or delete files, so the entry table never grows and no data is moved"
+    )]
+    safe_mode: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "output",
+        help = "Patch a temporary copy next to the PCK and rename it over the original once it is written to disk, so an interrupted apply never leaves a half-written PCK"
+    )]
+    atomic: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "atomic"],
+        help = "Append the new data to the PCK but keep the new header and entry table in <name>.pending until `commit` (or `abort`), so the game sees no change until then"
+    )]
+    stage: bool,
+
+    #[arg(
+        long,
+        help = "Delete entries listed under delete by setting their removal flag instead of shrinking the entry table and truncating the file (Godot 4.4+ v2 packs)"
+    )]
+    mark_removed: bool,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PCK",
+        help = "Copy the PCK here and patch the copy, leaving the original untouched"
+    )]
+    output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "output",
+        help = "Rebuild the patched copy as a brand new pack: entries sorted by path, data tightly packed, no leftover regions, so the same mod always yields the same bytes"
+    )]
+    repack: bool,
+
+    #[arg(
+        long,
+        help = "Keep running and rewrite changed resources whenever the mod folder changes (loose backend)"
+    )]
+    watch: bool,
+
+    #[arg(
+        long,
+        help = "Don't copy the PCK to <name>.bak-<time> before patching it in place (location and count: [backup] in bpb_enhance.toml)"
+    )]
+    no_backup: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Patch in place without asking even when --no-backup is set and the PCK has no backup yet, and switch to an override pack without asking when the PCK is read-only"
+    )]
+    yes: bool,
+
+    #[arg(
+        long,
+        value_name = "OPTION",
+        value_delimiter = ',',
+        help = "Turn on optional features from the mod's [options] table, e.g. --enable item-library"
+    )]
+    enable: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "OPTION",
+        value_delimiter = ',',
+        help = "Turn off optional features from the mod's [options] table"
+    )]
+    disable: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum VerifyMoves {
+    /// Copy moved entries without re-hashing them
+    Off,
+    /// Warn when a moved entry does not match its recorded MD5
+    Warn,
+    /// Replace the recorded MD5 of mismatching entries with the actual one
+    Repair,
+}
+
+impl From<VerifyMoves> for pck::MoveVerification {
+    fn from(mode: VerifyMoves) -> Self {
+        match mode {
+            VerifyMoves::Off => pck::MoveVerification::Off,
+            VerifyMoves::Warn => pck::MoveVerification::Warn,
+            VerifyMoves::Repair => pck::MoveVerification::Repair,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    /// Patch the game PCK directly
+    InPlace,
+    /// Leave the game PCK untouched and write a per-mod override pack to <game dir>/mods
+    Overlay,
+    /// Write changed resources as loose files to <game dir>/res_override for development builds
+    /// that load them; falls back to in-place when that folder does not exist
+    Loose,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    #[arg(help = "Original PCK file, or an http:// URL when built with the online feature")]
+    old: PathBuf,
+
+    #[arg(help = "Modified PCK file")]
+    new: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write the changed entries and a replace.toml to DIR, ready for apply"
+    )]
+    export: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "export",
+        help = "Game version for the exported manifest (detected from OLD when omitted)"
+    )]
+    game_version: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct CheckInstallArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct PendingArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct CompactArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct ExportTransferArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        value_name = "DIR",
+        help = "Folder to write the chunks and transfer.toml to"
+    )]
+    output: PathBuf,
+
+    #[arg(long, value_name = "MIB", default_value_t = 256, help = "Size of each chunk in MiB")]
+    chunk_size: u64,
+}
+
+#[derive(Debug, Args)]
+struct ImportTransferArgs {
+    #[arg(help = "Folder containing transfer.toml and the chunks")]
+    dir: PathBuf,
+
+    #[arg(short, long, help = "PCK file to create or replace with the reassembled archive")]
+    pck: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct ExportZipArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(short, long, value_name = "ZIP", help = "Archive to create (overwritten if it exists)")]
+    output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct ImportZipArgs {
+    #[arg(short, long, help = "Path to the PCK file to write into")]
+    pck: PathBuf,
+
+    #[arg(help = "ZIP whose files replace or are added to the entries of --pck")]
+    zip: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PCK",
+        help = "Copy the PCK here and import into the copy, leaving the original untouched"
+    )]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ExtractArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(short, long, value_name = "DIR", help = "Folder to write the files to")]
+    output: PathBuf,
+
+    #[arg(
+        help = "Entries to extract, e.g. res://Core/Game.gde, or glob patterns like 'res://Core/*.gde' and '**/*.png' (all entries when omitted)"
+    )]
+    paths: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct AnalyzeCompressionArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 64 * 1024,
+        help = "Ignore entries smaller than this"
+    )]
+    min_size: u64,
+
+    #[arg(long, default_value_t = 20, help = "Show at most this many entries")]
+    top: usize,
+}
+
+#[derive(Debug, Args)]
+struct ListArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Show size, MD5, content type and which mod wrote each entry (and when)"
+    )]
+    verbose: bool,
+
+    #[arg(
+        long = "type",
+        value_name = "TYPE",
+        value_parser = parse_res_type,
+        help = "Only list entries of these content types: script, texture, scene, audio, translation, font, other"
+    )]
+    types: Vec<ResType>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format; json always includes size, MD5, type and origin")]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        value_name = "CSV",
+        help = "Show readable names from a mapping of obfuscated paths"
+    )]
+    map: Option<PathBuf>,
+
+    #[arg(long, help = "Only list entries whose (mapped) path contains this text")]
+    filter: Option<String>,
+
+    #[arg(
+        help = "Only list entries whose (mapped) path matches one of these glob patterns, e.g. '**/*.png'"
+    )]
+    patterns: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Mark entries whose data is byte-identical to another entry's (reads the candidates' data)"
+    )]
+    duplicates: bool,
+}
+
+#[derive(Debug, Args)]
+struct LintManifestArgs {
+    #[arg(help = "Path to replace.toml, or a folder containing it")]
+    manifest: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Diagnostics output format")]
+    format: OutputFormat,
+
+    #[arg(long, help = "Exit with failure on warnings as well as errors")]
+    deny_warnings: bool,
+}
+
+#[cfg(feature = "mount")]
+#[derive(Debug, Args)]
+struct MountArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(help = "Existing empty folder to show the PCK's res:// tree in")]
+    mountpoint: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Accept file writes and record them in DIR as a mod (assets plus replace.toml) instead of rejecting them"
+    )]
+    capture: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "capture",
+        help = "Game version for the captured manifest (detected from the PCK when omitted)"
+    )]
+    game_version: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct MergeArgs {
+    #[arg(short, long, help = "Path to the PCK file to merge into")]
+    pck: PathBuf,
+
+    #[arg(help = "PCK whose entries replace or are added to those of --pck")]
+    overlay: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PCK",
+        help = "Copy the PCK here and merge into the copy, leaving the original untouched"
+    )]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct NewModArgs {
+    #[arg(help = "Folder to create; its name is used as the mod name")]
+    name: PathBuf,
+
+    #[arg(
+        long,
+        help = "PCK file used to detect the game version and suggest real resource paths"
+    )]
+    pck: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Game version the mod targets (detected from --pck when omitted)"
+    )]
+    game_version: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct PackArgs {
+    #[arg(help = "Folder whose contents become res:// paths")]
+    source: PathBuf,
+
+    #[arg(short, long, help = "Output PCK, or executable when --embed is given")]
+    output: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Godot export template executable to embed the pack into"
+    )]
+    embed: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_godot_version,
+        default_value = "3.0.0",
+        help = "Godot version written to the header; engines reject packs newer than themselves"
+    )]
+    godot_version: [u32; 3],
+}
+
+#[derive(Debug, Args)]
+struct RebaseArgs {
+    #[arg(help = "Mod folder containing replace.toml")]
+    assets: PathBuf,
+
+    #[arg(long, help = "PCK of the game version the mod was built against")]
+    old: PathBuf,
+
+    #[arg(long, help = "PCK of the game version to rebase onto")]
+    new: PathBuf,
+
+    #[arg(short, long, help = "Folder to write the rebased mod to")]
+    output: PathBuf,
+
+    #[arg(
+        long,
+        help = "Version of the new game (detected from --new when omitted)"
+    )]
+    game_version: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct RemapArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "CSV",
+        help = "Mapping of obfuscated paths to readable names"
+    )]
+    map: PathBuf,
+
+    #[arg(long, help = "Rename readable names back to the obfuscated paths")]
+    reverse: bool,
+}
+
+#[derive(Debug, Args)]
+struct RepairArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(
+        long,
+        help = "Drop duplicate entries, keeping the most recently written one"
+    )]
+    dedupe_entries: bool,
+
+    #[arg(
+        long,
+        help = "Point entries with byte-identical data at a single shared copy"
+    )]
+    share_identical_data: bool,
+
+    #[arg(
+        long,
+        help = "Drop entries whose data lies past the end of the file or whose path is garbage, and rewrite the entry table"
+    )]
+    fix_table: bool,
+}
+
+#[derive(Debug, Args)]
+struct RestoreArgs {
+    #[arg(short, long, help = "Path to the PCK file to restore")]
+    pck: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "BACKUP",
+        help = "Restore from this backup instead of the latest one"
+    )]
+    from: Option<PathBuf>,
+
+    #[arg(long, conflicts_with = "from", help = "List the backups of the PCK, oldest first")]
+    list: bool,
+}
+
+#[derive(Debug, Args)]
+struct TweakPackArgs {
+    #[arg(help = "Folder containing replace.toml and the assets it references")]
+    folder: PathBuf,
+
+    #[arg(long, help = "Version of the asset pack, shown by the GUI and in patch reports")]
+    version: String,
+}
+
+#[derive(Debug, Args)]
+struct VerifyArgs {
+    #[arg(short, long, help = "Path to the PCK file")]
+    pck: PathBuf,
+
+    #[arg(
+        long,
+        value_parser = parse_percent,
+        default_value = "100%",
+        help = "Check only a random sample of entries, e.g. 5%"
+    )]
+    sample: f64,
+
+    #[arg(long, help = "Seed for --sample; the same seed checks the same entries")]
+    seed: Option<u64>,
+
+    #[arg(long, help = "Stop at the first mismatch")]
+    fail_fast: bool,
+}
+
+#[derive(Debug, Args)]
+struct StressArgs {
+    #[arg(short, long, help = "Path to the PCK file; it is copied, never modified")]
+    pck: PathBuf,
+
+    #[arg(long, default_value_t = 200, help = "Number of apply/delete/compact cycles")]
+    cycles: usize,
+
+    #[arg(long, help = "Seed for the random cycles; the same seed replays the same run")]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Where to put the scratch copy (default: the system temp folder)"
+    )]
+    scratch: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+pub fn run() -> Result<()> {
+    let cli = Cli::parse();
+    if cli.portable {
+        config::enable_portable();
+    }
+    let settings = config::load();
+    let json_progress = matches!(cli.progress, OutputFormat::Json);
+    if json_progress {
+        progress::enable_stderr();
+    }
+    let _diagnostics = report_diagnostics(json_progress);
+    if let Some(pipe) = &cli.progress_pipe {
+        progress::connect_pipe(pipe)?;
+    }
+    let threads = cli.jobs.unwrap_or(settings.threads).resolve();
+    let key = cli.encryption_key;
+    let limits = if cli.force_limits_off {
+        pck::TableLimits::OFF
+    } else {
+        settings.limits.resolve()
+    };
+
+    let result = match cli.command {
+        Some(Command::Apply(args)) => run_apply(args, threads, key, limits),
+        Some(Command::Abort(args)) => run_abort(args),
+        Some(Command::AnalyzeCompression(args)) => run_analyze_compression(args, limits),
+        Some(Command::CheckInstall(args)) => run_check_install(args),
+        Some(Command::Commit(args)) => run_commit(args),
+        Some(Command::Compact(args)) => run_compact(args, key, limits),
+        Some(Command::Diff(args)) => run_diff(args, limits),
+        Some(Command::ExportTransfer(args)) => run_export_transfer(args),
+        Some(Command::ExportZip(args)) => run_export_zip(args, limits),
+        Some(Command::Extract(args)) => run_extract(args, threads, key, limits),
+        Some(Command::ImportTransfer(args)) => run_import_transfer(args),
+        Some(Command::ImportZip(args)) => run_import_zip(args, threads, limits),
+        Some(Command::List(args)) => run_list(args, threads, key, limits),
+        Some(Command::LintManifest(args)) => run_lint_manifest(args),
+        Some(Command::Merge(args)) => run_merge(args, threads, limits),
+        #[cfg(feature = "mount")]
+        Some(Command::Mount(args)) => run_mount(args),
+        Some(Command::NewMod(args)) => run_new_mod(args),
+        Some(Command::Pack(args)) => run_pack(args),
+        Some(Command::Rebase(args)) => run_rebase(args, limits),
+        Some(Command::Remap(args)) => run_remap(args, key, limits),
+        Some(Command::Repair(args)) => run_repair(args, key, limits),
+        Some(Command::Restore(args)) => run_restore(args),
+        Some(Command::Stress(args)) => run_stress(args, threads),
+        Some(Command::TweakPack(args)) => run_tweak_pack(args),
+        Some(Command::Verify(args)) => run_verify(args, threads, key, limits),
+        None => run_apply(cli.apply, threads, key, limits),
+    };
+    if let Err(err) = &result {
+        progress::emit(&progress::Event::Failed {
+            message: &format!("{:#}", err),
+        });
+    }
+    result
+}
+
+fn run_apply(
+    args: ApplyArgs,
+    threads: usize,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    // clap guarantees both are present when no subcommand is given.
+    let pck = args.pck.clone().context("--pck is required")?;
+    let assets = args.assets.clone().context("--assets is required")?;
+
+    let pck_path = PathBuf::from(&pck);
+    let assets_path = PathBuf::from(&assets);
+
+    if !pck_path.exists() {
+        anyhow::bail!("PCK file does not exist: {}", pck);
+    }
+    if !pck_path.is_file() {
+        anyhow::bail!("Path is not a file: {}", pck);
+    }
+    if !assets_path.exists() {
+        anyhow::bail!("Assets folder does not exist: {}", assets);
+    }
+    if !assets_path.is_dir() {
+        anyhow::bail!("Path is not a directory: {}", assets);
+    }
+
+    let replace_toml = assets_path.join("replace.toml");
+    if !replace_toml.exists() {
+        anyhow::bail!("replace.toml not found in assets folder: {}", assets);
+    }
+
+    println!("Processing PCK file: {}", pck);
+    println!("Using assets folder: {}", assets);
+    let mapping = PathMapping::load_optional(args.map.as_deref())?;
+    let selection = option_selection(&args.enable, &args.disable)?;
+
+    if args.watch && !matches!(args.backend, Backend::Loose) {
+        anyhow::bail!("--watch is only supported by the loose backend");
+    }
+    if let Backend::Overlay | Backend::Loose = args.backend
+        && let Some(option) = in_place_only_option(&args)
+    {
+        anyhow::bail!("{} is only supported by the in-place backend", option);
+    }
+
+    if let Backend::Loose = args.backend {
+        if write_loose(&pck, &assets, &mapping, &selection)? {
+            if args.watch {
+                watch_loose(&pck, &assets_path, &mapping, &selection)?;
+            }
+            return Ok(());
+        }
+        if args.watch {
+            anyhow::bail!(
+                "--watch needs a res_override folder next to the PCK (created by development builds of the game)"
+            );
+        }
+        println!("No res_override folder next to the PCK; falling back to the in-place backend");
+    }
+
+    let overlay = match args.backend {
+        Backend::Overlay => true,
+        _ => match platform::write_protection(&pck_path) {
+            Some(protection) if args.output.is_none() => {
+                offer_overlay(&pck_path, protection, &args)?
+            }
+            _ => false,
+        },
+    };
+    if overlay {
+        let pack = tweak::build_override_pack(&pck, &assets, args.priority, mapping, &selection)
+            .with_context(|| format!("Failed to build override pack for: {}", pck))?;
+        println!("Override pack written to: {}", pack.display());
+        return Ok(());
+    }
+
+    recover_interrupted(&pck_path)?;
+    // Detection is advisory: an unreadable PCK fails the apply itself with a better message.
+    let findings = foreign::detect(&pck_path).unwrap_or_default();
+    if !findings.is_empty() {
+        println!("Warning: {} appears to have been modified by another tool:", pck);
+        for finding in &findings {
+            println!("  - {}", finding);
+        }
+    }
+    let label = (!findings.is_empty()).then_some(backup::PRE_EXISTING_LABEL);
+
+    let target_path = match &args.output {
+        Some(output) => {
+            copy_for_output(&pck_path, output)?;
+            println!("Copied {} to {}", pck, output.display());
+            output.clone()
+        }
+        None => {
+            if !args.no_backup {
+                let backup = backup::create_labeled(&pck_path, &config::load().backup, label)
+                    .context(
+                        "Failed to back up the PCK; nothing was changed (--no-backup skips it)",
+                    )?;
+                match label {
+                    Some(label) => {
+                        println!("Backed up {} to {} ({})", pck, backup.display(), label)
+                    }
+                    None => println!("Backed up {} to {}", pck, backup.display()),
+                }
+            } else {
+                if label.is_some() {
+                    println!(
+                        "Skipping the backup (--no-backup); this state can't be restored later"
+                    );
+                }
+                confirm_without_backup(&pck_path, args.yes)?;
+            }
+            pck_path.clone()
+        }
+    };
+    let target = target_path.to_string_lossy().to_string();
+
+    let options = tweak::ApplyOptions {
+        verify_moves: args.verify_moves.map(Into::into),
+        safe_mode: args.safe_mode,
+        atomic: args.atomic,
+        stage: args.stage,
+        mark_removed: args.mark_removed,
+        selection,
+        jobs: Some(threads),
+        limits: Some(limits),
+        encryption_key: key,
+        cancel: cancel_on_ctrl_c(),
+    };
+    let report = match tweak::tweak_game_gde(&target, &assets, mapping, &options) {
+        Ok(report) => report,
+        Err(err) => {
+            if let Some(bundle) = &args.explain {
+                match explain::write_bundle(bundle, &target_path, &assets_path, &err) {
+                    Ok(()) => println!("Diagnostics bundle written to: {}", bundle.display()),
+                    Err(bundle_err) => {
+                        println!("Failed to write diagnostics bundle: {:#}", bundle_err)
+                    }
+                }
+            }
+            if args.output.is_some() {
+                // The half-patched copy is useless; the original was never touched.
+                let _ = std::fs::remove_file(&target_path);
+            }
+            return Err(err.context(format!("Failed to tweak PCK file: {}", target)));
+        }
+    };
+
+    if args.stage {
+        println!("Staged changes for PCK file: {}", target);
+        println!(
+            "The game still sees the original contents; run `commit -p {}` to activate them or `abort -p {}` to discard them",
+            target, target
+        );
+    } else {
+        println!("Successfully tweaked PCK file: {}", target);
+    }
+    if args.repack {
+        let repacked = repack_file(&target_path, key, limits).inspect_err(|_| {
+            let _ = std::fs::remove_file(&target_path);
+        })?;
+        println!(
+            "Repacked {}: {} entries, {} -> {} bytes",
+            target,
+            repacked.entries,
+            repacked.size_before,
+            repacked.size_after
+        );
+    }
+    if args.output.is_some() {
+        println!("Original left untouched: {}", pck);
+    }
+
+    if let Some(report_path) = &args.report {
+        report.write_to(report_path)?;
+        println!("Patch report written to: {}", report_path.display());
+    }
+    Ok(())
+}
+
+/// The first option given that only the in-place backend supports.
+fn in_place_only_option(args: &ApplyArgs) -> Option<&'static str> {
+    [
+        (args.report.is_some(), "--report"),
+        (args.safe_mode, "--safe-mode"),
+        (args.atomic, "--atomic"),
+        (args.stage, "--stage"),
+        (args.mark_removed, "--mark-removed"),
+        (args.output.is_some(), "--output"),
+        (args.repack, "--repack"),
+    ]
+    .into_iter()
+    .find_map(|(given, option)| given.then_some(option))
+}
+
+/// The PCK can't be patched in place (read-only file or drive, or no permission). Explain why and
+/// how to fix it, and offer the override pack backend, which leaves the PCK untouched, when the
+/// game folder takes new files. Returns true when the user switches to it.
+fn offer_overlay(
+    pck: &Path,
+    protection: platform::WriteProtection,
+    args: &ApplyArgs,
+) -> Result<bool> {
+    use platform::WriteProtection;
+
+    let (reason, fix) = match protection {
+        WriteProtection::ReadOnlyFile => (
+            "the file is marked read-only",
+            "clear \"Read-only\" in the file's properties",
+        ),
+        WriteProtection::ReadOnlyVolume => (
+            "the drive it is on is read-only or write-protected",
+            "make the drive writable, or move the game to another drive in Steam > Settings > Storage",
+        ),
+        WriteProtection::AccessDenied => (
+            "this account may not write there (NTFS permissions or a protected folder such as Program Files)",
+            "give your account write access to the game folder, run as administrator, or move the game to a library outside protected folders in Steam > Settings > Storage",
+        ),
+    };
+    let blocked = || {
+        format!(
+            "Can't patch {} in place: {}.\nTo patch it, {}; or write a patched copy elsewhere with --output <PCK>",
+            pck.display(),
+            reason,
+            fix
+        )
+    };
+    if let Some(option) = in_place_only_option(args) {
+        anyhow::bail!(
+            "{}\n({} rules out an override pack instead)",
+            blocked(),
+            option
+        );
+    }
+    if !overlay::can_write_packs(pck) {
+        anyhow::bail!("{}", blocked());
+    }
+
+    println!("Can't patch {} in place: {}.", pck.display(), reason);
+    println!(
+        "The mod can go into an override pack in {} instead, leaving the PCK untouched (--backend overlay).",
+        overlay::overlay_dir(pck)?.display()
+    );
+    println!("To patch in place instead, {}.", fix);
+    if args.yes {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "{}\nOr pass --backend overlay (or --yes) to write an override pack",
+            blocked()
+        );
+    }
+    print!("Write an override pack instead? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        anyhow::bail!("Cancelled; nothing was changed");
+    }
+    Ok(true)
+}
+
+/// With `--no-backup` and no earlier backup, a patched PCK can only be restored through Steam:
+/// say so and require `--yes`, or a "y" typed at the terminal, before the first write.
+fn confirm_without_backup(pck: &Path, yes: bool) -> Result<()> {
+    if backup::latest(pck, &config::load().backup)?.is_some() {
+        return Ok(());
+    }
+    println!("{} has no backup and --no-backup skips making one.", pck.display());
+    println!("Once it is patched, the only way back to the original is to have Steam restore it:");
+    println!(
+        "  Steam > Library > right-click Backpack Battles > Properties > Installed Files > Verify integrity of game files"
+    );
+    println!(
+        "Run without --no-backup to keep a backup instead; `restore -p {}` then undoes the apply.",
+        pck.display()
+    );
+    if yes {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Refusing to patch a PCK without a backup; pass --yes to go ahead");
+    }
+    print!("Patch it anyway? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        anyhow::bail!("Cancelled; nothing was changed");
+    }
+    Ok(())
+}
+
+/// Route PCK diagnostics away from stdout, which is kept for command output: warnings go to
+/// stderr (as JSON events with `--progress json`) and debug diagnostics only to the log.
+/// The routing lasts as long as the returned scope.
+fn report_diagnostics(json_progress: bool) -> pck::DiagnosticsScope {
+    pck::scope_diagnostics(Arc::new(move |diagnostic: &pck::Diagnostic| {
+        if diagnostic.level() > log::Level::Warn {
+            return pck::LogSink.report(diagnostic);
+        }
+        let message = diagnostic.to_string();
+        progress::emit(&progress::Event::Warning { message: &message });
+        if !json_progress {
+            eprintln!("⚠ {}", message);
+        }
+    }))
+}
+
+/// Roll back a modification of `pck` that was interrupted by a crash before anything else writes
+/// to it; replaying that journal after another command has rewritten the file would corrupt it.
+fn recover_interrupted(pck: &Path) -> Result<()> {
+    if journal::recover(pck).with_context(|| {
+        format!("Failed to roll back the interrupted modification of {}", pck.display())
+    })? {
+        println!("Rolled back an interrupted modification of {} first", pck.display());
+    }
+    Ok(())
+}
+
+/// Let Ctrl+C stop a long pack operation between two chunks instead of killing it mid-write.
+/// Call it right before that operation so earlier steps can still be interrupted normally.
+fn cancel_on_ctrl_c() -> CancelToken {
+    let token = CancelToken::new();
+    platform::cancel_on_interrupt(token.clone());
+    token
+}
+
+/// Rewrite `path` as a clean pack through a temporary file next to it.
+fn repack_file(
+    path: &Path,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<pck::RepackReport> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".repack.partial");
+    let partial = PathBuf::from(partial);
+    let repacked = write_repacked(path, &partial, key, limits).and_then(|report| {
+        std::fs::rename(&partial, path)?;
+        Ok(report)
+    });
+    if repacked.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    repacked.with_context(|| format!("Failed to repack {}", path.display()))
+}
+
+fn write_repacked(
+    path: &Path,
+    partial: &Path,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<pck::RepackReport> {
+    let mut src = pck::Keyed::new(File::open(path)?, key).with_limits(limits);
+    let mut out = File::create(partial)?;
+    let io = config::load().io.resolve(src.get_ref().metadata()?.len());
+    let report = pck::repack(&mut src, &mut out, &io)?;
+    out.sync_all()?;
+    Ok(report)
+}
+
+/// `--enable`/`--disable` as a selection; naming the same option in both is an error.
+fn option_selection(enable: &[String], disable: &[String]) -> Result<OptionSelection> {
+    if let Some(both) = enable.iter().find(|id| disable.contains(id)) {
+        anyhow::bail!("Option {} is both enabled and disabled", both);
+    }
+    let choices = enable
+        .iter()
+        .map(|id| (id.clone(), true))
+        .chain(disable.iter().map(|id| (id.clone(), false)))
+        .collect();
+    Ok(OptionSelection(choices))
+}
+
+/// Write the mod's changed resources as loose files into the game's res_override folder and
+/// list what was updated. Returns false when the game has no res_override folder to write into.
+fn write_loose(
+    pck: &str,
+    assets: &str,
+    mapping: &PathMapping,
+    selection: &OptionSelection,
+) -> Result<bool> {
+    let Some((dir, written)) =
+        tweak::write_loose_overrides(pck, assets, mapping.clone(), selection)
+            .with_context(|| format!("Failed to write loose overrides for: {}", pck))?
+    else {
+        return Ok(false);
+    };
+    for path in &written {
+        println!("updated   {}", path);
+    }
+    println!(
+        "{} changed resource(s) written to: {}",
+        written.len(),
+        dir.display()
+    );
+    Ok(true)
+}
+
+/// How often `--watch` polls the mod folder for changes.
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Rewrites loose overrides whenever a file in the mod folder is added, removed or modified.
+/// Runs until the process is interrupted; failed rewrites are reported and retried on the next change.
+fn watch_loose(
+    pck: &str,
+    assets: &Path,
+    mapping: &PathMapping,
+    selection: &OptionSelection,
+) -> Result<()> {
+    let assets_str = assets.to_string_lossy();
+    let mut last = folder_state(assets)?;
+    println!(
+        "Watching {} for changes (Ctrl+C to stop)...",
+        assets.display()
+    );
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        let current = match folder_state(assets) {
+            Ok(state) => state,
+            // Editors briefly remove files while saving; try again next tick.
+            Err(_) => continue,
+        };
+        if current == last {
+            continue;
+        }
+        last = current;
+        if let Err(err) = write_loose(pck, &assets_str, mapping, selection) {
+            println!("{:#}", err);
+        }
+    }
+}
+
+/// Size and modification time of every file under `dir`, in path order.
+fn folder_state(dir: &Path) -> Result<Vec<(String, u64, Option<SystemTime>)>> {
+    pck::collect_pack_inputs(dir)?
+        .into_iter()
+        .map(|(path, input)| {
+            let pck::PackInput::File(file) = input else {
+                unreachable!("collect_pack_inputs only returns files");
+            };
+            let meta = std::fs::metadata(&file)
+                .with_context(|| format!("Failed to read: {}", file.display()))?;
+            Ok((path, meta.len(), meta.modified().ok()))
+        })
+        .collect()
+}
+
+/// Copy the stock PCK to `--output`, refusing to overwrite the original itself.
+fn copy_for_output(pck: &Path, output: &Path) -> Result<()> {
+    if let (Ok(a), Ok(b)) = (pck.canonicalize(), output.canonicalize())
+        && a == b
+    {
+        anyhow::bail!("--output must differ from --pck; omit it to patch in place");
+    }
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    std::fs::copy(pck, output)
+        .with_context(|| format!("Failed to copy {} to {}", pck.display(), output.display()))?;
+    Ok(())
+}
+
+fn run_diff(args: DiffArgs, limits: pck::TableLimits) -> Result<()> {
+    let diffs = diff::diff_pcks(&args.old, &args.new, limits).with_context(|| {
+        format!(
+            "Failed to compare {} with {}",
+            args.old.display(),
+            args.new.display()
+        )
+    })?;
+
+    for entry in &diffs {
+        let marker = match entry.change {
+            EntryChange::Added => '+',
+            EntryChange::Replaced => '~',
+            EntryChange::Deleted => '-',
+        };
+        println!("{} {}", marker, entry.path);
+    }
+    let count = |change| diffs.iter().filter(|d| d.change == change).count();
+    println!(
+        "{} added, {} changed, {} removed",
+        count(EntryChange::Added),
+        count(EntryChange::Replaced),
+        count(EntryChange::Deleted)
+    );
+
+    if let Some(out_dir) = &args.export {
+        let summary = diff::export_patchset(
+            &args.old,
+            &args.new,
+            &diffs,
+            out_dir,
+            args.game_version.as_deref(),
+        )
+        .with_context(|| format!("Failed to export patchset to: {}", out_dir.display()))?;
+        println!(
+            "Exported {} file(s) ({} bytes, {} deduplicated) to {}",
+            summary.files_written,
+            summary.bytes_written,
+            summary.deduplicated,
+            out_dir.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_analyze_compression(args: AnalyzeCompressionArgs, limits: pck::TableLimits) -> Result<()> {
+    let analysis = compression::analyze(&args.pck, args.min_size, limits)
+        .with_context(|| format!("Failed to analyze PCK file: {}", args.pck.display()))?;
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+
+    if !analysis.candidates.is_empty() {
+        println!("size_mib\testimated_mib\tsaving\tkind\tpath");
+    }
+    for estimate in analysis.candidates.iter().take(args.top) {
+        println!(
+            "{:.2}\t{:.2}\t{:.0}%\t{}\t{}",
+            mib(estimate.size),
+            mib(estimate.estimated),
+            estimate.saving() as f64 * 100.0 / estimate.size as f64,
+            estimate.kind,
+            estimate.path
+        );
+    }
+    if analysis.candidates.len() > args.top {
+        println!(
+            "... {} more (raise --top to show them)",
+            analysis.candidates.len() - args.top
+        );
+    }
+
+    let saving = analysis.total_saving();
+    println!(
+        "{} uncompressed entr{} of at least {} bytes could shrink by about {:.1} MiB ({:.1}% of the {:.1} MiB archive)",
+        analysis.candidates.len(),
+        if analysis.candidates.len() == 1 { "y" } else { "ies" },
+        args.min_size,
+        mib(saving),
+        saving as f64 * 100.0 / analysis.archive_size.max(1) as f64,
+        mib(analysis.archive_size)
+    );
+    println!(
+        "{} already-compressed entr{} ({:.1} MiB) skipped",
+        analysis.compressed_entries,
+        if analysis.compressed_entries == 1 { "y" } else { "ies" },
+        mib(analysis.compressed_bytes)
+    );
+    println!("Estimates are approximate; real zstd/deflate output is usually a little smaller");
+    Ok(())
+}
+
+fn run_check_install(args: CheckInstallArgs) -> Result<()> {
+    let Some(check) = depot::check(&args.pck)? else {
+        anyhow::bail!(
+            "{} is not inside a Steam library (steamapps/common/<game>) with an appmanifest",
+            args.pck.display()
+        );
+    };
+    println!(
+        "App {} build {}",
+        check.build.app_id,
+        check.build.build_id.as_deref().unwrap_or("unknown")
+    );
+    match check.build.size_on_disk {
+        Some(expected) => println!(
+            "Install size: {} bytes on disk, {} bytes according to Steam",
+            check.actual_size, expected
+        ),
+        None => println!(
+            "Install size: {} bytes on disk, Steam did not record one",
+            check.actual_size
+        ),
+    }
+    match check.verdict {
+        depot::Verdict::Matches => println!("Matches the Steam install; safe to patch"),
+        depot::Verdict::Modded => println!(
+            "Modded: the PCK carries this tool's marker; restore or verify before patching again"
+        ),
+        depot::Verdict::Corrupted if check.modded.is_none() => {
+            anyhow::bail!("The PCK file table cannot be read; verify the game files in Steam")
+        }
+        depot::Verdict::Corrupted => anyhow::bail!(
+            "The install differs from what Steam downloaded but is not modded; \
+             the download is probably incomplete or corrupted, verify the game files in Steam"
+        ),
+        depot::Verdict::Unknown => {
+            println!("Not modded; Steam recorded no install size to compare against")
+        }
+    }
+    Ok(())
+}
+
+fn run_compact(
+    args: CompactArgs,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    recover_interrupted(&args.pck)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&args.pck)
+        .with_context(|| format!("Failed to open PCK file: {}", args.pck.display()))?;
+    let mut file = pck::Keyed::new(file, key).with_limits(limits);
+    let io = pck::IoOptions {
+        cancel: cancel_on_ctrl_c(),
+        ..config::load().io.resolve(file.get_ref().metadata()?.len())
+    };
+    let report = pck::compact(&mut file, &io)
+        .with_context(|| format!("Failed to compact PCK file: {}", args.pck.display()))?;
+    println!(
+        "Reclaimed {} bytes ({} -> {}), moved {} entr{}",
+        report.reclaimed(),
+        report.size_before,
+        report.size_after,
+        report.moved_entries,
+        if report.moved_entries == 1 { "y" } else { "ies" }
+    );
+    Ok(())
+}
+
+fn run_export_transfer(args: ExportTransferArgs) -> Result<()> {
+    let chunk_size = args.chunk_size.saturating_mul(1024 * 1024);
+    let manifest = transfer::export(&args.pck, &args.output, chunk_size).with_context(|| {
+        format!("Failed to export {} for transfer", args.pck.display())
+    })?;
+    println!(
+        "Wrote {} chunk{} ({} bytes, MD5 {}) and {} to {}",
+        manifest.chunks.len(),
+        if manifest.chunks.len() == 1 { "" } else { "s" },
+        manifest.size,
+        manifest.md5_hex,
+        transfer::MANIFEST_NAME,
+        args.output.display()
+    );
+    println!("Copy the whole folder, then run import-transfer on the other PC");
+    Ok(())
+}
+
+fn run_import_transfer(args: ImportTransferArgs) -> Result<()> {
+    let (manifest, bad) = transfer::verify(&args.dir)
+        .with_context(|| format!("Failed to verify chunks in {}", args.dir.display()))?;
+    if !bad.is_empty() {
+        for chunk in &bad {
+            match chunk {
+                transfer::BadChunk::Missing(file) => eprintln!("missing: {}", file),
+                transfer::BadChunk::Changed(file) => eprintln!("corrupted: {}", file),
+            }
+        }
+        anyhow::bail!(
+            "{} of {} chunk(s) failed verification; copy them again from the source PC",
+            bad.len(),
+            manifest.chunks.len()
+        );
+    }
+
+    let imported = transfer::import(&args.dir, &manifest, &args.pck)
+        .with_context(|| format!("Failed to reassemble {}", args.pck.display()))?;
+    match imported {
+        transfer::Imported::UpToDate => {
+            println!("{} already matches the transfer; nothing written", args.pck.display())
+        }
+        transfer::Imported::Written { bytes } => println!(
+            "Reassembled {} ({} bytes, MD5 {})",
+            args.pck.display(),
+            bytes,
+            manifest.md5_hex
+        ),
+    }
+    Ok(())
+}
+
+fn run_export_zip(args: ExportZipArgs, limits: pck::TableLimits) -> Result<()> {
+    let files = zip::export_pck(&args.pck, &args.output, limits).with_context(|| {
+        format!("Failed to export PCK file to ZIP: {}", args.pck.display())
+    })?;
+    println!(
+        "Exported {} file{} to {}",
+        files,
+        if files == 1 { "" } else { "s" },
+        args.output.display()
+    );
+    Ok(())
+}
+
+fn run_import_zip(args: ImportZipArgs, threads: usize, limits: pck::TableLimits) -> Result<()> {
+    recover_interrupted(&args.pck)?;
+    let target = match &args.output {
+        Some(output) => {
+            copy_for_output(&args.pck, output)?;
+            output.clone()
+        }
+        None => args.pck.clone(),
+    };
+    let io = pck::IoOptions {
+        jobs: threads,
+        ..config::load().io.resolve(std::fs::metadata(&target)?.len())
+    };
+    let summary = match zip::import_zip(&target, &args.zip, &io, limits) {
+        Ok(summary) => summary,
+        Err(err) => {
+            if args.output.is_some() {
+                let _ = std::fs::remove_file(&target);
+            }
+            return Err(err.context(format!(
+                "Failed to import {} into {}",
+                args.zip.display(),
+                args.pck.display()
+            )));
+        }
+    };
+
+    for path in &summary.added {
+        println!("+ {}", path);
+    }
+    for path in &summary.replaced {
+        println!("~ {}", path);
+    }
+    println!(
+        "Imported into {}: {} added, {} replaced, {} already identical",
+        target.display(),
+        summary.added.len(),
+        summary.replaced.len(),
+        summary.unchanged
+    );
+    Ok(())
+}
+
+fn run_extract(
+    args: ExtractArgs,
+    threads: usize,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    let paths: Vec<&str> = args.paths.iter().map(String::as_str).collect();
+    let options = pck::ExtractOptions {
+        encryption_key: key,
+        jobs: threads,
+        limits,
+        cancel: cancel_on_ctrl_c(),
+    };
+    let extracted = pck::extract_files_with(&args.pck, &paths, &args.output, &options)
+        .with_context(|| format!("Failed to extract from PCK file: {}", args.pck.display()))?;
+    println!(
+        "Extracted {} file{} to {}",
+        extracted.files,
+        if extracted.files == 1 { "" } else { "s" },
+        args.output.display()
+    );
+
+    if !extracted.pairs.is_empty() {
+        // 与之前导出到同一目录的记录合并，同一配对文件以本次为准
+        let record = args.output.join(import_pairs::FILE_NAME);
+        let mut pairs = match std::fs::read_to_string(&record) {
+            Ok(content) => import_pairs::parse(&content)
+                .with_context(|| format!("Invalid import pair record: {}", record.display()))?,
+            Err(_) => Vec::new(),
+        };
+        pairs.retain(|old| !extracted.pairs.iter().any(|new| new.companion == old.companion));
+        pairs.extend(extracted.pairs);
+        std::fs::write(&record, import_pairs::to_toml(&pairs))
+            .with_context(|| format!("Failed to write {}", record.display()))?;
+        println!(
+            "Kept {} .import/.remap pair{} together; apply re-imports them as a group ({})",
+            pairs.len(),
+            if pairs.len() == 1 { "" } else { "s" },
+            record.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_list(
+    args: ListArgs,
+    threads: usize,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    let file = File::open(&args.pck)
+        .with_context(|| format!("Failed to open PCK file: {}", args.pck.display()))?;
+    let mut file = pck::Keyed::new(file, key).with_limits(limits);
+    let entries = pck::list_entries(&mut file)?;
+    let mapping = PathMapping::load_optional(args.map.as_deref())?;
+
+    if mapping.is_empty() && mapping::looks_obfuscated(entries.iter().map(|e| &e.path)) {
+        eprintln!("Entry names look obfuscated; pass --map <CSV> to show readable names");
+    }
+
+    // (显示名, entry)
+    let mut shown: Vec<(&str, &pck::PckEntryInfo)> = entries
+        .iter()
+        .map(|entry| (mapping.logical(&entry.path), entry))
+        .filter(|(name, _)| args.filter.as_deref().is_none_or(|f| name.contains(f)))
+        .filter(|(name, entry)| {
+            args.patterns.is_empty()
+                || args.patterns.iter().any(|pattern| {
+                    pck::glob_match(pattern, &entry.path) || pck::glob_match(pattern, name)
+                })
+        })
+        .collect();
+    shown.sort_by(|a, b| (a.0, &a.1.path).cmp(&(b.0, &b.1.path)));
+
+    // 只有需要显示或筛选类型时才读取文件头
+    let need_types =
+        args.verbose || !args.types.is_empty() || matches!(args.format, OutputFormat::Json);
+    let mut types: HashMap<&str, ResType> = HashMap::new();
+    if need_types {
+        // Each worker reads through its own handle so seeks don't interfere.
+        let detected = Pool::new(threads).map_init(
+            &shown,
+            || None,
+            |reader, (name, entry)| {
+                let reader = match reader {
+                    Some(reader) => reader,
+                    None => reader.insert(BufReader::new(File::open(&args.pck)?)),
+                };
+                let head = res_type::read_head(reader, entry)
+                    .with_context(|| format!("Failed to read {}", entry.path))?;
+                Ok(res_type::detect(name, &head))
+            },
+        )?;
+        for ((_, entry), kind) in shown.iter().zip(detected) {
+            types.insert(&entry.path, kind);
+        }
+        if !args.types.is_empty() {
+            shown.retain(|(_, entry)| args.types.contains(&types[entry.path.as_str()]));
+        }
+    }
+
+    // 实际路径 -> 同组中排在最前的路径
+    let mut same_as: HashMap<String, String> = HashMap::new();
+    if args.duplicates {
+        for group in pck::find_identical_data(&mut file)? {
+            for path in &group.paths[1..] {
+                same_as.insert(path.clone(), group.paths[0].clone());
+            }
+        }
+    }
+    let badge = |path: &str| {
+        same_as
+            .get(path)
+            .map(|first| format!("\t[same data as {}]", mapping.logical(first)))
+            .unwrap_or_default()
+    };
+
+    if let OutputFormat::Json = args.format {
+        let store = ProvenanceStore::load(&args.pck)?;
+        let items: Vec<String> = shown
+            .iter()
+            .map(|(name, entry)| {
+                let digest = EntryDigest {
+                    size: entry.size,
+                    md5: entry.md5,
+                };
+                let origin = store.lookup(&entry.path, &digest);
+                format!(
+                    "{{\"path\":{},\"name\":{},\"type\":\"{}\",\"size\":{},\"md5\":\"{}\",\"mod\":{},\"written_at\":{},\"same_as\":{}}}",
+                    json_string(&entry.path),
+                    json_string(name),
+                    types[entry.path.as_str()].as_str(),
+                    entry.size,
+                    entry.md5_hex(),
+                    origin.map_or("null".to_string(), |p| json_string(&p.mod_source)),
+                    origin.map_or("null".to_string(), |p| p.written_at.to_string()),
+                    same_as
+                        .get(&entry.path)
+                        .map_or("null".to_string(), |first| json_string(first))
+                )
+            })
+            .collect();
+        println!("[{}]", items.join(","));
+        return Ok(());
+    }
+
+    if !args.verbose {
+        for (name, entry) in &shown {
+            println!("{}{}", name, badge(&entry.path));
+        }
+        return Ok(());
+    }
+
+    let store = ProvenanceStore::load(&args.pck)?;
+    for (name, entry) in &shown {
+        let digest = EntryDigest {
+            size: entry.size,
+            md5: entry.md5,
+        };
+        let origin = match store.lookup(&entry.path, &digest) {
+            Some(p) => format!(
+                "{} @ {}",
+                p.mod_source,
+                provenance::format_timestamp(p.written_at)
+            ),
+            None => "-".to_string(),
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}{}",
+            name,
+            entry.size,
+            entry.md5_hex(),
+            types[entry.path.as_str()].as_str(),
+            origin,
+            badge(&entry.path)
+        );
+    }
+    Ok(())
+}
+
+fn run_lint_manifest(args: LintManifestArgs) -> Result<()> {
+    let manifest_path = if args.manifest.is_dir() {
+        args.manifest.join("replace.toml")
+    } else {
+        args.manifest
+    };
+
+    let diagnostics = lint::lint_manifest(&manifest_path)?;
+    let file = manifest_path.display().to_string();
+
+    match args.format {
+        OutputFormat::Text => {
+            print!("{}", lint::render_text(&file, &diagnostics));
+            if diagnostics.is_empty() {
+                println!("{}: no problems found", file);
+            }
+        }
+        OutputFormat::Json => println!("{}", lint::render_json(&file, &diagnostics)),
+    }
+
+    let failed = diagnostics.iter().any(|d| {
+        d.severity == lint::Severity::Error
+            || (args.deny_warnings && d.severity == lint::Severity::Warning)
+    });
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_merge(args: MergeArgs, threads: usize, limits: pck::TableLimits) -> Result<()> {
+    recover_interrupted(&args.pck)?;
+    let target = match &args.output {
+        Some(output) => {
+            copy_for_output(&args.pck, output)?;
+            output.clone()
+        }
+        None => args.pck.clone(),
+    };
+    let io = pck::IoOptions {
+        jobs: threads,
+        ..config::load().io.resolve(std::fs::metadata(&target)?.len())
+    };
+    let summary = match merge::merge_pck(&target, &args.overlay, &io, limits) {
+        Ok(summary) => summary,
+        Err(err) => {
+            if args.output.is_some() {
+                let _ = std::fs::remove_file(&target);
+            }
+            return Err(err.context(format!(
+                "Failed to merge {} into {}",
+                args.overlay.display(),
+                args.pck.display()
+            )));
+        }
+    };
+
+    for path in &summary.added {
+        println!("+ {}", path);
+    }
+    for path in &summary.replaced {
+        println!("~ {}", path);
+    }
+    println!(
+        "Merged into {}: {} added, {} replaced, {} already identical",
+        target.display(),
+        summary.added.len(),
+        summary.replaced.len(),
+        summary.unchanged
+    );
+    Ok(())
+}
+
+#[cfg(feature = "mount")]
+fn run_mount(args: MountArgs) -> Result<()> {
+    let capture = args
+        .capture
+        .as_deref()
+        .map(|dir| mount::Capture::new(dir, &args.pck, args.game_version.as_deref()))
+        .transpose()?;
+    let mode = match &args.capture {
+        Some(dir) => format!("writes are captured to {}", dir.display()),
+        None => "read-only".to_string(),
+    };
+    // 截断与写入各捕获一次，同一路径只输出一行
+    let mut captured = std::collections::BTreeSet::new();
+    mount::mount(&args.pck, &args.mountpoint, capture, |event| match event {
+        mount::MountEvent::Skipped(path) => {
+            eprintln!("Skipped {} (its path clashes with another entry)", path)
+        }
+        mount::MountEvent::Ready => println!(
+            "Mounted {} on {} ({}); press Ctrl+C to unmount",
+            args.pck.display(),
+            args.mountpoint.display(),
+            mode
+        ),
+        mount::MountEvent::Captured(path) => {
+            if captured.insert(path.to_string()) {
+                println!("~ {}", path);
+            }
+        }
+        mount::MountEvent::CaptureFailed(path, err) => {
+            eprintln!("Failed to capture {}: {:#}", path, err)
+        }
+    })
+    .with_context(|| format!("Failed to mount {}", args.pck.display()))?;
+
+    println!("Unmounted {}", args.mountpoint.display());
+    if let Some(dir) = &args.capture
+        && dir.join("replace.toml").is_file()
+    {
+        println!(
+            "Captured changes are in {}; apply them like any other mod folder",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_new_mod(args: NewModArgs) -> Result<()> {
+    scaffold::new_mod(
+        &args.name,
+        args.pck.as_deref(),
+        args.game_version.as_deref(),
+    )
+    .with_context(|| format!("Failed to create mod: {}", args.name.display()))?;
+
+    println!("Created mod skeleton: {}", args.name.display());
+    Ok(())
+}
+
+fn run_pack(args: PackArgs) -> Result<()> {
+    if !args.source.is_dir() {
+        anyhow::bail!("Path is not a directory: {}", args.source.display());
+    }
+
+    let [major, minor, patch] = args.godot_version;
+    let mut options = pck::PackOptions::new().godot_version(major, minor, patch);
+    let io_config = config::load().io;
+    if io_config != config::IoConfig::default() {
+        options = options.io(io_config.resolve(0));
+    }
+    if let Some(template) = &args.embed {
+        options = options.embed_into(template);
+    }
+
+    let count = pck::pack_directory(&args.source, &args.output, &options)
+    .with_context(|| format!("Failed to pack folder: {}", args.source.display()))?;
+
+    match &args.embed {
+        Some(template) => println!(
+            "Packed {} file(s) into executable {} (template: {})",
+            count,
+            args.output.display(),
+            template.display()
+        ),
+        None => println!("Packed {} file(s) into {}", count, args.output.display()),
+    }
+    Ok(())
+}
+
+/// Parse `3.5.2` into `[3, 5, 2]`.
+fn parse_godot_version(value: &str) -> std::result::Result<[u32; 3], String> {
+    let parts: Vec<u32> = value
+        .split('.')
+        .map(|part| part.parse::<u32>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| format!("invalid version: {}", value))?;
+
+    parts
+        .try_into()
+        .map_err(|_| format!("expected MAJOR.MINOR.PATCH, got: {}", value))
+}
+
+fn run_rebase(args: RebaseArgs, limits: pck::TableLimits) -> Result<()> {
+    let entries = rebase::rebase_mod(
+        &args.assets,
+        &args.old,
+        &args.new,
+        &args.output,
+        args.game_version.as_deref(),
+        limits,
+    )
+    .with_context(|| format!("Failed to rebase mod: {}", args.assets.display()))?;
+
+    for entry in &entries {
+        match &entry.status {
+            RebaseStatus::Unchanged | RebaseStatus::Added => {}
+            RebaseStatus::Moved(to) => println!("moved     {} -> {}", entry.path, to),
+            RebaseStatus::OriginalChanged => {
+                println!("CHECK     {}: original changed in the new version", entry.path)
+            }
+            RebaseStatus::NowExists => {
+                println!("CHECK     {}: now exists in the new version", entry.path)
+            }
+            RebaseStatus::Missing => {
+                println!("CHECK     {}: not found in the new version", entry.path)
+            }
+        }
+    }
+
+    let attention = entries.iter().filter(|e| e.status.needs_attention()).count();
+    println!(
+        "Rebased mod written to {} ({} target(s), {} need manual attention)",
+        args.output.display(),
+        entries.len(),
+        attention
+    );
+    Ok(())
+}
+
+fn run_remap(
+    args: RemapArgs,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    let mapping = PathMapping::load(&args.map)?;
+    recover_interrupted(&args.pck)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&args.pck)
+        .with_context(|| format!("Failed to open PCK file: {}", args.pck.display()))?;
+    let mut file = pck::Keyed::new(file, key).with_limits(limits);
+    let archive_size = file.get_ref().metadata()?.len();
+    let (header, index) = pck::read_header_and_index(&mut file)?;
+
+    let renames: Vec<(&str, &str)> = mapping
+        .pairs()
+        .map(|(physical, logical)| {
+            if args.reverse {
+                (logical, physical)
+            } else {
+                (physical, logical)
+            }
+        })
+        .collect();
+    let io = config::load().io.resolve(archive_size);
+    let renamed = pck::rename_entries(&mut file, &header, &index, renames, &io)
+        .with_context(|| format!("Failed to remap PCK file: {}", args.pck.display()))?;
+
+    println!(
+        "Renamed {} of {} mapped entr{}",
+        renamed,
+        mapping.len(),
+        if mapping.len() == 1 { "y" } else { "ies" }
+    );
+    Ok(())
+}
+
+fn run_repair(
+    args: RepairArgs,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    let writes = args.dedupe_entries || args.share_identical_data || args.fix_table;
+    if writes {
+        recover_interrupted(&args.pck)?;
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(writes)
+        .open(&args.pck)
+        .with_context(|| format!("Failed to open PCK file: {}", args.pck.display()))?;
+    let mut file = pck::Keyed::new(file, key).with_limits(limits);
+
+    let salvage = if args.fix_table {
+        pck::salvage_table(&mut file)?
+    } else {
+        pck::check_table(&mut file)?
+    };
+    if !salvage.is_intact() {
+        print_salvage(&salvage, args.fix_table);
+        if !args.fix_table {
+            println!("Run again with --fix-table to rewrite the table without them");
+            return Ok(());
+        }
+    }
+
+    let duplicates = if args.dedupe_entries {
+        pck::dedupe_entries(&mut file)?
+    } else {
+        pck::find_duplicate_entries(&mut file)?
+    };
+
+    if duplicates.is_empty() {
+        println!("No duplicate entries found");
+        if args.share_identical_data {
+            share_identical_data(&mut file)?;
+        }
+        return Ok(());
+    }
+
+    for dup in &duplicates {
+        println!(
+            "Duplicate entry {}: keeping data at {}, {} {} older cop{} at {:?}",
+            dup.path,
+            dup.kept_offset,
+            if args.dedupe_entries {
+                "dropped"
+            } else {
+                "found"
+            },
+            dup.dropped_offsets.len(),
+            if dup.dropped_offsets.len() == 1 {
+                "y"
+            } else {
+                "ies"
+            },
+            dup.dropped_offsets
+        );
+    }
+
+    if args.dedupe_entries {
+        println!("Removed duplicates for {} path(s)", duplicates.len());
+        if args.share_identical_data {
+            share_identical_data(&mut file)?;
+        }
+    } else {
+        println!("Run again with --dedupe-entries to fix");
+    }
+    Ok(())
+}
+
+fn print_salvage(salvage: &pck::TableSalvage, fixed: bool) {
+    for dropped in &salvage.dropped {
+        let reason = match &dropped.reason {
+            pck::DropReason::InvalidPath => "invalid path".to_string(),
+            pck::DropReason::DataPastEnd { end, file_len } => {
+                format!("data ends at {}, past the end of the file ({})", end, file_len)
+            }
+            pck::DropReason::DataInTable => "data overlaps the header or entry table".to_string(),
+        };
+        println!(
+            "{} entry #{} {:?} at table offset {}: {}",
+            if fixed { "Dropped" } else { "Unusable" },
+            dropped.index,
+            dropped.path,
+            dropped.table_offset,
+            reason
+        );
+    }
+    if let Some(index) = salvage.unreadable_from {
+        println!(
+            "The entry table cannot be parsed past entry #{}; {} claimed entr{} {}",
+            index,
+            salvage.lost(),
+            if salvage.lost() == 1 { "y is" } else { "ies are" },
+            if fixed { "dropped" } else { "lost" }
+        );
+    }
+    println!(
+        "{} {} of {} entries",
+        if fixed { "Rewrote the entry table with" } else { "Usable:" },
+        salvage.kept,
+        salvage.claimed
+    );
+}
+
+fn share_identical_data(file: &mut pck::Keyed<File>) -> Result<()> {
+    let groups = pck::share_identical_data(file)?;
+    if groups.is_empty() {
+        println!("No byte-identical entries stored separately");
+        return Ok(());
+    }
+
+    for group in &groups {
+        println!(
+            "Shared {} bytes between {} entries: {}",
+            group.size,
+            group.paths.len(),
+            group.paths.join(", ")
+        );
+    }
+    let reclaimable: u64 = groups.iter().map(pck::IdenticalData::reclaimable).sum();
+    println!(
+        "Relinked {} group(s); {} bytes of duplicate data are no longer referenced",
+        groups.len(),
+        reclaimable
+    );
+    Ok(())
+}
+
+fn run_restore(args: RestoreArgs) -> Result<()> {
+    let settings = config::load().backup;
+    if args.list {
+        let backups = backup::list(&args.pck, &settings)?;
+        if backups.is_empty() {
+            println!("No backups of {}", args.pck.display());
+        }
+        for backup in backups {
+            match backup::label(&backup) {
+                Some(label) => println!("{} ({})", backup.display(), label),
+                None => println!("{}", backup.display()),
+            }
+        }
+        return Ok(());
+    }
+
+    let backup = match &args.from {
+        Some(backup) => backup::restore_from(&args.pck, backup).map(|()| backup.clone()),
+        None => backup::restore(&args.pck, &settings),
+    }
+    .with_context(|| format!("Failed to restore PCK file: {}", args.pck.display()))?;
+    println!("Restored {} from {}", args.pck.display(), backup.display());
+    Ok(())
+}
+
+fn run_commit(args: PendingArgs) -> Result<()> {
+    let pending = pending::commit(&args.pck)
+        .with_context(|| format!("Failed to commit staged changes: {}", args.pck.display()))?;
+    println!(
+        "Committed changes staged at {}: wrote {} bytes in {} place(s) of {}",
+        provenance::format_timestamp(pending.created_at),
+        pending.patch_bytes(),
+        pending.patch_count(),
+        args.pck.display()
+    );
+    Ok(())
+}
+
+fn run_abort(args: PendingArgs) -> Result<()> {
+    let pending = pending::abort(&args.pck)
+        .with_context(|| format!("Failed to discard staged changes: {}", args.pck.display()))?;
+    println!(
+        "Discarded changes staged at {}: {} is back to {} bytes",
+        provenance::format_timestamp(pending.created_at),
+        args.pck.display(),
+        pending.base_len
+    );
+    Ok(())
+}
+
+fn run_tweak_pack(args: TweakPackArgs) -> Result<()> {
+    let count = tweak_pack::write_index(&args.folder, &args.version).with_context(|| {
+        format!("Failed to index asset pack: {}", args.folder.display())
+    })?;
+    println!(
+        "Indexed {} file{} into {}",
+        count,
+        if count == 1 { "" } else { "s" },
+        args.folder.join(tweak_pack::INDEX_FILE).display()
+    );
+    Ok(())
+}
+
+fn run_verify(
+    args: VerifyArgs,
+    threads: usize,
+    key: Option<pck::EncryptionKey>,
+    limits: pck::TableLimits,
+) -> Result<()> {
+    let archive_size = std::fs::metadata(&args.pck)
+        .with_context(|| format!("Failed to open PCK file: {}", args.pck.display()))?
+        .len();
+    let io_config = config::load().io;
+
+    let options = pck::VerifyOptions {
+        sample_percent: args.sample,
+        seed: args.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        }),
+        jobs: threads,
+        fail_fast: args.fail_fast,
+        io: (io_config != config::IoConfig::default()).then(|| io_config.resolve(archive_size)),
+        encryption_key: key,
+        limits,
+    };
+    let report = pck::verify_pck(&args.pck, &options)
+        .with_context(|| format!("Failed to verify PCK file: {}", args.pck.display()))?;
+
+    for failure in &report.failures {
+        println!("FAILED {}: {}", failure.path, failure.reason);
+    }
+
+    println!(
+        "Checked {} of {} entries ({:.1} MiB) in {:.2}s, {:.1} MiB/s with {} job(s)",
+        report.checked_entries,
+        report.total_entries,
+        report.checked_bytes as f64 / (1024.0 * 1024.0),
+        report.elapsed.as_secs_f64(),
+        report.throughput() / (1024.0 * 1024.0),
+        threads
+    );
+    if report.stopped_early {
+        println!("Stopped at the first mismatch (--fail-fast)");
+    }
+
+    if report.failures.is_empty() {
+        println!("No mismatches found");
+        Ok(())
+    } else {
+        println!(
+            "{} entr{} failed verification",
+            report.failures.len(),
+            if report.failures.len() == 1 {
+                "y"
+            } else {
+                "ies"
+            }
+        );
+        std::process::exit(1);
+    }
+}
+
+fn run_stress(args: StressArgs, threads: usize) -> Result<()> {
+    let archive_size = std::fs::metadata(&args.pck)
+        .with_context(|| format!("Failed to open PCK file: {}", args.pck.display()))?
+        .len();
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let options = stress::StressOptions {
+        cycles: args.cycles,
+        seed,
+        jobs: threads,
+        io: config::load().io.resolve(archive_size),
+    };
+    let scratch = args.scratch.unwrap_or_else(stress::default_scratch);
+    println!("Running {} cycles with seed {}", args.cycles, seed);
+
+    let report = stress::run(&args.pck, &scratch, &options, |cycle, operation, elapsed| {
+        println!(
+            "[{}/{}] {} {:.1} ms",
+            cycle,
+            args.cycles,
+            operation.name(),
+            elapsed.as_secs_f64() * 1000.0
+        );
+    })
+    .with_context(|| format!("Stress run failed (replay with --seed {})", seed))?;
+
+    println!(
+        "Completed {} cycles: wrote {} entries, deleted {} entries, final size {} bytes",
+        report.cycles, report.entries_written, report.entries_deleted, report.final_size
+    );
+    println!(
+        "{:<8} {:>6} {:>10} {:>10} {:>10} {:>10}",
+        "op", "count", "min ms", "median ms", "p95 ms", "max ms"
+    );
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+    for (operation, t) in &report.timings {
+        println!(
+            "{:<8} {:>6} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+            operation.name(),
+            t.count,
+            ms(t.min),
+            ms(t.median),
+            ms(t.p95),
+            ms(t.max)
+        );
+    }
+    Ok(())
+}
+
+/// Parse a content type name such as `texture`.
+fn parse_res_type(value: &str) -> std::result::Result<ResType, String> {
+    ResType::from_name(value).ok_or_else(|| {
+        let names: Vec<&str> = ResType::ALL.iter().map(|kind| kind.as_str()).collect();
+        format!("expected one of {}, got: {}", names.join(", "), value)
+    })
+}
+
+/// Parse `5%` or `5` into a percentage in (0, 100].
+fn parse_percent(value: &str) -> std::result::Result<f64, String> {
+    let number = value.trim().trim_end_matches('%');
+    match number.parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => Err(format!("expected a percentage in (0, 100], got: {}", value)),
+    }
+}
@@ -0,0 +1,221 @@
+//! 估算把体积较大的未压缩 entry 转成引擎压缩格式后 PCK 能缩小多少，
+//! 为整理 PCK 和制作大体积贴图 MOD 提供参考。
+//!
+//! 不依赖压缩库：按块做一遍简化的 LZ 匹配，匹配按固定开销计，剩下的字面量按零阶熵计，
+//! 以此估计无损压缩后的大小。实际的 zstd/deflate 还会做熵编码与更长的窗口，通常比估计略小。
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::pck;
+use crate::res_type::{self, HEAD_LEN};
+
+/// 分块估计，让结果能适应同一文件中不同区域的数据特征；也是 LZ 匹配的窗口大小
+const BLOCK_SIZE: usize = 64 * 1024;
+/// 最短匹配长度
+const MIN_MATCH: usize = 4;
+/// 每个匹配（距离 + 长度）大约占用的位数
+const MATCH_COST_BITS: f64 = 24.0;
+
+/// 一个 entry 的存储方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// 数据本身已压缩（PNG、Ogg、压缩资源等），再压缩几乎没有收益
+    Compressed(&'static str),
+    /// 未压缩，括号内为内容类型（见 [`res_type::ResType::as_str`]）
+    Uncompressed(&'static str),
+}
+
+/// 一个候选 entry 的估计结果
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub path: String,
+    pub kind: &'static str,
+    pub size: u64,
+    pub estimated: u64,
+}
+
+impl Estimate {
+    pub fn saving(&self) -> u64 {
+        self.size - self.estimated
+    }
+}
+
+/// 整个 PCK 的分析结果
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub archive_size: u64,
+    /// 不小于下限的未压缩 entry，按可节省的字节数从大到小排列
+    pub candidates: Vec<Estimate>,
+    /// 因已压缩而跳过的 entry 数与总大小
+    pub compressed_entries: usize,
+    pub compressed_bytes: u64,
+}
+
+impl Analysis {
+    pub fn total_saving(&self) -> u64 {
+        self.candidates.iter().map(Estimate::saving).sum()
+    }
+}
+
+/// 分析 PCK 中大小不低于 `min_size` 的 entry；entry 表按 `limits` 读取
+pub fn analyze(pck_path: &Path, min_size: u64, limits: pck::TableLimits) -> Result<Analysis> {
+    let file =
+        File::open(pck_path).with_context(|| format!("无法打开文件: {}", pck_path.display()))?;
+    let archive_size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let (_, entries) = pck::read_entries(&mut reader, None, limits)?;
+
+    let mut analysis = Analysis {
+        archive_size,
+        ..Analysis::default()
+    };
+    for (path, entry) in entries {
+        if entry.size < min_size {
+            continue;
+        }
+        if entry.is_encrypted() {
+            // 加密数据与随机数据无异，无法再压缩
+            analysis.compressed_entries += 1;
+            analysis.compressed_bytes += entry.size;
+            continue;
+        }
+        reader
+            .seek(SeekFrom::Start(entry.offset))
+            .with_context(|| format!("无法定位文件数据: {}", path))?;
+        let mut data = (&mut reader).take(entry.size);
+
+        let mut head = Vec::with_capacity(HEAD_LEN);
+        (&mut data)
+            .take(HEAD_LEN as u64)
+            .read_to_end(&mut head)
+            .with_context(|| format!("无法读取文件数据: {}", path))?;
+        match classify(&path, &head) {
+            Storage::Compressed(_) => {
+                analysis.compressed_entries += 1;
+                analysis.compressed_bytes += entry.size;
+            }
+            Storage::Uncompressed(kind) => {
+                let estimated = estimate_compressed_size(head.as_slice().chain(data))
+                    .with_context(|| format!("无法读取文件数据: {}", path))?;
+                analysis.candidates.push(Estimate {
+                    path,
+                    kind,
+                    size: entry.size,
+                    estimated: estimated.min(entry.size),
+                });
+            }
+        }
+    }
+
+    analysis
+        .candidates
+        .sort_by(|a, b| b.saving().cmp(&a.saving()).then(a.path.cmp(&b.path)));
+    Ok(analysis)
+}
+
+/// 根据文件头判断数据是否已压缩，未压缩时按 [`res_type::detect`] 给出内容类型
+pub fn classify(path: &str, head: &[u8]) -> Storage {
+    match res_type::compressed_format(head) {
+        Some(format) => Storage::Compressed(format),
+        None => Storage::Uncompressed(res_type::detect(path, head).as_str()),
+    }
+}
+
+/// 按块估计无损压缩后的字节数
+pub fn estimate_compressed_size(mut reader: impl Read) -> std::io::Result<u64> {
+    let mut block = vec![0u8; BLOCK_SIZE];
+    let mut bits = 0.0f64;
+    loop {
+        let mut filled = 0;
+        while filled < BLOCK_SIZE {
+            match reader.read(&mut block[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        bits += block_cost_bits(&block[..filled]);
+        if filled < BLOCK_SIZE {
+            break;
+        }
+    }
+    Ok((bits / 8.0).ceil() as u64)
+}
+
+/// 贪心 LZ 匹配：每个位置用前 4 字节的哈希查找块内最近一次出现的位置
+fn block_cost_bits(block: &[u8]) -> f64 {
+    let mut last_seen = vec![usize::MAX; 1 << 16];
+    let mut literals = [0u64; 256];
+    let mut matches = 0u64;
+
+    let mut i = 0;
+    while i + MIN_MATCH <= block.len() {
+        let key = u32::from_le_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]]);
+        let slot = (key.wrapping_mul(2_654_435_761) >> 16) as usize;
+        let candidate = last_seen[slot];
+        last_seen[slot] = i;
+
+        if candidate != usize::MAX
+            && block[candidate..candidate + MIN_MATCH] == block[i..i + MIN_MATCH]
+        {
+            let mut len = MIN_MATCH;
+            while i + len < block.len() && block[candidate + len] == block[i + len] {
+                len += 1;
+            }
+            matches += 1;
+            i += len;
+        } else {
+            literals[block[i] as usize] += 1;
+            i += 1;
+        }
+    }
+    for &byte in &block[i..] {
+        literals[byte as usize] += 1;
+    }
+
+    entropy_bits(&literals) + matches as f64 * MATCH_COST_BITS
+}
+
+fn entropy_bits(counts: &[u64; 256]) -> f64 {
+    let total = counts.iter().sum::<u64>() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let count = count as f64;
+            -count * (count / total).log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repetitive_data_estimates_smaller_than_noise() {
+        let text = b"extends Node\nfunc _ready():\n\tpass\n".repeat(4000);
+        let estimated = estimate_compressed_size(text.as_slice()).unwrap();
+        assert!(estimated < text.len() as u64 / 20);
+
+        // 伪随机数据几乎无法压缩
+        let mut state = 0x2545_f491_u32;
+        let noise: Vec<u8> = (0..BLOCK_SIZE * 2)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let estimated = estimate_compressed_size(noise.as_slice()).unwrap();
+        assert!(estimated > noise.len() as u64 * 95 / 100);
+        assert_eq!(estimate_compressed_size(&[][..]).unwrap(), 0);
+    }
+}
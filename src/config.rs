@@ -0,0 +1,422 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result, anyhow, bail};
+use toml::{Table, Value};
+
+use crate::dirs;
+use crate::hotkey::Hotkey;
+use crate::pck::{IoOptions, MoveVerification, TableLimits};
+
+/// 工具设置文件名，放在配置目录（见 [`crate::dirs`]）
+const CONFIG_FILE: &str = "bpb_enhance.toml";
+/// 放在可执行文件旁时启用便携模式
+const PORTABLE_FLAG: &str = "portable.flag";
+
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+/// 工具自身的设置（与 MOD 的 replace.toml 无关）
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// 顶层 `threads`：所有并行任务共用的线程数
+    pub threads: Threads,
+    pub io: IoConfig,
+    pub staging: StagingConfig,
+    pub limits: LimitsConfig,
+    pub backup: BackupConfig,
+    pub gui: GuiConfig,
+}
+
+/// 并行线程数：`"auto"`（默认）为逻辑核心数，共享机器或机械硬盘上可以调低以减少并发 IO
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Threads {
+    #[default]
+    Auto,
+    Count(usize),
+}
+
+impl Threads {
+    pub fn resolve(self) -> usize {
+        match self {
+            Self::Auto => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            Self::Count(count) => count,
+        }
+    }
+}
+
+impl std::str::FromStr for Threads {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        match value.parse::<usize>() {
+            Ok(count) if count > 0 => Ok(Self::Count(count)),
+            _ => Err(format!("必须是正整数或 \"auto\"，实际为: {}", value)),
+        }
+    }
+}
+
+/// `[io]` 表；未设置的项按 PCK 大小自动选择
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IoConfig {
+    pub read_buffer: Option<usize>,
+    pub write_buffer: Option<usize>,
+    pub chunk_size: Option<usize>,
+    pub verify_moves: Option<MoveVerification>,
+    /// GUI 确认修改前是否在后台预读需要搬移的数据，默认开启
+    pub prefetch: Option<bool>,
+}
+
+/// `[staging]` 表：中间产物暂存目录的位置与大小上限
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StagingConfig {
+    pub dir: Option<PathBuf>,
+    pub max_size: Option<u64>,
+}
+
+/// `[limits]` 表：解析 entry 表时的安全上限
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LimitsConfig {
+    pub max_entries: Option<u32>,
+    pub max_path_len: Option<u32>,
+}
+
+/// `[backup]` 表：修改前备份的位置、保留份数与压缩
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BackupConfig {
+    /// 备份所在目录，未设置时为 PCK 所在目录；相对路径相对于数据目录（见 [`crate::dirs`]）
+    pub dir: Option<PathBuf>,
+    /// 保留最近几份备份，未设置时见 backup 模块的默认值
+    pub keep: Option<usize>,
+    /// 以 gzip 压缩备份时的级别（1–9），未设置时不压缩
+    pub compression_level: Option<u32>,
+}
+
+/// `[gui]` 表：只对 GUI 生效的设置
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GuiConfig {
+    /// 重新应用最近一次修改的全局快捷键，未设置时不注册
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub reapply_hotkey: Option<Hotkey>,
+    /// 内置修改资源包的目录，未设置时为可执行文件旁的 `tweaks`
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub tweak_pack: Option<PathBuf>,
+}
+
+impl LimitsConfig {
+    pub fn resolve(&self) -> TableLimits {
+        let default = TableLimits::DEFAULT;
+        TableLimits {
+            max_entries: self.max_entries.unwrap_or(default.max_entries),
+            max_path_len: self.max_path_len.unwrap_or(default.max_path_len),
+        }
+    }
+}
+
+impl IoConfig {
+    /// 以自动检测结果为基础，覆盖用户显式设置的项
+    pub fn resolve(&self, archive_size: u64) -> IoOptions {
+        let auto = IoOptions::auto(archive_size);
+        IoOptions {
+            read_buffer: self.read_buffer.unwrap_or(auto.read_buffer),
+            write_buffer: self.write_buffer.unwrap_or(auto.write_buffer),
+            chunk_size: self.chunk_size.unwrap_or(auto.chunk_size),
+            verify_moves: self.verify_moves.unwrap_or(auto.verify_moves),
+            jobs: auto.jobs,
+            cancel: auto.cancel,
+            overwrite_in_place: auto.overwrite_in_place,
+        }
+    }
+}
+
+/// 可执行文件所在目录：便携模式下设置、配置列表、暂存区与崩溃日志都放在这里
+pub fn exe_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.to_path_buf())
+}
+
+/// 命令行 `--portable`
+pub fn enable_portable() {
+    PORTABLE.store(true, Ordering::Relaxed);
+}
+
+/// 便携模式下工具自身的文件都放在可执行文件旁而不是系统目录（见 [`crate::dirs`]），
+/// 整个工具目录可以放到 U 盘或游戏目录中直接分享
+pub fn is_portable() -> bool {
+    PORTABLE.load(Ordering::Relaxed)
+        || exe_dir().is_some_and(|dir| dir.join(PORTABLE_FLAG).is_file())
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_file(CONFIG_FILE)
+}
+
+/// 读取设置；文件不存在时使用默认值，格式错误时提示并使用默认值
+pub fn load() -> Config {
+    let Some(path) = config_path().filter(|p| p.is_file()) else {
+        return Config::default();
+    };
+
+    let result = std::fs::read_to_string(&path)
+        .with_context(|| format!("无法读取设置: {}", path.display()))
+        .and_then(|content| parse(&content))
+        .with_context(|| format!("设置文件格式错误: {}", path.display()));
+
+    match result {
+        Ok(config) => config,
+        Err(err) => {
+            println!("⚠ {:#}，将使用默认设置", err);
+            Config::default()
+        }
+    }
+}
+
+fn parse(content: &str) -> Result<Config> {
+    let table: Table = content.parse()?;
+    let mut config = Config::default();
+
+    if let Some(threads) = table.get("threads") {
+        config.threads = match threads {
+            Value::Integer(n) => n.to_string().parse(),
+            Value::String(s) => s.parse(),
+            _ => Err("必须是正整数或 \"auto\"".to_string()),
+        }
+        .map_err(|err| anyhow!("threads {}", err))?;
+    }
+
+    if let Some(io) = table.get("io") {
+        let io = io.as_table().ok_or_else(|| anyhow!("io 必须是表"))?;
+        for (key, value) in io {
+            let invalid = || format!("io.{} 无效", key);
+            match key.as_str() {
+                "read_buffer" => config.io.read_buffer = parse_size(value).with_context(invalid)?,
+                "write_buffer" => {
+                    config.io.write_buffer = parse_size(value).with_context(invalid)?
+                }
+                "chunk_size" => config.io.chunk_size = parse_size(value).with_context(invalid)?,
+                "prefetch" => {
+                    config.io.prefetch = Some(
+                        value
+                            .as_bool()
+                            .ok_or_else(|| anyhow!("必须是布尔值"))
+                            .with_context(invalid)?,
+                    )
+                }
+                "verify_moves" => {
+                    config.io.verify_moves =
+                        Some(parse_move_verification(value).with_context(invalid)?)
+                }
+                other => bail!("[io] 中未知的字段: {}", other),
+            }
+        }
+    }
+
+    if let Some(staging) = table.get("staging") {
+        let staging = staging
+            .as_table()
+            .ok_or_else(|| anyhow!("staging 必须是表"))?;
+        for (key, value) in staging {
+            match key.as_str() {
+                "dir" => {
+                    let dir = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("staging.dir 必须是字符串"))?;
+                    config.staging.dir = Some(PathBuf::from(dir));
+                }
+                "max_size" => {
+                    config.staging.max_size = parse_size(value)
+                        .context("staging.max_size 无效")?
+                        .map(|size| size as u64);
+                }
+                other => bail!("[staging] 中未知的字段: {}", other),
+            }
+        }
+    }
+
+    if let Some(limits) = table.get("limits") {
+        let limits = limits
+            .as_table()
+            .ok_or_else(|| anyhow!("limits 必须是表"))?;
+        for (key, value) in limits {
+            let limit = value
+                .as_integer()
+                .and_then(|n| u32::try_from(n).ok())
+                .filter(|n| *n > 0)
+                .ok_or_else(|| anyhow!("limits.{} 必须是正整数", key));
+            match key.as_str() {
+                "max_entries" => config.limits.max_entries = Some(limit?),
+                "max_path_len" => config.limits.max_path_len = Some(limit?),
+                other => bail!("[limits] 中未知的字段: {}", other),
+            }
+        }
+    }
+
+    if let Some(backup) = table.get("backup") {
+        let backup = backup
+            .as_table()
+            .ok_or_else(|| anyhow!("backup 必须是表"))?;
+        for (key, value) in backup {
+            match key.as_str() {
+                "dir" => {
+                    let dir = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("backup.dir 必须是字符串"))?;
+                    config.backup.dir = Some(PathBuf::from(dir));
+                }
+                "keep" => {
+                    let keep = value
+                        .as_integer()
+                        .and_then(|n| usize::try_from(n).ok())
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| anyhow!("backup.keep 必须是正整数"))?;
+                    config.backup.keep = Some(keep);
+                }
+                "compression_level" => {
+                    let level = value
+                        .as_integer()
+                        .and_then(|n| u32::try_from(n).ok())
+                        .filter(|n| (1..=9).contains(n))
+                        .ok_or_else(|| anyhow!("backup.compression_level 必须是 1 到 9 的整数"))?;
+                    config.backup.compression_level = Some(level);
+                }
+                other => bail!("[backup] 中未知的字段: {}", other),
+            }
+        }
+    }
+
+    if let Some(gui) = table.get("gui") {
+        let gui = gui.as_table().ok_or_else(|| anyhow!("gui 必须是表"))?;
+        for (key, value) in gui {
+            match key.as_str() {
+                "reapply_hotkey" => {
+                    let hotkey = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("gui.reapply_hotkey 必须是字符串"))?;
+                    config.gui.reapply_hotkey = Some(
+                        hotkey
+                            .parse()
+                            .map_err(|err| anyhow!("gui.reapply_hotkey 无效: {}", err))?,
+                    );
+                }
+                "tweak_pack" => {
+                    let dir = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("gui.tweak_pack 必须是字符串"))?;
+                    config.gui.tweak_pack = Some(PathBuf::from(dir));
+                }
+                other => bail!("[gui] 中未知的字段: {}", other),
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+/// `"off"`、`"warn"` 或 `"repair"`
+fn parse_move_verification(value: &Value) -> Result<MoveVerification> {
+    match value.as_str().map(str::to_ascii_lowercase).as_deref() {
+        Some("off") => Ok(MoveVerification::Off),
+        Some("warn") => Ok(MoveVerification::Warn),
+        Some("repair") => Ok(MoveVerification::Repair),
+        _ => bail!("必须是 \"off\"、\"warn\" 或 \"repair\""),
+    }
+}
+
+/// 字节数：整数，或带单位的字符串（`"256KiB"`、`"4MiB"`）；`"auto"` 表示自动
+fn parse_size(value: &Value) -> Result<Option<usize>> {
+    let size = match value {
+        Value::Integer(n) if *n > 0 => *n as u64,
+        Value::String(s) if s.eq_ignore_ascii_case("auto") => return Ok(None),
+        Value::String(s) => {
+            let s = s.trim();
+            let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            let (number, unit) = s.split_at(split);
+            let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+                "" | "b" => 1,
+                "k" | "kb" | "kib" => 1024,
+                "m" | "mb" | "mib" => 1024 * 1024,
+                "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+                other => bail!("未知的单位: {}", other),
+            };
+            number
+                .parse::<u64>()
+                .map_err(|_| anyhow!("无效的大小: {}", s))?
+                .checked_mul(multiplier)
+                .ok_or_else(|| anyhow!("大小溢出: {}", s))?
+        }
+        _ => bail!("必须是正整数或带单位的字符串"),
+    };
+
+    if size == 0 {
+        bail!("必须大于 0");
+    }
+    Ok(Some(
+        usize::try_from(size).map_err(|_| anyhow!("大小超出范围"))?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_io_sizes_and_falls_back_to_auto() {
+        let config =
+            parse("[io]\nread_buffer = \"256KiB\"\nwrite_buffer = 65536\nchunk_size = \"auto\"\n")
+                .unwrap();
+        let io = config.io.resolve(10 * 1024 * 1024 * 1024);
+
+        assert_eq!(io.read_buffer, 256 * 1024);
+        assert_eq!(io.write_buffer, 65536);
+        assert_eq!(
+            io.chunk_size,
+            IoOptions::auto(10 * 1024 * 1024 * 1024).chunk_size
+        );
+
+        assert!(parse("[io]\nchunk_size = \"4 parsecs\"\n").is_err());
+        assert!(parse("[io]\nbuffer = 1\n").is_err());
+
+        let config = parse("[io]\nverify_moves = \"repair\"\n").unwrap();
+        assert_eq!(config.io.resolve(0).verify_moves, MoveVerification::Repair);
+        assert!(parse("[io]\nverify_moves = true\n").is_err());
+        let config = parse("[staging]\ndir = \"D:/scratch\"\nmax_size = \"1GiB\"\n").unwrap();
+        assert_eq!(config.staging.max_size, Some(1024 * 1024 * 1024));
+        assert!(parse("[staging]\nmax_size = 0\n").is_err());
+        assert_eq!(
+            parse("[io]\nprefetch = false\n").unwrap().io.prefetch,
+            Some(false)
+        );
+
+        let limits = parse("[limits]\nmax_entries = 5000000\n")
+            .unwrap()
+            .limits
+            .resolve();
+        assert_eq!(limits.max_entries, 5_000_000);
+        assert_eq!(limits.max_path_len, TableLimits::DEFAULT.max_path_len);
+        assert!(parse("[limits]\nmax_entries = -1\n").is_err());
+
+        let backup = parse("[backup]\ndir = \"E:/backups\"\nkeep = 5\n").unwrap().backup;
+        assert_eq!(backup.dir, Some(PathBuf::from("E:/backups")));
+        assert_eq!(backup.keep, Some(5));
+        assert!(parse("[backup]\nkeep = 0\n").is_err());
+        let backup = parse("[backup]\ncompression_level = 3\n").unwrap().backup;
+        assert_eq!(backup.compression_level, Some(3));
+        assert!(parse("[backup]\ncompression_level = 10\n").is_err());
+
+        assert_eq!(parse("threads = 2\n").unwrap().threads, Threads::Count(2));
+        assert_eq!(
+            parse("threads = \"auto\"\n").unwrap().threads,
+            Threads::Auto
+        );
+        assert!(parse("threads = 0\n").is_err());
+        assert!(Threads::Auto.resolve() >= 1);
+
+        let gui = parse("[gui]\nreapply_hotkey = \"Ctrl+Alt+F9\"\n").unwrap().gui;
+        assert_eq!(gui.reapply_hotkey, "Ctrl+Alt+F9".parse().ok());
+        assert!(parse("[gui]\nreapply_hotkey = \"F9\"\n").is_err());
+        let gui = parse("[gui]\ntweak_pack = \"D:/mods/tweaks\"\n").unwrap().gui;
+        assert_eq!(gui.tweak_pack, Some(PathBuf::from("D:/mods/tweaks")));
+    }
+}
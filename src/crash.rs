@@ -0,0 +1,67 @@
+use std::any::Any;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dirs;
+use crate::provenance::format_timestamp;
+
+/// 崩溃日志文件名，放在日志目录（见 [`crate::dirs`]）
+const CRASH_LOG_FILE: &str = "bpb_enhance_crash.log";
+
+pub fn crash_log_path() -> Option<PathBuf> {
+    Some(dirs::log_dir()?.join(CRASH_LOG_FILE))
+}
+
+/// 安装 panic hook：把 panic 信息与调用栈追加到崩溃日志，再交给默认 hook
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Some(path) = crash_log_path()
+            && dirs::create_parent(&path).is_ok()
+            && let Ok(mut log) = OpenOptions::new().create(true).append(true).open(path)
+        {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let thread = std::thread::current();
+            let _ = writeln!(
+                log,
+                "[{}] 线程 '{}' panic: {}\n{}\n",
+                format_timestamp(now),
+                thread.name().unwrap_or("<unnamed>"),
+                info,
+                std::backtrace::Backtrace::force_capture()
+            );
+        }
+        default_hook(info);
+    }));
+}
+
+/// 执行 f，panic 时返回 panic 信息而不是让窗口直接消失
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| panic_message(payload.as_ref()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "未知错误".to_string())
+}
+
+/// 恢复对话框中展示的说明
+pub fn describe(message: &str) -> String {
+    match crash_log_path() {
+        Some(path) => format!(
+            "程序内部错误：{}\n\n详细信息已写入 {}，反馈问题时请附上该文件。",
+            message,
+            path.display()
+        ),
+        None => format!("程序内部错误：{}", message),
+    }
+}
@@ -0,0 +1,134 @@
+//! 修改前核对游戏安装是否与 Steam 记录的一致，区分“已被修改”和“下载不完整或已损坏”。
+//!
+//! Steam 的 depot 清单（depotcache/*.manifest）是二进制 protobuf，这里不解析；
+//! 只用 appmanifest 中的 `SizeOnDisk` 与安装目录的实际大小比较，并检查 PCK 中是否有本工具写入的标记。
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::pck;
+use crate::steam::{self, InstalledBuild};
+
+/// 本工具写入 PCK 的标记文件
+const PLUGIN_VERSION_PATH: &str = "res://plugin_version.txt";
+/// 安装目录中由本工具创建、不属于 depot 的目录
+const TOOL_DIRS: &[&str] = &["mods", "res_override"];
+
+/// 核对结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// 大小与 Steam 记录一致，PCK 中没有修改标记
+    Matches,
+    /// PCK 带有本工具的修改标记
+    Modded,
+    /// 没有修改标记，但大小不一致或 PCK 无法读取，多半是下载不完整或文件损坏
+    Corrupted,
+    /// appmanifest 没有记录安装大小
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstallCheck {
+    pub build: InstalledBuild,
+    /// 安装目录中 depot 文件的实际总大小
+    pub actual_size: u64,
+    /// PCK 中是否有修改标记；文件表无法读取时为 None
+    pub modded: Option<bool>,
+    pub verdict: Verdict,
+}
+
+/// 核对 `pck_path` 所在的 Steam 安装；不在 Steam 库中时返回 None
+pub fn check(pck_path: &Path) -> Result<Option<InstallCheck>> {
+    let Some(build) = steam::installed_build(pck_path) else {
+        return Ok(None);
+    };
+    let pck_name = pck_path.file_name().unwrap_or_default().to_string_lossy();
+    let actual_size = depot_size(&build.game_dir, &pck_name)?;
+    let modded = File::open(pck_path)
+        .ok()
+        .and_then(|file| {
+            pck::read_entries(&mut BufReader::new(file), None, pck::TableLimits::DEFAULT).ok()
+        })
+        .map(|(_, entries)| entries.iter().any(|(path, _)| path == PLUGIN_VERSION_PATH));
+    let verdict = classify(build.size_on_disk, actual_size, modded);
+    Ok(Some(InstallCheck {
+        build,
+        actual_size,
+        modded,
+        verdict,
+    }))
+}
+
+pub fn classify(expected: Option<u64>, actual: u64, modded: Option<bool>) -> Verdict {
+    match (expected, modded) {
+        (_, Some(true)) => Verdict::Modded,
+        (_, None) => Verdict::Corrupted,
+        (None, Some(false)) => Verdict::Unknown,
+        (Some(expected), Some(false)) if expected == actual => Verdict::Matches,
+        (Some(_), Some(false)) => Verdict::Corrupted,
+    }
+}
+
+/// 安装目录的总大小，跳过本工具留下的备份、侧车文件与覆盖目录
+fn depot_size(game_dir: &Path, pck_name: &str) -> Result<u64> {
+    let artifact_prefix = format!("{}.", pck_name);
+    let mut total = 0;
+    let mut pending = vec![game_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let top_level = dir == game_dir;
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("无法读取目录: {}", dir.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let file_type = entry.file_type()?;
+            if top_level
+                && (name.starts_with(&artifact_prefix)
+                    || name.starts_with("bpb_enhance")
+                    || name == "portable.flag"
+                    || (file_type.is_dir() && TOOL_DIRS.contains(&name.as_str())))
+            {
+                continue;
+            }
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                total += entry.metadata()?.len();
+            }
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marker_decides_between_modded_and_corrupted() {
+        assert_eq!(classify(Some(100), 100, Some(false)), Verdict::Matches);
+        assert_eq!(classify(Some(100), 120, Some(true)), Verdict::Modded);
+        assert_eq!(classify(Some(100), 80, Some(false)), Verdict::Corrupted);
+        assert_eq!(classify(Some(100), 100, None), Verdict::Corrupted);
+        assert_eq!(classify(None, 100, Some(false)), Verdict::Unknown);
+    }
+
+    #[test]
+    fn tool_artifacts_do_not_count_towards_the_depot_size() {
+        let dir = std::env::temp_dir().join(format!("bpb_depot_{}", std::process::id()));
+        fs::create_dir_all(dir.join("mods")).unwrap();
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("Game.pck"), b"12345").unwrap();
+        fs::write(dir.join("data/lib.so"), b"123").unwrap();
+        fs::write(dir.join("Game.pck.bak"), b"backup").unwrap();
+        fs::write(dir.join("Game.pck.provenance.toml"), b"x").unwrap();
+        fs::write(dir.join("mods/10_a.pck"), b"mod").unwrap();
+
+        assert_eq!(depot_size(&dir, "Game.pck").unwrap(), 8);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use toml::Value;
+
+use crate::archive;
+use crate::pck;
+use crate::report::{EntryChange, EntryDigest};
+use crate::scaffold;
+
+/// apply 会自动写入的版本文件，不进入补丁集
+const PLUGIN_VERSION_PATH: &str = "res://plugin_version.txt";
+
+/// 两个 PCK 之间一个 entry 的差异
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDiff {
+    pub path: String,
+    pub change: EntryChange,
+    pub old: Option<EntryDigest>,
+    pub new: Option<EntryDigest>,
+}
+
+/// 导出补丁集的结果
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    /// 写入的资源文件数（相同数据只写一次）
+    pub files_written: usize,
+    pub bytes_written: u64,
+    /// 因数据相同而复用已有资源文件的 entry 数
+    pub deduplicated: usize,
+}
+
+/// 按 entry 表中的大小与 MD5 比较两个 PCK，不读取数据区
+pub fn diff_pcks(
+    old_path: &Path,
+    new_path: &Path,
+    limits: pck::TableLimits,
+) -> Result<Vec<EntryDiff>> {
+    let old = read_digests(old_path, limits)?;
+    let new = read_digests(new_path, limits)?;
+    Ok(diff_digests(&old, &new))
+}
+
+/// 读取 PCK 中全部 entry 的大小与 MD5；启用 online 特性时 `pck_path` 也可以是 http:// 地址
+pub fn read_digests(
+    pck_path: &Path,
+    limits: pck::TableLimits,
+) -> Result<HashMap<String, EntryDigest>> {
+    let reader = archive::open(pck_path)?;
+    let (_, entries) = pck::read_entries(reader, None, limits)
+        .with_context(|| format!("读取 PCK 头与索引失败: {}", pck_path.display()))?;
+    // 重复路径以表中最后一个为准，与 read_header_and_index 一致
+    Ok(entries
+        .into_iter()
+        .map(|(path, entry)| {
+            let digest = EntryDigest {
+                size: entry.size,
+                md5: entry.md5,
+            };
+            (path, digest)
+        })
+        .collect())
+}
+
+fn diff_digests(
+    old: &HashMap<String, EntryDigest>,
+    new: &HashMap<String, EntryDigest>,
+) -> Vec<EntryDiff> {
+    let mut diffs: Vec<EntryDiff> = new
+        .iter()
+        .filter_map(|(path, digest)| {
+            let change = match old.get(path) {
+                None => EntryChange::Added,
+                Some(before) if before != digest => EntryChange::Replaced,
+                Some(_) => return None,
+            };
+            Some(EntryDiff {
+                path: path.clone(),
+                change,
+                old: old.get(path).copied(),
+                new: Some(*digest),
+            })
+        })
+        .collect();
+    diffs.extend(
+        old.iter()
+            .filter(|(path, _)| !new.contains_key(*path))
+            .map(|(path, digest)| EntryDiff {
+                path: path.clone(),
+                change: EntryChange::Deleted,
+                old: Some(*digest),
+                new: None,
+            }),
+    );
+    diffs.sort_by(|a, b| a.path.cmp(&b.path));
+    diffs
+}
+
+/// 把差异导出为可直接 apply 的 MOD 目录：只包含新增/替换 entry 的数据与 replace.toml。
+/// 数据相同的 entry 共用同一个资源文件
+pub fn export_patchset(
+    old_path: &Path,
+    new_path: &Path,
+    diffs: &[EntryDiff],
+    out_dir: &Path,
+    game_version: Option<&str>,
+) -> Result<ExportSummary> {
+    if out_dir.exists() && fs::read_dir(out_dir)?.next().is_some() {
+        bail!("目标目录已存在且不为空: {}", out_dir.display());
+    }
+
+    let probe = scaffold::probe_pck(old_path)?;
+    let game_version = game_version
+        .map(str::to_string)
+        .or(probe.game_version)
+        .context("无法从旧 PCK 中确定游戏版本，请通过 --game-version 指定")?;
+
+    let mut new_file =
+        File::open(new_path).with_context(|| format!("无法打开文件: {}", new_path.display()))?;
+    let (_, new_index) = pck::read_header_and_index(&mut new_file)
+        .with_context(|| format!("读取 PCK 头与索引失败: {}", new_path.display()))?;
+
+    fs::create_dir_all(out_dir).with_context(|| format!("无法创建目录: {}", out_dir.display()))?;
+
+    let mut summary = ExportSummary::default();
+    let mut written: HashMap<EntryDigest, String> = HashMap::new();
+    let mut replace = Vec::new();
+    let mut delete = Vec::new();
+
+    for diff in diffs.iter().filter(|d| d.path != PLUGIN_VERSION_PATH) {
+        let Some(digest) = diff.new else {
+            delete.push(diff.path.clone());
+            continue;
+        };
+
+        if let Some(asset) = written.get(&digest) {
+            replace.push((diff.path.clone(), asset.clone()));
+            summary.deduplicated += 1;
+            continue;
+        }
+
+        let asset = asset_path_for(&diff.path)?;
+        let data = pck::read_file_data(&mut new_file, &new_index, &diff.path)
+            .with_context(|| format!("无法读取 entry 数据: {}", diff.path))?;
+        let target = out_dir.join(&asset);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("无法创建目录: {}", parent.display()))?;
+        }
+        fs::write(&target, &data).with_context(|| format!("无法写入: {}", target.display()))?;
+
+        summary.files_written += 1;
+        summary.bytes_written += data.len() as u64;
+        written.insert(digest, asset.clone());
+        replace.push((diff.path.clone(), asset));
+    }
+
+    let manifest = render_manifest(
+        &format!(
+            "由 bpb_enhance diff --export 生成，内容为与 {} 的差异",
+            new_path.display()
+        ),
+        &game_version,
+        probe.game_gde_hash.as_deref(),
+        &replace,
+        &delete,
+    );
+    fs::write(out_dir.join("replace.toml"), manifest).context("无法写入 replace.toml")?;
+
+    Ok(summary)
+}
+
+/// res://Core/Game.gde -> Core/Game.gde；拒绝会写出目标目录的路径
+pub fn asset_path_for(res_path: &str) -> Result<String> {
+    let relative = res_path.trim_start_matches("res://");
+    if relative.is_empty()
+        || relative
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        bail!("无法导出的 entry 路径: {}", res_path);
+    }
+    Ok(relative.to_string())
+}
+
+/// `comment` 写在文件首行，说明清单的来源
+pub fn render_manifest(
+    comment: &str,
+    game_version: &str,
+    game_gde_hash: Option<&str>,
+    replace: &[(String, String)],
+    delete: &[String],
+) -> String {
+    let quote = |s: &str| Value::String(s.to_string()).to_string();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}", comment);
+    out.push_str("\n[version]\n");
+    let _ = writeln!(out, "required-game-version = {}", quote(game_version));
+    out.push_str("plugin-version = \"0.1.0\"\n");
+
+    out.push_str("\n[version-hash]\n");
+    match game_gde_hash {
+        Some(hash) => {
+            let _ = writeln!(out, "{} = {}", quote(game_version), quote(hash));
+        }
+        None => {
+            let _ = writeln!(out, "# {} = \"<Game.gde 的 MD5>\"", quote(game_version));
+        }
+    }
+
+    out.push_str("\n[replace]\n");
+    for (res_path, asset) in replace {
+        let _ = writeln!(out, "{} = {}", quote(res_path), quote(asset));
+    }
+
+    if !delete.is_empty() {
+        out.push_str("\n[delete]\npaths = [\n");
+        for res_path in delete {
+            let _ = writeln!(out, "    {},", quote(res_path));
+        }
+        out.push_str("]\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_changes_and_renders_manifest() {
+        let old = HashMap::from([
+            ("res://a.txt".to_string(), EntryDigest::filled(1)),
+            ("res://b.txt".to_string(), EntryDigest::filled(2)),
+            ("res://gone.txt".to_string(), EntryDigest::filled(3)),
+        ]);
+        let new = HashMap::from([
+            ("res://a.txt".to_string(), EntryDigest::filled(1)),
+            ("res://b.txt".to_string(), EntryDigest::filled(9)),
+            ("res://new.txt".to_string(), EntryDigest::filled(9)),
+        ]);
+
+        let diffs = diff_digests(&old, &new);
+        let changes: Vec<(&str, EntryChange)> =
+            diffs.iter().map(|d| (d.path.as_str(), d.change)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("res://b.txt", EntryChange::Replaced),
+                ("res://gone.txt", EntryChange::Deleted),
+                ("res://new.txt", EntryChange::Added),
+            ]
+        );
+
+        let manifest = render_manifest(
+            "diff",
+            "1.0.0",
+            None,
+            &[
+                ("res://b.txt".to_string(), "b.txt".to_string()),
+                ("res://new.txt".to_string(), "b.txt".to_string()),
+            ],
+            &["res://gone.txt".to_string()],
+        );
+        let table: toml::Table = manifest.parse().unwrap();
+        assert_eq!(table["replace"]["res://new.txt"].as_str(), Some("b.txt"));
+        assert_eq!(table["delete"]["paths"][0].as_str(), Some("res://gone.txt"));
+
+        assert!(asset_path_for("res://../evil").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! 工具自身文件的默认位置，按各平台的惯例：Windows 为 `%APPDATA%` 与 `%LOCALAPPDATA%`，
+//! macOS 为 `~/Library` 下的 Application Support、Caches 与 Logs，其他平台按 XDG 规范
+//! （`$XDG_CONFIG_HOME` 等，未设置时为 `~/.config` 等）。
+//!
+//! 便携模式下一律使用可执行文件所在目录；旧版本放在可执行文件旁的文件仍然存在时继续使用。
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::config;
+
+/// 各平台目录下属于本工具的子目录名
+const APP_DIR: &str = "bpb_enhance";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Config,
+    Data,
+    Cache,
+    Log,
+}
+
+/// 设置、安装配置等需要保留的少量文件
+pub fn config_dir() -> Option<PathBuf> {
+    dir(Kind::Config)
+}
+
+/// 修改资源包、备份等程序数据
+pub fn data_dir() -> Option<PathBuf> {
+    dir(Kind::Data)
+}
+
+/// 暂存区等可以随时删除的文件
+pub fn cache_dir() -> Option<PathBuf> {
+    dir(Kind::Cache)
+}
+
+/// 崩溃日志
+pub fn log_dir() -> Option<PathBuf> {
+    dir(Kind::Log)
+}
+
+/// 配置目录下的文件 `name`；可执行文件旁已有同名文件时使用它
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    beside_exe(name, Path::is_file).or_else(|| Some(config_dir()?.join(name)))
+}
+
+/// 数据目录下的子目录 `name`；可执行文件旁已有同名目录（如随程序发布的资源包）时使用它
+pub fn data_subdir(name: &str) -> Option<PathBuf> {
+    beside_exe(name, Path::is_dir).or_else(|| Some(data_dir()?.join(name)))
+}
+
+/// 写入 `path` 之前创建它所在的目录
+pub fn create_parent(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent)
+            .with_context(|| format!("无法创建目录: {}", parent.display())),
+        _ => Ok(()),
+    }
+}
+
+fn beside_exe(name: &str, exists: fn(&Path) -> bool) -> Option<PathBuf> {
+    config::exe_dir()
+        .map(|dir| dir.join(name))
+        .filter(|path| exists(path))
+}
+
+fn dir(kind: Kind) -> Option<PathBuf> {
+    if config::is_portable() {
+        return config::exe_dir();
+    }
+    resolve(std::env::consts::OS, kind, |key| std::env::var_os(key))
+}
+
+/// 按平台 `os`（`std::env::consts::OS` 的取值）与环境变量确定目录；无法确定时返回 None
+fn resolve(os: &str, kind: Kind, env: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let var = |key: &str| {
+        env(key)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let home = || var("HOME");
+
+    let base = match os {
+        "windows" => {
+            let roaming = var("APPDATA");
+            let local = || var("LOCALAPPDATA").or_else(|| roaming.clone());
+            return match kind {
+                Kind::Config | Kind::Data => roaming.map(|dir| dir.join(APP_DIR)),
+                Kind::Cache => local().map(|dir| dir.join(APP_DIR).join("cache")),
+                Kind::Log => local().map(|dir| dir.join(APP_DIR).join("logs")),
+            };
+        }
+        "macos" => {
+            let library = home()?.join("Library");
+            match kind {
+                Kind::Config | Kind::Data => library.join("Application Support"),
+                Kind::Cache => library.join("Caches"),
+                Kind::Log => library.join("Logs"),
+            }
+        }
+        _ => {
+            let (key, fallback) = match kind {
+                Kind::Config => ("XDG_CONFIG_HOME", ".config"),
+                Kind::Data => ("XDG_DATA_HOME", ".local/share"),
+                Kind::Cache => ("XDG_CACHE_HOME", ".cache"),
+                Kind::Log => ("XDG_STATE_HOME", ".local/state"),
+            };
+            // 按 XDG 规范，相对路径视为未设置
+            var(key)
+                .filter(|path| path.is_absolute())
+                .or_else(|| Some(home()?.join(fallback)))?
+        }
+    };
+    Some(base.join(APP_DIR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    #[test]
+    fn follows_platform_conventions() {
+        let linux = [("HOME", "/home/me"), ("XDG_CACHE_HOME", "/tmp/cache")];
+        assert_eq!(
+            resolve("linux", Kind::Config, env(&linux)),
+            Some(PathBuf::from("/home/me/.config/bpb_enhance"))
+        );
+        assert_eq!(
+            resolve("linux", Kind::Cache, env(&linux)),
+            Some(PathBuf::from("/tmp/cache/bpb_enhance"))
+        );
+        // 相对路径的 XDG 变量被忽略
+        let relative = [("HOME", "/home/me"), ("XDG_DATA_HOME", "data")];
+        assert_eq!(
+            resolve("linux", Kind::Data, env(&relative)),
+            Some(PathBuf::from("/home/me/.local/share/bpb_enhance"))
+        );
+        assert_eq!(resolve("linux", Kind::Log, env(&[])), None);
+
+        let macos = [("HOME", "/Users/me")];
+        assert_eq!(
+            resolve("macos", Kind::Data, env(&macos)),
+            Some(PathBuf::from(
+                "/Users/me/Library/Application Support/bpb_enhance"
+            ))
+        );
+        assert_eq!(
+            resolve("macos", Kind::Log, env(&macos)),
+            Some(PathBuf::from("/Users/me/Library/Logs/bpb_enhance"))
+        );
+
+        let windows = [("APPDATA", "/Roaming"), ("LOCALAPPDATA", "/Local")];
+        assert_eq!(
+            resolve("windows", Kind::Config, env(&windows)),
+            Some(Path::new("/Roaming").join(APP_DIR))
+        );
+        assert_eq!(
+            resolve("windows", Kind::Cache, env(&windows)),
+            Some(Path::new("/Local").join(APP_DIR).join("cache"))
+        );
+        assert_eq!(
+            resolve("windows", Kind::Log, env(&windows[..1])),
+            Some(Path::new("/Roaming").join(APP_DIR).join("logs"))
+        );
+    }
+}
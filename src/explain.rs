@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::pck::{self, RawFileEntry};
+use crate::zip::ZipWriter;
+
+/// 失败 entry 前后各导出的表字节数
+const HEXDUMP_CONTEXT: u64 = 256;
+
+/// apply 失败时生成排查包：错误、header、entry 表布局、相关表字节的十六进制转储、
+/// replace.toml 与运行环境，打成一个 ZIP 方便附到 issue 中
+pub fn write_bundle(
+    out: &Path,
+    pck_path: &Path,
+    assets: &Path,
+    error: &anyhow::Error,
+) -> Result<()> {
+    let mut zip = ZipWriter::new(
+        File::create(out).with_context(|| format!("无法创建排查包: {}", out.display()))?,
+    );
+
+    zip.add_file("error.txt", format!("{:?}\n", error).as_bytes())?;
+    zip.add_file("environment.txt", environment(pck_path, assets).as_bytes())?;
+
+    let failing_path = failing_entry(error);
+    let (layout, table_bytes) = match inspect(pck_path, failing_path.as_deref()) {
+        Ok(result) => result,
+        Err(err) => (format!("无法读取 PCK 布局: {:?}\n", err), String::new()),
+    };
+    zip.add_file("layout.txt", layout.as_bytes())?;
+    zip.add_file("table.hexdump.txt", table_bytes.as_bytes())?;
+
+    match std::fs::read(assets.join("replace.toml")) {
+        Ok(manifest) => zip.add_file("replace.toml", &manifest)?,
+        Err(err) => zip.add_file("replace.toml.missing.txt", err.to_string().as_bytes())?,
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+fn environment(pck_path: &Path, assets: &Path) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "tool: bpb_enhance {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        out,
+        "os: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(out, "pck: {}", pck_path.display());
+    match std::fs::metadata(pck_path) {
+        Ok(meta) => {
+            let _ = writeln!(out, "pck size: {}", meta.len());
+            let _ = writeln!(out, "pck readonly: {}", meta.permissions().readonly());
+        }
+        Err(err) => {
+            let _ = writeln!(out, "pck metadata: {}", err);
+        }
+    }
+    let _ = writeln!(out, "assets: {}", assets.display());
+    out
+}
+
+/// 从错误信息中找出涉及的 res 路径
+fn failing_entry(error: &anyhow::Error) -> Option<String> {
+    error.chain().find_map(|cause| {
+        let text = cause.to_string();
+        let start = text.find("res://")?;
+        let path: String = text[start..]
+            .chars()
+            .take_while(|c| !c.is_whitespace() && !"，,：)）\"'".contains(*c))
+            .collect();
+        Some(path)
+    })
+}
+
+/// 返回（布局说明, 表字节转储）
+fn inspect(pck_path: &Path, failing_path: Option<&str>) -> Result<(String, String)> {
+    let mut file = File::open(pck_path)?;
+    let file_size = file.metadata()?.len();
+    let (header, index) = pck::read_header_and_index(&mut file)?;
+
+    // 整张表读取一次（加密的表也只解密一次），重复路径与索引一样以最后一个为准
+    let mut by_path: HashMap<String, RawFileEntry> = pck::read_entries(
+        std::io::BufReader::new(&mut file),
+        None,
+        pck::TableLimits::DEFAULT,
+    )?
+    .1
+    .into_iter()
+    .collect();
+    let layout = header.entry_layout();
+    let mut entries: Vec<(u64, String, RawFileEntry)> = Vec::with_capacity(index.len());
+    for (path, table_offset) in &index {
+        let entry = by_path
+            .remove(path)
+            .with_context(|| format!("entry 表中没有 {}", path))?;
+        entries.push((*table_offset, path.clone(), entry));
+    }
+    entries.sort_by_key(|(offset, _, _)| *offset);
+
+    let table_start = entries.first().map_or(0, |(offset, _, _)| *offset);
+    let table_end = entries
+        .last()
+        .map_or(0, |(offset, _, e)| offset + layout.entry_size(e.path_len));
+    let data_start = entries.iter().map(|(_, _, e)| e.offset).min().unwrap_or(0);
+
+    let mut layout = String::new();
+    let _ = writeln!(layout, "header: {:?}", header);
+    let _ = writeln!(layout, "file size: {}", file_size);
+    let _ = writeln!(layout, "entry table: {}..{}", table_start, table_end);
+    let _ = writeln!(layout, "first data offset: {}", data_start);
+    if table_end > data_start {
+        let _ = writeln!(layout, "! entry 表与数据区重叠");
+    }
+    let _ = writeln!(
+        layout,
+        "failing entry: {}",
+        failing_path.unwrap_or("（错误信息中未包含路径）")
+    );
+    layout.push_str("\n# table_offset\tdata_offset\tsize\tmd5\tpath\n");
+    for (table_offset, path, entry) in &entries {
+        let problem = if entry.offset.saturating_add(entry.stored_size()) > file_size {
+            "\t! 数据超出文件末尾"
+        } else if entry.offset < table_end {
+            "\t! 数据位于 entry 表内"
+        } else {
+            ""
+        };
+        let md5: String = entry.md5.iter().map(|b| format!("{:02x}", b)).collect();
+        let _ = writeln!(
+            layout,
+            "{}\t{}\t{}\t{}\t{}{}",
+            table_offset, entry.offset, entry.size, md5, path, problem
+        );
+    }
+
+    // 有失败 entry 时转储其前后的表字节，否则转储表开头
+    let center = failing_path
+        .and_then(|p| index.get(p).copied())
+        .unwrap_or(table_start);
+    let from = center.saturating_sub(HEXDUMP_CONTEXT).max(table_start);
+    let to = (center + HEXDUMP_CONTEXT).min(table_end).min(file_size);
+    let mut bytes = vec![0u8; to.saturating_sub(from) as usize];
+    file.seek(SeekFrom::Start(from))?;
+    file.read_exact(&mut bytes)?;
+
+    Ok((layout, hexdump(&bytes, from)))
+}
+
+/// `偏移  十六进制  ASCII` 格式，每行 16 字节
+fn hexdump(bytes: &[u8], base: u64) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect();
+        let _ = writeln!(
+            out,
+            "{:08x}  {:<47}  {}",
+            base + (i * 16) as u64,
+            hex.join(" "),
+            ascii
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hexdump_and_failing_path() {
+        let dump = hexdump(b"GDPC\x01\x00\x00\x00res://a.txt!", 0x10);
+        assert_eq!(
+            dump.lines().next().unwrap(),
+            "00000010  47 44 50 43 01 00 00 00 72 65 73 3a 2f 2f 61 2e  GDPC....res://a."
+        );
+
+        let error = anyhow::anyhow!("entry res://Core/Game.gde missing during move")
+            .context("写入/替换 PCK 文件失败");
+        assert_eq!(
+            failing_entry(&error).as_deref(),
+            Some("res://Core/Game.gde")
+        );
+    }
+}
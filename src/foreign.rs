@@ -0,0 +1,171 @@
+//! 检测其他 MOD 工具留下的修改：PCK 中或 PCK 旁的标记文件、最后一个 entry 之后追加的数据，
+//! 以及本工具写入后又被改动过的 entry。修改前发现这些痕迹时提示用户，并把备份标记为“已有修改”，
+//! 之后恢复时能分清备份里是不是原版。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::pck;
+use crate::provenance::ProvenanceStore;
+use crate::tweak::PLUGIN_VERSION_PATH;
+
+/// 其他工具写入 PCK 的路径前缀
+const MARKER_ENTRIES: &[(&str, &str)] = &[
+    ("res://addons/mod_loader/", "Godot Mod Loader"),
+    ("res://mods-unpacked/", "Godot Mod Loader"),
+];
+/// 其他工具放在 PCK 旁的文件
+const MARKER_FILES: &[(&str, &str)] = &[(
+    "override.cfg",
+    "通过 override.cfg 注入脚本的工具（如 Godot Mod Loader）",
+)];
+/// 导出时数据末尾可能有对齐填充，不超过这个长度的尾部数据不算追加
+const TRAILING_SLACK: u64 = 64;
+
+/// 一处其他工具留下的修改痕迹
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// PCK 中有其他工具的文件
+    MarkerEntry { path: String, tool: &'static str },
+    /// PCK 旁有其他工具使用的文件
+    MarkerFile { path: PathBuf, tool: &'static str },
+    /// 最后一个 entry 的数据之后还有数据，本工具从未修改过这个 PCK
+    TrailingData { bytes: u64 },
+    /// 本工具写入的 entry 之后又被改动
+    ChangedSinceApply {
+        path: String,
+        recorded: String,
+        current: String,
+    },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MarkerEntry { path, tool } => write!(f, "PCK 中有 {} 的文件 {}", tool, path),
+            Self::MarkerFile { path, tool } => {
+                write!(f, "PCK 旁有 {} 使用的 {}", tool, path.display())
+            }
+            Self::TrailingData { bytes } => {
+                write!(
+                    f,
+                    "最后一个文件之后追加了 {} 字节不属于任何 entry 的数据",
+                    bytes
+                )
+            }
+            Self::ChangedSinceApply {
+                path,
+                recorded,
+                current,
+            } => write!(
+                f,
+                "{} 在上次 apply 之后被改动过（记录的 MD5 为 {}，当前为 {}）",
+                path, recorded, current
+            ),
+        }
+    }
+}
+
+/// 检查 `pck_path` 上其他工具留下的修改痕迹，没有时返回空列表
+pub fn detect(pck_path: &Path) -> Result<Vec<Finding>> {
+    let file = File::open(pck_path).with_context(|| format!("无法打开: {}", pck_path.display()))?;
+    let archive_size = file.metadata()?.len();
+    let (_, entries) = pck::read_entries(BufReader::new(file), None, pck::TableLimits::DEFAULT)
+        .with_context(|| format!("无法读取文件表: {}", pck_path.display()))?;
+    let mut findings = Vec::new();
+
+    for (path, _) in &entries {
+        if let Some((_, tool)) = MARKER_ENTRIES
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix))
+        {
+            findings.push(Finding::MarkerEntry {
+                path: path.clone(),
+                tool,
+            });
+        }
+    }
+    let dir = pck_path.parent().unwrap_or(Path::new(""));
+    for (name, tool) in MARKER_FILES {
+        let path = dir.join(name);
+        if path.is_file() {
+            findings.push(Finding::MarkerFile { path, tool });
+        }
+    }
+
+    let ours = entries.iter().any(|(path, _)| path == PLUGIN_VERSION_PATH);
+    if ours {
+        // 本工具修改时不截断文件，尾部的旧数据不能说明什么；改为核对写入过的 entry
+        let current: HashMap<&str, String> = entries
+            .iter()
+            .map(|(path, entry)| {
+                let md5 = format!("{:x}", md5::Digest(entry.md5));
+                (path.as_str(), md5)
+            })
+            .collect();
+        for (path, provenance) in ProvenanceStore::load(pck_path)?.iter() {
+            if let Some(md5) = current.get(path.as_str())
+                && *md5 != provenance.md5_hex
+            {
+                findings.push(Finding::ChangedSinceApply {
+                    path: path.clone(),
+                    recorded: provenance.md5_hex.clone(),
+                    current: md5.clone(),
+                });
+            }
+        }
+    } else {
+        let data_end = entries
+            .iter()
+            .map(|(_, entry)| entry.offset + entry.stored_size())
+            .max()
+            .unwrap_or(archive_size);
+        if archive_size > data_end + TRAILING_SLACK {
+            findings.push(Finding::TrailingData {
+                bytes: archive_size - data_end,
+            });
+        }
+    }
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    fn finds_markers_and_appended_data() {
+        let dir = std::env::temp_dir().join(format!("bpb_foreign_{}", std::process::id()));
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        fs::write(dir.join("assets/main.gd"), b"extends Node").unwrap();
+        let pck = dir.join("game.pck");
+        let options = pck::PackOptions::default();
+        pck::pack_directory(dir.join("assets"), &pck, &options).unwrap();
+        assert_eq!(detect(&pck).unwrap(), []);
+
+        fs::create_dir_all(dir.join("assets/addons/mod_loader")).unwrap();
+        fs::write(dir.join("assets/addons/mod_loader/mod_loader.gd"), b"#").unwrap();
+        pck::pack_directory(dir.join("assets"), &pck, &options).unwrap();
+        fs::write(dir.join("override.cfg"), b"[autoload]\n").unwrap();
+        let mut file = OpenOptions::new().append(true).open(&pck).unwrap();
+        file.write_all(&[0xab; 4096]).unwrap();
+
+        let findings = detect(&pck).unwrap();
+        assert_eq!(findings.len(), 3, "{:?}", findings);
+        let Finding::MarkerEntry { path, tool } = &findings[0] else {
+            panic!("{:?}", findings[0]);
+        };
+        assert_eq!(path, "res://addons/mod_loader/mod_loader.gd");
+        assert_eq!(*tool, "Godot Mod Loader");
+        assert!(matches!(findings[1], Finding::MarkerFile { .. }));
+        assert!(matches!(findings[2], Finding::TrailingData { bytes } if bytes >= 4096));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! GUI 的全局快捷键：在设置文件的 `[gui] reapply_hotkey` 中配置（如 `"Ctrl+Alt+F9"`），
+//! 程序运行期间即使窗口不在前台，按下后也会重新应用最近一次成功的修改。
+//!
+//! 只支持 Windows（RegisterHotKey）；其他平台注册时返回错误，GUI 提示后忽略该设置。
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc;
+
+use anyhow::Result;
+
+/// Win32 的修饰键标志
+const MOD_ALT: u32 = 0x1;
+const MOD_CONTROL: u32 = 0x2;
+const MOD_SHIFT: u32 = 0x4;
+const MOD_WIN: u32 = 0x8;
+
+/// 一个全局快捷键：修饰键组合加一个主键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    /// MOD_* 标志的组合
+    modifiers: u32,
+    /// Win32 虚拟键码
+    key: u32,
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let mut modifiers = 0;
+        let mut key = None;
+        for part in value.split('+').map(str::trim) {
+            let flag = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => MOD_CONTROL,
+                "alt" => MOD_ALT,
+                "shift" => MOD_SHIFT,
+                "win" | "super" => MOD_WIN,
+                _ => {
+                    if key.is_some() {
+                        return Err(format!("只能有一个主键: {}", value));
+                    }
+                    key = Some(virtual_key(part).ok_or_else(|| format!("未知的按键: {}", part))?);
+                    continue;
+                }
+            };
+            modifiers |= flag;
+        }
+        let key = key.ok_or_else(|| format!("缺少主键: {}", value))?;
+        // 不带修饰键的全局快捷键会吞掉游戏里的正常按键
+        if modifiers == 0 {
+            return Err(format!(
+                "至少需要一个修饰键（Ctrl/Alt/Shift/Win）: {}",
+                value
+            ));
+        }
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, name) in [
+            (MOD_CONTROL, "Ctrl"),
+            (MOD_ALT, "Alt"),
+            (MOD_SHIFT, "Shift"),
+            (MOD_WIN, "Win"),
+        ] {
+            if self.modifiers & flag != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.key {
+            0x70..=0x87 => write!(f, "F{}", self.key - 0x70 + 1),
+            key => write!(f, "{}", char::from_u32(key).unwrap_or('?')),
+        }
+    }
+}
+
+/// 字母、数字与 F1–F24 的虚拟键码
+fn virtual_key(name: &str) -> Option<u32> {
+    let upper = name.to_ascii_uppercase();
+    if let [c] = upper.as_bytes()
+        && c.is_ascii_alphanumeric()
+    {
+        return Some(*c as u32);
+    }
+    let number: u32 = upper.strip_prefix('F')?.parse().ok()?;
+    (1..=24).contains(&number).then(|| 0x70 + number - 1)
+}
+
+/// 注册快捷键，每次按下时向返回的通道发送一次；注册失败（如已被其他程序占用）时返回错误
+#[cfg(windows)]
+pub fn register(hotkey: Hotkey) -> Result<mpsc::Receiver<()>> {
+    use std::ffi::c_void;
+
+    use anyhow::{Context, bail};
+
+    const MOD_NOREPEAT: u32 = 0x4000;
+    const WM_HOTKEY: u32 = 0x0312;
+    const HOTKEY_ID: i32 = 1;
+
+    #[repr(C)]
+    struct Msg {
+        hwnd: *mut c_void,
+        message: u32,
+        w_param: usize,
+        l_param: isize,
+        time: u32,
+        pt: [i32; 2],
+        private: u32,
+    }
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn RegisterHotKey(hwnd: *mut c_void, id: i32, modifiers: u32, vk: u32) -> i32;
+        fn GetMessageW(msg: *mut Msg, hwnd: *mut c_void, min: u32, max: u32) -> i32;
+    }
+
+    let (pressed_tx, pressed_rx) = mpsc::channel();
+    let (registered_tx, registered_rx) = mpsc::channel();
+    // 热键消息投递到注册它的线程，因此注册与消息循环都在专用线程中进行
+    std::thread::spawn(move || {
+        let modifiers = hotkey.modifiers | MOD_NOREPEAT;
+        // SAFETY: 不关联窗口的注册，参数均为普通整数
+        let ok = unsafe { RegisterHotKey(std::ptr::null_mut(), HOTKEY_ID, modifiers, hotkey.key) };
+        let _ = registered_tx.send(ok != 0);
+        if ok == 0 {
+            return;
+        }
+        let mut msg = std::mem::MaybeUninit::<Msg>::uninit();
+        // SAFETY: msg 足够容纳 MSG 结构；返回 0（WM_QUIT）或 -1（出错）时退出循环
+        while unsafe { GetMessageW(msg.as_mut_ptr(), std::ptr::null_mut(), 0, 0) } > 0 {
+            // SAFETY: GetMessageW 返回正数时已写入 msg
+            let msg = unsafe { msg.assume_init_ref() };
+            if msg.message == WM_HOTKEY && pressed_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    if !registered_rx.recv().context("快捷键线程意外退出")? {
+        bail!("无法注册快捷键 {}，可能已被其他程序占用", hotkey);
+    }
+    Ok(pressed_rx)
+}
+
+#[cfg(not(windows))]
+pub fn register(hotkey: Hotkey) -> Result<mpsc::Receiver<()>> {
+    anyhow::bail!("当前平台不支持全局快捷键 {}", hotkey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifier_combinations() {
+        let hotkey: Hotkey = "ctrl + Alt+F9".parse().unwrap();
+        assert_eq!(hotkey.modifiers, MOD_CONTROL | MOD_ALT);
+        assert_eq!(hotkey.key, 0x78);
+        assert_eq!(hotkey.to_string(), "Ctrl+Alt+F9");
+        assert_eq!(
+            "Shift+Win+r".parse::<Hotkey>().unwrap().to_string(),
+            "Shift+Win+R"
+        );
+
+        assert!("F9".parse::<Hotkey>().is_err());
+        assert!("Ctrl+A+B".parse::<Hotkey>().is_err());
+        assert!("Ctrl+F25".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Alt".parse::<Hotkey>().is_err());
+    }
+}
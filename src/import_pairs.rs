@@ -0,0 +1,103 @@
+//! extract 导出的资源与 `.import`/`.remap` 配对文件的关联记录，写在导出目录的 `import_pairs.toml` 中。
+//!
+//! 把导出目录当作 MOD 注入时，只要替换了一组配对中的任意文件，就把目录中同组的其他文件一并写入，
+//! 避免配对文件与它指向的导入数据不一致导致引擎加载错误的资源。
+
+use anyhow::{Result, anyhow};
+use toml::{Table, Value};
+
+use crate::pck::ImportPair;
+
+/// 关联记录的文件名，位于导出目录（即 MOD 目录）的根下
+pub const FILE_NAME: &str = "import_pairs.toml";
+
+pub fn to_toml(pairs: &[ImportPair]) -> String {
+    let mut items = Table::new();
+    for pair in pairs {
+        let mut item = Table::new();
+        item.insert("source".into(), Value::String(pair.source.clone()));
+        item.insert(
+            "targets".into(),
+            Value::Array(pair.targets.iter().cloned().map(Value::String).collect()),
+        );
+        items.insert(pair.companion.clone(), Value::Table(item));
+    }
+
+    let mut table = Table::new();
+    table.insert("pairs".into(), Value::Table(items));
+    format!(
+        "# 由 bpb_enhance extract 生成：注入时同组文件会一起写入，请勿拆开修改\n{}",
+        table
+    )
+}
+
+pub fn parse(content: &str) -> Result<Vec<ImportPair>> {
+    let table: Table = content.parse()?;
+    let mut pairs = Vec::new();
+    if let Some(Value::Table(items)) = table.get("pairs") {
+        for (companion, item) in items {
+            let source = item
+                .get("source")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("{} 缺少 source", companion))?;
+            let targets = item
+                .get("targets")
+                .and_then(Value::as_array)
+                .map(|targets| {
+                    targets
+                        .iter()
+                        .filter_map(|t| t.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            pairs.push(ImportPair {
+                source: source.to_string(),
+                companion: companion.clone(),
+                targets,
+            });
+        }
+    }
+    Ok(pairs)
+}
+
+/// 与 `replaced` 中的文件同组、但本身没有被替换的文件
+pub fn missing_members(pairs: &[ImportPair], replaced: &[&str]) -> Vec<String> {
+    let mut missing = Vec::new();
+    for pair in pairs {
+        let members: Vec<&str> = std::iter::once(pair.source.as_str())
+            .chain(std::iter::once(pair.companion.as_str()))
+            .chain(pair.targets.iter().map(String::as_str))
+            .collect();
+        if !members.iter().any(|m| replaced.contains(m)) {
+            continue;
+        }
+        for member in members {
+            if !replaced.contains(&member) && !missing.iter().any(|m| m == member) {
+                missing.push(member.to_string());
+            }
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_completes_partial_replacements() {
+        let pairs = vec![ImportPair {
+            source: "res://icon.png".to_string(),
+            companion: "res://icon.png.import".to_string(),
+            targets: vec!["res://.godot/imported/icon.png-1.ctex".to_string()],
+        }];
+        let parsed = parse(&to_toml(&pairs)).unwrap();
+        assert_eq!(parsed, pairs);
+
+        assert_eq!(
+            missing_members(&parsed, &["res://.godot/imported/icon.png-1.ctex"]),
+            vec!["res://icon.png", "res://icon.png.import"]
+        );
+        assert!(missing_members(&parsed, &["res://other.png"]).is_empty());
+    }
+}
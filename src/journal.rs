@@ -0,0 +1,395 @@
+//! 就地修改的回滚日志 `<文件名>.journal`：第一次写入 PCK 之前，把修改可能改写的原有字节
+//! （header、entry 表及其扩展覆盖的区域、可能原位写入的 entry、删除时可能截掉的尾部）与原文件长度
+//! 写入日志并落盘，修改完成并落盘后删除。
+//!
+//! 程序被强制结束或断电时日志会留下，下次修改同一个 PCK 或启动 GUI 时据此把这些字节写回、
+//! 截掉追加的数据，PCK 恢复为修改前的字节。日志同时记录修改不会碰到的区域的抽样指纹，
+//! PCK 在此期间被其他操作改动过时拒绝写回，以免旧字节覆盖别处的数据。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+/// 不带指纹的旧格式，仍然可以回滚
+const MAGIC_V1: &[u8; 8] = b"BPBJRNL1";
+const MAGIC: &[u8; 8] = b"BPBJRNL2";
+
+/// 指纹在每段未保存区间的开头与结尾各取的字节数
+const FINGERPRINT_SAMPLE: u64 = 4096;
+
+/// 回滚时每次从日志复制回 PCK 的字节数
+const RESTORE_CHUNK: usize = 1024 * 1024;
+
+pub fn journal_path(pck: &Path) -> PathBuf {
+    let mut path = pck.to_path_buf().into_os_string();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+/// 进行中的修改；[`Journal::finish`] 之前 drop（包括出错返回）时日志保留，下次回滚
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// 保存 `file`（即 `pck`）中 `ranges`（偏移, 长度）的当前内容与文件长度；区间可以重叠，超出文件的部分忽略。
+    /// 内容按 `chunk_size` 分块写入日志，不整段载入内存
+    pub fn begin(
+        pck: &Path,
+        file: &mut File,
+        ranges: &[(u64, u64)],
+        chunk_size: usize,
+    ) -> Result<Self> {
+        let path = journal_path(pck);
+        if path.exists() {
+            bail!("{} 有未完成的修改记录: {}", pck.display(), path.display());
+        }
+        let len = file.metadata().context("无法读取 PCK 文件大小")?.len();
+
+        let ranges = merge_ranges(ranges, len);
+        let fingerprint = fingerprint(file, &ranges, len)
+            .with_context(|| format!("无法读取: {}", pck.display()))?;
+
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let journal = File::create(&partial)
+            .with_context(|| format!("无法写入修改记录: {}", partial.display()))?;
+        let mut out = BufWriter::new(journal);
+        let mut digest = md5::Context::new();
+        let mut put = |bytes: &[u8]| {
+            digest.consume(bytes);
+            out.write_all(bytes)
+        };
+        put(MAGIC)?;
+        put(&fingerprint)?;
+        put(&len.to_le_bytes())?;
+        put(&(ranges.len() as u64).to_le_bytes())?;
+        let chunk_size = chunk_size.max(1);
+        let mut buf = vec![0u8; chunk_size];
+        for (offset, size) in ranges {
+            put(&offset.to_le_bytes())?;
+            put(&size.to_le_bytes())?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut left = size;
+            while left > 0 {
+                let chunk = &mut buf[..left.min(chunk_size as u64) as usize];
+                file.read_exact(chunk)
+                    .with_context(|| format!("无法读取: {}", pck.display()))?;
+                put(chunk)?;
+                left -= chunk.len() as u64;
+            }
+        }
+        // 写到一半的日志校验不通过，说明 PCK 还没被改动过，回滚时直接丢弃
+        out.write_all(&digest.finalize().0)?;
+        let journal = out.into_inner().map_err(|err| err.into_error())?;
+        journal.sync_all()?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("无法写入修改记录: {}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    /// 修改已完成：把 PCK 落盘后删除日志
+    pub fn finish(self, file: &File) -> Result<()> {
+        file.sync_all().context("无法把修改写入磁盘")?;
+        fs::remove_file(&self.path)
+            .with_context(|| format!("无法删除修改记录: {}", self.path.display()))
+    }
+}
+
+/// 有未完成的修改时把 `pck` 回滚到修改前，返回是否回滚过。
+/// PCK 中修改不会碰到的区域与日志记录的不一致时报错，不写回也不删除日志
+pub fn recover(pck: &Path) -> Result<bool> {
+    let path = journal_path(pck);
+    let mut journal = match File::open(&path) {
+        Ok(journal) => journal,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => {
+            return Err(err).with_context(|| format!("无法读取修改记录: {}", path.display()));
+        }
+    };
+    let Some(Recorded {
+        len,
+        fingerprint: recorded,
+        ranges,
+    }) = read_journal(&mut journal)
+        .with_context(|| format!("无法读取修改记录: {}", path.display()))?
+    else {
+        // 日志没写完就中断了，PCK 还没有被改动
+        drop(journal);
+        fs::remove_file(&path).with_context(|| format!("无法删除修改记录: {}", path.display()))?;
+        return Ok(false);
+    };
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pck)
+        .with_context(|| format!("无法打开 {} 进行回滚", pck.display()))?;
+    if let Some(recorded) = recorded {
+        let saved: Vec<(u64, u64)> = ranges.iter().map(|r| (r.offset, r.size)).collect();
+        let current = file.metadata().context("无法读取 PCK 文件大小")?.len();
+        let unchanged = sample_windows(&saved, len)
+            .last()
+            .is_none_or(|w| w.0 + w.1 <= current)
+            && fingerprint(&mut file, &saved, len)
+                .with_context(|| format!("无法读取: {}", pck.display()))?
+                == recorded;
+        if !unchanged {
+            bail!(
+                "{} 在上次中断的修改之后被其他操作改动过，无法按修改记录回滚；请从备份恢复，\
+                 确认无误后删除 {}",
+                pck.display(),
+                path.display()
+            );
+        }
+    }
+
+    let mut buf = vec![0u8; RESTORE_CHUNK];
+    for range in &ranges {
+        journal.seek(SeekFrom::Start(range.data_at))?;
+        file.seek(SeekFrom::Start(range.offset))?;
+        let mut left = range.size;
+        while left > 0 {
+            let chunk = &mut buf[..left.min(RESTORE_CHUNK as u64) as usize];
+            journal
+                .read_exact(chunk)
+                .with_context(|| format!("无法读取修改记录: {}", path.display()))?;
+            file.write_all(chunk)
+                .with_context(|| format!("回滚失败: {}", pck.display()))?;
+            left -= chunk.len() as u64;
+        }
+    }
+    file.set_len(len)
+        .with_context(|| format!("回滚失败: {}", pck.display()))?;
+    file.sync_all()
+        .with_context(|| format!("回滚失败: {}", pck.display()))?;
+    drop(journal);
+    fs::remove_file(&path).with_context(|| format!("无法删除修改记录: {}", path.display()))?;
+    Ok(true)
+}
+
+/// 排序合并重叠或相邻的区间，并截到文件长度内
+fn merge_ranges(ranges: &[(u64, u64)], len: u64) -> Vec<(u64, u64)> {
+    let mut sorted: Vec<(u64, u64)> = ranges
+        .iter()
+        .filter(|(offset, _)| *offset < len)
+        .map(|&(offset, size)| (offset, (offset + size).min(len)))
+        .filter(|(start, end)| start < end)
+        .collect();
+    sorted.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in sorted {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(start, end)| (start, end - start))
+        .collect()
+}
+
+/// 修改不会改写的区域（原文件长度内、日志没有保存的区间）中用于指纹的抽样区间（偏移, 长度）：
+/// 每段的开头与结尾各 [`FINGERPRINT_SAMPLE`] 字节，按偏移排列
+fn sample_windows(saved: &[(u64, u64)], len: u64) -> Vec<(u64, u64)> {
+    let mut windows = Vec::new();
+    let mut gap_start = 0;
+    for (start, end) in saved
+        .iter()
+        .map(|&(offset, size)| (offset, offset + size))
+        .chain([(len, len)])
+    {
+        if gap_start < start {
+            let head = (start - gap_start).min(FINGERPRINT_SAMPLE);
+            windows.push((gap_start, head));
+            let tail_start = start
+                .saturating_sub(FINGERPRINT_SAMPLE)
+                .max(gap_start + head);
+            if tail_start < start {
+                windows.push((tail_start, start - tail_start));
+            }
+        }
+        gap_start = gap_start.max(end);
+    }
+    windows
+}
+
+/// 抽样区间内容的 MD5
+fn fingerprint(file: &mut File, saved: &[(u64, u64)], len: u64) -> io::Result<[u8; 16]> {
+    let mut context = md5::Context::new();
+    let mut buf = vec![0u8; FINGERPRINT_SAMPLE as usize];
+    for (offset, size) in sample_windows(saved, len) {
+        let buf = &mut buf[..size as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)?;
+        context.consume(&*buf);
+    }
+    Ok(context.finalize().0)
+}
+
+/// 日志中的一段修改前的内容，数据留在日志文件中
+struct SavedRange {
+    /// 在 PCK 中的偏移
+    offset: u64,
+    size: u64,
+    /// 数据在日志文件中的位置
+    data_at: u64,
+}
+
+/// 解析后的日志
+struct Recorded {
+    /// 原文件长度
+    len: u64,
+    /// 旧格式的日志没有指纹
+    fingerprint: Option<[u8; 16]>,
+    ranges: Vec<SavedRange>,
+}
+
+/// 流式校验并解析日志；日志不完整或校验不通过时返回 None
+fn read_journal(journal: &mut File) -> io::Result<Option<Recorded>> {
+    let Some(body_len) = journal.metadata()?.len().checked_sub(16) else {
+        return Ok(None);
+    };
+    let mut reader = BufReader::new(journal);
+    let mut digest = md5::Context::new();
+    io::copy(&mut reader.by_ref().take(body_len), &mut digest)?;
+    let mut recorded = [0u8; 16];
+    reader.read_exact(&mut recorded)?;
+    if digest.finalize().0 != recorded {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    let fingerprint = match &magic {
+        MAGIC => {
+            let mut fingerprint = [0u8; 16];
+            reader.read_exact(&mut fingerprint)?;
+            Some(fingerprint)
+        }
+        MAGIC_V1 => None,
+        _ => return Ok(None),
+    };
+    let len = read_u64(&mut reader)?;
+    let count = read_u64(&mut reader)?;
+    let mut ranges = Vec::new();
+    for _ in 0..count {
+        let offset = read_u64(&mut reader)?;
+        let size = read_u64(&mut reader)?;
+        let data_at = reader.stream_position()?;
+        if data_at.checked_add(size).is_none_or(|end| end > body_len) {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(data_at + size))?;
+        ranges.push(SavedRange {
+            offset,
+            size,
+            data_at,
+        });
+    }
+    Ok(Some(Recorded {
+        len,
+        fingerprint,
+        ranges,
+    }))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupted_writes_roll_back() {
+        let dir = std::env::temp_dir().join(format!("bpb_journal_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        let original: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&pck, &original).unwrap();
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&pck)
+            .unwrap();
+        let journal =
+            Journal::begin(&pck, &mut file, &[(0, 100), (50, 200), (9_000, 5_000)], 64).unwrap();
+        // 模拟写到一半被中断：改写 header、截断尾部后追加
+        file.seek(SeekFrom::Start(10)).unwrap();
+        file.write_all(b"half-written table").unwrap();
+        file.set_len(9_500).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(&[0xee; 3_000]).unwrap();
+        drop(journal);
+        drop(file);
+
+        assert!(Journal::begin(&pck, &mut File::open(&pck).unwrap(), &[], 64).is_err());
+        assert!(recover(&pck).unwrap());
+        assert_eq!(fs::read(&pck).unwrap(), original);
+        assert!(!recover(&pck).unwrap());
+
+        // 正常完成时不留日志；写了一半的日志直接丢弃
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&pck)
+            .unwrap();
+        Journal::begin(&pck, &mut file, &[(0, 100)], 64)
+            .unwrap()
+            .finish(&file)
+            .unwrap();
+        assert!(!journal_path(&pck).exists());
+        fs::write(journal_path(&pck), b"BPBJRNL1\x10").unwrap();
+        assert!(!recover(&pck).unwrap());
+        assert!(!journal_path(&pck).exists());
+        assert_eq!(
+            merge_ranges(&[(5, 10), (0, 3), (3, 1), (20, 100)], 50),
+            [(0, 4), (5, 10), (20, 30)]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_roll_back_a_pck_changed_elsewhere() {
+        let dir = std::env::temp_dir().join(format!("bpb_journal_moved_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        fs::write(&pck, vec![7u8; 20_000]).unwrap();
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&pck)
+            .unwrap();
+        drop(Journal::begin(&pck, &mut file, &[(0, 100), (19_000, 1_000)], 64).unwrap());
+        // 日志留下后 PCK 被整理：未保存区域的数据前移、文件变短
+        file.seek(SeekFrom::Start(100)).unwrap();
+        file.write_all(&[9u8; 50]).unwrap();
+        file.set_len(15_000).unwrap();
+        drop(file);
+
+        let changed = fs::read(&pck).unwrap();
+        assert!(recover(&pck).is_err());
+        assert_eq!(fs::read(&pck).unwrap(), changed);
+        assert!(journal_path(&pck).exists());
+
+        assert_eq!(
+            sample_windows(&[(0, 100), (10_000, 100)], 10_200),
+            [(100, 4096), (5_904, 4096), (10_100, 100)]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
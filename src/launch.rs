@@ -0,0 +1,93 @@
+//! 通过本工具启动游戏并观察退出情况：应用 MOD 后游戏连续两次启动即崩溃时，
+//! 提示撤销修改或从备份恢复
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// 启动后在这段时间内异常退出视为“启动即崩溃”
+pub const QUICK_EXIT: Duration = Duration::from_secs(30);
+/// 连续崩溃多少次后提示恢复
+const CRASHES_BEFORE_OFFER: u32 = 2;
+
+/// PCK 旁与它同名的游戏可执行文件
+pub fn game_executable(pck: &Path) -> Option<PathBuf> {
+    let stem = pck.file_stem()?.to_str()?;
+    let dir = pck.parent()?;
+    let candidates = if cfg!(windows) {
+        vec![dir.join(format!("{}.exe", stem))]
+    } else if cfg!(target_os = "macos") {
+        // <游戏>.app/Contents/Resources/<名称>.pck 对应 Contents/MacOS/<名称>
+        vec![dir.join("../MacOS").join(stem)]
+    } else {
+        vec![
+            dir.join(format!("{}.x86_64", stem)),
+            dir.join(format!("{}.sh", stem)),
+            dir.join(stem),
+        ]
+    };
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+/// 统计应用 MOD 之后的连续崩溃次数
+#[derive(Debug, Default)]
+pub struct CrashWatch {
+    /// 应用过 MOD，且之后游戏还没有正常运行过
+    armed: bool,
+    quick_crashes: u32,
+}
+
+impl CrashWatch {
+    /// 应用 MOD 后调用，重新开始计数
+    pub fn mod_applied(&mut self) {
+        self.armed = true;
+        self.quick_crashes = 0;
+    }
+
+    /// 修改被撤销或恢复后调用，不再提示
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// 游戏退出后调用；应用 MOD 后连续两次启动即崩溃时返回 true，并重新计数
+    pub fn game_exited(&mut self, success: bool, ran_for: Duration) -> bool {
+        if !self.armed {
+            return false;
+        }
+        if success || ran_for >= QUICK_EXIT {
+            // 游戏正常运行过，说明 MOD 没有导致启动崩溃
+            self.reset();
+            return false;
+        }
+        self.quick_crashes += 1;
+        if self.quick_crashes >= CRASHES_BEFORE_OFFER {
+            self.quick_crashes = 0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_restore_after_two_quick_crashes_following_an_apply() {
+        let quick = Duration::from_secs(3);
+        let mut watch = CrashWatch::default();
+        // 没有应用过 MOD 时崩溃与本工具无关
+        assert!(!watch.game_exited(false, quick));
+        assert!(!watch.game_exited(false, quick));
+
+        watch.mod_applied();
+        assert!(!watch.game_exited(false, quick));
+        assert!(watch.game_exited(false, quick));
+
+        // 中间有一次正常运行时不再计数
+        watch.mod_applied();
+        assert!(!watch.game_exited(false, quick));
+        assert!(!watch.game_exited(false, QUICK_EXIT));
+        assert!(!watch.game_exited(false, quick));
+        assert!(!watch.game_exited(false, quick));
+    }
+}
@@ -0,0 +1,4 @@
+//! Godot PCK 读写库，供 bpb_enhance 的 CLI/GUI 以及其他工具使用
+
+pub mod pck;
+pub mod pool;
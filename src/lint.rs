@@ -0,0 +1,700 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use toml::Spanned;
+use toml::de::{DeTable, DeValue};
+
+use crate::manifest::{self, line_column};
+use crate::pck;
+use crate::progress::json_string;
+use crate::stub;
+use crate::tweak::resolve_asset_path;
+
+/// 由工具自动写入的版本标记，不应出现在 manifest 中
+const PLUGIN_VERSION_PATH: &str = "res://plugin_version.txt";
+/// 删除后游戏无法启动或版本校验失效的 entry
+const CRITICAL_PATHS: [&str; 2] = ["res://project.binary", "res://Core/Game.gde"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// 校验 replace.toml，返回全部诊断（不会因为第一个错误中断）
+pub fn lint_manifest(manifest_path: &Path) -> Result<Vec<Diagnostic>> {
+    let content = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("无法读取 manifest: {}", manifest_path.display()))?;
+    let base_dir = manifest_path.parent().unwrap_or(Path::new("."));
+
+    Ok(lint_content(&content, base_dir))
+}
+
+pub fn render_text(file: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::new();
+    for d in diagnostics {
+        let _ = writeln!(
+            out,
+            "{}:{}:{}: {}[{}]: {}",
+            file,
+            d.line,
+            d.column,
+            d.severity.as_str(),
+            d.code,
+            d.message
+        );
+    }
+    out
+}
+
+pub fn render_json(file: &str, diagnostics: &[Diagnostic]) -> String {
+    let items: Vec<String> = diagnostics
+        .iter()
+        .map(|d| {
+            format!(
+                "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"line\":{},\"column\":{}}}",
+                d.severity.as_str(),
+                d.code,
+                json_string(&d.message),
+                d.line,
+                d.column
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"file\":{},\"diagnostics\":[{}]}}",
+        json_string(file),
+        items.join(",")
+    )
+}
+
+struct Linter<'a> {
+    content: &'a str,
+    base_dir: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn push(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        span: Range<usize>,
+        message: String,
+    ) {
+        let (line, column) = line_column(self.content, span.start);
+        self.diagnostics.push(Diagnostic {
+            severity,
+            code,
+            message,
+            line,
+            column,
+        });
+    }
+
+    fn error(&mut self, code: &'static str, span: Range<usize>, message: String) {
+        self.push(Severity::Error, code, span, message);
+    }
+
+    fn warning(&mut self, code: &'static str, span: Range<usize>, message: String) {
+        self.push(Severity::Warning, code, span, message);
+    }
+
+    fn expect_table<'t, 'i>(
+        &mut self,
+        name: &str,
+        value: &'t Spanned<DeValue<'i>>,
+    ) -> Option<&'t DeTable<'i>> {
+        match value.get_ref() {
+            DeValue::Table(table) => Some(table),
+            _ => {
+                self.error("invalid-type", value.span(), format!("{} 必须是表", name));
+                None
+            }
+        }
+    }
+
+    /// 返回 required-game-version（若存在且为字符串）
+    fn check_version(&mut self, key_span: Range<usize>, table: &DeTable<'_>) -> Option<String> {
+        let mut required_game_version = None;
+        let mut has_plugin_version = false;
+
+        for (key, value) in table.iter() {
+            let name = key.get_ref().as_ref();
+            match name {
+                "required-game-version" | "plugin-version" => {
+                    let DeValue::String(text) = value.get_ref() else {
+                        self.error(
+                            "invalid-type",
+                            value.span(),
+                            format!("[version] 中的 {} 必须是字符串", name),
+                        );
+                        continue;
+                    };
+                    if name == "plugin-version" {
+                        has_plugin_version = true;
+                    } else {
+                        required_game_version = Some(text.to_string());
+                    }
+                }
+                other => self.warning(
+                    "unknown-key",
+                    key.span(),
+                    format!("[version] 中未知的字段: {}", other),
+                ),
+            }
+        }
+
+        if required_game_version.is_none() {
+            self.error(
+                "missing-key",
+                key_span.clone(),
+                "[version] 缺少 required-game-version 字段".to_string(),
+            );
+        }
+        if !has_plugin_version {
+            self.error(
+                "missing-key",
+                key_span,
+                "[version] 缺少 plugin-version 字段".to_string(),
+            );
+        }
+
+        required_game_version
+    }
+
+    fn check_version_hash(&mut self, table: &DeTable<'_>) -> Vec<String> {
+        let mut versions = Vec::new();
+        for (key, value) in table.iter() {
+            if !matches!(value.get_ref(), DeValue::String(_)) {
+                self.error(
+                    "invalid-type",
+                    value.span(),
+                    format!("[version-hash] 中的值必须是字符串: {}", key.get_ref()),
+                );
+            }
+            versions.push(key.get_ref().to_string());
+        }
+        versions
+    }
+
+    fn check_target(&mut self, section: &str, target: &str, span: Range<usize>) {
+        if !target.starts_with("res://") {
+            self.error(
+                "suspicious-target",
+                span,
+                format!("[{}] 目标路径必须以 res:// 开头: {}", section, target),
+            );
+        } else if target.contains('\\') {
+            self.warning(
+                "suspicious-target",
+                span,
+                format!("[{}] 目标路径应使用 / 作为分隔符: {}", section, target),
+            );
+        } else if target == PLUGIN_VERSION_PATH {
+            self.warning(
+                "suspicious-target",
+                span,
+                format!(
+                    "{} 由工具自动维护，不应在 manifest 中修改",
+                    PLUGIN_VERSION_PATH
+                ),
+            );
+        }
+    }
+
+    fn check_replace(&mut self, table: &DeTable<'_>) -> Vec<(String, Range<usize>)> {
+        let mut targets = Vec::new();
+        let mut seen_lowercase: HashMap<String, String> = HashMap::new();
+
+        for (key, value) in table.iter() {
+            let target = key.get_ref().to_string();
+            self.check_target("replace", &target, key.span());
+
+            if let Some(previous) = seen_lowercase.insert(target.to_lowercase(), target.clone()) {
+                self.warning(
+                    "duplicate-target",
+                    key.span(),
+                    format!("{} 与 {} 仅大小写不同，可能指向同一资源", target, previous),
+                );
+            }
+
+            match value.get_ref() {
+                DeValue::String(asset) => self.check_asset(asset, value.span()),
+                _ => self.error(
+                    "invalid-type",
+                    value.span(),
+                    format!("[replace] 中的值必须是字符串: {}", target),
+                ),
+            }
+
+            targets.push((target, key.span()));
+        }
+
+        targets
+    }
+
+    fn check_asset(&mut self, asset: &str, span: Range<usize>) {
+        if is_absolute_local_path(asset) {
+            self.error(
+                "absolute-path",
+                span,
+                format!("资源路径不能是本机绝对路径: {}", asset),
+            );
+            return;
+        }
+
+        if asset.contains('\\') {
+            self.warning(
+                "suspicious-asset",
+                span.clone(),
+                format!("资源路径应使用 / 作为分隔符: {}", asset),
+            );
+        }
+
+        let full_path = resolve_asset_path(self.base_dir, asset);
+        if !full_path.is_file() {
+            self.error(
+                "missing-file",
+                span,
+                format!("资产文件不存在: {}", full_path.display()),
+            );
+        }
+    }
+
+    fn check_original_md5(&mut self, table: &DeTable<'_>) {
+        for (key, value) in table.iter() {
+            self.check_target("original-md5", key.get_ref(), key.span());
+            match value.get_ref() {
+                DeValue::String(md5) if manifest::is_md5_hex(md5) => {}
+                _ => self.error(
+                    "invalid-hash",
+                    value.span(),
+                    format!("[original-md5] 中的值必须是 32 位十六进制 MD5: {}", key.get_ref()),
+                ),
+            }
+        }
+    }
+
+    /// 检查 include 的文件是否存在，返回是否有 include
+    fn check_includes(&mut self, value: &Spanned<DeValue<'_>>) -> bool {
+        let DeValue::Array(items) = value.get_ref() else {
+            self.error(
+                "invalid-type",
+                value.span(),
+                "include 必须是文件路径的数组".to_string(),
+            );
+            return false;
+        };
+
+        for item in items {
+            let DeValue::String(path) = item.get_ref() else {
+                self.error(
+                    "invalid-type",
+                    item.span(),
+                    "include 数组元素必须是字符串".to_string(),
+                );
+                continue;
+            };
+            let full_path = resolve_asset_path(self.base_dir, path);
+            if !full_path.is_file() {
+                self.error(
+                    "missing-file",
+                    item.span(),
+                    format!("include 的文件不存在: {}", full_path.display()),
+                );
+            }
+        }
+        !items.is_empty()
+    }
+
+    /// 检查 `delete`/`stub` 的路径列表：数组或包含 paths 的表
+    fn check_path_list(
+        &mut self,
+        section: &str,
+        value: &Spanned<DeValue<'_>>,
+    ) -> Vec<(String, Range<usize>)> {
+        let items = match value.get_ref() {
+            DeValue::Array(arr) => Some(arr),
+            DeValue::Table(table) => {
+                let mut paths = None;
+                for (key, inner) in table.iter() {
+                    if key.get_ref() == "paths" {
+                        match inner.get_ref() {
+                            DeValue::Array(arr) => paths = Some(arr),
+                            _ => self.error(
+                                "invalid-type",
+                                inner.span(),
+                                format!("{}.paths 必须是数组", section),
+                            ),
+                        }
+                    } else {
+                        self.warning(
+                            "unknown-key",
+                            key.span(),
+                            format!("[{}] 中未知的字段: {}", section, key.get_ref()),
+                        );
+                    }
+                }
+                if paths.is_none() {
+                    self.error(
+                        "missing-key",
+                        value.span(),
+                        format!("{} 表需要 paths 数组", section),
+                    );
+                }
+                paths
+            }
+            _ => {
+                self.error(
+                    "invalid-type",
+                    value.span(),
+                    format!("{} 必须是数组或包含 paths 的表", section),
+                );
+                None
+            }
+        };
+
+        let mut targets: Vec<(String, Range<usize>)> = Vec::new();
+        for item in items.into_iter().flatten() {
+            let DeValue::String(target) = item.get_ref() else {
+                self.error(
+                    "invalid-type",
+                    item.span(),
+                    format!("{} 数组元素必须是字符串", section),
+                );
+                continue;
+            };
+
+            if targets.iter().any(|(t, _)| t == target.as_ref()) {
+                self.error(
+                    "duplicate-target",
+                    item.span(),
+                    format!("[{}] 中重复的路径: {}", section, target),
+                );
+                continue;
+            }
+
+            self.check_target(section, target, item.span());
+            // 通配符模式匹配到关键文件时同样提示
+            if section == "delete"
+                && let Some(critical) = CRITICAL_PATHS
+                    .iter()
+                    .find(|path| pck::glob_match(target, path))
+            {
+                self.warning(
+                    "suspicious-action",
+                    item.span(),
+                    format!("删除 {} 可能导致游戏无法启动或版本校验失败", critical),
+                );
+            }
+            if section == "stub"
+                && let Err(err) = stub::stub_resource(target)
+            {
+                self.error("unsupported-stub", item.span(), err.to_string());
+            }
+
+            targets.push((target.to_string(), item.span()));
+        }
+
+        targets
+    }
+}
+
+fn lint_content(content: &str, base_dir: &Path) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        content,
+        base_dir,
+        diagnostics: Vec::new(),
+    };
+
+    let (root, errors) = DeTable::parse_recoverable(content);
+    for err in &errors {
+        linter.error(
+            "parse-error",
+            err.span().unwrap_or(0..0),
+            err.message().to_string(),
+        );
+    }
+
+    let mut required_game_version = None;
+    let mut hashed_versions = None;
+    let mut replace_targets = None;
+    let mut delete_targets = Vec::new();
+    let mut stub_targets = Vec::new();
+    let mut encrypt_targets = Vec::new();
+    let mut has_includes = false;
+
+    for (key, value) in root.get_ref().iter() {
+        match key.get_ref().as_ref() {
+            "version" => {
+                if let Some(table) = linter.expect_table("version", value) {
+                    required_game_version = linter.check_version(key.span(), table);
+                }
+            }
+            "version-hash" => {
+                if let Some(table) = linter.expect_table("version-hash", value) {
+                    hashed_versions = Some(linter.check_version_hash(table));
+                }
+            }
+            "replace" => {
+                if let Some(table) = linter.expect_table("replace", value) {
+                    replace_targets = Some(linter.check_replace(table));
+                }
+            }
+            "original-md5" => {
+                if let Some(table) = linter.expect_table("original-md5", value) {
+                    linter.check_original_md5(table);
+                }
+            }
+            "require" => {
+                if let Err((span, _, message)) = manifest::check_require(value) {
+                    linter.error("invalid-type", span, message);
+                }
+            }
+            "project-settings" => {
+                if let Err((span, _, message)) = manifest::check_project_settings(value) {
+                    linter.error("invalid-type", span, message);
+                }
+            }
+            "include" => has_includes = linter.check_includes(value),
+            "options" => match manifest::check_options(value) {
+                Err((span, _, message)) => linter.error("invalid-type", span, message),
+                Ok(()) => {
+                    let DeValue::Table(options) = value.get_ref() else {
+                        unreachable!("check_options 已保证 options 是表");
+                    };
+                    for (_, option) in options.iter() {
+                        let fields = option.get_ref().as_table();
+                        if let Some(include) = fields.and_then(|t| t.get("include")) {
+                            linter.check_includes(include);
+                        }
+                    }
+                }
+            },
+            "delete" => delete_targets = linter.check_path_list("delete", value),
+            "stub" => stub_targets = linter.check_path_list("stub", value),
+            "encrypt" => encrypt_targets = linter.check_path_list("encrypt", value),
+            other => linter.warning(
+                "unknown-key",
+                key.span(),
+                format!("未知的顶层字段: {}", other),
+            ),
+        }
+    }
+
+    // 只有在文档可以解析时才报告缺失的表，避免与语法错误重复；
+    // 有 include 时这些表可能由其他文件提供
+    if errors.is_empty() && !has_includes {
+        let keys: Vec<&str> = root
+            .get_ref()
+            .iter()
+            .map(|(k, _)| k.get_ref().as_ref())
+            .collect();
+        for required in ["version", "version-hash", "replace"] {
+            if !keys.contains(&required) {
+                linter.error(
+                    "missing-key",
+                    0..0,
+                    format!("replace.toml 缺少 [{}] 表", required),
+                );
+            }
+        }
+    }
+
+    if let (Some(version), Some(hashed)) = (&required_game_version, &hashed_versions)
+        && !hashed.contains(version)
+    {
+        linter.warning(
+            "missing-key",
+            0..0,
+            format!(
+                "[version-hash] 中没有 {} 的哈希，未注入过的游戏将无法通过版本校验",
+                version
+            ),
+        );
+    }
+
+    for (target, span) in replace_targets.iter().flatten() {
+        if delete_targets.iter().any(|(t, _)| t == target) {
+            linter.warning(
+                "duplicate-target",
+                span.clone(),
+                format!("{} 同时出现在 [replace] 与 delete 中", target),
+            );
+        }
+    }
+
+    // 与 replace/delete 冲突时 apply 会直接失败
+    for (target, span) in &stub_targets {
+        let other = if replace_targets.iter().flatten().any(|(t, _)| t == target) {
+            Some("[replace]")
+        } else if delete_targets.iter().any(|(t, _)| t == target) {
+            Some("delete")
+        } else {
+            None
+        };
+        if let Some(other) = other {
+            linter.error(
+                "duplicate-target",
+                span.clone(),
+                format!("{} 同时出现在 {} 与 stub 中", target, other),
+            );
+        }
+    }
+
+    // 只能加密本 MOD 写入的文件；有 include 时目标可能在其他文件中
+    for (target, span) in &encrypt_targets {
+        let written = replace_targets.iter().flatten().any(|(t, _)| t == target)
+            || stub_targets.iter().any(|(t, _)| t == target);
+        if !written && !has_includes {
+            linter.error(
+                "suspicious-target",
+                span.clone(),
+                format!("{} 不在 [replace] 或 stub 中，无法加密", target),
+            );
+        }
+    }
+
+    linter.diagnostics.sort_by_key(|d| (d.line, d.column));
+    linter.diagnostics
+}
+
+fn is_absolute_local_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || path.starts_with('\\')
+        || path.starts_with('~')
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<&'static str> {
+        diagnostics.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn reports_unknown_keys_with_position() {
+        let manifest = r#"
+[version]
+required-game-version = "1.0.10b"
+plugin-version = "0.6.2"
+author = "someone"
+
+[version-hash]
+"1.0.10b" = "597baead816b32429c2ea9ac5f340ae8"
+
+[replace]
+"#;
+        let diagnostics = lint_content(manifest, Path::new("."));
+        assert_eq!(codes(&diagnostics), vec!["unknown-key"]);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (5, 1));
+    }
+
+    #[test]
+    fn reports_path_and_target_problems() {
+        let manifest = r#"
+[version]
+required-game-version = "1.0.10b"
+plugin-version = "0.6.2"
+
+[version-hash]
+
+[replace]
+"res://Core/Game.gde" = "C:\\mods\\Game.gde"
+"Core/Shop.gde" = "missing/Shop.gde"
+
+[delete]
+paths = ["res://Core/Game.gde", "res://Core/Game.gde"]
+"#;
+        let diagnostics = lint_content(manifest, Path::new("/nonexistent"));
+        let found = codes(&diagnostics);
+        for expected in [
+            "absolute-path",
+            "suspicious-target",
+            "missing-file",
+            "duplicate-target",
+            "suspicious-action",
+        ] {
+            assert!(
+                found.contains(&expected),
+                "missing {} in {:?}",
+                expected,
+                found
+            );
+        }
+        assert!(
+            diagnostics
+                .iter()
+                .any(|d| d.message.contains("[version-hash]"))
+        );
+    }
+
+    #[test]
+    fn reports_stub_and_encrypt_problems() {
+        let manifest = r#"
+stub = ["res://UI/Menu.tscn", "res://Core/Game.gde"]
+encrypt = ["res://UI/Menu.tscn", "res://Art/Other.png"]
+
+[version]
+required-game-version = "1.0.10b"
+plugin-version = "0.6.2"
+
+[version-hash]
+"1.0.10b" = "597baead816b32429c2ea9ac5f340ae8"
+
+[replace]
+"res://UI/Menu.tscn" = "Menu.tscn"
+"#;
+        let diagnostics = lint_content(manifest, Path::new("/nonexistent"));
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                "duplicate-target",
+                "unsupported-stub",
+                "suspicious-target",
+                "missing-file"
+            ]
+        );
+    }
+
+    #[test]
+    fn json_output_escapes_messages() {
+        let diagnostics = vec![Diagnostic {
+            severity: Severity::Error,
+            code: "parse-error",
+            message: "bad \"quote\"".to_string(),
+            line: 1,
+            column: 2,
+        }];
+        assert_eq!(
+            render_json("replace.toml", &diagnostics),
+            r#"{"file":"replace.toml","diagnostics":[{"severity":"error","code":"parse-error","message":"bad \"quote\"","line":1,"column":2}]}"#
+        );
+    }
+}
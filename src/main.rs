@@ -1,32 +1,122 @@
 #![cfg_attr(all(windows, not(debug_assertions)), windows_subsystem = "windows")]
 
-mod pck;
+#[cfg(feature = "gui")]
+mod about;
+#[cfg(feature = "cli")]
+mod archive;
+#[cfg(feature = "gui")]
+mod automation;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod backup;
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "cli")]
+mod compression;
+mod config;
+#[cfg(feature = "gui")]
+mod crash;
+#[cfg(feature = "cli")]
+mod depot;
+#[cfg(feature = "cli")]
+mod diff;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod dirs;
+#[cfg(feature = "cli")]
+mod explain;
+mod foreign;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod hotkey;
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod import_pairs;
+mod journal;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod launch;
+#[cfg(feature = "cli")]
+mod lint;
+mod manifest;
+#[cfg(feature = "cli")]
+mod mapping;
+#[cfg(feature = "cli")]
+mod merge;
+#[cfg(feature = "mount")]
+mod mount;
+#[cfg(feature = "gui")]
+mod onboarding;
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod overlay;
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod pending;
+mod platform;
+#[cfg(feature = "gui")]
+mod profiles;
+mod project_settings;
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod progress;
+mod provenance;
+#[cfg(feature = "cli")]
+mod rebase;
+#[cfg(feature = "online")]
+mod remote;
+mod report;
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod res_type;
+#[cfg(feature = "cli")]
+mod scaffold;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod staging;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
 mod steam;
+#[cfg(feature = "cli")]
+mod stress;
+mod stub;
+#[cfg(feature = "cli")]
+mod transfer;
 mod tweak;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod tweak_pack;
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+mod undo;
+#[cfg(feature = "cli")]
+mod zip;
 
-use std::path::PathBuf;
-use anyhow::{Context, Result};
+use bpb_enhance::pck;
 
 #[cfg(feature = "gui")]
-use anyhow::anyhow;
+use anyhow::{Context, Result, anyhow};
+#[cfg(feature = "gui")]
+use std::path::{Path, PathBuf};
 
 #[cfg(feature = "gui")]
 use gpui::{
-    AppContext, Application, Bounds, Context as GpuiContext, IntoElement, ParentElement, Render,
-    SharedString, Styled, Window, WindowBounds, WindowOptions, div, point, px, size,
+    AppContext, Application, Bounds, Context as GpuiContext, InteractiveElement, IntoElement,
+    KeyBinding, ParentElement, Render, SharedString, Styled, Window, WindowBounds, WindowOptions,
+    actions, div, point, px, size,
 };
 #[cfg(feature = "gui")]
 use gpui_component::{
-    ActiveTheme as _, Root, StyledExt as _, WindowExt,
+    ActiveTheme as _, IndexPath, Root, Sizable as _, StyledExt as _, WindowExt,
     button::{Button, ButtonVariants},
+    checkbox::Checkbox,
+    dialog::DialogButtonProps,
     h_flex,
     input::{Input, InputState},
     notification::NotificationType,
+    select::{Select, SelectEvent, SelectState},
     v_flex,
 };
 #[cfg(feature = "gui")]
+use automation::{OutcomeKind, Step};
+#[cfg(feature = "gui")]
 use rfd::FileDialog;
 #[cfg(feature = "gui")]
+use std::cell::Cell;
+#[cfg(feature = "gui")]
+use std::rc::Rc;
+#[cfg(feature = "gui")]
+use bpb_enhance::pck::DiagnosticsSink;
+#[cfg(feature = "gui")]
+use std::sync::Arc;
+#[cfg(feature = "gui")]
 use std::sync::mpsc;
 #[cfg(feature = "gui")]
 use std::thread;
@@ -37,31 +127,29 @@ use tweak::tweak_game_gde;
 const DEFAULT_STEAM_PATH: &str = r"C:\Program Files (x86)\Steam\steamapps\common\Backpack Battles";
 #[cfg(feature = "gui")]
 const DEFAULT_PCK_NAME: &str = "BackpackBattles.pck";
+/// 通知记录最多保留的条数
+#[cfg(feature = "gui")]
+const MAX_HISTORY: usize = 100;
 
-#[cfg(feature = "cli")]
-use clap::Parser;
-
-#[cfg(feature = "cli")]
-#[derive(Debug, Parser)]
-#[command(name = "bpb_enhance")]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    #[arg(short, long, help = "Path to the PCK file")]
-    pck: String,
-
-    #[arg(short, long, help = "Path to the assets folder containing replace.toml")]
-    assets: String,
-}
+#[cfg(feature = "gui")]
+actions!(bpb_enhance, [Undo]);
 
 #[cfg(feature = "gui")]
 fn main() {
+    crash::install_hook();
+    if std::env::args().skip(1).any(|arg| arg == "--portable") {
+        config::enable_portable();
+    }
+
     Application::new().run(|app| {
         gpui_component::init(app);
+        // 输入框获得焦点时 Ctrl+Z 仍用于撤销输入
+        app.bind_keys([KeyBinding::new("secondary-z", Undo, None)]);
 
         let window_options = WindowOptions {
             window_bounds: Some(WindowBounds::Windowed(Bounds::new(
                 point(px(100.), px(100.)),
-                size(px(700.), px(240.)),
+                size(px(820.), px(300.)),
             ))),
             window_min_size: Some(size(px(520.), px(220.))),
             ..WindowOptions::default()
@@ -76,38 +164,8 @@ fn main() {
 }
 
 #[cfg(feature = "cli")]
-fn main() -> Result<()> {
-    let args = Args::parse();
-
-    let pck_path = PathBuf::from(&args.pck);
-    let assets_path = PathBuf::from(&args.assets);
-
-    if !pck_path.exists() {
-        anyhow::bail!("PCK file does not exist: {}", args.pck);
-    }
-    if !pck_path.is_file() {
-        anyhow::bail!("Path is not a file: {}", args.pck);
-    }
-    if !assets_path.exists() {
-        anyhow::bail!("Assets folder does not exist: {}", args.assets);
-    }
-    if !assets_path.is_dir() {
-        anyhow::bail!("Path is not a directory: {}", args.assets);
-    }
-
-    let replace_toml = assets_path.join("replace.toml");
-    if !replace_toml.exists() {
-        anyhow::bail!("replace.toml not found in assets folder: {}", args.assets);
-    }
-
-    println!("Processing PCK file: {}", args.pck);
-    println!("Using assets folder: {}", args.assets);
-
-    tweak::tweak_game_gde(&args.pck, &args.assets)
-        .with_context(|| format!("Failed to tweak PCK file: {}", args.pck))?;
-
-    println!("Successfully tweaked PCK file: {}", args.pck);
-    Ok(())
+fn main() -> anyhow::Result<()> {
+    cli::run()
 }
 
 #[cfg(feature = "gui")]
@@ -115,30 +173,164 @@ struct RootView {
     game_path: gpui::Entity<InputState>,
     default_detected: bool,
     picker_open: bool,
+    last_report: Option<report::PatchReport>,
+    /// 最近一次成功修改的 PCK，全局快捷键重新应用到它
+    last_applied: Option<String>,
+    automation: Option<automation::Automation>,
+    history: Vec<HistoryEntry>,
+    /// 读取与修改 PCK 时的警告，操作结束后移入通知记录
+    diagnostics: Arc<pck::DiagnosticsBuffer>,
+    profiles: profiles::Profiles,
+    /// 修改资源包中可单独开关的功能，资源包无法加载时为空
+    tweak_options: Vec<manifest::ManifestOption>,
+    onboarding: onboarding::Onboarding,
+    /// 检测到其他工具的修改后用户选择先备份：即使关闭了备份，下一次修改前也备份一次
+    backup_next_apply: bool,
+    undo: undo::UndoStack,
+    crash_watch: launch::CrashWatch,
+    focus_handle: gpui::FocusHandle,
+    profile_select: gpui::Entity<SelectState<Vec<String>>>,
+    _subscriptions: Vec<gpui::Subscription>,
+}
+
+/// 本次运行中出现过的通知，供“通知记录”查看
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    /// Unix 时间戳（秒）
+    at: u64,
+    kind: NotificationType,
+    message: String,
+    /// 通知涉及的文件或目录，供“打开所在文件夹”和“复制路径”使用
+    path: Option<PathBuf>,
 }
 
 #[cfg(feature = "gui")]
 impl RootView {
     fn new(window: &mut Window, cx: &mut GpuiContext<Self>) -> Self {
         let detected_path = detect_default_path();
+        let profiles = profiles::Profiles::load();
+        let initial_path = profiles
+            .active_profile()
+            .map(|p| p.game_path.clone())
+            .or_else(|| detected_path.clone());
+        let startup_pck = initial_path
+            .as_deref()
+            .and_then(|path| resolve_pck_path(path).ok());
 
         let game_path = cx.new(|cx| {
             let mut state = InputState::new(window, cx)
                 .placeholder("输入游戏目录或 PCK 文件路径")
                 .clean_on_escape();
 
-            if let Some(path) = detected_path.clone() {
+            if let Some(path) = initial_path {
                 state = state.default_value(path);
             }
 
             state
         });
 
-        Self {
+        let selected = profiles
+            .active
+            .as_deref()
+            .and_then(|name| profiles.list.iter().position(|p| p.name == name))
+            .map(IndexPath::new);
+        let names = profiles.names();
+        let profile_select = cx.new(|cx| SelectState::new(names, selected, window, cx));
+        let _subscriptions = vec![cx.subscribe_in(
+            &profile_select,
+            window,
+            |view, _, event: &SelectEvent<Vec<String>>, window, cx| {
+                let SelectEvent::Confirm(Some(name)) = event else {
+                    return;
+                };
+                view.switch_profile(name.clone(), window, cx);
+            },
+        )];
+
+        let automation = match automation::Automation::from_env() {
+            Some(Ok(automation)) => {
+                cx.on_next_frame(window, |view, window, cx| view.run_automation(window, cx));
+                Some(automation)
+            }
+            Some(Err(err)) => {
+                println!("{:?}", err);
+                std::process::exit(2);
+            }
+            None => None,
+        };
+
+        let onboarding = onboarding::Onboarding::load();
+        // 脚本模式视为已确认，不弹出须知
+        if !onboarding.accepted && automation.is_none() {
+            cx.on_next_frame(window, |view, window, cx| {
+                view.show_onboarding(false, window, cx)
+            });
+        }
+
+        let (tweak_options, options_error) = match tweak::tweak_options() {
+            Ok(options) => (options, None),
+            Err(err) => (Vec::new(), Some(err)),
+        };
+
+        let focus_handle = cx.focus_handle();
+        window.focus(&focus_handle);
+
+        let diagnostics = Arc::new(pck::DiagnosticsBuffer::default());
+
+        let mut view = Self {
             game_path,
             default_detected: detected_path.is_some(),
             picker_open: false,
+            last_report: None,
+            last_applied: None,
+            automation,
+            history: Vec::new(),
+            diagnostics,
+            profiles,
+            tweak_options,
+            onboarding,
+            backup_next_apply: false,
+            undo: undo::UndoStack::default(),
+            crash_watch: launch::CrashWatch::default(),
+            focus_handle,
+            profile_select,
+            _subscriptions,
+        };
+        if let Some(hotkey) = config::load().gui.reapply_hotkey {
+            match hotkey::register(hotkey) {
+                Ok(pressed) => {
+                    view.listen_for_hotkey(pressed, cx);
+                    view.record(
+                        NotificationType::Info,
+                        format!("按 {} 可重新应用最近一次修改", hotkey),
+                    );
+                }
+                Err(err) => view.record(NotificationType::Warning, format!("{:#}", err)),
+            }
+        }
+        if let Some(err) = options_error {
+            view.record(NotificationType::Warning, format!("{:#}", err));
+        }
+        if let Some(path) = &detected_path {
+            view.record_with_path(
+                NotificationType::Info,
+                format!("已自动检测到游戏路径：{}", path),
+                PathBuf::from(path),
+            );
         }
+        if let Some(pck) = startup_pck {
+            match journal::recover(&pck) {
+                Ok(true) => view.record_with_path(
+                    NotificationType::Warning,
+                    format!("上次修改时程序意外退出，已把 {} 回滚到那次修改之前", pck.display()),
+                    pck,
+                ),
+                Ok(false) => {}
+                Err(err) => view.record(NotificationType::Error, format!("{:#}", err)),
+            }
+        }
+        view
     }
 }
 
@@ -148,6 +340,8 @@ impl Render for RootView {
         let game_path_input = Input::new(&self.game_path).prefix(div().text_sm().child("📁"));
 
         div()
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(|view, _: &Undo, window, cx| view.undo(window, cx)))
             .size_full()
             .bg(cx.theme().secondary)
             .child(
@@ -164,7 +358,49 @@ impl Render for RootView {
                                         .font_semibold()
                                         .child("Backpack Battles 修改工具"),
                                 )
-                                .child(h_flex().gap_2().children(self.default_hint(cx))),
+                                .child(
+                                    h_flex()
+                                        .gap_2()
+                                        .children(self.default_hint(cx))
+                                        .child(
+                                            Select::new(&self.profile_select)
+                                                .placeholder("安装配置")
+                                                .w(px(140.)),
+                                        )
+                                        .child(
+                                            Button::new("save-profile").label("保存配置").on_click(
+                                                cx.listener(|view, _, window, cx| {
+                                                    view.on_save_profile_click(window, cx);
+                                                }),
+                                            ),
+                                        )
+                                        .children(self.profiles.active.is_some().then(|| {
+                                            Button::new("remove-profile")
+                                                .label("删除配置")
+                                                .on_click(cx.listener(|view, _, window, cx| {
+                                                    view.on_remove_profile_click(window, cx);
+                                                }))
+                                        }))
+                                        .child(
+                                            Button::new("onboarding").label("使用须知").on_click(
+                                                cx.listener(|view, _, window, cx| {
+                                                    view.show_onboarding(false, window, cx);
+                                                }),
+                                            ),
+                                        )
+                                        .child(Button::new("about").label("关于").on_click(
+                                            cx.listener(|view, _, window, cx| {
+                                                view.on_about_click(window, cx);
+                                            }),
+                                        ))
+                                        .child(
+                                            Button::new("history")
+                                                .label(format!("🔔 {}", self.history.len()))
+                                                .on_click(cx.listener(|view, _, window, cx| {
+                                                    view.on_history_click(window, cx);
+                                                })),
+                                        ),
+                                ),
                         )
                         .child(
                             v_flex()
@@ -173,14 +409,64 @@ impl Render for RootView {
                                     h_flex()
                                         .gap_2()
                                         .items_center()
-                                        .child(div().text_sm().font_semibold().child("游戏路径")),
+                                        .child(div().text_sm().font_semibold().child("游戏路径"))
+                                        .child(
+                                            Button::new("reveal-game-path")
+                                                .ghost()
+                                                .xsmall()
+                                                .label("打开所在文件夹")
+                                                .on_click(cx.listener(|view, _, window, cx| {
+                                                    let path = view.current_path(cx);
+                                                    reveal_path(Path::new(path.trim()), window, cx);
+                                                })),
+                                        )
+                                        .child(
+                                            Button::new("copy-game-path")
+                                                .ghost()
+                                                .xsmall()
+                                                .label("复制路径")
+                                                .on_click(cx.listener(|view, _, window, cx| {
+                                                    let path = view.current_path(cx);
+                                                    copy_path(Path::new(path.trim()), window, cx);
+                                                })),
+                                        ),
                                 )
                                 .child(game_path_input),
                         )
+                        .children(self.options_panel(cx))
                         .child(
                             h_flex()
                                 .gap_2()
                                 .justify_end()
+                                .children(self.undo.peek().map(|label| {
+                                    Button::new("undo")
+                                        .label(format!("撤销「{}」", label))
+                                        .on_click(cx.listener(|view, _, window, cx| {
+                                            view.undo(window, cx);
+                                        }))
+                                }))
+                                .children(
+                                    (self.onboarding.backup == onboarding::BackupMode::BeforeApply)
+                                        .then(|| {
+                                            Button::new("restore").label("从备份恢复").on_click(
+                                                cx.listener(|view, _, window, cx| {
+                                                    view.on_restore_click(window, cx);
+                                                }),
+                                            )
+                                        }),
+                                )
+                                .children(self.last_report.is_some().then(|| {
+                                    Button::new("report").label("导出报告").on_click(cx.listener(
+                                        |view, _, _, _| {
+                                            view.on_export_report_click();
+                                        },
+                                    ))
+                                }))
+                                .child(Button::new("launch").label("启动游戏").on_click(
+                                    cx.listener(|view, _, window, cx| {
+                                        view.on_launch_click(window, cx);
+                                    }),
+                                ))
                                 .child(Button::new("pick").primary().label("选择文件").on_click(
                                     cx.listener(|view, _, window, cx| {
                                         view.on_pick_click(window, cx);
@@ -219,6 +505,68 @@ impl RootView {
         }
     }
 
+    /// 资源包中可选功能的开关，开关状态随当前安装配置保存
+    fn options_panel(&self, cx: &mut GpuiContext<Self>) -> Option<gpui::AnyElement> {
+        if self.tweak_options.is_empty() {
+            return None;
+        }
+        let choices = self.profiles.options();
+        let checkboxes = self.tweak_options.iter().map(|option| {
+            let id = option.id.clone();
+            let checked = choices.get(&option.id).copied().unwrap_or(option.default);
+            let label = match &option.description {
+                Some(description) => format!("{}（{}）", option.title, description),
+                None => option.title.clone(),
+            };
+            Checkbox::new(SharedString::from(format!("tweak-option-{}", option.id)))
+                .label(label)
+                .checked(checked)
+                .on_click(cx.listener(move |view, checked: &bool, window, cx| {
+                    view.set_tweak_option(&id, *checked, window, cx);
+                }))
+        });
+        Some(
+            v_flex()
+                .gap_1()
+                .child(div().text_sm().font_semibold().child("修改内容"))
+                .child(h_flex().gap_4().flex_wrap().children(checkboxes))
+                .child(
+                    div()
+                        .text_xs()
+                        .text_color(cx.theme().muted_foreground)
+                        .child("关闭已应用过的功能时，请先从备份恢复或通过 Steam 验证文件，再重新应用。"),
+                )
+                .into_any_element(),
+        )
+    }
+
+    fn set_tweak_option(
+        &mut self,
+        id: &str,
+        enabled: bool,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) {
+        self.profiles.set_option(id, enabled);
+        if let Err(err) = self.profiles.save() {
+            println!("{:?}", err);
+            self.show_error("保存配置失败", format!("{:#}", err), window, cx);
+        }
+        cx.notify();
+    }
+
+    /// 当前的可选功能开关；只保留资源包中仍存在的功能，资源包更新后旧的开关不会导致修改失败
+    fn option_selection(&self) -> manifest::OptionSelection {
+        manifest::OptionSelection(
+            self.profiles
+                .options()
+                .iter()
+                .filter(|(id, _)| self.tweak_options.iter().any(|option| option.id == **id))
+                .map(|(id, enabled)| (id.clone(), *enabled))
+                .collect(),
+        )
+    }
+
     fn set_game_path(&self, path: &str, window: &mut Window, cx: &mut GpuiContext<Self>) {
         self.game_path.update(cx, |input, cx| {
             input.set_value(path.to_string(), window, cx)
@@ -229,36 +577,819 @@ impl RootView {
         self.game_path.read(cx).value().to_string()
     }
 
+    /// 先弹出确认框，确认期间在后台预读需要搬移的数据
     fn on_apply_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        // 首次写入前必须确认过使用须知
+        if !self.onboarding.accepted {
+            return self.show_onboarding(true, window, cx);
+        }
+
+        let pck_path = match resolve_pck_path(&self.current_path(cx)) {
+            Ok(path) => path,
+            // 路径无效时直接走修改流程，由它统一提示错误
+            Err(_) => return self.apply(window, cx),
+        };
+        if self.offer_overlay_if_read_only(&pck_path, window, cx) {
+            return;
+        }
+
+        let pck_str = pck_path.to_string_lossy().to_string();
+        if let Err(err) = tweak::prefetch_for_apply(&pck_str, &self.option_selection()) {
+            println!("预读失败: {:?}", err);
+        }
+
+        // 关闭了备份时，发现其他工具的修改就建议先备份；开启备份时备份会自动加上标签
+        let findings = foreign::detect(&pck_path).unwrap_or_default();
+        let offer_backup =
+            !findings.is_empty() && self.onboarding.backup != onboarding::BackupMode::BeforeApply;
+        let weak = cx.entity().downgrade();
+        window.open_dialog(cx, move |dialog, _, _| {
+            let weak = weak.clone();
+            let mut content = v_flex()
+                .gap_2()
+                .child(format!("将修改 {}，请先关闭游戏。", pck_str));
+            if !findings.is_empty() {
+                content = content.child("检测到这个 PCK 已被其他工具修改过：");
+                for finding in &findings {
+                    content = content.child(format!("· {}", finding));
+                }
+            }
+            if offer_backup {
+                content = content.child("建议先备份，备份会标记为“已有修改”，恢复时不会被当作原版。");
+            }
+            let dialog = dialog
+                .title("确认修改")
+                .child(content.child(path_actions("confirm", PathBuf::from(&pck_str))))
+                .confirm();
+            let dialog = if offer_backup {
+                dialog.button_props(
+                    DialogButtonProps::default()
+                        .ok_text("备份后修改")
+                        .cancel_text("取消"),
+                )
+            } else {
+                dialog
+            };
+            dialog.on_ok(move |_, window, cx| {
+                let _ = weak.update(cx, |view, cx| {
+                    view.backup_next_apply = offer_backup;
+                    view.apply(window, cx)
+                });
+                true
+            })
+        });
+    }
+
+    fn apply(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
         let input_path = self.current_path(cx);
+        self.apply_to(input_path, window, cx);
+    }
 
-        let result = resolve_pck_path(&input_path).and_then(|pck_path| {
-            let pck_str = pck_path
-                .to_str()
-                .ok_or_else(|| anyhow!("路径包含非法字符"))?
-                .to_string();
+    /// 全局快捷键：不弹确认框，直接重新应用到最近一次成功修改的 PCK（如 Steam 校验文件后）
+    fn reapply(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        match self.last_applied.clone() {
+            Some(path) if self.onboarding.accepted => self.apply_to(path, window, cx),
+            Some(_) => self.show_onboarding(true, window, cx),
+            None => {
+                let msg = "本次运行中还没有成功的修改，请先点击“应用”";
+                self.record(NotificationType::Warning, msg.to_string());
+                window.push_notification((NotificationType::Warning, SharedString::from(msg)), cx);
+            }
+        }
+    }
 
-            tweak_game_gde(&pck_str)
-                .with_context(|| format!("修改失败，文件: {}", pck_str))?;
+    fn apply_to(&mut self, input_path: String, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        if let Ok(pck_path) = resolve_pck_path(&input_path)
+            && self.offer_overlay_if_read_only(&pck_path, window, cx)
+        {
+            return;
+        }
+        let backup_first = self.onboarding.backup == onboarding::BackupMode::BeforeApply
+            || std::mem::take(&mut self.backup_next_apply);
+        let selection = self.option_selection();
 
-            Ok::<_, anyhow::Error>(pck_str)
+        let diagnostics = pck::scope_diagnostics(self.diagnostics.clone());
+        let result = crash::catch(|| {
+            resolve_pck_path(&input_path).and_then(|pck_path| {
+                let pck_str = pck_path
+                    .to_str()
+                    .ok_or_else(|| anyhow!("路径包含非法字符"))?
+                    .to_string();
+
+                // 检测失败不影响修改，读取 PCK 出错时由后面的修改流程报告
+                let foreign = foreign::detect(&pck_path).unwrap_or_default();
+                let label = (!foreign.is_empty()).then_some(backup::PRE_EXISTING_LABEL);
+                let backup = backup_first
+                    .then(|| backup::create_labeled(&pck_path, &config::load().backup, label))
+                    .transpose()
+                    .context("备份失败，未做任何修改（可在“使用须知”中关闭备份）")?;
+                self.undo
+                    .record(format!("修改 {}", pck_str), &pck_path)
+                    .context("无法保存撤销快照，未做任何修改")?;
+
+                let report = tweak_game_gde(&pck_str, &selection)
+                    .with_context(|| format!("修改失败，文件: {}", pck_str))?;
+
+                Ok::<_, anyhow::Error>((pck_str, report, backup))
+            })
         });
+        drop(diagnostics);
+        self.record_diagnostics();
+
+        let result = match result {
+            Ok(result) => result,
+            Err(panic_message) => {
+                let message = crash::describe(&panic_message);
+                self.show_error("程序出错", message, window, cx);
+                return;
+            }
+        };
 
         match result {
-            Ok(path) => {
+            Ok((path, report, backup)) => {
+                self.undo.finish();
+                self.crash_watch.mod_applied();
+                self.last_report = Some(report);
+                self.last_applied = Some(path.clone());
+                if let Some(backup) = backup {
+                    self.record_with_path(
+                        NotificationType::Info,
+                        format!("已备份修改前的 PCK：{}", backup.display()),
+                        backup,
+                    );
+                }
                 let msg = format!("修改完成：{}", path);
+                if let Some(automation) = &mut self.automation {
+                    automation.record_outcome(OutcomeKind::Success, &msg);
+                }
+                self.record_with_path(NotificationType::Success, msg.clone(), PathBuf::from(&path));
                 window.push_notification((NotificationType::Success, SharedString::from(msg)), cx);
             }
             Err(err) => {
-                let message = format!("{:#}", err);
+                println!("{:?}", err);
+                self.show_error("操作失败", format!("{:#}", err), window, cx);
+            }
+        }
+    }
+
+    /// PCK 无法就地修改（只读文件、只读磁盘、没有权限）时说明原因与解决办法，游戏目录可以写入时
+    /// 提供改为生成覆盖包，不修改 PCK；返回是否拦下了这次修改
+    fn offer_overlay_if_read_only(
+        &mut self,
+        pck_path: &Path,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) -> bool {
+        use platform::WriteProtection;
+
+        let Some(protection) = platform::write_protection(pck_path) else {
+            return false;
+        };
+        let fix = match protection {
+            WriteProtection::ReadOnlyFile => "在文件属性中取消勾选“只读”",
+            WriteProtection::ReadOnlyVolume => {
+                "让磁盘可以写入，或在 Steam 设置的“存储空间”中把游戏移动到其他磁盘"
+            }
+            WriteProtection::AccessDenied => {
+                "给当前账户游戏目录的写入权限、以管理员身份运行，或在 Steam 设置的“存储空间”中把游戏移动到受保护位置之外的库"
+            }
+        };
+        let message = format!(
+            "无法修改 {}：{}。\n\n要直接修改 PCK，请{}。",
+            pck_path.display(),
+            protection,
+            fix
+        );
+        let overlay_dir = overlay::overlay_dir(pck_path)
+            .ok()
+            .filter(|_| overlay::can_write_packs(pck_path));
+        let Some(overlay_dir) = overlay_dir.filter(|_| self.automation.is_none()) else {
+            self.show_error("无法写入 PCK", message, window, cx);
+            return true;
+        };
 
+        let weak = cx.entity().downgrade();
+        let pck_path = pck_path.to_path_buf();
+        window.open_dialog(cx, move |dialog, _, _| {
+            let weak = weak.clone();
+            let pck_path = pck_path.clone();
+            dialog
+                .title("无法写入 PCK")
+                .child(
+                    v_flex().gap_2().child(message.clone()).child(format!(
+                        "也可以不修改 PCK，把修改生成为覆盖包放到 {}。",
+                        overlay_dir.display()
+                    )),
+                )
+                .confirm()
+                .button_props(
+                    DialogButtonProps::default()
+                        .ok_text("生成覆盖包")
+                        .cancel_text("取消"),
+                )
+                .on_ok(move |_, window, cx| {
+                    let _ = weak.update(cx, |view, cx| view.apply_overlay(&pck_path, window, cx));
+                    true
+                })
+        });
+        true
+    }
+
+    /// 覆盖包模式：用修改资源包生成 `mods/` 下的覆盖包，PCK 保持不变，因此不需要备份与撤销快照
+    fn apply_overlay(&mut self, pck_path: &Path, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let selection = self.option_selection();
+        let result = crash::catch(|| {
+            let pck_str = pck_path.to_str().ok_or_else(|| anyhow!("路径包含非法字符"))?;
+            tweak::build_override_pack(pck_str, &selection)
+                .with_context(|| format!("生成覆盖包失败，文件: {}", pck_str))
+        });
+        match result {
+            Ok(Ok(pack)) => {
+                let msg = format!("已生成覆盖包，PCK 未被修改：{}", pack.display());
+                self.record_with_path(NotificationType::Success, msg.clone(), pack);
+                window.push_notification((NotificationType::Success, SharedString::from(msg)), cx);
+            }
+            Ok(Err(err)) => {
                 println!("{:?}", err);
+                self.show_error("操作失败", format!("{:#}", err), window, cx);
+            }
+            Err(panic_message) => {
+                let message = crash::describe(&panic_message);
+                self.show_error("程序出错", message, window, cx);
+            }
+        }
+    }
 
-                window.open_dialog(cx, move |dialog, _, _| {
-                    dialog.title("操作失败").alert().child(message.clone())
+    fn show_error(
+        &mut self,
+        title: &'static str,
+        message: String,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) {
+        if let Some(automation) = &mut self.automation {
+            automation.record_outcome(OutcomeKind::Error, &message);
+        }
+        self.record(NotificationType::Error, format!("{}：{}", title, message));
+        window.open_dialog(cx, move |dialog, _, _| {
+            dialog.title(title).alert().child(message.clone())
+        });
+    }
+
+    /// 确认后用最近一次的备份覆盖 PCK；备份先经过校验，损坏时不做任何修改
+    fn on_restore_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let pck_path = match resolve_pck_path(&self.current_path(cx)) {
+            Ok(path) => path,
+            Err(err) => return self.show_error("恢复失败", format!("{:#}", err), window, cx),
+        };
+        let backup = match backup::latest(&pck_path, &config::load().backup) {
+            Ok(Some(backup)) => backup,
+            Ok(None) => {
+                let message = format!("找不到 {} 的备份", pck_path.display());
+                return self.show_error("恢复失败", message, window, cx);
+            }
+            Err(err) => return self.show_error("恢复失败", format!("{:#}", err), window, cx),
+        };
+
+        let weak = cx.entity().downgrade();
+        window.open_dialog(cx, move |dialog, _, _| {
+            let weak = weak.clone();
+            let (pck_path, backup) = (pck_path.clone(), backup.clone());
+            dialog
+                .title("确认恢复")
+                .child(
+                    v_flex()
+                        .gap_2()
+                        .child(format!(
+                            "将用 {} 覆盖 {}，请先关闭游戏。",
+                            backup.display(),
+                            pck_path.display()
+                        ))
+                        .child(path_actions("restore", backup.clone())),
+                )
+                .confirm()
+                .on_ok(move |_, window, cx| {
+                    let (pck_path, backup) = (pck_path.clone(), backup.clone());
+                    let _ = weak.update(cx, |view, cx| {
+                        view.restore(&pck_path, &backup, window, cx)
+                    });
+                    true
+                })
+        });
+    }
+
+    fn restore(
+        &mut self,
+        pck_path: &Path,
+        backup: &Path,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) {
+        let label = format!("从备份恢复 {}", pck_path.display());
+        let result = self
+            .undo
+            .record(label, pck_path)
+            .context("无法保存撤销快照，未做任何修改")
+            .and_then(|_| backup::restore_from(pck_path, backup));
+        match result {
+            Ok(()) => {
+                self.undo.finish();
+                self.crash_watch.reset();
+                self.last_report = None;
+                let msg = format!("已从 {} 恢复", backup.display());
+                self.record_with_path(NotificationType::Success, msg.clone(), pck_path.into());
+                window.push_notification((NotificationType::Success, SharedString::from(msg)), cx);
+            }
+            Err(err) => {
+                println!("{:?}", err);
+                self.show_error("恢复失败", format!("{:#}", err), window, cx);
+            }
+        }
+        cx.notify();
+    }
+
+    /// 启动 PCK 旁的游戏可执行文件，并在后台等待它退出
+    fn on_launch_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let result = resolve_pck_path(&self.current_path(cx)).and_then(|pck_path| {
+            let exe = launch::game_executable(&pck_path)
+                .ok_or_else(|| anyhow!("在 PCK 旁找不到游戏可执行文件，请通过 Steam 启动"))?;
+            let child = std::process::Command::new(&exe)
+                .current_dir(exe.parent().unwrap_or(Path::new(".")))
+                .spawn()
+                .with_context(|| format!("无法启动 {}", exe.display()))?;
+            Ok((exe, child))
+        });
+        let (exe, mut child) = match result {
+            Ok(launched) => launched,
+            Err(err) => return self.show_error("启动失败", format!("{:#}", err), window, cx),
+        };
+        self.record_with_path(
+            NotificationType::Info,
+            format!("已启动游戏：{}", exe.display()),
+            exe,
+        );
+
+        let started = std::time::Instant::now();
+        let weak = cx.entity().downgrade();
+        cx.spawn(move |_, app: &mut gpui::AsyncApp| {
+            let app = app.clone();
+            async move {
+                let status = app
+                    .background_executor()
+                    .spawn(async move { child.wait() })
+                    .await;
+                let ran_for = started.elapsed();
+                let _ = app.update(|app| {
+                    if let Some(window) = app.active_window() {
+                        let _ = app.update_window(window, |_, window, cx| {
+                            weak.update(cx, |view, cx| {
+                                view.on_game_exit(status, ran_for, window, cx)
+                            })
+                        });
+                    }
                 });
             }
+        })
+        .detach();
+    }
+
+    /// 在后台等待快捷键，每次按下时在主线程上重新应用
+    fn listen_for_hotkey(&self, pressed: mpsc::Receiver<()>, cx: &mut GpuiContext<Self>) {
+        let weak = cx.entity().downgrade();
+        cx.spawn(move |_, app: &mut gpui::AsyncApp| {
+            let app = app.clone();
+            async move {
+                let mut pressed = pressed;
+                loop {
+                    let (received, rx) = app
+                        .background_executor()
+                        .spawn(async move { (pressed.recv(), pressed) })
+                        .await;
+                    if received.is_err() {
+                        break;
+                    }
+                    pressed = rx;
+                    let _ = app.update(|app| {
+                        if let Some(window) = app.windows().first().copied() {
+                            let _ = app.update_window(window, |_, window, cx| {
+                                weak.update(cx, |view, cx| view.reapply(window, cx))
+                            });
+                        }
+                    });
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// 应用 MOD 后游戏连续启动即崩溃时，提示撤销修改或从备份恢复
+    fn on_game_exit(
+        &mut self,
+        status: std::io::Result<std::process::ExitStatus>,
+        ran_for: std::time::Duration,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) {
+        let success = status.as_ref().is_ok_and(|s| s.success());
+        let message = match &status {
+            Ok(status) if status.success() => "游戏已退出".to_string(),
+            Ok(status) => format!("游戏异常退出（{}），运行了 {} 秒", status, ran_for.as_secs()),
+            Err(err) => format!("无法获取游戏的退出状态: {}", err),
+        };
+        let kind = if success {
+            NotificationType::Info
+        } else {
+            NotificationType::Warning
+        };
+        self.record(kind, message);
+
+        if !self.crash_watch.game_exited(success, ran_for) {
+            return;
         }
+        let can_undo = self.undo.peek().is_some();
+        let action = if can_undo {
+            "撤销最近一次修改"
+        } else {
+            "从备份恢复"
+        };
+        let weak = cx.entity().downgrade();
+        window.open_dialog(cx, move |dialog, _, _| {
+            let weak = weak.clone();
+            dialog
+                .title("游戏连续启动失败")
+                .child(format!(
+                    "应用 MOD 后游戏连续两次在启动后 {} 秒内崩溃，可能是最近应用的 MOD 与当前游戏版本不兼容。是否{}？",
+                    launch::QUICK_EXIT.as_secs(),
+                    action
+                ))
+                .confirm()
+                .button_props(
+                    DialogButtonProps::default()
+                        .ok_text(action)
+                        .cancel_text("暂不处理"),
+                )
+                .on_ok(move |_, window, cx| {
+                    let _ = weak.update(cx, |view, cx| {
+                        if can_undo {
+                            view.undo(window, cx);
+                        } else {
+                            view.on_restore_click(window, cx);
+                        }
+                    });
+                    true
+                })
+        });
+        cx.notify();
+    }
+
+    /// 撤销本次运行中最近一次修改（Ctrl+Z）
+    fn undo(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        match self.undo.undo() {
+            Ok(Some((label, pck))) => {
+                self.crash_watch.reset();
+                self.last_report = None;
+                let msg = format!("已撤销「{}」", label);
+                self.record_with_path(NotificationType::Success, msg.clone(), pck);
+                window.push_notification((NotificationType::Success, SharedString::from(msg)), cx);
+            }
+            Ok(None) => {
+                window.push_notification(
+                    (
+                        NotificationType::Info,
+                        SharedString::from("没有可撤销的修改"),
+                    ),
+                    cx,
+                );
+            }
+            Err(err) => {
+                println!("{:?}", err);
+                self.show_error("撤销失败", format!("{:#}", err), window, cx);
+            }
+        }
+        cx.notify();
+    }
+
+    /// 说明会修改什么、备份放在哪里、如何恢复，并让用户选择是否备份；
+    /// `then_apply` 为真时确认后继续修改流程
+    fn show_onboarding(
+        &mut self,
+        then_apply: bool,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) {
+        let backup_first = Rc::new(Cell::new(
+            self.onboarding.backup == onboarding::BackupMode::BeforeApply,
+        ));
+        let weak = cx.entity().downgrade();
+
+        window.open_dialog(cx, move |dialog, _, _| {
+            let checkbox_state = backup_first.clone();
+            let ok_state = backup_first.clone();
+            let weak = weak.clone();
+            dialog
+                .title("使用须知")
+                .w(px(560.))
+                .child(
+                    v_flex()
+                        .gap_2()
+                        .text_sm()
+                        .child(format!(
+                            "本工具会直接修改游戏目录中的 {}（游戏的脚本与资源包），不会改动存档或其他文件。修改前请先关闭游戏。",
+                            DEFAULT_PCK_NAME
+                        ))
+                        .child(format!(
+                            "备份：开启后，每次修改前会把 PCK 复制为 {}.bak-<时间>（默认在同一目录，可在设置文件的 [backup] 中修改位置与保留份数），保留最近 3 份。恢复时关闭游戏，点击“从备份恢复”即恢复到最近一次修改之前，备份会先经过校验，损坏时不会覆盖 {}。",
+                            DEFAULT_PCK_NAME, DEFAULT_PCK_NAME
+                        ))
+                        .child(
+                            "随时可以通过 Steam 还原：在库中右键 Backpack Battles → 属性 → 已安装文件 → 验证游戏文件的完整性，Steam 会重新下载被修改的文件。",
+                        )
+                        .child("游戏更新后修改会被覆盖，需要重新应用。")
+                        .child(
+                            Checkbox::new("onboarding-backup")
+                                .label("修改前备份 PCK（推荐，需要与 PCK 同样大小的磁盘空间）")
+                                .checked(backup_first.get())
+                                .on_click(move |checked, window, _| {
+                                    checkbox_state.set(*checked);
+                                    window.refresh();
+                                }),
+                        ),
+                )
+                .confirm()
+                .button_props(
+                    DialogButtonProps::default()
+                        .ok_text("我已了解，同意修改")
+                        .cancel_text("稍后"),
+                )
+                .on_ok(move |_, window, cx| {
+                    let backup = if ok_state.get() {
+                        onboarding::BackupMode::BeforeApply
+                    } else {
+                        onboarding::BackupMode::Off
+                    };
+                    let _ = weak.update(cx, |view, cx| {
+                        view.accept_onboarding(backup, window, cx);
+                        if then_apply {
+                            view.on_apply_click(window, cx);
+                        }
+                    });
+                    true
+                })
+        });
+    }
+
+    fn accept_onboarding(
+        &mut self,
+        backup: onboarding::BackupMode,
+        window: &mut Window,
+        cx: &mut GpuiContext<Self>,
+    ) {
+        self.onboarding.accepted = true;
+        self.onboarding.backup = backup;
+        if let Err(err) = self.onboarding.save() {
+            // 保存失败时本次运行仍按确认结果执行，下次启动会再次显示
+            println!("{:?}", err);
+            self.show_error("保存设置失败", format!("{:#}", err), window, cx);
+        }
+        let message = match backup {
+            onboarding::BackupMode::BeforeApply => "已确认使用须知，修改前会自动备份 PCK",
+            onboarding::BackupMode::Off => "已确认使用须知，修改前不会备份 PCK",
+        };
+        self.record(NotificationType::Info, message.to_string());
+        cx.notify();
+    }
+
+    /// 把修改过程中收集到的警告放进通知记录，调试信息只写日志
+    fn record_diagnostics(&mut self) {
+        for diagnostic in self.diagnostics.take() {
+            if diagnostic.level() <= log::Level::Warn {
+                self.record(NotificationType::Warning, diagnostic.to_string());
+            } else {
+                pck::LogSink.report(&diagnostic);
+            }
+        }
+    }
+
+    fn record(&mut self, kind: NotificationType, message: String) {
+        self.push_history(kind, message, None);
+    }
+
+    fn record_with_path(&mut self, kind: NotificationType, message: String, path: PathBuf) {
+        self.push_history(kind, message, Some(path));
+    }
+
+    /// 记入通知记录，超出上限时丢弃最早的
+    fn push_history(&mut self, kind: NotificationType, message: String, path: Option<PathBuf>) {
+        let at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.history.push(HistoryEntry {
+            at,
+            kind,
+            message,
+            path,
+        });
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+    }
+
+    fn on_history_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let history = self.history.clone();
+        window.open_dialog(cx, move |dialog, _, _| {
+            let rows = history.iter().rev().enumerate().map(|(i, entry)| {
+                let icon = match entry.kind {
+                    NotificationType::Success => "✓",
+                    NotificationType::Error | NotificationType::Warning => "✗",
+                    NotificationType::Info => "ℹ",
+                };
+                h_flex()
+                    .gap_2()
+                    .items_start()
+                    .child(div().text_xs().child(icon))
+                    .child(
+                        div()
+                            .text_xs()
+                            .child(provenance::format_timestamp(entry.at)),
+                    )
+                    .child(div().text_sm().child(entry.message.clone()))
+                    .children(
+                        entry
+                            .path
+                            .clone()
+                            .map(|path| path_actions(("history", i), path)),
+                    )
+            });
+
+            dialog.title("通知记录").child(if history.is_empty() {
+                v_flex().child(div().text_sm().child("本次运行还没有通知"))
+            } else {
+                v_flex().gap_2().children(rows)
+            })
+        });
+    }
+
+    /// 版本与运行环境，附一键复制，方便反馈问题时附上
+    fn on_about_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let items = about::collect();
+        let text = about::to_text(&items);
+        window.open_dialog(cx, move |dialog, _, _| {
+            let text = text.clone();
+            let rows = items.iter().map(|(name, value)| {
+                h_flex()
+                    .gap_2()
+                    .items_start()
+                    .child(div().text_sm().font_semibold().w(px(96.)).child(*name))
+                    .child(div().text_sm().child(value.clone()))
+            });
+            dialog
+                .title("关于")
+                .w(px(560.))
+                .child(
+                    v_flex().gap_1().children(rows).child(
+                        h_flex().justify_end().child(
+                            Button::new("copy-about")
+                                .label("复制全部")
+                                .on_click(move |_, window, cx| {
+                                    cx.write_to_clipboard(gpui::ClipboardItem::new_string(
+                                        text.clone(),
+                                    ));
+                                    window.push_notification(
+                                        (NotificationType::Success, SharedString::from("已复制")),
+                                        cx,
+                                    );
+                                }),
+                        ),
+                    ),
+                )
+        });
+    }
+
+    /// 切换到指定配置，并记住为下次启动时的默认配置
+    fn switch_profile(&mut self, name: String, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let Some(profile) = self.profiles.get(&name).cloned() else {
+            return;
+        };
+        self.set_game_path(&profile.game_path, window, cx);
+        self.profiles.active = Some(name);
+        self.save_profiles(window, cx);
+        self.record_with_path(
+            NotificationType::Info,
+            format!("已切换到配置「{}」：{}", profile.name, profile.game_path),
+            PathBuf::from(&profile.game_path),
+        );
+    }
+
+    /// 把当前游戏路径保存为配置（同名覆盖）
+    fn on_save_profile_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let default_name = self.profiles.active.clone().unwrap_or_default();
+        let name_input = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("配置名称，例如 正式版、测试分支")
+                .default_value(default_name)
+        });
+        let weak = cx.entity().downgrade();
+
+        window.open_dialog(cx, move |dialog, _, _| {
+            let name_input = name_input.clone();
+            let weak = weak.clone();
+            dialog
+                .title("保存配置")
+                .child(Input::new(&name_input))
+                .confirm()
+                .on_ok(move |_, window, cx| {
+                    let name = name_input.read(cx).value().trim().to_string();
+                    if name.is_empty() {
+                        return false;
+                    }
+                    let _ = weak.update(cx, |view, cx| {
+                        let path = view.current_path(cx);
+                        view.profiles.upsert(&name, path.trim());
+                        view.profiles.active = Some(name.clone());
+                        view.save_profiles(window, cx);
+                        view.record(NotificationType::Info, format!("已保存配置「{}」", name));
+                    });
+                    true
+                })
+        });
+    }
+
+    fn on_remove_profile_click(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let Some(name) = self.profiles.active.clone() else {
+            return;
+        };
+        self.profiles.remove(&name);
+        self.save_profiles(window, cx);
+        self.record(NotificationType::Info, format!("已删除配置「{}」", name));
+    }
+
+    /// 写入配置文件并刷新下拉列表
+    fn save_profiles(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let names = self.profiles.names();
+        let active = self.profiles.active.clone();
+        self.profile_select.update(cx, |select, cx| {
+            select.set_items(names, window, cx);
+            match &active {
+                Some(name) => select.set_selected_value(name, window, cx),
+                None => select.set_selected_index(None, window, cx),
+            }
+        });
+
+        if let Err(err) = self.profiles.save() {
+            println!("{:?}", err);
+            self.show_error("保存配置失败", format!("{:#}", err), window, cx);
+        }
+        cx.notify();
+    }
+
+    /// 按顺序执行 GUI 脚本中的步骤（见 automation 模块）
+    fn run_automation(&mut self, window: &mut Window, cx: &mut GpuiContext<Self>) {
+        let steps = match &mut self.automation {
+            Some(automation) => automation.take_steps(),
+            None => return,
+        };
+
+        for step in steps {
+            match step {
+                Step::SetPath(path) => self.set_game_path(&path, window, cx),
+                // 脚本模式跳过确认框
+                Step::ClickApply => self.apply(window, cx),
+                Step::Expect(kind, text) => {
+                    if let Some(automation) = &mut self.automation {
+                        automation.check(kind, text.as_deref());
+                    }
+                }
+                Step::Quit => {
+                    let code = self.automation.as_ref().map_or(0, |a| a.exit_code());
+                    std::process::exit(code);
+                }
+            }
+        }
+    }
+
+    fn on_export_report_click(&mut self) {
+        let Some(report) = self.last_report.clone() else {
+            return;
+        };
+
+        thread::spawn(move || {
+            let result = crash::catch(|| {
+                let picked = FileDialog::new()
+                    .set_file_name("bpb_enhance_report.md")
+                    .add_filter("Markdown", &["md"])
+                    .add_filter("HTML", &["html"])
+                    .save_file();
+                if let Some(path) = picked
+                    && let Err(err) = report.write_to(&path)
+                {
+                    println!("{:?}", err);
+                }
+            });
+            if let Err(panic_message) = result {
+                println!("{}", crash::describe(&panic_message));
+            }
+        });
     }
 
     fn on_pick_click(&mut self, _window: &mut Window, cx: &mut GpuiContext<Self>) {
@@ -269,10 +1400,15 @@ impl RootView {
 
         let (tx, rx) = mpsc::channel();
         thread::spawn(move || {
-            let picked = FileDialog::new()
-                .add_filter("PCK 文件", &["pck"])
-                .pick_file()
-                .and_then(|p| p.to_str().map(|s| s.to_string()));
+            // 文件对话框崩溃时发送 None，保证 picker_open 被复位
+            let picked = crash::catch(|| {
+                FileDialog::new()
+                    .add_filter("PCK 文件", &["pck"])
+                    .pick_file()
+                    .and_then(|p| p.to_str().map(|s| s.to_string()))
+            })
+            .ok()
+            .flatten();
             let _ = tx.send(picked);
         });
 
@@ -284,16 +1420,16 @@ impl RootView {
                 let _ = app_clone.update(|app| {
                     let mut cleared = false;
 
-                    if let Some(path) = picked.clone() {
-                        if let Some(window) = app.active_window() {
-                            let _ = app.update_window(window, |_, window, cx| {
-                                weak.update(cx, |view, cx| {
-                                    view.picker_open = false;
-                                    view.set_game_path(&path, window, cx);
-                                })
-                            });
-                            cleared = true;
-                        }
+                    if let Some(path) = picked.clone()
+                        && let Some(window) = app.active_window()
+                    {
+                        let _ = app.update_window(window, |_, window, cx| {
+                            weak.update(cx, |view, cx| {
+                                view.picker_open = false;
+                                view.set_game_path(&path, window, cx);
+                            })
+                        });
+                        cleared = true;
                     }
 
                     if !cleared {
@@ -308,6 +1444,62 @@ impl RootView {
     }
 }
 
+/// 路径旁的“打开所在文件夹”与“复制路径”按钮
+#[cfg(feature = "gui")]
+fn path_actions(id: impl Into<gpui::ElementId>, path: PathBuf) -> impl IntoElement {
+    let id = id.into();
+    let copied = path.clone();
+    h_flex()
+        .gap_1()
+        .child(
+            Button::new((id.clone(), "reveal"))
+                .ghost()
+                .xsmall()
+                .label("打开所在文件夹")
+                .on_click(move |_, window, cx| reveal_path(&path, window, cx)),
+        )
+        .child(
+            Button::new((id, "copy"))
+                .ghost()
+                .xsmall()
+                .label("复制路径")
+                .on_click(move |_, window, cx| copy_path(&copied, window, cx)),
+        )
+}
+
+#[cfg(feature = "gui")]
+fn reveal_path(path: &Path, window: &mut Window, cx: &mut gpui::App) {
+    let result = if path.as_os_str().is_empty() {
+        Err(anyhow!("请先输入游戏路径"))
+    } else if !path.exists() {
+        Err(anyhow!("路径不存在：{}", path.display()))
+    } else {
+        platform::open_in_file_manager(path)
+    };
+    if let Err(err) = result {
+        println!("{:?}", err);
+        window.push_notification(
+            (
+                NotificationType::Error,
+                SharedString::from(format!("{:#}", err)),
+            ),
+            cx,
+        );
+    }
+}
+
+#[cfg(feature = "gui")]
+fn copy_path(path: &Path, window: &mut Window, cx: &mut gpui::App) {
+    if path.as_os_str().is_empty() {
+        return;
+    }
+    cx.write_to_clipboard(gpui::ClipboardItem::new_string(path.display().to_string()));
+    window.push_notification(
+        (NotificationType::Success, SharedString::from("已复制路径")),
+        cx,
+    );
+}
+
 #[cfg(feature = "gui")]
 fn detect_default_path() -> Option<String> {
     // 1) Try Steam multi-library detection.
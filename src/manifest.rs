@@ -0,0 +1,827 @@
+//! replace.toml 的结构校验与组合：apply 前先检查表、字段与值的类型，出错时报告准确的行列号、
+//! 出错的字段和一段正确写法的示例，而不是只给出一句"解析失败"。
+//!
+//! 大型 MOD 可以用 `include = ["common.toml", "textures/manifest.toml"]` 把规则拆到多个文件，
+//! 路径与资源路径一样相对于 MOD 目录；[`compose`] 把它们合并成一份 manifest，并记录每条规则的来源。
+//!
+//! `[options]` 把 MOD 拆成可单独开关的功能，每个功能的规则写在它 include 的文件中，
+//! 只有启用的功能才会被合并。
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+
+use anyhow::{Context, Result, bail};
+use toml::de::{DeTable, DeValue};
+use toml::{Spanned, Table, Value};
+
+/// MOD 目录中的主 manifest
+pub const ROOT_FILE: &str = "replace.toml";
+
+const VERSION_EXAMPLE: &str =
+    "[version]\nrequired-game-version = \"1.0.10b\"\nplugin-version = \"0.6.2\"";
+const ORIGINAL_MD5_EXAMPLE: &str =
+    "[original-md5]\n\"res://Core/Game.gde\" = \"75715e94e1166e3e699e1b63f01b9488\"";
+const VERSION_HASH_EXAMPLE: &str =
+    "[version-hash]\n\"1.0.10b\" = \"597baead816b32429c2ea9ac5f340ae8\"";
+const REPLACE_EXAMPLE: &str = "[replace]\n\"res://Core/Game.gde\" = \"Core/Game.gde\"";
+const DELETE_EXAMPLE: &str = "delete = [\"res://UI/Unused.tscn\"]";
+const STUB_EXAMPLE: &str = "stub = [\"res://UI/Unused.tscn\"]";
+const ENCRYPT_EXAMPLE: &str = "encrypt = [\"res://Art/Commission.png\"]";
+const REQUIRE_EXAMPLE: &str =
+    "[require]\nmin_tool_version = \"0.4\"\nplatform = \"windows\"\nfree_space_mb = 500";
+const INCLUDE_EXAMPLE: &str = "include = [\"common.toml\", \"textures/manifest.toml\"]";
+const PROJECT_SETTINGS_EXAMPLE: &str =
+    "[project-settings]\n\"display/window/size/viewport_width\" = 1920";
+const OPTIONS_EXAMPLE: &str =
+    "[options.item-library]\ntitle = \"物品库改进\"\ninclude = [\"options/item_library.toml\"]";
+
+/// 带位置的 manifest 结构错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub file: String,
+    /// 1 起始的行列号
+    pub line: usize,
+    pub column: usize,
+    /// 出错的字段，如 `version.plugin-version`
+    pub key: String,
+    pub message: String,
+    pub example: &'static str,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}（字段: {}）\n正确写法示例:",
+            self.file, self.line, self.column, self.message, self.key
+        )?;
+        for line in self.example.lines() {
+            write!(f, "\n    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// 检查完整 manifest 的结构，返回遇到的第一个错误；未知字段留给 lint 提示，不视为错误
+pub fn check_schema(content: &str) -> Result<(), SchemaError> {
+    check(ROOT_FILE, content, true)
+}
+
+/// `require_tables` 为 false 时用于 include 的片段：必需的表与字段可以由其他文件提供
+fn check(file: &str, content: &str, require_tables: bool) -> Result<(), SchemaError> {
+    let error = |span: Range<usize>, key: &str, message: String, example| {
+        let (line, column) = line_column(content, span.start);
+        SchemaError {
+            file: file.to_string(),
+            line,
+            column,
+            key: key.to_string(),
+            message,
+            example,
+        }
+    };
+
+    let root = DeTable::parse(content).map_err(|err| {
+        let span = err.span().unwrap_or(0..0);
+        let section = section_at(content, span.start);
+        error(
+            span,
+            section.unwrap_or("(顶层)"),
+            err.message().trim_end().to_string(),
+            section.map_or(REPLACE_EXAMPLE, example_for),
+        )
+    })?;
+
+    for required in ["version", "version-hash", "replace"] {
+        if require_tables && !root.get_ref().iter().any(|(k, _)| k.get_ref() == required) {
+            return Err(error(
+                0..0,
+                required,
+                format!("缺少 [{}] 表", required),
+                example_for(required),
+            ));
+        }
+    }
+
+    for (key, value) in root.get_ref().iter() {
+        let name = key.get_ref().as_ref();
+        match name {
+            "version" | "version-hash" | "replace" | "original-md5" => {
+                let DeValue::Table(table) = value.get_ref() else {
+                    return Err(error(
+                        value.span(),
+                        name,
+                        format!("{} 必须是表", name),
+                        example_for(name),
+                    ));
+                };
+                if name == "version" && require_tables {
+                    for field in ["required-game-version", "plugin-version"] {
+                        if !table.iter().any(|(k, _)| k.get_ref() == field) {
+                            return Err(error(
+                                key.span(),
+                                &format!("version.{}", field),
+                                format!("[version] 缺少 {} 字段", field),
+                                VERSION_EXAMPLE,
+                            ));
+                        }
+                    }
+                }
+                for (inner_key, inner) in table.iter() {
+                    // [version] 中的未知字段不做类型要求
+                    if name == "version"
+                        && !matches!(
+                            inner_key.get_ref().as_ref(),
+                            "required-game-version" | "plugin-version"
+                        )
+                    {
+                        continue;
+                    }
+                    if !matches!(inner.get_ref(), DeValue::String(_)) {
+                        return Err(error(
+                            inner.span(),
+                            &format!("{}.{}", name, inner_key.get_ref()),
+                            format!("[{}] 中的值必须是字符串", name),
+                            example_for(name),
+                        ));
+                    }
+                }
+            }
+            "include" => {
+                let valid = match value.get_ref() {
+                    DeValue::Array(arr) => arr
+                        .iter()
+                        .all(|item| matches!(item.get_ref(), DeValue::String(_))),
+                    _ => false,
+                };
+                if !valid {
+                    return Err(error(
+                        value.span(),
+                        name,
+                        "include 必须是文件路径的数组".to_string(),
+                        INCLUDE_EXAMPLE,
+                    ));
+                }
+            }
+            "require" => check_require(value)
+                .map_err(|(span, key, message)| error(span, &key, message, REQUIRE_EXAMPLE))?,
+            "project-settings" => check_project_settings(value).map_err(|(span, key, message)| {
+                error(span, &key, message, PROJECT_SETTINGS_EXAMPLE)
+            })?,
+            "options" if file != ROOT_FILE => {
+                return Err(error(
+                    key.span(),
+                    name,
+                    format!("options 只能写在 {} 中", ROOT_FILE),
+                    OPTIONS_EXAMPLE,
+                ));
+            }
+            "options" => check_options(value)
+                .map_err(|(span, key, message)| error(span, &key, message, OPTIONS_EXAMPLE))?,
+            "delete" | "stub" | "encrypt" => check_path_list(name, value)
+                .map_err(|(span, key, message)| error(span, &key, message, example_for(name)))?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// 字段错误：（位置, 字段, 说明）
+pub type FieldError = (Range<usize>, String, String);
+
+/// 检查 `[require]`：未知的前提条件视为错误，避免作者写错字段名后条件被静默忽略
+pub fn check_require(value: &Spanned<DeValue<'_>>) -> Result<(), FieldError> {
+    let DeValue::Table(table) = value.get_ref() else {
+        return Err((
+            value.span(),
+            "require".to_string(),
+            "require 必须是表".to_string(),
+        ));
+    };
+
+    for (key, inner) in table.iter() {
+        let name = key.get_ref().as_ref();
+        let valid = match name {
+            "min_tool_version" => matches!(inner.get_ref(), DeValue::String(_)),
+            "platform" => match inner.get_ref() {
+                DeValue::String(_) => true,
+                DeValue::Array(arr) => arr
+                    .iter()
+                    .all(|item| matches!(item.get_ref(), DeValue::String(_))),
+                _ => false,
+            },
+            "free_space_mb" => matches!(inner.get_ref(), DeValue::Integer(_)),
+            other => {
+                return Err((
+                    key.span(),
+                    format!("require.{}", other),
+                    format!(
+                        "未知的前提条件 {}，可用的有 min_tool_version、platform、free_space_mb",
+                        other
+                    ),
+                ));
+            }
+        };
+        if !valid {
+            let expected = match name {
+                "min_tool_version" => "版本号字符串",
+                "platform" => "平台名或平台名数组",
+                _ => "整数",
+            };
+            return Err((
+                inner.span(),
+                format!("require.{}", name),
+                format!("require.{} 必须是{}", name, expected),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 检查 `[project-settings]`：值只能是布尔、整数、浮点数或字符串
+pub fn check_project_settings(value: &Spanned<DeValue<'_>>) -> Result<(), FieldError> {
+    let DeValue::Table(table) = value.get_ref() else {
+        return Err((
+            value.span(),
+            "project-settings".to_string(),
+            "project-settings 必须是表".to_string(),
+        ));
+    };
+    for (key, inner) in table.iter() {
+        if !matches!(
+            inner.get_ref(),
+            DeValue::Boolean(_) | DeValue::Integer(_) | DeValue::Float(_) | DeValue::String(_)
+        ) {
+            return Err((
+                inner.span(),
+                format!("project-settings.{}", key.get_ref()),
+                "[project-settings] 中的值必须是布尔、整数、浮点数或字符串".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 检查 `[options]`：每个可选功能是包含 title 与 include 的表，description 与 default 可省略
+pub fn check_options(value: &Spanned<DeValue<'_>>) -> Result<(), FieldError> {
+    let DeValue::Table(options) = value.get_ref() else {
+        return Err((
+            value.span(),
+            "options".to_string(),
+            "options 必须是表".to_string(),
+        ));
+    };
+
+    for (id, option) in options.iter() {
+        let id = id.get_ref();
+        let DeValue::Table(fields) = option.get_ref() else {
+            return Err((
+                option.span(),
+                format!("options.{}", id),
+                format!("options.{} 必须是表", id),
+            ));
+        };
+        for required in ["title", "include"] {
+            if !fields.iter().any(|(k, _)| k.get_ref() == required) {
+                return Err((
+                    option.span(),
+                    format!("options.{}.{}", id, required),
+                    format!("可选功能 {} 缺少 {} 字段", id, required),
+                ));
+            }
+        }
+        for (key, inner) in fields.iter() {
+            let name = key.get_ref().as_ref();
+            let valid = match name {
+                "title" | "description" => matches!(inner.get_ref(), DeValue::String(_)),
+                "default" => matches!(inner.get_ref(), DeValue::Boolean(_)),
+                "include" => match inner.get_ref() {
+                    DeValue::Array(arr) => arr
+                        .iter()
+                        .all(|item| matches!(item.get_ref(), DeValue::String(_))),
+                    _ => false,
+                },
+                other => {
+                    return Err((
+                        key.span(),
+                        format!("options.{}.{}", id, other),
+                        format!(
+                            "未知的字段 {}，可用的有 title、description、default、include",
+                            other
+                        ),
+                    ));
+                }
+            };
+            if !valid {
+                let expected = match name {
+                    "default" => "布尔值",
+                    "include" => "文件路径的数组",
+                    _ => "字符串",
+                };
+                return Err((
+                    inner.span(),
+                    format!("options.{}.{}", id, name),
+                    format!("options.{}.{} 必须是{}", id, name, expected),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 检查 `key = [...]` 或 `[key] paths = [...]`
+fn check_path_list(name: &str, value: &Spanned<DeValue<'_>>) -> Result<(), FieldError> {
+    let items = match value.get_ref() {
+        DeValue::Array(arr) => arr,
+        DeValue::Table(table) => {
+            let paths = table
+                .iter()
+                .find(|(k, _)| k.get_ref() == "paths")
+                .map(|(_, v)| v)
+                .ok_or_else(|| {
+                    (
+                        value.span(),
+                        name.to_string(),
+                        format!("{} 表需要 paths 数组", name),
+                    )
+                })?;
+            match paths.get_ref() {
+                DeValue::Array(arr) => arr,
+                _ => {
+                    return Err((
+                        paths.span(),
+                        format!("{}.paths", name),
+                        format!("{}.paths 必须是数组", name),
+                    ));
+                }
+            }
+        }
+        _ => {
+            return Err((
+                value.span(),
+                name.to_string(),
+                format!("{} 必须是数组或包含 paths 的表", name),
+            ));
+        }
+    };
+
+    for (i, item) in items.iter().enumerate() {
+        if !matches!(item.get_ref(), DeValue::String(_)) {
+            return Err((
+                item.span(),
+                format!("{}[{}]", name, i),
+                format!("{} 数组元素必须是字符串", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `[options]` 中的一个可选功能：启用时合并它 include 的文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestOption {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// 用户没有选择时是否启用，省略时为 true
+    pub default: bool,
+    pub include: Vec<String>,
+}
+
+/// 读取主 manifest 中的可选功能，按 id 排序
+pub fn parse_options(root: &str) -> Result<Vec<ManifestOption>> {
+    check(ROOT_FILE, root, false)?;
+    let table: Table = toml::from_str(root).with_context(|| format!("解析 {} 失败", ROOT_FILE))?;
+    let Some(options) = table.get("options").and_then(Value::as_table) else {
+        return Ok(Vec::new());
+    };
+
+    // 结构校验已保证字段齐全且类型正确
+    let text = |fields: &Table, key: &str| {
+        fields.get(key).and_then(Value::as_str).map(str::to_string)
+    };
+    Ok(options
+        .iter()
+        .filter_map(|(id, fields)| {
+            let fields = fields.as_table()?;
+            Some(ManifestOption {
+                id: id.clone(),
+                title: text(fields, "title")?,
+                description: text(fields, "description"),
+                default: fields.get("default").and_then(Value::as_bool).unwrap_or(true),
+                include: fields
+                    .get("include")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|path| path.as_str().map(str::to_string))
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+/// 对可选功能的选择（id -> 是否启用），没有提到的功能按其 default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionSelection(pub BTreeMap<String, bool>);
+
+impl OptionSelection {
+    /// 启用的功能；选择了 manifest 中没有的功能时报错，避免拼错的 id 被静默忽略
+    pub fn enabled<'a>(&self, options: &'a [ManifestOption]) -> Result<Vec<&'a ManifestOption>> {
+        if let Some(unknown) = self
+            .0
+            .keys()
+            .find(|id| !options.iter().any(|option| option.id == **id))
+        {
+            let known: Vec<&str> = options.iter().map(|option| option.id.as_str()).collect();
+            bail!(
+                "MOD 中没有可选功能 {}，可用的有: {}",
+                unknown,
+                if known.is_empty() {
+                    "（无）".to_string()
+                } else {
+                    known.join(", ")
+                }
+            );
+        }
+        Ok(options
+            .iter()
+            .filter(|option| self.0.get(&option.id).copied().unwrap_or(option.default))
+            .collect())
+    }
+}
+
+/// 合并 include 后的 manifest
+#[derive(Debug)]
+pub struct Composed {
+    pub content: String,
+    /// 启用并已合并的可选功能 id
+    pub enabled_options: Vec<String>,
+    /// （表名, 规则的键或路径）-> 定义它的文件
+    origins: HashMap<(String, String), String>,
+}
+
+impl Composed {
+    /// 规则所在的文件；没有 include 时都是 replace.toml
+    pub fn origin(&self, section: &str, key: &str) -> &str {
+        self.origins
+            .get(&(section.to_string(), key.to_string()))
+            .map_or(ROOT_FILE, String::as_str)
+    }
+}
+
+/// 从主 manifest 开始递归合并 include 的文件，`load` 按相对 MOD 目录的路径读取文件。
+/// 同一规则在不同文件中取值不同时报错；同一文件被多次 include 只合并一次，形成循环时报错。
+/// 按 `selection` 启用可选功能，启用的功能 include 的文件视为主 manifest 的 include
+pub fn compose<F>(root: &str, selection: &OptionSelection, mut load: F) -> Result<Composed>
+where
+    F: FnMut(&str) -> Result<String>,
+{
+    let options = parse_options(root)?;
+    let enabled = selection.enabled(&options)?;
+    let mut composer = Composer {
+        merged: Table::new(),
+        origins: HashMap::new(),
+        visited: HashSet::from([ROOT_FILE.to_string()]),
+        stack: Vec::new(),
+        option_includes: enabled
+            .iter()
+            .flat_map(|option| option.include.iter().map(|path| normalize(path)))
+            .collect(),
+    };
+    composer.add(ROOT_FILE, root, &mut load)?;
+    Ok(Composed {
+        content: composer.merged.to_string(),
+        enabled_options: enabled.iter().map(|option| option.id.clone()).collect(),
+        origins: composer.origins,
+    })
+}
+
+struct Composer {
+    merged: Table,
+    origins: HashMap<(String, String), String>,
+    visited: HashSet<String>,
+    /// 当前 include 链，用于检测循环
+    stack: Vec<String>,
+    /// 启用的可选功能 include 的文件，随主 manifest 一起合并
+    option_includes: Vec<String>,
+}
+
+impl Composer {
+    fn add(
+        &mut self,
+        file: &str,
+        content: &str,
+        load: &mut dyn FnMut(&str) -> Result<String>,
+    ) -> Result<()> {
+        check(file, content, false)?;
+        let table: Table =
+            toml::from_str(content).with_context(|| format!("解析 {} 失败", file))?;
+
+        self.stack.push(file.to_string());
+        let mut includes: Vec<String> = table
+            .get("include")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .map(|include| normalize(include.as_str().unwrap_or_default()))
+            .collect();
+        if file == ROOT_FILE {
+            includes.append(&mut self.option_includes);
+        }
+        for name in includes {
+            if self.stack.contains(&name) {
+                bail!("include 形成循环: {} -> {}", self.stack.join(" -> "), name);
+            }
+            if !self.visited.insert(name.clone()) {
+                continue;
+            }
+            let text =
+                load(&name).with_context(|| format!("无法读取 {} 中 include 的 {}", file, name))?;
+            self.add(&name, &text, load)?;
+        }
+        self.stack.pop();
+
+        for (section, value) in table {
+            match section.as_str() {
+                "include" | "options" => {}
+                "version" | "version-hash" | "replace" | "require" | "original-md5"
+                | "project-settings" => {
+                    let Value::Table(rules) = value else {
+                        unreachable!("结构校验已保证 [{}] 是表", section);
+                    };
+                    let merged = self
+                        .merged
+                        .entry(section.clone())
+                        .or_insert_with(|| Value::Table(Table::new()))
+                        .as_table_mut()
+                        .expect("合并结果中的表");
+                    for (key, rule) in rules {
+                        let origin_key = (section.clone(), key.clone());
+                        match merged.get(&key) {
+                            Some(existing) if *existing != rule => bail!(
+                                "{} 中 [{}] 的 {} 与 {} 中的定义冲突",
+                                file,
+                                section,
+                                key,
+                                self.origins[&origin_key]
+                            ),
+                            Some(_) => {}
+                            None => {
+                                merged.insert(key, rule);
+                                self.origins.insert(origin_key, file.to_string());
+                            }
+                        }
+                    }
+                }
+                "delete" | "stub" | "encrypt" => {
+                    let paths = match value {
+                        Value::Array(arr) => arr,
+                        Value::Table(mut t) => match t.remove("paths") {
+                            Some(Value::Array(arr)) => arr,
+                            _ => unreachable!("结构校验已保证 {}.paths 是数组", section),
+                        },
+                        _ => unreachable!("结构校验已保证 {} 是数组或表", section),
+                    };
+                    let merged = self
+                        .merged
+                        .entry(section.clone())
+                        .or_insert_with(|| Value::Array(Vec::new()))
+                        .as_array_mut()
+                        .expect("合并结果中的数组");
+                    for path in paths {
+                        let origin_key = (
+                            section.clone(),
+                            path.as_str().unwrap_or_default().to_string(),
+                        );
+                        if let Entry::Vacant(slot) = self.origins.entry(origin_key) {
+                            slot.insert(file.to_string());
+                            merged.push(path);
+                        }
+                    }
+                }
+                // 未知字段保留第一次出现的值，由 lint 提示
+                _ => {
+                    self.merged.entry(section).or_insert(value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_start_matches("./").replace('\\', "/")
+}
+
+/// 32 位十六进制的 MD5
+pub fn is_md5_hex(text: &str) -> bool {
+    text.len() == 32 && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn example_for(section: &str) -> &'static str {
+    match section {
+        "version" => VERSION_EXAMPLE,
+        "version-hash" => VERSION_HASH_EXAMPLE,
+        "original-md5" => ORIGINAL_MD5_EXAMPLE,
+        "delete" => DELETE_EXAMPLE,
+        "stub" => STUB_EXAMPLE,
+        "encrypt" => ENCRYPT_EXAMPLE,
+        "require" => REQUIRE_EXAMPLE,
+        "include" => INCLUDE_EXAMPLE,
+        "project-settings" => PROJECT_SETTINGS_EXAMPLE,
+        "options" => OPTIONS_EXAMPLE,
+        _ => REPLACE_EXAMPLE,
+    }
+}
+
+/// 偏移所在的 `[section]`，位于第一个表头之前时为 None
+fn section_at(content: &str, offset: usize) -> Option<&str> {
+    content[..offset.min(content.len())]
+        .lines()
+        .rev()
+        .find_map(|line| {
+            let line = line.trim();
+            line.strip_prefix('[')?
+                .strip_suffix(']')
+                .map(|name| name.trim_matches(|c| c == '[' || c == ']' || c == ' '))
+        })
+}
+
+/// 将字节偏移换算为 1 起始的行列号
+pub fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(content.len());
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"
+[version]
+required-game-version = "1.0.10b"
+plugin-version = "0.6.2"
+
+[version-hash]
+"1.0.10b" = "597baead816b32429c2ea9ac5f340ae8"
+
+[replace]
+"res://Core/Game.gde" = "Core/Game.gde"
+"#;
+
+    #[test]
+    fn reports_location_key_and_example() {
+        assert_eq!(check_schema(VALID), Ok(()));
+
+        let wrong_type = VALID.replace("plugin-version = \"0.6.2\"", "plugin-version = 6");
+        let err = check_schema(&wrong_type).unwrap_err();
+        assert_eq!((err.line, err.column), (4, 18));
+        assert_eq!(err.key, "version.plugin-version");
+        assert_eq!(err.example, VERSION_EXAMPLE);
+
+        let bad_list = format!("{}\n[delete]\npaths = [\"res://a.tscn\", 3]\n", VALID);
+        let err = check_schema(&bad_list).unwrap_err();
+        assert_eq!(
+            (err.line, err.column, err.key.as_str()),
+            (13, 26, "delete[1]")
+        );
+
+        let syntax = VALID.replace("\"res://Core/Game.gde\" =", "\"res://Core/Game.gde\" ==");
+        let err = check_schema(&syntax).unwrap_err();
+        assert_eq!((err.line, err.key.as_str()), (10, "replace"));
+        assert!(err.to_string().starts_with("replace.toml:10:"));
+        assert!(err.to_string().contains("    [replace]"));
+    }
+
+    #[test]
+    fn composes_includes_with_provenance() {
+        let root = r#"
+include = ["common.toml", "./textures/manifest.toml"]
+
+[version]
+required-game-version = "1.0.10b"
+plugin-version = "0.6.2"
+
+[replace]
+"res://Core/Game.gde" = "Core/Game.gde"
+"#;
+        let files = HashMap::from([
+            (
+                "common.toml",
+                "[version-hash]\n\"1.0.10b\" = \"597baead816b32429c2ea9ac5f340ae8\"\n",
+            ),
+            (
+                "textures/manifest.toml",
+                "include = [\"common.toml\"]\ndelete = [\"res://UI/Unused.tscn\"]\n[replace]\n\"res://a.png\" = \"a.png\"\n",
+            ),
+            ("loop.toml", "include = [\"replace.toml\"]\n"),
+            (
+                "conflict.toml",
+                "[replace]\n\"res://Core/Game.gde\" = \"Other.gde\"\n",
+            ),
+        ]);
+        let load = |name: &str| {
+            files
+                .get(name)
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("missing {}", name))
+        };
+        let none = OptionSelection::default();
+
+        let composed = compose(root, &none, load).unwrap();
+        assert_eq!(check_schema(&composed.content), Ok(()));
+        assert_eq!(
+            composed.origin("replace", "res://a.png"),
+            "textures/manifest.toml"
+        );
+        assert_eq!(composed.origin("replace", "res://Core/Game.gde"), ROOT_FILE);
+        assert_eq!(composed.origin("version-hash", "1.0.10b"), "common.toml");
+        assert_eq!(
+            composed.origin("delete", "res://UI/Unused.tscn"),
+            "textures/manifest.toml"
+        );
+
+        let cycle = compose(&root.replace("common.toml\", ", "loop.toml\", "), &none, load);
+        let cycle = cycle.unwrap_err();
+        assert!(
+            cycle
+                .to_string()
+                .contains("replace.toml -> loop.toml -> replace.toml")
+        );
+
+        let conflict = compose(&root.replace("common.toml", "conflict.toml"), &none, load);
+        let conflict = conflict.unwrap_err();
+        assert!(conflict.to_string().contains("conflict.toml"));
+
+        let bad_setting = format!("{}\n[project-settings]\n\"a/b\" = [1]\n", VALID);
+        let err = check_schema(&bad_setting).unwrap_err();
+        assert_eq!(err.key, "project-settings.a/b");
+        assert_eq!(err.example, PROJECT_SETTINGS_EXAMPLE);
+
+        let bad_fragment = compose("include = [\"bad.toml\"]", &none, |_| {
+            Ok("delete = 3".to_string())
+        });
+        let err = bad_fragment.unwrap_err().downcast::<SchemaError>().unwrap();
+        assert_eq!((err.file.as_str(), err.line), ("bad.toml", 1));
+    }
+
+    #[test]
+    fn options_choose_which_files_are_merged() {
+        let root = format!(
+            "{}\n[options.items]\ntitle = \"物品\"\ninclude = [\"items.toml\"]\n\
+             [options.history]\ntitle = \"记录\"\ndefault = false\ninclude = [\"./history.toml\"]\n",
+            VALID
+        );
+        let load = |name: &str| match name {
+            "items.toml" => Ok("[replace]\n\"res://Items/Item.gde\" = \"Items/Item.gde\"".into()),
+            "history.toml" => Ok("delete = [\"res://UI/History.tscn\"]\n".into()),
+            _ => Err(anyhow::anyhow!("missing {}", name)),
+        };
+        let none = OptionSelection::default();
+
+        let options = parse_options(&root).unwrap();
+        let ids: Vec<(&str, bool)> = options
+            .iter()
+            .map(|option| (option.id.as_str(), option.default))
+            .collect();
+        assert_eq!(ids, [("history", false), ("items", true)]);
+
+        let composed = compose(&root, &none, load).unwrap();
+        assert_eq!(composed.enabled_options, ["items"]);
+        assert_eq!(composed.origin("replace", "res://Items/Item.gde"), "items.toml");
+        assert!(!composed.content.contains("History.tscn"));
+        assert!(!composed.content.contains("[options"));
+
+        let selection = OptionSelection(BTreeMap::from([
+            ("items".to_string(), false),
+            ("history".to_string(), true),
+        ]));
+        let composed = compose(&root, &selection, load).unwrap();
+        assert_eq!(composed.enabled_options, ["history"]);
+        assert!(!composed.content.contains("Item.gde\" = \"Items"));
+        assert_eq!(composed.origin("delete", "res://UI/History.tscn"), "history.toml");
+
+        let typo = OptionSelection(BTreeMap::from([("item".to_string(), false)]));
+        let err = compose(&root, &typo, load).unwrap_err();
+        assert!(err.to_string().contains("history, items"), "{}", err);
+
+        let missing_title = root.replace("title = \"物品\"\n", "");
+        assert_eq!(
+            check_schema(&missing_title).unwrap_err().key,
+            "options.items.title"
+        );
+        let nested = compose("include = [\"a.toml\"]", &none, |_| {
+            Ok("[options.x]\ntitle = \"x\"".into())
+        });
+        assert!(nested.unwrap_err().to_string().contains("只能写在"));
+    }
+}
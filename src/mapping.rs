@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+/// 判断文件名是否像混淆过的名字时，文件名（不含扩展名）的最短长度
+const MIN_OBFUSCATED_STEM: usize = 8;
+
+/// 混淆路径与可读路径的对照表，来自用户提供的 CSV（每行 `混淆路径,可读路径`）
+#[derive(Debug, Default, Clone)]
+pub struct PathMapping {
+    to_logical: HashMap<String, String>,
+    to_physical: HashMap<String, String>,
+}
+
+impl PathMapping {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("无法读取映射文件: {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("映射文件格式错误: {}", path.display()))
+    }
+
+    /// 读取可选的映射文件，未指定时返回空映射
+    pub fn load_optional(path: Option<&Path>) -> Result<Self> {
+        path.map_or_else(|| Ok(Self::default()), Self::load)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut mapping = Self::default();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((physical, logical)) = line.split_once(',') else {
+                bail!("第 {} 行缺少逗号: {}", number + 1, line);
+            };
+            let (physical, logical) = (physical.trim(), logical.trim());
+            if number == 0 && physical.eq_ignore_ascii_case("obfuscated") {
+                // 表头
+                continue;
+            }
+            if physical.is_empty() || logical.is_empty() {
+                bail!("第 {} 行的路径不能为空", number + 1);
+            }
+
+            let (physical, logical) = (normalize(physical), normalize(logical));
+            if mapping.to_logical.contains_key(&physical) {
+                bail!("第 {} 行重复映射了 {}", number + 1, physical);
+            }
+            if mapping.to_physical.contains_key(&logical) {
+                bail!(
+                    "第 {} 行的可读路径 {} 已被其他条目使用",
+                    number + 1,
+                    logical
+                );
+            }
+            mapping.to_logical.insert(physical.clone(), logical.clone());
+            mapping.to_physical.insert(logical, physical);
+        }
+
+        Ok(mapping)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_logical.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.to_logical.len()
+    }
+
+    /// PCK 中的实际路径 -> 用于展示的可读路径；没有映射时原样返回
+    pub fn logical<'a>(&'a self, physical: &'a str) -> &'a str {
+        self.to_logical
+            .get(physical)
+            .map_or(physical, String::as_str)
+    }
+
+    /// manifest 中的可读路径 -> PCK 中的实际路径；没有映射时原样返回
+    pub fn physical<'a>(&'a self, logical: &'a str) -> &'a str {
+        self.to_physical
+            .get(logical)
+            .map_or(logical, String::as_str)
+    }
+
+    /// 全部（实际路径, 可读路径）
+    pub fn pairs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.to_logical
+            .iter()
+            .map(|(physical, logical)| (physical.as_str(), logical.as_str()))
+    }
+}
+
+fn normalize(path: &str) -> String {
+    if path.starts_with("res://") {
+        path.to_string()
+    } else {
+        format!("res://{}", path.trim_start_matches('/'))
+    }
+}
+
+/// 粗略判断 PCK 的资源路径是否被混淆过：多数文件名是长串十六进制或没有元音的随机串
+pub fn looks_obfuscated<'a>(paths: impl IntoIterator<Item = &'a String>) -> bool {
+    let (mut total, mut suspicious) = (0usize, 0usize);
+    for path in paths {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let stem = name.split('.').next().unwrap_or(name);
+        if stem.is_empty() {
+            continue;
+        }
+        total += 1;
+
+        let hex = stem.chars().all(|c| c.is_ascii_hexdigit());
+        let no_vowels = !stem
+            .chars()
+            .any(|c| matches!(c.to_ascii_lowercase(), 'a' | 'e' | 'i' | 'o' | 'u'));
+        if stem.len() >= MIN_OBFUSCATED_STEM && (hex || no_vowels) {
+            suspicious += 1;
+        }
+    }
+    total > 0 && suspicious * 2 > total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mapping_and_detects_obfuscation() {
+        let mapping = PathMapping::parse(
+            "obfuscated,logical\n\
+             res://a/3f9c2b1d7e.gde, res://Core/Game.gde\n\
+             # 注释\n\
+             x/zq7w9k2vbn.tscn,UI/Menu.tscn\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            mapping.logical("res://a/3f9c2b1d7e.gde"),
+            "res://Core/Game.gde"
+        );
+        assert_eq!(
+            mapping.physical("res://UI/Menu.tscn"),
+            "res://x/zq7w9k2vbn.tscn"
+        );
+        assert_eq!(mapping.physical("res://other.png"), "res://other.png");
+        assert!(PathMapping::parse("a,b\nc,b\n").is_err());
+
+        let obfuscated: Vec<String> = ["res://a/3f9c2b1d7e.gde", "res://x/zq7w9k2vbn.tscn"]
+            .map(String::from)
+            .to_vec();
+        let readable: Vec<String> = ["res://Core/Game.gde", "res://UI/Menu.tscn"]
+            .map(String::from)
+            .to_vec();
+        assert!(looks_obfuscated(&obfuscated));
+        assert!(!looks_obfuscated(&readable));
+    }
+}
@@ -0,0 +1,130 @@
+//! 把 MOD 作者发布的小 PCK（overlay）合并进游戏 PCK：overlay 中的每个 entry 按路径替换或新增，
+//! 走与 apply 相同的替换/新增流程。与游戏中已有数据完全相同的 entry 跳过，不重复写入。
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::diff;
+use crate::pck::{IoOptions, PckArchive, TableLimits};
+
+/// 一次合并的结果，路径按字母排序
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    /// 数据与目标 PCK 中相同而跳过的 entry 数
+    pub unchanged: usize,
+}
+
+/// 把 `overlay` 中的全部 entry 写入 `target`；overlay 中加密的 entry 解密后以明文写入
+pub fn merge_pck(
+    target: &Path,
+    overlay: &Path,
+    io: &IoOptions,
+    limits: TableLimits,
+) -> Result<MergeSummary> {
+    if let (Ok(a), Ok(b)) = (target.canonicalize(), overlay.canonicalize())
+        && a == b
+    {
+        bail!("overlay 与目标是同一个 PCK: {}", target.display());
+    }
+
+    let existing = diff::read_digests(target, limits)?;
+    let incoming = diff::read_digests(overlay, limits)?;
+    if incoming.is_empty() {
+        bail!("overlay 中没有任何 entry: {}", overlay.display());
+    }
+
+    let mut summary = MergeSummary::default();
+    let mut paths: Vec<&String> = incoming.keys().collect();
+    paths.sort();
+    paths.retain(|path| match existing.get(*path) {
+        None => {
+            summary.added.push(path.to_string());
+            true
+        }
+        Some(digest) if *digest != incoming[*path] => {
+            summary.replaced.push(path.to_string());
+            true
+        }
+        Some(_) => {
+            summary.unchanged += 1;
+            false
+        }
+    });
+    if paths.is_empty() {
+        return Ok(summary);
+    }
+
+    let overlay = PckArchive::open_limited(overlay, limits)?;
+    let mut archive = PckArchive::edit_limited(target, limits)?;
+    for path in paths {
+        let data = overlay.read(path)?;
+        if archive.contains(path) {
+            archive.replace(path, data)?;
+        } else {
+            archive.add(path, data)?;
+        }
+    }
+    archive.commit_with(io)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pck;
+    use std::fs;
+
+    #[test]
+    fn overlay_entries_replace_and_add() {
+        let dir = std::env::temp_dir().join(format!("bpb_merge_{}", std::process::id()));
+        for (folder, files) in [
+            ("game", [("a.txt", "alpha"), ("b.txt", "bravo")]),
+            ("mod", [("a.txt", "alpha"), ("b.txt", "BRAVO!")]),
+        ] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+            for (name, data) in files {
+                fs::write(dir.join(folder).join(name), data).unwrap();
+            }
+        }
+        fs::write(dir.join("mod/c.txt"), "charlie").unwrap();
+        let options = pck::PackOptions::default();
+        pck::pack_directory(dir.join("game"), dir.join("game.pck"), &options).unwrap();
+        pck::pack_directory(dir.join("mod"), dir.join("mod.pck"), &options).unwrap();
+
+        let target = dir.join("game.pck");
+        let summary = merge_pck(
+            &target,
+            &dir.join("mod.pck"),
+            &IoOptions::auto(0),
+            TableLimits::DEFAULT,
+        )
+        .unwrap();
+        assert_eq!(summary.added, ["res://c.txt"]);
+        assert_eq!(summary.replaced, ["res://b.txt"]);
+        assert_eq!(summary.unchanged, 1);
+
+        let merged = PckArchive::open(&target).unwrap();
+        for (path, data) in [
+            ("res://a.txt", "alpha"),
+            ("res://b.txt", "BRAVO!"),
+            ("res://c.txt", "charlie"),
+        ] {
+            assert_eq!(merged.read(path).unwrap(), data.as_bytes());
+        }
+
+        // 再次合并时全部相同，不写入
+        let again = merge_pck(
+            &target,
+            &dir.join("mod.pck"),
+            &IoOptions::auto(0),
+            TableLimits::DEFAULT,
+        )
+        .unwrap();
+        assert_eq!(again.unchanged, 3);
+        assert!(merge_pck(&target, &target, &IoOptions::auto(0), TableLimits::DEFAULT).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
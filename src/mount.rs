@@ -0,0 +1,1065 @@
+//! 把 PCK 挂载为文件系统：res:// 下的目录结构原样呈现为目录，可以在文件管理器中浏览、
+//! 用平常的工具直接打开资源，不必先 extract。默认只读，任何写入都返回 EROFS；
+//! 指定捕获目录时，写入的内容不改动 PCK，而是连同 replace.toml 记录到捕获目录，
+//! 之后像普通 MOD 一样 apply。
+//!
+//! 只支持 Linux：直接通过 /dev/fuse 与内核的 FUSE 协议通信，不依赖 libfuse。
+//! 以 root 运行时直接 mount(2)，否则借助 fusermount3（fuse3 软件包）完成挂载。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{diff, scaffold};
+
+/// 根目录的 inode
+const ROOT: u64 = 1;
+
+#[derive(Debug)]
+enum Node {
+    Dir {
+        parent: u64,
+        /// 根目录为 `res://`，其余不带结尾的 `/`
+        res_path: String,
+        children: BTreeMap<String, u64>,
+    },
+    File {
+        res_path: String,
+        size: u64,
+    },
+}
+
+/// 挂载呈现的目录树；inode 为节点在 `nodes` 中的下标加 1
+#[derive(Debug)]
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    /// 由（res 路径, 大小）建立目录树，返回目录树与无法呈现而跳过的路径：
+    /// 含空段、`.` 或 `..` 的路径，以及与已有目录或文件重名的路径
+    fn build(entries: impl IntoIterator<Item = (String, u64)>) -> (Self, Vec<String>) {
+        let mut tree = Tree {
+            nodes: vec![Node::Dir {
+                parent: ROOT,
+                res_path: "res://".to_string(),
+                children: BTreeMap::new(),
+            }],
+        };
+        let mut entries: Vec<(String, u64)> = entries.into_iter().collect();
+        entries.sort();
+
+        let mut skipped = Vec::new();
+        for (res_path, size) in entries {
+            if tree.insert_file(&res_path, size).is_none() {
+                skipped.push(res_path);
+            }
+        }
+        (tree, skipped)
+    }
+
+    fn insert_file(&mut self, res_path: &str, size: u64) -> Option<u64> {
+        let parts: Vec<&str> = res_path.strip_prefix("res://")?.split('/').collect();
+        let (name, dirs) = parts.split_last()?;
+        let mut parent = ROOT;
+        for dir in dirs {
+            parent = match self.lookup(parent, dir) {
+                Some(ino) => matches!(self.get(ino)?, Node::Dir { .. }).then_some(ino)?,
+                None => self.create(parent, dir, None)?,
+            };
+        }
+        self.create(parent, name, Some(size))
+    }
+
+    fn get(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(usize::try_from(ino.checked_sub(1)?).ok()?)
+    }
+
+    fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
+        match self.get(parent)? {
+            Node::Dir { children, .. } => children.get(name).copied(),
+            Node::File { .. } => None,
+        }
+    }
+
+    /// 在 `parent` 下新建文件（`size` 为 Some）或目录；名称无效或已存在时返回 None
+    fn create(&mut self, parent: u64, name: &str, size: Option<u64>) -> Option<u64> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return None;
+        }
+        if self.lookup(parent, name).is_some() {
+            return None;
+        }
+        let res_path = match self.get(parent)? {
+            Node::Dir { res_path, .. } if parent == ROOT => format!("{}{}", res_path, name),
+            Node::Dir { res_path, .. } => format!("{}/{}", res_path, name),
+            Node::File { .. } => return None,
+        };
+
+        let ino = self.nodes.len() as u64 + 1;
+        self.nodes.push(match size {
+            Some(size) => Node::File { res_path, size },
+            None => Node::Dir {
+                parent,
+                res_path,
+                children: BTreeMap::new(),
+            },
+        });
+        if let Some(Node::Dir { children, .. }) = self.nodes.get_mut(parent as usize - 1) {
+            children.insert(name.to_string(), ino);
+        }
+        Some(ino)
+    }
+
+    fn set_size(&mut self, ino: u64, new_size: u64) {
+        if let Some(Node::File { size, .. }) = self.nodes.get_mut(ino as usize - 1) {
+            *size = new_size;
+        }
+    }
+
+    fn file_count(&self) -> u64 {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node, Node::File { .. }))
+            .count() as u64
+    }
+}
+
+/// 挂载期间捕获的写入：资源文件按 res:// 布局写在捕获目录中，每次写入后重写 replace.toml
+#[derive(Debug)]
+pub struct Capture {
+    dir: PathBuf,
+    source: PathBuf,
+    game_version: String,
+    game_gde_hash: Option<String>,
+    /// 已捕获的 res 路径
+    files: BTreeSet<String>,
+}
+
+impl Capture {
+    /// 捕获目录须不存在或为空；未指定游戏版本时从 PCK 中检测
+    pub fn new(dir: &Path, pck_path: &Path, game_version: Option<&str>) -> Result<Self> {
+        if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+            bail!("捕获目录已存在且不为空: {}", dir.display());
+        }
+        let probe = scaffold::probe_pck(pck_path)?;
+        let game_version = game_version
+            .map(str::to_string)
+            .or(probe.game_version)
+            .context("无法从 PCK 中确定游戏版本，请通过 --game-version 指定")?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            source: pck_path.to_path_buf(),
+            game_version,
+            game_gde_hash: probe.game_gde_hash,
+            files: BTreeSet::new(),
+        })
+    }
+
+    /// 已捕获的文件返回捕获的内容，未捕获的返回 None
+    fn read(&self, res_path: &str) -> Option<Result<Vec<u8>>> {
+        if !self.files.contains(res_path) {
+            return None;
+        }
+        Some(diff::asset_path_for(res_path).and_then(|asset| {
+            let path = self.dir.join(asset);
+            fs::read(&path).with_context(|| format!("无法读取: {}", path.display()))
+        }))
+    }
+
+    fn write(&mut self, res_path: &str, data: &[u8]) -> Result<()> {
+        let target = self.dir.join(diff::asset_path_for(res_path)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("无法创建目录: {}", parent.display()))?;
+        }
+        fs::write(&target, data).with_context(|| format!("无法写入: {}", target.display()))?;
+        self.files.insert(res_path.to_string());
+
+        let replace = self
+            .files
+            .iter()
+            .map(|path| Ok((path.clone(), diff::asset_path_for(path)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let manifest = diff::render_manifest(
+            &format!(
+                "由 bpb_enhance mount --capture 生成，内容为挂载 {} 期间写入的文件",
+                self.source.display()
+            ),
+            &self.game_version,
+            self.game_gde_hash.as_deref(),
+            &replace,
+            &[],
+        );
+        fs::write(self.dir.join("replace.toml"), manifest).context("无法写入 replace.toml")
+    }
+}
+
+/// 挂载期间的事件，供调用方输出
+#[derive(Debug)]
+pub enum MountEvent<'a> {
+    /// 路径无法呈现为文件，未出现在挂载中
+    Skipped(&'a str),
+    /// 已挂载，开始处理请求
+    Ready,
+    Captured(&'a str),
+    CaptureFailed(&'a str, &'a anyhow::Error),
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::mount;
+
+/// 挂载并处理请求，直到被卸载（Ctrl+C 或 `fusermount3 -u`）后返回
+#[cfg(not(target_os = "linux"))]
+pub fn mount(
+    _pck_path: &Path,
+    _mountpoint: &Path,
+    _capture: Option<Capture>,
+    _on_event: impl FnMut(MountEvent),
+) -> Result<()> {
+    bail!("当前平台不支持挂载 PCK（仅支持 Linux 的 FUSE）")
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::HashMap;
+    use std::ffi::CString;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::pck::PckArchive;
+
+    // FUSE 请求的操作码（include/uapi/linux/fuse.h）
+    const LOOKUP: u32 = 1;
+    const FORGET: u32 = 2;
+    const GETATTR: u32 = 3;
+    const SETATTR: u32 = 4;
+    const SYMLINK: u32 = 6;
+    const MKNOD: u32 = 8;
+    const MKDIR: u32 = 9;
+    const UNLINK: u32 = 10;
+    const RMDIR: u32 = 11;
+    const RENAME: u32 = 12;
+    const LINK: u32 = 13;
+    const OPEN: u32 = 14;
+    const READ: u32 = 15;
+    const WRITE: u32 = 16;
+    const STATFS: u32 = 17;
+    const RELEASE: u32 = 18;
+    const FSYNC: u32 = 20;
+    const SETXATTR: u32 = 21;
+    const REMOVEXATTR: u32 = 24;
+    const FLUSH: u32 = 25;
+    const INIT: u32 = 26;
+    const OPENDIR: u32 = 27;
+    const READDIR: u32 = 28;
+    const RELEASEDIR: u32 = 29;
+    const FSYNCDIR: u32 = 30;
+    const ACCESS: u32 = 34;
+    const CREATE: u32 = 35;
+    const INTERRUPT: u32 = 36;
+    const DESTROY: u32 = 38;
+    const BATCH_FORGET: u32 = 42;
+    const RENAME2: u32 = 45;
+
+    /// 实现的协议版本 7.31，内核取双方中较低的版本
+    const KERNEL_VERSION: u32 = 7;
+    const KERNEL_MINOR_VERSION: u32 = 31;
+    const BIG_WRITES: u32 = 1 << 5;
+    const FATTR_SIZE: u32 = 1 << 3;
+    const FATTR_FH: u32 = 1 << 6;
+    const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+    /// 单次写入请求的最大数据量；读取请求的缓冲区须能容纳它加上请求头
+    const MAX_WRITE: usize = 128 * 1024;
+    const IN_HEADER_LEN: usize = 40;
+    const OUT_HEADER_LEN: usize = 16;
+    /// 属性与目录项的缓存时间（秒）
+    const TTL: u64 = 1;
+    const BLOCK_SIZE: u32 = 4096;
+
+    /// 收到 SIGINT/SIGTERM/SIGHUP 后置位，请求循环随即卸载并返回
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_signal(_: libc::c_int) {
+        STOP.store(true, Ordering::SeqCst);
+    }
+
+    /// 挂载并处理请求，直到被卸载（Ctrl+C 或 `fusermount3 -u`）后返回
+    pub fn mount(
+        pck_path: &Path,
+        mountpoint: &Path,
+        capture: Option<Capture>,
+        mut on_event: impl FnMut(MountEvent),
+    ) -> Result<()> {
+        if !mountpoint.is_dir() {
+            bail!("挂载点不是已存在的目录: {}", mountpoint.display());
+        }
+        let archive = PckArchive::open(pck_path)?;
+        let digests = diff::read_digests(pck_path, crate::pck::TableLimits::DEFAULT)?;
+        let (tree, skipped) = Tree::build(
+            digests
+                .into_iter()
+                .map(|(path, digest)| (path, digest.size)),
+        );
+        for path in &skipped {
+            on_event(MountEvent::Skipped(path));
+        }
+        let metadata = fs::metadata(pck_path)?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+
+        let connection = Connection::open(mountpoint, capture.is_none())?;
+        stop_on_signals();
+        let mut session = Session {
+            tree,
+            archive,
+            capture,
+            handles: HashMap::new(),
+            next_fh: 1,
+            // SAFETY: 无参数、总是成功的系统调用
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            mtime,
+            pck_size: metadata.len(),
+        };
+        on_event(MountEvent::Ready);
+        session.run(&connection.device, &mut on_event)
+    }
+
+    /// 已挂载的 FUSE 连接，drop 时卸载
+    struct Connection {
+        device: File,
+        mountpoint: PathBuf,
+        /// None 表示以 root 直接挂载，否则为挂载时使用的 fusermount 程序
+        fusermount: Option<&'static str>,
+    }
+
+    impl Connection {
+        fn open(mountpoint: &Path, read_only: bool) -> Result<Self> {
+            // SAFETY: 无参数、总是成功的系统调用
+            let (device, fusermount) = if unsafe { libc::geteuid() } == 0 {
+                (mount_directly(mountpoint, read_only)?, None)
+            } else {
+                let (device, program) = mount_with_fusermount(mountpoint, read_only)?;
+                (device, Some(program))
+            };
+            Ok(Self {
+                device,
+                mountpoint: mountpoint.to_path_buf(),
+                fusermount,
+            })
+        }
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            // 已在外部卸载时这里会失败，忽略即可
+            match self.fusermount {
+                Some(program) => {
+                    let _ = Command::new(program)
+                        .args(["-u", "-z", "--"])
+                        .arg(&self.mountpoint)
+                        .status();
+                }
+                None => {
+                    if let Ok(target) = CString::new(self.mountpoint.as_os_str().as_bytes()) {
+                        // SAFETY: target 为合法的 C 字符串
+                        unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) };
+                    }
+                }
+            }
+        }
+    }
+
+    fn mount_directly(mountpoint: &Path, read_only: bool) -> Result<File> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/fuse")
+            .context("无法打开 /dev/fuse，内核可能未启用 FUSE")?;
+        // SAFETY: 无参数、总是成功的系统调用
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        // 以 root 挂载时允许其他用户访问，权限由内核按文件模式检查
+        let options = CString::new(format!(
+            "fd={},rootmode=40000,user_id={},group_id={},allow_other,default_permissions",
+            device.as_raw_fd(),
+            uid,
+            gid
+        ))?;
+        let target = CString::new(mountpoint.as_os_str().as_bytes())?;
+        let mut flags = libc::MS_NOSUID | libc::MS_NODEV;
+        if read_only {
+            flags |= libc::MS_RDONLY;
+        }
+        // SAFETY: 各参数均为合法的 C 字符串，options 在调用期间有效
+        let result = unsafe {
+            libc::mount(
+                c"bpb_enhance".as_ptr(),
+                target.as_ptr(),
+                c"fuse.bpb_enhance".as_ptr(),
+                flags,
+                options.as_ptr().cast(),
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("无法挂载到 {}", mountpoint.display()));
+        }
+        Ok(device)
+    }
+
+    /// 非 root 用户通过 fusermount 挂载：它在挂载后经 `_FUSE_COMMFD` 指定的套接字传回 /dev/fuse
+    fn mount_with_fusermount(mountpoint: &Path, read_only: bool) -> Result<(File, &'static str)> {
+        let mut fds = [0; 2];
+        // SAFETY: fds 可容纳两个描述符；不设 CLOEXEC，fusermount 需要继承其中一端
+        if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error()).context("无法创建与 fusermount 通信的套接字");
+        }
+        // SAFETY: socketpair 成功后两个描述符归这里所有
+        let (ours, theirs) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        let options = format!(
+            "{}nosuid,nodev,fsname=bpb_enhance,subtype=pck",
+            if read_only { "ro," } else { "" }
+        );
+        for program in ["fusermount3", "fusermount"] {
+            let status = Command::new(program)
+                .arg("-o")
+                .arg(&options)
+                .arg("--")
+                .arg(mountpoint)
+                .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+                .status();
+            match status {
+                Ok(status) if status.success() => {
+                    drop(theirs);
+                    return Ok((File::from(receive_fd(&ours)?), program));
+                }
+                Ok(status) => bail!(
+                    "{} 无法挂载到 {}（{}）",
+                    program,
+                    mountpoint.display(),
+                    status
+                ),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err).with_context(|| format!("无法运行 {}", program)),
+            }
+        }
+        bail!("找不到 fusermount3，请安装 fuse3 软件包或以 root 运行")
+    }
+
+    fn receive_fd(socket: &OwnedFd) -> Result<OwnedFd> {
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr().cast(),
+            iov_len: byte.len(),
+        };
+        // 用 u64 数组保证控制信息的对齐
+        let mut control = [0u64; 8];
+        // SAFETY: 全零的 msghdr 是合法的初始值
+        let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        // SAFETY: message 引用的缓冲区在调用期间有效
+        if unsafe { libc::recvmsg(socket.as_raw_fd(), &mut message, 0) } < 0 {
+            return Err(io::Error::last_os_error()).context("未收到 fusermount 传回的 FUSE 设备");
+        }
+        // SAFETY: recvmsg 已填好 message；只在确认是 SCM_RIGHTS 后读取其中的描述符
+        unsafe {
+            let header = libc::CMSG_FIRSTHDR(&message);
+            if header.is_null()
+                || (*header).cmsg_level != libc::SOL_SOCKET
+                || (*header).cmsg_type != libc::SCM_RIGHTS
+            {
+                bail!("fusermount 没有传回 FUSE 设备");
+            }
+            let fd = std::ptr::read_unaligned(libc::CMSG_DATA(header).cast::<libc::c_int>());
+            Ok(OwnedFd::from_raw_fd(fd))
+        }
+    }
+
+    fn stop_on_signals() {
+        // SAFETY: 处理函数只写一个原子变量；不设 SA_RESTART，阻塞中的 read 因此以 EINTR 返回
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+    }
+
+    /// 请求体中的定长字段；请求体由内核构造，长度不足时按 0 处理
+    struct Fields<'a>(&'a [u8]);
+
+    impl Fields<'_> {
+        fn u32(&mut self) -> u32 {
+            let value = self
+                .0
+                .get(..4)
+                .map_or(0, |b| u32::from_ne_bytes(b.try_into().unwrap()));
+            self.skip(4);
+            value
+        }
+
+        fn u64(&mut self) -> u64 {
+            let value = self
+                .0
+                .get(..8)
+                .map_or(0, |b| u64::from_ne_bytes(b.try_into().unwrap()));
+            self.skip(8);
+            value
+        }
+
+        fn skip(&mut self, len: usize) {
+            self.0 = self.0.get(len..).unwrap_or_default();
+        }
+
+        /// 剩余部分中以 NUL 结尾的文件名
+        fn name(&self) -> Option<&str> {
+            let end = self.0.iter().position(|&b| b == 0)?;
+            std::str::from_utf8(&self.0[..end]).ok()
+        }
+    }
+
+    /// 按原生字节序拼装回复
+    #[derive(Default)]
+    struct Out(Vec<u8>);
+
+    impl Out {
+        fn u16(&mut self, value: u16) -> &mut Self {
+            self.0.extend_from_slice(&value.to_ne_bytes());
+            self
+        }
+
+        fn u32(&mut self, value: u32) -> &mut Self {
+            self.0.extend_from_slice(&value.to_ne_bytes());
+            self
+        }
+
+        fn u64(&mut self, value: u64) -> &mut Self {
+            self.0.extend_from_slice(&value.to_ne_bytes());
+            self
+        }
+    }
+
+    /// None 表示该请求不需要回复；Err 为 errno
+    type Reply = Option<std::result::Result<Vec<u8>, i32>>;
+
+    /// 打开的文件：整个 entry 读入内存，写入先改这里，flush/release 时捕获
+    struct Handle {
+        ino: u64,
+        data: Vec<u8>,
+        dirty: bool,
+    }
+
+    struct Session {
+        tree: Tree,
+        archive: PckArchive,
+        capture: Option<Capture>,
+        handles: HashMap<u64, Handle>,
+        next_fh: u64,
+        uid: u32,
+        gid: u32,
+        mtime: u64,
+        pck_size: u64,
+    }
+
+    impl Session {
+        fn run(&mut self, device: &File, on_event: &mut impl FnMut(MountEvent)) -> Result<()> {
+            let mut buffer = vec![0u8; MAX_WRITE + 4096];
+            while !STOP.load(Ordering::SeqCst) {
+                let len = match (&*device).read(&mut buffer) {
+                    Ok(len) => len,
+                    Err(err) => match err.raw_os_error() {
+                        // 被信号打断，或请求在读取前已被中断
+                        Some(libc::EINTR | libc::ENOENT | libc::EAGAIN) => continue,
+                        // 已在外部卸载
+                        Some(libc::ENODEV) => return Ok(()),
+                        _ => return Err(err).context("读取 FUSE 请求失败"),
+                    },
+                };
+                if len < IN_HEADER_LEN {
+                    bail!("FUSE 请求不完整（{} 字节）", len);
+                }
+                let mut header = Fields(&buffer[4..IN_HEADER_LEN]);
+                let opcode = header.u32();
+                let unique = header.u64();
+                let nodeid = header.u64();
+                let body = Fields(&buffer[IN_HEADER_LEN..len]);
+
+                if let Some(result) = self.handle(opcode, nodeid, body, on_event) {
+                    let (error, data) = match result {
+                        Ok(data) => (0, data),
+                        Err(errno) => (-errno, Vec::new()),
+                    };
+                    let mut reply = Out::default();
+                    reply
+                        .u32((OUT_HEADER_LEN + data.len()) as u32)
+                        .u32(error as u32)
+                        .u64(unique);
+                    reply.0.extend_from_slice(&data);
+                    // ENOENT 表示请求已被中断，不再需要回复
+                    if let Err(err) = (&*device).write(&reply.0)
+                        && err.raw_os_error() != Some(libc::ENOENT)
+                    {
+                        return Err(err).context("回复 FUSE 请求失败");
+                    }
+                }
+                if opcode == DESTROY {
+                    break;
+                }
+            }
+            Ok(())
+        }
+
+        fn handle(
+            &mut self,
+            opcode: u32,
+            nodeid: u64,
+            mut body: Fields,
+            on_event: &mut impl FnMut(MountEvent),
+        ) -> Reply {
+            let reply = match opcode {
+                INIT => {
+                    let major = body.u32();
+                    let _minor = body.u32();
+                    let max_readahead = body.u32();
+                    let flags = body.u32();
+                    if major != KERNEL_VERSION {
+                        return Some(Err(libc::EPROTO));
+                    }
+                    let mut out = Out::default();
+                    out.u32(KERNEL_VERSION)
+                        .u32(KERNEL_MINOR_VERSION)
+                        .u32(max_readahead)
+                        .u32(flags & BIG_WRITES)
+                        .u16(16)
+                        .u16(12)
+                        .u32(MAX_WRITE as u32)
+                        .u32(1)
+                        .u16(0)
+                        .u16(0)
+                        .u32(0);
+                    out.0.resize(64, 0);
+                    Ok(out.0)
+                }
+                DESTROY | RELEASEDIR | FSYNCDIR | ACCESS => Ok(Vec::new()),
+                FORGET | BATCH_FORGET | INTERRUPT => return None,
+                LOOKUP => match body.name().and_then(|name| self.tree.lookup(nodeid, name)) {
+                    Some(ino) => Ok(self.entry(ino)),
+                    None => Err(libc::ENOENT),
+                },
+                GETATTR => self.attr_out(nodeid),
+                SETATTR => self.setattr(nodeid, body, on_event),
+                OPENDIR => match self.tree.get(nodeid) {
+                    Some(Node::Dir { .. }) => Ok(open_out(0, 0)),
+                    Some(Node::File { .. }) => Err(libc::ENOTDIR),
+                    None => Err(libc::ENOENT),
+                },
+                READDIR => {
+                    let _fh = body.u64();
+                    let offset = body.u64();
+                    let size = body.u32() as usize;
+                    self.readdir(nodeid, offset, size)
+                }
+                OPEN => self.open(nodeid, body.u32()),
+                READ => {
+                    let fh = body.u64();
+                    let offset = body.u64();
+                    let size = body.u32() as usize;
+                    match self.handles.get(&fh) {
+                        Some(handle) => {
+                            let start = (offset as usize).min(handle.data.len());
+                            let end = start.saturating_add(size).min(handle.data.len());
+                            Ok(handle.data[start..end].to_vec())
+                        }
+                        None => Err(libc::EBADF),
+                    }
+                }
+                WRITE => {
+                    let fh = body.u64();
+                    let offset = body.u64() as usize;
+                    let size = body.u32() as usize;
+                    body.skip(20);
+                    let data = body.0.get(..size).unwrap_or(body.0);
+                    self.write(fh, offset, data)
+                }
+                FLUSH | FSYNC => self.save(body.u64(), on_event).map(|()| Vec::new()),
+                RELEASE => {
+                    let fh = body.u64();
+                    let saved = self.save(fh, on_event);
+                    self.handles.remove(&fh);
+                    saved.map(|()| Vec::new())
+                }
+                CREATE => {
+                    let _flags = body.u32();
+                    let _mode = body.u32();
+                    body.skip(8);
+                    self.create(nodeid, body.name(), true, on_event)
+                }
+                MKDIR => {
+                    body.skip(8);
+                    self.create(nodeid, body.name(), false, on_event)
+                }
+                STATFS => {
+                    let blocks = self.pck_size.div_ceil(BLOCK_SIZE as u64);
+                    let mut out = Out::default();
+                    out.u64(blocks)
+                        .u64(0)
+                        .u64(0)
+                        .u64(self.tree.file_count())
+                        .u64(0)
+                        .u32(BLOCK_SIZE)
+                        .u32(255)
+                        .u32(BLOCK_SIZE);
+                    out.0.resize(80, 0);
+                    Ok(out.0)
+                }
+                // 捕获只记录文件内容的改动，删除、改名与链接一律拒绝
+                SYMLINK | MKNOD | UNLINK | RMDIR | RENAME | RENAME2 | LINK | SETXATTR
+                | REMOVEXATTR => Err(libc::EROFS),
+                _ => Err(libc::ENOSYS),
+            };
+            Some(reply)
+        }
+
+        fn attr(&self, ino: u64, out: &mut Out) {
+            let writable = if self.capture.is_some() { 0o200 } else { 0 };
+            let (size, mode, nlink) = match self.tree.get(ino) {
+                Some(Node::File { size, .. }) => (*size, libc::S_IFREG | 0o444 | writable, 1),
+                _ => (0, libc::S_IFDIR | 0o555 | writable, 2),
+            };
+            out.u64(ino)
+                .u64(size)
+                .u64(size.div_ceil(512))
+                .u64(self.mtime)
+                .u64(self.mtime)
+                .u64(self.mtime)
+                .u32(0)
+                .u32(0)
+                .u32(0)
+                .u32(mode)
+                .u32(nlink)
+                .u32(self.uid)
+                .u32(self.gid)
+                .u32(0)
+                .u32(BLOCK_SIZE)
+                .u32(0);
+        }
+
+        fn entry(&self, ino: u64) -> Vec<u8> {
+            let mut out = Out::default();
+            out.u64(ino).u64(0).u64(TTL).u64(TTL).u32(0).u32(0);
+            self.attr(ino, &mut out);
+            out.0
+        }
+
+        fn attr_out(&self, ino: u64) -> std::result::Result<Vec<u8>, i32> {
+            self.tree.get(ino).ok_or(libc::ENOENT)?;
+            let mut out = Out::default();
+            out.u64(TTL).u32(0).u32(0);
+            self.attr(ino, &mut out);
+            Ok(out.0)
+        }
+
+        fn readdir(&self, ino: u64, offset: u64, size: usize) -> std::result::Result<Vec<u8>, i32> {
+            let Some(Node::Dir {
+                parent, children, ..
+            }) = self.tree.get(ino)
+            else {
+                return Err(libc::ENOTDIR);
+            };
+            let entries = [(".", ino), ("..", *parent)]
+                .into_iter()
+                .chain(children.iter().map(|(name, ino)| (name.as_str(), *ino)));
+
+            let mut out = Out::default();
+            for (index, (name, child)) in entries.enumerate().skip(offset as usize) {
+                let kind = match self.tree.get(child) {
+                    Some(Node::File { .. }) => libc::DT_REG,
+                    _ => libc::DT_DIR,
+                };
+                let len = (24 + name.len()).next_multiple_of(8);
+                if out.0.len() + len > size {
+                    break;
+                }
+                out.u64(child)
+                    .u64(index as u64 + 1)
+                    .u32(name.len() as u32)
+                    .u32(kind as u32);
+                out.0.extend_from_slice(name.as_bytes());
+                out.0.resize(out.0.len().next_multiple_of(8), 0);
+            }
+            Ok(out.0)
+        }
+
+        /// 读取文件的当前内容：已捕获的取捕获的版本，否则取 PCK 中的数据
+        fn load(&self, res_path: &str) -> Result<Vec<u8>> {
+            match self
+                .capture
+                .as_ref()
+                .and_then(|capture| capture.read(res_path))
+            {
+                Some(data) => data,
+                None => self.archive.read(res_path),
+            }
+        }
+
+        fn open(&mut self, ino: u64, flags: u32) -> std::result::Result<Vec<u8>, i32> {
+            let writing = flags as i32 & libc::O_ACCMODE != libc::O_RDONLY;
+            if writing && self.capture.is_none() {
+                return Err(libc::EROFS);
+            }
+            let res_path = match self.tree.get(ino) {
+                Some(Node::File { res_path, .. }) => res_path,
+                Some(Node::Dir { .. }) => return Err(libc::EISDIR),
+                None => return Err(libc::ENOENT),
+            };
+            let data = self.load(res_path).map_err(|_| libc::EIO)?;
+
+            let fh = self.next_fh;
+            self.next_fh += 1;
+            self.handles.insert(
+                fh,
+                Handle {
+                    ino,
+                    data,
+                    dirty: false,
+                },
+            );
+            // 只读挂载时内容不会变化，允许内核保留页缓存
+            let open_flags = if self.capture.is_none() {
+                FOPEN_KEEP_CACHE
+            } else {
+                0
+            };
+            Ok(open_out(fh, open_flags))
+        }
+
+        fn write(
+            &mut self,
+            fh: u64,
+            offset: usize,
+            data: &[u8],
+        ) -> std::result::Result<Vec<u8>, i32> {
+            let handle = self.handles.get_mut(&fh).ok_or(libc::EBADF)?;
+            let end = offset + data.len();
+            if handle.data.len() < end {
+                handle.data.resize(end, 0);
+            }
+            handle.data[offset..end].copy_from_slice(data);
+            handle.dirty = true;
+            self.tree.set_size(handle.ino, handle.data.len() as u64);
+
+            let mut out = Out::default();
+            out.u32(data.len() as u32).u32(0);
+            Ok(out.0)
+        }
+
+        /// 只处理改变大小（截断）；权限与时间不保存，按成功返回当前属性
+        fn setattr(
+            &mut self,
+            ino: u64,
+            mut body: Fields,
+            on_event: &mut impl FnMut(MountEvent),
+        ) -> std::result::Result<Vec<u8>, i32> {
+            let valid = body.u32();
+            body.skip(4);
+            let fh = body.u64();
+            let size = body.u64() as usize;
+            if valid & FATTR_SIZE != 0 {
+                if self.capture.is_none() {
+                    return Err(libc::EROFS);
+                }
+                let Some(Node::File { res_path, .. }) = self.tree.get(ino) else {
+                    return Err(libc::EISDIR);
+                };
+                match self.handles.get_mut(&fh).filter(|_| valid & FATTR_FH != 0) {
+                    Some(handle) => {
+                        handle.data.resize(size, 0);
+                        handle.dirty = true;
+                    }
+                    None => {
+                        let res_path = res_path.clone();
+                        let mut data = self.load(&res_path).map_err(|_| libc::EIO)?;
+                        data.resize(size, 0);
+                        self.capture_file(&res_path, &data, on_event)?;
+                    }
+                }
+                self.tree.set_size(ino, size as u64);
+            }
+            self.attr_out(ino)
+        }
+
+        fn create(
+            &mut self,
+            parent: u64,
+            name: Option<&str>,
+            file: bool,
+            on_event: &mut impl FnMut(MountEvent),
+        ) -> std::result::Result<Vec<u8>, i32> {
+            if self.capture.is_none() {
+                return Err(libc::EROFS);
+            }
+            let name = name.ok_or(libc::EINVAL)?;
+            if self.tree.lookup(parent, name).is_some() {
+                return Err(libc::EEXIST);
+            }
+            let ino = self
+                .tree
+                .create(parent, name, file.then_some(0))
+                .ok_or(libc::EINVAL)?;
+            let mut reply = self.entry(ino);
+            if !file {
+                return Ok(reply);
+            }
+
+            // 新文件立即捕获，之后的写入在 flush/release 时覆盖
+            if let Some(Node::File { res_path, .. }) = self.tree.get(ino) {
+                self.capture_file(&res_path.clone(), &[], on_event)?;
+            }
+            let fh = self.next_fh;
+            self.next_fh += 1;
+            self.handles.insert(
+                fh,
+                Handle {
+                    ino,
+                    data: Vec::new(),
+                    dirty: false,
+                },
+            );
+            reply.extend_from_slice(&open_out(fh, 0));
+            Ok(reply)
+        }
+
+        fn save(
+            &mut self,
+            fh: u64,
+            on_event: &mut impl FnMut(MountEvent),
+        ) -> std::result::Result<(), i32> {
+            let handle = self.handles.get(&fh).ok_or(libc::EBADF)?;
+            if !handle.dirty {
+                return Ok(());
+            }
+            let Some(Node::File { res_path, .. }) = self.tree.get(handle.ino) else {
+                return Err(libc::EBADF);
+            };
+            let (res_path, data) = (res_path.clone(), handle.data.clone());
+            self.capture_file(&res_path, &data, on_event)?;
+            if let Some(handle) = self.handles.get_mut(&fh) {
+                handle.dirty = false;
+            }
+            Ok(())
+        }
+
+        fn capture_file(
+            &mut self,
+            res_path: &str,
+            data: &[u8],
+            on_event: &mut impl FnMut(MountEvent),
+        ) -> std::result::Result<(), i32> {
+            let capture = self.capture.as_mut().ok_or(libc::EROFS)?;
+            match capture.write(res_path, data) {
+                Ok(()) => {
+                    on_event(MountEvent::Captured(res_path));
+                    Ok(())
+                }
+                Err(err) => {
+                    on_event(MountEvent::CaptureFailed(res_path, &err));
+                    Err(libc::EIO)
+                }
+            }
+        }
+    }
+
+    fn open_out(fh: u64, open_flags: u32) -> Vec<u8> {
+        let mut out = Out::default();
+        out.u64(fh).u32(open_flags).u32(0);
+        out.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_directories_from_res_paths() {
+        let (mut tree, skipped) = Tree::build([
+            ("res://Core/Game.gde".to_string(), 10),
+            ("res://Core/Data/items.json".to_string(), 20),
+            ("res://icon.png".to_string(), 30),
+            ("res://icon.png/x".to_string(), 1),
+            ("res://bad//path".to_string(), 1),
+        ]);
+        assert_eq!(skipped, ["res://bad//path", "res://icon.png/x"]);
+        assert_eq!(tree.file_count(), 3);
+
+        let core = tree.lookup(ROOT, "Core").unwrap();
+        let data = tree.lookup(core, "Data").unwrap();
+        let items = tree.lookup(data, "items.json").unwrap();
+        assert!(matches!(
+            tree.get(items),
+            Some(Node::File { res_path, size: 20 }) if res_path == "res://Core/Data/items.json"
+        ));
+        assert!(matches!(tree.get(data), Some(Node::Dir { parent, .. }) if *parent == core));
+        assert!(tree.lookup(items, "x").is_none());
+
+        let new = tree.create(data, "new.json", Some(0)).unwrap();
+        assert!(matches!(
+            tree.get(new),
+            Some(Node::File { res_path, .. }) if res_path == "res://Core/Data/new.json"
+        ));
+        assert!(tree.create(data, "new.json", Some(0)).is_none());
+        assert!(tree.create(new, "child", None).is_none());
+    }
+
+    #[test]
+    fn capture_writes_assets_and_manifest() {
+        let dir = std::env::temp_dir().join(format!("bpb_mount_{}", std::process::id()));
+        fs::create_dir_all(dir.join("game")).unwrap();
+        fs::write(dir.join("game/a.txt"), "alpha").unwrap();
+        let pck = dir.join("game.pck");
+        crate::pck::pack_directory(dir.join("game"), &pck, &Default::default()).unwrap();
+
+        assert!(Capture::new(&dir, &pck, Some("1.0.0")).is_err());
+        let mut capture = Capture::new(&dir.join("capture"), &pck, Some("1.0.0")).unwrap();
+        assert!(capture.read("res://a.txt").is_none());
+        capture.write("res://a.txt", b"ALPHA").unwrap();
+        capture.write("res://new/b.txt", b"bravo").unwrap();
+        assert_eq!(capture.read("res://a.txt").unwrap().unwrap(), b"ALPHA");
+        assert!(capture.write("res://../evil", b"").is_err());
+
+        let manifest: toml::Table = fs::read_to_string(dir.join("capture/replace.toml"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            manifest["replace"]["res://new/b.txt"].as_str(),
+            Some("new/b.txt")
+        );
+        assert_eq!(
+            manifest["version"]["required-game-version"].as_str(),
+            Some("1.0.0")
+        );
+        assert_eq!(fs::read(dir.join("capture/new/b.txt")).unwrap(), b"bravo");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+//! GUI 首次启动时的使用须知：说明会修改什么、备份放在哪里、如何通过 Steam 恢复，
+//! 并记录用户的备份选择。确认结果保存在配置目录（见 [`crate::dirs`]）的
+//! `bpb_enhance_onboarding.toml`，
+//! 确认之前不会执行任何写入
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+use toml::{Table, Value};
+
+use crate::dirs;
+
+const ONBOARDING_FILE: &str = "bpb_enhance_onboarding.toml";
+
+/// 修改前是否备份 PCK
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupMode {
+    /// 每次修改前把 PCK 复制一份到同目录（见 backup 模块）
+    #[default]
+    BeforeApply,
+    Off,
+}
+
+impl BackupMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::BeforeApply => "before-apply",
+            Self::Off => "off",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Onboarding {
+    /// 用户已阅读须知并同意修改游戏文件
+    pub accepted: bool,
+    pub backup: BackupMode,
+}
+
+impl Onboarding {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_file(ONBOARDING_FILE)
+    }
+
+    /// 读取确认结果；文件不存在或格式错误时视为尚未确认
+    pub fn load() -> Self {
+        let Some(path) = Self::path().filter(|p| p.is_file()) else {
+            return Self::default();
+        };
+
+        let result = std::fs::read_to_string(&path)
+            .with_context(|| format!("无法读取使用须知的确认记录: {}", path.display()))
+            .and_then(|content| Self::parse(&content))
+            .with_context(|| format!("使用须知的确认记录格式错误: {}", path.display()));
+
+        match result {
+            Ok(onboarding) => onboarding,
+            Err(err) => {
+                println!("⚠ {:#}，将重新显示使用须知", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow!("无法确定确认记录的保存位置"))?;
+        dirs::create_parent(&path)?;
+        std::fs::write(&path, self.to_toml())
+            .with_context(|| format!("无法保存确认记录: {}", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let table: Table = content.parse()?;
+        let backup = match table.get("backup").map(|v| v.as_str()) {
+            None => BackupMode::default(),
+            Some(Some("before-apply")) => BackupMode::BeforeApply,
+            Some(Some("off")) => BackupMode::Off,
+            Some(other) => bail!(
+                "backup 只能是 \"before-apply\" 或 \"off\"，实际为 {:?}",
+                other.unwrap_or("非字符串")
+            ),
+        };
+        Ok(Self {
+            accepted: table
+                .get("accepted")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            backup,
+        })
+    }
+
+    fn to_toml(&self) -> String {
+        let mut table = Table::new();
+        table.insert("accepted".into(), Value::Boolean(self.accepted));
+        table.insert("backup".into(), Value::String(self.backup.as_str().into()));
+        table.to_string()
+    }
+}
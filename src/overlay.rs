@@ -0,0 +1,211 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::platform;
+
+/// 覆盖包所在目录（位于游戏目录下）
+const OVERLAY_DIR: &str = "mods";
+/// 未指定时覆盖包的加载顺序
+pub const DEFAULT_PRIORITY: u16 = 100;
+/// 加载顺序配置，供游戏内的加载脚本读取
+const LOAD_ORDER_FILE: &str = "load_order.cfg";
+/// 开发版游戏从这里读取松散的 `res://` 覆盖文件（位于游戏目录下）
+const LOOSE_DIR: &str = "res_override";
+
+/// 覆盖包目录：`<游戏目录>/mods`
+pub fn overlay_dir(pck_path: &Path) -> Result<PathBuf> {
+    let game_dir = pck_path
+        .parent()
+        .ok_or_else(|| anyhow!("无法确定游戏目录: {}", pck_path.display()))?;
+    Ok(game_dir.join(OVERLAY_DIR))
+}
+
+/// 能否在覆盖包目录中写入覆盖包：目录已存在时检查它本身，否则检查能否在游戏目录中创建它。
+/// PCK 只读而游戏目录可写时（如只给 PCK 设置了只读属性），可以改用覆盖包
+pub fn can_write_packs(pck_path: &Path) -> bool {
+    let Ok(dir) = overlay_dir(pck_path) else {
+        return false;
+    };
+    let target = if dir.is_dir() {
+        dir.as_path()
+    } else {
+        match dir.parent() {
+            Some(parent) => parent,
+            None => return false,
+        }
+    };
+    platform::dir_write_protection(target).is_none()
+}
+
+/// 松散覆盖目录：`<游戏目录>/res_override`，只有支持它的开发版游戏才会创建
+pub fn loose_dir(pck_path: &Path) -> Result<PathBuf> {
+    let game_dir = pck_path
+        .parent()
+        .ok_or_else(|| anyhow!("无法确定游戏目录: {}", pck_path.display()))?;
+    Ok(game_dir.join(LOOSE_DIR))
+}
+
+/// 把资源按 `res://` 路径写成 `dir` 下的松散文件，内容没有变化的跳过，返回实际写入的路径
+pub fn write_loose_files(dir: &Path, files: &[(String, Vec<u8>)]) -> Result<Vec<String>> {
+    let mut written = Vec::new();
+    for (res_path, data) in files {
+        let relative = res_path.strip_prefix("res://").unwrap_or(res_path);
+        if relative.is_empty()
+            || Path::new(relative)
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("无法写成松散文件的路径: {}", res_path);
+        }
+
+        let path = dir.join(relative);
+        if fs::read(&path).is_ok_and(|existing| existing == *data) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("无法创建目录: {}", parent.display()))?;
+        }
+        fs::write(&path, data).with_context(|| format!("无法写入: {}", path.display()))?;
+        written.push(res_path.clone());
+    }
+    Ok(written)
+}
+
+/// MOD 名中只保留字母、数字、`-` 与 `_`，其余替换为 `_`
+pub fn sanitize_mod_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if sanitized.is_empty() {
+        "mod".to_string()
+    } else {
+        sanitized
+    }
+}
+
+/// 覆盖包文件名：`<优先级>_<MOD 名>.pck`，优先级补零使文件名排序即加载顺序
+pub fn pack_file_name(priority: u16, mod_name: &str) -> String {
+    format!("{:05}_{}.pck", priority, mod_name)
+}
+
+/// 解析覆盖包文件名，返回（优先级, MOD 名）
+fn parse_pack_file_name(file_name: &str) -> Option<(u16, &str)> {
+    let stem = file_name.strip_suffix(".pck")?;
+    let (priority, name) = stem.split_once('_')?;
+    if priority.is_empty() || !priority.bytes().all(|b| b.is_ascii_digit()) || name.is_empty() {
+        return None;
+    }
+    Some((priority.parse().ok()?, name))
+}
+
+/// 删除同一 MOD 以其他优先级生成的旧覆盖包
+pub fn remove_stale_packs(dir: &Path, mod_name: &str, keep: &str) -> Result<()> {
+    for file_name in pack_file_names(dir)? {
+        if file_name != keep && parse_pack_file_name(&file_name).is_some_and(|(_, n)| n == mod_name)
+        {
+            fs::remove_file(dir.join(&file_name))
+                .with_context(|| format!("无法删除旧的覆盖包: {}", file_name))?;
+        }
+    }
+    Ok(())
+}
+
+/// 按优先级（相同时按文件名）重写加载顺序配置，返回其路径
+pub fn write_load_order(dir: &Path) -> Result<PathBuf> {
+    let mut packs: Vec<(u16, String)> = pack_file_names(dir)?
+        .into_iter()
+        .filter_map(|f| parse_pack_file_name(&f).map(|(p, _)| (p, f.clone())))
+        .collect();
+    packs.sort();
+
+    let path = dir.join(LOAD_ORDER_FILE);
+    fs::write(&path, render_load_order(packs.iter().map(|(_, f)| f.as_str())))
+        .with_context(|| format!("无法写入加载顺序: {}", path.display()))?;
+    Ok(path)
+}
+
+fn render_load_order<'a>(packs: impl Iterator<Item = &'a str>) -> String {
+    let mut out = String::from(
+        "# 由 bpb_enhance 生成，请勿手动修改\n\
+         # 按顺序调用 ProjectSettings.load_resource_pack()，后加载的覆盖先加载的\n",
+    );
+    for pack in packs {
+        out.push_str(pack);
+        out.push('\n');
+    }
+    out
+}
+
+fn pack_file_names(dir: &Path) -> Result<Vec<String>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("无法读取目录: {}", dir.display()))? {
+        let entry = entry?;
+        if entry.path().is_file()
+            && let Some(name) = entry.file_name().to_str()
+        {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_names_sort_by_priority() {
+        let mut names = vec![
+            pack_file_name(200, "late"),
+            pack_file_name(5, "early"),
+            pack_file_name(100, &sanitize_mod_name("我的 mod!")),
+        ];
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["00005_early.pck", "00100_我的_mod_.pck", "00200_late.pck"]
+        );
+        assert_eq!(
+            parse_pack_file_name("00100_my_mod.pck"),
+            Some((100, "my_mod"))
+        );
+        assert_eq!(parse_pack_file_name("base.pck"), None);
+        assert_eq!(parse_pack_file_name("load_order.cfg"), None);
+    }
+
+    #[test]
+    fn loose_files_are_only_rewritten_when_changed() {
+        let dir = std::env::temp_dir().join(format!("bpb_loose_{}", std::process::id()));
+        let files = vec![
+            ("res://Core/Game.gde".to_string(), b"v1".to_vec()),
+            ("res://icon.png".to_string(), b"png".to_vec()),
+        ];
+        assert_eq!(write_loose_files(&dir, &files).unwrap().len(), 2);
+        assert_eq!(fs::read(dir.join("Core/Game.gde")).unwrap(), b"v1");
+
+        let changed = vec![("res://Core/Game.gde".to_string(), b"v2".to_vec())];
+        assert!(write_loose_files(&dir, &files[1..]).unwrap().is_empty());
+        assert_eq!(
+            write_loose_files(&dir, &changed).unwrap(),
+            vec!["res://Core/Game.gde"]
+        );
+        assert!(write_loose_files(&dir, &[("res://../x".to_string(), vec![])]).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,18 +1,27 @@
 use std::{
+    cell::RefCell,
     collections::{HashMap, HashSet},
-    fs::File,
+    fs::{self, File, OpenOptions},
     io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use binrw::{BinRead, BinWrite};
 use multi_index_map::MultiIndexMap;
 
+use crate::pool::{CancelToken, Cancelled, Pool};
+
 #[derive(BinRead, BinWrite, Debug, Clone)]
 #[br(
     magic = b"GDPC",
     little,
-    assert(version == 1, "only PCK version 1 is supported"),
+    assert(version == 1 || version == 2, "only PCK versions 1 and 2 are supported"),
     assert(file_count > 0, "no files in PCK")
 )]
 #[bw(magic = b"GDPC", little)]
@@ -21,25 +30,180 @@ pub struct Header {
     pub godot_version_major: u32,
     pub godot_version_minor: u32,
     pub godot_version_patch: u32,
+    /// v2 起的 PCK 标志位，v1 中为 0
+    #[br(if(version >= 2))]
+    #[bw(if(*version >= 2))]
+    pub flags: u32,
+    /// v2 起 entry 数据偏移的基准位置，v1 中为 0；带 [`PACK_REL_FILEBASE`] 时相对 PCK 起点
+    #[br(if(version >= 2))]
+    #[bw(if(*version >= 2))]
+    pub file_base: u64,
     pub reserved: [u32; 16],
     pub file_count: u32,
 }
 
+impl Header {
+    /// Header 的长度（含 magic）
+    pub fn size(&self) -> u64 {
+        if self.version >= 2 { 100 } else { 88 }
+    }
+
+    /// 该版本 entry 表的布局
+    pub fn entry_layout(&self) -> EntryLayout {
+        EntryLayout {
+            file_base: self.data_base(),
+            has_flags: self.version >= 2,
+        }
+    }
+
+    /// entry 数据偏移的基准在文件中的绝对位置。带 [`PACK_REL_FILEBASE`] 时 file_base 相对 PCK 起点，
+    /// 而这里读写的 PCK 都从文件开头开始（嵌入可执行文件的需先分离），两种写法的值相同
+    fn data_base(&self) -> u64 {
+        if self.version >= 2 { self.file_base } else { 0 }
+    }
+
+    /// 让数据偏移以文件中的绝对位置 `offset` 为基准；标志位（包括 PACK_REL_FILEBASE）保持不变
+    fn set_data_base(&mut self, offset: u64) {
+        if self.version >= 2 {
+            self.file_base = offset;
+        }
+    }
+}
+
+/// 写出新 PCK 时按 Godot 主版本选择格式：Godot 4 使用 v2，Godot 3 使用 v1
+fn format_version_for(godot_major: u32) -> u32 {
+    if godot_major >= 4 { 2 } else { 1 }
+}
+
+/// header 标志位：entry 表已加密（读写时需要密钥，见 [`Keyed`]）
+const PACK_DIR_ENCRYPTED: u32 = 1 << 0;
+/// header 标志位：file_base 相对 PCK 起点而不是文件开头（Godot 4.2 起的导出会设置）
+const PACK_REL_FILEBASE: u32 = 1 << 1;
+/// entry 标志位：数据已加密
+const PACK_FILE_ENCRYPTED: u32 = 1 << 0;
+/// entry 标志位：该路径已删除（Godot 4.4 起），引擎与这里的读取都把它当作不存在
+const PACK_FILE_REMOVAL: u32 = 1 << 1;
+
+/// 从文件开头识别出的 PCK 格式变体。目前能读写小端 v1 与 v2，其他变体（主机平台的大端导出、
+/// 新版本格式）在这里给出说明找到了什么的错误，而不是 binrw 的断言文本；支持新变体时只需扩展这里
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderVariant {
+    /// PC 平台导出的小端 v1（Godot 3）
+    V1LittleEndian,
+    /// PC 平台导出的小端 v2（Godot 4），带 PCK 标志位、file_base 与每个 entry 的标志位
+    V2LittleEndian,
+}
+
+/// 读取开头的 magic 与版本字段识别变体，读取后恢复原位置
+pub fn detect_header_variant<R: Read + Seek>(reader: &mut R) -> Result<HeaderVariant> {
+    let start = reader.stream_position()?;
+    let mut head = [0u8; 8];
+    let mut filled = 0;
+    while filled < head.len() {
+        match reader.read(&mut head[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    reader.seek(SeekFrom::Start(start))?;
+
+    if filled < head.len() {
+        bail!("文件太小，不是 PCK（只有 {} 字节）", filled);
+    }
+    let (magic, version) = head.split_at(4);
+    let version_le = u32::from_le_bytes(version.try_into().unwrap());
+    let version_be = u32::from_be_bytes(version.try_into().unwrap());
+    match magic {
+        b"GDPC" if version_le == 1 => Ok(HeaderVariant::V1LittleEndian),
+        b"GDPC" if version_le == 2 => Ok(HeaderVariant::V2LittleEndian),
+        b"GDPC" if version_be < 16 => bail!(
+            "不支持的平台导出：找到大端字节序的 PCK 头（版本 {}），通常来自主机平台导出，只支持 PC 平台的小端 PCK",
+            version_be
+        ),
+        b"GDPC" if version_le < 16 => bail!(
+            "不支持的 PCK 版本 {}：只支持版本 1（Godot 3 导出）与版本 2（Godot 4 导出）",
+            version_le
+        ),
+        b"GDPC" => bail!("不支持的 PCK 变体：无法识别的版本字段 {:02x?}", version),
+        b"CPDG" => {
+            bail!("不支持的平台导出：magic 为 CPDG（字节序反转的 GDPC），这是大端平台导出的 PCK")
+        }
+        _ => bail!(
+            "不是 PCK 文件：开头为 {:02x?} 而不是 GDPC（嵌入了 PCK 的可执行文件需要先分离出 PCK）",
+            magic
+        ),
+    }
+}
+
+/// 读取并校验文件开头的 header，读取后位于 entry 表起点
+pub fn read_header<R: Read + Seek>(reader: &mut R) -> Result<Header> {
+    reader
+        .seek(SeekFrom::Start(0))
+        .context("failed to seek to header start")?;
+    detect_header_variant(reader)?;
+    Header::read(reader).context("failed to read PCK header")
+}
+
+/// 嵌入可执行文件时 PCK 起始位置的对齐
+const EMBED_ALIGNMENT: u64 = 8;
+
+/// entry 表的二进制布局，由 header 版本决定（见 [`Header::entry_layout`]）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryLayout {
+    /// 表中数据偏移的基准；读取后的 [`RawFileEntry::offset`] 已加上它，为文件内绝对偏移
+    pub file_base: u64,
+    /// 每个 entry 末尾是否有标志位字段（v2）
+    pub has_flags: bool,
+}
+
+impl EntryLayout {
+    /// 路径长度为 `path_len` 的 entry 在表中占用的字节数
+    pub fn entry_size(&self, path_len: u32) -> u64 {
+        // path_len 字段 + 路径 + offset(u64) + size(u64) + md5(16) [+ flags(u32)]
+        4 + path_len as u64 + 8 + 8 + 16 + if self.has_flags { 4 } else { 0 }
+    }
+}
+
 #[derive(BinRead, Debug, Clone)]
-#[br(little)]
+#[br(little, import_raw(layout: EntryLayout))]
 pub struct RawFileEntry {
     pub path_len: u32,
 
     #[br(count = path_len)]
     pub path_bytes: Vec<u8>,
 
+    // 损坏的表中偏移可能是任意值，饱和后会被当作超出文件
+    #[br(map = |offset: u64| offset.saturating_add(layout.file_base))]
     pub offset: u64,
     pub size: u64,
 
     pub md5: [u8; 16],
+
+    /// v2 的 entry 标志位（如数据加密），v1 中为 0
+    #[br(if(layout.has_flags))]
+    pub flags: u32,
 }
 
 impl RawFileEntry {
+    /// 数据是否以 Godot 的加密块格式存放
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & PACK_FILE_ENCRYPTED != 0
+    }
+
+    /// 是否带删除标志（见 [`DeleteMode::MarkRemoved`]）
+    pub fn is_removed(&self) -> bool {
+        self.flags & PACK_FILE_REMOVAL != 0
+    }
+
+    /// 数据在文件中实际占用的字节数；加密的数据带块头并按 16 字节补齐，比 `size` 大
+    pub fn stored_size(&self) -> u64 {
+        if self.is_encrypted() {
+            encrypted_size(self.size)
+        } else {
+            self.size
+        }
+    }
+
     /// 解析 entry 的路径字符串（去掉末尾的 NUL）
     fn path(&self) -> Result<String> {
         Ok(String::from_utf8(self.path_bytes.clone())?
@@ -58,504 +222,5701 @@ struct EntryRecord {
     entry: RawFileEntry,
 }
 
-fn entry_binary_size(path_len: u32) -> u64 {
-    // path_len + offset(u64) + size(u64) + md5(16) + path_len field itself
-    4 + path_len as u64 + 8 + 8 + 16
-}
-
 fn normalized_path_bytes(path: &str) -> Vec<u8> {
     let mut path_bytes = path.as_bytes().to_vec();
 
-    while path_bytes.len() % 4 != 0 {
+    while !path_bytes.len().is_multiple_of(4) {
         path_bytes.push(0);
     }
 
     path_bytes
 }
 
-#[derive(Debug, Clone, Copy)]
-struct TablePlan {
-    table_start: u64,
-    table_end_after: u64,
-    next_new_table_offset: u64,
+fn hex_digest(md5: &[u8; 16]) -> String {
+    md5.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-struct AppendCtx {
-    writer: File,
-    reader: BufReader<File>,
-    append_pos: u64,
+/// entry 表在文件中的区间：起点紧跟 header，终点由逐个跳过 file_count 个 entry 得出。
+/// 其他打包工具可能在表与数据区之间留有填充，因此不能用第一个 entry 的位置或数据起点推断
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableRegion {
+    pub start: u64,
+    pub end: u64,
 }
 
-impl AppendCtx {
-    /// 创建读写上下文，定位到文件末尾用于追加
-    fn new(pck_file: &mut File) -> Result<Self> {
-        // Windows 上 try_clone 句柄共享文件指针，避免缓冲，写入前显式 seek
-        let mut writer = pck_file.try_clone()?;
-        writer
-            .seek(SeekFrom::End(0))
-            .context("failed to seek to file end")?;
-        let append_pos = writer.stream_position().context("failed to get file end")?;
+fn table_region<S: PckStorage>(pck_file: &mut S) -> Result<TableRegion> {
+    let mut reader = BufReader::new(&mut *pck_file);
+    let header = read_header(&mut reader)?;
+    let layout = header.entry_layout();
 
-        let reader = BufReader::new(pck_file.try_clone()?);
+    let start = reader.stream_position()?;
+    if header.flags & PACK_DIR_ENCRYPTED != 0 {
+        // 加密块头中记录了明文长度，不需要密钥
+        reader.seek(SeekFrom::Start(start + 16))?;
+        let plain_len = u64::read_le(&mut reader).context("failed to read encrypted table")?;
+        return Ok(TableRegion {
+            start,
+            end: start + encrypted_size(plain_len),
+        });
+    }
 
-        Ok(Self {
-            writer,
-            reader,
-            append_pos,
-        })
+    let mut end = start;
+    for _ in 0..header.file_count {
+        reader.seek(SeekFrom::Start(end))?;
+        let path_len = u32::read_le(&mut reader).context("failed to read RawFileEntry")?;
+        end += layout.entry_size(path_len);
     }
+    Ok(TableRegion { start, end })
+}
 
-    /// 将数据追加到末尾，返回起始偏移
-    fn append_bytes(&mut self, data: &[u8], path: &str) -> Result<u64> {
-        let offset = self.append_pos;
-        self.writer
-            .seek(SeekFrom::Start(offset))
-            .with_context(|| format!("failed to seek writer to append_pos for {}", path))?;
-        self.writer
-            .write_all(data)
-            .with_context(|| format!("failed to append data for {}", path))?;
-        self.append_pos += data.len() as u64;
-        Ok(offset)
+/// 明文长度为 `plain_size` 的 entry 表在文件中占用的字节数
+fn table_extent(header: &Header, plain_size: u64) -> u64 {
+    if header.flags & PACK_DIR_ENCRYPTED != 0 {
+        encrypted_size(plain_size)
+    } else {
+        plain_size
     }
+}
 
-    /// 读取指定范围并复制到末尾，返回新偏移
-    fn move_range(&mut self, offset: u64, size: u64, path: &str) -> Result<u64> {
-        let data_size: usize = size
-            .try_into()
-            .map_err(|_| anyhow!("file too large to move: {}", path))?;
-        let mut buf = vec![0u8; data_size];
-        self.reader
-            .seek(SeekFrom::Start(offset))
-            .with_context(|| format!("failed to seek data for {}", path))?;
-        self.reader
-            .read_exact(&mut buf)
-            .with_context(|| format!("failed to read data for {}", path))?;
-        let dst = self.append_bytes(&buf, path)?;
+/// 修改后的 entry 表：仍从原表起点开始写，延伸到 `table_end_after`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TablePlan {
+    /// 修改前的表区间
+    pub region: TableRegion,
+    /// 修改后表的终点；数据起点在此之前的 entry 需要搬到文件末尾
+    pub table_end_after: u64,
+    /// 新增 entry 的排序起点，排在全部原有 entry 之后
+    pub next_new_table_offset: u64,
+}
 
-        Ok(dst)
+/// 会被扩展后的 entry 表覆盖、需要搬到文件末尾的数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveTarget {
+    pub path: String,
+    pub offset: u64,
+    /// 文件中实际占用的字节数（加密数据含块头与补齐）
+    pub size: u64,
+}
+
+/// 一次替换/新增的完整规划；预演、确认、进度估计与磁盘空间检查都使用它，而不是各自重新计算
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyPlan {
+    pub table: TablePlan,
+    /// 已存在、将被替换的路径
+    pub replaced: Vec<String>,
+    /// `replaced` 中新数据不大于原数据、直接写回原位置的路径
+    pub overwritten: Vec<String>,
+    /// 将新增的路径
+    pub added: Vec<String>,
+    /// 按表顺序排列
+    pub moves: Vec<MoveTarget>,
+    /// 将追加到文件末尾的新数据字节数（不含搬移与原位写入）
+    pub new_data_bytes: u64,
+    /// 写回原位置的字节数
+    pub overwrite_bytes: u64,
+    /// 修改前的文件大小
+    pub archive_size: u64,
+}
+
+impl ApplyPlan {
+    /// 需要搬移的字节数
+    pub fn move_bytes(&self) -> u64 {
+        self.moves.iter().map(|m| m.size).sum()
     }
 
-    /// 刷新写缓冲
-    fn flush(&mut self) -> Result<()> {
-        self.writer.flush().context("failed to flush appended data")
+    /// 修改后重写的整张 entry 表的字节数
+    pub fn table_bytes(&self) -> u64 {
+        self.table.table_end_after - self.table.region.start
     }
-}
 
-/// 将原有 entry 读取进多索引结构（按路径 / 表偏移）
-fn build_entry_map(
-    pck_file: &mut File,
-    entry_offsets: &HashMap<String, u64>,
-) -> Result<MultiIndexEntryRecordMap> {
-    let mut reader = BufReader::new(pck_file.try_clone()?);
-    let mut entry_map = MultiIndexEntryRecordMap::default();
+    /// 修改期间读写的总字节数，用于估计进度：搬移数据的读与写、新数据、原位写入与重写 entry 表
+    pub fn io_bytes(&self) -> u64 {
+        self.move_bytes() * 2 + self.new_data_bytes + self.overwrite_bytes + self.table_bytes()
+    }
 
-    for (path, entry_offset) in entry_offsets {
-        reader
-            .seek(SeekFrom::Start(*entry_offset))
-            .with_context(|| format!("failed to seek entry {}", path))?;
-        let entry: RawFileEntry = RawFileEntry::read(&mut reader)
-            .with_context(|| format!("failed to read entry {}", path))?;
+    /// 修改前完整复制 `copies` 次 PCK（备份、原子模式的临时副本）的读写字节数
+    pub fn copy_bytes(&self, copies: u64) -> u64 {
+        self.archive_size * 2 * copies
+    }
 
-        entry_map.insert(EntryRecord {
-            path: path.clone(),
-            table_offset: *entry_offset,
-            entry,
-        });
+    /// 修改后文件增长的字节数，即需要的磁盘空间；修改不会截断文件，旧数据仍占用空间
+    pub fn growth(&self) -> u64 {
+        let append_start = self.archive_size.max(self.table.table_end_after);
+        append_start - self.archive_size + self.move_bytes() + self.new_data_bytes
     }
 
-    Ok(entry_map)
+    /// entry 表或原有数据都不变（只替换已有文件且无需搬移）
+    pub fn keeps_layout(&self) -> bool {
+        self.added.is_empty() && self.moves.is_empty()
+    }
 }
 
-fn split_inputs<'a>(
-    files: Vec<(&'a str, &'a [u8])>,
-    entry_map: &MultiIndexEntryRecordMap,
-) -> (Vec<(String, &'a [u8])>, Vec<(String, &'a [u8])>) {
-    // 按是否已存在拆分为替换列表和新增列表
-    let mut replace_inputs = Vec::new();
-    let mut add_inputs = Vec::new();
+/// 搬移旧数据时是否重新计算 MD5
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MoveVerification {
+    /// 按表中记录的大小直接复制，沿用原 MD5
+    #[default]
+    Off,
+    /// 重新计算 MD5，与表中记录不一致时输出警告
+    Warn,
+    /// 重新计算 MD5，不一致时用实际值修正表中记录
+    Repair,
+}
 
-    for (path, data) in files {
-        let path_string = path.to_string();
-        if entry_map.get_by_path(&path_string).is_some() {
-            replace_inputs.push((path_string, data));
-        } else {
-            add_inputs.push((path_string, data));
+/// 读写缓冲、分块大小、数据搬移校验与取消标记
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoOptions {
+    /// 读取原有数据时的缓冲大小
+    pub read_buffer: usize,
+    /// 顺序写出（打包、写表）时的缓冲大小
+    pub write_buffer: usize,
+    /// 搬移/复制数据时每次处理的块大小
+    pub chunk_size: usize,
+    /// 搬移旧数据时的 MD5 校验方式
+    pub verify_moves: MoveVerification,
+    /// 写入前并行计算 MD5 的线程数
+    pub jobs: usize,
+    /// 替换与整理在每块数据之间检查，取消后尽快停下并返回 [`Cancelled`]。替换被取消时
+    /// 已写入的数据不会撤销，需要配合回滚日志或临时副本使用
+    pub cancel: CancelToken,
+    /// 新数据不大于原数据时写回原位置而不是追加。会改写现有数据，中断后原 entry 无法恢复，
+    /// 只应在有回滚日志时开启
+    pub overwrite_in_place: bool,
+}
+
+impl IoOptions {
+    /// 按 PCK 大小自动选择：小文件用小缓冲，大文件用大块减少系统调用
+    pub fn auto(archive_size: u64) -> Self {
+        const MIB: u64 = 1024 * 1024;
+        let (read_buffer, write_buffer, chunk_size) = match archive_size {
+            s if s < 64 * MIB => (64 * 1024, 64 * 1024, 1024 * 1024),
+            s if s < 1024 * MIB => (256 * 1024, 256 * 1024, 4 * 1024 * 1024),
+            _ => (1024 * 1024, 1024 * 1024, 16 * 1024 * 1024),
+        };
+
+        Self {
+            read_buffer,
+            write_buffer,
+            chunk_size,
+            verify_moves: MoveVerification::Off,
+            jobs: logical_cores(),
+            cancel: CancelToken::default(),
+            overwrite_in_place: false,
         }
     }
+}
 
-    (replace_inputs, add_inputs)
+/// 解析 entry 表时的安全上限：损坏或伪造的 PCK 可能声称有数百万个 entry 或极长的路径，
+/// 不加限制会按声称的数量无界分配内存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableLimits {
+    pub max_entries: u32,
+    pub max_path_len: u32,
 }
 
-fn plan_table(
-    entry_map: &MultiIndexEntryRecordMap,
-    add_inputs: &[(String, &[u8])],
-) -> Result<TablePlan> {
-    // 预估新增后表区间范围与下一个表偏移起点
-    let table_start = entry_map
-        .iter_by_table_offset()
-        .next()
-        .map(|e| e.table_offset)
-        .ok_or_else(|| anyhow!("empty entry list"))?;
+impl TableLimits {
+    /// 远大于任何真实游戏的 entry 数与路径长度
+    pub const DEFAULT: Self = Self {
+        max_entries: 1_000_000,
+        max_path_len: 4096,
+    };
+    /// 不做限制（`--force-limits-off`）
+    pub const OFF: Self = Self {
+        max_entries: u32::MAX,
+        max_path_len: u32::MAX,
+    };
+}
 
-    let current_size: u64 = entry_map
-        .iter_by_table_offset()
-        .map(|e| entry_binary_size(e.entry.path_len))
-        .sum();
+impl Default for TableLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
 
-    let additional: u64 = add_inputs
-        .iter()
-        .map(|(path, _)| {
-            let len = normalized_path_bytes(path).len();
-            entry_binary_size(len as u32)
-        })
-        .sum();
+/// 并行任务默认的线程数
+fn logical_cores() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// 读取与修改 PCK 时发现的问题与调试信息，交给 [`DiagnosticsSink`] 处理，不直接输出
+#[derive(Debug, Clone)]
+pub enum Diagnostic {
+    /// 读到的 PCK 头
+    Header(Header),
+    /// entry 表中有重复路径，只使用最后一个
+    DuplicateEntry { path: String },
+    /// 搬移时算出的 MD5 与表中记录不一致；`repaired` 表示已用实际值修正
+    MoveDigestMismatch {
+        path: String,
+        actual: [u8; 16],
+        recorded: [u8; 16],
+        repaired: bool,
+    },
+    /// 一次修改中搬移的数据里 MD5 不一致的 entry 总数
+    MoveDigestMismatches { count: usize },
+}
 
-    let table_end_after = table_start + current_size + additional;
+impl Diagnostic {
+    /// 调试信息为 [`log::Level::Debug`]，其余为 [`log::Level::Warn`]
+    pub fn level(&self) -> log::Level {
+        match self {
+            Self::Header(_) => log::Level::Debug,
+            _ => log::Level::Warn,
+        }
+    }
+}
 
-    Ok(TablePlan {
-        table_start,
-        table_end_after,
-        next_new_table_offset: table_start + current_size,
-    })
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Header(header) => write!(f, "Header: {:?}", header),
+            Self::DuplicateEntry { path } => write!(
+                f,
+                "PCK 中存在重复的 entry: {}，仅使用最后一个（可使用 repair --dedupe-entries 修复）",
+                path
+            ),
+            Self::MoveDigestMismatch {
+                path,
+                actual,
+                recorded,
+                repaired,
+            } => write!(
+                f,
+                "{} 的实际 MD5 {} 与表中记录 {} 不一致{}",
+                path,
+                hex_digest(actual),
+                hex_digest(recorded),
+                if *repaired { "，已修正" } else { "" }
+            ),
+            Self::MoveDigestMismatches { count } => {
+                write!(
+                    f,
+                    "搬移的数据中有 {} 个 entry 的 MD5 与表中记录不一致",
+                    count
+                )
+            }
+        }
+    }
 }
 
-/// 读取 Header 和全部文件条目，返回：
-/// - header
-/// - entries 映射：res_path -> 在 FileTable 中该 entry 的起始偏移
-/// 解析 PCK 头与 entry 表，返回 header 与路径到表偏移的映射
-pub fn read_header_and_index(file: &mut File) -> Result<(Header, HashMap<String, u64>)> {
-    let mut reader = BufReader::new(file.try_clone()?);
-    reader
-        .seek(SeekFrom::Start(0))
-        .context("failed to seek to header start")?;
+/// 接收 [`Diagnostic`]；通过 [`scope_diagnostics`] 在某个线程上生效
+pub trait DiagnosticsSink: Send + Sync {
+    fn report(&self, diagnostic: &Diagnostic);
+}
 
-    let header = Header::read(&mut reader).context("failed to read PCK header")?;
-    println!("Header: {:?}", header);
+impl<F: Fn(&Diagnostic) + Send + Sync> DiagnosticsSink for F {
+    fn report(&self, diagnostic: &Diagnostic) {
+        self(diagnostic)
+    }
+}
 
-    let mut index = HashMap::with_capacity(header.file_count as usize);
+/// 默认的去向：按 [`Diagnostic::level`] 写入 `log`
+pub struct LogSink;
 
-    for _ in 0..header.file_count {
-        let entry_offset = reader
-            .stream_position()
-            .context("failed to get entry offset")?;
-        let entry: RawFileEntry =
-            RawFileEntry::read(&mut reader).context("failed to read RawFileEntry")?;
+impl DiagnosticsSink for LogSink {
+    fn report(&self, diagnostic: &Diagnostic) {
+        log::log!(diagnostic.level(), "{}", diagnostic);
+    }
+}
 
-        let path = entry
-            .path()
-            .with_context(|| "invalid UTF-8 in entry path")?;
+/// 先收集起来，之后由调用方一次取走（GUI 在修改结束后放进通知记录）
+#[derive(Debug, Default)]
+pub struct DiagnosticsBuffer(Mutex<Vec<Diagnostic>>);
 
-        index.insert(path, entry_offset);
+impl DiagnosticsBuffer {
+    /// 取走目前收集到的全部诊断
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
     }
+}
 
-    Ok((header, index))
+impl DiagnosticsSink for DiagnosticsBuffer {
+    fn report(&self, diagnostic: &Diagnostic) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(diagnostic.clone());
+    }
 }
 
-/// 批量替换（以及新增）PCK 中文件。
-/// 流程：
-/// 1. 先区分需要替换的与新增的文件
-/// 2. 如果新增导致条目区间变大，则把被覆盖风险的文件数据搬到末尾
-/// 3. 把新增和替换的数据统一追加到末尾，并更新/新增对应 entry
-/// 4. 重写 header 的 file_count 以及完整的 entry 表
-/// 批量替换/新增文件：计算迁移、追加数据并重写 entry 表与 file_count
-pub fn replace_files_in_pck(
-    pck_file: &mut File,
-    header: &Header,
-    entry_offsets: &HashMap<String, u64>,
-    files: Vec<(&str, &[u8])>,
-) -> Result<()> {
-    if files.is_empty() {
-        return Ok(());
+thread_local! {
+    static DIAGNOSTICS: RefCell<Option<Arc<dyn DiagnosticsSink>>> = const { RefCell::new(None) };
+}
+
+/// 在返回的 [`DiagnosticsScope`] 存在期间，当前线程上的诊断交给 `sink`；
+/// 其他线程与 scope 结束之后不受影响，没有 scope 时使用默认的 [`LogSink`]
+pub fn scope_diagnostics(sink: Arc<dyn DiagnosticsSink>) -> DiagnosticsScope {
+    let previous = DIAGNOSTICS.with(|current| current.borrow_mut().replace(sink));
+    DiagnosticsScope { previous }
+}
+
+/// 见 [`scope_diagnostics`]；drop 时恢复之前的去向
+#[must_use = "诊断去向只在 DiagnosticsScope 存在期间生效"]
+pub struct DiagnosticsScope {
+    previous: Option<Arc<dyn DiagnosticsSink>>,
+}
+
+impl Drop for DiagnosticsScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        DIAGNOSTICS.with(|current| *current.borrow_mut() = previous);
     }
+}
 
-    let mut dedup = HashSet::new();
-    for (path, _) in &files {
-        if !dedup.insert(*path) {
-            return Err(anyhow!("重复的路径: {}", path));
+fn diagnose(diagnostic: Diagnostic) {
+    match DIAGNOSTICS.with(|current| current.borrow().clone()) {
+        Some(sink) => sink.report(&diagnostic),
+        None => LogSink.report(&diagnostic),
+    }
+}
+
+/// 修改 PCK 的阶段，见 [`Progress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStage {
+    /// 把会被扩展后的 entry 表覆盖的旧数据搬到文件末尾
+    Move,
+    /// 追加新数据或写回原位置
+    Append,
+    /// 重写 header 与 entry 表
+    Table,
+}
+
+impl ProgressStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Move => "move",
+            Self::Append => "append",
+            Self::Table => "table",
         }
     }
+}
 
-    let mut entry_map = build_entry_map(pck_file, entry_offsets)?;
+/// 修改 PCK 时的进度回调：每个阶段开始时以 `done_bytes = 0` 调用一次，之后每写完一块调用一次，
+/// 阶段结束时 `done_bytes == total_bytes`。没有要做的阶段（如无需搬移）不会回调。
+/// 闭包 `Fn(ProgressStage, u64, u64)` 可以直接使用，`()` 表示不关心进度
+pub trait Progress {
+    fn on_progress(&self, stage: ProgressStage, done_bytes: u64, total_bytes: u64);
+}
 
-    let (replace_inputs, add_inputs) = split_inputs(files, &entry_map);
-    let plan = plan_table(&entry_map, &add_inputs)?;
-    let replace_paths: HashSet<String> = replace_inputs.iter().map(|(p, _)| p.clone()).collect();
+impl Progress for () {
+    fn on_progress(&self, _: ProgressStage, _: u64, _: u64) {}
+}
 
-    let mut append = AppendCtx::new(pck_file)?;
+impl<F: Fn(ProgressStage, u64, u64)> Progress for F {
+    fn on_progress(&self, stage: ProgressStage, done_bytes: u64, total_bytes: u64) {
+        self(stage, done_bytes, total_bytes)
+    }
+}
 
-    // 2) 迁移会被新 entry 覆盖的旧文件（仅未替换的）
-    let move_targets: Vec<(String, u64, u64)> = entry_map
-        .iter_by_table_offset()
-        .filter(|r| !replace_paths.contains(r.path.as_str()))
-        .filter(|r| r.entry.offset < plan.table_end_after)
-        .map(|r| (r.path.clone(), r.entry.offset, r.entry.size))
-        .collect();
+/// 当前阶段已写入的字节数；每累计一块（`chunk_size`）或阶段写完时回调一次，
+/// 大量小文件不会产生同样多的回调
+struct StageProgress<'a> {
+    progress: &'a dyn Progress,
+    stage: ProgressStage,
+    done: u64,
+    total: u64,
+    reported: u64,
+    step: u64,
+}
 
-    for (path, offset, size) in move_targets {
-        let new_offset = append.move_range(offset, size, &path)?;
-        entry_map
-            .update_by_path(&path, |entry: &mut RawFileEntry| {
-                entry.offset = new_offset;
-            })
-            .ok_or_else(|| anyhow!("entry {} missing during move", path))?;
+impl<'a> StageProgress<'a> {
+    fn new(progress: &'a dyn Progress, step: usize) -> Self {
+        Self {
+            progress,
+            stage: ProgressStage::Move,
+            done: 0,
+            total: 0,
+            reported: 0,
+            step: step as u64,
+        }
     }
 
-    // 3) 先新增后替换，避免新增 entry 位置被重复计算
-    let mut next_new_table_offset = plan.next_new_table_offset;
-    for (path, data) in add_inputs.iter() {
-        let mut path_bytes = normalized_path_bytes(path);
-        let path_len = path_bytes.len() as u32;
+    fn begin(&mut self, stage: ProgressStage, total: u64) {
+        self.stage = stage;
+        self.done = 0;
+        self.total = total;
+        self.reported = 0;
+        if total > 0 {
+            self.progress.on_progress(stage, 0, total);
+        }
+    }
 
-        let new_offset = append.append_bytes(data, &path)?;
-        let digest = md5::compute(data);
-        let raw_entry = RawFileEntry {
-            path_len,
-            path_bytes: std::mem::take(&mut path_bytes),
-            offset: new_offset,
-            size: data.len() as u64,
-            md5: digest.0,
-        };
+    fn advance(&mut self, bytes: u64) {
+        if self.total == 0 || bytes == 0 {
+            return;
+        }
+        self.done = (self.done + bytes).min(self.total);
+        if self.done == self.total || self.done - self.reported >= self.step {
+            self.reported = self.done;
+            self.progress.on_progress(self.stage, self.done, self.total);
+        }
+    }
+}
 
-        entry_map.insert(EntryRecord {
-            path: path.clone(),
-            table_offset: next_new_table_offset,
-            entry: raw_entry,
-        });
-        next_new_table_offset += entry_binary_size(path_len);
+/// PCK 所在的可读写存储。除了磁盘上的文件，测试可以直接使用内存中的 `Cursor<Vec<u8>>`，
+/// 其他容器中的 PCK 实现这几个方法后也能使用本模块的读取与修改操作
+pub trait PckStorage: Read + Write + Seek {
+    /// 当前长度
+    fn size(&mut self) -> std::io::Result<u64>;
+
+    /// 截断到 `len`，或用 0 延长到 `len`
+    fn set_size(&mut self, len: u64) -> std::io::Result<()>;
+
+    /// 最后修改时间，用于发现其他程序在修改期间写入；无法得知时为 None，只比较长度
+    fn modified(&mut self) -> Option<SystemTime> {
+        None
     }
 
-    for (path, data) in replace_inputs {
-        let new_offset = append.append_bytes(data, &path)?;
-        let digest = md5::compute(data);
-        entry_map
-            .update_by_path(&path, |entry: &mut RawFileEntry| {
-                entry.offset = new_offset;
-                entry.size = data.len() as u64;
-                entry.md5.copy_from_slice(&digest.0);
-            })
-            .ok_or_else(|| anyhow!("entry {} not found in PCK", path))?;
+    /// 只读映射全部内容；不支持时为 None，调用方回退到 seek + read
+    #[cfg(feature = "mmap")]
+    fn map(&self) -> Option<memmap2::Mmap> {
+        None
     }
 
-    append.flush()?;
+    /// 读写加密的 entry 表与 entry 数据时使用的密钥；没有时为 None（见 [`Keyed`]）
+    fn encryption_key(&self) -> Option<&EncryptionKey> {
+        None
+    }
 
-    // 4) 重写 header 和 entry 表
-    let (_, min_data_offset) = entry_map
-        .iter_by_table_offset()
-        .map(|e| (e.path.clone(), e.entry.offset))
-        .min_by_key(|(_, off)| *off)
-        .ok_or_else(|| anyhow!("no entries to write"))?;
+    /// 解析 entry 表时的上限（见 [`Keyed::with_limits`]）
+    fn table_limits(&self) -> TableLimits {
+        TableLimits::DEFAULT
+    }
+}
 
-    let recalculated_table_size: u64 = entry_map
-        .iter_by_table_offset()
-        .map(|e| entry_binary_size(e.entry.path_len))
-        .sum();
+impl PckStorage for File {
+    fn size(&mut self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
 
-    if plan.table_start + recalculated_table_size > min_data_offset {
-        return Err(anyhow!(
-            "新 entry 表长度 {} 超出数据起始 {}，请检查迁移逻辑",
-            recalculated_table_size,
-            min_data_offset
-        ));
+    fn set_size(&mut self, len: u64) -> std::io::Result<()> {
+        self.set_len(len)
     }
 
-    // 更新 header 并整体写回
-    let new_file_count: u32 = entry_map
-        .len()
-        .try_into()
-        .map_err(|_| anyhow!("文件数量过多，超出 u32 限制"))?;
+    fn modified(&mut self) -> Option<SystemTime> {
+        self.metadata().and_then(|meta| meta.modified()).ok()
+    }
 
-    let mut new_header = header.clone();
-    new_header.file_count = new_file_count;
+    /// 几百 MB 的 PCK 上，读取 entry 表和搬移数据时可省去大量系统调用
+    #[cfg(feature = "mmap")]
+    fn map(&self) -> Option<memmap2::Mmap> {
+        // SAFETY: 映射只读，且只在单次操作内使用；本工具修改 PCK 前会确认没有其他程序在写入
+        // （见 FileSnapshot），写入时只追加或写回已读取完毕的区间，不会改动映射中仍要读取的数据
+        unsafe { memmap2::Mmap::map(self) }.ok()
+    }
+}
 
-    {
-        let mut header_writer = BufWriter::new(pck_file.try_clone()?);
-        header_writer
-            .seek(SeekFrom::Start(0))
-            .context("failed to seek header start")?;
-        new_header
-            .write_le(&mut header_writer)
-            .context("failed to write header")?;
-        header_writer.flush().context("failed to flush header")?;
-    }
-
-    // 重写 entry 表（按 table_offset 顺序）
-    let mut table_writer = BufWriter::new(pck_file.try_clone()?);
-    table_writer
-        .seek(SeekFrom::Start(plan.table_start))
-        .context("failed to seek to entry table start")?;
-    for record in entry_map.iter_by_table_offset() {
-        // 手动写入每个字段以确保正确性
-        record
-            .entry
-            .path_len
-            .write_le(&mut table_writer)
-            .with_context(|| format!("failed to write path_len for {}", record.path))?;
-        table_writer
-            .write_all(&record.entry.path_bytes)
-            .with_context(|| format!("failed to write path_bytes for {}", record.path))?;
-        record
-            .entry
-            .offset
-            .write_le(&mut table_writer)
-            .with_context(|| format!("failed to write offset for {}", record.path))?;
-        record
-            .entry
-            .size
-            .write_le(&mut table_writer)
-            .with_context(|| format!("failed to write size for {}", record.path))?;
-        table_writer
-            .write_all(&record.entry.md5)
-            .with_context(|| format!("failed to write md5 for {}", record.path))?;
+impl PckStorage for std::io::Cursor<Vec<u8>> {
+    fn size(&mut self) -> std::io::Result<u64> {
+        Ok(self.get_ref().len() as u64)
     }
-    table_writer
-        .flush()
-        .context("failed to flush entry table")?;
 
-    // 5) 截断文件到正确大小（删除旧数据）
-    // let final_size = entry_map
-    //     .iter_by_table_offset()
-    //     .map(|e| e.entry.offset + e.entry.size)
-    //     .max()
-    //     .unwrap_or(plan.table_start + recalculated_table_size);
+    fn set_size(&mut self, len: u64) -> std::io::Result<()> {
+        let len = usize::try_from(len).map_err(std::io::Error::other)?;
+        self.get_mut().resize(len, 0);
+        Ok(())
+    }
+}
 
-    // pck_file.set_len(final_size).context("failed to truncate file")?;
+/// 附带加密密钥的存储。Godot 4 加密导出的 PCK 通过它读写，密钥只属于这一个 PCK，
+/// 同一进程中可以同时处理使用不同密钥的 PCK
+#[derive(Debug)]
+pub struct Keyed<S> {
+    inner: S,
+    key: Option<EncryptionKey>,
+    limits: TableLimits,
+}
 
-    Ok(())
+impl<S> Keyed<S> {
+    pub fn new(inner: S, key: Option<EncryptionKey>) -> Self {
+        Self {
+            inner,
+            key,
+            limits: TableLimits::DEFAULT,
+        }
+    }
+
+    /// 读取这个 PCK 的 entry 表时使用 `limits` 而不是 [`TableLimits::DEFAULT`]
+    pub fn with_limits(mut self, limits: TableLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
 }
 
-/// 删除指定路径的文件 entry，并重写 entry 表与文件数量
-#[allow(dead_code)]
-pub fn delete_files_in_pck(
-    pck_file: &mut File,
-    header: &Header,
-    entry_offsets: &HashMap<String, u64>,
-    paths: Vec<&str>,
-) -> Result<()> {
-    if paths.is_empty() {
-        return Ok(());
+impl Keyed<File> {
+    /// 同一文件的另一个句柄，使用相同的密钥与上限
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        Ok(Self::new(self.inner.try_clone()?, self.key).with_limits(self.limits))
+    }
+}
+
+impl<S: Read> Read for Keyed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
     }
+}
 
-    let mut to_remove = HashSet::new();
-    for path in paths {
-        if !to_remove.insert(path.to_string()) {
-            return Err(anyhow!("重复的路径: {}", path));
+impl<S: Write> Write for Keyed<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: Seek> Seek for Keyed<S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<S: PckStorage> PckStorage for Keyed<S> {
+    fn size(&mut self) -> std::io::Result<u64> {
+        self.inner.size()
+    }
+
+    fn set_size(&mut self, len: u64) -> std::io::Result<()> {
+        self.inner.set_size(len)
+    }
+
+    fn modified(&mut self) -> Option<SystemTime> {
+        self.inner.modified()
+    }
+
+    #[cfg(feature = "mmap")]
+    fn map(&self) -> Option<memmap2::Mmap> {
+        self.inner.map()
+    }
+
+    fn encryption_key(&self) -> Option<&EncryptionKey> {
+        self.key.as_ref()
+    }
+
+    fn table_limits(&self) -> TableLimits {
+        self.limits
+    }
+}
+
+/// 大小与修改时间快照，用于发现其他进程在修改期间写入了同一个 PCK
+#[derive(Debug, Clone, Copy)]
+struct FileSnapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileSnapshot {
+    fn take<S: PckStorage>(file: &mut S) -> Result<Self> {
+        Ok(Self {
+            len: file.size().context("failed to read PCK size")?,
+            modified: file.modified(),
+        })
+    }
+
+    /// 自快照以来没有写入过时，大小与修改时间都应不变
+    fn ensure_unchanged<S: PckStorage>(&self, file: &mut S) -> Result<()> {
+        let now = Self::take(file)?;
+        if now.len != self.len || now.modified != self.modified {
+            bail!(
+                "PCK 文件在修改过程中被其他程序改动（大小 {} -> {}），已中止，未写入 entry 表",
+                self.len,
+                now.len
+            );
         }
+        Ok(())
     }
+}
 
-    let entry_map = build_entry_map(pck_file, entry_offsets)?;
+struct AppendCtx<'a, S: PckStorage> {
+    /// 读取与写入共用，每次读写前显式定位
+    file: &'a mut S,
+    append_pos: u64,
+    chunk_size: usize,
+    verify_moves: MoveVerification,
+    /// 搬移时发现 MD5 与表中记录不一致的 entry
+    move_mismatches: usize,
+    snapshot: FileSnapshot,
+    /// 是否已经追加过数据
+    appended: bool,
+    /// 是否已经把数据写回过原位置
+    overwritten: bool,
+    progress: StageProgress<'a>,
+    /// 每块数据写入前检查
+    cancel: CancelToken,
+    /// 创建时文件内容的映射，搬移数据时代替 reader
+    #[cfg(feature = "mmap")]
+    map: Option<memmap2::Mmap>,
+}
 
-    // 仅删除实际存在的路径，不存在的静默跳过
-    let existing_to_remove: HashSet<String> = to_remove
-        .into_iter()
-        .filter(|p| entry_map.get_by_path(p).is_some())
-        .collect();
+impl<'a, S: PckStorage> AppendCtx<'a, S> {
+    /// 创建读写上下文，定位到文件末尾用于追加；`min_pos` 之前的区域（扩展后的 entry 表）不会被追加数据占用。
+    /// 写入的字节按 [`Self::begin_stage`] 设置的阶段报告给 `progress`
+    fn new(
+        pck_file: &'a mut S,
+        min_pos: u64,
+        io: &IoOptions,
+        progress: &'a dyn Progress,
+    ) -> Result<Self> {
+        let append_pos = pck_file
+            .seek(SeekFrom::End(0))
+            .context("failed to seek to file end")?
+            .max(min_pos);
 
-    if existing_to_remove.is_empty() {
-        // 没有可删除的 entry，直接返回
-        return Ok(());
+        Ok(Self {
+            snapshot: FileSnapshot::take(pck_file)?,
+            #[cfg(feature = "mmap")]
+            map: pck_file.map(),
+            file: pck_file,
+            append_pos,
+            chunk_size: io.chunk_size.max(1),
+            verify_moves: io.verify_moves,
+            move_mismatches: 0,
+            appended: false,
+            overwritten: false,
+            progress: StageProgress::new(progress, io.chunk_size.max(1)),
+            cancel: io.cancel.clone(),
+        })
     }
 
-    let table_start = entry_map
-        .iter_by_table_offset()
-        .next()
-        .map(|e| e.table_offset)
-        .ok_or_else(|| anyhow!("empty entry list"))?;
+    /// 开始一个阶段，之后写入的字节计入该阶段的进度
+    fn begin_stage(&mut self, stage: ProgressStage, total: u64) {
+        self.progress.begin(stage, total);
+    }
 
-    let mut current_offset = table_start;
-    let mut remaining = Vec::new();
-    for record in entry_map.iter_by_table_offset() {
-        if existing_to_remove.contains(&record.path) {
-            continue;
+    fn ensure_not_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(Cancelled.into());
         }
+        Ok(())
+    }
 
-        let binary_size = entry_binary_size(record.entry.path_len);
-        remaining.push(EntryRecord {
-            path: record.path.clone(),
-            table_offset: current_offset,
-            entry: record.entry.clone(),
-        });
-        current_offset += binary_size;
+    /// 检查文件没有被其他进程追加或截断：追加过数据后文件末尾应正好是 append_pos
+    fn ensure_unmodified(&mut self) -> Result<()> {
+        if !self.appended && !self.overwritten {
+            return self.snapshot.ensure_unchanged(self.file);
+        }
+
+        // 原位写入会改变修改时间，之后只能检查大小
+        let expected = if self.appended {
+            self.append_pos
+        } else {
+            self.snapshot.len
+        };
+        let len = self.file.size().context("failed to read PCK size")?;
+        if len != expected {
+            bail!(
+                "PCK 文件在修改过程中被其他程序改动（预期大小 {}，实际 {}），已中止，未写入 entry 表",
+                expected,
+                len
+            );
+        }
+        Ok(())
     }
 
-    if remaining.is_empty() {
-        return Err(anyhow!("删除后没有剩余文件，PCK 至少需要一个 entry"));
+    /// 分块写入一个 entry 的数据，内存占用与 entry 大小无关：`at` 为 None 时追加到末尾，
+    /// 否则写回该区间 `(偏移, 大小)`，数据超出区间时报错。返回起始偏移、明文大小与明文 MD5。
+    /// 加密时先读一遍数据得到块头中的 MD5，再读一遍边加密边写入
+    fn write_entry_data(
+        &mut self,
+        data: EntryData,
+        key: Option<&EncryptionKey>,
+        at: Option<(u64, u64)>,
+        known_md5: Option<[u8; 16]>,
+        path: &str,
+    ) -> Result<(u64, u64, [u8; 16])> {
+        let start = at.map_or(self.append_pos, |(offset, _)| offset);
+        let end = at.map(|(offset, size)| offset + size);
+        let mut cursor = at.map(|(offset, _)| offset);
+        let mut write = |ctx: &mut Self, bytes: &[u8]| -> Result<()> {
+            match &mut cursor {
+                Some(pos) => {
+                    if Some(*pos + bytes.len() as u64) > end {
+                        bail!("{} 在写入过程中变大，超出了原来的数据区间", path);
+                    }
+                    ctx.overwrite_bytes(*pos, bytes, path)?;
+                    *pos += bytes.len() as u64;
+                }
+                None => {
+                    ctx.append_bytes(bytes, path)?;
+                }
+            }
+            Ok(())
+        };
+        // 加密按 AES 分组进行，块大小取 16 的倍数
+        let mut buf = vec![0u8; self.chunk_size.next_multiple_of(16)];
+
+        let Some(key) = key else {
+            let mut reader = data.open()?;
+            // 已预先算好 MD5 时不再边写边算
+            let mut digest = known_md5.is_none().then(md5::Context::new);
+            let mut size = 0u64;
+            loop {
+                let n = read_full(&mut reader, &mut buf)
+                    .with_context(|| format!("无法读取文件: {}", path))?;
+                if let Some(digest) = &mut digest {
+                    digest.consume(&buf[..n]);
+                }
+                write(self, &buf[..n])?;
+                size += n as u64;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            let md5 = match digest {
+                Some(digest) => digest.finalize().0,
+                None => known_md5.unwrap_or_default(),
+            };
+            return Ok((start, size, md5));
+        };
+
+        let size = data.len()?;
+        let md5 = match known_md5 {
+            Some(md5) => md5,
+            None => data.digest().with_context(|| format!("无法读取文件: {}", path))?,
+        };
+        let iv = random_iv();
+        let mut head = Vec::with_capacity(ENCRYPTED_BLOCK_HEADER as usize);
+        head.extend_from_slice(&md5);
+        head.extend_from_slice(&size.to_le_bytes());
+        head.extend_from_slice(&iv);
+        write(self, &head)?;
+
+        let mut cfb = Cfb::new(key, iv);
+        let mut reader = data.open()?;
+        let mut written = 0u64;
+        loop {
+            let n = read_full(&mut reader, &mut buf)
+                .with_context(|| format!("无法读取文件: {}", path))?;
+            let padded = n.next_multiple_of(16);
+            buf[n..padded].fill(0);
+            cfb.apply(&mut buf[..padded], true);
+            write(self, &buf[..padded])?;
+            written += n as u64;
+            if n < buf.len() {
+                break;
+            }
+        }
+        if written != size {
+            bail!("{} 在写入过程中被修改（{} -> {} 字节）", path, size, written);
+        }
+        Ok((start, size, md5))
     }
 
-    let recalculated_table_size = current_offset - table_start;
-    let min_data_offset = remaining
-        .iter()
-        .map(|e| e.entry.offset)
-        .min()
-        .ok_or_else(|| anyhow!("no entries to write after deletion"))?;
+    /// 把数据写回 entry 原来的位置，不改变追加位置
+    fn overwrite_bytes(&mut self, offset: u64, data: &[u8], path: &str) -> Result<()> {
+        self.ensure_not_cancelled()?;
+        self.ensure_unmodified()?;
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .with_context(|| format!("failed to seek writer to old data of {}", path))?;
+        self.file
+            .write_all(data)
+            .with_context(|| format!("failed to overwrite data for {}", path))?;
+        // 回到追加位置，finish 据此确认末尾没有多出数据
+        self.file
+            .seek(SeekFrom::Start(self.append_pos))
+            .with_context(|| format!("failed to seek writer back after overwriting {}", path))?;
+        self.overwritten |= !data.is_empty();
+        self.progress.advance(data.len() as u64);
+        Ok(())
+    }
 
-    if table_start + recalculated_table_size > min_data_offset {
-        return Err(anyhow!(
-            "删除后 entry 表越界：新长度 {} 覆盖数据起点 {}",
-            recalculated_table_size,
-            min_data_offset
-        ));
+    /// 将数据追加到末尾，返回起始偏移
+    fn append_bytes(&mut self, data: &[u8], path: &str) -> Result<u64> {
+        self.ensure_not_cancelled()?;
+        self.ensure_unmodified()?;
+        let offset = self.append_pos;
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .with_context(|| format!("failed to seek writer to append_pos for {}", path))?;
+        self.file
+            .write_all(data)
+            .with_context(|| format!("failed to append data for {}", path))?;
+        self.append_pos += data.len() as u64;
+        // 空数据不会真正写入，文件大小不变
+        self.appended |= !data.is_empty();
+        self.progress.advance(data.len() as u64);
+        Ok(offset)
     }
 
-    let new_file_count: u32 = remaining
-        .len()
-        .try_into()
-        .map_err(|_| anyhow!("文件数量过多，超出 u32 限制"))?;
+    /// 把 entry 的数据搬到末尾并更新偏移；按设置校验（或修正）表中记录的 MD5
+    fn move_entry(&mut self, entry: &mut RawFileEntry, path: &str) -> Result<()> {
+        // 加密数据的 MD5 是明文的，搬移时无法直接校验
+        let verify = self.verify_moves != MoveVerification::Off && !entry.is_encrypted();
+        let mut digest = verify.then(md5::Context::new);
+        let size = entry.stored_size();
+        entry.offset = self.move_range(entry.offset, size, path, digest.as_mut())?;
 
-    let mut new_header = header.clone();
-    new_header.file_count = new_file_count;
+        let Some(actual) = digest.map(|d| d.finalize().0) else {
+            return Ok(());
+        };
+        if actual != entry.md5 {
+            self.move_mismatches += 1;
+            let repair = self.verify_moves == MoveVerification::Repair;
+            diagnose(Diagnostic::MoveDigestMismatch {
+                path: path.to_string(),
+                actual,
+                recorded: entry.md5,
+                repaired: repair,
+            });
+            if repair {
+                entry.md5 = actual;
+            }
+        }
+        Ok(())
+    }
+
+    /// 分块读取指定范围并复制到末尾，返回新偏移；提供 digest 时同时计算 MD5
+    fn move_range(
+        &mut self,
+        offset: u64,
+        size: u64,
+        path: &str,
+        mut digest: Option<&mut md5::Context>,
+    ) -> Result<u64> {
+        let dst = self.append_pos;
+        #[cfg(feature = "mmap")]
+        if let Some(map) = self.map.take() {
+            let range = usize::try_from(offset + size)
+                .ok()
+                .filter(|end| *end <= map.len())
+                .map(|end| offset as usize..end);
+            let result = range.map(|range| {
+                for chunk in map[range].chunks(self.chunk_size) {
+                    if let Some(digest) = digest.as_mut() {
+                        digest.consume(chunk);
+                    }
+                    self.append_bytes(chunk, path)?;
+                }
+                Ok(dst)
+            });
+            self.map = Some(map);
+            // 超出映射范围（如映射之后追加的数据）时回退到 reader
+            if let Some(result) = result {
+                return result;
+            }
+        }
+        let mut buf = vec![0u8; size.min(self.chunk_size as u64) as usize];
+        let mut done = 0u64;
+
+        while done < size {
+            let n = buf.len().min((size - done) as usize);
+            // 写入会移动文件指针，每块都重新定位
+            self.file
+                .seek(SeekFrom::Start(offset + done))
+                .with_context(|| format!("failed to seek data for {}", path))?;
+            self.file
+                .read_exact(&mut buf[..n])
+                .with_context(|| format!("failed to read data for {}", path))?;
+            if let Some(digest) = digest.as_mut() {
+                digest.consume(&buf[..n]);
+            }
+            self.append_bytes(&buf[..n], path)?;
+            done += n as u64;
+        }
+
+        Ok(dst)
+    }
+
+    /// 刷新写缓冲，并在重写 entry 表之前确认写入位置与文件大小仍与预期一致
+    fn finish(&mut self) -> Result<()> {
+        if self.move_mismatches > 0 {
+            diagnose(Diagnostic::MoveDigestMismatches {
+                count: self.move_mismatches,
+            });
+        }
+        self.file.flush().context("failed to flush appended data")?;
+        if self.appended {
+            let pos = self
+                .file
+                .stream_position()
+                .context("failed to get append position")?;
+            if pos != self.append_pos {
+                bail!(
+                    "追加位置异常（预期 {}，实际 {}），已中止，未写入 entry 表",
+                    self.append_pos,
+                    pos
+                );
+            }
+        }
+        self.ensure_unmodified()
+    }
+}
+
+/// 将索引中的 entry 读取进多索引结构（按路径 / 表偏移）；整张表只读取一次，加密的表也只解密一次
+fn build_entry_map<S: PckStorage>(
+    pck_file: &mut S,
+    entry_offsets: &HashMap<String, u64>,
+) -> Result<MultiIndexEntryRecordMap> {
+    let (_, records) = read_table(pck_file)?;
+    let mut entry_map = MultiIndexEntryRecordMap::default();
 
+    for record in records {
+        if entry_offsets.get(&record.path) == Some(&record.table_offset) {
+            entry_map.insert(record);
+        }
+    }
+    if let Some((path, offset)) = entry_offsets
+        .iter()
+        .find(|(path, _)| entry_map.get_by_path(*path).is_none())
     {
-        let mut header_writer = BufWriter::new(pck_file.try_clone()?);
-        header_writer
-            .seek(SeekFrom::Start(0))
-            .context("failed to seek header start")?;
-        new_header
-            .write_le(&mut header_writer)
-            .context("failed to write header")?;
-        header_writer.flush().context("failed to flush header")?;
-    }
-
-    let mut table_writer = BufWriter::new(pck_file.try_clone()?);
-    table_writer
-        .seek(SeekFrom::Start(table_start))
-        .context("failed to seek to entry table start")?;
-    for record in &remaining {
-        record
-            .entry
-            .path_len
-            .write_le(&mut table_writer)
-            .with_context(|| format!("failed to write path_len for {}", record.path))?;
-        table_writer
-            .write_all(&record.entry.path_bytes)
-            .with_context(|| format!("failed to write path_bytes for {}", record.path))?;
-        record
-            .entry
-            .offset
-            .write_le(&mut table_writer)
-            .with_context(|| format!("failed to write offset for {}", record.path))?;
-        record
-            .entry
-            .size
-            .write_le(&mut table_writer)
-            .with_context(|| format!("failed to write size for {}", record.path))?;
-        table_writer
-            .write_all(&record.entry.md5)
-            .with_context(|| format!("failed to write md5 for {}", record.path))?;
+        bail!(
+            "failed to read entry {}: no entry at table offset {}",
+            path,
+            offset
+        );
     }
-    table_writer
-        .flush()
-        .context("failed to flush entry table")?;
 
-    let table_end = table_start + recalculated_table_size;
-    let data_end = remaining
+    Ok(entry_map)
+}
+
+/// 替换或新增的 entry 数据：内存中的字节，或写入时再按块读取的文件
+#[derive(Clone, Copy)]
+enum EntryData<'a> {
+    Bytes(&'a [u8]),
+    File(&'a Path),
+}
+
+impl<'a> EntryData<'a> {
+    fn len(&self) -> Result<u64> {
+        match self {
+            EntryData::Bytes(data) => Ok(data.len() as u64),
+            EntryData::File(path) => Ok(fs::metadata(path)
+                .with_context(|| format!("无法读取文件: {}", path.display()))?
+                .len()),
+        }
+    }
+
+    fn digest(&self) -> Result<[u8; 16]> {
+        Ok(digest_reader(self.open()?)?.0)
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + 'a>> {
+        Ok(match *self {
+            EntryData::Bytes(data) => Box::new(data),
+            EntryData::File(path) => Box::new(
+                File::open(path).with_context(|| format!("无法打开文件: {}", path.display()))?,
+            ),
+        })
+    }
+}
+
+impl<'a> From<&'a PackInput> for EntryData<'a> {
+    fn from(input: &'a PackInput) -> Self {
+        match input {
+            PackInput::File(path) => EntryData::File(path),
+            PackInput::Data(data) => EntryData::Bytes(data),
+        }
+    }
+}
+
+/// 计算 MD5 时每次读取的字节数
+const DIGEST_CHUNK: usize = 1024 * 1024;
+
+/// 分块读取并计算 MD5，内存占用与数据大小无关；返回 MD5 与读到的字节数
+pub fn digest_reader(mut reader: impl Read) -> std::io::Result<([u8; 16], u64)> {
+    let mut digest = md5::Context::new();
+    let mut buf = vec![0u8; DIGEST_CHUNK];
+    let mut size = 0u64;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        digest.consume(&buf[..n]);
+        size += n as u64;
+        if n < buf.len() {
+            return Ok((digest.finalize().0, size));
+        }
+    }
+}
+
+/// 读满 `buf`，返回读到的字节数；小于 `buf.len()` 说明已到末尾
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+type PathDataList<'a> = Vec<(String, EntryData<'a>)>;
+
+fn split_inputs<'a>(
+    files: Vec<(&'a str, EntryData<'a>)>,
+    entry_map: &MultiIndexEntryRecordMap,
+) -> (PathDataList<'a>, PathDataList<'a>) {
+    // 按是否已存在拆分为替换列表和新增列表
+    let mut replace_inputs = Vec::new();
+    let mut add_inputs = Vec::new();
+
+    for (path, data) in files {
+        let path_string = path.to_string();
+        if entry_map.get_by_path(&path_string).is_some() {
+            replace_inputs.push((path_string, data));
+        } else {
+            add_inputs.push((path_string, data));
+        }
+    }
+
+    (replace_inputs, add_inputs)
+}
+
+/// 在写入前并行计算内存中数据与加密 entry（写入前本就要先读一遍）的 MD5；
+/// 未加密的文件输入仍在写入时边读边算，避免多读一遍
+fn digest_in_parallel(
+    replace_inputs: &PathDataList,
+    add_inputs: &PathDataList,
+    entry_map: &MultiIndexEntryRecordMap,
+    jobs: usize,
+) -> Result<HashMap<String, [u8; 16]>> {
+    let encrypted = |path: &String| {
+        entry_map
+            .get_by_path(path)
+            .is_some_and(|record| record.entry.is_encrypted())
+    };
+    let pending: Vec<&(String, EntryData)> = replace_inputs
         .iter()
-        .map(|e| e.entry.offset + e.entry.size)
-        .max()
-        .unwrap_or(table_end);
-    let final_size = table_end.max(data_end);
-    pck_file
-        .set_len(final_size)
-        .context("failed to truncate after deletion")?;
+        .chain(add_inputs)
+        .filter(|(path, data)| matches!(data, EntryData::Bytes(_)) || encrypted(path))
+        .collect();
+    if pending.len() < 2 {
+        return Ok(HashMap::new());
+    }
 
-    Ok(())
+    let digests = Pool::new(jobs).map(&pending, |(path, data)| {
+        let md5 = data
+            .digest()
+            .with_context(|| format!("无法读取文件: {}", path))?;
+        Ok((path.clone(), md5))
+    })?;
+    Ok(digests.into_iter().collect())
+}
+
+fn plan_table(
+    region: TableRegion,
+    header: &Header,
+    entry_map: &MultiIndexEntryRecordMap,
+    add_inputs: &[(String, &[u8])],
+) -> Result<TablePlan> {
+    // 预估新增后表区间范围与下一个表偏移起点；表总是从 header 之后整体重写
+    let table_start = region.start;
+    let layout = header.entry_layout();
+
+    let current_size: u64 = entry_map
+        .iter_by_table_offset()
+        .map(|e| layout.entry_size(e.entry.path_len))
+        .sum();
+
+    let additional: u64 = add_inputs
+        .iter()
+        .map(|(path, _)| {
+            let len = normalized_path_bytes(path).len();
+            layout.entry_size(len as u32)
+        })
+        .sum();
+
+    let table_end_after = table_start + table_extent(header, current_size + additional);
+
+    // 新 entry 的表偏移只用于排序，排在所有原有 entry 之后（原表中被去掉的重复 entry 会留下空位）
+    Ok(TablePlan {
+        region,
+        table_end_after,
+        next_new_table_offset: region.end.max(table_start + current_size),
+    })
+}
+
+/// 规划写入 `files`（路径与数据大小）会做的改动，不修改文件；`overwrite_in_place`
+/// 应与写入时的 [`IoOptions::overwrite_in_place`] 一致
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs::{self, File};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_plan_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/a.txt"), b"old")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let mut file = File::open(dir.join("game.pck"))?;
+/// let (header, index) = pck::read_header_and_index(&mut file)?;
+/// let files = [("res://a.txt", 3), ("res://b.txt", 5)];
+/// let plan = pck::plan_apply(&mut file, &header, &index, &files, false)?;
+/// assert_eq!(plan.added, ["res://b.txt"]);
+/// // 新 entry 让表覆盖了 a.txt 原来的数据，但 a.txt 本身要被替换，不需要搬移
+/// assert!(plan.moves.is_empty());
+/// assert_eq!(plan.new_data_bytes, 8);
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn plan_apply<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: &[(&str, u64)],
+    overwrite_in_place: bool,
+) -> Result<ApplyPlan> {
+    let entry_map = build_entry_map(pck_file, entry_offsets)?;
+    let encrypt = HashSet::new();
+    plan_apply_with(pck_file, header, &entry_map, files, &encrypt, overwrite_in_place)
+}
+
+/// `encrypt` 中的路径写入后加密保存，按加密块的大小规划
+fn plan_apply_with<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_map: &MultiIndexEntryRecordMap,
+    files: &[(&str, u64)],
+    encrypt: &HashSet<&str>,
+    overwrite_in_place: bool,
+) -> Result<ApplyPlan> {
+    let added: Vec<String> = files
+        .iter()
+        .filter(|(path, _)| entry_map.get_by_path(&path.to_string()).is_none())
+        .map(|(path, _)| path.to_string())
+        .collect();
+    let add_inputs: Vec<(String, &[u8])> =
+        added.iter().map(|p| (p.clone(), [].as_slice())).collect();
+    let table = plan_table(table_region(pck_file)?, header, entry_map, &add_inputs)?;
+
+    let mut replaced = Vec::new();
+    let mut overwritten = Vec::new();
+    let mut new_data_bytes = 0;
+    let mut overwrite_bytes = 0;
+    for &(path, size) in files {
+        let Some(record) = entry_map.get_by_path(&path.to_string()) else {
+            new_data_bytes += if encrypt.contains(path) {
+                encrypted_size(size)
+            } else {
+                size
+            };
+            continue;
+        };
+        // 原来加密的 entry 替换后仍加密保存
+        let stored = if record.entry.is_encrypted() || encrypt.contains(path) {
+            encrypted_size(size)
+        } else {
+            size
+        };
+        if overwrite_in_place && fits_in_place(record, stored, entry_map, table.table_end_after) {
+            overwrite_bytes += stored;
+            overwritten.push(path.to_string());
+        } else {
+            new_data_bytes += stored;
+        }
+        replaced.push(path.to_string());
+    }
+    let moves = entry_map
+        .iter_by_table_offset()
+        .filter(|r| !replaced.contains(&r.path))
+        .filter(|r| r.entry.offset < table.table_end_after)
+        .map(|r| MoveTarget {
+            path: r.path.clone(),
+            offset: r.entry.offset,
+            size: r.entry.stored_size(),
+        })
+        .collect();
+
+    Ok(ApplyPlan {
+        table,
+        replaced,
+        overwritten,
+        added,
+        moves,
+        new_data_bytes,
+        overwrite_bytes,
+        archive_size: pck_file.size().context("failed to read PCK size")?,
+    })
+}
+
+/// 新数据（`stored` 为写入的字节数）能否写回 `record` 原来的位置：不大于原数据、
+/// 不在扩展后的 entry 表内，且原数据没有与其他 entry 共用（见 [`share_identical_data`]）
+fn fits_in_place(
+    record: &EntryRecord,
+    stored: u64,
+    entry_map: &MultiIndexEntryRecordMap,
+    table_end_after: u64,
+) -> bool {
+    let start = record.entry.offset;
+    let end = start + record.entry.stored_size();
+    stored <= record.entry.stored_size()
+        && start >= table_end_after
+        && !entry_map.iter_by_table_offset().any(|other| {
+            other.path != record.path
+                && other.entry.offset < end
+                && other.entry.offset + other.entry.stored_size() > start
+        })
+}
+
+/// 解析 PCK 头与 entry 表，返回 header 与 res_path 到该 entry 在表中起始偏移的映射
+pub fn read_header_and_index<S: PckStorage>(
+    file: &mut S,
+) -> Result<(Header, HashMap<String, u64>)> {
+    let (header, records) = read_table(file)?;
+    diagnose(Diagnostic::Header(header.clone()));
+
+    let mut index = HashMap::with_capacity(records.len());
+
+    for record in records {
+        // 重复路径只保留表中最后一个，与旧行为一致
+        if index.insert(record.path.clone(), record.table_offset).is_some() {
+            diagnose(Diagnostic::DuplicateEntry { path: record.path });
+        }
+    }
+
+    Ok((header, index))
+}
+
+/// 按表顺序读取全部 entry（保留重复路径）
+fn read_table<S: PckStorage>(file: &mut S) -> Result<(Header, Vec<EntryRecord>)> {
+    let (key, limits) = (file.encryption_key().copied(), file.table_limits());
+    #[cfg(feature = "mmap")]
+    if let Some(map) = file.map() {
+        return read_table_limited(&mut std::io::Cursor::new(&map[..]), limits, key.as_ref());
+    }
+    read_table_limited(&mut BufReader::new(file), limits, key.as_ref())
+}
+
+/// 从任意可定位的数据源（如远程只读 PCK）读取 header 与全部 entry（按表顺序，保留重复路径）；
+/// entry 表加密时用 `key` 解密，entry 数与路径长度超过 `limits` 时报错
+pub fn read_entries<R: Read + Seek>(
+    mut reader: R,
+    key: Option<&EncryptionKey>,
+    limits: TableLimits,
+) -> Result<(Header, Vec<(String, RawFileEntry)>)> {
+    let (header, records) = read_table_limited(&mut reader, limits, key)?;
+    Ok((header, records.into_iter().map(|r| (r.path, r.entry)).collect()))
+}
+
+/// 一个 entry 的元数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PckEntryInfo {
+    pub path: String,
+    /// 数据在文件中的绝对偏移
+    pub offset: u64,
+    /// 原始数据大小（不含加密带来的额外字节）
+    pub size: u64,
+    pub md5: [u8; 16],
+    /// entry 在表中的位置，与 [`read_header_and_index`] 返回的值相同
+    pub table_offset: u64,
+    pub encrypted: bool,
+}
+
+impl PckEntryInfo {
+    pub fn md5_hex(&self) -> String {
+        hex_digest(&self.md5)
+    }
+}
+
+/// 按表顺序列出全部 entry；重复路径只保留表中最后一个，与 [`read_header_and_index`] 一致
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs::{self, File};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_list_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/readme.txt"), b"hello")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let entries = pck::list_entries(&mut File::open(dir.join("game.pck"))?)?;
+/// assert_eq!(entries[0].path, "res://readme.txt");
+/// assert_eq!(entries[0].size, 5);
+/// assert_eq!(entries[0].md5_hex(), "5d41402abc4b2a76b9719d911017c592");
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn list_entries<S: PckStorage>(pck_file: &mut S) -> Result<Vec<PckEntryInfo>> {
+    let (_, records) = read_table(pck_file)?;
+    let last: HashMap<&str, u64> = records
+        .iter()
+        .map(|r| (r.path.as_str(), r.table_offset))
+        .collect();
+    Ok(records
+        .iter()
+        .filter(|r| last[r.path.as_str()] == r.table_offset)
+        .map(|r| PckEntryInfo {
+            path: r.path.clone(),
+            offset: r.entry.offset,
+            size: r.entry.size,
+            md5: r.entry.md5,
+            table_offset: r.table_offset,
+            encrypted: r.entry.is_encrypted(),
+        })
+        .collect())
+}
+
+fn read_table_limited<R: Read + Seek>(
+    reader: &mut R,
+    limits: TableLimits,
+    key: Option<&EncryptionKey>,
+) -> Result<(Header, Vec<EntryRecord>)> {
+    let header = read_header(reader)?;
+    let layout = header.entry_layout();
+
+    if header.file_count > limits.max_entries {
+        bail!(
+            "这看起来不是有效的 PCK：声称有 {} 个 entry，超过上限 {}（确实是超大的 PCK 时可调高设置中的 [limits] max_entries，或使用 --force-limits-off）",
+            header.file_count,
+            limits.max_entries
+        );
+    }
+    // 每个 entry 至少占 36 字节，文件装不下声称的数量时不必逐个读取
+    let table_start = reader.stream_position()?;
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(table_start))?;
+    let room = stream_len.saturating_sub(table_start) / layout.entry_size(0);
+    if header.file_count as u64 > room {
+        bail!(
+            "这看起来不是有效的 PCK：声称有 {} 个 entry，但剩余的 {} 字节最多只能容纳 {} 个",
+            header.file_count,
+            stream_len.saturating_sub(table_start),
+            room
+        );
+    }
+
+    let mut records = Vec::with_capacity(header.file_count as usize);
+    if header.flags & PACK_DIR_ENCRYPTED != 0 {
+        // 解密后的表偏移仍从文件中的表起点算起，与未加密时的排序方式一致
+        let key = require_key(key, "PCK 的 entry 表")?;
+        let table = decrypt_block(reader, key, "entry 表")?;
+        let mut table = std::io::Cursor::new(table);
+        read_records(&mut table, table_start, &header, limits, &mut records)?;
+    } else {
+        read_records(reader, 0, &header, limits, &mut records)?;
+    }
+    // 带删除标志的 entry 当作不存在；之后重写 entry 表时它们会被直接去掉
+    records.retain(|record| !record.entry.is_removed());
+
+    Ok((header, records))
+}
+
+/// 逐个读取 entry；记录的表偏移为 `base` 加上读取位置
+fn read_records<R: Read + Seek>(
+    reader: &mut R,
+    base: u64,
+    header: &Header,
+    limits: TableLimits,
+    records: &mut Vec<EntryRecord>,
+) -> Result<()> {
+    let layout = header.entry_layout();
+    for i in 0..header.file_count {
+        let position = reader
+            .stream_position()
+            .context("failed to get entry offset")?;
+        let table_offset = base + position;
+        let path_len = u32::read_le(reader).context("failed to read RawFileEntry")?;
+        if path_len > limits.max_path_len {
+            bail!(
+                "这看起来不是有效的 PCK：第 {} 个 entry（表偏移 {}）的路径长 {} 字节，超过上限 {}（可调高设置中的 [limits] max_path_len，或使用 --force-limits-off）",
+                i + 1,
+                table_offset,
+                path_len,
+                limits.max_path_len
+            );
+        }
+        reader.seek(SeekFrom::Start(position))?;
+        let entry: RawFileEntry =
+            RawFileEntry::read_args(reader, layout).context("failed to read RawFileEntry")?;
+
+        let path = entry
+            .path()
+            .with_context(|| "invalid UTF-8 in entry path")?;
+
+        records.push(EntryRecord {
+            path,
+            table_offset,
+            entry,
+        });
+    }
+
+    Ok(())
+}
+
+/// 同一路径在 entry 表中出现多次的情况
+#[derive(Debug, Clone)]
+pub struct DuplicateEntry {
+    pub path: String,
+    /// 保留的 entry 的数据偏移（最新写入，即偏移最大的那个）
+    pub kept_offset: u64,
+    /// 被丢弃的 entry 的数据偏移
+    pub dropped_offsets: Vec<u64>,
+}
+
+/// 找出重复路径，每个路径保留数据偏移最大的 entry；返回保留的记录（按表顺序）与重复信息
+fn split_duplicates(records: Vec<EntryRecord>) -> (Vec<EntryRecord>, Vec<DuplicateEntry>) {
+    let mut newest: HashMap<String, u64> = HashMap::new();
+    for record in &records {
+        let offset = newest.entry(record.path.clone()).or_insert(record.entry.offset);
+        *offset = (*offset).max(record.entry.offset);
+    }
+
+    let mut kept = Vec::with_capacity(newest.len());
+    let mut duplicates: Vec<DuplicateEntry> = Vec::new();
+    let mut kept_paths = HashSet::new();
+
+    for record in records {
+        let newest_offset = newest[&record.path];
+        if record.entry.offset == newest_offset && kept_paths.insert(record.path.clone()) {
+            kept.push(record);
+            continue;
+        }
+
+        match duplicates.iter_mut().find(|d| d.path == record.path) {
+            Some(dup) => dup.dropped_offsets.push(record.entry.offset),
+            None => duplicates.push(DuplicateEntry {
+                path: record.path.clone(),
+                kept_offset: newest_offset,
+                dropped_offsets: vec![record.entry.offset],
+            }),
+        }
+    }
+
+    (kept, duplicates)
+}
+
+/// 只检测重复 entry，不修改文件
+pub fn find_duplicate_entries<S: PckStorage>(pck_file: &mut S) -> Result<Vec<DuplicateEntry>> {
+    let (_, records) = read_table(pck_file)?;
+    Ok(split_duplicates(records).1)
+}
+
+/// 删除重复 entry，每个路径只保留最新写入的一个，并重写 entry 表与 file_count
+pub fn dedupe_entries<S: PckStorage>(pck_file: &mut S) -> Result<Vec<DuplicateEntry>> {
+    let snapshot = FileSnapshot::take(pck_file)?;
+    let (header, records) = read_table(pck_file)?;
+    let region = table_region(pck_file)?;
+
+    let (kept, duplicates) = split_duplicates(records);
+    if duplicates.is_empty() {
+        return Ok(duplicates);
+    }
+
+    // 去重后表只会变短，不会覆盖数据区
+    snapshot.ensure_unchanged(pck_file)?;
+    let records: Vec<&EntryRecord> = kept.iter().collect();
+    write_header_and_table(pck_file, &header, region, &records)?;
+
+    Ok(duplicates)
+}
+
+/// entry 被 [`salvage_table`] 丢弃的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropReason {
+    /// 路径为空、不是有效的 UTF-8 或含有控制字符
+    InvalidPath,
+    /// 数据终点 `end` 超出文件长度，多半是写入数据前被中断
+    DataPastEnd { end: u64, file_len: u64 },
+    /// 数据落在 header 或 entry 表之内
+    DataInTable,
+}
+
+/// 表中一个无法使用的 entry
+#[derive(Debug, Clone)]
+pub struct DroppedEntry {
+    /// 在表中的序号，从 0 开始
+    pub index: u32,
+    pub table_offset: u64,
+    /// 路径无效时为原始字节的有损转换
+    pub path: String,
+    pub reason: DropReason,
+}
+
+/// 逐个检查 entry 表的结果
+#[derive(Debug, Clone)]
+pub struct TableSalvage {
+    /// header 声称的 entry 数
+    pub claimed: u32,
+    /// 可以保留的 entry 数
+    pub kept: usize,
+    pub dropped: Vec<DroppedEntry>,
+    /// 从这个序号起表无法继续解析（路径长度异常或超出文件），之后声称的 entry 全部丢失
+    pub unreadable_from: Option<u32>,
+}
+
+impl TableSalvage {
+    pub fn is_intact(&self) -> bool {
+        self.dropped.is_empty() && self.unreadable_from.is_none()
+    }
+
+    /// 表无法继续解析而丢失的 entry 数
+    pub fn lost(&self) -> u32 {
+        self.unreadable_from.map_or(0, |index| self.claimed - index)
+    }
+}
+
+/// 容错地逐个读取 entry 表：表解析不下去时停下，数据或路径明显无效的 entry 记为丢弃，
+/// 而不是像 [`read_table`] 那样整体报错。返回 header、扫描到的表区间与可以保留的记录
+fn scan_table<S: PckStorage>(
+    pck_file: &mut S,
+) -> Result<(Header, TableRegion, Vec<EntryRecord>, TableSalvage)> {
+    let file_len = pck_file.size().context("failed to read PCK size")?;
+    let limits = pck_file.table_limits();
+    let mut reader = BufReader::new(&mut *pck_file);
+    let header = read_header(&mut reader)?;
+    if header.flags & PACK_DIR_ENCRYPTED != 0 {
+        bail!("entry 表已加密，无法逐个检查 entry；请从备份恢复");
+    }
+    let layout = header.entry_layout();
+
+    let start = reader.stream_position()?;
+    let mut position = start;
+    let mut unreadable_from = None;
+    let mut scanned = Vec::new();
+    for index in 0..header.file_count {
+        reader.seek(SeekFrom::Start(position))?;
+        let entry = u32::read_le(&mut reader)
+            .ok()
+            .filter(|&path_len| {
+                path_len <= limits.max_path_len
+                    && position + layout.entry_size(path_len) <= file_len
+            })
+            .and_then(|_| {
+                reader.seek(SeekFrom::Start(position)).ok()?;
+                RawFileEntry::read_args(&mut reader, layout).ok()
+            });
+        let Some(entry) = entry else {
+            unreadable_from = Some(index);
+            break;
+        };
+        let next = position + layout.entry_size(entry.path_len);
+        scanned.push((index, position, entry));
+        position = next;
+    }
+    let region = TableRegion {
+        start,
+        end: position,
+    };
+
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for (index, table_offset, entry) in scanned {
+        if entry.is_removed() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .ok()
+            .filter(|path| !path.is_empty() && !path.contains(char::is_control));
+        let end = entry.offset.saturating_add(entry.stored_size());
+        let checked = match path {
+            None => Err(DropReason::InvalidPath),
+            Some(_) if end > file_len => Err(DropReason::DataPastEnd { end, file_len }),
+            Some(_) if entry.stored_size() > 0 && entry.offset < region.end => {
+                Err(DropReason::DataInTable)
+            }
+            Some(path) => Ok(path),
+        };
+        match checked {
+            Ok(path) => kept.push(EntryRecord {
+                path,
+                table_offset,
+                entry,
+            }),
+            Err(reason) => dropped.push(DroppedEntry {
+                index,
+                table_offset,
+                path: String::from_utf8_lossy(&entry.path_bytes)
+                    .trim_end_matches('\0')
+                    .to_string(),
+                reason,
+            }),
+        }
+    }
+
+    let salvage = TableSalvage {
+        claimed: header.file_count,
+        kept: kept.len(),
+        dropped,
+        unreadable_from,
+    };
+    Ok((header, region, kept, salvage))
+}
+
+/// 只检查 entry 表能否完整读取、每个 entry 是否可用，不修改文件
+pub fn check_table<S: PckStorage>(pck_file: &mut S) -> Result<TableSalvage> {
+    let (_, _, _, salvage) = scan_table(pck_file)?;
+    Ok(salvage)
+}
+
+/// 丢弃表中无法使用的 entry（见 [`check_table`]），用剩下的 entry 重写 entry 表与 file_count。
+/// 中断的修改留下的表可能指向文件末尾之外或含有错乱的路径长度，修复后其余 entry 可以照常读取
+pub fn salvage_table<S: PckStorage>(pck_file: &mut S) -> Result<TableSalvage> {
+    let snapshot = FileSnapshot::take(pck_file)?;
+    let (header, region, kept, salvage) = scan_table(pck_file)?;
+    if salvage.is_intact() {
+        return Ok(salvage);
+    }
+    if kept.is_empty() {
+        bail!("entry 表中没有可以保留的 entry，无法修复；请从备份恢复");
+    }
+
+    snapshot.ensure_unchanged(pck_file)?;
+    let records: Vec<&EntryRecord> = kept.iter().collect();
+    // 新表只会变短。扫描到的表终点可能是按错乱的长度读出来的，原表剩下的字节保留为填充而不清零，
+    // 以免抹掉其后的数据
+    let region = TableRegion {
+        start: region.start,
+        end: region.start,
+    };
+    write_header_and_table(pck_file, &header, region, &records)?;
+
+    Ok(salvage)
+}
+
+/// 数据完全相同、但存放在不同位置的一组 entry
+#[derive(Debug, Clone)]
+pub struct IdenticalData {
+    pub size: u64,
+    /// 共享后保留的数据偏移（组内最小的偏移）
+    pub kept_offset: u64,
+    /// 组内全部路径（按路径排序）
+    pub paths: Vec<String>,
+    /// 组内不同数据副本的个数
+    pub copies: usize,
+}
+
+impl IdenticalData {
+    /// 共享后不再被引用的字节数
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.copies as u64 - 1)
+    }
+}
+
+/// 找出数据相同的 entry：先按表中记录的大小与 MD5 分组，只对候选组读取数据计算 xxHash64 指纹确认
+pub fn find_identical_data<S: PckStorage>(pck_file: &mut S) -> Result<Vec<IdenticalData>> {
+    let (_, records) = read_table(pck_file)?;
+    let (kept, _) = split_duplicates(records);
+    identical_groups(pck_file, &kept)
+}
+
+fn identical_groups<S: PckStorage>(
+    pck_file: &mut S,
+    records: &[EntryRecord],
+) -> Result<Vec<IdenticalData>> {
+    let mut candidates: HashMap<(u64, [u8; 16], bool), Vec<&EntryRecord>> = HashMap::new();
+    for record in records.iter().filter(|r| r.entry.size > 0) {
+        let key = (record.entry.size, record.entry.md5, record.entry.is_encrypted());
+        candidates.entry(key).or_default().push(record);
+    }
+
+    let mut reader = BufReader::new(&mut *pck_file);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut groups = Vec::new();
+
+    for ((size, _, _), members) in candidates {
+        let offsets: HashSet<u64> = members.iter().map(|r| r.entry.offset).collect();
+        if offsets.len() < 2 {
+            continue;
+        }
+        let stored_size = members[0].entry.stored_size();
+
+        // 指纹 -> 数据偏移；已共享同一偏移的 entry 只计算一次
+        let mut fingerprints: HashMap<u64, u64> = HashMap::new();
+        for &offset in &offsets {
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut hasher = Xxh64::new(0);
+            let mut remaining = stored_size;
+            while remaining > 0 {
+                let n = buf.len().min(remaining as usize);
+                reader
+                    .read_exact(&mut buf[..n])
+                    .with_context(|| format!("failed to read data at {}", offset))?;
+                hasher.update(&buf[..n]);
+                remaining -= n as u64;
+            }
+            fingerprints.insert(offset, hasher.finish());
+        }
+
+        let mut by_fingerprint: HashMap<u64, Vec<&EntryRecord>> = HashMap::new();
+        for record in members {
+            by_fingerprint
+                .entry(fingerprints[&record.entry.offset])
+                .or_default()
+                .push(record);
+        }
+        for same in by_fingerprint.into_values() {
+            let offsets: HashSet<u64> = same.iter().map(|r| r.entry.offset).collect();
+            if offsets.len() < 2 {
+                continue;
+            }
+            let mut paths: Vec<String> = same.iter().map(|r| r.path.clone()).collect();
+            paths.sort();
+            groups.push(IdenticalData {
+                size,
+                kept_offset: *offsets.iter().min().unwrap(),
+                paths,
+                copies: offsets.len(),
+            });
+        }
+    }
+
+    groups.sort_by(|a, b| a.paths.cmp(&b.paths));
+    Ok(groups)
+}
+
+/// 让数据相同的 entry 指向同一份数据并重写 entry 表。
+/// 多余的副本不再被引用，但仍留在文件中（与删除 entry 一样不截断文件），可用 [`compact`] 回收
+pub fn share_identical_data<S: PckStorage>(pck_file: &mut S) -> Result<Vec<IdenticalData>> {
+    let snapshot = FileSnapshot::take(pck_file)?;
+    let (header, records) = read_table(pck_file)?;
+    let (mut kept, duplicates) = split_duplicates(records);
+    if !duplicates.is_empty() {
+        bail!(
+            "PCK 中有 {} 个路径存在重复 entry，请先使用 repair --dedupe-entries 修复",
+            duplicates.len()
+        );
+    }
+    let region = table_region(pck_file)?;
+
+    let groups = identical_groups(pck_file, &kept)?;
+    if groups.is_empty() {
+        return Ok(groups);
+    }
+
+    let shared: HashMap<&str, u64> = groups
+        .iter()
+        .flat_map(|g| g.paths.iter().map(move |p| (p.as_str(), g.kept_offset)))
+        .collect();
+    for record in &mut kept {
+        if let Some(&offset) = shared.get(record.path.as_str()) {
+            record.entry.offset = offset;
+        }
+    }
+
+    // 只修改数据偏移，表的大小不变
+    snapshot.ensure_unchanged(pck_file)?;
+    let records: Vec<&EntryRecord> = kept.iter().collect();
+    write_header_and_table(pck_file, &header, region, &records)?;
+
+    Ok(groups)
+}
+
+/// 整理数据区的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactReport {
+    pub size_before: u64,
+    pub size_after: u64,
+    /// 数据位置发生变化的 entry 数
+    pub moved_entries: usize,
+}
+
+impl CompactReport {
+    /// 回收的字节数
+    pub fn reclaimed(&self) -> u64 {
+        self.size_before - self.size_after
+    }
+}
+
+/// 把仍被 entry 引用的数据按原顺序紧密排列到 entry 表之后，更新偏移并截断文件，
+/// 回收替换、删除后留下的旧数据。
+/// 按偏移从小到大逐段复制，共享或重叠的数据作为一段整体搬移，相对位置不变。
+/// 表中仍引用的数据被覆盖之前先写入新的 entry 表；目标与原位置重叠的段先整段复制到文件末尾之后、
+/// 写表，再搬回目标位置（文件会临时增大该段的大小）。任何时候中断，表中的 entry 都指向完好的数据
+///
+/// ```
+/// use bpb_enhance::pck::{self, IoOptions, PackOptions};
+/// use std::fs::{self, OpenOptions};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_compact_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/a.txt"), b"original")?;
+/// let path = dir.join("game.pck");
+/// pck::pack_directory(dir.join("assets"), &path, &PackOptions::new())?;
+///
+/// let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+/// let (header, index) = pck::read_header_and_index(&mut file)?;
+/// let io = IoOptions::auto(0);
+/// // 比原数据大，追加到文件末尾，原数据成为无用数据
+/// let files = vec![("res://a.txt", b"replacement".as_slice())];
+/// pck::replace_files_in_pck_with(&mut file, &header, &index, files, &io)?;
+///
+/// let report = pck::compact(&mut file, &io)?;
+/// assert_eq!(report.reclaimed(), 8);
+/// let (_, index) = pck::read_header_and_index(&mut file)?;
+/// assert_eq!(pck::read_file_data(&mut file, &index, "res://a.txt")?, b"replacement");
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn compact<S: PckStorage>(pck_file: &mut S, io: &IoOptions) -> Result<CompactReport> {
+    let snapshot = FileSnapshot::take(pck_file)?;
+    let size_before = snapshot.len;
+    let (mut header, mut records) = read_table(pck_file)?;
+    let region = table_region(pck_file)?;
+    let data_start = region.end;
+    // 数据偏移相对 file_base，让它紧跟 entry 表
+    header.set_data_base(data_start);
+
+    // (起点, 终点, 其中的 entry)
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    order.sort_by_key(|&i| records[i].entry.offset);
+    let mut spans: Vec<(u64, u64, Vec<usize>)> = Vec::new();
+    for i in order {
+        let record = &records[i];
+        let start = record.entry.offset;
+        let end = start
+            .checked_add(record.entry.stored_size())
+            .filter(|&end| end <= size_before)
+            .ok_or_else(|| anyhow!("{} 的数据超出文件末尾，请先修复 PCK", record.path))?;
+        if start < data_start {
+            bail!("{} 的数据位于 entry 表之前，无法整理", record.path);
+        }
+        match spans.last_mut() {
+            Some((_, span_end, members)) if start < *span_end => {
+                *span_end = (*span_end).max(end);
+                members.push(i);
+            }
+            _ => spans.push((start, end, vec![i])),
+        }
+    }
+
+    snapshot.ensure_unchanged(pck_file)?;
+    let cancel = &io.cancel;
+    let mut mover = SpanMover {
+        header: &header,
+        region,
+        buf: vec![0u8; io.chunk_size.max(1)],
+        unsaved: Vec::new(),
+    };
+    let mut cursor = data_start;
+    let mut moved_entries = 0;
+    for (start, end, members) in &spans {
+        // 写入已搬移部分的新偏移后停下，PCK 仍然有效
+        if cancel.is_cancelled() {
+            mover.save(pck_file, &records)?;
+            return Err(Cancelled.into());
+        }
+        let len = end - start;
+        if *start != cursor {
+            if cursor + len > *start {
+                // 原位置会在复制中途被覆盖，中断后两处都不完整：经过原文件末尾之后中转
+                mover.move_span(pck_file, &mut records, members, *start, size_before, len)?;
+                mover.move_span(pck_file, &mut records, members, size_before, cursor, len)?;
+            } else {
+                mover.move_span(pck_file, &mut records, members, *start, cursor, len)?;
+            }
+            moved_entries += members.len();
+        }
+        cursor += len;
+    }
+
+    mover.save(pck_file, &records)?;
+    pck_file
+        .set_size(cursor)
+        .context("failed to truncate PCK after compaction")?;
+
+    Ok(CompactReport {
+        size_before,
+        size_after: cursor,
+        moved_entries,
+    })
+}
+
+/// [`compact`] 中逐段搬移数据，并记录哪些旧位置仍被文件中的 entry 表引用
+struct SpanMover<'a> {
+    header: &'a Header,
+    region: TableRegion,
+    buf: Vec<u8>,
+    /// 已搬移、但新偏移还没写入表的数据原来的区间：表中仍指向这里，覆盖之前要先写表
+    unsaved: Vec<(u64, u64)>,
+}
+
+impl SpanMover<'_> {
+    /// 把 `members` 共用的 `len` 字节从 `from` 复制到 `to` 并更新它们的偏移；
+    /// 目标区间与 `from` 不能重叠
+    fn move_span<S: PckStorage>(
+        &mut self,
+        file: &mut S,
+        records: &mut [EntryRecord],
+        members: &[usize],
+        from: u64,
+        to: u64,
+        len: u64,
+    ) -> Result<()> {
+        if self.unsaved.iter().any(|&(start, end)| to < end && start < to + len) {
+            self.save(file, records)?;
+        }
+        copy_within_file(file, from, to, len, &mut self.buf)?;
+        for &i in members {
+            records[i].entry.offset = to + (records[i].entry.offset - from);
+        }
+        self.unsaved.push((from, from + len));
+        Ok(())
+    }
+
+    /// 把当前的偏移写入 entry 表
+    fn save<S: PckStorage>(&mut self, file: &mut S, records: &[EntryRecord]) -> Result<()> {
+        let table: Vec<&EntryRecord> = records.iter().collect();
+        write_header_and_table(file, self.header, self.region, &table)?;
+        self.unsaved.clear();
+        Ok(())
+    }
+}
+
+/// 把 `from` 处的 `len` 字节复制到 `to`，按块从前往后复制；`to` 在 `from` 之前或两段不重叠
+fn copy_within_file<S: PckStorage>(
+    file: &mut S,
+    from: u64,
+    to: u64,
+    len: u64,
+    buf: &mut [u8],
+) -> Result<()> {
+    debug_assert!(to < from || from + len <= to);
+    let mut done = 0;
+    while done < len {
+        let n = buf.len().min((len - done) as usize);
+        file.seek(SeekFrom::Start(from + done))?;
+        file.read_exact(&mut buf[..n])
+            .with_context(|| format!("failed to read data at {}", from + done))?;
+        file.seek(SeekFrom::Start(to + done))?;
+        file.write_all(&buf[..n])
+            .with_context(|| format!("failed to write data at {}", to + done))?;
+        done += n as u64;
+    }
+    Ok(())
+}
+
+/// 重新打包的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepackReport {
+    pub entries: usize,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// 把 `src` 重新写成一个全新的 PCK 到 `out`：新的 header，entry 表按路径排序，数据按同一顺序紧跟其后，
+/// 不含替换、删除留下的旧数据，也不含带删除标志或重复路径的 entry（重复时保留表中最后一个）。
+/// entry 数据（包括加密的数据）原样复制，未加密的数据复制时核对 MD5。
+///
+/// 只要 entry 的内容相同，无论 `src` 之前被修改过多少次，输出的字节都相同；
+/// 唯一的例外是加密的 entry 表，每次加密使用新的 IV
+///
+/// ```
+/// use bpb_enhance::pck::{self, IoOptions, PackOptions};
+/// use std::fs::{self, File, OpenOptions};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_repack_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/a.txt"), b"original")?;
+/// let path = dir.join("game.pck");
+/// pck::pack_directory(dir.join("assets"), &path, &PackOptions::new())?;
+///
+/// let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+/// let (header, index) = pck::read_header_and_index(&mut file)?;
+/// let files = vec![("res://a.txt", b"replacement".as_slice())];
+/// pck::replace_files_in_pck_with(&mut file, &header, &index, files, &IoOptions::auto(0))?;
+///
+/// let mut out = File::create(dir.join("clean.pck"))?;
+/// let report = pck::repack(&mut file, &mut out, &IoOptions::auto(0))?;
+/// assert_eq!(report.size_before - report.size_after, 8);
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn repack<S: PckStorage, W: PckStorage>(
+    src: &mut S,
+    out: &mut W,
+    io: &IoOptions,
+) -> Result<RepackReport> {
+    let size_before = src.size().context("failed to read PCK size")?;
+    let key = src.encryption_key().copied();
+    let (old_header, records) = read_table(src)?;
+    let mut latest: HashMap<String, EntryRecord> = HashMap::with_capacity(records.len());
+    for record in records {
+        latest.insert(record.path.clone(), record);
+    }
+    let mut records: Vec<EntryRecord> = latest.into_values().collect();
+    records.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut header = Header {
+        reserved: [0; 16],
+        file_count: records
+            .len()
+            .try_into()
+            .map_err(|_| anyhow!("文件数量过多，超出 u32 限制"))?,
+        ..old_header.clone()
+    };
+    let layout = header.entry_layout();
+    let plain_len: u64 = records
+        .iter()
+        .map(|r| layout.entry_size(r.entry.path_len))
+        .sum();
+    let encrypted = header.flags & PACK_DIR_ENCRYPTED != 0;
+    let table_len = if encrypted {
+        encrypted_size(plain_len)
+    } else {
+        plain_len
+    };
+    let data_start = header.size() + table_len;
+    header.set_data_base(data_start);
+
+    // 按新顺序分配偏移；原来共享同一段数据的 entry 仍共享
+    let mut placed: HashMap<(u64, u64), u64> = HashMap::new();
+    let mut copies = Vec::new();
+    let mut cursor = data_start;
+    for record in &mut records {
+        let stored = record.entry.stored_size();
+        let end = record.entry.offset.checked_add(stored);
+        if end.is_none_or(|end| end > size_before) {
+            bail!("{} 的数据超出文件末尾，请先修复 PCK", record.path);
+        }
+        let key = (record.entry.offset, stored);
+        let offset = match placed.get(&key) {
+            Some(&offset) => offset,
+            None => {
+                copies.push((record.path.clone(), record.entry.clone(), cursor));
+                placed.insert(key, cursor);
+                cursor += stored;
+                cursor - stored
+            }
+        };
+        record.entry.offset = offset;
+    }
+
+    let mut writer = BufWriter::with_capacity(io.write_buffer, &mut *out);
+    header
+        .write_le(&mut writer)
+        .context("failed to write header")?;
+    let mut table = std::io::Cursor::new(Vec::new());
+    let refs: Vec<&EntryRecord> = records.iter().collect();
+    write_entries(&mut table, header.entry_layout(), &refs)?;
+    let table = table.into_inner();
+    if encrypted {
+        let key = require_key(key.as_ref(), "PCK 的 entry 表")?;
+        writer.write_all(&encrypt_block(key, &table))
+    } else {
+        writer.write_all(&table)
+    }
+    .context("failed to write entry table")?;
+
+    let mut reader = BufReader::with_capacity(io.read_buffer, &mut *src);
+    let mut buf = vec![0u8; io.chunk_size.max(1)];
+    for (path, entry, _) in &copies {
+        reader
+            .seek(SeekFrom::Start(entry.offset))
+            .with_context(|| format!("无法定位文件数据: {}", path))?;
+        let mut data = (&mut reader).take(entry.stored_size());
+        let mut digest = (!entry.is_encrypted()).then(md5::Context::new);
+        let mut copied = 0u64;
+        loop {
+            let n = data
+                .read(&mut buf)
+                .with_context(|| format!("无法读取文件数据: {}", path))?;
+            if n == 0 {
+                break;
+            }
+            if let Some(digest) = &mut digest {
+                digest.consume(&buf[..n]);
+            }
+            writer
+                .write_all(&buf[..n])
+                .with_context(|| format!("failed to write data for {}", path))?;
+            copied += n as u64;
+        }
+        if copied != entry.stored_size() {
+            bail!("文件数据不完整: {}（PCK 可能已被截断）", path);
+        }
+        if digest.is_some_and(|digest| digest.finalize().0 != entry.md5) {
+            bail!("{} 的数据与 entry 表中的 MD5 不一致，请先用 verify 检查", path);
+        }
+    }
+    writer.flush().context("failed to flush PCK")?;
+    drop(writer);
+    out.set_size(cursor).context("failed to truncate PCK")?;
+
+    Ok(RepackReport {
+        entries: records.len(),
+        size_before,
+        size_after: cursor,
+    })
+}
+
+/// Godot 4 导出加密使用的 AES-256 密钥，即导出时的 script encryption key（64 个十六进制字符）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl std::str::FromStr for EncryptionKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err("expected 64 hexadecimal characters (a 256-bit key)".to_string());
+        }
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+        }
+        Ok(Self(key))
+    }
+}
+
+fn require_key<'a>(key: Option<&'a EncryptionKey>, what: &str) -> Result<&'a EncryptionKey> {
+    key.ok_or_else(|| anyhow!("{} 已加密，需要提供导出时使用的加密密钥", what))
+}
+
+/// 加密块头：明文 MD5(16) + 明文长度(u64) + IV(16)
+const ENCRYPTED_BLOCK_HEADER: u64 = 40;
+
+/// 明文长度为 `plain_len` 的加密块在文件中占用的字节数（密文按 AES 分组补齐）
+fn encrypted_size(plain_len: u64) -> u64 {
+    ENCRYPTED_BLOCK_HEADER + plain_len.next_multiple_of(16)
+}
+
+/// AES-256-CFB128，与 Godot 的 FileAccessEncrypted 相同
+fn aes_cfb(key: &EncryptionKey, iv: [u8; 16], data: &mut [u8], encrypt: bool) {
+    Cfb::new(key, iv).apply(data, encrypt);
+}
+
+/// 可分段处理的 CFB128 状态；除最后一段外，每段长度须为 16 的倍数
+struct Cfb {
+    cipher: aes::Aes256,
+    register: [u8; 16],
+}
+
+impl Cfb {
+    fn new(key: &EncryptionKey, iv: [u8; 16]) -> Self {
+        use aes::cipher::KeyInit;
+
+        Self {
+            cipher: aes::Aes256::new(&key.0.into()),
+            register: iv,
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8], encrypt: bool) {
+        use aes::cipher::BlockEncrypt;
+
+        for chunk in data.chunks_mut(16) {
+            let mut stream = self.register.into();
+            self.cipher.encrypt_block(&mut stream);
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                let input = *byte;
+                *byte ^= stream[i];
+                self.register[i] = if encrypt { *byte } else { input };
+            }
+        }
+    }
+}
+
+/// 读取并解密一个加密块（不带 magic），用块头中的 MD5 确认密钥正确
+fn decrypt_block<R: Read>(reader: &mut R, key: &EncryptionKey, what: &str) -> Result<Vec<u8>> {
+    let mut head = [0u8; ENCRYPTED_BLOCK_HEADER as usize];
+    reader
+        .read_exact(&mut head)
+        .with_context(|| format!("无法读取 {} 的加密块头", what))?;
+    let md5: [u8; 16] = head[..16].try_into().unwrap();
+    let plain_len = u64::from_le_bytes(head[16..24].try_into().unwrap());
+    let iv: [u8; 16] = head[24..].try_into().unwrap();
+
+    let padded = plain_len.next_multiple_of(16);
+    let mut data = Vec::new();
+    reader.take(padded).read_to_end(&mut data)?;
+    if (data.len() as u64) < padded {
+        bail!(
+            "{} 的加密数据不完整：需要 {} 字节，只有 {} 字节",
+            what,
+            padded,
+            data.len()
+        );
+    }
+    aes_cfb(key, iv, &mut data, false);
+    data.truncate(plain_len as usize);
+    if md5::compute(&data).0 != md5 {
+        bail!("{} 解密后 MD5 不匹配，加密密钥不正确", what);
+    }
+    Ok(data)
+}
+
+/// 按 Godot 的加密块格式加密 `data`，每次使用新的 IV
+fn encrypt_block(key: &EncryptionKey, data: &[u8]) -> Vec<u8> {
+    let iv = random_iv();
+    let mut cipher = data.to_vec();
+    cipher.resize(data.len().next_multiple_of(16), 0);
+    aes_cfb(key, iv, &mut cipher, true);
+
+    let mut block = Vec::with_capacity(ENCRYPTED_BLOCK_HEADER as usize + cipher.len());
+    block.extend_from_slice(&md5::compute(data).0);
+    block.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    block.extend_from_slice(&iv);
+    block.extend_from_slice(&cipher);
+    block
+}
+
+/// CFB 只要求 IV 不重复，不要求不可预测：用随机种子的哈希混合时间与计数器
+fn random_iv() -> [u8; 16] {
+    use std::hash::{BuildHasher, Hasher};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let state = std::collections::hash_map::RandomState::new();
+    let mut iv = [0u8; 16];
+    for half in iv.chunks_mut(8) {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos()),
+        );
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    iv
+}
+
+/// 流式 xxHash64，用于快速比较 entry 数据
+struct Xxh64 {
+    seed: u64,
+    acc: [u64; 4],
+    buffer: [u8; 32],
+    buffered: usize,
+    total_len: u64,
+}
+
+impl Xxh64 {
+    const P1: u64 = 0x9E37_79B1_85EB_CA87;
+    const P2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+    const P3: u64 = 0x1656_67B1_9E37_79F9;
+    const P4: u64 = 0x85EB_CA77_C2B2_AE63;
+    const P5: u64 = 0x27D4_EB2F_1656_67C5;
+
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            acc: [
+                seed.wrapping_add(Self::P1).wrapping_add(Self::P2),
+                seed.wrapping_add(Self::P2),
+                seed,
+                seed.wrapping_sub(Self::P1),
+            ],
+            buffer: [0; 32],
+            buffered: 0,
+            total_len: 0,
+        }
+    }
+
+    fn round(acc: u64, input: u64) -> u64 {
+        acc.wrapping_add(input.wrapping_mul(Self::P2))
+            .rotate_left(31)
+            .wrapping_mul(Self::P1)
+    }
+
+    fn merge(hash: u64, acc: u64) -> u64 {
+        (hash ^ Self::round(0, acc))
+            .wrapping_mul(Self::P1)
+            .wrapping_add(Self::P4)
+    }
+
+    fn stripe(&mut self, block: &[u8]) {
+        for (acc, lane) in self.acc.iter_mut().zip(block.chunks_exact(8)) {
+            *acc = Self::round(*acc, u64::from_le_bytes(lane.try_into().unwrap()));
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        if self.buffered > 0 {
+            let take = (32 - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < 32 {
+                return;
+            }
+            let block = self.buffer;
+            self.stripe(&block);
+            self.buffered = 0;
+        }
+
+        let mut blocks = data.chunks_exact(32);
+        for block in &mut blocks {
+            self.stripe(block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hash = if self.total_len >= 32 {
+            let [a, b, c, d] = self.acc;
+            let mut hash = a
+                .rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18));
+            for acc in self.acc {
+                hash = Self::merge(hash, acc);
+            }
+            hash
+        } else {
+            self.seed.wrapping_add(Self::P5)
+        };
+        hash = hash.wrapping_add(self.total_len);
+
+        let mut rest = &self.buffer[..self.buffered];
+        while rest.len() >= 8 {
+            let lane = u64::from_le_bytes(rest[..8].try_into().unwrap());
+            hash = (hash ^ Self::round(0, lane))
+                .rotate_left(27)
+                .wrapping_mul(Self::P1)
+                .wrapping_add(Self::P4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let lane = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+            hash = (hash ^ lane.wrapping_mul(Self::P1))
+                .rotate_left(23)
+                .wrapping_mul(Self::P2)
+                .wrapping_add(Self::P3);
+            rest = &rest[4..];
+        }
+        for &byte in rest {
+            hash = (hash ^ (byte as u64).wrapping_mul(Self::P5))
+                .rotate_left(11)
+                .wrapping_mul(Self::P1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(Self::P2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(Self::P3);
+        hash ^ (hash >> 32)
+    }
+}
+
+/// 重写 header 的 file_count 以及从表起点开始的完整 entry 表；
+/// 新表比原表短时把原表剩下的部分清零，避免留下看似有效的旧 entry。
+/// 与文件中现有内容相同的部分不再写入，只追加数据时不会改动 header，
+/// 对磁盘镜像、备份与同步软件更友好
+fn write_header_and_table<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    region: TableRegion,
+    records: &[&EntryRecord],
+) -> Result<()> {
+    let new_file_count: u32 = records
+        .len()
+        .try_into()
+        .map_err(|_| anyhow!("文件数量过多，超出 u32 限制"))?;
+
+    let mut new_header = header.clone();
+    new_header.file_count = new_file_count;
+    let mut header_bytes = std::io::Cursor::new(Vec::new());
+    new_header
+        .write_le(&mut header_bytes)
+        .context("failed to write header")?;
+    let header_bytes = header_bytes.into_inner();
+
+    let mut plain = std::io::Cursor::new(Vec::new());
+    write_entries(&mut plain, header.entry_layout(), records)?;
+    let plain = plain.into_inner();
+
+    let key = pck_file.encryption_key().copied();
+    let mut reader = BufReader::new(&mut *pck_file);
+    let header_unchanged = reads_as(&mut reader, 0, &header_bytes);
+    let table_bytes = if header.flags & PACK_DIR_ENCRYPTED != 0 {
+        let key = require_key(key.as_ref(), "PCK 的 entry 表")?;
+        // 每次加密使用新的 IV，只能比较解密后的内容
+        let unchanged = reader.seek(SeekFrom::Start(region.start)).is_ok()
+            && decrypt_block(&mut reader, key, "PCK 的 entry 表").is_ok_and(|old| old == plain);
+        if unchanged && header_unchanged {
+            return Ok(());
+        }
+        (!unchanged).then(|| encrypt_block(key, &plain))
+    } else {
+        let mut table = plain;
+        // 新表比原表短时把原表剩下的部分清零
+        let old_len = (region.end - region.start) as usize;
+        if table.len() < old_len {
+            table.resize(old_len, 0);
+        }
+        (!reads_as(&mut reader, region.start, &table)).then_some(table)
+    };
+
+    // header 与表相邻，都有改动时合并为一次写入：header 中的 file_base、file_count
+    // 与表中的偏移不会只落盘一半
+    let (start, mut bytes) = if header_unchanged {
+        (region.start, Vec::new())
+    } else {
+        debug_assert_eq!(header_bytes.len() as u64, region.start);
+        (0, header_bytes)
+    };
+    if let Some(mut table) = table_bytes {
+        let table_end = region.start + table.len() as u64;
+        if table_end < region.end {
+            table.resize((region.end - region.start) as usize, 0);
+        }
+        bytes.extend_from_slice(&table);
+    }
+    if !bytes.is_empty() {
+        pck_file
+            .seek(SeekFrom::Start(start))
+            .context("failed to seek to entry table start")?;
+        pck_file
+            .write_all(&bytes)
+            .context("failed to write entry table")?;
+    }
+    pck_file.flush().context("failed to flush entry table")?;
+
+    Ok(())
+}
+
+/// 文件中 `offset` 处是否正好是 `expected`
+fn reads_as<R: Read + Seek>(reader: &mut R, offset: u64, expected: &[u8]) -> bool {
+    let mut existing = vec![0u8; expected.len()];
+    reader.seek(SeekFrom::Start(offset)).is_ok()
+        && reader.read_exact(&mut existing).is_ok()
+        && existing == expected
+}
+
+/// 按顺序写出 entry 表；数据偏移按 `layout` 换算回相对 file_base 的偏移
+fn write_entries<W: Write + Seek>(
+    table_writer: &mut W,
+    layout: EntryLayout,
+    records: &[&EntryRecord],
+) -> Result<()> {
+    for record in records {
+        // 手动写入每个字段以确保正确性
+        record
+            .entry
+            .path_len
+            .write_le(table_writer)
+            .with_context(|| format!("failed to write path_len for {}", record.path))?;
+        table_writer
+            .write_all(&record.entry.path_bytes)
+            .with_context(|| format!("failed to write path_bytes for {}", record.path))?;
+        let offset = record
+            .entry
+            .offset
+            .checked_sub(layout.file_base)
+            .ok_or_else(|| anyhow!("entry {} 的数据位于 file_base 之前", record.path))?;
+        offset
+            .write_le(table_writer)
+            .with_context(|| format!("failed to write offset for {}", record.path))?;
+        record
+            .entry
+            .size
+            .write_le(table_writer)
+            .with_context(|| format!("failed to write size for {}", record.path))?;
+        table_writer
+            .write_all(&record.entry.md5)
+            .with_context(|| format!("failed to write md5 for {}", record.path))?;
+        if layout.has_flags {
+            record
+                .entry
+                .flags
+                .write_le(table_writer)
+                .with_context(|| format!("failed to write flags for {}", record.path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 按 res 路径读取文件数据
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs::{self, File};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_read_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/readme.txt"), b"hello")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let mut file = File::open(dir.join("game.pck"))?;
+/// let (_, index) = pck::read_header_and_index(&mut file)?;
+/// assert_eq!(pck::read_file_data(&mut file, &index, "res://readme.txt")?, b"hello");
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn read_file_data<S: PckStorage>(
+    pck_file: &mut S,
+    entry_offsets: &HashMap<String, u64>,
+    res_path: &str,
+) -> Result<Vec<u8>> {
+    let entry_offset = entry_offsets
+        .get(res_path)
+        .ok_or_else(|| anyhow!("PCK 中不存在文件: {}", res_path))?;
+
+    let (key, limits) = (pck_file.encryption_key().copied(), pck_file.table_limits());
+    let mut reader = BufReader::new(&mut *pck_file);
+    let header = read_header(&mut reader)?;
+    let entry = read_entry_at(&mut reader, &header, *entry_offset, key.as_ref(), limits)
+        .with_context(|| format!("无法读取文件 entry: {}", res_path))?;
+
+    read_entry_data(&mut reader, &entry, res_path, key.as_ref())
+}
+
+/// 计算 entry 当前数据的 MD5：未加密的数据分块读取，不整个载入内存；加密的数据解密后计算
+pub fn read_file_md5<S: PckStorage>(
+    pck_file: &mut S,
+    entry_offsets: &HashMap<String, u64>,
+    res_path: &str,
+) -> Result<[u8; 16]> {
+    let entry_offset = entry_offsets
+        .get(res_path)
+        .ok_or_else(|| anyhow!("PCK 中不存在文件: {}", res_path))?;
+
+    let (key, limits) = (pck_file.encryption_key().copied(), pck_file.table_limits());
+    let mut reader = BufReader::new(&mut *pck_file);
+    let header = read_header(&mut reader)?;
+    let entry = read_entry_at(&mut reader, &header, *entry_offset, key.as_ref(), limits)
+        .with_context(|| format!("无法读取文件 entry: {}", res_path))?;
+    if entry.is_encrypted() {
+        let data = read_entry_data(&mut reader, &entry, res_path, key.as_ref())?;
+        return Ok(md5::compute(data).0);
+    }
+
+    reader
+        .seek(SeekFrom::Start(entry.offset))
+        .with_context(|| format!("无法定位文件数据: {}", res_path))?;
+    let (md5, size) = digest_reader(reader.take(entry.size))
+        .with_context(|| format!("无法读取文件数据: {}", res_path))?;
+    if size != entry.size {
+        bail!("{} 的数据不完整：应有 {} 字节，只读到 {} 字节", res_path, entry.size, size);
+    }
+    Ok(md5)
+}
+
+/// 读取 entry 的数据，加密的 entry 用 `key` 解密后返回
+fn read_entry_data<R: Read + Seek>(
+    reader: &mut R,
+    entry: &RawFileEntry,
+    res_path: &str,
+    key: Option<&EncryptionKey>,
+) -> Result<Vec<u8>> {
+    reader
+        .seek(SeekFrom::Start(entry.offset))
+        .with_context(|| format!("无法定位文件数据: {}", res_path))?;
+    if entry.is_encrypted() {
+        let key = require_key(key, res_path)?;
+        let mut stored = reader.take(entry.stored_size());
+        return decrypt_block(&mut stored, key, res_path);
+    }
+
+    let mut data = vec![0u8; entry.size as usize];
+    reader
+        .read_exact(&mut data)
+        .with_context(|| format!("无法读取文件数据: {}", res_path))?;
+
+    Ok(data)
+}
+
+/// 按顺序读取 entry 数据的读取器：未加密的数据直接从 `reader` 流式读取，
+/// 加密的数据用 `key` 解密后从内存读取
+pub fn entry_reader<'a, R: Read + Seek>(
+    reader: &'a mut R,
+    entry: &RawFileEntry,
+    res_path: &str,
+    key: Option<&EncryptionKey>,
+) -> Result<Box<dyn Read + 'a>> {
+    if entry.is_encrypted() {
+        let data = read_entry_data(reader, entry, res_path, key)?;
+        return Ok(Box::new(std::io::Cursor::new(data)));
+    }
+    reader
+        .seek(SeekFrom::Start(entry.offset))
+        .with_context(|| format!("无法定位文件数据: {}", res_path))?;
+    Ok(Box::new(reader.take(entry.size)))
+}
+
+/// 引擎为资源生成的配对文件后缀：`.import` 指向导入后的数据，`.remap` 指向编译后的脚本或场景
+const COMPANION_SUFFIXES: [&str; 2] = [".import", ".remap"];
+
+/// 资源与其 `.import`/`.remap` 配对文件的关联
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportPair {
+    /// 原资源路径；导出的游戏中原资源本身常常不在 PCK 里
+    pub source: String,
+    pub companion: String,
+    /// 配对文件 `[remap]` 中指向、且存在于 PCK 中的文件
+    pub targets: Vec<String>,
+}
+
+/// 不在 PCK 中、但有 `.import`/`.remap` 配对文件的路径（如 `res://icon.png`）实际由引擎加载的文件，
+/// 即配对文件 `[remap]` 中指向且存在于 PCK 中的文件。路径本身存在或没有配对文件时返回 None
+pub fn resolve_remap<S: PckStorage>(
+    pck_file: &mut S,
+    entry_offsets: &HashMap<String, u64>,
+    res_path: &str,
+) -> Result<Option<Vec<String>>> {
+    if entry_offsets.contains_key(res_path) {
+        return Ok(None);
+    }
+    for suffix in COMPANION_SUFFIXES {
+        let companion = format!("{}{}", res_path, suffix);
+        if !entry_offsets.contains_key(&companion) {
+            continue;
+        }
+        let content = read_file_data(pck_file, entry_offsets, &companion)?;
+        let targets: Vec<String> = remap_targets(&content)
+            .into_iter()
+            .filter(|target| entry_offsets.contains_key(target))
+            .collect();
+        if !targets.is_empty() {
+            return Ok(Some(targets));
+        }
+    }
+    Ok(None)
+}
+
+/// [`extract_files_with`] 的选项
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// 解密加密的 entry 表与 entry 数据的密钥
+    pub encryption_key: Option<EncryptionKey>,
+    /// 并行导出的线程数
+    pub jobs: usize,
+    /// 解析 entry 表时的上限
+    pub limits: TableLimits,
+    /// 每导出一个文件前检查，取消后返回 [`Cancelled`]
+    pub cancel: CancelToken,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            encryption_key: None,
+            jobs: logical_cores(),
+            limits: TableLimits::DEFAULT,
+            cancel: CancelToken::default(),
+        }
+    }
+}
+
+/// [`extract_files`] 的结果
+#[derive(Debug, Clone, Default)]
+pub struct Extraction {
+    pub files: usize,
+    pub pairs: Vec<ImportPair>,
+}
+
+/// `.import`/`.remap` 文件 `[remap]` 段中 `path`、`path.s3tc` 等键指向的 res 路径
+pub fn remap_targets(content: &[u8]) -> Vec<String> {
+    let content = String::from_utf8_lossy(content);
+    let mut in_remap = false;
+    let mut targets = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_remap = line == "[remap]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if !in_remap || (key != "path" && !key.starts_with("path.")) {
+            continue;
+        }
+        let value = value.trim().trim_matches('"');
+        if value.starts_with("res://") && !targets.iter().any(|t| t == value) {
+            targets.push(value.to_string());
+        }
+    }
+    targets
+}
+
+/// `pattern` 是否含通配符（`*`、`?`、`[`）；不含时按普通路径处理
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// 按通配符匹配 entry 路径：`*` 与 `?` 不跨越 `/`，单独成段的 `**` 匹配任意层目录（含零层），
+/// `[abc]`、`[a-z]`、`[!abc]` 匹配一个字符。两边都可省略 `res://` 前缀
+///
+/// ```
+/// use bpb_enhance::pck::glob_match;
+///
+/// assert!(glob_match("res://Core/*.gde", "res://Core/Game.gde"));
+/// assert!(!glob_match("res://Core/*.gde", "res://Core/UI/Menu.gde"));
+/// assert!(glob_match("**/*.png", "res://UI/icons/close.png"));
+/// ```
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let relative = |path: &'_ str| {
+        path.strip_prefix("res://")
+            .unwrap_or(path)
+            .trim_start_matches('/')
+            .split('/')
+            .map(|part| part.chars().collect::<Vec<char>>())
+            .collect::<Vec<_>>()
+    };
+    match_segments(&relative(pattern), &relative(path))
+}
+
+fn match_segments(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first[..] == ['*', '*'] => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(name, path)| {
+            match_segment(first, name) && match_segments(rest, path)
+        }),
+    }
+}
+
+fn match_segment(pattern: &[char], name: &[char]) -> bool {
+    if let Some(rest) = pattern.strip_prefix(&['*']) {
+        return (0..=name.len()).any(|skip| match_segment(rest, &name[skip..]));
+    }
+    let (Some((&p, pattern)), Some((&c, name))) = (pattern.split_first(), name.split_first())
+    else {
+        return pattern.is_empty() && name.is_empty();
+    };
+    match p {
+        '?' => match_segment(pattern, name),
+        '[' => match match_class(pattern, c) {
+            Some((matched, len)) => matched && match_segment(&pattern[len..], name),
+            None => c == '[' && match_segment(pattern, name),
+        },
+        _ => p == c && match_segment(pattern, name),
+    }
+}
+
+/// `[` 之后的字符类：返回 `c` 是否匹配与字符类（含结尾的 `]`）的长度；没有结尾的 `]` 时返回 None，
+/// `[` 按普通字符处理
+fn match_class(class: &[char], c: char) -> Option<(bool, usize)> {
+    let negated = matches!(class.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    let mut i = start;
+    let mut matched = false;
+    while let Some(&first) = class.get(i) {
+        // 紧跟在 `[`（或 `[!`）后的 `]` 是普通字符
+        if first == ']' && i > start {
+            return Some((matched != negated, i + 1));
+        }
+        match (class.get(i + 1), class.get(i + 2)) {
+            (Some('-'), Some(&last)) if last != ']' => {
+                matched |= (first..=last).contains(&c);
+                i += 3;
+            }
+            _ => {
+                matched |= first == c;
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+/// 把 `paths` 指定的文件（为空时为全部文件）按 `res://` 下的目录结构写到 `out_dir`。
+/// 路径可省略 `res://` 前缀，也可以是通配符模式（见 [`glob_match`]）；重复的 entry 取表中最后一个。
+///
+/// 资源的 `.import`/`.remap` 配对文件及其指向的导入数据会一并导出，关联记录在返回值中，
+/// 否则修改后再注入时引擎仍会按配对文件加载旧数据
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs;
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_extract_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets/Core"))?;
+/// fs::write(dir.join("assets/Core/Game.gde"), b"extends Node")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let out = dir.join("out");
+/// let extracted = pck::extract_files(dir.join("game.pck"), &["Core/Game.gde"], &out)?;
+/// assert_eq!(extracted.files, 1);
+/// assert_eq!(fs::read(dir.join("out/Core/Game.gde"))?, b"extends Node");
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn extract_files(
+    pck_path: impl AsRef<Path>,
+    paths: &[&str],
+    out_dir: impl AsRef<Path>,
+) -> Result<Extraction> {
+    extract_files_with(pck_path, paths, out_dir, &ExtractOptions::default())
+}
+
+/// 同 [`extract_files`]，使用指定的密钥与线程数
+pub fn extract_files_with(
+    pck_path: impl AsRef<Path>,
+    paths: &[&str],
+    out_dir: impl AsRef<Path>,
+    options: &ExtractOptions,
+) -> Result<Extraction> {
+    let (pck_path, out_dir) = (pck_path.as_ref(), out_dir.as_ref());
+    let key = options.encryption_key.as_ref();
+    let file =
+        File::open(pck_path).with_context(|| format!("无法打开文件: {}", pck_path.display()))?;
+    let mut reader = BufReader::new(file);
+    let (_, records) = read_table_limited(&mut reader, options.limits, key)?;
+    let entries: HashMap<String, RawFileEntry> =
+        records.into_iter().map(|r| (r.path, r.entry)).collect();
+
+    let mut selected: Vec<String> = if paths.is_empty() {
+        entries.keys().cloned().collect()
+    } else {
+        let mut selected = Vec::new();
+        for path in paths {
+            let res_path = match path.strip_prefix("res://") {
+                Some(_) => path.to_string(),
+                None => format!("res://{}", path.trim_start_matches('/')),
+            };
+            // 文件名本身含 `[` 等字符时按原样匹配
+            if !is_glob(path) || entries.contains_key(&res_path) {
+                selected.push(res_path);
+                continue;
+            }
+            let mut matched: Vec<String> = entries
+                .keys()
+                .filter(|entry| glob_match(path, entry))
+                .cloned()
+                .collect();
+            if matched.is_empty() {
+                bail!("PCK 中没有匹配 {} 的文件", path);
+            }
+            matched.sort();
+            selected.append(&mut matched);
+        }
+        selected
+    };
+
+    // 选中配对文件时也带上原资源；原资源不在 PCK 中但有配对文件时视为存在
+    let mut sources: Vec<String> = Vec::new();
+    for res_path in &selected {
+        let source = COMPANION_SUFFIXES
+            .iter()
+            .find_map(|suffix| res_path.strip_suffix(suffix))
+            .unwrap_or(res_path);
+        let has_companion = COMPANION_SUFFIXES
+            .iter()
+            .any(|suffix| entries.contains_key(&format!("{}{}", source, suffix)));
+        if !entries.contains_key(res_path) && !has_companion {
+            bail!("PCK 中不存在文件: {}", res_path);
+        }
+        sources.push(source.to_string());
+    }
+    sources.sort();
+    sources.dedup();
+
+    let mut pairs = Vec::new();
+    for source in &sources {
+        for suffix in COMPANION_SUFFIXES {
+            let companion = format!("{}{}", source, suffix);
+            let Some(entry) = entries.get(&companion) else {
+                continue;
+            };
+            let content = read_entry_data(&mut reader, entry, &companion, key)?;
+            let targets: Vec<String> = remap_targets(&content)
+                .into_iter()
+                .filter(|target| entries.contains_key(target))
+                .collect();
+            selected.push(companion.clone());
+            selected.extend(targets.iter().cloned());
+            pairs.push(ImportPair {
+                source: source.clone(),
+                companion,
+                targets,
+            });
+        }
+    }
+    selected.retain(|path| entries.contains_key(path));
+    selected.sort();
+    selected.dedup();
+
+    // 先检查全部路径，避免写出一部分后才发现错误
+    let mut targets = Vec::with_capacity(selected.len());
+    for res_path in &selected {
+        let entry = &entries[res_path];
+        let relative = res_path.strip_prefix("res://").unwrap_or(res_path);
+        if relative.is_empty()
+            || Path::new(relative)
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("无法导出到目录外的路径: {}", res_path);
+        }
+        targets.push((res_path, entry, out_dir.join(relative)));
+    }
+
+    // 每个线程用独立的句柄读取，不共享文件指针
+    Pool::new(options.jobs).cancel_on(&options.cancel).map_init(
+        &targets,
+        || None,
+        |reader, (res_path, entry, target)| {
+            let reader = match reader {
+                Some(reader) => reader,
+                None => reader.insert(BufReader::new(File::open(pck_path).with_context(|| {
+                    format!("无法打开文件: {}", pck_path.display())
+                })?)),
+            };
+            extract_entry(reader, res_path, entry, target, key)
+        },
+    )?;
+    Ok(Extraction {
+        files: selected.len(),
+        pairs,
+    })
+}
+
+/// 把一个 entry 的数据写到 `target`，加密的 entry 先用 `key` 解密
+fn extract_entry(
+    reader: &mut BufReader<File>,
+    res_path: &str,
+    entry: &RawFileEntry,
+    target: &Path,
+    key: Option<&EncryptionKey>,
+) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("无法创建目录: {}", parent.display()))?;
+    }
+    reader
+        .seek(SeekFrom::Start(entry.offset))
+        .with_context(|| format!("无法定位文件数据: {}", res_path))?;
+    let mut out =
+        File::create(target).with_context(|| format!("无法写入: {}", target.display()))?;
+    if entry.is_encrypted() {
+        let key = require_key(key, res_path)?;
+        let mut stored = reader.take(entry.stored_size());
+        let data = decrypt_block(&mut stored, key, res_path)?;
+        return out
+            .write_all(&data)
+            .with_context(|| format!("无法写入: {}", target.display()));
+    }
+    let copied = std::io::copy(&mut reader.take(entry.size), &mut out)
+        .with_context(|| format!("无法导出: {}", res_path))?;
+    if copied != entry.size {
+        bail!("文件数据不完整: {}（PCK 可能已被截断）", res_path);
+    }
+    Ok(())
+}
+
+/// 读取表偏移 `table_offset` 处的 entry；entry 表加密时在用 `key` 解密后的表（按 `limits` 读取）中查找
+pub fn read_entry_at<R: Read + Seek>(
+    reader: &mut R,
+    header: &Header,
+    table_offset: u64,
+    key: Option<&EncryptionKey>,
+    limits: TableLimits,
+) -> Result<RawFileEntry> {
+    if header.flags & PACK_DIR_ENCRYPTED != 0 {
+        let (_, records) = read_table_limited(reader, limits, key)?;
+        return records
+            .into_iter()
+            .find(|r| r.table_offset == table_offset)
+            .map(|r| r.entry)
+            .ok_or_else(|| anyhow!("no entry at table offset {}", table_offset));
+    }
+
+    reader
+        .seek(SeekFrom::Start(table_offset))
+        .context("failed to seek entry")?;
+    RawFileEntry::read_args(reader, header.entry_layout()).context("failed to read RawFileEntry")
+}
+
+/// 批量替换（以及新增）PCK 中文件。
+/// 流程：
+/// 1. 先区分需要替换的与新增的文件
+/// 2. 如果新增导致条目区间变大，则把被覆盖风险的文件数据搬到末尾
+/// 3. 把新增和替换的数据追加到末尾（开启 [`IoOptions::overwrite_in_place`] 时，不大于原数据的替换写回原位置），并更新/新增对应 entry
+/// 4. 重写 header 的 file_count 以及完整的 entry 表
+///
+/// 批量替换/新增文件：计算迁移、追加数据并重写 entry 表与 file_count
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs::{self, OpenOptions};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_replace_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/a.txt"), b"old")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let mut file = OpenOptions::new().read(true).write(true).open(dir.join("game.pck"))?;
+/// let (header, index) = pck::read_header_and_index(&mut file)?;
+/// pck::replace_files_in_pck(
+///     &mut file,
+///     &header,
+///     &index,
+///     vec![("res://a.txt", b"new".as_slice()), ("res://b.txt", b"added".as_slice())],
+/// )?;
+///
+/// let (_, index) = pck::read_header_and_index(&mut file)?;
+/// assert_eq!(pck::read_file_data(&mut file, &index, "res://a.txt")?, b"new");
+/// assert_eq!(pck::read_file_data(&mut file, &index, "res://b.txt")?, b"added");
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn replace_files_in_pck<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, &[u8])>,
+) -> Result<()> {
+    let archive_size = pck_file.size().context("failed to read PCK size")?;
+    replace_files_in_pck_with(
+        pck_file,
+        header,
+        entry_offsets,
+        files,
+        &IoOptions::auto(archive_size),
+    )
+}
+
+/// 只替换已有路径的内容：不新增 entry、不改变路径，因此 entry 表不会增长，原有数据也不会被搬移。
+/// 任一路径在 PCK 中不存在时直接报错，不做任何修改
+pub fn replace_existing_files_in_pck<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, &[u8])>,
+    io: &IoOptions,
+    progress: &dyn Progress,
+) -> Result<()> {
+    let missing: Vec<&str> = files
+        .iter()
+        .map(|(path, _)| *path)
+        .filter(|path| !entry_offsets.contains_key(*path))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "安全模式只允许替换已有文件，以下路径在 PCK 中不存在: {}",
+            missing.join(", ")
+        );
+    }
+
+    replace_files_in_pck_reporting(pck_file, header, entry_offsets, files, &[], io, progress)
+}
+
+/// 同 `replace_files_in_pck`，使用指定的 IO 设置
+pub fn replace_files_in_pck_with<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, &[u8])>,
+    io: &IoOptions,
+) -> Result<()> {
+    replace_files_in_pck_reporting(pck_file, header, entry_offsets, files, &[], io, &())
+}
+
+/// 同 [`replace_files_in_pck_with`]，并把 `encrypt` 中的路径写成加密的 entry（需要 v2 及以上的 PCK，
+/// 密钥与读取时相同，即游戏导出时使用的密钥，由 `pck_file` 提供，见 [`Keyed`]）。
+/// 引擎只用编译进游戏的那一个密钥解密，因此无法为 MOD 单独派生密钥
+pub fn replace_files_in_pck_encrypting<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, &[u8])>,
+    encrypt: &[&str],
+    io: &IoOptions,
+) -> Result<()> {
+    replace_files_in_pck_reporting(pck_file, header, entry_offsets, files, encrypt, io, &())
+}
+
+/// 同 [`replace_files_in_pck_encrypting`]（`encrypt` 为空时即 [`replace_files_in_pck_with`]），
+/// 搬移旧数据、写入新数据与重写 entry 表时通过 `progress` 报告进度
+pub fn replace_files_in_pck_reporting<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, &[u8])>,
+    encrypt: &[&str],
+    io: &IoOptions,
+    progress: &dyn Progress,
+) -> Result<()> {
+    if let Some(path) = encrypt.iter().find(|p| !files.iter().any(|(f, _)| f == *p)) {
+        bail!("{} 不在要写入的文件中，无法加密", path);
+    }
+    let files = files
+        .into_iter()
+        .map(|(path, data)| (path, EntryData::Bytes(data)))
+        .collect();
+    let encrypt = encrypt.iter().copied().collect();
+    replace_entries(pck_file, header, entry_offsets, files, &encrypt, io, progress)
+}
+
+/// 同 [`replace_files_in_pck_with`]，数据可以来自文件：文件在写入时按 `io.chunk_size` 分块读取，
+/// 不会整个载入内存，适合替换大体积的贴图、音频
+pub fn replace_inputs_in_pck<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, &PackInput)>,
+    io: &IoOptions,
+) -> Result<()> {
+    let files = files
+        .into_iter()
+        .map(|(path, input)| (path, EntryData::from(input)))
+        .collect();
+    replace_entries(pck_file, header, entry_offsets, files, &HashSet::new(), io, &())
+}
+
+fn replace_entries<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    files: Vec<(&str, EntryData)>,
+    encrypt: &HashSet<&str>,
+    io: &IoOptions,
+    progress: &dyn Progress,
+) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    if !encrypt.is_empty() && header.version < 2 {
+        bail!("PCK 格式版本 {} 不支持加密 entry（需要 Godot 4 导出的 v2 及以上）", header.version);
+    }
+    let key = pck_file.encryption_key().copied();
+    if !encrypt.is_empty() && key.is_none() {
+        bail!("加密写入 entry 需要提供游戏导出时使用的加密密钥");
+    }
+
+    let mut dedup = HashSet::new();
+    for (path, _) in &files {
+        if !dedup.insert(*path) {
+            return Err(anyhow!("重复的路径: {}", path));
+        }
+    }
+
+    let layout = header.entry_layout();
+    let mut entry_map = build_entry_map(pck_file, entry_offsets)?;
+
+    let sizes = files
+        .iter()
+        .map(|(p, d)| Ok((*p, d.len()?)))
+        .collect::<Result<Vec<(&str, u64)>>>()?;
+    let apply_plan =
+        plan_apply_with(pck_file, header, &entry_map, &sizes, encrypt, io.overwrite_in_place)?;
+    let table_bytes = apply_plan.table_bytes();
+    let plan = apply_plan.table;
+    let (replace_inputs, add_inputs) = split_inputs(files, &entry_map);
+    let known_md5 = digest_in_parallel(&replace_inputs, &add_inputs, &entry_map, io.jobs)?;
+
+    // 数据区很小时，扩展后的 entry 表可能越过文件末尾
+    let mut append = AppendCtx::new(pck_file, plan.table_end_after, io, progress)?;
+
+    // 2) 迁移会被新 entry 覆盖的旧文件（仅未替换的）
+    append.begin_stage(ProgressStage::Move, apply_plan.move_bytes());
+    for target in apply_plan.moves {
+        let path = target.path;
+        let mut moved = entry_map
+            .get_by_path(&path)
+            .ok_or_else(|| anyhow!("entry {} missing during move", path))?
+            .entry
+            .clone();
+        append.move_entry(&mut moved, &path)?;
+        entry_map
+            .update_by_path(&path, |entry: &mut RawFileEntry| {
+                entry.offset = moved.offset;
+                entry.md5 = moved.md5;
+            })
+            .ok_or_else(|| anyhow!("entry {} missing during move", path))?;
+    }
+
+    // 3) 先新增后替换，避免新增 entry 位置被重复计算
+    append.begin_stage(
+        ProgressStage::Append,
+        apply_plan.new_data_bytes + apply_plan.overwrite_bytes,
+    );
+    let mut next_new_table_offset = plan.next_new_table_offset;
+    for (path, data) in add_inputs.iter() {
+        let mut path_bytes = normalized_path_bytes(path);
+        let path_len = path_bytes.len() as u32;
+
+        let md5 = known_md5.get(path).copied();
+        let key = key.filter(|_| encrypt.contains(path.as_str()));
+        let (new_offset, size, md5) =
+            append.write_entry_data(*data, key.as_ref(), None, md5, path)?;
+        let raw_entry = RawFileEntry {
+            path_len,
+            path_bytes: std::mem::take(&mut path_bytes),
+            offset: new_offset,
+            size,
+            md5,
+            flags: if key.is_some() { PACK_FILE_ENCRYPTED } else { 0 },
+        };
+
+        entry_map.insert(EntryRecord {
+            path: path.clone(),
+            table_offset: next_new_table_offset,
+            entry: raw_entry,
+        });
+        next_new_table_offset += layout.entry_size(path_len);
+    }
+
+    for (path, data) in replace_inputs {
+        let old = &entry_map
+            .get_by_path(&path)
+            .ok_or_else(|| anyhow!("entry {} not found in PCK", path))?
+            .entry;
+        // 原来加密的 entry 替换后仍加密保存
+        let key = if old.is_encrypted() {
+            Some(*require_key(key.as_ref(), &path)?)
+        } else {
+            key.filter(|_| encrypt.contains(path.as_str()))
+        };
+        let at = apply_plan
+            .overwritten
+            .contains(&path)
+            .then_some((old.offset, old.stored_size()));
+        let md5 = known_md5.get(&path).copied();
+        let (new_offset, size, md5) =
+            append.write_entry_data(data, key.as_ref(), at, md5, &path)?;
+        entry_map
+            .update_by_path(&path, |entry: &mut RawFileEntry| {
+                entry.offset = new_offset;
+                entry.size = size;
+                entry.md5 = md5;
+                if key.is_some() {
+                    entry.flags |= PACK_FILE_ENCRYPTED;
+                }
+            })
+            .ok_or_else(|| anyhow!("entry {} not found in PCK", path))?;
+    }
+
+    append.finish()?;
+
+    // 4) 重写 header 和 entry 表
+    let (_, min_data_offset) = entry_map
+        .iter_by_table_offset()
+        .map(|e| (e.path.clone(), e.entry.offset))
+        .min_by_key(|(_, off)| *off)
+        .ok_or_else(|| anyhow!("no entries to write"))?;
+
+    let recalculated_table_size: u64 = entry_map
+        .iter_by_table_offset()
+        .map(|e| layout.entry_size(e.entry.path_len))
+        .sum();
+    let recalculated_table_size = table_extent(header, recalculated_table_size);
+
+    if plan.region.start + recalculated_table_size > min_data_offset {
+        return Err(anyhow!(
+            "新 entry 表长度 {} 超出数据起始 {}，请检查迁移逻辑",
+            recalculated_table_size,
+            min_data_offset
+        ));
+    }
+
+    // 更新 header 并整体写回 entry 表（按 table_offset 顺序）
+    let records: Vec<&EntryRecord> = entry_map.iter_by_table_offset().collect();
+    progress.on_progress(ProgressStage::Table, 0, table_bytes);
+    write_header_and_table(pck_file, header, plan.region, &records)?;
+    progress.on_progress(ProgressStage::Table, table_bytes, table_bytes);
+
+    // 5) 截断文件到正确大小（删除旧数据）
+    // let final_size = entry_map
+    //     .iter_by_table_offset()
+    //     .map(|e| e.entry.offset + e.entry.size)
+    //     .max()
+    //     .unwrap_or(plan.region.start + recalculated_table_size);
+
+    // pck_file.set_len(final_size).context("failed to truncate file")?;
+
+    Ok(())
+}
+
+/// 写入这些路径后 entry 表会延伸覆盖的旧数据区间 `(偏移, 大小)`，即修改时需要搬移的数据，按偏移排序；
+/// 被替换的路径会写到新位置，不需要搬移（见 [`plan_apply`]）
+pub fn ranges_to_move<S: PckStorage>(
+    pck_file: &mut S,
+    entry_offsets: &HashMap<String, u64>,
+    new_paths: &[&str],
+) -> Result<Vec<(u64, u64)>> {
+    let header = read_header(&mut BufReader::new(&mut *pck_file))?;
+    let files: Vec<(&str, u64)> = new_paths.iter().map(|path| (*path, 0)).collect();
+    let plan = plan_apply(pck_file, &header, entry_offsets, &files, false)?;
+
+    let mut ranges: Vec<(u64, u64)> = plan.moves.iter().map(|m| (m.offset, m.size)).collect();
+    ranges.sort_unstable();
+    Ok(ranges)
+}
+
+/// 在后台线程顺序读取指定区间并丢弃数据，让系统缓存提前载入（机械硬盘上可明显缩短随后的修改时间）。
+/// 相邻或重叠的区间会合并；线程返回实际读取的字节数
+pub fn prefetch(
+    path: PathBuf,
+    mut ranges: Vec<(u64, u64)>,
+    io: IoOptions,
+) -> std::thread::JoinHandle<Result<u64>> {
+    std::thread::spawn(move || {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (offset, size) in ranges {
+            match merged.last_mut() {
+                Some((start, len)) if offset <= *start + *len => {
+                    *len = (*len).max(offset + size - *start);
+                }
+                _ => merged.push((offset, size)),
+            }
+        }
+
+        let mut file = File::open(&path)
+            .with_context(|| format!("无法打开文件: {}", path.display()))?;
+        let mut buf = vec![0u8; io.chunk_size.max(1)];
+        let mut total = 0u64;
+        for (offset, size) in merged {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut reader = (&mut file).take(size);
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                total += n as u64;
+            }
+        }
+        Ok(total)
+    })
+}
+
+/// [`delete_files_in_pck_with`] 删除 entry 的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteMode {
+    /// 从 entry 表中去掉，重写更短的表并截断文件末尾不再引用的数据
+    #[default]
+    Rewrite,
+    /// 只给 entry 打上删除标志（v2，Godot 4.4 起）：表的长度与全部数据都不变，
+    /// 不会因为截断或表变短而损坏 PCK
+    MarkRemoved,
+}
+
+/// 删除指定路径的文件 entry，并重写 entry 表与文件数量
+pub fn delete_files_in_pck<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    paths: Vec<&str>,
+) -> Result<()> {
+    delete_files_in_pck_with(pck_file, header, entry_offsets, paths, DeleteMode::Rewrite)
+}
+
+/// 按 `mode` 删除指定路径的文件 entry；不存在的路径跳过
+pub fn delete_files_in_pck_with<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    paths: Vec<&str>,
+    mode: DeleteMode,
+) -> Result<()> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let godot = (header.godot_version_major, header.godot_version_minor);
+    if mode == DeleteMode::MarkRemoved && (header.version < 2 || godot < (4, 4)) {
+        bail!(
+            "该 PCK（格式 v{}，Godot {}.{}）不支持删除标志，需要 Godot 4.4 起导出的 PCK",
+            header.version,
+            header.godot_version_major,
+            header.godot_version_minor
+        );
+    }
+
+    let mut to_remove = HashSet::new();
+    for path in paths {
+        if !to_remove.insert(path.to_string()) {
+            return Err(anyhow!("重复的路径: {}", path));
+        }
+    }
+
+    let snapshot = FileSnapshot::take(pck_file)?;
+    let layout = header.entry_layout();
+    let entry_map = build_entry_map(pck_file, entry_offsets)?;
+
+    // 仅删除实际存在的路径，不存在的静默跳过
+    let existing_to_remove: HashSet<String> = to_remove
+        .into_iter()
+        .filter(|p| entry_map.get_by_path(p).is_some())
+        .collect();
+
+    if existing_to_remove.is_empty() {
+        // 没有可删除的 entry，直接返回
+        return Ok(());
+    }
+
+    let region = table_region(pck_file)?;
+    if mode == DeleteMode::MarkRemoved {
+        let marked: Vec<EntryRecord> = entry_map
+            .iter_by_table_offset()
+            .map(|record| {
+                let mut entry = record.entry.clone();
+                if existing_to_remove.contains(&record.path) {
+                    entry.flags |= PACK_FILE_REMOVAL;
+                }
+                EntryRecord {
+                    path: record.path.clone(),
+                    table_offset: record.table_offset,
+                    entry,
+                }
+            })
+            .collect();
+        snapshot.ensure_unchanged(pck_file)?;
+        let records: Vec<&EntryRecord> = marked.iter().collect();
+        return write_header_and_table(pck_file, header, region, &records);
+    }
+    let table_start = region.start;
+
+    let mut current_offset = table_start;
+    let mut remaining = Vec::new();
+    for record in entry_map.iter_by_table_offset() {
+        if existing_to_remove.contains(&record.path) {
+            continue;
+        }
+
+        let binary_size = layout.entry_size(record.entry.path_len);
+        remaining.push(EntryRecord {
+            path: record.path.clone(),
+            table_offset: current_offset,
+            entry: record.entry.clone(),
+        });
+        current_offset += binary_size;
+    }
+
+    if remaining.is_empty() {
+        return Err(anyhow!("删除后没有剩余文件，PCK 至少需要一个 entry"));
+    }
+
+    let recalculated_table_size = table_extent(header, current_offset - table_start);
+    let min_data_offset = remaining
+        .iter()
+        .map(|e| e.entry.offset)
+        .min()
+        .ok_or_else(|| anyhow!("no entries to write after deletion"))?;
+
+    if table_start + recalculated_table_size > min_data_offset {
+        return Err(anyhow!(
+            "删除后 entry 表越界：新长度 {} 覆盖数据起点 {}",
+            recalculated_table_size,
+            min_data_offset
+        ));
+    }
+
+    snapshot.ensure_unchanged(pck_file)?;
+    let records: Vec<&EntryRecord> = remaining.iter().collect();
+    write_header_and_table(pck_file, header, region, &records)?;
+
+    let table_end = table_start + recalculated_table_size;
+    let data_end = remaining
+        .iter()
+        .map(|e| e.entry.offset + e.entry.stored_size())
+        .max()
+        .unwrap_or(table_end);
+    let final_size = table_end.max(data_end);
+    pck_file
+        .set_size(final_size)
+        .context("failed to truncate after deletion")?;
+
+    Ok(())
+}
+
+/// 批量重命名 entry（旧路径 -> 新路径），返回实际重命名的数量；不存在的旧路径跳过。
+/// 路径变长导致 entry 表扩展时，先把会被覆盖的数据搬到文件末尾
+pub fn rename_entries<S: PckStorage>(
+    pck_file: &mut S,
+    header: &Header,
+    entry_offsets: &HashMap<String, u64>,
+    renames: Vec<(&str, &str)>,
+    io: &IoOptions,
+) -> Result<usize> {
+    let layout = header.entry_layout();
+    let entry_map = build_entry_map(pck_file, entry_offsets)?;
+
+    let mut targets = HashMap::new();
+    for (from, to) in renames {
+        if entry_map.get_by_path(&from.to_string()).is_none() || from == to {
+            continue;
+        }
+        if targets.insert(from.to_string(), to.to_string()).is_some() {
+            bail!("重复的路径: {}", from);
+        }
+    }
+    if targets.is_empty() {
+        return Ok(0);
+    }
+
+    // 重命名后的路径不能与其他 entry 冲突
+    let mut final_paths = HashSet::new();
+    for record in entry_map.iter_by_table_offset() {
+        let path = targets.get(&record.path).unwrap_or(&record.path);
+        if !final_paths.insert(path.clone()) {
+            bail!("重命名后路径冲突: {}", path);
+        }
+    }
+
+    let region = table_region(pck_file)?;
+    let table_start = region.start;
+
+    let mut current_offset = table_start;
+    let mut records = Vec::with_capacity(entry_map.len());
+    for record in entry_map.iter_by_table_offset() {
+        let mut entry = record.entry.clone();
+        let path = match targets.get(&record.path) {
+            Some(to) => {
+                entry.path_bytes = normalized_path_bytes(to);
+                entry.path_len = entry.path_bytes.len() as u32;
+                to.clone()
+            }
+            None => record.path.clone(),
+        };
+
+        records.push(EntryRecord {
+            path,
+            table_offset: current_offset,
+            entry,
+        });
+        current_offset += layout.entry_size(records.last().unwrap().entry.path_len);
+    }
+
+    let table_end = table_start + table_extent(header, current_offset - table_start);
+    let mut append = AppendCtx::new(pck_file, table_end, io, &())?;
+    for record in records.iter_mut().filter(|r| r.entry.offset < table_end) {
+        append.move_entry(&mut record.entry, &record.path)?;
+    }
+    append.finish()?;
+
+    let records: Vec<&EntryRecord> = records.iter().collect();
+    write_header_and_table(pck_file, header, region, &records)?;
+
+    Ok(targets.len())
+}
+
+/// 打开的 PCK：持有文件与解析好的索引。替换、新增与删除先暂存在内存中，读取时已能看到，
+/// [`commit`](PckArchive::commit) 时一次写入文件，调用方不必自己维护 `File`、[`Header`] 与偏移表
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions, PckArchive};
+/// use std::fs;
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_archive_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/a.txt"), b"old")?;
+/// fs::write(dir.join("assets/b.txt"), b"unused")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let mut archive = PckArchive::edit(dir.join("game.pck"))?;
+/// archive.replace("res://a.txt", b"new".to_vec())?;
+/// archive.add("res://c.txt", b"added".to_vec())?;
+/// archive.delete("res://b.txt")?;
+/// archive.commit()?;
+///
+/// let archive = PckArchive::open(dir.join("game.pck"))?;
+/// assert_eq!(archive.read("res://a.txt")?, b"new");
+/// assert_eq!(archive.paths(), ["res://a.txt", "res://c.txt"]);
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub struct PckArchive {
+    path: PathBuf,
+    file: Keyed<File>,
+    writable: bool,
+    header: Header,
+    index: HashMap<String, u64>,
+    /// 路径 -> 暂存的新数据，None 表示删除
+    pending: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl PckArchive {
+    /// 以只读方式打开
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(path.as_ref(), false, None, TableLimits::DEFAULT)
+    }
+
+    /// 以读写方式打开，修改在 [`commit`](Self::commit) 时写入
+    pub fn edit(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(path.as_ref(), true, None, TableLimits::DEFAULT)
+    }
+
+    /// 同 [`open`](Self::open)，加密的 entry 表与 entry 数据用 `key` 读写
+    pub fn open_encrypted(path: impl AsRef<Path>, key: EncryptionKey) -> Result<Self> {
+        Self::open_with(path.as_ref(), false, Some(key), TableLimits::DEFAULT)
+    }
+
+    /// 同 [`edit`](Self::edit)，加密的 entry 表与 entry 数据用 `key` 读写
+    pub fn edit_encrypted(path: impl AsRef<Path>, key: EncryptionKey) -> Result<Self> {
+        Self::open_with(path.as_ref(), true, Some(key), TableLimits::DEFAULT)
+    }
+
+    /// 同 [`open`](Self::open)，按 `limits` 而不是默认上限读取 entry 表
+    pub fn open_limited(path: impl AsRef<Path>, limits: TableLimits) -> Result<Self> {
+        Self::open_with(path.as_ref(), false, None, limits)
+    }
+
+    /// 同 [`edit`](Self::edit)，按 `limits` 而不是默认上限读取 entry 表
+    pub fn edit_limited(path: impl AsRef<Path>, limits: TableLimits) -> Result<Self> {
+        Self::open_with(path.as_ref(), true, None, limits)
+    }
+
+    fn open_with(
+        path: &Path,
+        writable: bool,
+        key: Option<EncryptionKey>,
+        limits: TableLimits,
+    ) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .open(path)
+            .with_context(|| format!("无法打开文件: {}", path.display()))?;
+        let mut file = Keyed::new(file, key).with_limits(limits);
+        let (header, index) = read_header_and_index(&mut file)
+            .with_context(|| format!("读取 PCK 头与索引失败: {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            writable,
+            header,
+            index,
+            pending: HashMap::new(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// 包含暂存修改在内的全部路径，按字母排序
+    pub fn paths(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = self
+            .index
+            .keys()
+            .filter(|path| !self.pending.contains_key(*path))
+            .chain(
+                self.pending
+                    .iter()
+                    .filter(|(_, data)| data.is_some())
+                    .map(|(path, _)| path),
+            )
+            .map(String::as_str)
+            .collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        match self.pending.get(path) {
+            Some(data) => data.is_some(),
+            None => self.index.contains_key(path),
+        }
+    }
+
+    /// 读取 entry 的数据；暂存了新数据时返回暂存的数据
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        match self.pending.get(path) {
+            Some(Some(data)) => Ok(data.clone()),
+            Some(None) => bail!("{} 已被删除", path),
+            None => read_file_data(&mut self.file.try_clone()?, &self.index, path),
+        }
+    }
+
+    /// 暂存已有 entry 的新数据
+    pub fn replace(&mut self, path: &str, data: Vec<u8>) -> Result<()> {
+        if !self.contains(path) {
+            bail!("PCK 中不存在文件: {}，新增请使用 add", path);
+        }
+        self.pending.insert(path.to_string(), Some(data));
+        Ok(())
+    }
+
+    /// 暂存一个新 entry
+    pub fn add(&mut self, path: &str, data: Vec<u8>) -> Result<()> {
+        if self.contains(path) {
+            bail!("PCK 中已有文件: {}，替换请使用 replace", path);
+        }
+        if !path.starts_with("res://") {
+            bail!("路径必须以 res:// 开头: {}", path);
+        }
+        self.pending.insert(path.to_string(), Some(data));
+        Ok(())
+    }
+
+    /// 暂存删除；删除尚未写入的新 entry 时只是撤销新增
+    pub fn delete(&mut self, path: &str) -> Result<()> {
+        if !self.contains(path) {
+            bail!("PCK 中不存在文件: {}", path);
+        }
+        if self.index.contains_key(path) {
+            self.pending.insert(path.to_string(), None);
+        } else {
+            self.pending.remove(path);
+        }
+        Ok(())
+    }
+
+    /// 是否有尚未写入的修改
+    pub fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// 写入暂存的修改，之后可以继续读取与修改
+    pub fn commit(&mut self) -> Result<()> {
+        let archive_size = self.file.size().context("failed to read PCK size")?;
+        self.commit_with(&IoOptions::auto(archive_size))
+    }
+
+    /// 同 [`commit`](Self::commit)，使用指定的 IO 设置
+    pub fn commit_with(&mut self, io: &IoOptions) -> Result<()> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+        if !self.writable {
+            bail!("{} 以只读方式打开，无法写入修改", self.path.display());
+        }
+
+        let deletes: Vec<&str> = self
+            .pending
+            .iter()
+            .filter(|(_, data)| data.is_none())
+            .map(|(path, _)| path.as_str())
+            .collect();
+        if !deletes.is_empty() {
+            delete_files_in_pck(&mut self.file, &self.header, &self.index, deletes)
+                .with_context(|| format!("删除文件失败: {}", self.path.display()))?;
+            (self.header, self.index) = read_header_and_index(&mut self.file)?;
+        }
+
+        let writes: Vec<(&str, &[u8])> = self
+            .pending
+            .iter()
+            .filter_map(|(path, data)| Some((path.as_str(), data.as_deref()?)))
+            .collect();
+        if !writes.is_empty() {
+            replace_files_in_pck_with(&mut self.file, &self.header, &self.index, writes, io)
+                .with_context(|| format!("写入文件失败: {}", self.path.display()))?;
+            (self.header, self.index) = read_header_and_index(&mut self.file)?;
+        }
+
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// 打包时单个 entry 的数据来源
+pub enum PackInput {
+    File(PathBuf),
+    Data(Vec<u8>),
+}
+
+impl PackInput {
+    fn open(&self) -> Result<Box<dyn Read + '_>> {
+        Ok(match self {
+            PackInput::File(path) => Box::new(
+                File::open(path).with_context(|| format!("无法打开文件: {}", path.display()))?,
+            ),
+            PackInput::Data(data) => Box::new(data.as_slice()),
+        })
+    }
+}
+
+/// 收集目录下全部文件，映射为 `res://` 路径（按路径排序）
+pub fn collect_pack_inputs(root: &Path) -> Result<Vec<(String, PackInput)>> {
+    let mut inputs = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("无法读取目录: {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let mut res_path = String::from("res://");
+            for (i, component) in relative.components().enumerate() {
+                let name = component
+                    .as_os_str()
+                    .to_str()
+                    .ok_or_else(|| anyhow!("文件名不是有效的 UTF-8: {}", path.display()))?;
+                if i > 0 {
+                    res_path.push('/');
+                }
+                res_path.push_str(name);
+            }
+            inputs.push((res_path, PackInput::File(path)));
+        }
+    }
+
+    inputs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(inputs)
+}
+
+/// 从零写出 PCK：header、entry 表、数据区依次排列，返回 PCK 长度。
+/// `base_offset` 为 PCK 在输出文件中的起始位置（嵌入可执行文件时不为 0），entry 中的数据偏移为文件内绝对偏移
+pub fn write_pck<S: PckStorage>(
+    out: &mut S,
+    base_offset: u64,
+    godot_version: [u32; 3],
+    files: &[(String, PackInput)],
+    io: &IoOptions,
+) -> Result<u64> {
+    let file_count: u32 = files
+        .len()
+        .try_into()
+        .map_err(|_| anyhow!("文件数量过多，超出 u32 限制"))?;
+    if file_count == 0 {
+        bail!("没有可打包的文件");
+    }
+
+    let [major, minor, patch] = godot_version;
+    let mut header = Header {
+        version: format_version_for(major),
+        godot_version_major: major,
+        godot_version_minor: minor,
+        godot_version_patch: patch,
+        flags: 0,
+        file_base: 0,
+        reserved: [0; 16],
+        file_count,
+    };
+    let table_start = base_offset + header.size();
+    let mut layout = header.entry_layout();
+    let table_size: u64 = files
+        .iter()
+        .map(|(path, _)| layout.entry_size(normalized_path_bytes(path).len() as u32))
+        .sum();
+    // v2 的数据偏移相对于紧跟 entry 表的数据区起点。与引擎一样，4.2 起 file_base 相对 PCK 起点，
+    // 嵌入的 PCK 因此不依赖它在可执行文件中的位置；更早的版本不认识该标志位，只能写绝对偏移
+    if header.version >= 2 {
+        layout.file_base = table_start + table_size;
+        header.file_base = layout.file_base;
+        if (major, minor) >= (4, 2) {
+            header.flags |= PACK_REL_FILEBASE;
+            header.file_base -= base_offset;
+        }
+    }
+
+    let mut writer = BufWriter::with_capacity(io.write_buffer, &mut *out);
+    writer
+        .seek(SeekFrom::Start(table_start + table_size))
+        .context("failed to seek to data start")?;
+
+    // 先写数据区，边写边计算 MD5，再回填 header 与 entry 表
+    let mut records = Vec::with_capacity(files.len());
+    let mut table_offset = table_start;
+    let mut buf = vec![0u8; io.chunk_size.max(1)];
+    for (res_path, source) in files {
+        let offset = writer.stream_position()?;
+        let mut reader = source.open()?;
+        let mut digest = md5::Context::new();
+        let mut size = 0u64;
+        loop {
+            let n = reader
+                .read(&mut buf)
+                .with_context(|| format!("无法读取文件: {}", res_path))?;
+            if n == 0 {
+                break;
+            }
+            digest.consume(&buf[..n]);
+            writer
+                .write_all(&buf[..n])
+                .with_context(|| format!("failed to write data for {}", res_path))?;
+            size += n as u64;
+        }
+
+        let path_bytes = normalized_path_bytes(res_path);
+        let path_len = path_bytes.len() as u32;
+        records.push(EntryRecord {
+            path: res_path.clone(),
+            table_offset,
+            entry: RawFileEntry {
+                path_len,
+                path_bytes,
+                offset,
+                size,
+                md5: digest.finalize().0,
+                flags: 0,
+            },
+        });
+        table_offset += layout.entry_size(path_len);
+    }
+    let pck_end = writer.stream_position()?;
+
+    writer
+        .seek(SeekFrom::Start(base_offset))
+        .context("failed to seek header start")?;
+    header
+        .write_le(&mut writer)
+        .context("failed to write header")?;
+    let records: Vec<&EntryRecord> = records.iter().collect();
+    write_entries(&mut writer, layout, &records)?;
+    writer.flush().context("failed to flush PCK")?;
+
+    Ok(pck_end - base_offset)
+}
+
+/// `pack_directory` 的选项
+#[derive(Debug, Clone)]
+pub struct PackOptions {
+    godot_version: [u32; 3],
+    embed_template: Option<PathBuf>,
+    io: Option<IoOptions>,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            // 引擎只拒绝比自身更新的 PCK，默认取最低的 3.0.0
+            godot_version: [3, 0, 0],
+            embed_template: None,
+            io: None,
+        }
+    }
+}
+
+impl PackOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 写入 header 的 Godot 版本；主版本为 4 及以上时写出 v2 格式
+    pub fn godot_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.godot_version = [major, minor, patch];
+        self
+    }
+
+    /// 复制该可执行文件并把 PCK 嵌入其末尾
+    pub fn embed_into(mut self, template: impl Into<PathBuf>) -> Self {
+        self.embed_template = Some(template.into());
+        self
+    }
+
+    /// 指定 IO 设置；默认按输入文件总大小自动选择
+    pub fn io(mut self, io: IoOptions) -> Self {
+        self.io = Some(io);
+        self
+    }
+}
+
+/// 将目录打包为 PCK，返回打包的文件数量。
+/// 嵌入可执行文件时，末尾追加 PCK 长度(u64) + magic 作为尾部，供引擎定位嵌入的 PCK
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs::{self, File};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_pack_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets/Core"))?;
+/// fs::write(dir.join("assets/Core/Game.gde"), b"extends Node")?;
+///
+/// let out = dir.join("game.pck");
+/// let count = pck::pack_directory(dir.join("assets"), &out, &PackOptions::new().godot_version(3, 5, 2))?;
+/// assert_eq!(count, 1);
+///
+/// let mut file = File::open(&out)?;
+/// let (header, index) = pck::read_header_and_index(&mut file)?;
+/// assert_eq!(header.godot_version_minor, 5);
+/// assert!(index.contains_key("res://Core/Game.gde"));
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn pack_directory(
+    src_dir: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    options: &PackOptions,
+) -> Result<usize> {
+    let out_path = out_path.as_ref();
+    let mut files = collect_pack_inputs(src_dir.as_ref())?;
+    // 输出文件位于源目录中（如重复打包到同一位置）时不把它自身打进去
+    if let Ok(out_canonical) = fs::canonicalize(out_path) {
+        files.retain(|(_, input)| match input {
+            PackInput::File(path) => fs::canonicalize(path).ok().as_ref() != Some(&out_canonical),
+            PackInput::Data(_) => true,
+        });
+    }
+    let template = options.embed_template.as_deref();
+
+    let mut out = match template {
+        Some(template) => {
+            // fs::copy 会保留可执行权限
+            fs::copy(template, out_path)
+                .with_context(|| format!("无法复制可执行文件: {}", template.display()))?;
+            OpenOptions::new().read(true).write(true).open(out_path)
+        }
+        None => File::create(out_path),
+    }
+    .with_context(|| format!("无法创建输出文件: {}", out_path.display()))?;
+
+    let mut base_offset = out.seek(SeekFrom::End(0))?;
+    if template.is_some() {
+        let padding = base_offset.next_multiple_of(EMBED_ALIGNMENT) - base_offset;
+        out.write_all(&vec![0u8; padding as usize])?;
+        base_offset += padding;
+    }
+
+    let io = match options.io.clone() {
+        Some(io) => io,
+        None => {
+            let total: u64 = files
+                .iter()
+                .map(|(_, input)| match input {
+                    PackInput::File(path) => fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+                    PackInput::Data(data) => data.len() as u64,
+                })
+                .sum();
+            IoOptions::auto(total)
+        }
+    };
+
+    let pck_size = write_pck(&mut out, base_offset, options.godot_version, &files, &io)?;
+
+    if template.is_some() {
+        out.seek(SeekFrom::Start(base_offset + pck_size))?;
+        out.write_all(&pck_size.to_le_bytes())?;
+        out.write_all(b"GDPC")?;
+    }
+    out.flush().context("failed to flush output")?;
+
+    Ok(files.len())
+}
+
+/// `verify_pck` 的选项
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// 抽样比例（0-100]；100 表示全部校验
+    pub sample_percent: f64,
+    /// 抽样种子，相同种子选中相同的 entry
+    pub seed: u64,
+    /// 并行线程数
+    pub jobs: usize,
+    /// 发现第一个错误后立即停止
+    pub fail_fast: bool,
+    /// 缓冲区设置；`None` 时按 PCK 大小自动选择
+    pub io: Option<IoOptions>,
+    /// 解密加密的 entry 表与 entry 数据的密钥
+    pub encryption_key: Option<EncryptionKey>,
+    /// 解析 entry 表时的上限
+    pub limits: TableLimits,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            sample_percent: 100.0,
+            seed: 0,
+            jobs: 1,
+            fail_fast: false,
+            io: None,
+            encryption_key: None,
+            limits: TableLimits::DEFAULT,
+        }
+    }
+}
+
+/// 校验失败的 entry
+#[derive(Debug, Clone)]
+pub struct VerifyFailure {
+    pub path: String,
+    pub reason: String,
+    /// 表中记录的 MD5
+    pub expected: [u8; 16],
+    /// 实际数据的 MD5；数据无法读取时为 None
+    pub actual: Option<[u8; 16]>,
+}
+
+/// 一次校验的结果汇总
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub total_entries: usize,
+    pub checked_entries: usize,
+    pub checked_bytes: u64,
+    pub failures: Vec<VerifyFailure>,
+    pub elapsed: Duration,
+    /// 因 fail_fast 提前结束
+    pub stopped_early: bool,
+}
+
+impl VerifyReport {
+    /// 吞吐量（字节/秒）
+    pub fn throughput(&self) -> f64 {
+        self.checked_bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// 按抽样比例选择 entry：对路径做带种子的 FNV-1a 哈希，落在比例内的被选中
+fn sample_selected(path: &str, seed: u64, sample_percent: f64) -> bool {
+    if sample_percent >= 100.0 {
+        return true;
+    }
+    let mut hash = 0xcbf2_9ce4_8422_2325u64 ^ seed;
+    for byte in path.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    (hash % 1_000_000) as f64 / 10_000.0 < sample_percent
+}
+
+/// 重新计算 entry 数据的 MD5 并与表中记录比对，可抽样、可并行。
+/// 每个线程使用独立的文件句柄，避免共享文件指针
+pub fn verify_pck(pck_path: &Path, options: &VerifyOptions) -> Result<VerifyReport> {
+    let mut file =
+        File::open(pck_path).with_context(|| format!("无法打开文件: {}", pck_path.display()))?;
+    verify_with(&mut file, options, || Ok(File::open(pck_path)?))
+}
+
+/// 单线程重新计算已打开 PCK 中每个 entry 的 MD5，与表中记录比对
+///
+/// ```
+/// use bpb_enhance::pck::{self, PackOptions};
+/// use std::fs::{self, File};
+///
+/// let dir = std::env::temp_dir().join(format!("bpb_doc_verify_{}", std::process::id()));
+/// fs::create_dir_all(dir.join("assets"))?;
+/// fs::write(dir.join("assets/readme.txt"), b"hello")?;
+/// pck::pack_directory(dir.join("assets"), dir.join("game.pck"), &PackOptions::new())?;
+///
+/// let report = pck::verify(&mut File::open(dir.join("game.pck"))?)?;
+/// assert_eq!(report.checked_entries, 1);
+/// assert!(report.failures.is_empty());
+/// # fs::remove_dir_all(&dir)?;
+/// # anyhow::Ok(())
+/// ```
+pub fn verify(pck_file: &mut File) -> Result<VerifyReport> {
+    let mut file = pck_file.try_clone()?;
+    verify_with(&mut file, &VerifyOptions::default(), || Ok(pck_file.try_clone()?))
+}
+
+/// `open` 为每个校验线程打开独立的读取句柄
+fn verify_with(
+    file: &mut File,
+    options: &VerifyOptions,
+    open: impl Fn() -> Result<File> + Sync,
+) -> Result<VerifyReport> {
+    let started = Instant::now();
+    let archive_size = file.metadata()?.len();
+    let key = options.encryption_key.as_ref();
+    let (_, records) = read_table_limited(&mut BufReader::new(&mut *file), options.limits, key)?;
+    let total_entries = records.len();
+
+    let mut selected: Vec<&EntryRecord> = records
+        .iter()
+        .filter(|r| sample_selected(&r.path, options.seed, options.sample_percent))
+        .collect();
+    if selected.is_empty() {
+        // 抽样比例很小时至少校验一个
+        selected.extend(records.first());
+    }
+
+    let io = options
+        .io
+        .clone()
+        .unwrap_or_else(|| IoOptions::auto(archive_size));
+    let stop = AtomicBool::new(false);
+    let checked_entries = AtomicUsize::new(0);
+    let checked_bytes = Mutex::new(0u64);
+    let failures = Mutex::new(Vec::new());
+
+    // 每个任务第一次用到时才打开句柄，任务之间不共享文件指针
+    let check = |state: &mut Option<(BufReader<File>, Vec<u8>)>, record: &&EntryRecord| {
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let (reader, buf) = match state {
+            Some(state) => state,
+            None => state.insert((
+                BufReader::with_capacity(io.read_buffer, open()?),
+                vec![0u8; io.chunk_size.max(1)],
+            )),
+        };
+        let entry = &record.entry;
+        let stored_end = entry.offset.saturating_add(entry.stored_size());
+
+        let mismatch = |actual: [u8; 16]| {
+            let reason = format!(
+                "MD5 不匹配：表中为 {}，实际为 {}",
+                hex_digest(&entry.md5),
+                hex_digest(&actual)
+            );
+            (reason, Some(actual))
+        };
+        let result = if stored_end > archive_size {
+            let reason = format!(
+                "数据范围 {}..{} 超出文件大小 {}",
+                entry.offset, stored_end, archive_size
+            );
+            Err((reason, None))
+        } else if entry.is_encrypted() {
+            // 加密块自带明文 MD5，解密时即完成校验
+            reader.seek(SeekFrom::Start(entry.offset))?;
+            let mut stored = (&mut *reader).take(entry.stored_size());
+            require_key(key, &record.path)
+                .and_then(|key| decrypt_block(&mut stored, key, &record.path))
+                .map_err(|e| (format!("{:#}", e), None))
+                .and_then(|data| match md5::compute(&data).0 {
+                    actual if actual == entry.md5 => Ok(()),
+                    actual => Err(mismatch(actual)),
+                })
+        } else {
+            reader.seek(SeekFrom::Start(entry.offset))?;
+            let mut digest = md5::Context::new();
+            let mut remaining = entry.size;
+            while remaining > 0 {
+                let n = remaining.min(buf.len() as u64) as usize;
+                reader.read_exact(&mut buf[..n])?;
+                digest.consume(&buf[..n]);
+                remaining -= n as u64;
+            }
+            let actual = digest.finalize().0;
+            if actual == entry.md5 {
+                Ok(())
+            } else {
+                Err(mismatch(actual))
+            }
+        };
+
+        checked_entries.fetch_add(1, Ordering::Relaxed);
+        *checked_bytes.lock().unwrap() += entry.size;
+        if let Err((reason, actual)) = result {
+            failures.lock().unwrap().push(VerifyFailure {
+                path: record.path.clone(),
+                reason,
+                expected: entry.md5,
+                actual,
+            });
+            if options.fail_fast {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    Pool::new(options.jobs).map_init(&selected, || None, check)?;
+
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by(|a, b| a.path.cmp(&b.path));
+    let checked_entries = checked_entries.into_inner();
+
+    Ok(VerifyReport {
+        total_entries,
+        checked_entries,
+        checked_bytes: checked_bytes.into_inner().unwrap(),
+        stopped_early: stop.into_inner() && checked_entries < selected.len(),
+        failures,
+        elapsed: started.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, table_offset: u64, data_offset: u64) -> EntryRecord {
+        let path_bytes = normalized_path_bytes(path);
+        EntryRecord {
+            path: path.to_string(),
+            table_offset,
+            entry: RawFileEntry {
+                path_len: path_bytes.len() as u32,
+                path_bytes,
+                offset: data_offset,
+                size: 1,
+                md5: [0; 16],
+                flags: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn split_duplicates_keeps_newest_data() {
+        let records = vec![
+            record("res://a", 88, 5000),
+            record("res://b", 120, 1000),
+            record("res://a", 152, 900),
+            record("res://a", 184, 7000),
+        ];
+
+        let (kept, duplicates) = split_duplicates(records);
+
+        let kept: Vec<(&str, u64)> = kept.iter().map(|r| (r.path.as_str(), r.entry.offset)).collect();
+        assert_eq!(kept, vec![("res://b", 1000), ("res://a", 7000)]);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].kept_offset, 7000);
+        assert_eq!(duplicates[0].dropped_offsets, vec![5000, 900]);
+    }
+
+    #[test]
+    fn pack_directory_round_trips_and_embeds() {
+        let dir = std::env::temp_dir().join(format!("bpb_enhance_pack_{}", std::process::id()));
+        let src = dir.join("src");
+        fs::create_dir_all(src.join("Core")).unwrap();
+        fs::write(src.join("Core/Game.gde"), b"game script").unwrap();
+        fs::write(src.join("icon.png"), b"png").unwrap();
+        fs::write(dir.join("template.exe"), b"MZ-template").unwrap();
+
+        let out = dir.join("game.pck");
+        let options = PackOptions::new().godot_version(3, 5, 2);
+        assert_eq!(pack_directory(&src, &out, &options).unwrap(), 2);
+        let mut file = File::open(&out).unwrap();
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(header.godot_version_minor, 5);
+        assert_eq!(
+            read_file_data(&mut file, &index, "res://Core/Game.gde").unwrap(),
+            b"game script"
+        );
+
+        let exe = dir.join("game.exe");
+        pack_directory(&src, &exe, &options.embed_into(dir.join("template.exe"))).unwrap();
+        let bytes = fs::read(&exe).unwrap();
+        let (body, trailer) = bytes.split_at(bytes.len() - 12);
+        assert_eq!(&trailer[8..], b"GDPC");
+        let pck_size = u64::from_le_bytes(trailer[..8].try_into().unwrap()) as usize;
+        let pck_start = body.len() - pck_size;
+        assert_eq!(pck_start, 16);
+        assert_eq!(&body[pck_start..pck_start + 4], b"GDPC");
+        // 数据偏移为可执行文件内的绝对偏移
+        let game_gde = body.windows(11).position(|w| w == b"game script").unwrap();
+        assert!(game_gde > pck_start);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sampling_is_deterministic_per_seed() {
+        let paths: Vec<String> = (0..2000).map(|i| format!("res://file_{}.tres", i)).collect();
+        let picked = |seed| {
+            paths
+                .iter()
+                .filter(|p| sample_selected(p, seed, 5.0))
+                .count()
+        };
+
+        assert_eq!(picked(1), picked(1));
+        assert!((50..=150).contains(&picked(1)), "{}", picked(1));
+        assert!(paths.iter().all(|p| sample_selected(p, 7, 100.0)));
+    }
+
+    #[test]
+    fn append_detects_concurrent_writer() {
+        let path = std::env::temp_dir().join(format!("bpb_append_guard_{}", std::process::id()));
+        fs::write(&path, vec![0u8; 128]).unwrap();
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+
+        let mut append = AppendCtx::new(&mut file, 0, &IoOptions::auto(128), &()).unwrap();
+        assert_eq!(append.append_bytes(b"ours", "res://a").unwrap(), 128);
+        append.finish().unwrap();
+
+        // 另一个进程在我们追加之后也写了数据
+        let mut other = OpenOptions::new().append(true).open(&path).unwrap();
+        other.write_all(b"theirs").unwrap();
+        assert!(append.append_bytes(b"more", "res://b").is_err());
+        assert!(append.finish().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn move_verification_warns_or_repairs_md5() {
+        let path = std::env::temp_dir().join(format!("bpb_verify_moves_{}", std::process::id()));
+        fs::write(&path, b"payload").unwrap();
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let stale = RawFileEntry {
+            path_len: 8,
+            path_bytes: normalized_path_bytes("res://a"),
+            offset: 0,
+            size: 7,
+            md5: [0; 16],
+            flags: 0,
+        };
+
+        for (mode, expected) in [
+            (MoveVerification::Off, [0; 16]),
+            (MoveVerification::Warn, [0; 16]),
+            (MoveVerification::Repair, md5::compute(b"payload").0),
+        ] {
+            let io = IoOptions {
+                verify_moves: mode,
+                ..IoOptions::auto(7)
+            };
+            let mut append = AppendCtx::new(&mut file, 0, &io, &()).unwrap();
+            let mut entry = stale.clone();
+            append.move_entry(&mut entry, "res://a").unwrap();
+            append.finish().unwrap();
+
+            assert_eq!(entry.md5, expected);
+            assert_eq!(append.move_mismatches, usize::from(mode != MoveVerification::Off));
+            assert_eq!(read_range(&mut file, entry.offset, 7), b"payload");
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn read_range(file: &mut File, offset: u64, size: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; size];
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn ranges_to_move_follow_table_growth() {
+        let dir = std::env::temp_dir().join(format!("bpb_prefetch_{}", std::process::id()));
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a.txt"), b"first").unwrap();
+        fs::write(src.join("b.txt"), b"second").unwrap();
+        let out = dir.join("game.pck");
+        pack_directory(&src, &out, &PackOptions::new()).unwrap();
+
+        let mut file = File::open(&out).unwrap();
+        let (_, index) = read_header_and_index(&mut file).unwrap();
+        assert!(ranges_to_move(&mut file, &index, &["res://a.txt"]).unwrap().is_empty());
+
+        // 新增的 entry（52 字节）会覆盖紧跟在表后面的两个小文件
+        let ranges = ranges_to_move(&mut file, &index, &["res://new.txt"]).unwrap();
+        assert_eq!(ranges.iter().map(|r| r.1).collect::<Vec<_>>(), vec![5, 6]);
+
+        let mut overlapping = ranges.clone();
+        overlapping.push((ranges[0].0 + 2, 3));
+        let read = prefetch(out.clone(), overlapping, IoOptions::auto(0))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(read, 11);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn apply_plan_predicts_moves_and_growth() {
+        let dir = std::env::temp_dir().join(format!("bpb_plan_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut file = open_pck(
+            &dir.join("plan.pck"),
+            &padded_pck(1, &[("res://a.txt", b"aaa"), ("res://b.txt", b"bbbb")], 0),
+        );
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+
+        let files: Vec<(&str, &[u8])> = vec![("res://b.txt", b"replaced"), ("res://new.txt", b"x")];
+        let sizes: Vec<(&str, u64)> = files.iter().map(|(p, d)| (*p, d.len() as u64)).collect();
+        let plan = plan_apply(&mut file, &header, &index, &sizes, false).unwrap();
+        assert_eq!(plan.replaced, ["res://b.txt"]);
+        assert_eq!(plan.added, ["res://new.txt"]);
+        // b.txt 会被替换，只有 a.txt 需要搬移
+        let moved: Vec<&str> = plan.moves.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(moved, ["res://a.txt"]);
+        assert!(!plan.keeps_layout());
+        // 搬移读写各一次，再加新数据与重写的 entry 表
+        let table: u64 = ["res://a.txt", "res://b.txt", "res://new.txt"]
+            .iter()
+            .map(|p| header.entry_layout().entry_size(normalized_path_bytes(p).len() as u32))
+            .sum();
+        assert_eq!(plan.table_bytes(), table);
+        assert_eq!(plan.io_bytes(), 3 * 2 + 9 + table);
+        assert_eq!(plan.copy_bytes(1), plan.archive_size * 2);
+
+        let before = file.metadata().unwrap().len();
+        replace_files_in_pck(&mut file, &header, &index, files).unwrap();
+        assert_eq!(file.metadata().unwrap().len() - before, plan.growth());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn progress_is_reported_per_stage_in_chunks() {
+        let dir = std::env::temp_dir().join(format!("bpb_progress_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut file = open_pck(
+            &dir.join("progress.pck"),
+            &padded_pck(1, &[("res://a.txt", b"aaa"), ("res://b.txt", b"bbbb")], 0),
+        );
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let io = IoOptions {
+            chunk_size: 4,
+            ..IoOptions::auto(0)
+        };
+
+        let events = std::cell::RefCell::new(Vec::new());
+        let record = |stage, done, total| events.borrow_mut().push((stage, done, total));
+        let files: Vec<(&str, &[u8])> = vec![("res://b.txt", b"replaced"), ("res://new.txt", b"x")];
+        replace_files_in_pck_reporting(&mut file, &header, &index, files, &[], &io, &record)
+            .unwrap();
+        let table = events.borrow().last().unwrap().2;
+        // 新增的 1 字节不足一块，不单独回调
+        assert_eq!(
+            events.into_inner(),
+            [
+                (ProgressStage::Move, 0, 3),
+                (ProgressStage::Move, 3, 3),
+                (ProgressStage::Append, 0, 9),
+                (ProgressStage::Append, 9, 9),
+                (ProgressStage::Table, 0, table),
+                (ProgressStage::Table, table, table),
+            ]
+        );
+        assert_eq!(contents(&mut file)[1].1, b"replaced");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replacements_that_fit_overwrite_their_old_data() {
+        let dir = std::env::temp_dir().join(format!("bpb_overwrite_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<(&str, &[u8])> = vec![
+            ("res://a.gde", b"old script"),
+            ("res://b.txt", b"same"),
+            ("res://c.txt", b"same"),
+        ];
+        let mut file = open_pck(&dir.join("a.pck"), &padded_pck(2, &files, 0));
+        share_identical_data(&mut file).unwrap();
+        let size = file.metadata().unwrap().len();
+
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        // 原位写入排在追加之后，追加位置的检查不能受它影响
+        let files: Vec<(&str, &[u8])> =
+            vec![("res://b.txt", b"diff"), ("res://a.gde", b"new one")];
+        let sizes: Vec<(&str, u64)> = files.iter().map(|(p, d)| (*p, d.len() as u64)).collect();
+        // 默认全部追加，不改写原有数据
+        let plan = plan_apply(&mut file, &header, &index, &sizes, false).unwrap();
+        assert!(plan.overwritten.is_empty());
+        assert_eq!(plan.new_data_bytes, 11);
+        let plan = plan_apply(&mut file, &header, &index, &sizes, true).unwrap();
+        // b.txt 与 c.txt 共用数据，只能追加
+        assert_eq!(plan.overwritten, ["res://a.gde"]);
+        assert_eq!(plan.new_data_bytes, 4);
+
+        let io = IoOptions {
+            overwrite_in_place: true,
+            ..IoOptions::auto(0)
+        };
+        replace_files_in_pck_with(&mut file, &header, &index, files, &io).unwrap();
+        assert_eq!(file.metadata().unwrap().len() - size, plan.growth());
+        let expected: Vec<(String, Vec<u8>)> = [
+            ("res://a.gde", b"new one".as_slice()),
+            ("res://b.txt", b"diff"),
+            ("res://c.txt", b"same"),
+        ]
+        .iter()
+        .map(|(p, d)| (p.to_string(), d.to_vec()))
+        .collect();
+        assert_eq!(contents(&mut file), expected);
+        assert!(verify(&mut file).unwrap().failures.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// header 后接 `paths` 对应的 entry（数据区为空）
+    fn table_bytes(file_count: u32, paths: &[&str]) -> std::io::Cursor<Vec<u8>> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        Header {
+            version: 1,
+            godot_version_major: 3,
+            godot_version_minor: 0,
+            godot_version_patch: 0,
+            flags: 0,
+            file_base: 0,
+            reserved: [0; 16],
+            file_count,
+        }
+        .write(&mut out)
+        .unwrap();
+        for path in paths {
+            let bytes = normalized_path_bytes(path);
+            out.write_all(&(bytes.len() as u32).to_le_bytes()).unwrap();
+            out.write_all(&bytes).unwrap();
+            out.write_all(&[0; 32]).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn table_limits_reject_absurd_archives() {
+        let limits = TableLimits {
+            max_entries: 2,
+            max_path_len: 16,
+        };
+        let message = |mut data: std::io::Cursor<Vec<u8>>, limits| {
+            format!("{:#}", read_table_limited(&mut data, limits, None).unwrap_err())
+        };
+
+        let ok = read_table_limited(&mut table_bytes(1, &["res://a.txt"]), limits, None).unwrap();
+        assert_eq!(ok.1.len(), 1);
+
+        assert!(message(table_bytes(3, &[]), limits).contains("超过上限 2"));
+        // 声称的数量超出文件能容纳的范围，即使关闭上限也会拒绝
+        assert!(
+            message(table_bytes(2_000_000, &[]), TableLimits::OFF).contains("最多只能容纳 0 个")
+        );
+        let long = format!("res://{}", "x".repeat(40));
+        assert!(message(table_bytes(1, &[&long]), limits).contains("路径长 48 字节"));
+        assert!(read_table_limited(&mut table_bytes(1, &[&long]), TableLimits::OFF, None).is_ok());
+
+        // 上限属于各自的存储，同一进程中互不影响
+        let three = || table_bytes(3, &["res://a.txt", "res://b.txt", "res://c.txt"]);
+        assert!(list_entries(&mut Keyed::new(three(), None).with_limits(limits)).is_err());
+        assert_eq!(list_entries(&mut Keyed::new(three(), None)).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn header_variants_are_named() {
+        let detect = |head: &[u8]| {
+            detect_header_variant(&mut std::io::Cursor::new(head.to_vec()))
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(
+            detect(b"GDPC\x01\x00\x00\x00rest"),
+            Ok(HeaderVariant::V1LittleEndian)
+        );
+        assert!(detect(b"GDPC\x00\x00\x00\x01").unwrap_err().contains("大端"));
+        assert!(detect(b"CPDG\x00\x00\x00\x01").unwrap_err().contains("CPDG"));
+        assert_eq!(
+            detect(b"GDPC\x02\x00\x00\x00"),
+            Ok(HeaderVariant::V2LittleEndian)
+        );
+        assert!(detect(b"GDPC\x03\x00\x00\x00").unwrap_err().contains("版本 3"));
+        assert!(detect(b"MZ\x90\x00\x03\x00\x00\x00").unwrap_err().contains("不是 PCK"));
+        assert!(detect(b"GDP").unwrap_err().contains("3 字节"));
+    }
+
+    /// 模拟其他打包工具的输出：表与数据区之间留 `gap` 字节填充，数据按 16 字节对齐
+    fn padded_pck(version: u32, files: &[(&str, &[u8])], gap: u64) -> Vec<u8> {
+        let mut header = Header {
+            version,
+            godot_version_major: if version >= 2 { 4 } else { 3 },
+            godot_version_minor: 5,
+            godot_version_patch: 2,
+            flags: 0,
+            file_base: 0,
+            reserved: [0; 16],
+            file_count: files.len() as u32,
+        };
+        let paths: Vec<Vec<u8>> = files.iter().map(|(p, _)| normalized_path_bytes(p)).collect();
+        let layout = header.entry_layout();
+        let table_size: u64 = paths.iter().map(|p| layout.entry_size(p.len() as u32)).sum();
+        let align = |offset: u64| offset.next_multiple_of(16);
+
+        let mut offsets = Vec::new();
+        let mut data_offset = align(header.size() + table_size + gap);
+        if version >= 2 {
+            header.file_base = data_offset;
+        }
+        for (_, data) in files {
+            offsets.push(data_offset);
+            data_offset = align(data_offset + data.len() as u64);
+        }
+
+        let mut out = Vec::new();
+        header
+            .write_le(&mut std::io::Cursor::new(&mut out))
+            .unwrap();
+        for ((path, (_, data)), offset) in paths.iter().zip(files).zip(&offsets) {
+            out.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            out.extend_from_slice(path);
+            out.extend_from_slice(&(offset - header.file_base).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            out.extend_from_slice(&md5::compute(data).0);
+            if version >= 2 {
+                out.extend_from_slice(&0u32.to_le_bytes());
+            }
+        }
+        for ((_, data), offset) in files.iter().zip(&offsets) {
+            out.resize(*offset as usize, 0xAA);
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    fn open_pck(path: &Path, bytes: &[u8]) -> File {
+        fs::write(path, bytes).unwrap();
+        OpenOptions::new().read(true).write(true).open(path).unwrap()
+    }
+
+    fn contents<S: PckStorage>(file: &mut S) -> Vec<(String, Vec<u8>)> {
+        let (_, index) = read_header_and_index(file).unwrap();
+        let mut paths: Vec<&String> = index.keys().collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|p| (p.clone(), read_file_data(file, &index, p).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn extraction_stays_inside_the_output_folder() {
+        let dir = std::env::temp_dir().join(format!("bpb_extract_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("evil.pck");
+        let files: Vec<(&str, &[u8])> =
+            vec![("res://ok.txt", b"ok"), ("res://../escape.txt", b"x")];
+        fs::write(&pck, padded_pck(1, &files, 0)).unwrap();
+
+        let out = dir.join("out");
+        assert!(extract_files(&pck, &[], &out).is_err());
+        // 检查在写出任何文件之前进行
+        assert!(!out.exists());
+        assert_eq!(extract_files(&pck, &["ok.txt"], &out).unwrap().files, 1);
+        assert_eq!(fs::read(out.join("ok.txt")).unwrap(), b"ok");
+        assert!(!dir.join("escape.txt").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extraction_brings_import_companions_along() {
+        let dir = std::env::temp_dir().join(format!("bpb_extract_pairs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("game.pck");
+        let import = b"[remap]\nimporter=\"texture\"\n\
+                       path=\"res://.godot/imported/icon.png-1.ctex\"\n\
+                       \n[deps]\nsource_file=\"res://icon.png\"\n";
+        let files: Vec<(&str, &[u8])> = vec![
+            ("res://icon.png.import", import),
+            ("res://.godot/imported/icon.png-1.ctex", b"GST2"),
+            ("res://other.txt", b"other"),
+        ];
+        fs::write(&pck, padded_pck(2, &files, 0)).unwrap();
+
+        // 导出的游戏中原图不在 PCK 里，按配对文件导出
+        let out = dir.join("out");
+        let extracted = extract_files(&pck, &["icon.png"], &out).unwrap();
+        assert_eq!(extracted.files, 2);
+        assert_eq!(
+            extracted.pairs,
+            vec![ImportPair {
+                source: "res://icon.png".to_string(),
+                companion: "res://icon.png.import".to_string(),
+                targets: vec!["res://.godot/imported/icon.png-1.ctex".to_string()],
+            }]
+        );
+        assert_eq!(fs::read(out.join(".godot/imported/icon.png-1.ctex")).unwrap(), b"GST2");
+        assert!(!out.join("other.txt").exists());
+        assert!(extract_files(&pck, &["missing.png"], &out).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn globs_select_entries_by_pattern() {
+        for (pattern, path, expected) in [
+            ("res://Core/*.gde", "res://Core/Game.gde", true),
+            ("Core/*.gde", "res://Core/UI/Menu.gde", false),
+            ("**/*.png", "res://icon.png", true),
+            ("res://UI/**/*.png", "res://UI/a/b/close.png", true),
+            ("res://UI/**", "res://Core/Game.gde", false),
+            ("res://level_?.tscn", "res://level_1.tscn", true),
+            ("res://level_?.tscn", "res://level_10.tscn", false),
+            ("res://[a-c]*.txt", "res://bravo.txt", true),
+            ("res://[!a-c]*.txt", "res://bravo.txt", false),
+            ("res://[]x].txt", "res://].txt", true),
+            ("res://[oops.txt", "res://[oops.txt", true),
+        ] {
+            assert_eq!(glob_match(pattern, path), expected, "{} vs {}", pattern, path);
+        }
+        assert!(is_glob("**/*.png") && !is_glob("res://Core/Game.gde"));
+
+        let dir = std::env::temp_dir().join(format!("bpb_extract_glob_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("game.pck");
+        let files: Vec<(&str, &[u8])> = vec![
+            ("res://Core/Game.gde", b"game"),
+            ("res://Core/UI/Menu.gde", b"menu"),
+            ("res://icon.png", b"png"),
+        ];
+        fs::write(&pck, padded_pck(1, &files, 0)).unwrap();
+
+        let out = dir.join("out");
+        let extracted = extract_files(&pck, &["Core/*.gde", "res://Core/Game.gde"], &out);
+        assert_eq!(extracted.unwrap().files, 1);
+        assert!(!out.join("Core/UI/Menu.gde").exists());
+        assert_eq!(extract_files(&pck, &["**/*.gde"], &out).unwrap().files, 2);
+        let error = extract_files(&pck, &["**/*.ogg"], &out).unwrap_err();
+        assert!(error.to_string().contains("没有匹配"), "{}", error);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn logical_paths_resolve_through_remap_companions() {
+        let dir = std::env::temp_dir().join(format!("bpb_remap_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<(&str, &[u8])> = vec![
+            ("res://Game.gd.remap", b"[remap]\n\npath=\"res://Game.gdc\"\n"),
+            ("res://Game.gdc", b"GDSC"),
+            ("res://plain.txt", b"plain"),
+        ];
+        let mut file = open_pck(&dir.join("remap.pck"), &padded_pck(1, &files, 0));
+        let (_, index) = read_header_and_index(&mut file).unwrap();
+
+        assert_eq!(
+            resolve_remap(&mut file, &index, "res://Game.gd").unwrap(),
+            Some(vec!["res://Game.gdc".to_string()])
+        );
+        assert_eq!(resolve_remap(&mut file, &index, "res://plain.txt").unwrap(), None);
+        assert_eq!(resolve_remap(&mut file, &index, "res://missing.png").unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unchanged_header_and_table_are_not_rewritten() {
+        let dir = std::env::temp_dir().join(format!("bpb_unchanged_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.pck");
+        let files: Vec<(&str, &[u8])> = vec![("res://a.txt", b"aaa"), ("res://b.txt", b"bb")];
+        drop(open_pck(&path, &padded_pck(1, &files, 0)));
+
+        // 只读句柄：任何写入都会失败
+        let mut file = File::open(&path).unwrap();
+        let (header, mut records) = read_table(&mut file).unwrap();
+        let region = table_region(&mut file).unwrap();
+        let unchanged: Vec<&EntryRecord> = records.iter().collect();
+        write_header_and_table(&mut file, &header, region, &unchanged).unwrap();
+
+        records[1].entry.size = 1;
+        let changed: Vec<&EntryRecord> = records.iter().collect();
+        assert!(write_header_and_table(&mut file, &header, region, &changed).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn listing_keeps_the_last_duplicate_in_table_order() {
+        let dir = std::env::temp_dir().join(format!("bpb_list_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<(&str, &[u8])> =
+            vec![("res://b.txt", b"old"), ("res://a.txt", b"a"), ("res://b.txt", b"newer")];
+        let mut file = open_pck(&dir.join("dup.pck"), &padded_pck(1, &files, 0));
+
+        let entries = list_entries(&mut file).unwrap();
+        let listed: Vec<(&str, u64)> = entries.iter().map(|e| (e.path.as_str(), e.size)).collect();
+        assert_eq!(listed, [("res://a.txt", 1), ("res://b.txt", 5)]);
+        // 重复的 entry 交给诊断去向，而不是直接打印
+        let diagnostics = Arc::new(DiagnosticsBuffer::default());
+        let scope = scope_diagnostics(diagnostics.clone());
+        let (_, index) = read_header_and_index(&mut file).unwrap();
+        drop(scope);
+        assert_eq!(index["res://b.txt"], entries[1].table_offset);
+        assert!(diagnostics.take().iter().any(|diagnostic| matches!(
+            diagnostic,
+            Diagnostic::DuplicateEntry { path } if path == "res://b.txt"
+        )));
+        assert_eq!(entries[1].md5, md5::compute(b"newer").0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn salvaging_drops_entries_past_the_end_and_an_unreadable_tail() {
+        let dir = std::env::temp_dir().join(format!("bpb_salvage_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<(&str, &[u8])> =
+            vec![("res://a.txt", b"aaaa"), ("res://b.txt", b"bbbb"), ("res://c.txt", b"cccc")];
+        let mut bytes = padded_pck(1, &files, 0);
+        // v1 的 entry 各占 48 字节：b 的数据偏移指向文件之外，c 的路径长度错乱
+        let entry = |i: usize| 88 + 48 * i;
+        bytes[entry(1) + 16..entry(1) + 24].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        bytes[entry(2)..entry(2) + 4].copy_from_slice(&0xffff_ff00u32.to_le_bytes());
+        let mut file = open_pck(&dir.join("broken.pck"), &bytes);
+        assert!(read_header_and_index(&mut file).is_err());
+
+        let found = check_table(&mut file).unwrap();
+        assert_eq!((found.claimed, found.kept, found.lost()), (3, 1, 1));
+        assert_eq!(found.unreadable_from, Some(2));
+        assert_eq!(found.dropped.len(), 1);
+        assert_eq!(found.dropped[0].path, "res://b.txt");
+        assert!(matches!(found.dropped[0].reason, DropReason::DataPastEnd { .. }));
+
+        let fixed = salvage_table(&mut file).unwrap();
+        assert_eq!(fixed.kept, 1);
+        assert_eq!(contents(&mut file), [("res://a.txt".to_string(), b"aaaa".to_vec())]);
+        assert!(check_table(&mut file).unwrap().is_intact());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repacking_into_the_source_folder_skips_the_output() {
+        let dir = std::env::temp_dir().join(format!("bpb_repack_{}", std::process::id()));
+        fs::create_dir_all(dir.join("Core")).unwrap();
+        fs::write(dir.join("Core/Game.gde"), b"extends Node").unwrap();
+        let out = dir.join("override.pck");
+
+        assert_eq!(pack_directory(&dir, &out, &PackOptions::new()).unwrap(), 1);
+        assert_eq!(pack_directory(&dir, &out, &PackOptions::new()).unwrap(), 1);
+        let (_, index) = read_header_and_index(&mut File::open(&out).unwrap()).unwrap();
+        assert!(!index.contains_key("res://override.pck"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_reports_expected_and_actual_digests() {
+        let dir = std::env::temp_dir().join(format!("bpb_verify_md5_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut bytes = padded_pck(1, &[("res://a.txt", b"aaa"), ("res://b.txt", b"bbb")], 0);
+        *bytes.last_mut().unwrap() = b'x';
+        let mut file = open_pck(&dir.join("bad.pck"), &bytes);
+
+        let report = verify(&mut file).unwrap();
+        assert_eq!(report.checked_entries, 2);
+        assert_eq!(report.failures.len(), 1);
+        let failure = &report.failures[0];
+        assert_eq!(failure.path, "res://b.txt");
+        assert_eq!(failure.expected, md5::compute(b"bbb").0);
+        assert_eq!(failure.actual, Some(md5::compute(b"bbx").0));
+        assert!(failure.reason.contains(&hex_digest(&failure.expected)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn packs_in_memory_are_edited_like_files() {
+        let files: Vec<(&str, &[u8])> = vec![("res://a.txt", b"old"), ("res://b.txt", b"bee")];
+        let mut pck = std::io::Cursor::new(padded_pck(2, &files, 0));
+
+        let (header, index) = read_header_and_index(&mut pck).unwrap();
+        let edits = vec![
+            ("res://a.txt", b"replacement".as_slice()),
+            ("res://c.txt", b"new"),
+        ];
+        replace_files_in_pck(&mut pck, &header, &index, edits).unwrap();
+        let (header, index) = read_header_and_index(&mut pck).unwrap();
+        delete_files_in_pck(&mut pck, &header, &index, vec!["res://b.txt"]).unwrap();
+        compact(&mut pck, &IoOptions::auto(0)).unwrap();
+
+        let (_, index) = read_header_and_index(&mut pck).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            read_file_data(&mut pck, &index, "res://a.txt").unwrap(),
+            b"replacement"
+        );
+        assert_eq!(
+            read_file_data(&mut pck, &index, "res://c.txt").unwrap(),
+            b"new"
+        );
+
+        let mut out = std::io::Cursor::new(Vec::new());
+        let report = repack(&mut pck, &mut out, &IoOptions::auto(0)).unwrap();
+        assert_eq!(report.size_after, out.get_ref().len() as u64);
+        let (_, repacked) = read_header_and_index(&mut out).unwrap();
+        for path in ["res://a.txt", "res://c.txt"] {
+            assert_eq!(
+                read_file_data(&mut out, &repacked, path).unwrap(),
+                read_file_data(&mut pck, &index, path).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn compaction_packs_shared_data_and_truncates() {
+        let dir = std::env::temp_dir().join(format!("bpb_compact_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for version in [1, 2] {
+            let files: Vec<(&str, &[u8])> = vec![
+                ("res://a.txt", b"same"),
+                ("res://b.txt", b"gone"),
+                ("res://c.txt", b"same"),
+            ];
+            let path = dir.join(format!("v{}.pck", version));
+            let mut file = open_pck(&path, &padded_pck(version, &files, 64));
+            share_identical_data(&mut file).unwrap();
+            let (header, index) = read_header_and_index(&mut file).unwrap();
+            delete_files_in_pck(&mut file, &header, &index, vec!["res://b.txt"]).unwrap();
+            let before = contents(&mut file);
+
+            let io = IoOptions::auto(0);
+            let report = compact(&mut file, &io).unwrap();
+            // 只剩 a 与 c 共享的一份数据，紧跟在 entry 表之后
+            let region = table_region(&mut file).unwrap();
+            assert_eq!(report.size_after, region.end + 4);
+            assert_eq!(file.metadata().unwrap().len(), report.size_after);
+            assert_eq!(contents(&mut file), before);
+            assert!(verify(&mut file).unwrap().failures.is_empty());
+            // 已经紧密排列时不再移动
+            assert_eq!(compact(&mut file, &io).unwrap().reclaimed(), 0);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 每次写入后记录完整内容，用来检查在任意一次写入后中断时的文件
+    struct Recorded {
+        inner: std::io::Cursor<Vec<u8>>,
+        states: Vec<Vec<u8>>,
+    }
+
+    impl Read for Recorded {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for Recorded {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let n = self.inner.write(buf)?;
+            self.states.push(self.inner.get_ref().clone());
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Recorded {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    impl PckStorage for Recorded {
+        fn size(&mut self) -> std::io::Result<u64> {
+            self.inner.size()
+        }
+
+        fn set_size(&mut self, len: u64) -> std::io::Result<()> {
+            self.inner.set_size(len)?;
+            self.states.push(self.inner.get_ref().clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn compaction_can_stop_after_any_write() {
+        for version in [1, 2] {
+            let (a, b, c) = ([b'a'; 300], [b'b'; 300], [b'c'; 300]);
+            let files: Vec<(&str, &[u8])> =
+                vec![("res://a.txt", &a), ("res://b.txt", &b), ("res://c.txt", &c)];
+            let mut file = std::io::Cursor::new(padded_pck(version, &files, 64));
+            let (header, index) = read_header_and_index(&mut file).unwrap();
+            delete_files_in_pck(&mut file, &header, &index, vec!["res://b.txt"]).unwrap();
+            let before = contents(&mut file);
+
+            // a 只前移 64 字节，与原位置重叠；c 前移超过自身大小
+            let mut file = Recorded {
+                inner: file,
+                states: Vec::new(),
+            };
+            let io = IoOptions {
+                chunk_size: 32,
+                ..IoOptions::auto(0)
+            };
+            assert_eq!(compact(&mut file, &io).unwrap().moved_entries, 2);
+            assert!(file.states.len() > 20);
+            for state in file.states {
+                assert_eq!(contents(&mut std::io::Cursor::new(state)), before);
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_operations_leave_a_valid_pck() {
+        let dir = std::env::temp_dir().join(format!("bpb_cancel_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cancel.pck");
+        let mut file = open_pck(
+            &path,
+            &padded_pck(1, &[("res://a.txt", b"aaa"), ("res://b.txt", b"bbbb")], 64),
+        );
+        let before = contents(&mut file);
+        let cancel = CancelToken::new();
+
+        // 搬移完成后、写入新数据之前取消：entry 表未被改写
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let files: Vec<(&str, &[u8])> = vec![("res://new/added.txt", b"x")];
+        let on_progress = |stage, _, _| {
+            if stage == ProgressStage::Append {
+                cancel.cancel();
+            }
+        };
+        let io = IoOptions {
+            cancel: cancel.clone(),
+            ..IoOptions::auto(0)
+        };
+        let err = replace_files_in_pck_reporting(
+            &mut file,
+            &header,
+            &index,
+            files,
+            &[],
+            &io,
+            &on_progress,
+        )
+        .unwrap_err();
+        assert!(err.is::<Cancelled>());
+        assert_eq!(contents(&mut file), before);
+
+        let err = compact(&mut file, &io).unwrap_err();
+        assert!(err.is::<Cancelled>());
+        assert_eq!(contents(&mut file), before);
+        let options = ExtractOptions {
+            cancel,
+            ..ExtractOptions::default()
+        };
+        let err = extract_files_with(&path, &[], dir.join("out"), &options).unwrap_err();
+        assert!(err.is::<Cancelled>());
+
+        assert!(compact(&mut file, &IoOptions::auto(0)).unwrap().reclaimed() > 0);
+        assert_eq!(contents(&mut file), before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repacking_gives_the_same_bytes_for_the_same_contents() {
+        let dir = std::env::temp_dir().join(format!("bpb_repack_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let io = IoOptions::auto(0);
+        for version in [1, 2] {
+            // 同样的最终内容：一个经过多次修改，一个直接打包
+            let path = dir.join(format!("edited_v{}.pck", version));
+            let mut edited = open_pck(&path, &padded_pck(version, &[("res://b.txt", b"bb")], 0));
+            for (path, data) in [
+                ("res://b.txt", b"longer b".as_slice()),
+                ("res://a.txt", b"aaa"),
+                ("res://c.txt", b"gone"),
+                ("res://b.txt", b"final b"),
+            ] {
+                let (header, index) = read_header_and_index(&mut edited).unwrap();
+                replace_files_in_pck(&mut edited, &header, &index, vec![(path, data)]).unwrap();
+            }
+            let (header, index) = read_header_and_index(&mut edited).unwrap();
+            delete_files_in_pck(&mut edited, &header, &index, vec!["res://c.txt"]).unwrap();
+            let fresh_files: Vec<(&str, &[u8])> =
+                vec![("res://b.txt", b"final b"), ("res://a.txt", b"aaa")];
+            let mut fresh = open_pck(
+                &dir.join(format!("fresh_v{}.pck", version)),
+                &padded_pck(version, &fresh_files, 32),
+            );
+
+            let mut outputs = Vec::new();
+            for (name, file) in [("a", &mut edited), ("b", &mut fresh)] {
+                let out_path = dir.join(format!("repacked_{}_v{}.pck", name, version));
+                let mut out = open_pck(&out_path, b"stale bytes longer than nothing");
+                let report = repack(file, &mut out, &io).unwrap();
+                assert_eq!(report.entries, 2);
+                assert_eq!(contents(&mut out), contents(file));
+                assert!(verify(&mut out).unwrap().failures.is_empty());
+                outputs.push(fs::read(&out_path).unwrap());
+            }
+            assert_eq!(outputs[0], outputs[1]);
+            // 表之后紧跟按路径排序的数据
+            assert!(outputs[0].ends_with(b"aaafinal b"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn padded_archives_keep_their_table_at_the_header() {
+        let dir = std::env::temp_dir().join(format!("bpb_padded_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut file = open_pck(
+            &dir.join("padded.pck"),
+            &padded_pck(1, &[("res://a.txt", b"aaa"), ("res://b.txt", b"bbbb")], 64),
+        );
+        let region = table_region(&mut file).unwrap();
+        assert_eq!(region, TableRegion { start: 88, end: 88 + 2 * 48 });
+
+        // 新 entry 放得进填充区时不需要搬移数据
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        assert!(ranges_to_move(&mut file, &index, &["res://c.txt"]).unwrap().is_empty());
+        replace_files_in_pck(
+            &mut file,
+            &header,
+            &index,
+            vec![("res://c.txt", b"c"), ("res://a.txt", b"new a")],
+        )
+        .unwrap();
+
+        // 再新增多个 entry，表越过填充区后原有数据被搬走
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let many: Vec<String> = (0..4).map(|i| format!("res://more/{}.txt", i)).collect();
+        let new_paths: Vec<&str> = many.iter().map(String::as_str).collect();
+        assert!(!ranges_to_move(&mut file, &index, &new_paths).unwrap().is_empty());
+        replace_files_in_pck(
+            &mut file,
+            &header,
+            &index,
+            many.iter().map(|p| (p.as_str(), b"m".as_slice())).collect(),
+        )
+        .unwrap();
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        delete_files_in_pck(&mut file, &header, &index, vec!["res://b.txt"]).unwrap();
+
+        let result = contents(&mut file);
+        assert_eq!(result.len(), 6);
+        assert_eq!(result[0], ("res://a.txt".to_string(), b"new a".to_vec()));
+        assert_eq!(result[1], ("res://c.txt".to_string(), b"c".to_vec()));
+        assert!(result[2..].iter().all(|(_, data)| data == b"m"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dropped_duplicate_at_table_start_is_overwritten() {
+        let dir = std::env::temp_dir().join(format!("bpb_dup_start_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // 第一个 entry 是被后面同名 entry 取代的旧版本，索引中不包含它
+        let mut file = open_pck(
+            &dir.join("dup.pck"),
+            &padded_pck(
+                1,
+                &[("res://a.txt", b"old"), ("res://b.txt", b"b"), ("res://a.txt", b"newer")],
+                0,
+            ),
+        );
+
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        replace_files_in_pck(&mut file, &header, &index, vec![("res://c.txt", b"c")]).unwrap();
+
+        let (_, records) = read_table(&mut file).unwrap();
+        let paths: Vec<&str> = records.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["res://b.txt", "res://a.txt", "res://c.txt"]);
+        assert_eq!(records[0].table_offset, 88);
+        assert_eq!(contents(&mut file)[0].1, b"newer");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn v2_archives_round_trip_through_edits() {
+        let dir = std::env::temp_dir().join(format!("bpb_v2_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut bytes = padded_pck(2, &[("res://a.txt", b"aaa"), ("res://b.txt", b"bbbb")], 8);
+        // 给 b.txt 一个本工具不认识的标志位：修改时应原样保留
+        let b_flags = 100 + 52 + 4 + 12 + 32;
+        bytes[b_flags] = 0x80;
+        let mut file = open_pck(&dir.join("v2.pck"), &bytes);
+
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(header.version, 2);
+        let region = table_region(&mut file).unwrap();
+        assert_eq!(region, TableRegion { start: 100, end: 100 + 2 * 52 });
+        replace_files_in_pck(
+            &mut file,
+            &header,
+            &index,
+            vec![("res://a.txt", b"new a"), ("res://dir/c.txt", b"c")],
+        )
+        .unwrap();
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        rename_entries(
+            &mut file,
+            &header,
+            &index,
+            vec![("res://dir/c.txt", "res://a_much_longer_directory/c.txt")],
+            &IoOptions::auto(0),
+        )
+        .unwrap();
+
+        let (header, records) = read_table(&mut file).unwrap();
+        assert_eq!((header.version, header.file_count), (2, 3));
+        let flags: Vec<(&str, u32)> = records
+            .iter()
+            .map(|r| (r.path.as_str(), r.entry.flags))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                ("res://a.txt", 0),
+                ("res://b.txt", 0x80),
+                ("res://a_much_longer_directory/c.txt", 0),
+            ]
+        );
+        let (_, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(read_file_data(&mut file, &index, "res://a.txt").unwrap(), b"new a");
+        assert_eq!(
+            read_file_data(&mut file, &index, "res://a_much_longer_directory/c.txt").unwrap(),
+            b"c"
+        );
+        assert_eq!(read_file_data(&mut file, &index, "res://b.txt").unwrap(), b"bbbb");
+
+        delete_files_in_pck(&mut file, &header, &index, vec!["res://b.txt"]).unwrap();
+        assert_eq!(contents(&mut file).len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_file_base_is_kept_through_edits() {
+        let dir = std::env::temp_dir().join(format!("bpb_rel_base_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut bytes = padded_pck(2, &[("res://a.txt", b"aaaa"), ("res://b.txt", b"bb")], 0);
+        // magic + version + 三段 Godot 版本号之后是 PCK 标志位
+        bytes[20..24].copy_from_slice(&PACK_REL_FILEBASE.to_le_bytes());
+        let mut file = open_pck(&dir.join("rel.pck"), &bytes);
+
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let files: Vec<(&str, &[u8])> =
+            vec![("res://a.txt", b"a longer replacement"), ("res://c.txt", b"c")];
+        replace_files_in_pck(&mut file, &header, &index, files).unwrap();
+        let (header, _) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(header.flags, PACK_REL_FILEBASE);
+
+        compact(&mut file, &IoOptions::auto(0)).unwrap();
+        let (header, _) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(header.flags, PACK_REL_FILEBASE);
+        assert_eq!(header.file_base, table_region(&mut file).unwrap().end);
+        assert_eq!(
+            contents(&mut file),
+            vec![
+                ("res://a.txt".to_string(), b"a longer replacement".to_vec()),
+                ("res://b.txt".to_string(), b"bb".to_vec()),
+                ("res://c.txt".to_string(), b"c".to_vec()),
+            ]
+        );
+        let report = verify_pck(&dir.join("rel.pck"), &VerifyOptions::default()).unwrap();
+        assert!(report.failures.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removal_flag_hides_entries_without_shrinking_the_table() {
+        let dir = std::env::temp_dir().join(format!("bpb_removal_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: [(&str, &[u8]); 3] =
+            [("res://a.txt", b"aaaa"), ("res://b.txt", b"bb"), ("res://c.txt", b"c")];
+        let bytes = padded_pck(2, &files, 0);
+        let mut file = open_pck(&dir.join("v2.pck"), &bytes);
+
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let mode = DeleteMode::MarkRemoved;
+        delete_files_in_pck_with(&mut file, &header, &index, vec!["res://b.txt"], mode).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), bytes.len() as u64);
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(header.file_count, 3);
+        assert!(!index.contains_key("res://b.txt"));
+        let paths: Vec<String> = contents(&mut file).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, ["res://a.txt", "res://c.txt"]);
+        let report = verify_pck(&dir.join("v2.pck"), &VerifyOptions::default()).unwrap();
+        assert!(report.failures.is_empty());
+
+        // 重新写入同一路径时，带删除标志的旧 entry 随表重写一起去掉
+        replace_files_in_pck(&mut file, &header, &index, vec![("res://b.txt", b"new b")]).unwrap();
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(header.file_count, 3);
+        assert_eq!(read_file_data(&mut file, &index, "res://b.txt").unwrap(), b"new b");
+
+        let mut v1 = open_pck(&dir.join("v1.pck"), &padded_pck(1, &files, 0));
+        let (header, index) = read_header_and_index(&mut v1).unwrap();
+        let error = delete_files_in_pck_with(&mut v1, &header, &index, vec!["res://a.txt"], mode)
+            .unwrap_err();
+        assert!(error.to_string().contains("Godot 4.4"), "{}", error);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entry_digests_are_streamed_in_chunks() {
+        let dir = std::env::temp_dir().join(format!("bpb_digest_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // 跨越多个读取块
+        let big: Vec<u8> = (0..DIGEST_CHUNK * 2 + 7).map(|i| (i % 251) as u8).collect();
+        let files: [(&str, &[u8]); 2] = [("res://big.ogv", &big), ("res://small.txt", b"abc")];
+        let mut file = open_pck(&dir.join("digest.pck"), &padded_pck(2, &files, 0));
+
+        let (_, index) = read_header_and_index(&mut file).unwrap();
+        for (path, data) in files {
+            assert_eq!(read_file_md5(&mut file, &index, path).unwrap(), md5::compute(data).0);
+        }
+        assert_eq!(
+            digest_reader(big.as_slice()).unwrap(),
+            (md5::compute(&big).0, big.len() as u64)
+        );
+        assert!(read_file_md5(&mut file, &index, "res://missing.txt").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn godot4_packs_are_written_as_v2() {
+        let dir = std::env::temp_dir().join(format!("bpb_pack_v2_{}", std::process::id()));
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        fs::write(dir.join("assets/a.txt"), b"hello").unwrap();
+        let out = dir.join("game.pck");
+        let options = PackOptions::new().godot_version(4, 2, 1);
+        pack_directory(dir.join("assets"), &out, &options).unwrap();
+
+        let mut file = File::open(&out).unwrap();
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!((header.version, header.file_base), (2, 100 + 4 + 12 + 32 + 4));
+        assert_eq!(header.flags, PACK_REL_FILEBASE);
+        assert_eq!(read_file_data(&mut file, &index, "res://a.txt").unwrap(), b"hello");
+        assert!(verify_pck(&out, &VerifyOptions::default()).unwrap().failures.is_empty());
+
+        // 嵌入时 file_base 仍相对 PCK 起点
+        fs::write(dir.join("template.exe"), b"MZ").unwrap();
+        let exe = dir.join("game.exe");
+        pack_directory(dir.join("assets"), &exe, &options.embed_into(dir.join("template.exe")))
+            .unwrap();
+        let bytes = fs::read(&exe).unwrap();
+        assert_eq!(&bytes[8..12], b"GDPC");
+        let file_base = u64::from_le_bytes(bytes[8 + 24..8 + 32].try_into().unwrap());
+        assert_eq!(file_base, header.file_base);
+        assert_eq!(&bytes[8 + file_base as usize..][..5], b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn aes_cfb_matches_reference_vector() {
+        // NIST SP 800-38A F.3.17 CFB128-AES256.Encrypt 第一个分组
+        let key: EncryptionKey = "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"
+            .parse()
+            .unwrap();
+        let iv: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let mut block = *b"\x6b\xc1\xbe\xe2\x2e\x40\x9f\x96\xe9\x3d\x7e\x11\x73\x93\x17\x2a";
+        aes_cfb(&key, iv, &mut block, true);
+        assert_eq!(hex_digest(&block), "dc7e84bfda79164b7ecd8486985d3860");
+
+        let sealed = encrypt_block(&key, b"hello");
+        assert_eq!(sealed.len() as u64, encrypted_size(5));
+        assert_eq!(decrypt_block(&mut sealed.as_slice(), &key, "x").unwrap(), b"hello");
+        let other: EncryptionKey = "00".repeat(32).parse().unwrap();
+        let error = decrypt_block(&mut sealed.as_slice(), &other, "x").unwrap_err();
+        assert!(error.to_string().contains("密钥不正确"));
+        assert!("abc".parse::<EncryptionKey>().is_err());
+    }
+
+    /// 模拟 Godot 4 加密导出：entry 表加密，secret.txt 的数据也加密
+    fn encrypted_pck(key: &EncryptionKey) -> Vec<u8> {
+        let files: [(&str, &[u8], bool); 2] = [
+            ("res://plain.txt", b"plain", false),
+            ("res://secret.txt", b"secret data", true),
+        ];
+        let mut header = Header {
+            version: 2,
+            godot_version_major: 4,
+            godot_version_minor: 2,
+            godot_version_patch: 0,
+            flags: PACK_DIR_ENCRYPTED,
+            file_base: 0,
+            reserved: [0; 16],
+            file_count: files.len() as u32,
+        };
+        let layout = header.entry_layout();
+        let table_size: u64 = files
+            .iter()
+            .map(|(p, _, _)| layout.entry_size(normalized_path_bytes(p).len() as u32))
+            .sum();
+        header.file_base = header.size() + encrypted_size(table_size);
+
+        let mut table = Vec::new();
+        let mut data = Vec::new();
+        for (path, content, encrypted) in files {
+            let stored = if encrypted {
+                encrypt_block(key, content)
+            } else {
+                content.to_vec()
+            };
+            let path_bytes = normalized_path_bytes(path);
+            table.extend_from_slice(&(path_bytes.len() as u32).to_le_bytes());
+            table.extend_from_slice(&path_bytes);
+            table.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            table.extend_from_slice(&(content.len() as u64).to_le_bytes());
+            table.extend_from_slice(&md5::compute(content).0);
+            table.extend_from_slice(&(encrypted as u32 * PACK_FILE_ENCRYPTED).to_le_bytes());
+            data.extend_from_slice(&stored);
+        }
+
+        let mut out = Vec::new();
+        header
+            .write_le(&mut std::io::Cursor::new(&mut out))
+            .unwrap();
+        out.extend_from_slice(&encrypt_block(key, &table));
+        out.extend_from_slice(&data);
+        out
+    }
+
+    #[test]
+    fn encrypted_archives_need_the_right_key() {
+        let dir = std::env::temp_dir().join(format!("bpb_encrypted_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key: EncryptionKey = "1f".repeat(32).parse().unwrap();
+        let path = dir.join("encrypted.pck");
+        let file = open_pck(&path, &encrypted_pck(&key));
+
+        let missing = read_header_and_index(&mut file.try_clone().unwrap()).unwrap_err();
+        assert!(format!("{:#}", missing).contains("需要提供导出时使用的加密密钥"));
+        let wrong_key = Some("2e".repeat(32).parse().unwrap());
+        let wrong = read_header_and_index(&mut Keyed::new(file.try_clone().unwrap(), wrong_key))
+            .unwrap_err();
+        assert!(format!("{:#}", wrong).contains("密钥不正确"));
+
+        // 密钥属于各自的 PCK，不同密钥的 PCK 可以同时打开
+        let other_key: EncryptionKey = "4d".repeat(32).parse().unwrap();
+        let other_path = dir.join("other.pck");
+        let mut other = Keyed::new(
+            open_pck(&other_path, &encrypted_pck(&other_key)),
+            Some(other_key),
+        );
+        let mut file = Keyed::new(file, Some(key));
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let (_, other_index) = read_header_and_index(&mut other).unwrap();
+        assert_eq!(
+            read_file_data(&mut other, &other_index, "res://secret.txt").unwrap(),
+            b"secret data"
+        );
+        assert_eq!(
+            read_file_data(&mut file, &index, "res://secret.txt").unwrap(),
+            b"secret data"
+        );
+
+        // 替换加密的 entry 并新增 entry，表增长后原有数据被搬走
+        let added = format!("res://{}.txt", "added".repeat(8));
+        replace_files_in_pck(
+            &mut file,
+            &header,
+            &index,
+            vec![("res://secret.txt", b"new secret"), (&added, b"added")],
+        )
+        .unwrap();
+        let (_, records) = read_table(&mut file).unwrap();
+        let encrypted: Vec<&str> = records
+            .iter()
+            .filter(|r| r.entry.is_encrypted())
+            .map(|r| r.path.as_str())
+            .collect();
+        assert_eq!(encrypted, vec!["res://secret.txt"]);
+        let result = contents(&mut file);
+        assert_eq!(result[0], (added.clone(), b"added".to_vec()));
+        assert_eq!(result[1].1, b"plain");
+        assert_eq!(result[2].1, b"new secret");
+        let verify_options = VerifyOptions {
+            encryption_key: Some(key),
+            ..VerifyOptions::default()
+        };
+        assert!(verify_pck(&path, &verify_options).unwrap().failures.is_empty());
+
+        // MOD 新增的文件与原来未加密的文件都可以加密写入
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let files: Vec<(&str, &[u8])> =
+            vec![("res://art.png", b"commissioned"), ("res://plain.txt", b"hidden now")];
+        replace_files_in_pck_encrypting(
+            &mut file,
+            &header,
+            &index,
+            files.clone(),
+            &["res://art.png", "res://plain.txt"],
+            &IoOptions::auto(0),
+        )
+        .unwrap();
+        let (_, records) = read_table(&mut file).unwrap();
+        assert!(records.iter().all(|r| r.entry.is_encrypted() || r.path == added));
+        let (_, index) = read_header_and_index(&mut file).unwrap();
+        assert_eq!(
+            read_file_data(&mut file, &index, "res://art.png").unwrap(),
+            b"commissioned"
+        );
+        assert!(verify_pck(&path, &verify_options).unwrap().failures.is_empty());
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+        let io = IoOptions::auto(0);
+        let error =
+            replace_files_in_pck_encrypting(&mut file, &header, &index, files, &["res://x"], &io)
+                .unwrap_err();
+        assert!(error.to_string().contains("不在要写入的文件中"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_inputs_are_streamed_in_chunks() {
+        let dir = std::env::temp_dir().join(format!("bpb_stream_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<(&str, &[u8])> = vec![("res://big.bin", b"small")];
+        let mut file = open_pck(&dir.join("a.pck"), &padded_pck(2, &files, 0));
+        let (header, index) = read_header_and_index(&mut file).unwrap();
+
+        let big: Vec<u8> = (0..100u8).collect();
+        fs::write(dir.join("big.bin"), &big).unwrap();
+        let io = IoOptions {
+            chunk_size: 7,
+            ..IoOptions::auto(0)
+        };
+        let inputs = [
+            PackInput::File(dir.join("big.bin")),
+            PackInput::Data(b"added".to_vec()),
+        ];
+        let files = vec![("res://big.bin", &inputs[0]), ("res://added.txt", &inputs[1])];
+        replace_inputs_in_pck(&mut file, &header, &index, files, &io).unwrap();
+        let expected = vec![
+            ("res://added.txt".to_string(), b"added".to_vec()),
+            ("res://big.bin".to_string(), big.clone()),
+        ];
+        assert_eq!(contents(&mut file), expected);
+        assert!(verify(&mut file).unwrap().failures.is_empty());
+
+        // 分段加密与整块加密结果相同
+        let key: EncryptionKey = "3c".repeat(32).parse().unwrap();
+        let mut whole = big.clone();
+        aes_cfb(&key, [7; 16], &mut whole, true);
+        let mut chunked = big;
+        let mut cfb = Cfb::new(&key, [7; 16]);
+        for chunk in chunked.chunks_mut(32) {
+            cfb.apply(chunk, true);
+        }
+        assert_eq!(chunked, whole);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archive_stages_changes_until_commit() {
+        let dir = std::env::temp_dir().join(format!("bpb_archive_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.pck");
+        let files: &[(&str, &[u8])] = &[("res://a.txt", b"alpha"), ("res://b.txt", b"bravo")];
+        fs::write(&path, padded_pck(1, files, 0)).unwrap();
+
+        let mut readonly = PckArchive::open(&path).unwrap();
+        readonly.replace("res://a.txt", b"A".to_vec()).unwrap();
+        assert!(readonly.commit().is_err());
+
+        let mut archive = PckArchive::edit(&path).unwrap();
+        assert!(archive.replace("res://c.txt", b"x".to_vec()).is_err());
+        assert!(archive.add("res://a.txt", b"x".to_vec()).is_err());
+        archive.add("res://c.txt", b"charlie".to_vec()).unwrap();
+        archive.replace("res://a.txt", b"ALPHA".to_vec()).unwrap();
+        archive.delete("res://b.txt").unwrap();
+        archive.add("res://d.txt", b"undone".to_vec()).unwrap();
+        archive.delete("res://d.txt").unwrap();
+
+        // 提交前读取已能看到暂存的修改，文件本身不变
+        assert_eq!(archive.read("res://a.txt").unwrap(), b"ALPHA");
+        assert!(archive.read("res://b.txt").is_err());
+        assert_eq!(archive.paths(), ["res://a.txt", "res://c.txt"]);
+        assert_eq!(fs::read(&path).unwrap(), padded_pck(1, files, 0));
+
+        archive.commit().unwrap();
+        assert!(!archive.is_dirty());
+        assert_eq!(archive.header().file_count, 2);
+        assert_eq!(archive.read("res://c.txt").unwrap(), b"charlie");
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(
+            contents(&mut file),
+            [
+                ("res://a.txt".to_string(), b"ALPHA".to_vec()),
+                ("res://c.txt".to_string(), b"charlie".to_vec()),
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn xxh64_matches_reference_vectors() {
+        let hash = |data: &[u8]| {
+            let mut hasher = Xxh64::new(0);
+            hasher.update(data);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xEF46_DB37_51D8_E999);
+        assert_eq!(hash(b"abc"), 0x44BC_2CF5_AD77_0999);
+        assert_eq!(
+            hash(b"Nobody inspects the spammish repetition"),
+            0xFBCE_A83C_8A37_8BF1
+        );
+
+        // 分段输入与一次输入结果一致
+        let data: Vec<u8> = (0..100u8).collect();
+        let mut split = Xxh64::new(0);
+        split.update(&data[..7]);
+        split.update(&data[7..45]);
+        split.update(&data[45..]);
+        assert_eq!(split.finish(), hash(&data));
+    }
 }
@@ -0,0 +1,409 @@
+//! 两阶段修改（`apply --stage`）的待提交日志 `<文件名>.pending`。
+//!
+//! 暂存时在临时副本上完成整个修改，再与原 PCK 逐块比较：超出原文件长度的部分（新数据、搬移的数据）
+//! 直接追加到 PCK 末尾，原有字节的改动（header、entry 表）记入日志而不写入 PCK。
+//! 追加的数据不被任何 entry 引用，游戏读到的内容在提交之前不变。
+//!
+//! `commit` 把日志中的改动写入 PCK 后删除日志；提交中断时可以再次运行，已写入的部分会被识别并跳过。
+//! `abort` 截掉追加的数据并删除日志，PCK 恢复为暂存前的字节。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+
+use crate::provenance::ProvenanceStore;
+
+const MAGIC: &[u8; 8] = b"BPBSTAGE";
+const FORMAT_VERSION: u32 = 1;
+/// 逐块比较时的块大小
+const CHUNK_SIZE: usize = 1024 * 1024;
+/// 相距不超过这么多字节的改动合并为一段，避免 entry 表被拆成大量零碎的写入
+const MERGE_GAP: u64 = 4096;
+
+/// 日志中的一段改动：提交时写入 `offset` 处
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Patch {
+    offset: u64,
+    /// PCK 中这段字节暂存时的 MD5，提交前核对，发现其他程序的改动
+    before: [u8; 16],
+    data: Vec<u8>,
+}
+
+/// 一次暂存的修改
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pending {
+    /// 暂存前的 PCK 大小，放弃时截回这个长度
+    pub base_len: u64,
+    /// 追加数据后的 PCK 大小
+    pub staged_len: u64,
+    /// 提交后的 PCK 大小；修改中删除了数据时小于 `base_len`
+    pub final_len: u64,
+    /// 暂存时间（Unix 秒）
+    pub created_at: u64,
+    patches: Vec<Patch>,
+    /// 提交时写入的来源记录，为空时不写
+    provenance: String,
+}
+
+impl Pending {
+    /// 提交时改写的原有字节数
+    pub fn patch_bytes(&self) -> u64 {
+        self.patches.iter().map(|p| p.data.len() as u64).sum()
+    }
+
+    pub fn patch_count(&self) -> usize {
+        self.patches.len()
+    }
+
+    /// 已追加到 PCK 末尾、提交后才被引用的字节数
+    pub fn appended_bytes(&self) -> u64 {
+        self.staged_len - self.base_len
+    }
+}
+
+pub fn journal_path(pck: &Path) -> PathBuf {
+    let mut path = pck.to_path_buf().into_os_string();
+    path.push(".pending");
+    PathBuf::from(path)
+}
+
+/// 有尚未提交的暂存修改时拒绝其他修改，否则之后的提交会覆盖它们
+pub fn ensure_none(pck: &Path) -> Result<()> {
+    let journal = journal_path(pck);
+    if journal.exists() {
+        bail!(
+            "{} 有尚未提交的暂存修改（{}），请先运行 commit 或 abort",
+            pck.display(),
+            journal.display()
+        );
+    }
+    Ok(())
+}
+
+/// 把 `staged`（在 `pck` 的副本上完成的修改）转为 `pck` 末尾追加的数据与待提交日志；
+/// `provenance` 为提交后的来源记录
+pub fn stage(pck: &Path, staged: &Path, provenance: String) -> Result<Pending> {
+    ensure_none(pck)?;
+    let mut live = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pck)
+        .with_context(|| format!("无法打开: {}", pck.display()))?;
+    let mut copy = File::open(staged).with_context(|| format!("无法打开: {}", staged.display()))?;
+    let base_len = live.metadata()?.len();
+    let final_len = copy.metadata()?.len();
+
+    let spans = changed_spans(&mut live, &mut copy, base_len.min(final_len))?;
+    let mut patches = Vec::with_capacity(spans.len());
+    for (offset, len) in spans {
+        patches.push(Patch {
+            offset,
+            before: md5::compute(read_at(&mut live, offset, len)?).0,
+            data: read_at(&mut copy, offset, len)?,
+        });
+    }
+
+    if final_len > base_len {
+        copy.seek(SeekFrom::Start(base_len))?;
+        live.seek(SeekFrom::Start(base_len))?;
+        io::copy(&mut (&mut copy).take(final_len - base_len), &mut live)
+            .with_context(|| format!("无法追加数据到: {}", pck.display()))?;
+        live.sync_all()
+            .with_context(|| format!("无法写入磁盘: {}", pck.display()))?;
+    }
+
+    let pending = Pending {
+        base_len,
+        staged_len: base_len.max(final_len),
+        final_len,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        patches,
+        provenance,
+    };
+    let journal = journal_path(pck);
+    let mut partial = journal.clone().into_os_string();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let mut out = File::create(&partial)
+        .with_context(|| format!("无法写入暂存日志: {}", partial.display()))?;
+    out.write_all(&pending.encode())?;
+    out.sync_all()?;
+    fs::rename(&partial, &journal)
+        .with_context(|| format!("无法写入暂存日志: {}", journal.display()))?;
+    Ok(pending)
+}
+
+/// 读取 `pck` 的待提交日志，没有暂存修改时返回 None
+pub fn load(pck: &Path) -> Result<Option<Pending>> {
+    let journal = journal_path(pck);
+    let bytes = match fs::read(&journal) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("无法读取暂存日志: {}", journal.display()));
+        }
+    };
+    Pending::decode(&bytes)
+        .map(Some)
+        .with_context(|| format!("暂存日志已损坏: {}", journal.display()))
+}
+
+/// 把暂存的改动写入 `pck`，写入并落盘后删除日志
+pub fn commit(pck: &Path) -> Result<Pending> {
+    let pending = load(pck)?.with_context(|| format!("{} 没有暂存的修改", pck.display()))?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pck)
+        .with_context(|| format!("无法打开: {}", pck.display()))?;
+    let len = file.metadata()?.len();
+    // 上次提交在截断之后中断时，文件已经是提交后的长度
+    if len != pending.staged_len && len != pending.final_len {
+        bail!(
+            "{} 在暂存后被修改过（大小 {}，应为 {}），请运行 abort 后重新 apply",
+            pck.display(),
+            len,
+            pending.staged_len
+        );
+    }
+
+    // 先全部核对再写入：每段要么仍是暂存时的内容，要么已在中断的提交中写入
+    let mut todo = Vec::new();
+    for patch in &pending.patches {
+        let current = md5::compute(read_at(&mut file, patch.offset, patch.data.len() as u64)?).0;
+        if current == patch.before {
+            todo.push(patch);
+        } else if current != md5::compute(&patch.data).0 {
+            bail!(
+                "{} 在暂存后被修改过（偏移 {} 处的数据不一致），请运行 abort 后重新 apply",
+                pck.display(),
+                patch.offset
+            );
+        }
+    }
+    for patch in todo {
+        file.seek(SeekFrom::Start(patch.offset))?;
+        file.write_all(&patch.data)
+            .with_context(|| format!("无法写入: {}", pck.display()))?;
+    }
+    if pending.final_len < len {
+        file.set_len(pending.final_len)
+            .with_context(|| format!("无法截断: {}", pck.display()))?;
+    }
+    file.sync_all()
+        .with_context(|| format!("无法写入磁盘: {}", pck.display()))?;
+
+    if !pending.provenance.is_empty()
+        && let Err(err) = fs::write(ProvenanceStore::sidecar_path(pck), &pending.provenance)
+    {
+        println!("⚠ 无法记录修改来源: {}", err);
+    }
+    let journal = journal_path(pck);
+    fs::remove_file(&journal).with_context(|| format!("无法删除: {}", journal.display()))?;
+    Ok(pending)
+}
+
+/// 截掉暂存时追加的数据并删除日志
+pub fn abort(pck: &Path) -> Result<Pending> {
+    let pending = load(pck)?.with_context(|| format!("{} 没有暂存的修改", pck.display()))?;
+    let file = OpenOptions::new()
+        .write(true)
+        .open(pck)
+        .with_context(|| format!("无法打开: {}", pck.display()))?;
+    let len = file.metadata()?.len();
+    if len != pending.staged_len {
+        bail!(
+            "{} 在暂存后被修改过（大小 {}，应为 {}），无法安全放弃；可以从备份恢复",
+            pck.display(),
+            len,
+            pending.staged_len
+        );
+    }
+    file.set_len(pending.base_len)
+        .with_context(|| format!("无法截断: {}", pck.display()))?;
+    file.sync_all()
+        .with_context(|| format!("无法写入磁盘: {}", pck.display()))?;
+
+    let journal = journal_path(pck);
+    fs::remove_file(&journal).with_context(|| format!("无法删除: {}", journal.display()))?;
+    Ok(pending)
+}
+
+/// 两个文件前 `len` 字节中内容不同的区间 `(偏移, 长度)`，相近的区间合并
+fn changed_spans(a: &mut File, b: &mut File, len: u64) -> Result<Vec<(u64, u64)>> {
+    a.seek(SeekFrom::Start(0))?;
+    b.seek(SeekFrom::Start(0))?;
+    let mut a = BufReader::new(a);
+    let mut b = BufReader::new(b);
+    let mut buf_a = vec![0u8; CHUNK_SIZE];
+    let mut buf_b = vec![0u8; CHUNK_SIZE];
+    let mut spans: Vec<(u64, u64)> = Vec::new();
+
+    let mut offset = 0;
+    while offset < len {
+        let n = (len - offset).min(CHUNK_SIZE as u64) as usize;
+        a.read_exact(&mut buf_a[..n])?;
+        b.read_exact(&mut buf_b[..n])?;
+        let differs = |i: &usize| buf_a[*i] != buf_b[*i];
+        if let Some(first) = (0..n).find(differs) {
+            let last = (0..n).rfind(differs).unwrap_or(first);
+            let start = offset + first as u64;
+            let end = offset + last as u64 + 1;
+            match spans.last_mut() {
+                Some((prev, prev_len)) if start - (*prev + *prev_len) <= MERGE_GAP => {
+                    *prev_len = end - *prev;
+                }
+                _ => spans.push((start, end - start)),
+            }
+        }
+        offset += n as u64;
+    }
+    Ok(spans)
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+impl Pending {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        for value in [
+            self.base_len,
+            self.staged_len,
+            self.final_len,
+            self.created_at,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.provenance.len() as u64).to_le_bytes());
+        out.extend_from_slice(self.provenance.as_bytes());
+        out.extend_from_slice(&(self.patches.len() as u64).to_le_bytes());
+        for patch in &self.patches {
+            out.extend_from_slice(&patch.offset.to_le_bytes());
+            out.extend_from_slice(&(patch.data.len() as u64).to_le_bytes());
+            out.extend_from_slice(&patch.before);
+            out.extend_from_slice(&patch.data);
+        }
+        // 末尾是前面全部内容的 MD5，写到一半的日志不会被当作完整的
+        let digest = md5::compute(&out).0;
+        out.extend_from_slice(&digest);
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let Some((body, digest)) = bytes.split_last_chunk::<16>() else {
+            bail!("日志不完整");
+        };
+        if md5::compute(body).0 != *digest {
+            bail!("日志校验和不符");
+        }
+        let mut reader = body;
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != *MAGIC {
+            bail!("不是暂存日志");
+        }
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != FORMAT_VERSION {
+            bail!("不支持的暂存日志版本 {}", u32::from_le_bytes(version));
+        }
+        let base_len = read_u64(&mut reader)?;
+        let staged_len = read_u64(&mut reader)?;
+        let final_len = read_u64(&mut reader)?;
+        let created_at = read_u64(&mut reader)?;
+        let mut provenance = vec![0u8; usize::try_from(read_u64(&mut reader)?)?];
+        reader.read_exact(&mut provenance)?;
+
+        let count = read_u64(&mut reader)?;
+        let mut patches = Vec::new();
+        for _ in 0..count {
+            let offset = read_u64(&mut reader)?;
+            let mut data = vec![0u8; usize::try_from(read_u64(&mut reader)?)?];
+            let mut before = [0u8; 16];
+            reader.read_exact(&mut before)?;
+            reader.read_exact(&mut data)?;
+            patches.push(Patch {
+                offset,
+                before,
+                data,
+            });
+        }
+        Ok(Self {
+            base_len,
+            staged_len,
+            final_len,
+            created_at,
+            patches,
+            provenance: String::from_utf8(provenance)?,
+        })
+    }
+}
+
+fn read_u64(reader: &mut &[u8]) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stage_then_commit_or_abort() {
+        let dir = std::env::temp_dir().join(format!("bpb_pending_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        let staged = dir.join("Game.pck.staged");
+        let original: Vec<u8> = (0..3 * CHUNK_SIZE as u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut modified = original.clone();
+        modified[10..20].copy_from_slice(b"new header");
+        modified[CHUNK_SIZE + 5] ^= 0xff;
+        modified.extend_from_slice(b"appended data");
+
+        for commit_it in [false, true] {
+            fs::write(&pck, &original).unwrap();
+            fs::write(&staged, &modified).unwrap();
+            let pending = stage(&pck, &staged, String::new()).unwrap();
+            assert_eq!(pending.patch_count(), 2);
+            assert_eq!(pending.appended_bytes(), 13);
+            // 暂存后原有字节不变，只在末尾追加
+            let live = fs::read(&pck).unwrap();
+            assert_eq!(live[..original.len()], original[..]);
+            assert!(stage(&pck, &staged, String::new()).is_err());
+
+            if commit_it {
+                commit(&pck).unwrap();
+                assert_eq!(fs::read(&pck).unwrap(), modified);
+            } else {
+                abort(&pck).unwrap();
+                assert_eq!(fs::read(&pck).unwrap(), original);
+            }
+            assert!(load(&pck).unwrap().is_none());
+        }
+
+        // 暂存后被其他程序改动时拒绝提交
+        fs::write(&pck, &original).unwrap();
+        stage(&pck, &staged, String::new()).unwrap();
+        let mut live = fs::read(&pck).unwrap();
+        live[12] = b'!';
+        fs::write(&pck, &live).unwrap();
+        let error = commit(&pck).unwrap_err();
+        assert!(error.to_string().contains("被修改过"), "{}", error);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! 与操作系统相关的小工具，尽量不引入平台相关的依赖
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+#[cfg(feature = "cli")]
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+#[cfg(feature = "cli")]
+use bpb_enhance::pool::CancelToken;
+
+/// [`cancel_on_interrupt`] 登记的取消标记；信号处理函数中只做原子操作
+#[cfg(feature = "cli")]
+static INTERRUPT: OnceLock<CancelToken> = OnceLock::new();
+
+/// 当前平台在 manifest 中的名称：windows / linux / macos
+pub fn name() -> &'static str {
+    std::env::consts::OS
+}
+
+/// `path` 所在磁盘的可用字节数，无法确定时返回 None
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+    let mut free = 0u64;
+    // SAFETY: wide 以 0 结尾，不需要的输出参数可以为空指针
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(free)
+}
+
+/// `path` 所在磁盘的可用字节数，无法确定时返回 None
+#[cfg(not(windows))]
+pub fn free_space(path: &Path) -> Option<u64> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    // POSIX 格式：表头一行，之后是 文件系统 总块数 已用 可用 ...，单位 KiB
+    let output = std::process::Command::new("df")
+        .arg("-Pk")
+        .arg(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let available_kib: u64 = stdout
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(available_kib * 1024)
+}
+
+/// 无法就地修改文件的原因，见 [`write_protection`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteProtection {
+    /// 文件设置了只读属性
+    ReadOnlyFile,
+    /// 所在的卷以只读方式挂载或有写保护（光盘、写保护的移动硬盘、只读挂载的分区等）
+    ReadOnlyVolume,
+    /// 没有写入权限（NTFS 权限、Program Files 等受保护的位置）
+    AccessDenied,
+}
+
+impl fmt::Display for WriteProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ReadOnlyFile => "文件设置了只读属性",
+            Self::ReadOnlyVolume => "所在的磁盘是只读的或有写保护",
+            Self::AccessDenied => {
+                "当前账户没有写入权限（NTFS 权限或 Program Files 等受保护的位置）"
+            }
+        })
+    }
+}
+
+/// 检查能否就地修改 `path`：文件本身可写，且同目录中能创建备份、回滚日志等文件。
+/// 只报告权限与只读卷这类不会自行消失的原因；文件被占用等其他错误留给修改流程报告
+pub fn write_protection(path: &Path) -> Option<WriteProtection> {
+    if fs::metadata(path).is_ok_and(|m| m.permissions().readonly()) {
+        return Some(WriteProtection::ReadOnlyFile);
+    }
+    // 只打开不写入，不会改动文件
+    if let Err(err) = OpenOptions::new().write(true).open(path)
+        && let Some(protection) = classify_write_error(&err)
+    {
+        return Some(protection);
+    }
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir_write_protection(dir),
+        _ => dir_write_protection(Path::new(".")),
+    }
+}
+
+/// 检查能否在目录 `dir` 中创建文件
+pub fn dir_write_protection(dir: &Path) -> Option<WriteProtection> {
+    let probe = dir.join(format!(".bpb_enhance_probe_{}", std::process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            None
+        }
+        Err(err) => classify_write_error(&err),
+    }
+}
+
+fn classify_write_error(err: &io::Error) -> Option<WriteProtection> {
+    // ERROR_WRITE_PROTECT：介质有写保护
+    #[cfg(windows)]
+    if err.raw_os_error() == Some(19) {
+        return Some(WriteProtection::ReadOnlyVolume);
+    }
+    match err.kind() {
+        io::ErrorKind::ReadOnlyFilesystem => Some(WriteProtection::ReadOnlyVolume),
+        io::ErrorKind::PermissionDenied => Some(WriteProtection::AccessDenied),
+        _ => None,
+    }
+}
+
+/// 在系统文件管理器中显示 `path`：文件会在其所在目录中被选中，目录直接打开
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub fn open_in_file_manager(path: &Path) -> Result<()> {
+    let is_file = path.is_file();
+
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("explorer");
+        if is_file {
+            let mut arg = std::ffi::OsString::from("/select,");
+            arg.push(path);
+            command.arg(arg);
+        } else {
+            command.arg(path);
+        }
+        command
+    };
+
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("open");
+        if is_file {
+            command.arg("-R");
+        }
+        command.arg(path);
+        command
+    };
+
+    // 其他桌面环境没有统一的“选中文件”方式，打开所在目录
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let mut command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(if is_file {
+            path.parent().unwrap_or(Path::new("."))
+        } else {
+            path
+        });
+        command
+    };
+
+    // explorer 成功时也可能返回非 0，只关心能否启动
+    command
+        .spawn()
+        .with_context(|| format!("无法打开文件管理器: {}", path.display()))?;
+    Ok(())
+}
+
+/// 第一次 Ctrl+C 时取消 `token`，让正在进行的操作在两块数据之间停下并回滚；
+/// 第二次按默认方式立即结束进程（就地修改留下的回滚日志会在下次运行时处理）
+#[cfg(feature = "cli")]
+pub fn cancel_on_interrupt(token: CancelToken) {
+    if INTERRUPT.set(token).is_err() {
+        return;
+    }
+    install_interrupt_handler();
+}
+
+#[cfg(all(feature = "cli", windows))]
+fn install_interrupt_handler() {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    /// CTRL_C_EVENT 与 CTRL_BREAK_EVENT；返回 0 时交给系统默认处理（结束进程）
+    extern "system" fn on_ctrl(event: u32) -> i32 {
+        let Some(token) = INTERRUPT.get().filter(|_| event <= 1) else {
+            return 0;
+        };
+        if token.is_cancelled() {
+            return 0;
+        }
+        token.cancel();
+        1
+    }
+
+    // SAFETY: 处理函数在系统创建的线程中运行，只读取已初始化的 OnceLock 并做原子写入
+    unsafe {
+        SetConsoleCtrlHandler(on_ctrl, 1);
+    }
+}
+
+#[cfg(all(feature = "cli", unix))]
+fn install_interrupt_handler() {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIG_DFL: usize = 0;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_signal(_: c_int) {
+        if let Some(token) = INTERRUPT.get() {
+            token.cancel();
+        }
+        // 恢复默认处理，再按一次 Ctrl+C 直接结束进程
+        // SAFETY: signal 是异步信号安全的
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    // SAFETY: 处理函数只做原子写入与 signal 调用，均为异步信号安全
+    unsafe {
+        signal(SIGINT, on_signal as extern "C" fn(c_int) as usize);
+    }
+}
+
+#[cfg(all(feature = "cli", not(any(windows, unix))))]
+fn install_interrupt_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_free_space_for_files_and_dirs() {
+        let dir = std::env::temp_dir();
+        assert!(free_space(&dir).is_some());
+        assert!(free_space(&dir.join("not-created-yet.pck")).is_some());
+    }
+
+    #[test]
+    fn detects_read_only_files() {
+        let dir = std::env::temp_dir().join(format!("bpb_read_only_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        fs::write(&pck, b"GDPC").unwrap();
+        assert_eq!(write_protection(&pck), None);
+        assert_eq!(dir_write_protection(&dir), None);
+        // 检查不留下探测文件
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let mut permissions = fs::metadata(&pck).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&pck, permissions.clone()).unwrap();
+        assert_eq!(write_protection(&pck), Some(WriteProtection::ReadOnlyFile));
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&pck, permissions).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+//! 有界的并行执行器：固定数量的工作线程依次领取任务，任一任务出错或被取消时其余线程不再领取新任务，
+//! 任务中的 panic 在调用线程上重新抛出，完成的任务数汇总后回调给进度显示。
+//!
+//! 解包、计算 MD5、校验与类型检测共用它，不再各自创建线程。
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+/// 取消标记：克隆后共享同一个标记，任一处调用 [`CancelToken::cancel`] 后所有持有者都能看到
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// 只有同一个标记（及其克隆）相等
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// 任务被取消时返回的错误，可以用 `err.is::<Cancelled>()` 与其他错误区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("操作已取消")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// 一组任务的执行设置：线程数、取消标记与进度回调
+pub struct Pool<'a> {
+    threads: usize,
+    cancel: CancelToken,
+    progress: Option<&'a (dyn Fn(usize, usize) + Sync)>,
+}
+
+impl<'a> Pool<'a> {
+    /// 最多使用 `threads` 个工作线程（至少 1 个）；只有 1 个时直接在调用线程上执行
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            cancel: CancelToken::new(),
+            progress: None,
+        }
+    }
+
+    /// 每个任务开始前检查 `cancel`，取消后返回 [`Cancelled`]
+    pub fn cancel_on(mut self, cancel: &CancelToken) -> Self {
+        self.cancel = cancel.clone();
+        self
+    }
+
+    /// 每完成一个任务以（已完成数, 总数）调用 `progress`，可能来自任一工作线程
+    pub fn progress(mut self, progress: &'a (dyn Fn(usize, usize) + Sync)) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// 对每一项执行 `task`，按 `items` 的顺序返回结果；出错时返回最先记录的错误
+    pub fn map<T: Sync, R: Send>(
+        &self,
+        items: &[T],
+        task: impl Fn(&T) -> Result<R> + Sync,
+    ) -> Result<Vec<R>> {
+        self.map_init(items, || (), |_, item| task(item))
+    }
+
+    /// 同 [`Self::map`]，每个工作线程在领取第一个任务时用 `init` 创建自己的状态（如独立的文件句柄），
+    /// 之后的任务复用它
+    pub fn map_init<T: Sync, S, R: Send>(
+        &self,
+        items: &[T],
+        init: impl Fn() -> S + Sync,
+        task: impl Fn(&mut S, &T) -> Result<R> + Sync,
+    ) -> Result<Vec<R>> {
+        let total = items.len();
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let error: Mutex<Option<anyhow::Error>> = Mutex::new(None);
+        let panicked: Mutex<Option<Box<dyn Any + Send>>> = Mutex::new(None);
+
+        let work = || {
+            let mut state = None;
+            let mut finished = Vec::new();
+            while !stop.load(Ordering::Relaxed) && !self.cancel.is_cancelled() {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                    task(state.get_or_insert_with(&init), item)
+                }));
+                match outcome {
+                    Ok(Ok(result)) => {
+                        finished.push((index, result));
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if let Some(progress) = self.progress {
+                            progress(done, total);
+                        }
+                    }
+                    Ok(Err(err)) => {
+                        stop.store(true, Ordering::Relaxed);
+                        lock(&error).get_or_insert(err);
+                    }
+                    Err(payload) => {
+                        stop.store(true, Ordering::Relaxed);
+                        lock(&panicked).get_or_insert(payload);
+                    }
+                }
+            }
+            finished
+        };
+
+        let workers = self.threads.min(total);
+        let mut finished = if workers <= 1 {
+            work()
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = (0..workers).map(|_| scope.spawn(work)).collect();
+                // 任务中的 panic 已被捕获，工作线程本身不会 panic
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().unwrap_or_default())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        if let Some(payload) = panicked.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            panic::resume_unwind(payload);
+        }
+        if let Some(err) = error.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            return Err(err);
+        }
+        if finished.len() < total {
+            return Err(Cancelled.into());
+        }
+        finished.sort_unstable_by_key(|(index, _)| *index);
+        Ok(finished.into_iter().map(|(_, result)| result).collect())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+
+    #[test]
+    fn runs_in_order_and_stops_on_errors_panics_and_cancel() {
+        let items: Vec<u64> = (0..100).collect();
+        let reported = AtomicUsize::new(0);
+        let progress = |done: usize, total: usize| {
+            assert_eq!(total, 100);
+            reported.fetch_max(done, Ordering::Relaxed);
+        };
+        let doubled = Pool::new(4)
+            .progress(&progress)
+            .map(&items, |n| Ok(n * 2))
+            .unwrap();
+        assert_eq!(doubled, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+        assert_eq!(reported.into_inner(), 100);
+
+        // 每个线程只初始化一次状态
+        let inits = AtomicUsize::new(0);
+        let init = || inits.fetch_add(1, Ordering::Relaxed);
+        Pool::new(3).map_init(&items, init, |_, _| Ok(())).unwrap();
+        assert!((1..=3).contains(&inits.into_inner()));
+
+        let err = Pool::new(4)
+            .map(
+                &items,
+                |n| if *n == 42 { bail!("bad {}", n) } else { Ok(()) },
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "bad 42");
+
+        let panic = panic::catch_unwind(|| {
+            Pool::new(4).map(&items, |n| {
+                assert_ne!(*n, 7, "task 7 panicked");
+                Ok(())
+            })
+        })
+        .unwrap_err();
+        assert!(
+            panic
+                .downcast_ref::<String>()
+                .unwrap()
+                .contains("task 7 panicked")
+        );
+
+        let cancel = CancelToken::new();
+        let err = Pool::new(2)
+            .cancel_on(&cancel)
+            .map(&items, |n| {
+                if *n == 10 {
+                    cancel.cancel();
+                }
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(err.is::<Cancelled>());
+        assert!(
+            Pool::new(8)
+                .map(&[] as &[u8], |_| Ok(()))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! GUI 的多套安装配置（正式版、测试分支、通过 SMB 访问的 Deck 等），每套配置记录一个游戏路径
+//! 与要应用的可选功能，保存在配置目录（见 [`crate::dirs`]）的 `bpb_enhance_profiles.toml`
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow};
+use toml::{Table, Value};
+
+use crate::dirs;
+
+const PROFILES_FILE: &str = "bpb_enhance_profiles.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub game_path: String,
+    /// 修改资源包中可选功能的开关（id -> 是否启用），未记录的按资源包的默认值
+    pub options: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Profiles {
+    /// 当前选中的配置名
+    pub active: Option<String>,
+    pub list: Vec<Profile>,
+    /// 没有选中配置时使用的可选功能开关
+    pub options: BTreeMap<String, bool>,
+}
+
+impl Profiles {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_file(PROFILES_FILE)
+    }
+
+    /// 读取配置列表；文件不存在时为空，格式错误时提示并忽略
+    pub fn load() -> Self {
+        let Some(path) = Self::path().filter(|p| p.is_file()) else {
+            return Self::default();
+        };
+
+        let result = std::fs::read_to_string(&path)
+            .with_context(|| format!("无法读取配置列表: {}", path.display()))
+            .and_then(|content| Self::parse(&content))
+            .with_context(|| format!("配置列表格式错误: {}", path.display()));
+
+        match result {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                println!("⚠ {:#}，将忽略已保存的配置", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow!("无法确定配置列表的保存位置"))?;
+        dirs::create_parent(&path)?;
+        std::fs::write(&path, self.to_toml())
+            .with_context(|| format!("无法保存配置列表: {}", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let table: Table = content.parse()?;
+        let mut profiles = Self {
+            active: table
+                .get("active")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            list: Vec::new(),
+            options: parse_choices(table.get("options"))?,
+        };
+
+        let entries = match table.get("profile") {
+            Some(value) => value
+                .as_array()
+                .ok_or_else(|| anyhow!("profile 必须是表数组"))?
+                .as_slice(),
+            None => &[],
+        };
+        for (i, entry) in entries.iter().enumerate() {
+            let field = |key: &str| {
+                entry
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("第 {} 个 profile 缺少 {}", i + 1, key))
+            };
+            let name = field("name")?;
+            profiles.upsert(&name, &field("game-path")?);
+            let choices = parse_choices(entry.get("options"))
+                .with_context(|| format!("profile {} 的 options 格式错误", name))?;
+            if let Some(profile) = profiles.list.iter_mut().find(|p| p.name == name) {
+                profile.options = choices;
+            }
+        }
+
+        if profiles
+            .active
+            .as_deref()
+            .is_some_and(|name| profiles.get(name).is_none())
+        {
+            profiles.active = None;
+        }
+        Ok(profiles)
+    }
+
+    fn to_toml(&self) -> String {
+        let mut table = Table::new();
+        if let Some(active) = &self.active {
+            table.insert("active".into(), Value::String(active.clone()));
+        }
+        if !self.options.is_empty() {
+            table.insert("options".into(), choices_table(&self.options));
+        }
+        let entries = self
+            .list
+            .iter()
+            .map(|profile| {
+                let mut entry = Table::new();
+                entry.insert("name".into(), Value::String(profile.name.clone()));
+                entry.insert("game-path".into(), Value::String(profile.game_path.clone()));
+                if !profile.options.is_empty() {
+                    entry.insert("options".into(), choices_table(&profile.options));
+                }
+                Value::Table(entry)
+            })
+            .collect();
+        table.insert("profile".into(), Value::Array(entries));
+        table.to_string()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.list.iter().find(|p| p.name == name)
+    }
+
+    pub fn active_profile(&self) -> Option<&Profile> {
+        self.active.as_deref().and_then(|name| self.get(name))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.list.iter().map(|p| p.name.clone()).collect()
+    }
+
+    /// 新增配置，同名时更新其游戏路径；新配置沿用当前的可选功能开关
+    pub fn upsert(&mut self, name: &str, game_path: &str) {
+        let options = self.options().clone();
+        match self.list.iter_mut().find(|p| p.name == name) {
+            Some(profile) => profile.game_path = game_path.to_string(),
+            None => self.list.push(Profile {
+                name: name.to_string(),
+                game_path: game_path.to_string(),
+                options,
+            }),
+        }
+    }
+
+    /// 当前配置的可选功能开关，没有选中配置时为全局的开关
+    pub fn options(&self) -> &BTreeMap<String, bool> {
+        match self.active_profile() {
+            Some(profile) => &profile.options,
+            None => &self.options,
+        }
+    }
+
+    pub fn set_option(&mut self, id: &str, enabled: bool) {
+        let active = self.active.clone();
+        let options = match self.list.iter_mut().find(|p| Some(&p.name) == active.as_ref()) {
+            Some(profile) => &mut profile.options,
+            None => &mut self.options,
+        };
+        options.insert(id.to_string(), enabled);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.list.retain(|p| p.name != name);
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+    }
+}
+
+fn parse_choices(value: Option<&Value>) -> Result<BTreeMap<String, bool>> {
+    let Some(value) = value else {
+        return Ok(BTreeMap::new());
+    };
+    value
+        .as_table()
+        .ok_or_else(|| anyhow!("options 必须是表"))?
+        .iter()
+        .map(|(id, enabled)| {
+            let enabled = enabled
+                .as_bool()
+                .ok_or_else(|| anyhow!("options.{} 必须是布尔值", id))?;
+            Ok((id.clone(), enabled))
+        })
+        .collect()
+}
+
+fn choices_table(choices: &BTreeMap<String, bool>) -> Value {
+    Value::Table(
+        choices
+            .iter()
+            .map(|(id, enabled)| (id.clone(), Value::Boolean(*enabled)))
+            .collect(),
+    )
+}
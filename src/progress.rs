@@ -0,0 +1,198 @@
+//! 供外部前端显示进度的结构化事件，每个事件是一行 JSON。
+//!
+//! `--progress json` 把事件写到 stderr；`--progress-pipe` 另外写入命名管道
+//! （Windows 上默认 `\\.\pipe\bpb_enhance`，由启动器事先创建并读取），
+//! 供无法解析 stderr 的前端使用。两处输出的事件完全相同。
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+
+/// Windows 上 `--progress-pipe` 的默认管道名
+pub const DEFAULT_PIPE: &str = r"\\.\pipe\bpb_enhance";
+
+static SINKS: Mutex<Vec<Box<dyn Write + Send>>> = Mutex::new(Vec::new());
+
+/// 一个进度事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    /// 进入某个阶段，`message` 是给用户看的说明
+    Stage { stage: &'a str, message: &'a str },
+    /// 修改计划：替换、新增与搬移的文件数，以及预计读写的字节数。
+    /// `io_bytes` 是总数，其中搬移、重写 entry 表与完整复制 PCK 的部分另外列出，
+    /// 前端可据此按阶段分配进度，而不是只按追加的数据估计
+    Plan {
+        replaced: usize,
+        added: usize,
+        moves: usize,
+        move_bytes: u64,
+        table_bytes: u64,
+        copy_bytes: u64,
+        io_bytes: u64,
+    },
+    /// 写入 PCK 时某个阶段（`move`、`append`、`table`）已完成的字节数，
+    /// 每块数据写完发一次，阶段结束时 `done_bytes == total_bytes`
+    Progress {
+        stage: &'a str,
+        done_bytes: u64,
+        total_bytes: u64,
+    },
+    /// 不影响结果的问题（如重复的 entry、MD5 与表中记录不一致）
+    Warning { message: &'a str },
+    /// 修改完成
+    Finished { pck: &'a str, entries: usize },
+    /// 命令失败
+    Failed { message: &'a str },
+}
+
+impl Event<'_> {
+    pub fn to_json(&self) -> String {
+        match self {
+            Event::Stage { stage, message } => format!(
+                "{{\"event\":\"stage\",\"stage\":{},\"message\":{}}}",
+                json_string(stage),
+                json_string(message)
+            ),
+            Event::Plan {
+                replaced,
+                added,
+                moves,
+                move_bytes,
+                table_bytes,
+                copy_bytes,
+                io_bytes,
+            } => format!(
+                "{{\"event\":\"plan\",\"replaced\":{},\"added\":{},\"moves\":{},\
+                 \"move_bytes\":{},\"table_bytes\":{},\"copy_bytes\":{},\"io_bytes\":{}}}",
+                replaced, added, moves, move_bytes, table_bytes, copy_bytes, io_bytes
+            ),
+            Event::Progress {
+                stage,
+                done_bytes,
+                total_bytes,
+            } => format!(
+                "{{\"event\":\"progress\",\"stage\":{},\"done_bytes\":{},\"total_bytes\":{}}}",
+                json_string(stage),
+                done_bytes,
+                total_bytes
+            ),
+            Event::Warning { message } => format!(
+                "{{\"event\":\"warning\",\"message\":{}}}",
+                json_string(message)
+            ),
+            Event::Finished { pck, entries } => format!(
+                "{{\"event\":\"finished\",\"pck\":{},\"entries\":{}}}",
+                json_string(pck),
+                entries
+            ),
+            Event::Failed { message } => format!(
+                "{{\"event\":\"failed\",\"message\":{}}}",
+                json_string(message)
+            ),
+        }
+    }
+}
+
+/// 命令行 `--progress json`
+pub fn enable_stderr() {
+    add_sink(Box::new(std::io::stderr()));
+}
+
+/// 命令行 `--progress-pipe`：以客户端身份连接前端创建的命名管道
+pub fn connect_pipe(path: &Path) -> Result<()> {
+    let pipe = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to connect to progress pipe: {}", path.display()))?;
+    add_sink(Box::new(pipe));
+    Ok(())
+}
+
+fn add_sink(sink: Box<dyn Write + Send>) {
+    SINKS.lock().unwrap_or_else(|e| e.into_inner()).push(sink);
+}
+
+/// 把事件写到所有输出；写入失败的输出（前端已关闭管道）随即移除，不影响修改本身
+pub fn emit(event: &Event) {
+    let mut sinks = SINKS.lock().unwrap_or_else(|e| e.into_inner());
+    if sinks.is_empty() {
+        return;
+    }
+    let line = event.to_json() + "\n";
+    sinks.retain_mut(|sink| {
+        sink.write_all(line.as_bytes())
+            .and_then(|_| sink.flush())
+            .is_ok()
+    });
+}
+
+/// 转义为 JSON 字符串字面量
+pub fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            other => out.push(other),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_serialize_as_single_json_lines() {
+        let stage = Event::Stage {
+            stage: "write",
+            message: "正在写入 \"Game.pck\"\n",
+        };
+        assert_eq!(
+            stage.to_json(),
+            r#"{"event":"stage","stage":"write","message":"正在写入 \"Game.pck\"\n"}"#
+        );
+        let plan = Event::Plan {
+            replaced: 2,
+            added: 1,
+            moves: 1,
+            move_bytes: 100,
+            table_bytes: 96,
+            copy_bytes: 0,
+            io_bytes: 4096,
+        };
+        assert_eq!(
+            plan.to_json(),
+            r#"{"event":"plan","replaced":2,"added":1,"moves":1,"move_bytes":100,"table_bytes":96,"copy_bytes":0,"io_bytes":4096}"#
+        );
+        let progress = Event::Progress {
+            stage: "move",
+            done_bytes: 1024,
+            total_bytes: 4096,
+        };
+        assert_eq!(
+            progress.to_json(),
+            r#"{"event":"progress","stage":"move","done_bytes":1024,"total_bytes":4096}"#
+        );
+        let warning = Event::Warning {
+            message: "PCK 中存在重复的 entry: res://a",
+        };
+        assert_eq!(
+            warning.to_json(),
+            r#"{"event":"warning","message":"PCK 中存在重复的 entry: res://a"}"#
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! 导出游戏的 `res://project.binary`（ECFG 格式的项目设置）的读取与修改，
+//! 让 MOD 用 replace.toml 的 `[project-settings]` 改窗口大小、功能开关等设置，而不必替换整个文件。
+//!
+//! 格式：`ECFG` + 设置数(u32)，每项为 键长(u32) + 以 NUL 结尾的 UTF-8 键 + 值长(u32) + Variant 编码的值。
+//! 只解码布尔、整数、浮点数与字符串；其他类型原样保留，写回时不变。
+
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+
+/// 项目设置在 PCK 中的路径
+pub const PROJECT_BINARY_PATH: &str = "res://project.binary";
+
+const MAGIC: &[u8; 4] = b"ECFG";
+/// Variant 类型编号，Godot 3 与 4 相同
+const TYPE_BOOL: u32 = 1;
+const TYPE_INT: u32 = 2;
+const TYPE_FLOAT: u32 = 3;
+const TYPE_STRING: u32 = 4;
+/// 类型头中的标志位：整数或浮点数按 64 位存储
+const ENCODE_FLAG_64: u32 = 1 << 16;
+
+/// 一项设置的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// 不解码的类型：包括类型头在内的原始编码
+    Other(Vec<u8>),
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Setting::Bool(value) => write!(f, "{}", value),
+            Setting::Int(value) => write!(f, "{}", value),
+            Setting::Float(value) => write!(f, "{}", value),
+            Setting::String(value) => write!(f, "{:?}", value),
+            Setting::Other(data) => write!(f, "<{} 字节的其他类型>", data.len()),
+        }
+    }
+}
+
+impl Setting {
+    /// replace.toml 中的标量值
+    pub fn from_toml(value: &toml::Value) -> Option<Self> {
+        Some(match value {
+            toml::Value::Boolean(value) => Setting::Bool(*value),
+            toml::Value::Integer(value) => Setting::Int(*value),
+            toml::Value::Float(value) => Setting::Float(*value),
+            toml::Value::String(value) => Setting::String(value.clone()),
+            _ => return None,
+        })
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        let header = read_u32(data, 0)?;
+        let wide = header & ENCODE_FLAG_64 != 0;
+        Ok(match header & 0xff {
+            TYPE_BOOL => Setting::Bool(read_u32(data, 4)? != 0),
+            TYPE_INT if wide => Setting::Int(i64::from_le_bytes(read_array(data, 4)?)),
+            TYPE_INT => Setting::Int(read_u32(data, 4)? as i32 as i64),
+            TYPE_FLOAT if wide => Setting::Float(f64::from_le_bytes(read_array(data, 4)?)),
+            TYPE_FLOAT => Setting::Float(f32::from_le_bytes(read_array(data, 4)?) as f64),
+            TYPE_STRING => {
+                let len = read_u32(data, 4)? as usize;
+                let bytes = data.get(8..8 + len).context("字符串值不完整")?;
+                Setting::String(String::from_utf8(bytes.to_vec()).context("字符串值不是 UTF-8")?)
+            }
+            _ => Setting::Other(data.to_vec()),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Setting::Bool(value) => {
+                out.extend_from_slice(&TYPE_BOOL.to_le_bytes());
+                out.extend_from_slice(&(*value as u32).to_le_bytes());
+            }
+            Setting::Int(value) => match i32::try_from(*value) {
+                Ok(narrow) => {
+                    out.extend_from_slice(&TYPE_INT.to_le_bytes());
+                    out.extend_from_slice(&narrow.to_le_bytes());
+                }
+                Err(_) => {
+                    out.extend_from_slice(&(TYPE_INT | ENCODE_FLAG_64).to_le_bytes());
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            },
+            // 与引擎一样，能无损表示为 f32 时按 32 位存储
+            Setting::Float(value) if *value as f32 as f64 == *value => {
+                out.extend_from_slice(&TYPE_FLOAT.to_le_bytes());
+                out.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+            Setting::Float(value) => {
+                out.extend_from_slice(&(TYPE_FLOAT | ENCODE_FLAG_64).to_le_bytes());
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            Setting::String(value) => {
+                out.extend_from_slice(&TYPE_STRING.to_le_bytes());
+                out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                out.extend_from_slice(value.as_bytes());
+                out.resize(out.len().next_multiple_of(4), 0);
+            }
+            Setting::Other(data) => out.extend_from_slice(data),
+        }
+        out
+    }
+}
+
+/// 按原顺序保存的全部设置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectSettings {
+    entries: Vec<(String, Setting)>,
+}
+
+impl ProjectSettings {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.get(..4) != Some(MAGIC.as_slice()) {
+            bail!("不是有效的 project.binary（缺少 ECFG 标识）");
+        }
+        let count = read_u32(data, 4)?;
+        let mut pos = 8;
+        let mut entries = Vec::new();
+        for i in 0..count {
+            let key_len = read_u32(data, pos)? as usize;
+            let key = data
+                .get(pos + 4..pos + 4 + key_len)
+                .with_context(|| format!("第 {} 项设置的键不完整", i + 1))?;
+            pos += 4 + key_len;
+            let key = String::from_utf8(key.strip_suffix(b"\0").unwrap_or(key).to_vec())
+                .with_context(|| format!("第 {} 项设置的键不是 UTF-8", i + 1))?;
+
+            let value_len = read_u32(data, pos)? as usize;
+            let value = data
+                .get(pos + 4..pos + 4 + value_len)
+                .with_context(|| format!("设置 {} 的值不完整", key))?;
+            pos += 4 + value_len;
+            let value = Setting::decode(value).with_context(|| format!("无法解析设置 {}", key))?;
+            entries.push((key, value));
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in &self.entries {
+            out.extend_from_slice(&(key.len() as u32 + 1).to_le_bytes());
+            out.extend_from_slice(key.as_bytes());
+            out.push(0);
+            let value = value.encode();
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(&value);
+        }
+        out
+    }
+
+    #[cfg(test)]
+    fn get(&self, key: &str) -> Option<&Setting> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// 修改已有设置，或在末尾新增；返回原来的值
+    pub fn set(&mut self, key: &str, value: Setting) -> Option<Setting> {
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, existing)) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.entries.push((key.to_string(), value));
+                None
+            }
+        }
+    }
+}
+
+fn read_array<const N: usize>(data: &[u8], pos: usize) -> Result<[u8; N]> {
+    data.get(pos..pos + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("project.binary 数据不完整")
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    read_array(data, pos).map(u32::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_values_and_keeps_unknown_types() {
+        let vector2 = [5u8, 0, 0, 0, 0, 0, 0x80, 0x3f, 0, 0, 0, 0x40].to_vec();
+        let mut original = ProjectSettings::default();
+        original.set(
+            "application/config/name",
+            Setting::String("Backpack".into()),
+        );
+        original.set("display/window/size/viewport_width", Setting::Int(1280));
+        original.set("rendering/scale", Setting::Other(vector2.clone()));
+        let bytes = original.to_bytes();
+        assert_eq!(&bytes[..4], b"ECFG");
+
+        let mut settings = ProjectSettings::parse(&bytes).unwrap();
+        assert_eq!(settings, original);
+        let old = settings.set("display/window/size/viewport_width", Setting::Int(1 << 40));
+        assert_eq!(old, Some(Setting::Int(1280)));
+        settings.set("debug/enabled", Setting::Bool(true));
+        settings.set("physics/step", Setting::Float(0.1));
+
+        let patched = ProjectSettings::parse(&settings.to_bytes()).unwrap();
+        assert_eq!(
+            patched.get("display/window/size/viewport_width"),
+            Some(&Setting::Int(1 << 40))
+        );
+        assert_eq!(patched.get("physics/step"), Some(&Setting::Float(0.1)));
+        assert_eq!(
+            patched.get("rendering/scale"),
+            Some(&Setting::Other(vector2))
+        );
+        assert!(ProjectSettings::parse(b"GDPC").is_err());
+    }
+}
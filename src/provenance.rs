@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow};
+use toml::{Table, Value};
+
+use crate::report::{EntryChange, EntryDigest, PatchReport};
+
+/// 记录文件后缀：与 PCK 放在一起，不写入 PCK 本身
+const SIDECAR_SUFFIX: &str = ".provenance.toml";
+
+/// 某个 entry 版本由哪个 MOD 在何时写入
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub mod_source: String,
+    /// Unix 时间戳（秒）
+    pub written_at: u64,
+    pub md5_hex: String,
+}
+
+/// 一个 PCK 的全部写入记录，按 res 路径索引
+#[derive(Debug, Default)]
+pub struct ProvenanceStore {
+    entries: BTreeMap<String, Provenance>,
+}
+
+impl ProvenanceStore {
+    pub fn sidecar_path(pck_path: &Path) -> PathBuf {
+        let mut name = pck_path.as_os_str().to_owned();
+        name.push(SIDECAR_SUFFIX);
+        PathBuf::from(name)
+    }
+
+    /// 读取 PCK 对应的记录文件，不存在时返回空记录
+    pub fn load(pck_path: &Path) -> Result<Self> {
+        let path = Self::sidecar_path(pck_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("无法读取来源记录: {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("来源记录格式错误: {}", path.display()))
+    }
+
+    pub fn save(&self, pck_path: &Path) -> Result<()> {
+        let path = Self::sidecar_path(pck_path);
+        std::fs::write(&path, self.to_toml())
+            .with_context(|| format!("无法写入来源记录: {}", path.display()))
+    }
+
+    /// 用一次 apply 的报告更新记录：新增/替换的 entry 记为该 MOD 写入，删除的 entry 移除记录
+    pub fn record(&mut self, report: &PatchReport, written_at: u64) {
+        for entry in &report.entries {
+            match (entry.change, entry.after) {
+                (EntryChange::Deleted, _) | (_, None) => {
+                    self.entries.remove(&entry.path);
+                }
+                (_, Some(after)) => {
+                    self.entries.insert(
+                        entry.path.clone(),
+                        Provenance {
+                            mod_source: report.mod_source.clone(),
+                            written_at,
+                            md5_hex: after.md5_hex(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// 全部记录，按路径排序
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Provenance)> {
+        self.entries.iter()
+    }
+
+    /// 查询当前 entry 版本的来源；MD5 不一致说明已被其他途径（如游戏更新）覆盖，视为未知
+    #[cfg_attr(not(feature = "cli"), allow(dead_code))]
+    pub fn lookup(&self, path: &str, current: &EntryDigest) -> Option<&Provenance> {
+        self.entries
+            .get(path)
+            .filter(|p| p.md5_hex == current.md5_hex())
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let table: Table = content.parse()?;
+        let mut entries = BTreeMap::new();
+
+        if let Some(Value::Table(items)) = table.get("entries") {
+            for (path, item) in items {
+                let field = |key: &str| {
+                    item.get(key)
+                        .ok_or_else(|| anyhow!("{} 缺少 {}", path, key))
+                };
+                let provenance = Provenance {
+                    mod_source: field("mod")?.as_str().unwrap_or_default().to_string(),
+                    written_at: field("written-at")?.as_integer().unwrap_or_default().max(0) as u64,
+                    md5_hex: field("md5")?.as_str().unwrap_or_default().to_string(),
+                };
+                entries.insert(path.clone(), provenance);
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut items = Table::new();
+        for (path, provenance) in &self.entries {
+            let mut item = Table::new();
+            item.insert("mod".into(), Value::String(provenance.mod_source.clone()));
+            item.insert(
+                "written-at".into(),
+                Value::Integer(provenance.written_at as i64),
+            );
+            item.insert("md5".into(), Value::String(provenance.md5_hex.clone()));
+            items.insert(path.clone(), Value::Table(item));
+        }
+
+        let mut table = Table::new();
+        table.insert("entries".into(), Value::Table(items));
+        format!(
+            "# 由 bpb_enhance 自动维护：记录每个 entry 当前版本由哪个 MOD 写入\n{}",
+            table
+        )
+    }
+}
+
+/// 把本次 apply 写入的 entry 记到 PCK 旁的来源记录中
+pub fn record_apply(pck_path: &Path, report: &PatchReport) -> Result<()> {
+    after_apply(pck_path, report)?.save(pck_path)
+}
+
+/// 记录了本次 apply 之后的来源记录，不写入文件；暂存的修改在提交时才写入
+pub fn after_apply(pck_path: &Path, report: &PatchReport) -> Result<ProvenanceStore> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut store = ProvenanceStore::load(pck_path)?;
+    store.record(report, now);
+    Ok(store)
+}
+
+/// Unix 时间戳格式化为 `YYYY-MM-DD HH:MM:SS UTC`
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // 公历日期换算（Howard Hinnant 的 civil_from_days）
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ReportEntry;
+
+    #[test]
+    fn record_round_trips_and_tracks_live_version() {
+        let report = PatchReport {
+            pck_path: "BackpackBattles.pck".to_string(),
+            mod_source: "mods/my_mod".to_string(),
+            game_version: "1.0.10b".to_string(),
+            plugin_version: "0.1.0".to_string(),
+            archive_size_before: 0,
+            archive_size_after: 0,
+            entries: vec![
+                ReportEntry {
+                    path: "res://Core/Game.gde".to_string(),
+                    change: EntryChange::Replaced,
+                    before: Some(EntryDigest::filled(0)),
+                    after: Some(EntryDigest::filled(1)),
+                },
+                ReportEntry {
+                    path: "res://old.txt".to_string(),
+                    change: EntryChange::Deleted,
+                    before: Some(EntryDigest::filled(2)),
+                    after: None,
+                },
+            ],
+        };
+
+        let mut store = ProvenanceStore::parse(
+            "[entries.\"res://old.txt\"]\nmod = \"other\"\nwritten-at = 1\nmd5 = \"x\"\n",
+        )
+        .unwrap();
+        store.record(&report, 1_700_000_000);
+
+        let store = ProvenanceStore::parse(&store.to_toml()).unwrap();
+        let game = store
+            .lookup("res://Core/Game.gde", &EntryDigest::filled(1))
+            .unwrap();
+        assert_eq!(game.mod_source, "mods/my_mod");
+        assert_eq!(game.written_at, 1_700_000_000);
+        assert!(
+            store
+                .lookup("res://Core/Game.gde", &EntryDigest::filled(9))
+                .is_none()
+        );
+        assert!(!store.entries.contains_key("res://old.txt"));
+    }
+
+    #[test]
+    fn format_timestamp_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_210_096), "2024-02-29 12:34:56 UTC");
+    }
+}
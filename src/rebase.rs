@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use toml::{Table, Value};
+
+use crate::diff;
+use crate::pck::TableLimits;
+use crate::report::EntryDigest;
+use crate::scaffold;
+
+/// MOD 中一个目标路径在新版本游戏里的情况
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebaseStatus {
+    /// 原文件在新版本中未变
+    Unchanged,
+    /// 原文件内容未变但路径变了，已改写到新路径
+    Moved(String),
+    /// MOD 新增的文件，新版本中也不存在
+    Added,
+    /// 原文件在新版本中被修改，需要人工检查 MOD 的改动是否仍然适用
+    OriginalChanged,
+    /// MOD 新增的文件在新版本中已有同名文件
+    NowExists,
+    /// 原文件在新版本中找不到
+    Missing,
+}
+
+impl RebaseStatus {
+    pub fn needs_attention(&self) -> bool {
+        matches!(
+            self,
+            RebaseStatus::OriginalChanged | RebaseStatus::NowExists | RebaseStatus::Missing
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RebaseEntry {
+    pub path: String,
+    pub status: RebaseStatus,
+}
+
+/// 把针对旧版本（old_pck）制作的 MOD 迁移到新版本（new_pck），结果写入 out_dir。
+/// 移动过的资源自动改写路径，原文件有变化或丢失的条目标记出来供人工处理
+pub fn rebase_mod(
+    mod_dir: &Path,
+    old_pck: &Path,
+    new_pck: &Path,
+    out_dir: &Path,
+    game_version: Option<&str>,
+    limits: TableLimits,
+) -> Result<Vec<RebaseEntry>> {
+    if out_dir.exists() && fs::read_dir(out_dir)?.next().is_some() {
+        bail!("目标目录已存在且不为空: {}", out_dir.display());
+    }
+
+    let manifest_path = mod_dir.join("replace.toml");
+    let content = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("无法读取: {}", manifest_path.display()))?;
+    let mut table: Table = content.parse().context("解析 replace.toml 失败")?;
+
+    let old = diff::read_digests(old_pck, limits)?;
+    let new = diff::read_digests(new_pck, limits)?;
+
+    let probe = scaffold::probe_pck(new_pck)?;
+    let game_version = game_version
+        .map(str::to_string)
+        .or(probe.game_version)
+        .context("无法从新 PCK 中确定游戏版本，请通过 --game-version 指定")?;
+
+    let mut entries = Vec::new();
+    let mut relocate = |path: &str| {
+        let status = classify(path, &old, &new);
+        entries.push(RebaseEntry {
+            path: path.to_string(),
+            status: status.clone(),
+        });
+        match status {
+            RebaseStatus::Moved(to) => to,
+            _ => path.to_string(),
+        }
+    };
+
+    if let Some(Value::Table(replace)) = table.get_mut("replace") {
+        *replace = std::mem::take(replace)
+            .into_iter()
+            .map(|(path, asset)| (relocate(&path), asset))
+            .collect();
+    }
+    for key in ["delete", "stub", "encrypt"] {
+        let list = match table.get_mut(key) {
+            Some(Value::Array(list)) => list,
+            Some(Value::Table(t)) => match t.get_mut("paths") {
+                Some(Value::Array(list)) => list,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        for item in list.iter_mut() {
+            if let Value::String(path) = item {
+                *path = relocate(path);
+            }
+        }
+    }
+
+    set_game_version(&mut table, &game_version, probe.game_gde_hash)?;
+
+    copy_dir(mod_dir, out_dir)?;
+    fs::write(
+        out_dir.join("replace.toml"),
+        format!(
+            "# 由 bpb_enhance rebase 从 {} 迁移到游戏版本 {}\n{}",
+            mod_dir.display(),
+            game_version,
+            table
+        ),
+    )
+    .context("无法写入 replace.toml")?;
+
+    Ok(entries)
+}
+
+fn classify(
+    path: &str,
+    old: &HashMap<String, EntryDigest>,
+    new: &HashMap<String, EntryDigest>,
+) -> RebaseStatus {
+    match (old.get(path), new.get(path)) {
+        (Some(before), Some(after)) if before == after => RebaseStatus::Unchanged,
+        (Some(_), Some(_)) => RebaseStatus::OriginalChanged,
+        (None, Some(_)) => RebaseStatus::NowExists,
+        (None, None) => RebaseStatus::Added,
+        (Some(before), None) => {
+            // 只在新版本中恰好有一个新出现的同内容文件时才认为是移动
+            let mut candidates = new
+                .iter()
+                .filter(|(p, d)| *d == before && !old.contains_key(*p));
+            match (candidates.next(), candidates.next()) {
+                (Some((to, _)), None) => RebaseStatus::Moved(to.clone()),
+                _ => RebaseStatus::Missing,
+            }
+        }
+    }
+}
+
+/// 更新 required-game-version，并在已知时登记新版本 Game.gde 的哈希
+fn set_game_version(table: &mut Table, game_version: &str, hash: Option<String>) -> Result<()> {
+    let version = table
+        .get_mut("version")
+        .and_then(Value::as_table_mut)
+        .ok_or_else(|| anyhow!("replace.toml 缺少 [version] 表"))?;
+    version.insert(
+        "required-game-version".into(),
+        Value::String(game_version.to_string()),
+    );
+
+    if let Some(hash) = hash {
+        let hashes = table
+            .entry("version-hash")
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("[version-hash] 必须是表"))?;
+        hashes.insert(game_version.to_string(), Value::String(hash));
+    }
+    Ok(())
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst).with_context(|| format!("无法创建目录: {}", dst.display()))?;
+    for entry in fs::read_dir(src).with_context(|| format!("无法读取目录: {}", src.display()))?
+    {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("无法复制: {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_targets_against_new_build() {
+        let old = HashMap::from([
+            ("res://same.tscn".to_string(), EntryDigest::filled(1)),
+            ("res://edited.tscn".to_string(), EntryDigest::filled(2)),
+            ("res://old/moved.png".to_string(), EntryDigest::filled(3)),
+            ("res://gone.png".to_string(), EntryDigest::filled(4)),
+        ]);
+        let new = HashMap::from([
+            ("res://same.tscn".to_string(), EntryDigest::filled(1)),
+            ("res://edited.tscn".to_string(), EntryDigest::filled(9)),
+            ("res://new/moved.png".to_string(), EntryDigest::filled(3)),
+            ("res://mod_added.gd".to_string(), EntryDigest::filled(5)),
+        ]);
+
+        let status = |path| classify(path, &old, &new);
+        assert_eq!(status("res://same.tscn"), RebaseStatus::Unchanged);
+        assert_eq!(status("res://edited.tscn"), RebaseStatus::OriginalChanged);
+        assert_eq!(
+            status("res://old/moved.png"),
+            RebaseStatus::Moved("res://new/moved.png".to_string())
+        );
+        assert_eq!(status("res://gone.png"), RebaseStatus::Missing);
+        assert_eq!(status("res://mod_added.gd"), RebaseStatus::NowExists);
+        assert_eq!(status("res://brand_new.gd"), RebaseStatus::Added);
+    }
+}
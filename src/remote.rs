@@ -0,0 +1,209 @@
+//! 通过 HTTP Range 请求只读访问远程 PCK，用于和托管的原版参考包比较而无需整体下载。
+//! 只支持明文 `http://`；每次读取发起一个独立请求，调用方应套一层 BufReader 合并小读取
+
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 远程文件的只读视图，实现 Read + Seek
+#[derive(Debug)]
+pub struct HttpRangeReader {
+    host: String,
+    port: u16,
+    path: String,
+    len: u64,
+    pos: u64,
+}
+
+impl HttpRangeReader {
+    /// 解析 URL 并请求第一个字节以确认服务器支持 Range 请求、获取文件大小
+    pub fn open(url: &str) -> Result<Self> {
+        if url.starts_with("https://") {
+            bail!(
+                "暂不支持 https，请使用 http 地址的镜像或先下载参考 PCK: {}",
+                url
+            );
+        }
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("不是 http 地址: {}", url))?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| anyhow!("无效的端口: {}", authority))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            bail!("URL 缺少主机名: {}", url);
+        }
+
+        let mut reader = Self {
+            host: host.to_string(),
+            port,
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+            len: 0,
+            pos: 0,
+        };
+        let (total, _) = reader
+            .fetch(0, 1)
+            .with_context(|| format!("无法读取远程 PCK: {}", url))?;
+        reader.len = total;
+        Ok(reader)
+    }
+
+    /// 请求 `[start, start + count)`，返回（文件总大小, 数据）
+    fn fetch(&self, start: u64, count: u64) -> Result<(u64, Vec<u8>)> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))
+            .with_context(|| format!("无法连接 {}:{}", self.host, self.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: {}\r\nRange: bytes={}-{}\r\nUser-Agent: bpb_enhance/{}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            start,
+            start + count - 1,
+            env!("CARGO_PKG_VERSION")
+        )?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line)?;
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| anyhow!("无效的 HTTP 响应: {}", status_line.trim()))?;
+        match status {
+            "206" => {}
+            "200" => bail!("服务器不支持 Range 请求"),
+            other => bail!("HTTP 请求失败，状态码 {}", other),
+        }
+
+        let (mut content_length, mut total) = (None, None);
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                bail!("HTTP 响应头不完整");
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<u64>().ok();
+            } else if name.eq_ignore_ascii_case("content-range") {
+                // bytes 0-0/12345
+                total = value
+                    .rsplit_once('/')
+                    .and_then(|(_, t)| t.parse::<u64>().ok());
+            }
+        }
+
+        let total = total.ok_or_else(|| anyhow!("响应缺少 Content-Range 中的文件大小"))?;
+        let length = content_length.unwrap_or(count);
+        let mut data = Vec::with_capacity(length as usize);
+        reader.take(length).read_to_end(&mut data)?;
+        if (data.len() as u64) < length {
+            bail!("响应数据不完整（{} / {} 字节）", data.len(), length);
+        }
+        Ok((total, data))
+    }
+}
+
+impl Read for HttpRangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.len {
+            return Ok(0);
+        }
+        let count = (buf.len() as u64).min(self.len - self.pos);
+        let (_, data) = self.fetch(self.pos, count).map_err(io::Error::other)?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpRangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek 到了文件开头之前"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// 只处理 Range 请求的最小 HTTP 服务器
+    fn serve(data: &'static [u8], requests: usize) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                // 读到空行为止
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                let range = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Range: bytes="))
+                    .unwrap();
+                let (start, end) = range.split_once('-').unwrap();
+                let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                let body = &data[start..=end.min(data.len() - 1)];
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    body.len(),
+                    start,
+                    end,
+                    data.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn reads_ranges_over_http() {
+        let port = serve(b"GDPC remote archive", 3);
+        let mut reader =
+            HttpRangeReader::open(&format!("http://127.0.0.1:{}/game.pck", port)).unwrap();
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 19);
+
+        reader.seek(SeekFrom::Start(5)).unwrap();
+        let mut buf = [0u8; 6];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"remote");
+
+        reader.seek(SeekFrom::End(-7)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"archive");
+
+        assert!(HttpRangeReader::open("https://example.com/game.pck").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// 单个 entry 在某一时刻的大小与 MD5
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryDigest {
+    pub size: u64,
+    pub md5: [u8; 16],
+}
+
+impl EntryDigest {
+    pub fn md5_hex(&self) -> String {
+        self.md5.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// 测试用：大小为 1、MD5 的每个字节都是 `byte`
+    #[cfg(test)]
+    pub fn filled(byte: u8) -> Self {
+        Self {
+            size: 1,
+            md5: [byte; 16],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryChange {
+    Replaced,
+    Added,
+    Deleted,
+}
+
+impl EntryChange {
+    fn label(self) -> &'static str {
+        match self {
+            EntryChange::Replaced => "替换",
+            EntryChange::Added => "新增",
+            EntryChange::Deleted => "删除",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportEntry {
+    pub path: String,
+    pub change: EntryChange,
+    pub before: Option<EntryDigest>,
+    pub after: Option<EntryDigest>,
+}
+
+/// 一次 apply 的修改摘要，可导出为 Markdown 或 HTML
+#[derive(Debug, Clone)]
+pub struct PatchReport {
+    pub pck_path: String,
+    /// 资源来源（内置资源或 assets 目录）
+    pub mod_source: String,
+    pub game_version: String,
+    pub plugin_version: String,
+    pub archive_size_before: u64,
+    pub archive_size_after: u64,
+    pub entries: Vec<ReportEntry>,
+}
+
+impl PatchReport {
+    fn count(&self, change: EntryChange) -> usize {
+        self.entries.iter().filter(|e| e.change == change).count()
+    }
+
+    fn summary_rows(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("工具版本", env!("CARGO_PKG_VERSION").to_string()),
+            ("PCK 文件", self.pck_path.clone()),
+            ("MOD 来源", self.mod_source.clone()),
+            ("游戏版本", self.game_version.clone()),
+            ("MOD 版本", self.plugin_version.clone()),
+            (
+                "PCK 大小",
+                format!(
+                    "{} -> {} 字节",
+                    self.archive_size_before, self.archive_size_after
+                ),
+            ),
+            (
+                "变更统计",
+                format!(
+                    "替换 {} / 新增 {} / 删除 {}",
+                    self.count(EntryChange::Replaced),
+                    self.count(EntryChange::Added),
+                    self.count(EntryChange::Deleted)
+                ),
+            ),
+        ]
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# 背包乱斗增强工具修改报告\n");
+
+        for (key, value) in self.summary_rows() {
+            let _ = writeln!(out, "- **{}**：{}", key, escape_markdown(&value));
+        }
+
+        let _ = writeln!(out, "\n## 变更的资源\n");
+        let _ = writeln!(out, "| 路径 | 操作 | 原大小 | 原 MD5 | 新大小 | 新 MD5 |");
+        let _ = writeln!(out, "| --- | --- | ---: | --- | ---: | --- |");
+        for entry in &self.entries {
+            let (before_size, before_md5) = digest_cells(entry.before.as_ref());
+            let (after_size, after_md5) = digest_cells(entry.after.as_ref());
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                markdown_code(&entry.path),
+                entry.change.label(),
+                before_size,
+                before_md5,
+                after_size,
+                after_md5
+            );
+        }
+
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str("<title>背包乱斗增强工具修改报告</title>\n");
+        out.push_str(
+            "<style>body{font-family:sans-serif}table{border-collapse:collapse}\
+             td,th{border:1px solid #ccc;padding:4px 8px}code{font-size:90%}</style>\n",
+        );
+        out.push_str("</head>\n<body>\n<h1>背包乱斗增强工具修改报告</h1>\n<ul>\n");
+
+        for (key, value) in self.summary_rows() {
+            let _ = writeln!(
+                out,
+                "<li><b>{}</b>：{}</li>",
+                escape_html(key),
+                escape_html(&value)
+            );
+        }
+
+        out.push_str("</ul>\n<h2>变更的资源</h2>\n<table>\n");
+        out.push_str(
+            "<tr><th>路径</th><th>操作</th><th>原大小</th><th>原 MD5</th><th>新大小</th><th>新 MD5</th></tr>\n",
+        );
+        for entry in &self.entries {
+            let (before_size, before_md5) = digest_cells(entry.before.as_ref());
+            let (after_size, after_md5) = digest_cells(entry.after.as_ref());
+            let _ = writeln!(
+                out,
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&entry.path),
+                entry.change.label(),
+                before_size,
+                before_md5,
+                after_size,
+                after_md5
+            );
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+
+        out
+    }
+
+    /// 按扩展名选择格式写出报告：`.html`/`.htm` 为 HTML，其余为 Markdown
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let is_html = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"));
+
+        let content = if is_html {
+            self.to_html()
+        } else {
+            self.to_markdown()
+        };
+
+        std::fs::write(path, content)
+            .with_context(|| format!("无法写入修改报告: {}", path.display()))
+    }
+}
+
+fn digest_cells(digest: Option<&EntryDigest>) -> (String, String) {
+    match digest {
+        Some(d) => (d.size.to_string(), d.md5_hex()),
+        None => ("-".to_string(), "-".to_string()),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Markdown 正文中的特殊字符前加反斜杠，MOD 名、路径中的 `|`、`*` 等不会被当成格式
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '|') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 表格单元格中的代码片段：分隔符比内容中最长的一串反引号更长，`|` 转义后不会拆开表格的列
+fn markdown_code(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+    let text = text.replace('|', "\\|");
+    if longest == 0 {
+        format!("{}{}{}", fence, text, fence)
+    } else {
+        format!("{} {} {}", fence, text, fence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_report() -> PatchReport {
+        PatchReport {
+            pck_path: "BackpackBattles.pck".to_string(),
+            mod_source: "assets".to_string(),
+            game_version: "1.0.10b".to_string(),
+            plugin_version: "0.6.2".to_string(),
+            archive_size_before: 100,
+            archive_size_after: 160,
+            entries: vec![
+                ReportEntry {
+                    path: "res://Core/Game.gde".to_string(),
+                    change: EntryChange::Replaced,
+                    before: Some(EntryDigest {
+                        size: 10,
+                        md5: [0xab; 16],
+                    }),
+                    after: Some(EntryDigest {
+                        size: 12,
+                        md5: [0x01; 16],
+                    }),
+                },
+                ReportEntry {
+                    path: "res://<new>.txt".to_string(),
+                    change: EntryChange::Added,
+                    before: None,
+                    after: Some(EntryDigest {
+                        size: 3,
+                        md5: [0; 16],
+                    }),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn markdown_lists_entries_with_digests() {
+        let md = sample_report().to_markdown();
+        assert!(md.contains(
+            "| `res://Core/Game.gde` | 替换 | 10 | abababababababababababababababab | 12 |"
+        ));
+        assert!(md.contains("替换 1 / 新增 1 / 删除 0"));
+    }
+
+    #[test]
+    fn markdown_escapes_paths() {
+        let mut report = sample_report();
+        report.mod_source = "a|b*".to_string();
+        report.entries[1].path = "res://a|`b`.txt".to_string();
+        let md = report.to_markdown();
+        assert!(md.contains("- **MOD 来源**：a\\|b\\*"));
+        assert!(md.contains("| `` res://a\\|`b`.txt `` | 新增 |"));
+    }
+
+    #[test]
+    fn html_escapes_paths() {
+        let html = sample_report().to_html();
+        assert!(html.contains("res://&lt;new&gt;.txt"));
+        assert!(!html.contains("res://<new>.txt"));
+    }
+}
@@ -0,0 +1,224 @@
+//! 按文件头与扩展名判断 entry 的内容类型（脚本、贴图、场景、音频、翻译、字体），
+//! 供 `list` 的输出与按类型筛选使用。
+//!
+//! 先看文件头：Godot 二进制资源（RSRC）按头部记录的资源类名归类，其余常见格式按魔数归类；
+//! 文件头无法判断（加密、文本资源、未知格式）时再看扩展名。
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::pck::PckEntryInfo;
+
+/// 判断类型所需的文件头长度，足以容纳 RSRC 头部的资源类名
+pub const HEAD_LEN: usize = 64;
+
+/// entry 的内容类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResType {
+    /// GDScript 源码、编译或加密后的脚本
+    Script,
+    Texture,
+    Scene,
+    Audio,
+    Translation,
+    Font,
+    Other,
+}
+
+impl ResType {
+    pub const ALL: [Self; 7] = [
+        Self::Script,
+        Self::Texture,
+        Self::Scene,
+        Self::Audio,
+        Self::Translation,
+        Self::Font,
+        Self::Other,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Script => "script",
+            Self::Texture => "texture",
+            Self::Scene => "scene",
+            Self::Audio => "audio",
+            Self::Translation => "translation",
+            Self::Font => "font",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// 根据文件头 `head`（可以为空或不完整）与路径判断类型
+pub fn detect(path: &str, head: &[u8]) -> ResType {
+    if let Some(kind) = from_magic(head).kind {
+        return kind;
+    }
+    let extension = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+    match extension.to_ascii_lowercase().as_str() {
+        "gd" | "gde" | "gdc" => ResType::Script,
+        "ctex" | "stex" | "png" | "webp" | "jpg" | "jpeg" | "svg" => ResType::Texture,
+        "tscn" | "scn" => ResType::Scene,
+        "oggvorbisstr" | "oggstr" | "mp3str" | "sample" | "wav" | "ogg" | "mp3" => ResType::Audio,
+        "translation" => ResType::Translation,
+        "ttf" | "otf" | "woff" | "woff2" | "fontdata" => ResType::Font,
+        _ => ResType::Other,
+    }
+}
+
+/// 文件头表明数据本身已压缩（PNG、Ogg、压缩资源等）时返回格式名，再压缩几乎没有收益
+pub fn compressed_format(head: &[u8]) -> Option<&'static str> {
+    from_magic(head).compressed
+}
+
+/// 文件头能看出的信息：内容类型与已压缩的格式名，看不出的为 None
+struct Magic {
+    kind: Option<ResType>,
+    compressed: Option<&'static str>,
+}
+
+fn from_magic(head: &[u8]) -> Magic {
+    let u32_at = |offset: usize| {
+        head.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    let (kind, compressed) = match head {
+        [b'G', b'D', b'S', b'C', ..] => (Some(ResType::Script), None),
+        [b'R', b'S', b'R', b'C', ..] => (resource_class(head).and_then(from_class), None),
+        // Godot 的压缩二进制资源与 FileAccessCompressed，类型只能看扩展名
+        [b'R', b'S', b'C', b'C', ..] => (None, Some("compressed resource")),
+        [b'G', b'C', b'P', b'F', ..] => (None, Some("compressed file")),
+        [b'P', b'K', 3, 4, ..] => (None, Some("ZIP")),
+        // Godot 4 贴图：偏移 36 处的数据格式 1 = PNG、2 = WebP、3 = Basis Universal
+        [b'G', b'S', b'T', b'2', ..] => (
+            Some(ResType::Texture),
+            matches!(u32_at(36), Some(1..=3)).then_some("texture"),
+        ),
+        // Godot 3 贴图：偏移 16 处格式字段的第 20、21 位表示无损/有损压缩
+        [b'G', b'D', b'S', b'T', ..] => (
+            Some(ResType::Texture),
+            u32_at(16)
+                .filter(|format| format & (0b11 << 20) != 0)
+                .map(|_| "texture"),
+        ),
+        [0x89, b'P', b'N', b'G', ..] => (Some(ResType::Texture), Some("PNG")),
+        [0xff, 0xd8, 0xff, ..] => (Some(ResType::Texture), Some("JPEG")),
+        [b'R', b'I', b'F', b'F', _, _, _, _, rest @ ..] if rest.starts_with(b"WEBP") => {
+            (Some(ResType::Texture), Some("WebP"))
+        }
+        [b'R', b'I', b'F', b'F', _, _, _, _, rest @ ..] if rest.starts_with(b"WAVE") => {
+            (Some(ResType::Audio), None)
+        }
+        [b'O', b'g', b'g', b'S', ..] => (Some(ResType::Audio), Some("Ogg")),
+        [b'I', b'D', b'3', ..] => (Some(ResType::Audio), Some("MP3")),
+        [b'f', b'L', b'a', b'C', ..] => (Some(ResType::Audio), None),
+        [0, 1, 0, 0, ..] | [b'O', b'T', b'T', b'O', ..] => (Some(ResType::Font), None),
+        [b'w', b'O', b'F', b'F', ..] | [b'w', b'O', b'F', b'2', ..] => (Some(ResType::Font), None),
+        _ if head.starts_with(b"[gd_scene") => (Some(ResType::Scene), None),
+        _ => (None, None),
+    };
+    Magic { kind, compressed }
+}
+
+/// RSRC 头部：魔数、大端标记、real 精度、主次版本号与格式版本各 4 字节，随后是长度 + 资源类名
+fn resource_class(head: &[u8]) -> Option<&str> {
+    let big_endian = head.get(4..8)? != [0; 4];
+    let len = head.get(24..28)?;
+    let len = if big_endian {
+        u32::from_be_bytes(len.try_into().ok()?)
+    } else {
+        u32::from_le_bytes(len.try_into().ok()?)
+    } as usize;
+    let name = head.get(28..28 + len)?;
+    // 长度包含结尾的 0
+    std::str::from_utf8(name)
+        .ok()
+        .map(|s| s.trim_end_matches('\0'))
+}
+
+fn from_class(class: &str) -> Option<ResType> {
+    let kind = match class {
+        "PackedScene" => ResType::Scene,
+        "GDScript" => ResType::Script,
+        "Translation" | "OptimizedTranslation" | "PHashTranslation" => ResType::Translation,
+        "FontFile" | "FontData" | "DynamicFontData" | "BitmapFont" | "SystemFont" => ResType::Font,
+        class if class.starts_with("AudioStream") => ResType::Audio,
+        class if class.contains("Texture") => ResType::Texture,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// 读取 entry 数据的文件头；加密的 entry 返回空，只能按扩展名判断
+pub fn read_head<R: Read + Seek>(reader: &mut R, entry: &PckEntryInfo) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    if entry.encrypted {
+        return Ok(head);
+    }
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    reader
+        .take(entry.size.min(HEAD_LEN as u64))
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rsrc(class: &str) -> Vec<u8> {
+        let mut head = b"RSRC".to_vec();
+        head.extend_from_slice(&[0; 4]);
+        head.extend_from_slice(&[0; 4]);
+        head.extend_from_slice(&4u32.to_le_bytes());
+        head.extend_from_slice(&2u32.to_le_bytes());
+        head.extend_from_slice(&5u32.to_le_bytes());
+        head.extend_from_slice(&(class.len() as u32 + 1).to_le_bytes());
+        head.extend_from_slice(class.as_bytes());
+        head.push(0);
+        head
+    }
+
+    #[test]
+    fn classifies_by_magic_then_extension() {
+        assert_eq!(detect("res://a.scn", &rsrc("PackedScene")), ResType::Scene);
+        assert_eq!(
+            detect("res://a.res", &rsrc("AudioStreamWAV")),
+            ResType::Audio
+        );
+        assert_eq!(
+            detect("res://a.res", &rsrc("Translation")),
+            ResType::Translation
+        );
+        assert_eq!(detect("res://a.res", &rsrc("Curve")), ResType::Other);
+        assert_eq!(detect("res://a.ctex", b"GST2\x01\0\0\0"), ResType::Texture);
+        assert_eq!(detect("res://data.bin", b"OggS\0\x02"), ResType::Audio);
+        assert_eq!(detect("res://data.bin", b"\0\x01\0\0\0\x10"), ResType::Font);
+        assert_eq!(
+            detect("res://a.tscn", b"[gd_scene load_steps=2"),
+            ResType::Scene
+        );
+        // 文件头认不出（加密、文本）时看扩展名
+        assert_eq!(
+            detect("res://Core/Game.gde", &[0x3c, 0x91]),
+            ResType::Script
+        );
+        assert_eq!(detect("res://fr.translation", b""), ResType::Translation);
+        assert_eq!(detect("res://Font.TTF", b""), ResType::Font);
+        assert_eq!(detect("res://readme.txt", b"hello"), ResType::Other);
+
+        let mut ctex = b"GST2".to_vec();
+        ctex.resize(40, 0);
+        assert_eq!(compressed_format(&ctex), None);
+        ctex[36] = 2;
+        assert_eq!(compressed_format(&ctex), Some("texture"));
+        assert_eq!(detect("res://a.ctex", &ctex), ResType::Texture);
+        assert_eq!(compressed_format(b"\x89PNG\r\n"), Some("PNG"));
+        assert_eq!(compressed_format(b"OggS\0"), Some("Ogg"));
+        assert_eq!(compressed_format(b"extends"), None);
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::pck;
+
+const PLUGIN_VERSION_PATH: &str = "res://plugin_version.txt";
+const GAME_GDE_PATH: &str = "res://Core/Game.gde";
+/// 示例规则最多列出的条目数
+const MAX_EXAMPLES: usize = 5;
+
+/// 从 PCK 中探测到的版本信息与示例路径
+#[derive(Debug, Default)]
+pub struct PckProbe {
+    pub game_version: Option<String>,
+    pub game_gde_hash: Option<String>,
+    pub example_paths: Vec<String>,
+}
+
+/// 生成一个新的 MOD 目录：replace.toml、README.md 与示例规则对应的资源目录
+pub fn new_mod(
+    target_dir: &Path,
+    pck_path: Option<&Path>,
+    game_version: Option<&str>,
+) -> Result<()> {
+    let name = target_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| !n.is_empty())
+        .with_context(|| format!("无效的 MOD 名称: {}", target_dir.display()))?
+        .to_string();
+
+    if target_dir.exists() && fs::read_dir(target_dir)?.next().is_some() {
+        bail!("目标目录已存在且不为空: {}", target_dir.display());
+    }
+
+    let probe = match pck_path {
+        Some(path) => probe_pck(path)?,
+        None => PckProbe::default(),
+    };
+
+    let game_version = game_version
+        .map(str::to_string)
+        .or_else(|| probe.game_version.clone())
+        .context("无法从 PCK 中确定游戏版本，请通过 --game-version 指定")?;
+
+    let example_paths = if probe.example_paths.is_empty() {
+        vec![GAME_GDE_PATH.to_string()]
+    } else {
+        probe.example_paths.clone()
+    };
+
+    fs::create_dir_all(target_dir)
+        .with_context(|| format!("无法创建目录: {}", target_dir.display()))?;
+
+    for res_path in &example_paths {
+        if let Some(parent) = Path::new(asset_path_for(res_path)).parent() {
+            fs::create_dir_all(target_dir.join(parent))
+                .with_context(|| format!("无法创建资源目录: {}", parent.display()))?;
+        }
+    }
+
+    let manifest = render_manifest(
+        &name,
+        &game_version,
+        probe.game_gde_hash.as_deref(),
+        &example_paths,
+    );
+    fs::write(target_dir.join("replace.toml"), manifest).context("无法写入 replace.toml")?;
+    fs::write(
+        target_dir.join("README.md"),
+        render_readme(&name, &game_version),
+    )
+    .context("无法写入 README.md")?;
+
+    Ok(())
+}
+
+pub fn probe_pck(pck_path: &Path) -> Result<PckProbe> {
+    let mut file =
+        File::open(pck_path).with_context(|| format!("无法打开文件: {}", pck_path.display()))?;
+    let (_, index) = pck::read_header_and_index(&mut file)
+        .with_context(|| format!("读取 PCK 头与索引失败: {}", pck_path.display()))?;
+
+    let mut probe = PckProbe::default();
+
+    if index.contains_key(PLUGIN_VERSION_PATH) {
+        // 已注入过的 PCK：版本号可信，但 Game.gde 已被替换，哈希不能作为原版哈希
+        let content = pck::read_file_data(&mut file, &index, PLUGIN_VERSION_PATH)?;
+        probe.game_version = String::from_utf8_lossy(&content)
+            .lines()
+            .next()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty());
+    } else if index.contains_key(GAME_GDE_PATH) {
+        let data = pck::read_file_data(&mut file, &index, GAME_GDE_PATH)?;
+        probe.game_gde_hash = Some(format!("{:x}", md5::compute(&data)));
+    }
+
+    let mut scripts: Vec<&String> = index
+        .keys()
+        .filter(|path| path.ends_with(".gde") && path.as_str() != GAME_GDE_PATH)
+        .collect();
+    scripts.sort();
+
+    if index.contains_key(GAME_GDE_PATH) {
+        probe.example_paths.push(GAME_GDE_PATH.to_string());
+    }
+    probe.example_paths.extend(
+        scripts
+            .into_iter()
+            .take(MAX_EXAMPLES - probe.example_paths.len())
+            .cloned(),
+    );
+
+    Ok(probe)
+}
+
+/// res://Core/Game.gde -> Core/Game.gde
+fn asset_path_for(res_path: &str) -> &str {
+    res_path.trim_start_matches("res://")
+}
+
+fn render_manifest(
+    name: &str,
+    game_version: &str,
+    game_gde_hash: Option<&str>,
+    example_paths: &[String],
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {} —— 由 bpb_enhance new-mod 生成", name);
+    let _ = writeln!(out, "# 校验: bpb_enhance lint-manifest {}", name);
+    let _ = writeln!(
+        out,
+        "# 应用: bpb_enhance apply --pck <BackpackBattles.pck> --assets {}",
+        name
+    );
+    out.push_str("\n[version]\n");
+    let _ = writeln!(out, "required-game-version = \"{}\"", game_version);
+    out.push_str("plugin-version = \"0.1.0\"\n");
+
+    out.push_str("\n# 未注入过的游戏通过 res://Core/Game.gde 的 MD5 识别版本\n[version-hash]\n");
+    match game_gde_hash {
+        Some(hash) => {
+            let _ = writeln!(out, "\"{}\" = \"{}\"", game_version, hash);
+        }
+        None => {
+            let _ = writeln!(out, "# \"{}\" = \"<Game.gde 的 MD5>\"", game_version);
+        }
+    }
+
+    out.push_str("\n# 键为 PCK 内的 res:// 路径，值为相对本目录的资源文件\n[replace]\n");
+    for res_path in example_paths {
+        let _ = writeln!(out, "# \"{}\" = \"{}\"", res_path, asset_path_for(res_path));
+    }
+
+    out.push_str(
+        "\n# 如需删除 PCK 中的文件（路径可用 * 与 ** 通配符）：\n\
+         # [delete]\n# paths = [\"res://path/to/file\", \"res://Unused/**\"]\n",
+    );
+    out.push_str(
+        "\n# 删除会导致游戏崩溃的资源可改用占位资源代替（支持 tscn/tres/png/stex/txt/csv/json）：\n\
+         # [stub]\n# paths = [\"res://path/to/scene.tscn\"]\n",
+    );
+    out
+}
+
+fn render_readme(name: &str, game_version: &str) -> String {
+    format!(
+        "# {name}\n\n\
+         适用游戏版本：{game_version}\n\n\
+         ## 说明\n\n\
+         在这里描述 MOD 的改动内容。\n\n\
+         ## 开发\n\n\
+         1. 把修改后的资源放到与 `res://` 路径对应的子目录中，并在 `replace.toml` 的 `[replace]` 表中登记。\n\
+         2. 运行 `bpb_enhance lint-manifest {name}` 检查配置。\n\
+         3. 运行 `bpb_enhance apply --pck <BackpackBattles.pck> --assets {name}` 应用到游戏。\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_template_is_valid_toml() {
+        let manifest = render_manifest(
+            "my_mod",
+            "1.0.10b",
+            Some("597baead816b32429c2ea9ac5f340ae8"),
+            &[GAME_GDE_PATH.to_string()],
+        );
+        let table: toml::value::Table = toml::from_str(&manifest).unwrap();
+
+        assert_eq!(
+            table["version"]["required-game-version"].as_str(),
+            Some("1.0.10b")
+        );
+        assert_eq!(
+            table["version-hash"]["1.0.10b"].as_str(),
+            Some("597baead816b32429c2ea9ac5f340ae8")
+        );
+        assert!(table["replace"].as_table().unwrap().is_empty());
+        assert!(manifest.contains("# \"res://Core/Game.gde\" = \"Core/Game.gde\""));
+    }
+}
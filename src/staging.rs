@@ -0,0 +1,168 @@
+//! 受管理的暂存目录：转换后的贴图、解压出的资源、下载的 MOD 等中间产物统一放在这里，
+//! 不再由各功能各自处理临时文件。
+//!
+//! 每个 [`StagingArea`] 独占一个子目录并有大小上限，离开作用域时（包括 panic 展开）自动删除；
+//! 进程被强制结束而遗留的子目录，会在之后创建暂存区时按修改时间清理。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{config, dirs};
+
+/// 默认位于缓存目录（见 [`crate::dirs`]）下，无法确定缓存目录时位于系统临时目录下
+const STAGING_DIR: &str = "bpb_enhance_staging";
+/// 单个暂存区默认最多占用的字节数
+const DEFAULT_MAX_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+/// 超过这个时间没有修改的子目录视为遗留
+const STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// 一个暂存子目录，drop 时删除
+#[derive(Debug)]
+pub struct StagingArea {
+    dir: PathBuf,
+    max_size: u64,
+    used: u64,
+}
+
+impl StagingArea {
+    /// 按设置（`[staging] dir`、`[staging] max_size`）与便携模式创建暂存区，`purpose` 用于区分子目录
+    pub fn new(purpose: &str) -> Result<Self> {
+        let settings = config::load().staging;
+        let root = settings.dir.unwrap_or_else(|| {
+            dirs::cache_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(STAGING_DIR)
+        });
+        Self::create_in(
+            &root,
+            purpose,
+            settings.max_size.unwrap_or(DEFAULT_MAX_SIZE),
+        )
+    }
+
+    pub fn create_in(root: &Path, purpose: &str, max_size: u64) -> Result<Self> {
+        sweep_stale(root);
+
+        let name = format!(
+            "{}-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            purpose
+        );
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).with_context(|| format!("无法创建暂存目录: {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            max_size,
+            used: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 登记即将写入的字节数，超出上限时报错且不登记
+    pub fn reserve(&mut self, bytes: u64) -> Result<()> {
+        let after = self.used.saturating_add(bytes);
+        if after > self.max_size {
+            bail!(
+                "暂存区空间不足：已用 {} 字节，还需 {} 字节，上限 {} 字节（可在 [staging] max_size 中调整）",
+                self.used,
+                bytes,
+                self.max_size
+            );
+        }
+        self.used = after;
+        Ok(())
+    }
+
+    /// 把外部文件复制进暂存区，返回完整路径
+    pub fn copy_in(&mut self, name: &str, source: &Path) -> Result<PathBuf> {
+        let size = fs::metadata(source)
+            .with_context(|| format!("无法读取: {}", source.display()))?
+            .len();
+        let path = self.checked_path(name)?;
+        self.reserve(size)?;
+        if let Err(err) = fs::copy(source, &path) {
+            self.used -= size;
+            let _ = fs::remove_file(&path);
+            return Err(err).with_context(|| format!("无法复制到暂存区: {}", source.display()));
+        }
+        Ok(path)
+    }
+
+    /// 删除暂存文件并归还其占用的空间
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        let path = self.checked_path(name)?;
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&path).with_context(|| format!("无法删除: {}", path.display()))?;
+        self.used = self.used.saturating_sub(size);
+        Ok(())
+    }
+
+    fn checked_path(&self, name: &str) -> Result<PathBuf> {
+        if Path::new(name)
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("无效的暂存文件名: {}", name);
+        }
+        Ok(self.dir.join(name))
+    }
+}
+
+impl Drop for StagingArea {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 删除长时间未修改的遗留子目录；任何错误都忽略
+fn sweep_stale(root: &Path) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > STALE_AFTER);
+        if stale {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforces_cap_and_cleans_up_on_drop() {
+        let root = std::env::temp_dir().join(format!("bpb_staging_{}", std::process::id()));
+        let mut area = StagingArea::create_in(&root, "test", 8).unwrap();
+        let dir = area.path().to_path_buf();
+
+        let source = root.join("source.bin");
+        fs::write(&source, b"12345").unwrap();
+        let file = area.copy_in("a.bin", &source).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"12345");
+        assert!(area.reserve(4).is_err());
+        assert_eq!(area.used, 5);
+        assert!(area.copy_in("../escape.bin", &source).is_err());
+
+        drop(area);
+        assert!(!dir.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -10,8 +10,21 @@ pub fn detect_backpack_battles_pck() -> Option<PathBuf> {
     const GAME_DIR: &str = "Backpack Battles";
     const PCK_NAME: &str = "BackpackBattles.pck";
 
-    for steam_root in steam_root_candidates() {
-        for lib_root in steam_library_roots(&steam_root) {
+    let mut roots: Vec<(PathBuf, Option<PathBuf>)> = steam_root_candidates()
+        .into_iter()
+        .map(|root| (root, None))
+        .collect();
+
+    // Windows Steam installed inside a Wine/Proton prefix.
+    #[cfg(target_os = "linux")]
+    roots.extend(
+        wine_steam_roots()
+            .into_iter()
+            .map(|(prefix, root)| (root, Some(prefix))),
+    );
+
+    for (steam_root, prefix) in roots {
+        for lib_root in steam_library_roots(&steam_root, prefix.as_deref()) {
             let candidate = lib_root
                 .join("steamapps")
                 .join("common")
@@ -26,6 +39,212 @@ pub fn detect_backpack_battles_pck() -> Option<PathBuf> {
     None
 }
 
+/// All Steam installs found on this machine, including ones inside Wine/Proton prefixes.
+#[cfg(target_os = "linux")]
+pub fn steam_roots() -> Vec<PathBuf> {
+    let mut roots = steam_root_candidates();
+    roots.extend(wine_steam_roots().into_iter().map(|(_, root)| root));
+    roots
+}
+
+/// All Steam installs found on this machine.
+#[cfg(not(target_os = "linux"))]
+pub fn steam_roots() -> Vec<PathBuf> {
+    steam_root_candidates()
+}
+
+/// Evidence that Steam is in the middle of updating the install containing a PCK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateActivity {
+    /// The appmanifest `StateFlags` report a download/update/validation in progress.
+    StateFlags(u32),
+    /// Steam's per-app `downloading`/`temp` staging folder is not empty.
+    StagingDir(PathBuf),
+    /// A partial `.patch` file sits next to the archive.
+    PatchFile(PathBuf),
+}
+
+// AppState flags from Steam's appmanifest that mean files are still being written.
+const STATE_UPDATE_RUNNING: u32 = 0x100;
+const STATE_UPDATE_PAUSED: u32 = 0x200;
+const STATE_UPDATE_STARTED: u32 = 0x400;
+const STATE_UNINSTALLING: u32 = 0x800;
+const STATE_RECONFIGURING: u32 = 0x10000;
+const STATE_VALIDATING: u32 = 0x20000;
+const STATE_ADDING_FILES: u32 = 0x40000;
+const STATE_PREALLOCATING: u32 = 0x80000;
+const STATE_DOWNLOADING: u32 = 0x100000;
+const STATE_STAGING: u32 = 0x200000;
+const STATE_COMMITTING: u32 = 0x400000;
+const STATE_UPDATE_STOPPING: u32 = 0x800000;
+
+const BUSY_STATE_MASK: u32 = STATE_UPDATE_RUNNING
+    | STATE_UPDATE_PAUSED
+    | STATE_UPDATE_STARTED
+    | STATE_UNINSTALLING
+    | STATE_RECONFIGURING
+    | STATE_VALIDATING
+    | STATE_ADDING_FILES
+    | STATE_PREALLOCATING
+    | STATE_DOWNLOADING
+    | STATE_STAGING
+    | STATE_COMMITTING
+    | STATE_UPDATE_STOPPING;
+
+/// Check whether Steam is currently downloading or updating the game that owns `pck_path`.
+///
+/// Only installs laid out as `<library>/steamapps/common/<game>/<pck>` are checked against
+/// the appmanifest; for anything else only stray `.patch` files are looked for.
+pub fn detect_update_in_progress(pck_path: &Path) -> Option<UpdateActivity> {
+    let pck_path = std::path::absolute(pck_path).ok()?;
+    let game_dir = pck_path.parent()?;
+
+    if let Some(patch) = find_patch_file(game_dir) {
+        return Some(UpdateActivity::PatchFile(patch));
+    }
+
+    let (steamapps, manifest) = manifest_for_game_dir(game_dir)?;
+
+    if manifest.state_flags & BUSY_STATE_MASK != 0 {
+        return Some(UpdateActivity::StateFlags(manifest.state_flags));
+    }
+
+    for staging in ["downloading", "temp"] {
+        let dir = steamapps.join(staging).join(&manifest.app_id);
+        if dir_has_entries(&dir) {
+            return Some(UpdateActivity::StagingDir(dir));
+        }
+    }
+
+    None
+}
+
+/// What Steam's appmanifest records about the install containing a PCK.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledBuild {
+    pub app_id: String,
+    pub build_id: Option<String>,
+    /// Total size of the installed depot files, as Steam last wrote them.
+    pub size_on_disk: Option<u64>,
+    pub game_dir: PathBuf,
+}
+
+/// Look up the appmanifest for the install that owns `pck_path`.
+///
+/// Returns `None` unless the PCK sits in `<library>/steamapps/common/<game>/`.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub fn installed_build(pck_path: &Path) -> Option<InstalledBuild> {
+    let pck_path = std::path::absolute(pck_path).ok()?;
+    let game_dir = pck_path.parent()?;
+    let (_, manifest) = manifest_for_game_dir(game_dir)?;
+    Some(InstalledBuild {
+        app_id: manifest.app_id,
+        build_id: manifest.build_id,
+        size_on_disk: manifest.size_on_disk,
+        game_dir: game_dir.to_path_buf(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AppManifest {
+    app_id: String,
+    install_dir: String,
+    state_flags: u32,
+    build_id: Option<String>,
+    size_on_disk: Option<u64>,
+}
+
+/// The `steamapps` folder and appmanifest for `<library>/steamapps/common/<game>`.
+fn manifest_for_game_dir(game_dir: &Path) -> Option<(PathBuf, AppManifest)> {
+    let common_dir = game_dir.parent()?;
+    let steamapps = common_dir.parent()?;
+    if !common_dir
+        .file_name()
+        .is_some_and(|n| n.eq_ignore_ascii_case("common"))
+    {
+        return None;
+    }
+
+    let install_dir = game_dir.file_name()?.to_string_lossy().to_string();
+    let manifest = find_app_manifest(steamapps, &install_dir)?;
+    Some((steamapps.to_path_buf(), manifest))
+}
+
+fn find_app_manifest(steamapps: &Path, install_dir: &str) -> Option<AppManifest> {
+    let entries = fs::read_dir(steamapps).ok()?;
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !(name.starts_with("appmanifest_") && name.ends_with(".acf")) {
+            continue;
+        }
+
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        if let Some(manifest) = parse_app_manifest(&content)
+            && normalize_path_key(Path::new(&manifest.install_dir))
+                == normalize_path_key(Path::new(install_dir))
+        {
+            return Some(manifest);
+        }
+    }
+
+    None
+}
+
+fn parse_app_manifest(content: &str) -> Option<AppManifest> {
+    let mut app_id = None;
+    let mut install_dir = None;
+    let mut state_flags = None;
+    let mut build_id = None;
+    let mut size_on_disk = None;
+
+    for line in content.lines() {
+        let Some((key, value)) = parse_quoted_kv_pair(line.trim()) else {
+            continue;
+        };
+
+        // Only the first occurrence counts; nested sections (e.g. InstalledDepots) come later.
+        if key.eq_ignore_ascii_case("appid") && app_id.is_none() {
+            app_id = Some(value);
+        } else if key.eq_ignore_ascii_case("installdir") && install_dir.is_none() {
+            install_dir = Some(value);
+        } else if key.eq_ignore_ascii_case("StateFlags") && state_flags.is_none() {
+            state_flags = value.parse().ok();
+        } else if key.eq_ignore_ascii_case("buildid") && build_id.is_none() {
+            build_id = Some(value);
+        } else if key.eq_ignore_ascii_case("SizeOnDisk") && size_on_disk.is_none() {
+            size_on_disk = value.parse().ok();
+        }
+    }
+
+    Some(AppManifest {
+        app_id: app_id?,
+        install_dir: install_dir?,
+        state_flags: state_flags?,
+        build_id,
+        size_on_disk,
+    })
+}
+
+fn find_patch_file(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| {
+            p.is_file()
+                && p.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("patch"))
+        })
+}
+
+fn dir_has_entries(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some())
+}
+
 fn steam_root_candidates() -> Vec<PathBuf> {
     let mut candidates: Vec<PathBuf> = Vec::new();
 
@@ -64,13 +283,24 @@ fn steam_root_candidates() -> Vec<PathBuf> {
     )
 }
 
-fn steam_library_roots(steam_root: &Path) -> Vec<PathBuf> {
+/// List library roots of a Steam install. `prefix` is set when Steam lives inside a Wine
+/// prefix, in which case the Windows-style library paths are mapped into the prefix.
+fn steam_library_roots(steam_root: &Path, prefix: Option<&Path>) -> Vec<PathBuf> {
     // Always include Steam install dir itself as a library root.
     let mut roots = vec![steam_root.to_path_buf()];
 
     let vdf_path = steam_root.join("steamapps").join("libraryfolders.vdf");
     if let Ok(content) = fs::read_to_string(&vdf_path) {
-        roots.extend(parse_libraryfolders_vdf(&content));
+        let paths = parse_libraryfolders_vdf(&content);
+        match prefix {
+            #[cfg(target_os = "linux")]
+            Some(prefix) => roots.extend(
+                paths
+                    .iter()
+                    .filter_map(|p| map_windows_path(prefix, &p.to_string_lossy())),
+            ),
+            _ => roots.extend(paths),
+        }
     }
 
     dedup_paths(roots.into_iter().filter(|p| p.is_dir()).collect())
@@ -176,19 +406,216 @@ fn home_dir() -> Option<PathBuf> {
     std::env::var_os("HOME").map(PathBuf::from)
 }
 
+/// Find Windows Steam installs inside Wine/Proton prefixes, returning `(prefix, steam_root)`.
+///
+/// The prefix registry (`system.reg`/`user.reg`) is read directly, so no `wine` binary is needed.
+#[cfg(target_os = "linux")]
+fn wine_steam_roots() -> Vec<(PathBuf, PathBuf)> {
+    // (registry file, key, value) as written by Wine; HKCU lives in user.reg, HKLM in system.reg.
+    const QUERIES: [(&str, &str, &str); 4] = [
+        ("user.reg", r"Software\\Valve\\Steam", "SteamPath"),
+        ("user.reg", r"Software\\Valve\\Steam", "InstallPath"),
+        (
+            "system.reg",
+            r"Software\\Wow6432Node\\Valve\\Steam",
+            "InstallPath",
+        ),
+        ("system.reg", r"Software\\Valve\\Steam", "InstallPath"),
+    ];
+
+    let mut found = Vec::new();
+    for prefix in wine_prefix_candidates() {
+        for (file, key, value) in QUERIES {
+            let Ok(content) = fs::read_to_string(prefix.join(file)) else {
+                continue;
+            };
+            let Some(win_path) = reg_file_value(&content, key, value) else {
+                continue;
+            };
+            if let Some(root) = map_windows_path(&prefix, &win_path)
+                && root.is_dir()
+            {
+                found.push((prefix.clone(), root));
+                break;
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(target_os = "linux")]
+fn wine_prefix_candidates() -> Vec<PathBuf> {
+    let mut prefixes: Vec<PathBuf> = Vec::new();
+
+    if let Some(p) = std::env::var_os("WINEPREFIX") {
+        prefixes.push(PathBuf::from(p));
+    }
+
+    if let Some(home) = home_dir() {
+        prefixes.push(home.join(".wine"));
+
+        // Proton keeps one prefix per app under `compatdata/<appid>/pfx`.
+        for steam_root in [home.join(".local/share/Steam"), home.join(".steam/steam")] {
+            prefixes
+                .extend(subdirs(&steam_root.join("steamapps/compatdata")).map(|d| d.join("pfx")));
+        }
+
+        prefixes.extend(subdirs(&home.join(".local/share/bottles/bottles")));
+    }
+
+    dedup_paths(
+        prefixes
+            .into_iter()
+            .filter(|p| p.join("system.reg").is_file() || p.join("user.reg").is_file())
+            .collect(),
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn subdirs(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+}
+
+/// Read a string value from a `.reg` file (Wine's `user.reg`/`system.reg` or a `reg export`
+/// dump). `key` uses the file's own escaping (`Software\\\\Valve\\\\Steam` for Wine,
+/// `HKEY_CURRENT_USER\\Software\\Valve\\Steam` for exports) and is matched case-insensitively.
+#[cfg(any(windows, target_os = "linux"))]
+fn reg_file_value(content: &str, key: &str, value: &str) -> Option<String> {
+    let mut in_key = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if let Some(rest) = line.strip_prefix('[') {
+            // Section header: `[Software\\Valve\\Steam] 1700000000`
+            let section = rest.split(']').next().unwrap_or_default();
+            in_key = section.eq_ignore_ascii_case(key);
+            continue;
+        }
+
+        if !in_key || !line.starts_with('"') {
+            continue;
+        }
+
+        let Some((name, data)) = line.split_once("\"=") else {
+            continue;
+        };
+        if !unescape_reg_string(&name[1..]).eq_ignore_ascii_case(value) {
+            continue;
+        }
+
+        // Only plain string values (`"..."`) are supported; `str(2):`/`hex:` are skipped.
+        let data = data.strip_prefix('"')?.strip_suffix('"')?;
+        return Some(unescape_reg_string(data));
+    }
+
+    None
+}
+
+/// Undo `.reg` string escaping, including Wine's `\\x` hex escapes for non-ASCII text.
+#[cfg(any(windows, target_os = "linux"))]
+fn unescape_reg_string(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('x') => {
+                let mut hex = String::new();
+                while hex.len() < 4 && chars.peek().is_some_and(|h| h.is_ascii_hexdigit()) {
+                    hex.push(chars.next().unwrap_or_default());
+                }
+                if let Some(decoded) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    out.push(decoded);
+                }
+            }
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+
+    out
+}
+
+/// Map `C:\\foo\\bar` (or `c:/foo/bar`) to the matching directory inside a Wine prefix.
+///
+/// Drive letters go through `dosdevices/<x>:` (falling back to `drive_c` for C:), and each
+/// component is matched case-insensitively, as Windows paths are.
+#[cfg(target_os = "linux")]
+fn map_windows_path(prefix: &Path, win_path: &str) -> Option<PathBuf> {
+    let normalized = win_path.trim().replace('\\', "/");
+    let (drive, rest) = split_drive(&normalized)?;
+
+    let dosdevice = prefix.join("dosdevices").join(format!("{}:", drive));
+    let mut current = if dosdevice.exists() {
+        dosdevice
+    } else if drive == 'c' {
+        prefix.join("drive_c")
+    } else {
+        return None;
+    };
+
+    for component in rest.split('/').filter(|c| !c.is_empty()) {
+        let exact = current.join(component);
+        current = if exact.exists() {
+            exact
+        } else {
+            fs::read_dir(&current)
+                .ok()?
+                .flatten()
+                .find(|e| {
+                    e.file_name().to_string_lossy().to_lowercase() == component.to_lowercase()
+                })?
+                .path()
+        };
+    }
+
+    Some(current)
+}
+
+/// Split `c:/foo` into (`c`, `/foo`); the drive letter is lowercased.
+#[cfg(target_os = "linux")]
+fn split_drive(path: &str) -> Option<(char, &str)> {
+    let mut chars = path.chars();
+    let drive = chars.next()?;
+    if !drive.is_ascii_alphabetic() || chars.next()? != ':' {
+        return None;
+    }
+    Some((drive.to_ascii_lowercase(), &path[2..]))
+}
+
 #[cfg(windows)]
 fn steam_root_from_registry() -> Option<PathBuf> {
     // Avoid a Windows-only dependency by shelling out to `reg.exe`.
     // This keeps macOS/Linux builds (GUI feature) dependency-free.
     const QUERIES: [(&str, &str); 4] = [
-        (r"HKCU\Software\Valve\Steam", "SteamPath"),
-        (r"HKCU\Software\Valve\Steam", "InstallPath"),
-        (r"HKLM\SOFTWARE\WOW6432Node\Valve\Steam", "InstallPath"),
-        (r"HKLM\SOFTWARE\Valve\Steam", "InstallPath"),
+        (r"HKEY_CURRENT_USER\Software\Valve\Steam", "SteamPath"),
+        (r"HKEY_CURRENT_USER\Software\Valve\Steam", "InstallPath"),
+        (
+            r"HKEY_LOCAL_MACHINE\SOFTWARE\WOW6432Node\Valve\Steam",
+            "InstallPath",
+        ),
+        (r"HKEY_LOCAL_MACHINE\SOFTWARE\Valve\Steam", "InstallPath"),
     ];
 
     for (key, value) in QUERIES {
-        if let Some(path) = reg_query_string(key, value) {
+        let Some(content) = reg_export(key) else {
+            continue;
+        };
+        if let Some(path) = reg_file_value(&content, key, value) {
             let normalized = path.trim().replace('/', "\\");
             if !normalized.is_empty() {
                 return Some(PathBuf::from(normalized));
@@ -199,42 +626,52 @@ fn steam_root_from_registry() -> Option<PathBuf> {
     None
 }
 
+/// Dump a registry key with `reg export`.
+///
+/// `reg query` prints through the console code page (GBK, CP866, ...) and pads columns with
+/// runs of spaces, which mangles non-ASCII install paths and paths with repeated spaces.
+/// Exports are always UTF-16 with quoted values, so neither problem applies.
 #[cfg(windows)]
-fn reg_query_string(key: &str, value: &str) -> Option<String> {
+fn reg_export(key: &str) -> Option<String> {
+    use std::os::windows::process::CommandExt;
     use std::process::Command;
 
-    let out = Command::new("reg")
-        .args(["query", key, "/v", value])
+    // Don't flash a console window when called from the GUI.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    // The staging area is removed (together with the export) when it goes out of scope.
+    let staging = crate::staging::StagingArea::new("reg").ok()?;
+    let out_path = staging.path().join("export.reg");
+    let status = Command::new("reg")
+        .args(["export", key])
+        .arg(&out_path)
+        .arg("/y")
+        .creation_flags(CREATE_NO_WINDOW)
         .output()
-        .ok()?;
+        .ok()?
+        .status;
+
+    let bytes = fs::read(&out_path).ok();
 
-    if !out.status.success() {
+    if !status.success() {
         return None;
     }
+    bytes.map(|b| decode_reg_export(&b))
+}
 
-    let stdout = String::from_utf8_lossy(&out.stdout);
-    for line in stdout.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-
-        // Expected: <ValueName> <Type> <Data...>
-        // Example: SteamPath    REG_SZ    C:\Program Files (x86)\Steam
-        if !trimmed.starts_with(value) {
-            continue;
-        }
-
-        let parts: Vec<&str> = trimmed.split_whitespace().collect();
-        if parts.len() < 3 {
-            continue;
+/// Decode a `.reg` file: UTF-16LE with BOM (regedit format 5.00) or UTF-8/ASCII (REGEDIT4).
+#[cfg(any(windows, test))]
+fn decode_reg_export(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFF, 0xFE]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
         }
-
-        // Data may contain spaces; join everything after the type.
-        return Some(parts[2..].join(" "));
+        None => String::from_utf8_lossy(bytes).into_owned(),
     }
-
-    None
 }
 
 #[cfg(test)]
@@ -278,6 +715,126 @@ mod tests {
         assert!(paths.iter().any(|p| p.to_string_lossy().contains("Games")));
     }
 
+    #[test]
+    fn parse_app_manifest_state_flags() {
+        let acf = r#"
+            "AppState"
+            {
+                "appid"        "2427700"
+                "name"        "Backpack Battles"
+                "StateFlags"        "1026"
+                "installdir"        "Backpack Battles"
+                "SizeOnDisk"        "1048576"
+                "buildid"        "17283746"
+                "InstalledDepots"
+                {
+                    "2427701"
+                    {
+                        "manifest"        "123"
+                    }
+                }
+            }
+        "#;
+
+        let manifest = parse_app_manifest(acf).unwrap();
+        assert_eq!(manifest.app_id, "2427700");
+        assert_eq!(manifest.install_dir, "Backpack Battles");
+        assert_eq!(manifest.state_flags, 1026);
+        assert_eq!(manifest.size_on_disk, Some(1048576));
+        assert_eq!(manifest.build_id.as_deref(), Some("17283746"));
+        assert_ne!(manifest.state_flags & BUSY_STATE_MASK, 0);
+        assert_eq!(4 & BUSY_STATE_MASK, 0);
+    }
+
+    #[cfg(any(windows, target_os = "linux"))]
+    #[test]
+    fn reg_file_value_reads_wine_registry() {
+        let reg = r#"WINE REGISTRY Version 2
+;; All keys relative to \\User\\S-1-5-21-0-0-0-1000
+
+[Software\\Valve\\Steam] 1700000000
+#time=1da0000000000000
+"AutoLoginUser"="player"
+"SteamPath"="c:/program files (x86)/steam"
+
+[Software\\Wow6432Node\\Valve\\Steam] 1700000000
+"InstallPath"="D:\\\x6e38\x620f\\Steam"
+"#;
+
+        assert_eq!(
+            reg_file_value(reg, r"Software\\Valve\\Steam", "SteamPath").as_deref(),
+            Some("c:/program files (x86)/steam")
+        );
+        assert_eq!(
+            reg_file_value(reg, r"software\\wow6432node\\valve\\steam", "InstallPath").as_deref(),
+            Some(r"D:\游戏\Steam")
+        );
+        assert_eq!(reg_file_value(reg, r"Software\\Valve\\Steam", "InstallPath"), None);
+    }
+
+    #[cfg(any(windows, target_os = "linux"))]
+    #[test]
+    fn reg_file_value_reads_exported_non_ascii_paths() {
+        let reg = "Windows Registry Editor Version 5.00\r\n\r\n\
+            [HKEY_CURRENT_USER\\Software\\Valve\\Steam]\r\n\
+            \"SteamExe\"=\"c:/программы/steam/steam.exe\"\r\n\
+            \"SteamPath\"=\"c:/программы/steam\"\r\n\r\n\
+            [HKEY_LOCAL_MACHINE\\SOFTWARE\\WOW6432Node\\Valve\\Steam]\r\n\
+            \"InstallPath\"=\"D:\\\\游戏  平台\\\\Steam\"\r\n";
+
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(reg.encode_utf16().flat_map(u16::to_le_bytes));
+        let content = decode_reg_export(&bytes);
+
+        assert_eq!(
+            reg_file_value(&content, r"HKEY_CURRENT_USER\Software\Valve\Steam", "SteamPath")
+                .as_deref(),
+            Some("c:/программы/steam")
+        );
+        assert_eq!(
+            reg_file_value(
+                &content,
+                r"HKEY_LOCAL_MACHINE\SOFTWARE\WOW6432Node\Valve\Steam",
+                "InstallPath"
+            )
+            .as_deref(),
+            Some(r"D:\游戏  平台\Steam")
+        );
+    }
+
+    #[test]
+    fn parse_non_ascii_library_paths() {
+        let vdf = r#"
+            "libraryfolders"
+            {
+                "0"
+                {
+                    "path"		"D:\\游戏  库\\SteamLibrary"
+                }
+                "1"
+                {
+                    "path"		"/home/пользователь/Игры/Steam"
+                }
+            }
+        "#;
+
+        let paths = parse_libraryfolders_vdf(vdf);
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from(r"D:\游戏  库\SteamLibrary"),
+                PathBuf::from("/home/пользователь/Игры/Steam"),
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn split_drive_letters() {
+        assert_eq!(split_drive("C:/Program Files"), Some(('c', "/Program Files")));
+        assert_eq!(split_drive("/home/user"), None);
+    }
+
     #[test]
     fn parse_kv_pair_unescapes_backslashes() {
         let line = r#""path" "D:\\SteamLibrary""#;
@@ -0,0 +1,345 @@
+//! 写入路径的浸泡测试：在真实 PCK 的临时副本上反复随机替换、新增、删除与压缩，
+//! 每轮之后重读 entry 表、与预期的大小和 MD5 比对并完整校验数据，最后汇总各操作的耗时分布。
+//! 供修改写入逻辑后在真实数据上验证，不在帮助中列出。
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+
+use crate::pck::{self, IoOptions, VerifyOptions};
+
+/// 随机生成的数据最大长度
+const MAX_DATA_LEN: usize = 64 * 1024;
+/// 每次替换/新增最多涉及的 entry 数
+const MAX_BATCH: usize = 8;
+/// 新增 entry 所在的目录，便于在副本中辨认
+const ADDED_PREFIX: &str = "res://__stress__/";
+
+#[derive(Debug, Clone)]
+pub struct StressOptions {
+    pub cycles: usize,
+    pub seed: u64,
+    /// 每轮校验使用的线程数
+    pub jobs: usize,
+    pub io: IoOptions,
+}
+
+/// 每轮执行的写入操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operation {
+    Apply,
+    Delete,
+    Compact,
+    Verify,
+}
+
+impl Operation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Apply => "apply",
+            Operation::Delete => "delete",
+            Operation::Compact => "compact",
+            Operation::Verify => "verify",
+        }
+    }
+}
+
+/// 一种操作的耗时分布
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timings {
+    pub count: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl Timings {
+    fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        samples.sort();
+        let at = |q: f64| samples[((samples.len() - 1) as f64 * q).round() as usize];
+        Some(Self {
+            count: samples.len(),
+            min: *samples.first()?,
+            median: at(0.5),
+            p95: at(0.95),
+            max: *samples.last()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct StressReport {
+    pub cycles: usize,
+    pub entries_written: usize,
+    pub entries_deleted: usize,
+    pub final_size: u64,
+    /// 按操作排序
+    pub timings: Vec<(Operation, Timings)>,
+}
+
+/// 可复现的伪随机数（SplitMix64）
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `0..n` 中的一个数，n 为 0 时返回 0
+    fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len + 8);
+        while data.len() < len {
+            data.extend_from_slice(&self.next().to_le_bytes());
+        }
+        data.truncate(len);
+        data
+    }
+}
+
+/// 在 `scratch` 中的 PCK 副本上运行；失败时保留副本供排查，成功后删除
+pub fn run(
+    pck_path: &Path,
+    scratch: &Path,
+    options: &StressOptions,
+    mut on_cycle: impl FnMut(usize, Operation, Duration),
+) -> Result<StressReport> {
+    fs::create_dir_all(scratch)
+        .with_context(|| format!("无法创建临时目录: {}", scratch.display()))?;
+    let copy = scratch.join(format!("stress_{}.pck", std::process::id()));
+    fs::copy(pck_path, &copy)
+        .with_context(|| format!("无法复制 {} 到 {}", pck_path.display(), copy.display()))?;
+
+    let report = soak(&copy, options, &mut on_cycle)
+        .with_context(|| format!("副本已保留以便排查: {}", copy.display()))?;
+    let _ = fs::remove_file(&copy);
+    Ok(report)
+}
+
+fn soak(
+    copy: &Path,
+    options: &StressOptions,
+    on_cycle: &mut impl FnMut(usize, Operation, Duration),
+) -> Result<StressReport> {
+    let mut rng = Rng(options.seed);
+    let mut expected = read_model(copy)?;
+    let mut samples: HashMap<Operation, Vec<Duration>> = HashMap::new();
+    let mut entries_written = 0;
+    let mut entries_deleted = 0;
+    let mut next_added = 0usize;
+
+    for cycle in 1..=options.cycles {
+        let mut file = OpenOptions::new().read(true).write(true).open(copy)?;
+        let operation = match rng.below(10) {
+            0..=5 => Operation::Apply,
+            6..=7 if expected.len() > 2 => Operation::Delete,
+            _ => Operation::Compact,
+        };
+
+        let started = Instant::now();
+        match operation {
+            Operation::Apply => {
+                let mut paths: Vec<String> = Vec::new();
+                for _ in 0..=rng.below(MAX_BATCH) {
+                    let path = if rng.below(3) == 0 || expected.is_empty() {
+                        next_added += 1;
+                        format!("{}{}.bin", ADDED_PREFIX, next_added)
+                    } else {
+                        pick(&mut rng, &expected)
+                    };
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+                let data: Vec<Vec<u8>> = paths
+                    .iter()
+                    .map(|_| {
+                        let len = rng.below(MAX_DATA_LEN + 1);
+                        rng.bytes(len)
+                    })
+                    .collect();
+                let files: Vec<(&str, &[u8])> = paths
+                    .iter()
+                    .zip(&data)
+                    .map(|(path, data)| (path.as_str(), data.as_slice()))
+                    .collect();
+
+                let (header, index) = pck::read_header_and_index(&mut file)?;
+                pck::replace_files_in_pck_with(&mut file, &header, &index, files, &options.io)
+                    .with_context(|| format!("第 {} 轮替换/新增失败", cycle))?;
+                entries_written += data.len();
+                for (path, data) in paths.into_iter().zip(data) {
+                    expected.insert(path, (data.len() as u64, md5::compute(&data).0));
+                }
+            }
+            Operation::Delete => {
+                let mut paths = Vec::new();
+                for _ in 0..=rng.below(MAX_BATCH.min(expected.len() - 2)) {
+                    let path = pick(&mut rng, &expected);
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+                let (header, index) = pck::read_header_and_index(&mut file)?;
+                let delete: Vec<&str> = paths.iter().map(String::as_str).collect();
+                pck::delete_files_in_pck(&mut file, &header, &index, delete)
+                    .with_context(|| format!("第 {} 轮删除失败", cycle))?;
+                for path in &paths {
+                    expected.remove(path);
+                }
+                entries_deleted += paths.len();
+            }
+            Operation::Compact => {
+                pck::compact(&mut file, &options.io)
+                    .with_context(|| format!("第 {} 轮压缩失败", cycle))?;
+            }
+            Operation::Verify => unreachable!("校验不作为随机操作"),
+        }
+        let elapsed = started.elapsed();
+        samples.entry(operation).or_default().push(elapsed);
+        on_cycle(cycle, operation, elapsed);
+        drop(file);
+
+        let started = Instant::now();
+        check(copy, &expected, options.jobs)
+            .with_context(|| format!("第 {} 轮（{}）之后校验失败", cycle, operation.name()))?;
+        samples
+            .entry(Operation::Verify)
+            .or_default()
+            .push(started.elapsed());
+    }
+
+    let mut timings: Vec<(Operation, Timings)> = samples
+        .into_iter()
+        .filter_map(|(operation, samples)| Some((operation, Timings::from_samples(samples)?)))
+        .collect();
+    timings.sort_by_key(|(operation, _)| *operation);
+    Ok(StressReport {
+        cycles: options.cycles,
+        entries_written,
+        entries_deleted,
+        final_size: fs::metadata(copy)?.len(),
+        timings,
+    })
+}
+
+fn pick(rng: &mut Rng, expected: &HashMap<String, (u64, [u8; 16])>) -> String {
+    let mut paths: Vec<&String> = expected.keys().collect();
+    paths.sort();
+    paths[rng.below(paths.len())].clone()
+}
+
+/// 路径 -> （大小, MD5）
+fn read_model(path: &Path) -> Result<HashMap<String, (u64, [u8; 16])>> {
+    let file = File::open(path).with_context(|| format!("无法打开: {}", path.display()))?;
+    let (_, entries) = pck::read_entries(BufReader::new(file), None, pck::TableLimits::DEFAULT)?;
+    Ok(entries
+        .into_iter()
+        .map(|(path, entry)| (path, (entry.size, entry.md5)))
+        .collect())
+}
+
+/// entry 表与预期一致，且每个 entry 的数据与表中的 MD5 一致
+fn check(path: &Path, expected: &HashMap<String, (u64, [u8; 16])>, jobs: usize) -> Result<()> {
+    let actual = read_model(path).context("无法重读 entry 表")?;
+    let mut problems: Vec<String> = Vec::new();
+    for (entry, digest) in expected {
+        match actual.get(entry) {
+            None => problems.push(format!("缺少 {}", entry)),
+            Some(found) if found != digest => problems.push(format!("{} 的大小或 MD5 不符", entry)),
+            Some(_) => {}
+        }
+    }
+    problems.extend(
+        actual
+            .keys()
+            .filter(|entry| !expected.contains_key(*entry))
+            .map(|entry| format!("多出 {}", entry)),
+    );
+    if !problems.is_empty() {
+        problems.sort();
+        bail!("entry 表与预期不符: {}", problems.join("; "));
+    }
+
+    let options = VerifyOptions {
+        jobs,
+        fail_fast: true,
+        ..VerifyOptions::default()
+    };
+    let report = pck::verify_pck(path, &options)?;
+    if let Some(failure) = report.failures.first() {
+        bail!("{} {}", failure.path, failure.reason);
+    }
+    Ok(())
+}
+
+/// 默认的临时目录
+pub fn default_scratch() -> PathBuf {
+    std::env::temp_dir().join("bpb_enhance_stress")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_a_short_soak_with_the_same_seed() {
+        let dir = std::env::temp_dir().join(format!("bpb_stress_{}", std::process::id()));
+        let source = dir.join("source");
+        fs::create_dir_all(&source).unwrap();
+        for (name, data) in [("a.txt", "alpha"), ("b.txt", "bravo"), ("c.txt", "charlie")] {
+            fs::write(source.join(name), data).unwrap();
+        }
+        let pck = dir.join("game.pck");
+        pck::pack_directory(&source, &pck, &pck::PackOptions::default()).unwrap();
+        let original = fs::read(&pck).unwrap();
+
+        let options = StressOptions {
+            cycles: 25,
+            seed: 7,
+            jobs: 2,
+            io: IoOptions::auto(0),
+        };
+        let mut operations = Vec::new();
+        let report = run(&pck, &dir.join("scratch"), &options, |_, op, _| {
+            operations.push(op)
+        })
+        .unwrap();
+        assert_eq!(report.cycles, 25);
+        assert_eq!(operations.len(), 25);
+        let verified = report
+            .timings
+            .iter()
+            .find(|(op, _)| *op == Operation::Verify)
+            .unwrap();
+        assert_eq!(verified.1.count, 25);
+        // 原 PCK 不受影响，副本已删除
+        assert_eq!(fs::read(&pck).unwrap(), original);
+        assert_eq!(fs::read_dir(dir.join("scratch")).unwrap().count(), 0);
+
+        let mut again = Vec::new();
+        run(&pck, &dir.join("scratch"), &options, |_, op, _| {
+            again.push(op)
+        })
+        .unwrap();
+        assert_eq!(again, operations);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{Result, bail};
+
+/// 1x1 全透明 PNG
+const TRANSPARENT_PNG: [u8; 68] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60, 0x00, 0x02, 0x00,
+    0x00, 0x05, 0x00, 0x01, 0x7a, 0x5e, 0xab, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44,
+    0xae, 0x42, 0x60, 0x82,
+];
+
+/// StreamTexture 数据格式标记：无损（PNG）压缩
+const STEX_FORMAT_BIT_LOSSLESS: u32 = 1 << 20;
+
+/// 支持生成占位资源的扩展名
+pub const SUPPORTED_EXTENSIONS: [&str; 7] = ["tscn", "tres", "png", "stex", "txt", "csv", "json"];
+
+/// 按目标路径的扩展名生成同类型的最小合法资源，用于代替直接删除
+pub fn stub_resource(res_path: &str) -> Result<Vec<u8>> {
+    let extension = res_path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let data = match extension.as_str() {
+        "tscn" => b"[gd_scene format=2]\n\n[node name=\"Stub\" type=\"Node\"]\n".to_vec(),
+        "tres" => b"[gd_resource type=\"Resource\" format=2]\n\n[resource]\n".to_vec(),
+        "png" => TRANSPARENT_PNG.to_vec(),
+        "stex" => stream_texture(&TRANSPARENT_PNG),
+        "txt" | "csv" => Vec::new(),
+        "json" => b"{}".to_vec(),
+        _ => bail!(
+            "无法为 {} 生成占位资源，仅支持: {}",
+            res_path,
+            SUPPORTED_EXTENSIONS.join(", ")
+        ),
+    };
+
+    Ok(data)
+}
+
+/// Godot 3 的 .stex：GDST 头 + 尺寸 + flags + 数据格式 + 单层 mipmap 的 PNG 数据
+fn stream_texture(png: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(32 + png.len());
+    out.extend_from_slice(b"GDST");
+    for dimension in [1u16, 0, 1, 0] {
+        // 宽、自定义宽、高、自定义高
+        out.extend_from_slice(&dimension.to_le_bytes());
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&STEX_FORMAT_BIT_LOSSLESS.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&(png.len() as u32).to_le_bytes());
+    out.extend_from_slice(png);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stubs_match_target_type() {
+        assert!(
+            stub_resource("res://UI/Menu.tscn")
+                .unwrap()
+                .starts_with(b"[gd_scene")
+        );
+        assert_eq!(
+            stub_resource("res://icon.PNG").unwrap(),
+            TRANSPARENT_PNG.to_vec()
+        );
+
+        let stex = stub_resource("res://.import/icon.png-abc.stex").unwrap();
+        assert_eq!(&stex[..4], b"GDST");
+        assert_eq!(&stex[28..], &TRANSPARENT_PNG[..]);
+
+        assert!(stub_resource("res://Core/Game.gde").is_err());
+    }
+}
@@ -0,0 +1,379 @@
+//! 把（修改过的）PCK 拆成带 MD5 的分块与清单 `transfer.toml`，复制到另一台电脑后再拼装回去。
+//!
+//! 拼装前逐块校验，不一致的分块全部列出，只需重新复制这几块；拼装时写入临时文件，
+//! 整个文件的 MD5 与清单一致后才替换目标 PCK。PCK 旁的来源记录一并传输。
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use toml::{Table, Value};
+
+use crate::provenance::ProvenanceStore;
+use crate::{journal, pck, pending};
+
+/// 清单文件名，位于分块所在目录
+pub const MANIFEST_NAME: &str = "transfer.toml";
+/// 清单中来源记录的文件名
+const PROVENANCE_NAME: &str = "provenance.toml";
+const COPY_BUF_SIZE: usize = 1024 * 1024;
+
+/// 一个分块
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub file: String,
+    pub size: u64,
+    pub md5_hex: String,
+}
+
+/// 一次传输的清单
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferManifest {
+    /// 原 PCK 的文件名
+    pub file_name: String,
+    pub size: u64,
+    pub md5_hex: String,
+    pub created_at: u64,
+    pub chunks: Vec<Chunk>,
+    /// 来源记录的 MD5；PCK 没有来源记录时为 None
+    pub provenance_md5: Option<String>,
+}
+
+/// 校验不通过的分块
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BadChunk {
+    Missing(String),
+    Changed(String),
+}
+
+/// 拼装的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Imported {
+    /// 目标 PCK 已与清单一致，没有写入
+    UpToDate,
+    Written {
+        bytes: u64,
+    },
+}
+
+/// 把 `pck` 按 `chunk_size` 字节拆分写入 `out_dir`，返回写入的清单
+pub fn export(pck: &Path, out_dir: &Path, chunk_size: u64) -> Result<TransferManifest> {
+    if chunk_size == 0 {
+        bail!("分块大小必须大于 0");
+    }
+    let file_name = pck
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("无效的 PCK 路径: {}", pck.display()))?
+        .to_string();
+    fs::create_dir_all(out_dir).with_context(|| format!("无法创建目录: {}", out_dir.display()))?;
+    let mut reader =
+        BufReader::new(File::open(pck).with_context(|| format!("无法打开: {}", pck.display()))?);
+
+    let mut whole = md5::Context::new();
+    let mut chunks = Vec::new();
+    let mut size = 0;
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    loop {
+        let name = format!("{}.part{:04}", file_name, chunks.len() + 1);
+        let path = out_dir.join(&name);
+        let mut out =
+            File::create(&path).with_context(|| format!("无法写入: {}", path.display()))?;
+        let mut digest = md5::Context::new();
+        let mut written = 0u64;
+        while written < chunk_size {
+            let want = (chunk_size - written).min(buf.len() as u64) as usize;
+            let n = reader
+                .read(&mut buf[..want])
+                .with_context(|| format!("无法读取: {}", pck.display()))?;
+            if n == 0 {
+                break;
+            }
+            out.write_all(&buf[..n])
+                .with_context(|| format!("无法写入: {}", path.display()))?;
+            digest.consume(&buf[..n]);
+            whole.consume(&buf[..n]);
+            written += n as u64;
+        }
+        if written == 0 && !chunks.is_empty() {
+            drop(out);
+            fs::remove_file(&path).with_context(|| format!("无法删除: {}", path.display()))?;
+            break;
+        }
+        out.sync_all()
+            .with_context(|| format!("无法写入: {}", path.display()))?;
+        chunks.push(Chunk {
+            file: name,
+            size: written,
+            md5_hex: format!("{:x}", digest.finalize()),
+        });
+        size += written;
+        if written < chunk_size {
+            break;
+        }
+    }
+
+    let sidecar = ProvenanceStore::sidecar_path(pck);
+    let provenance_md5 = match fs::read(&sidecar) {
+        Ok(content) => {
+            let target = out_dir.join(PROVENANCE_NAME);
+            fs::write(&target, &content)
+                .with_context(|| format!("无法写入: {}", target.display()))?;
+            Some(format!("{:x}", md5::compute(&content)))
+        }
+        Err(_) => None,
+    };
+
+    let manifest = TransferManifest {
+        file_name,
+        size,
+        md5_hex: format!("{:x}", whole.finalize()),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        chunks,
+        provenance_md5,
+    };
+    let path = out_dir.join(MANIFEST_NAME);
+    fs::write(&path, manifest.to_toml())
+        .with_context(|| format!("无法写入清单: {}", path.display()))?;
+    Ok(manifest)
+}
+
+/// 读取 `dir` 中的清单，校验全部分块；有不一致的分块时返回它们，不写入任何文件
+pub fn verify(dir: &Path) -> Result<(TransferManifest, Vec<BadChunk>)> {
+    let path = dir.join(MANIFEST_NAME);
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("无法读取清单: {}", path.display()))?;
+    let manifest = TransferManifest::parse(&content)
+        .with_context(|| format!("清单格式错误: {}", path.display()))?;
+
+    let mut bad = Vec::new();
+    for chunk in &manifest.chunks {
+        let path = dir.join(&chunk.file);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => {
+                bad.push(BadChunk::Missing(chunk.file.clone()));
+                continue;
+            }
+        };
+        let (md5, size) = pck::digest_reader(BufReader::new(file))
+            .with_context(|| format!("无法读取: {}", path.display()))?;
+        if size != chunk.size || format!("{:x}", md5::Digest(md5)) != chunk.md5_hex {
+            bad.push(BadChunk::Changed(chunk.file.clone()));
+        }
+    }
+    if let Some(expected) = &manifest.provenance_md5 {
+        let path = dir.join(PROVENANCE_NAME);
+        match fs::read(&path) {
+            Ok(content) if format!("{:x}", md5::compute(&content)) == *expected => {}
+            Ok(_) => bad.push(BadChunk::Changed(PROVENANCE_NAME.to_string())),
+            Err(_) => bad.push(BadChunk::Missing(PROVENANCE_NAME.to_string())),
+        }
+    }
+    Ok((manifest, bad))
+}
+
+/// 把 `dir` 中的分块拼装为 `dest`。调用前应先用 [`verify`] 确认分块完整；
+/// 拼装后的 MD5 仍与清单不一致时不替换 `dest`
+pub fn import(dir: &Path, manifest: &TransferManifest, dest: &Path) -> Result<Imported> {
+    if let Ok(file) = File::open(dest)
+        && file.metadata()?.len() == manifest.size
+    {
+        let (md5, _) = pck::digest_reader(BufReader::new(file))
+            .with_context(|| format!("无法读取: {}", dest.display()))?;
+        if format!("{:x}", md5::Digest(md5)) == manifest.md5_hex {
+            copy_provenance(dir, manifest, dest)?;
+            return Ok(Imported::UpToDate);
+        }
+    }
+    if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("无法创建目录: {}", parent.display()))?;
+    }
+
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".transfer.partial");
+    let partial = PathBuf::from(partial);
+    let written = assemble(dir, manifest, &partial);
+    if let Err(err) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(err);
+    }
+    fs::rename(&partial, dest).with_context(|| {
+        let _ = fs::remove_file(&partial);
+        format!("无法覆盖 {}，请确认游戏已关闭", dest.display())
+    })?;
+    // 目标 PCK 已整个换掉，原有的暂存修改与回滚日志随之作废
+    let _ = fs::remove_file(pending::journal_path(dest));
+    let _ = fs::remove_file(journal::journal_path(dest));
+    copy_provenance(dir, manifest, dest)?;
+    Ok(Imported::Written {
+        bytes: manifest.size,
+    })
+}
+
+fn assemble(dir: &Path, manifest: &TransferManifest, partial: &Path) -> Result<()> {
+    let mut out =
+        File::create(partial).with_context(|| format!("无法写入: {}", partial.display()))?;
+    let mut whole = md5::Context::new();
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    for chunk in &manifest.chunks {
+        let path = dir.join(&chunk.file);
+        let mut reader =
+            File::open(&path).with_context(|| format!("无法打开: {}", path.display()))?;
+        loop {
+            let n = reader
+                .read(&mut buf)
+                .with_context(|| format!("无法读取: {}", path.display()))?;
+            if n == 0 {
+                break;
+            }
+            whole.consume(&buf[..n]);
+            out.write_all(&buf[..n])
+                .with_context(|| format!("无法写入: {}", partial.display()))?;
+        }
+    }
+    if format!("{:x}", whole.finalize()) != manifest.md5_hex {
+        bail!("拼装后的 MD5 与清单不一致，分块在校验后被改动过");
+    }
+    out.sync_all()
+        .with_context(|| format!("无法写入: {}", partial.display()))
+}
+
+fn copy_provenance(dir: &Path, manifest: &TransferManifest, dest: &Path) -> Result<()> {
+    if manifest.provenance_md5.is_none() {
+        return Ok(());
+    }
+    let target = ProvenanceStore::sidecar_path(dest);
+    fs::copy(dir.join(PROVENANCE_NAME), &target)
+        .with_context(|| format!("无法写入来源记录: {}", target.display()))?;
+    Ok(())
+}
+
+impl TransferManifest {
+    pub fn to_toml(&self) -> String {
+        let mut table = Table::new();
+        table.insert("file".into(), Value::String(self.file_name.clone()));
+        table.insert("size".into(), Value::Integer(self.size as i64));
+        table.insert("md5".into(), Value::String(self.md5_hex.clone()));
+        table.insert("created_at".into(), Value::Integer(self.created_at as i64));
+        if let Some(md5) = &self.provenance_md5 {
+            table.insert("provenance_md5".into(), Value::String(md5.clone()));
+        }
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let mut item = Table::new();
+                item.insert("file".into(), Value::String(chunk.file.clone()));
+                item.insert("size".into(), Value::Integer(chunk.size as i64));
+                item.insert("md5".into(), Value::String(chunk.md5_hex.clone()));
+                Value::Table(item)
+            })
+            .collect();
+        table.insert("chunks".into(), Value::Array(chunks));
+        format!(
+            "# 由 bpb_enhance export-transfer 生成：复制整个目录后用 import-transfer 拼装\n{}",
+            table
+        )
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let table: Table = content.parse()?;
+        let string = |table: &Table, key: &str| {
+            table
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("缺少 {}", key))
+        };
+        let integer = |table: &Table, key: &str| {
+            table
+                .get(key)
+                .and_then(Value::as_integer)
+                .and_then(|value| u64::try_from(value).ok())
+                .ok_or_else(|| anyhow!("缺少 {}", key))
+        };
+
+        let mut chunks = Vec::new();
+        for item in table
+            .get("chunks")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let item = item.as_table().ok_or_else(|| anyhow!("chunks 格式错误"))?;
+            let file = string(item, "file")?;
+            // 分块只能在清单所在目录中
+            if file.contains(['/', '\\']) || file == ".." {
+                bail!("无效的分块文件名: {}", file);
+            }
+            chunks.push(Chunk {
+                file,
+                size: integer(item, "size")?,
+                md5_hex: string(item, "md5")?,
+            });
+        }
+        let manifest = Self {
+            file_name: string(&table, "file")?,
+            size: integer(&table, "size")?,
+            md5_hex: string(&table, "md5")?,
+            created_at: integer(&table, "created_at")?,
+            chunks,
+            provenance_md5: string(&table, "provenance_md5").ok(),
+        };
+        if manifest.chunks.iter().map(|c| c.size).sum::<u64>() != manifest.size {
+            bail!("分块大小之和与文件大小不一致");
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_round_trip_and_report_damage() {
+        let dir = std::env::temp_dir().join(format!("bpb_transfer_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 253) as u8).collect();
+        fs::write(&pck, &data).unwrap();
+        fs::write(ProvenanceStore::sidecar_path(&pck), "[entries]\n").unwrap();
+
+        let out = dir.join("transfer");
+        let manifest = export(&pck, &out, 4_000).unwrap();
+        let sizes: Vec<u64> = manifest.chunks.iter().map(|c| c.size).collect();
+        assert_eq!(sizes, [4_000, 4_000, 2_000]);
+        let content = fs::read_to_string(out.join(MANIFEST_NAME)).unwrap();
+        assert_eq!(TransferManifest::parse(&content).unwrap(), manifest);
+
+        fs::write(out.join("Game.pck.part0002"), [0u8; 4_000]).unwrap();
+        fs::remove_file(out.join("Game.pck.part0003")).unwrap();
+        let (_, bad) = verify(&out).unwrap();
+        assert_eq!(
+            bad,
+            [
+                BadChunk::Changed("Game.pck.part0002".into()),
+                BadChunk::Missing("Game.pck.part0003".into())
+            ]
+        );
+
+        let manifest = export(&pck, &out, 4_000).unwrap();
+        let (_, bad) = verify(&out).unwrap();
+        assert_eq!(bad, []);
+        let dest = dir.join("other/Game.pck");
+        let written = import(&out, &manifest, &dest).unwrap();
+        assert_eq!(written, Imported::Written { bytes: 10_000 });
+        assert_eq!(fs::read(&dest).unwrap(), data);
+        assert!(ProvenanceStore::sidecar_path(&dest).exists());
+        assert_eq!(import(&out, &manifest, &dest).unwrap(), Imported::UpToDate);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,55 +1,168 @@
+use crate::config;
+use crate::import_pairs;
+use crate::journal::{self, Journal};
+use crate::manifest;
+use crate::overlay;
 use crate::pck;
+use crate::pending;
+use crate::platform;
+use crate::progress;
+use crate::project_settings::{self, ProjectSettings, Setting};
+use crate::provenance;
+use crate::report::{EntryChange, EntryDigest, PatchReport, ReportEntry};
+use crate::steam::{self, UpdateActivity};
+use crate::stub;
 use anyhow::{anyhow, bail, Context, Result};
-use binrw::BinRead;
+use bpb_enhance::pool::{CancelToken, Cancelled};
 use cfg_if::cfg_if;
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// 用于识别未注入过的游戏版本的脚本
+const GAME_GDE_PATH: &str = "res://Core/Game.gde";
+/// 注入时写入的版本记录
+pub const PLUGIN_VERSION_PATH: &str = "res://plugin_version.txt";
+
+/// 就地修改时的可选行为，未设置的项使用 bpb_enhance.toml 中的设置
+#[derive(Debug, Default, Clone)]
+pub struct ApplyOptions {
+    /// 覆盖 `[io] verify_moves`
+    pub verify_moves: Option<pck::MoveVerification>,
+    /// 安全模式：只允许替换已有文件的内容，拒绝新增与删除，保证 entry 表不变、原有数据不被搬移
+    pub safe_mode: bool,
+    /// 原子模式：在同目录的临时副本上修改，完成并落盘后再改名覆盖原 PCK，中途中断不会损坏原文件
+    pub atomic: bool,
+    /// 两阶段模式：新数据追加到 PCK 末尾，header 与 entry 表的改动记入待提交日志，
+    /// 运行 commit 后才生效（见 [`pending`]）
+    pub stage: bool,
+    /// 删除时只给 entry 打上删除标志（Godot 4.4 起的 v2 PCK），不缩短 entry 表也不截断文件
+    pub mark_removed: bool,
+    /// 启用或关闭 MOD 的可选功能（replace.toml 的 `[options]`）
+    pub selection: manifest::OptionSelection,
+    /// 覆盖顶层 `threads`
+    pub jobs: Option<usize>,
+    /// 覆盖 `[limits]`
+    pub limits: Option<pck::TableLimits>,
+    /// 写入时在每块数据之间检查，取消后回滚到修改前并返回 [`Cancelled`]
+    pub cancel: CancelToken,
+    /// 游戏导出时使用的加密密钥，修改加密的 PCK 时需要
+    pub encryption_key: Option<pck::EncryptionKey>,
+}
 
 cfg_if! {
     if #[cfg(feature = "gui")] {
-        use rust_embed::RustEmbed;
+        use std::thread::JoinHandle;
 
-        #[derive(RustEmbed)]
-        #[folder = "assets"]
-        struct EmbeddedAssets;
+        use crate::tweak_pack::TweakPack;
+
+        /// 修改资源包；replace.toml 在打开时就读取并核对
+        struct PackSource {
+            pack: TweakPack,
+            config: String,
+        }
 
-        struct EmbeddedSource;
+        impl PackSource {
+            fn locate() -> Result<Self> {
+                let pack = TweakPack::locate().context("无法加载修改资源包")?;
+                let config = String::from_utf8(pack.read("replace.toml")?)
+                    .context("资源包中的 replace.toml 不是有效的 UTF-8 文本")?;
+                Ok(Self { pack, config })
+            }
+        }
 
-        impl AssetSource for EmbeddedSource {
+        impl AssetSource for PackSource {
             fn get_file(&self, relative_path: &str) -> Result<Vec<u8>> {
-                let asset = EmbeddedAssets::get(relative_path)
-                    .ok_or_else(|| anyhow!("嵌入资源缺失: {}", relative_path))?;
-                Ok(asset.data.to_vec())
+                self.pack.read(relative_path)
             }
 
             fn config_content(&self) -> Cow<'static, str> {
-                let config = EmbeddedAssets::get("replace.toml").expect("嵌入资源中缺少 replace.toml");
-                let content = std::str::from_utf8(&config.data).expect("无法解析 replace.toml 为 UTF-8");
-                Cow::Owned(content.to_string())
+                Cow::Owned(self.config.clone())
+            }
+
+            fn describe(&self) -> String {
+                format!("修改资源包 {}（{}）", self.pack.version(), self.pack.root().display())
             }
         }
 
-        pub fn tweak_game_gde(file_path: &str) -> Result<()> {
-            let source = EmbeddedSource;
-            run_tweak(file_path, &source)
+        /// 覆盖包模式下修改资源包使用的 MOD 名
+        const PACK_MOD_NAME: &str = "bpb_enhance";
+
+        pub fn tweak_game_gde(
+            file_path: &str,
+            selection: &manifest::OptionSelection,
+        ) -> Result<PatchReport> {
+            let source = PackSource::locate()?;
+            let options = ApplyOptions {
+                selection: selection.clone(),
+                ..ApplyOptions::default()
+            };
+            run_tweak(file_path, &source, &options)
+        }
+
+        /// 覆盖包模式：不修改原 PCK，用修改资源包生成 `mods/` 下的覆盖包，返回覆盖包路径
+        pub fn build_override_pack(
+            file_path: &str,
+            selection: &manifest::OptionSelection,
+        ) -> Result<PathBuf> {
+            let source = PackSource::locate()?;
+            let priority = overlay::DEFAULT_PRIORITY;
+            write_override_pack(file_path, &source, PACK_MOD_NAME, priority, selection)
+        }
+
+        /// 资源包中可单独开关的功能
+        pub fn tweak_options() -> Result<Vec<manifest::ManifestOption>> {
+            manifest::parse_options(&PackSource::locate()?.config)
+        }
+
+        /// 在用户确认修改前，后台预读修改时需要搬移的数据；设置中关闭预读时返回 None
+        pub fn prefetch_for_apply(
+            file_path: &str,
+            selection: &manifest::OptionSelection,
+        ) -> Result<Option<JoinHandle<Result<u64>>>> {
+            let io_config = config::load().io;
+            if !io_config.prefetch.unwrap_or(true) {
+                return Ok(None);
+            }
+
+            let mut file = std::fs::File::open(file_path)
+                .with_context(|| format!("无法打开文件: {}", file_path))?;
+            let archive_size = file.metadata().context("无法读取 PCK 文件大小")?.len();
+            let (_, index) = pck::read_header_and_index(&mut file)?;
+            let (replacements, _) = load_manifest(&PackSource::locate()?, selection)?;
+            let new_paths: Vec<&str> = replacements
+                .iter()
+                .map(|(path, _)| path.as_str())
+                .chain(std::iter::once(PLUGIN_VERSION_PATH))
+                .collect();
+            let ranges = pck::ranges_to_move(&mut file, &index, &new_paths)?;
+
+            let io = io_config.resolve(archive_size);
+            Ok(Some(pck::prefetch(file_path.into(), ranges, io)))
         }
     } else {
+        use crate::mapping::PathMapping;
+
+        /// 将 replace.toml 中的资源路径解析为 assets 目录下的实际路径
+        pub fn resolve_asset_path(base_path: &Path, relative_path: &str) -> PathBuf {
+            if relative_path.starts_with("../") {
+                base_path.join(relative_path.trim_start_matches("../"))
+            } else if relative_path.starts_with("./") {
+                base_path.join(relative_path.trim_start_matches("./"))
+            } else {
+                base_path.join(relative_path)
+            }
+        }
+
         struct FileSystemSource {
             base_path: PathBuf,
+            mapping: PathMapping,
         }
 
         impl AssetSource for FileSystemSource {
             fn get_file(&self, relative_path: &str) -> Result<Vec<u8>> {
-                let full_path = if relative_path.starts_with("../") {
-                    self.base_path.join(relative_path.trim_start_matches("../"))
-                } else if relative_path.starts_with("./") {
-                    self.base_path.join(relative_path.trim_start_matches("./"))
-                } else {
-                    self.base_path.join(relative_path)
-                };
+                let full_path = resolve_asset_path(&self.base_path, relative_path);
 
                 if !full_path.exists() {
                     bail!("资产文件不存在: {}", full_path.display());
@@ -66,13 +179,76 @@ cfg_if! {
                     .unwrap();
                 Cow::Owned(config_str)
             }
+
+            fn describe(&self) -> String {
+                self.base_path.display().to_string()
+            }
+
+            fn physical_path(&self, res_path: &str) -> String {
+                self.mapping.physical(res_path).to_string()
+            }
+        }
+
+        /// mapping 把 replace.toml 中的可读路径解析为混淆后的实际路径，为空时不做转换
+        pub fn tweak_game_gde(
+            file_path: &str,
+            assets_path: &str,
+            mapping: PathMapping,
+            options: &ApplyOptions,
+        ) -> Result<PatchReport> {
+            let source = FileSystemSource {
+                base_path: PathBuf::from(assets_path),
+                mapping,
+            };
+            run_tweak(file_path, &source, options)
+        }
+
+        /// 覆盖包模式：不修改原 PCK，把 MOD 打成 `mods/<优先级>_<MOD 名>.pck` 并更新加载顺序，返回覆盖包路径
+        pub fn build_override_pack(
+            file_path: &str,
+            assets_path: &str,
+            priority: u16,
+            mapping: PathMapping,
+            selection: &manifest::OptionSelection,
+        ) -> Result<PathBuf> {
+            let base_path = PathBuf::from(assets_path);
+            let mod_name = std::path::absolute(&base_path)
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            let source = FileSystemSource { base_path, mapping };
+            write_override_pack(file_path, &source, &mod_name, priority, selection)
         }
 
-        pub fn tweak_game_gde(file_path: &str, assets_path: &str) -> Result<()> {
+        /// 松散文件模式：把替换资源写到 `res_override/` 供开发版游戏热加载，只写有变化的文件。
+        /// 开发版的脚本与发行版不同，因此不做版本与原始 MD5 校验；游戏目录中没有该目录时返回 None
+        pub fn write_loose_overrides(
+            file_path: &str,
+            assets_path: &str,
+            mapping: PathMapping,
+            selection: &manifest::OptionSelection,
+        ) -> Result<Option<(PathBuf, Vec<String>)>> {
+            let dir = overlay::loose_dir(Path::new(file_path))?;
+            if !dir.is_dir() {
+                return Ok(None);
+            }
             let source = FileSystemSource {
                 base_path: PathBuf::from(assets_path),
+                mapping,
             };
-            run_tweak(file_path, &source)
+
+            let manifest =
+                compose_manifest(&source, selection).context("加载 replace.toml 失败")?;
+            parse_requirements(&manifest.content)
+                .and_then(|requirements| check_requirements(&requirements, Path::new(file_path)))
+                .context("不满足 MOD 的运行前提")?;
+            let (replacements, delete_list) = load_manifest(&source, selection)?;
+            if !delete_list.is_empty() {
+                bail!("松散覆盖文件无法删除原 PCK 中的文件，请把 delete 改为 stub");
+            }
+
+            let written = overlay::write_loose_files(&dir, &replacements)?;
+            Ok(Some((dir, written)))
         }
     }
 }
@@ -87,85 +263,807 @@ struct VersionConfig {
 trait AssetSource {
     fn get_file(&self, relative_path: &str) -> Result<Vec<u8>>;
     fn config_content(&self) -> Cow<'static, str>;
+    /// 用于报告中展示的资源来源描述
+    fn describe(&self) -> String;
+    /// manifest 中的路径 -> PCK 中的实际路径
+    fn physical_path(&self, res_path: &str) -> String {
+        res_path.to_string()
+    }
 }
 
-fn run_tweak<S: AssetSource>(file_path: &str, source: &S) -> Result<()> {
-    let mut file = OpenOptions::new()
+/// 覆盖包模式的实现：校验 PCK 版本与原始 MD5 后把替换的资源打成 `mods/` 下的覆盖包
+fn write_override_pack<S: AssetSource>(
+    file_path: &str,
+    source: &S,
+    mod_name: &str,
+    priority: u16,
+    selection: &manifest::OptionSelection,
+) -> Result<PathBuf> {
+    let mut file =
+        std::fs::File::open(file_path).with_context(|| format!("无法打开文件: {}", file_path))?;
+    let (header, index) = pck::read_header_and_index(&mut file)
+        .with_context(|| format!("读取 PCK 头与索引失败: {}", file_path))?;
+
+    let manifest = compose_manifest(source, selection).context("加载 replace.toml 失败")?;
+    let version_config = parse_version_config(&manifest.content).context("加载版本配置失败")?;
+    parse_requirements(&manifest.content)
+        .and_then(|requirements| check_requirements(&requirements, Path::new(file_path)))
+        .context("不满足 MOD 的运行前提")?;
+    if !check_plugin_version_txt(&mut file, &index, &version_config).context("版本校验失败")?
+    {
+        let game_gde_path = source.physical_path(GAME_GDE_PATH);
+        check_game_gde_hash(&mut file, &index, &game_gde_path, &version_config)
+            .context("哈希校验失败")?;
+    }
+
+    let (replacements, delete_list) = load_manifest(source, selection)?;
+    let pinned: Vec<(String, String)> = parse_original_md5(&manifest.content)?
+        .into_iter()
+        .map(|(path, md5)| (source.physical_path(&path), md5))
+        .collect();
+    check_original_md5(&mut file, &index, &pinned, &replacements).context("目标文件校验失败")?;
+    if !delete_list.is_empty() {
+        bail!("覆盖包无法删除原 PCK 中的文件，请把 delete 改为 stub");
+    }
+    if replacements.is_empty() {
+        bail!("replace.toml 中没有需要打包的资源");
+    }
+
+    let dir = overlay::overlay_dir(Path::new(file_path))?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("无法创建目录: {}", dir.display()))?;
+    let mod_name = overlay::sanitize_mod_name(mod_name);
+    let pack_name = overlay::pack_file_name(priority, &mod_name);
+    let out_path = dir.join(&pack_name);
+
+    let mut inputs: Vec<(String, pck::PackInput)> = replacements
+        .into_iter()
+        .map(|(path, data)| (path, pck::PackInput::Data(data)))
+        .collect();
+    inputs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = std::fs::File::create(&out_path)
+        .with_context(|| format!("无法创建覆盖包: {}", out_path.display()))?;
+    let godot_version = [
+        header.godot_version_major,
+        header.godot_version_minor,
+        header.godot_version_patch,
+    ];
+    let total: u64 = inputs
+        .iter()
+        .map(|(_, input)| match input {
+            pck::PackInput::Data(data) => data.len() as u64,
+            pck::PackInput::File(_) => 0,
+        })
+        .sum();
+    let io = config::load().io.resolve(total);
+    pck::write_pck(&mut out, 0, godot_version, &inputs, &io)?;
+
+    overlay::remove_stale_packs(&dir, &mod_name, &pack_name)?;
+    overlay::write_load_order(&dir)?;
+
+    Ok(out_path)
+}
+
+/// 读取 replace.toml 并合并其 include 的文件与启用的可选功能
+fn compose_manifest<S: AssetSource>(
+    source: &S,
+    selection: &manifest::OptionSelection,
+) -> Result<manifest::Composed> {
+    manifest::compose(&source.config_content(), selection, |path| {
+        String::from_utf8(source.get_file(path)?)
+            .with_context(|| format!("{} 不是有效的 UTF-8 文本", path))
+    })
+}
+
+/// 解析 replace.toml 并把其中的路径解析为 PCK 中的实际路径
+fn load_manifest<S: AssetSource>(
+    source: &S,
+    selection: &manifest::OptionSelection,
+) -> Result<ParsedConfig> {
+    let (replacements, delete_list) = compose_manifest(source, selection)
+        .and_then(|manifest| parse_config(&manifest, |asset_path| source.get_file(asset_path)))
+        .context("加载 replace.toml 失败")?;
+    Ok((
+        replacements
+            .into_iter()
+            .map(|(path, data)| (source.physical_path(&path), data))
+            .collect(),
+        delete_list
+            .iter()
+            .map(|path| source.physical_path(path))
+            .collect(),
+    ))
+}
+
+fn run_tweak<S: AssetSource>(
+    file_path: &str,
+    source: &S,
+    options: &ApplyOptions,
+) -> Result<PatchReport> {
+    ensure_no_pending_update(file_path)?;
+    if journal::recover(Path::new(file_path))
+        .with_context(|| format!("修改失败，无法回滚上次未完成的修改: {}", file_path))?
+    {
+        println!("⚠ 上次修改 {} 时程序意外退出，已回滚到那次修改之前的状态", file_path);
+    }
+    pending::ensure_none(Path::new(file_path))?;
+
+    let atomic = if options.stage {
+        stage("stage_copy", "正在复制 PCK 到临时文件（暂存模式）...");
+        Some(AtomicCopy::create(Path::new(file_path))?)
+    } else if options.atomic {
+        stage("atomic_copy", "正在复制 PCK 到临时文件（原子模式）...");
+        Some(AtomicCopy::create(Path::new(file_path))?)
+    } else {
+        None
+    };
+    let work_path = atomic.as_ref().map_or(Path::new(file_path), |copy| &copy.partial);
+    let file = OpenOptions::new()
         .read(true)
         .write(true)
-        .open(file_path)
-        .with_context(|| format!("修改失败，无法打开文件: {}", file_path))?;
+        .open(work_path)
+        .with_context(|| format!("修改失败，无法打开文件: {}", work_path.display()))?;
+    let limits = options.limits.unwrap_or_else(|| config::load().limits.resolve());
+    let mut file = pck::Keyed::new(file, options.encryption_key).with_limits(limits);
+    let archive_size_before = file.get_ref().metadata().context("无法读取 PCK 文件大小")?.len();
 
-    println!("正在读取 PCK 文件头与索引...");
+    stage("read_index", "正在读取 PCK 文件头与索引...");
     let (header, index) = pck::read_header_and_index(&mut file)
         .with_context(|| format!("修改失败，读取 PCK 头与索引失败: {}", file_path))?;
 
-    println!("正在加载版本配置...");
-    let version_config = parse_version_config(&source.config_content())
+    let duplicates = pck::find_duplicate_entries(&mut file)
+        .with_context(|| format!("修改失败，检查重复 entry 失败: {}", file_path))?;
+    if !duplicates.is_empty() {
+        bail!(
+            "PCK 中有 {} 个路径存在重复 entry，请先使用 repair --dedupe-entries 修复: {}",
+            duplicates.len(),
+            file_path
+        );
+    }
+
+    stage("load_version", "正在加载版本配置...");
+    let manifest = compose_manifest(source, &options.selection)
+        .with_context(|| format!("修改失败，加载 replace.toml 失败: {}", file_path))?;
+    let version_config = parse_version_config(&manifest.content)
         .with_context(|| format!("修改失败，加载版本配置失败: {}", file_path))?;
     println!(
         "✓ 版本配置加载成功，要求游戏版本: {}",
         version_config.required_game_version
     );
 
-    println!("正在校验版本信息...");
-    let has_plugin_version = check_plugin_version_txt(&mut file, &header, &index, &version_config)
+    stage("check_requirements", "正在检查 MOD 的运行前提...");
+    parse_requirements(&manifest.content)
+        .and_then(|requirements| check_requirements(&requirements, Path::new(file_path)))
+        .with_context(|| format!("修改失败，不满足 MOD 的运行前提: {}", file_path))?;
+
+    stage("check_version", "正在校验版本信息...");
+    let has_plugin_version = check_plugin_version_txt(&mut file, &index, &version_config)
         .with_context(|| format!("修改失败，版本校验失败: {}", file_path))?;
 
     if !has_plugin_version {
-        println!("未检测到 plugin_version.txt，正在校验 Game.gde 哈希...");
-        check_game_gde_hash(&mut file, &header, &index, &version_config)
+        stage(
+            "check_game_hash",
+            "未检测到 plugin_version.txt，正在校验 Game.gde 哈希...",
+        );
+        let game_gde_path = source.physical_path(GAME_GDE_PATH);
+        check_game_gde_hash(&mut file, &index, &game_gde_path, &version_config)
             .with_context(|| format!("修改失败，哈希校验失败: {}", file_path))?;
     }
 
-    println!("正在加载替换配置...");
-    let (mut replacements_owned, delete_list) =
-        parse_config(&source.config_content(), |asset_path| {
-            source.get_file(asset_path)
-        })
-        .context("加载 replace.toml 失败")?;
+    stage("load_manifest", "正在加载替换配置...");
+    if !manifest.enabled_options.is_empty() {
+        println!("✓ 已启用可选功能: {}", manifest.enabled_options.join(", "));
+    }
+    let (mut replacements_owned, mut delete_list) = load_manifest(source, &options.selection)?;
     println!(
         "✓ 替换配置加载成功，{} 个文件待注入",
         replacements_owned.len()
     );
-
-    if !delete_list.is_empty() {
-        pck::delete_files_in_pck(
+    resolve_remaps(&mut file, &index, &mut replacements_owned, &mut delete_list)
+        .with_context(|| format!("修改失败，解析 .import/.remap 重定向失败: {}", file_path))?;
+    let paired = add_import_pairs(source, &mut replacements_owned)?;
+    if paired > 0 {
+        println!(
+            "✓ 按 {} 一并注入 {} 个配对文件",
+            import_pairs::FILE_NAME,
+            paired
+        );
+    }
+    let settings = parse_project_settings(&manifest.content)
+        .with_context(|| format!("修改失败，加载 [project-settings] 失败: {}", file_path))?;
+    if !settings.is_empty() {
+        let project_binary = source.physical_path(project_settings::PROJECT_BINARY_PATH);
+        patch_project_settings(
             &mut file,
-            &header,
             &index,
-            delete_list.iter().map(|s| s.as_str()).collect(),
+            &project_binary,
+            &settings,
+            &mut replacements_owned,
         )
-        .context("删除指定文件失败")?;
-        println!("✓ 已删除 {} 个指定文件", delete_list.len());
+        .with_context(|| format!("修改失败，修改项目设置失败: {}", file_path))?;
+    }
+    let encrypt = parse_path_list(&parse_manifest_table(&manifest.content)?, "encrypt")?;
+    let encrypt = resolve_encrypt_list(&mut file, &index, &encrypt, source, &replacements_owned)
+        .with_context(|| format!("修改失败，解析 encrypt 失败: {}", file_path))?;
+    if !encrypt.is_empty() {
+        if options.safe_mode {
+            bail!("安全模式不支持 encrypt：加密会改变 entry 的大小与标志");
+        }
+        println!("✓ {} 个文件将加密写入", encrypt.len());
     }
 
-    let (header, index) = pck::read_header_and_index(&mut file).context("删除后重读 PCK 失败")?;
+    let plugin_version_path = PLUGIN_VERSION_PATH;
+    let touched_paths: Vec<String> = delete_list
+        .iter()
+        .chain(replacements_owned.iter().map(|(path, _)| path))
+        .cloned()
+        .chain(std::iter::once(plugin_version_path.to_string()))
+        .collect();
+    let before = snapshot_entries(&mut file, &index, &touched_paths)
+        .context("读取修改前的 entry 信息失败")?;
+
+    let pinned = parse_original_md5(&manifest.content)
+        .with_context(|| format!("修改失败，加载 [original-md5] 失败: {}", file_path))?;
+    if !pinned.is_empty() {
+        let message = format!("正在校验 {} 个目标文件的原始 MD5...", pinned.len());
+        stage("check_original_md5", &message);
+        let pinned: Vec<(String, String)> = pinned
+            .into_iter()
+            .map(|(path, md5)| (source.physical_path(&path), md5))
+            .collect();
+        check_original_md5(&mut file, &index, &pinned, &replacements_owned)
+            .with_context(|| format!("修改失败，目标文件校验失败: {}", file_path))?;
+    }
+
+    if options.safe_mode {
+        check_safe_mode(
+            &index,
+            &replacements_owned,
+            &delete_list,
+            plugin_version_path,
+        )?;
+    }
 
     let plugin_version_content = create_plugin_version_content(&version_config);
-    println!(
-        "✓ 准备注入 plugin_version.txt (版本: {})",
-        version_config.plugin_version
-    );
-    replacements_owned.push((
-        "res://plugin_version.txt".to_string(),
-        plugin_version_content,
-    ));
+    let settings = config::load();
+    let mut io = settings.io.resolve(archive_size_before);
+    io.jobs = options.jobs.unwrap_or_else(|| settings.threads.resolve());
+    io.cancel = options.cancel.clone();
+    // 原位写回会改写原有数据，只在有回滚日志的就地修改中开启
+    io.overwrite_in_place = atomic.is_none();
+    if let Some(verify_moves) = options.verify_moves {
+        io.verify_moves = verify_moves;
+    }
+    // 临时副本（原子、暂存模式）出错时直接丢弃，只有就地修改需要回滚日志
+    let journal = match atomic {
+        Some(_) => None,
+        None => {
+            let mut planned: Vec<(&str, u64)> = replacements_owned
+                .iter()
+                .map(|(path, data)| (path.as_str(), data.len() as u64))
+                .collect();
+            planned.push((plugin_version_path, plugin_version_content.len() as u64));
+            let ranges = rollback_ranges(&mut file, &header, &index, &planned, &delete_list)
+                .context("规划修改失败")?;
+            Some(Journal::begin(work_path, file.get_mut(), &ranges, io.chunk_size)?)
+        }
+    };
 
-    let replacements: Vec<(&str, &[u8])> = replacements_owned
-        .iter()
-        .map(|(path, data)| (path.as_str(), data.as_slice()))
-        .collect();
+    // 日志建立之后的任何错误都立即回滚，日志只留给进程被强制结束的情况
+    let changed = (|| -> Result<Vec<ReportEntry>> {
+        if !delete_list.is_empty() {
+            stage("delete", "正在删除指定文件...");
+            let mode = if options.mark_removed {
+                pck::DeleteMode::MarkRemoved
+            } else {
+                pck::DeleteMode::Rewrite
+            };
+            pck::delete_files_in_pck_with(
+                &mut file,
+                &header,
+                &index,
+                delete_list.iter().map(|s| s.as_str()).collect(),
+                mode,
+            )
+            .context("删除指定文件失败")?;
+            println!("✓ 已删除 {} 个指定文件", delete_list.len());
+        }
+
+        let (header, index) = pck::read_header_and_index(&mut file).context("删除后重读 PCK 失败")?;
+
+        println!(
+            "✓ 准备注入 plugin_version.txt (版本: {})",
+            version_config.plugin_version
+        );
+        replacements_owned.push((plugin_version_path.to_string(), plugin_version_content));
+
+        let replacements: Vec<(&str, &[u8])> = replacements_owned
+            .iter()
+            .map(|(path, data)| (path.as_str(), data.as_slice()))
+            .collect();
+
+        let sizes: Vec<(&str, u64)> = replacements
+            .iter()
+            .map(|(path, data)| (*path, data.len() as u64))
+            .collect();
+        let plan = pck::plan_apply(&mut file, &header, &index, &sizes, io.overwrite_in_place)
+            .context("规划修改失败")?;
+        println!(
+            "✓ 将替换 {} 个（其中 {} 个写回原位置）、新增 {} 个文件，搬移 {} 个 entry（{} 字节），\
+             PCK 预计增大 {} 字节",
+            plan.replaced.len(),
+            plan.overwritten.len(),
+            plan.added.len(),
+            plan.moves.len(),
+            plan.move_bytes(),
+            plan.growth()
+        );
+        // 原子模式在此之前已完整复制过一次 PCK，一并计入，前端的进度不会在复制后回退
+        let copy_bytes = plan.copy_bytes(atomic.is_some() as u64);
+        progress::emit(&progress::Event::Plan {
+            replaced: plan.replaced.len(),
+            added: plan.added.len(),
+            moves: plan.moves.len(),
+            move_bytes: plan.move_bytes(),
+            table_bytes: plan.table_bytes(),
+            copy_bytes,
+            io_bytes: plan.io_bytes() + copy_bytes,
+        });
+        match platform::free_space(Path::new(file_path)) {
+            Some(free) if free < plan.growth() => bail!(
+                "磁盘空间不足：修改需要约 {} MB，PCK 所在磁盘只有 {} MB 可用",
+                plan.growth().div_ceil(1024 * 1024),
+                free / 1024 / 1024
+            ),
+            Some(_) => {}
+            None => println!("⚠ 无法确定磁盘可用空间，跳过空间检查"),
+        }
+
+        stage("write", "正在写入 PCK...");
+        let report_progress = |stage: pck::ProgressStage, done_bytes, total_bytes| {
+            progress::emit(&progress::Event::Progress {
+                stage: stage.as_str(),
+                done_bytes,
+                total_bytes,
+            });
+        };
+        let written = if options.safe_mode {
+            pck::replace_existing_files_in_pck(
+                &mut file,
+                &header,
+                &index,
+                replacements,
+                &io,
+                &report_progress,
+            )
+        } else {
+            let encrypt: Vec<&str> = encrypt.iter().map(String::as_str).collect();
+            pck::replace_files_in_pck_reporting(
+                &mut file,
+                &header,
+                &index,
+                replacements,
+                &encrypt,
+                &io,
+                &report_progress,
+            )
+        };
+        written.context("写入/替换 PCK 文件失败")?;
+
+        let (_, index) = pck::read_header_and_index(&mut file).context("修改后重读 PCK 失败")?;
+        let after = snapshot_entries(&mut file, &index, &touched_paths)
+            .context("读取修改后的 entry 信息失败")?;
+
+        let mut entries: Vec<ReportEntry> = delete_list
+            .iter()
+            .filter(|path| before.contains_key(path.as_str()))
+            .map(|path| ReportEntry {
+                path: path.clone(),
+                change: EntryChange::Deleted,
+                before: before.get(path).copied(),
+                after: None,
+            })
+            .collect();
+        entries.extend(replacements_owned.iter().map(|(path, _)| {
+            let existed = before.contains_key(path) && !delete_list.contains(path);
+            ReportEntry {
+                path: path.clone(),
+                change: if existed {
+                    EntryChange::Replaced
+                } else {
+                    EntryChange::Added
+                },
+                before: if existed { before.get(path).copied() } else { None },
+                after: after.get(path).copied(),
+            }
+        }));
+        Ok(entries)
+    })();
+    let entries = match changed {
+        Ok(entries) => entries,
+        Err(err) => return Err(roll_back_on_error(err, journal, file.into_inner(), work_path)),
+    };
+
+    if let Some(journal) = journal {
+        journal.finish(file.get_ref())?;
+    }
+    let archive_size_after = file.get_ref().metadata().context("无法读取 PCK 文件大小")?.len();
+    let report = PatchReport {
+        pck_path: file_path.to_string(),
+        mod_source: source.describe(),
+        game_version: version_config.required_game_version,
+        plugin_version: version_config.plugin_version,
+        archive_size_before,
+        archive_size_after,
+        entries,
+    };
+
+    match atomic {
+        Some(copy) if options.stage => {
+            stage("stage_journal", "正在把修改写入待提交日志...");
+            drop(file);
+            // 来源记录只用于排查冲突，生成失败不影响本次修改
+            let provenance = provenance::after_apply(Path::new(file_path), &report)
+                .map(|store| store.to_toml())
+                .unwrap_or_else(|err| {
+                    println!("⚠ 无法记录修改来源: {:#}", err);
+                    String::new()
+                });
+            let pending = pending::stage(Path::new(file_path), &copy.partial, provenance)?;
+            println!(
+                "✅ 修改已暂存：已追加 {} 字节数据，{} 处共 {} 字节的改动将在 commit 时写入",
+                pending.appended_bytes(),
+                pending.patch_count(),
+                pending.patch_bytes()
+            );
+        }
+        Some(copy) => {
+            stage("atomic_commit", "正在用修改后的临时文件替换原 PCK...");
+            copy.commit(file.into_inner())?;
+        }
+        None => {}
+    }
+    if !options.stage {
+        println!("✅ 所有修改已完成！");
+        // 来源记录只用于排查冲突，写入失败不影响本次修改
+        if let Err(err) = provenance::record_apply(Path::new(file_path), &report) {
+            println!("⚠ 无法记录修改来源: {:#}", err);
+        }
+    }
+    progress::emit(&progress::Event::Finished {
+        pck: file_path,
+        entries: report.entries.len(),
+    });
+
+    Ok(report)
+}
+
+/// 就地修改出错或被取消（如 Ctrl+C）时立即按回滚日志恢复 PCK，不等到下次修改；
+/// 临时副本（原子、暂存模式）在丢弃时自动删除，不需要回滚
+fn roll_back_on_error(
+    err: anyhow::Error,
+    journal: Option<Journal>,
+    file: File,
+    pck: &Path,
+) -> anyhow::Error {
+    let Some(journal) = journal else {
+        return err;
+    };
+    drop(journal);
+    drop(file);
+    let what = if err.is::<Cancelled>() {
+        "已取消"
+    } else {
+        "修改失败"
+    };
+    match journal::recover(pck) {
+        Ok(_) => {
+            println!("{}，{} 已回滚到修改前的状态", what, pck.display());
+            err
+        }
+        Err(rollback) => err.context(format!("{}，但回滚失败: {:#}", what, rollback)),
+    }
+}
+
+/// 就地修改可能改写的原有字节区间（偏移, 长度），保存到回滚日志中：
+/// header 与 entry 表（含扩展后覆盖的数据）、新数据不大于原数据而可能原位写入的 entry，
+/// 以及删除后可能被截掉的文件尾部。删除后表只会更短，按删除前规划即可覆盖实际写入的范围
+fn rollback_ranges<P: pck::PckStorage>(
+    file: &mut P,
+    header: &pck::Header,
+    index: &HashMap<String, u64>,
+    replacements: &[(&str, u64)],
+    delete_list: &[String],
+) -> Result<Vec<(u64, u64)>> {
+    let plan = pck::plan_apply(file, header, index, replacements, true)?;
+    let (key, limits) = (file.encryption_key().copied(), file.table_limits());
+    let (_, entries) = pck::read_entries(std::io::BufReader::new(file), key.as_ref(), limits)?;
+    let entries: HashMap<String, pck::RawFileEntry> = entries.into_iter().collect();
+
+    let table_end = plan.table.table_end_after.max(plan.table.region.end);
+    let mut ranges = vec![(0, table_end)];
+    for (path, size) in replacements {
+        if let Some(entry) = entries.get(*path)
+            && *size <= entry.stored_size()
+        {
+            ranges.push((entry.offset, entry.stored_size()));
+        }
+    }
+    if !delete_list.is_empty() {
+        let kept_end = entries
+            .iter()
+            .filter(|(path, _)| !delete_list.contains(path))
+            .map(|(_, entry)| entry.offset + entry.stored_size())
+            .max()
+            .unwrap_or(table_end);
+        ranges.push((kept_end, plan.archive_size.saturating_sub(kept_end)));
+    }
+    Ok(ranges)
+}
+
+/// 原子模式下 PCK 的临时副本，与 PCK 在同一目录以保证改名是原子的；未提交就被丢弃时删除副本
+struct AtomicCopy {
+    partial: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl AtomicCopy {
+    fn create(target: &Path) -> Result<Self> {
+        let mut partial = target.as_os_str().to_os_string();
+        partial.push(".atomic.partial");
+        let partial = PathBuf::from(partial);
+
+        let size = fs::metadata(target)
+            .with_context(|| format!("修改失败，无法读取文件: {}", target.display()))?
+            .len();
+        if let Some(free) = platform::free_space(target)
+            && free < size
+        {
+            bail!(
+                "磁盘空间不足：原子模式需要先复制一份 PCK（约 {} MB），PCK 所在磁盘只有 {} MB 可用",
+                size.div_ceil(1024 * 1024),
+                free / 1024 / 1024
+            );
+        }
+
+        let copy = Self {
+            partial,
+            target: target.to_path_buf(),
+            committed: false,
+        };
+        fs::copy(target, &copy.partial).with_context(|| {
+            format!("无法复制 {} 到 {}", target.display(), copy.partial.display())
+        })?;
+        Ok(copy)
+    }
+
+    /// 把副本写入磁盘后改名覆盖原 PCK
+    fn commit(mut self, file: File) -> Result<()> {
+        file.sync_all()
+            .with_context(|| format!("无法写入磁盘: {}", self.partial.display()))?;
+        drop(file);
+        fs::rename(&self.partial, &self.target).with_context(|| {
+            format!("无法覆盖 {}，请确认游戏已关闭", self.target.display())
+        })?;
+        self.committed = true;
+        // 目录项也落盘后，断电时才不会退回到改名之前
+        #[cfg(unix)]
+        if let Some(dir) = self.target.parent()
+            && let Ok(dir) = File::open(dir)
+        {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+}
 
-    pck::replace_files_in_pck(&mut file, &header, &index, replacements)
-        .context("写入/替换 PCK 文件失败")?;
+impl Drop for AtomicCopy {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.partial);
+        }
+    }
+}
 
-    println!("✅ 所有修改已完成！");
+/// 把 PCK 中不存在、但经 `.import`/`.remap` 重定向的路径换成引擎实际加载的文件，
+/// 让 manifest 可以按可读的原资源路径编写。删除时连同配对文件一起删除；
+/// delete 中的通配符模式先展开为 PCK 中匹配的路径
+fn resolve_remaps<P: pck::PckStorage>(
+    file: &mut P,
+    index: &HashMap<String, u64>,
+    replacements: &mut Vec<(String, Vec<u8>)>,
+    delete_list: &mut Vec<String>,
+) -> Result<()> {
+    let mut resolved = Vec::with_capacity(replacements.len());
+    let mut remapped = Vec::new();
+    for (path, data) in replacements.drain(..) {
+        match pck::resolve_remap(file, index, &path)? {
+            Some(targets) => remapped.push((path, targets, data)),
+            None => resolved.push((path, data)),
+        }
+    }
+    for (path, targets, data) in remapped {
+        println!("✓ {} 重定向到 {}", path, targets.join(", "));
+        for target in targets {
+            // 同时写了原资源路径与重定向后的路径时，以明确写出的为准
+            if resolved.iter().any(|(existing, _)| *existing == target) {
+                println!("⚠ {} 已有单独的替换规则，忽略经 {} 的重定向", target, path);
+                continue;
+            }
+            resolved.push((target, data.clone()));
+        }
+    }
+    *replacements = resolved;
+
+    let mut expanded: Vec<String> = Vec::with_capacity(delete_list.len());
+    for pattern in delete_list.drain(..) {
+        if !pck::is_glob(&pattern) || index.contains_key(&pattern) {
+            expanded.push(pattern);
+            continue;
+        }
+        let mut matched: Vec<String> = index
+            .keys()
+            .filter(|path| pck::glob_match(&pattern, path))
+            .cloned()
+            .collect();
+        matched.sort();
+        // 再次应用时要删除的文件已经不在了，不作为错误
+        match matched.len() {
+            0 => println!("⚠ delete 中的 {} 没有匹配任何文件", pattern),
+            count => println!("✓ delete 中的 {} 匹配 {} 个文件", pattern, count),
+        }
+        expanded.append(&mut matched);
+    }
+
+    let mut resolved: Vec<String> = Vec::with_capacity(expanded.len());
+    for path in expanded {
+        let paths = match pck::resolve_remap(file, index, &path)? {
+            Some(targets) => {
+                println!("✓ {} 重定向到 {}", path, targets.join(", "));
+                [".import", ".remap"]
+                    .iter()
+                    .map(|suffix| format!("{}{}", path, suffix))
+                    .filter(|companion| index.contains_key(companion))
+                    .chain(targets)
+                    .collect()
+            }
+            None => vec![path],
+        };
+        for path in paths {
+            if !resolved.contains(&path) {
+                resolved.push(path);
+            }
+        }
+    }
+    *delete_list = resolved;
     Ok(())
 }
 
+/// `encrypt` 中的路径换成实际写入的路径（与 [`resolve_remaps`] 相同的重定向），
+/// 每个路径都必须是本 MOD 写入的文件
+fn resolve_encrypt_list<P: pck::PckStorage, S: AssetSource>(
+    file: &mut P,
+    index: &HashMap<String, u64>,
+    encrypt: &[String],
+    source: &S,
+    replacements: &[(String, Vec<u8>)],
+) -> Result<Vec<String>> {
+    let written = |path: &str| replacements.iter().any(|(p, _)| p == path);
+    let mut resolved = Vec::new();
+    for path in encrypt {
+        let path = source.physical_path(path);
+        let targets = if written(&path) {
+            vec![path]
+        } else {
+            match pck::resolve_remap(file, index, &path)? {
+                Some(targets) if targets.iter().all(|t| written(t)) => targets,
+                _ => bail!("{} 不是本 MOD 写入的文件，只能加密 [replace] 或 stub 中的文件", path),
+            }
+        };
+        for target in targets {
+            if !resolved.contains(&target) {
+                resolved.push(target);
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// 资源目录带有 extract 生成的配对记录时，补上与已替换文件同组、且目录中存在的文件，返回补上的数量
+fn add_import_pairs<S: AssetSource>(
+    source: &S,
+    replacements: &mut Vec<(String, Vec<u8>)>,
+) -> Result<usize> {
+    let Ok(content) = source.get_file(import_pairs::FILE_NAME) else {
+        return Ok(0);
+    };
+    let pairs = String::from_utf8(content)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| import_pairs::parse(&content))
+        .with_context(|| format!("{} 格式错误", import_pairs::FILE_NAME))?;
+
+    let replaced: Vec<&str> = replacements.iter().map(|(path, _)| path.as_str()).collect();
+    let mut added = Vec::new();
+    for member in import_pairs::missing_members(&pairs, &replaced) {
+        let asset_path = member.strip_prefix("res://").unwrap_or(&member);
+        match source.get_file(asset_path) {
+            Ok(data) => added.push((member, data)),
+            // 导出的游戏中原资源常常不在 PCK 里，目录中没有它是正常的
+            Err(_) if pairs.iter().any(|pair| pair.source == member) => {}
+            Err(_) => println!(
+                "⚠ {} 与已替换的文件同组，但资源目录中没有它，引擎可能仍加载旧数据",
+                member
+            ),
+        }
+    }
+    let count = added.len();
+    replacements.extend(added);
+    Ok(count)
+}
+
+/// 输出阶段说明，并作为进度事件发给外部前端
+fn stage(stage: &str, message: &str) {
+    println!("{}", message);
+    progress::emit(&progress::Event::Stage { stage, message });
+}
+
+/// 读取给定路径当前 entry 的大小与 MD5，不存在的路径跳过
+pub fn snapshot_entries<P: pck::PckStorage>(
+    pck_file: &mut P,
+    entry_offsets: &HashMap<String, u64>,
+    paths: &[String],
+) -> Result<HashMap<String, EntryDigest>> {
+    let (key, limits) = (pck_file.encryption_key().copied(), pck_file.table_limits());
+    let mut reader = std::io::BufReader::new(pck_file);
+    let header = pck::read_header(&mut reader)?;
+    let mut digests = HashMap::new();
+
+    for path in paths {
+        let Some(entry_offset) = entry_offsets.get(path) else {
+            continue;
+        };
+        let entry = pck::read_entry_at(&mut reader, &header, *entry_offset, key.as_ref(), limits)
+            .with_context(|| format!("无法读取文件 entry: {}", path))?;
+        digests.insert(
+            path.clone(),
+            EntryDigest {
+                size: entry.size,
+                md5: entry.md5,
+            },
+        );
+    }
+
+    Ok(digests)
+}
+
+/// Steam 正在下载/更新游戏时拒绝修改，避免破坏进行中的更新
+fn ensure_no_pending_update(file_path: &str) -> Result<()> {
+    match steam::detect_update_in_progress(std::path::Path::new(file_path)) {
+        None => Ok(()),
+        Some(UpdateActivity::StateFlags(flags)) => bail!(
+            "Steam 正在下载或更新游戏（StateFlags = {}），请等待更新完成后再修改",
+            flags
+        ),
+        Some(UpdateActivity::StagingDir(dir)) => bail!(
+            "Steam 的更新暂存目录不为空，游戏可能正在更新，请等待更新完成后再修改: {}",
+            dir.display()
+        ),
+        Some(UpdateActivity::PatchFile(patch)) => bail!(
+            "游戏目录中存在未完成的更新文件，请等待 Steam 更新完成后再修改: {}",
+            patch.display()
+        ),
+    }
+}
+
+/// 先做结构校验，出错时报告行列号与正确写法，再解析为表
+fn parse_manifest_table(config_str: &str) -> Result<toml::value::Table> {
+    manifest::check_schema(config_str)?;
+    toml::from_str(config_str).context("解析 replace.toml 失败")
+}
+
 fn parse_version_config(config_str: &str) -> Result<VersionConfig> {
-    let table: toml::value::Table = toml::from_str(config_str).context("解析 replace.toml 失败")?;
+    let table = parse_manifest_table(config_str)?;
 
     let version_table = table
         .get("version")
@@ -204,14 +1102,214 @@ fn parse_version_config(config_str: &str) -> Result<VersionConfig> {
     })
 }
 
-fn parse_config<F>(
-    config_str: &str,
-    mut load_asset: F,
-) -> Result<(Vec<(String, Vec<u8>)>, Vec<String>)>
+/// manifest 中 `[require]` 声明的运行前提，在任何写入之前检查
+#[derive(Debug, Default)]
+struct Requirements {
+    min_tool_version: Option<String>,
+    platforms: Vec<String>,
+    free_space_mb: Option<u64>,
+}
+
+const KNOWN_PLATFORMS: [&str; 3] = ["windows", "linux", "macos"];
+
+fn parse_requirements(config_str: &str) -> Result<Requirements> {
+    let table = parse_manifest_table(config_str)?;
+    let Some(require) = table.get("require").and_then(|v| v.as_table()) else {
+        return Ok(Requirements::default());
+    };
+
+    let platforms = match require.get("platform") {
+        Some(toml::Value::String(platform)) => vec![platform.clone()],
+        Some(toml::Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    for platform in &platforms {
+        if !KNOWN_PLATFORMS.contains(&platform.as_str()) {
+            bail!(
+                "require.platform 中未知的平台: {}（可用: {}）",
+                platform,
+                KNOWN_PLATFORMS.join("、")
+            );
+        }
+    }
+
+    let free_space_mb = match require.get("free_space_mb").and_then(|v| v.as_integer()) {
+        Some(mb) if mb < 0 => bail!("require.free_space_mb 不能为负数: {}", mb),
+        other => other.map(|mb| mb as u64),
+    };
+
+    Ok(Requirements {
+        min_tool_version: require
+            .get("min_tool_version")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        platforms,
+        free_space_mb,
+    })
+}
+
+/// 逐条检查前提条件；可用空间无法确定时只提示不阻止
+fn check_requirements(requirements: &Requirements, pck_path: &Path) -> Result<()> {
+    if let Some(min) = &requirements.min_tool_version {
+        let tool_version = env!("CARGO_PKG_VERSION");
+        if !version_at_least(tool_version, min)? {
+            bail!(
+                "此 MOD 需要 bpb_enhance {} 或更高版本，当前为 {}，请先更新本工具",
+                min,
+                tool_version
+            );
+        }
+    }
+
+    if !requirements.platforms.is_empty()
+        && !requirements.platforms.iter().any(|p| p == platform::name())
+    {
+        bail!(
+            "此 MOD 只支持 {}，当前平台为 {}",
+            requirements.platforms.join("、"),
+            platform::name()
+        );
+    }
+
+    if let Some(mb) = requirements.free_space_mb {
+        match platform::free_space(pck_path) {
+            Some(free) if free < mb.saturating_mul(1024 * 1024) => bail!(
+                "此 MOD 要求 PCK 所在磁盘至少有 {} MB 可用空间，当前只有 {} MB",
+                mb,
+                free / 1024 / 1024
+            ),
+            Some(_) => {}
+            None => println!("⚠ 无法确定磁盘可用空间，跳过 free_space_mb 检查"),
+        }
+    }
+
+    Ok(())
+}
+
+/// 按点分隔的数字逐段比较，缺少的段视为 0
+fn version_at_least(current: &str, required: &str) -> Result<bool> {
+    let parse = |version: &str| -> Option<Vec<u64>> {
+        version
+            .trim()
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect()
+    };
+    let required_parts = parse(required)
+        .ok_or_else(|| anyhow!("require.min_tool_version 不是有效的版本号: {}", required))?;
+    let mut current_parts = parse(current).unwrap_or_default();
+
+    let len = required_parts.len().max(current_parts.len());
+    current_parts.resize(len, 0);
+    let mut required_parts = required_parts;
+    required_parts.resize(len, 0);
+    Ok(current_parts >= required_parts)
+}
+
+/// `[original-md5]`：MOD 作者为目标文件固定的原始 MD5（res 路径, 小写十六进制）
+fn parse_original_md5(config_str: &str) -> Result<Vec<(String, String)>> {
+    let table = parse_manifest_table(config_str)?;
+    let Some(pinned) = table.get("original-md5").and_then(|v| v.as_table()) else {
+        return Ok(Vec::new());
+    };
+
+    pinned
+        .iter()
+        .map(|(path, value)| {
+            let md5 = value.as_str().unwrap_or_default();
+            if !manifest::is_md5_hex(md5) {
+                bail!("[original-md5] 中的值必须是 32 位十六进制 MD5: {}", path);
+            }
+            Ok((path.clone(), md5.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+fn parse_project_settings(config_str: &str) -> Result<Vec<(String, Setting)>> {
+    let table = parse_manifest_table(config_str)?;
+    let Some(settings) = table.get("project-settings").and_then(|v| v.as_table()) else {
+        return Ok(Vec::new());
+    };
+
+    settings
+        .iter()
+        .map(|(key, value)| {
+            let setting = Setting::from_toml(value).ok_or_else(|| {
+                anyhow!("[project-settings] 中的值必须是布尔、整数、浮点数或字符串: {}", key)
+            })?;
+            Ok((key.clone(), setting))
+        })
+        .collect()
+}
+
+/// 把 `settings` 写入 project.binary 并加入替换列表；MOD 同时替换了 project.binary 时修改替换后的内容
+fn patch_project_settings<P: pck::PckStorage>(
+    pck_file: &mut P,
+    index: &HashMap<String, u64>,
+    project_binary: &str,
+    settings: &[(String, Setting)],
+    replacements: &mut Vec<(String, Vec<u8>)>,
+) -> Result<()> {
+    let replaced = replacements.iter().position(|(path, _)| path == project_binary);
+    let data = match replaced {
+        Some(i) => replacements.remove(i).1,
+        None => pck::read_file_data(pck_file, index, project_binary)?,
+    };
+    let mut project = ProjectSettings::parse(&data)?;
+    for (key, value) in settings {
+        match project.set(key, value.clone()) {
+            Some(old) => println!("✓ 项目设置 {}: {} -> {}", key, old, value),
+            None => println!("✓ 新增项目设置 {} = {}", key, value),
+        }
+    }
+    replacements.push((project_binary.to_string(), project.to_bytes()));
+    Ok(())
+}
+
+/// 写入前逐个计算目标文件当前内容的 MD5：与固定值一致，或已经是本 MOD 要写入的内容（重复应用）时通过；
+/// 否则说明文件被其他工具改过，继续替换会叠加出错误的结果
+fn check_original_md5<P: pck::PckStorage>(
+    pck_file: &mut P,
+    index: &HashMap<String, u64>,
+    pinned: &[(String, String)],
+    replacements: &[(String, Vec<u8>)],
+) -> Result<()> {
+    for (path, expected) in pinned {
+        if !index.contains_key(path) {
+            bail!("[original-md5] 中的 {} 在 PCK 中不存在", path);
+        }
+        // 目标可能是很大的音视频，分块计算而不是整个读入内存
+        let current = format!("{:x}", md5::Digest(pck::read_file_md5(pck_file, index, path)?));
+        if current == *expected {
+            continue;
+        }
+        let already_applied = replacements
+            .iter()
+            .any(|(p, data)| p == path && compute_file_hash(data) == current);
+        if !already_applied {
+            bail!(
+                "{} 的 MD5 为 {}，与 MOD 要求的原始 MD5 {} 不符，可能已被其他工具修改；请先验证游戏文件完整性后再应用",
+                path,
+                current,
+                expected
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 替换列表（res 路径 -> 文件内容）与删除列表
+type ParsedConfig = (Vec<(String, Vec<u8>)>, Vec<String>);
+
+fn parse_config<F>(manifest: &manifest::Composed, mut load_asset: F) -> Result<ParsedConfig>
 where
     F: FnMut(&str) -> Result<Vec<u8>>,
 {
-    let table: toml::value::Table = toml::from_str(config_str).context("解析 replace.toml 失败")?;
+    let table = parse_manifest_table(&manifest.content)?;
 
     let replace_table = table
         .get("replace")
@@ -224,87 +1322,119 @@ where
             .as_str()
             .ok_or_else(|| anyhow!("[replace] 中的值必须是字符串: {}", res_path))?;
 
-        let asset_data = load_asset(asset_path)?;
+        let asset_data = load_asset(asset_path).with_context(|| {
+            format!(
+                "{} 的资源加载失败（规则来自 {}）",
+                res_path,
+                manifest.origin("replace", res_path)
+            )
+        })?;
         replacements.push((res_path.clone(), asset_data));
     }
 
-    let delete_list = table
-        .get("delete")
-        .map(|v| {
-            if let Some(arr) = v.as_array() {
-                arr.iter()
-                    .map(|val| {
-                        val.as_str()
-                            .ok_or_else(|| anyhow!("delete 数组元素必须是字符串"))
-                            .map(|s| s.to_string())
-                    })
-                    .collect::<Result<Vec<String>>>()
-            } else if let Some(t) = v.as_table() {
-                let arr = t
-                    .get("paths")
-                    .ok_or_else(|| anyhow!("delete 表需要 paths 数组"))?
-                    .as_array()
-                    .ok_or_else(|| anyhow!("delete.paths 必须是数组"))?;
-                arr.iter()
-                    .map(|val| {
-                        val.as_str()
-                            .ok_or_else(|| anyhow!("delete.paths 元素必须是字符串"))
-                            .map(|s| s.to_string())
-                    })
-                    .collect::<Result<Vec<String>>>()
-            } else {
-                Err(anyhow!("delete 必须是数组或包含 paths 的表"))
-            }
-        })
-        .transpose()?
-        .unwrap_or_default();
+    let delete_list = parse_path_list(&table, "delete")?;
+
+    // stub：用同类型的最小资源代替原文件，效果接近删除但不会让游戏因缺失资源崩溃
+    for res_path in parse_path_list(&table, "stub")? {
+        let stub_origin = manifest.origin("stub", &res_path);
+        if replacements.iter().any(|(path, _)| *path == res_path) {
+            bail!(
+                "{} 同时出现在 [replace]（{}）与 stub（{}）中",
+                res_path,
+                manifest.origin("replace", &res_path),
+                stub_origin
+            );
+        }
+        if delete_list.contains(&res_path) {
+            bail!(
+                "{} 同时出现在 delete（{}）与 stub（{}）中",
+                res_path,
+                manifest.origin("delete", &res_path),
+                stub_origin
+            );
+        }
+        replacements.push((res_path.clone(), stub::stub_resource(&res_path)?));
+    }
 
     Ok((replacements, delete_list))
 }
 
-fn compute_file_hash(data: &[u8]) -> String {
-    format!("{:x}", md5::compute(data))
-}
+/// 读取 `key = [...]` 或 `[key] paths = [...]` 形式的路径列表
+fn parse_path_list(table: &toml::value::Table, key: &str) -> Result<Vec<String>> {
+    let Some(value) = table.get(key) else {
+        return Ok(Vec::new());
+    };
 
-fn read_file_from_pck(
-    pck_file: &mut std::fs::File,
-    _header: &pck::Header,
-    entry_offsets: &HashMap<String, u64>,
-    res_path: &str,
-) -> Result<Vec<u8>> {
-    let entry_offset = entry_offsets
-        .get(res_path)
-        .ok_or_else(|| anyhow!("PCK 中不存在文件: {}", res_path))?;
+    let (arr, item_name) = if let Some(arr) = value.as_array() {
+        (arr, format!("{} 数组元素", key))
+    } else if let Some(t) = value.as_table() {
+        let arr = t
+            .get("paths")
+            .ok_or_else(|| anyhow!("{} 表需要 paths 数组", key))?
+            .as_array()
+            .ok_or_else(|| anyhow!("{}.paths 必须是数组", key))?;
+        (arr, format!("{}.paths 元素", key))
+    } else {
+        bail!("{} 必须是数组或包含 paths 的表", key);
+    };
 
-    let mut reader = std::io::BufReader::new(pck_file.try_clone()?);
-    reader
-        .seek(SeekFrom::Start(*entry_offset))
-        .with_context(|| format!("无法定位文件 entry: {}", res_path))?;
+    arr.iter()
+        .map(|val| {
+            val.as_str()
+                .ok_or_else(|| anyhow!("{}必须是字符串", item_name))
+                .map(|s| s.to_string())
+        })
+        .collect()
+}
 
-    let entry = pck::RawFileEntry::read(&mut reader)
-        .with_context(|| format!("无法读取文件 entry: {}", res_path))?;
+fn compute_file_hash(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}
 
-    let mut data = vec![0u8; entry.size as usize];
-    reader
-        .seek(SeekFrom::Start(entry.offset))
-        .with_context(|| format!("无法定位文件数据: {}", res_path))?;
-    reader
-        .read_exact(&mut data)
-        .with_context(|| format!("无法读取文件数据: {}", res_path))?;
+/// 安全模式下在修改前检查：所有替换目标（含 plugin_version.txt）都已存在，且没有要删除的已有文件
+fn check_safe_mode(
+    index: &HashMap<String, u64>,
+    replacements: &[(String, Vec<u8>)],
+    delete_list: &[String],
+    plugin_version_path: &str,
+) -> Result<()> {
+    let added: Vec<&str> = replacements
+        .iter()
+        .map(|(path, _)| path.as_str())
+        .chain(std::iter::once(plugin_version_path))
+        .filter(|path| !index.contains_key(*path))
+        .collect();
+    let deleted: Vec<&str> = delete_list
+        .iter()
+        .map(String::as_str)
+        .filter(|path| index.contains_key(*path))
+        .collect();
 
-    Ok(data)
+    let mut problems = Vec::new();
+    if !added.is_empty() {
+        problems.push(format!("需要新增 {}", added.join(", ")));
+    }
+    if !deleted.is_empty() {
+        problems.push(format!("需要删除 {}", deleted.join(", ")));
+    }
+    if !problems.is_empty() {
+        bail!(
+            "安全模式只允许替换已有文件的内容，该 MOD {}；请先以普通模式应用一次",
+            problems.join("，")
+        );
+    }
+    Ok(())
 }
 
-fn check_plugin_version_txt(
-    pck_file: &mut std::fs::File,
-    header: &pck::Header,
+fn check_plugin_version_txt<P: pck::PckStorage>(
+    pck_file: &mut P,
     entry_offsets: &HashMap<String, u64>,
     version_config: &VersionConfig,
 ) -> Result<bool> {
-    let plugin_version_path = "res://plugin_version.txt";
+    let plugin_version_path = PLUGIN_VERSION_PATH;
 
     if entry_offsets.contains_key(plugin_version_path) {
-        let content = read_file_from_pck(pck_file, header, entry_offsets, plugin_version_path)?;
+        let content = pck::read_file_data(pck_file, entry_offsets, plugin_version_path)?;
         let content_str =
             String::from_utf8(content).context("plugin_version.txt 内容无法解析为 UTF-8")?;
 
@@ -336,15 +1466,14 @@ fn check_plugin_version_txt(
     Ok(false)
 }
 
-fn check_game_gde_hash(
-    pck_file: &mut std::fs::File,
-    header: &pck::Header,
+fn check_game_gde_hash<P: pck::PckStorage>(
+    pck_file: &mut P,
     entry_offsets: &HashMap<String, u64>,
+    game_gde_path: &str,
     version_config: &VersionConfig,
 ) -> Result<()> {
-    let game_gde_path = "res://Core/Game.gde";
 
-    let game_gde_data = read_file_from_pck(pck_file, header, entry_offsets, game_gde_path)?;
+    let game_gde_data = pck::read_file_data(pck_file, entry_offsets, game_gde_path)?;
     let current_hash = compute_file_hash(&game_gde_data);
 
     let expected_hash = version_config
@@ -358,11 +1487,22 @@ fn check_game_gde_hash(
         })?;
 
     if current_hash != *expected_hash {
+        // 没有本工具的 plugin_version.txt，哈希又不属于任何已知版本时，也可能是被其他工具改过
+        let hint = if version_config
+            .version_hashes
+            .values()
+            .any(|hash| *hash == current_hash)
+        {
+            ""
+        } else {
+            "；如果游戏已是这个版本，PCK 可能已被其他 MOD 工具修改，可以用 Steam 验证游戏文件完整性后再试"
+        };
         anyhow::bail!(
-            "游戏版本不匹配，当前文件版本哈希: {}，当前插件适用于游戏版本 {}（期望哈希: {}）",
+            "游戏版本不匹配，当前文件版本哈希: {}，当前插件适用于游戏版本 {}（期望哈希: {}）{}",
             current_hash,
             version_config.required_game_version,
-            expected_hash
+            expected_hash,
+            hint
         );
     }
 
@@ -380,3 +1520,53 @@ fn create_plugin_version_content(version_config: &VersionConfig) -> Vec<u8> {
     )
     .into_bytes()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollback_ranges_cover_every_changed_byte() {
+        let dir = std::env::temp_dir().join(format!("bpb_tweak_rollback_{}", std::process::id()));
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        for (name, data) in [("a.txt", "alpha "), ("b.txt", "bravo "), ("c.txt", "charlie ")] {
+            fs::write(dir.join("assets").join(name), data.repeat(200)).unwrap();
+        }
+        let pck_path = dir.join("game.pck");
+        pck::pack_directory(dir.join("assets"), &pck_path, &pck::PackOptions::default()).unwrap();
+        let original = fs::read(&pck_path).unwrap();
+
+        let mut file = OpenOptions::new().read(true).write(true).open(&pck_path).unwrap();
+        let (header, index) = pck::read_header_and_index(&mut file).unwrap();
+        // 原位写回 b、删除最后的 c 截断文件、新增 d 使 entry 表扩展并搬移 a
+        let replacements: Vec<(&str, &[u8])> =
+            vec![("res://b.txt", b"BRAVO"), ("res://d.txt", b"delta")];
+        let delete_list = vec!["res://c.txt".to_string()];
+        let sizes: Vec<(&str, u64)> =
+            replacements.iter().map(|(path, data)| (*path, data.len() as u64)).collect();
+        let ranges = rollback_ranges(&mut file, &header, &index, &sizes, &delete_list).unwrap();
+
+        let mode = pck::DeleteMode::Rewrite;
+        pck::delete_files_in_pck_with(&mut file, &header, &index, vec!["res://c.txt"], mode)
+            .unwrap();
+        let (header, index) = pck::read_header_and_index(&mut file).unwrap();
+        let io = pck::IoOptions {
+            overwrite_in_place: true,
+            ..pck::IoOptions::auto(0)
+        };
+        pck::replace_files_in_pck_with(&mut file, &header, &index, replacements, &io).unwrap();
+
+        let patched = fs::read(&pck_path).unwrap();
+        let covered = |offset: u64| {
+            ranges
+                .iter()
+                .any(|(start, len)| (*start..start + len).contains(&offset))
+        };
+        for (offset, byte) in original.iter().enumerate() {
+            if patched.get(offset) != Some(byte) {
+                assert!(covered(offset as u64), "byte {} changed outside the journal", offset);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! GUI 应用的内置修改资源包：可执行文件旁或数据目录下的 `tweaks` 目录（或设置中的 `[gui] tweak_pack`），
+//! 内容与 CLI 的 assets 目录相同（replace.toml 及其引用的资源），另有记录资源包版本与每个文件 MD5 的
+//! `tweak_pack.toml`。资源包与程序分开发布，游戏更新后只需替换资源包，不必重新编译工具。
+//!
+//! 读取文件时先核对 MD5，索引之外的文件一律拒绝，避免用被改动或下载不完整的资源修改游戏。
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use toml::{Table, Value};
+
+use crate::{config, dirs, manifest, pck};
+
+/// 资源包索引的文件名，位于资源包根目录
+pub const INDEX_FILE: &str = "tweak_pack.toml";
+/// 默认的资源包目录名，见 [`dirs::data_subdir`]
+const DEFAULT_DIR: &str = "tweaks";
+
+#[derive(Debug, Clone)]
+pub struct TweakPack {
+    root: PathBuf,
+    version: String,
+    /// 相对路径 -> 十六进制 MD5
+    files: BTreeMap<String, String>,
+}
+
+impl TweakPack {
+    /// 读取 `root` 下的索引；只检查索引本身，文件在读取时逐个核对
+    pub fn open(root: &Path) -> Result<Self> {
+        let index = root.join(INDEX_FILE);
+        let content = fs::read_to_string(&index)
+            .with_context(|| format!("找不到修改资源包的索引: {}", index.display()))?;
+        let table: Table = content
+            .parse()
+            .with_context(|| format!("资源包索引格式错误: {}", index.display()))?;
+
+        let version = table
+            .get("version")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("资源包索引缺少 version"))?
+            .to_string();
+        let mut files = BTreeMap::new();
+        for (path, md5) in table
+            .get("files")
+            .and_then(Value::as_table)
+            .into_iter()
+            .flatten()
+        {
+            let md5 = md5
+                .as_str()
+                .filter(|md5| manifest::is_md5_hex(md5))
+                .ok_or_else(|| anyhow!("资源包索引中 {} 的 MD5 无效", path))?;
+            files.insert(path.clone(), md5.to_ascii_lowercase());
+        }
+        if !files.contains_key("replace.toml") {
+            bail!("资源包索引中没有 replace.toml: {}", index.display());
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            version,
+            files,
+        })
+    }
+
+    /// 设置中的 `[gui] tweak_pack`，未设置时为可执行文件旁的 `tweaks` 目录，
+    /// 不存在时为数据目录下的 `tweaks`
+    pub fn locate() -> Result<Self> {
+        let root = match config::load().gui.tweak_pack {
+            Some(root) => root,
+            None => dirs::data_subdir(DEFAULT_DIR).context("无法确定资源包目录")?,
+        };
+        Self::open(&root)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 读取资源包中的文件，路径写法与 replace.toml 相同；内容与索引中的 MD5 不符时报错
+    pub fn read(&self, relative_path: &str) -> Result<Vec<u8>> {
+        let relative = relative_path
+            .trim_start_matches("../")
+            .trim_start_matches("./");
+        let expected = self
+            .files
+            .get(relative)
+            .ok_or_else(|| anyhow!("资源包中没有 {}（不在 {} 中）", relative, INDEX_FILE))?;
+        let path = self.root.join(relative);
+        let data = fs::read(&path)
+            .with_context(|| format!("无法读取资源包中的文件: {}", path.display()))?;
+        let actual = format!("{:x}", md5::compute(&data));
+        if actual != *expected {
+            bail!(
+                "资源包中的 {} 已被改动或下载不完整（MD5 为 {}，应为 {}），请重新下载资源包",
+                relative,
+                actual,
+                expected
+            );
+        }
+        Ok(data)
+    }
+}
+
+/// 为 `root` 下除索引外的全部文件生成索引，返回收录的文件数
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub fn write_index(root: &Path, version: &str) -> Result<usize> {
+    if !root.join("replace.toml").is_file() {
+        bail!("{} 中没有 replace.toml", root.display());
+    }
+
+    let mut files = Table::new();
+    for (res_path, _) in pck::collect_pack_inputs(root)? {
+        let relative = res_path.trim_start_matches("res://");
+        if relative == INDEX_FILE {
+            continue;
+        }
+        let path = root.join(relative);
+        let file = File::open(&path).with_context(|| format!("无法打开: {}", path.display()))?;
+        let (md5, _) =
+            pck::digest_reader(file).with_context(|| format!("无法读取: {}", path.display()))?;
+        files.insert(
+            relative.to_string(),
+            Value::String(format!("{:x}", md5::Digest(md5))),
+        );
+    }
+
+    let count = files.len();
+    let mut table = Table::new();
+    table.insert("version".into(), Value::String(version.to_string()));
+    table.insert("files".into(), Value::Table(files));
+    let index = root.join(INDEX_FILE);
+    fs::write(
+        &index,
+        format!(
+            "# 由 bpb_enhance tweak-pack 生成：GUI 读取资源前核对这里的 MD5，修改资源后请重新生成\n{}",
+            table
+        ),
+    )
+    .with_context(|| format!("无法写入: {}", index.display()))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_only_files_matching_the_index() {
+        let root = std::env::temp_dir().join(format!("bpb_tweak_pack_{}", std::process::id()));
+        fs::create_dir_all(root.join("Core")).unwrap();
+        fs::write(root.join("replace.toml"), "[replace]\n").unwrap();
+        fs::write(root.join("Core/Game.gde"), b"extends Node").unwrap();
+        assert_eq!(write_index(&root, "1.2.0").unwrap(), 2);
+        fs::write(root.join("extra.txt"), b"not indexed").unwrap();
+
+        let pack = TweakPack::open(&root).unwrap();
+        assert_eq!(pack.version(), "1.2.0");
+        assert_eq!(pack.read("./Core/Game.gde").unwrap(), b"extends Node");
+        assert!(pack.read("extra.txt").is_err());
+
+        fs::write(root.join("Core/Game.gde"), b"extends Node # edited").unwrap();
+        let error = pack.read("Core/Game.gde").unwrap_err();
+        assert!(error.to_string().contains("已被改动"), "{}", error);
+
+        fs::remove_file(root.join("replace.toml")).unwrap();
+        assert!(write_index(&root, "1.2.1").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! GUI 会话内的撤销栈：每次修改 PCK 前把它复制到暂存区，撤销时按后进先出恢复。
+//! 快照随暂存区在程序退出时删除；需要跨会话恢复时使用 backup 模块的带时间戳备份
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::staging::StagingArea;
+
+/// 最多保留的快照数，超出时丢弃最早的
+const MAX_DEPTH: usize = 10;
+
+#[derive(Debug)]
+struct Snapshot {
+    /// 被撤销的操作，用于提示
+    label: String,
+    pck: PathBuf,
+    /// 暂存区中的文件名
+    name: String,
+    /// 操作完成后 PCK 的大小与修改时间，撤销前据此确认文件没有被其他程序改动
+    after: Option<(u64, SystemTime)>,
+}
+
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    area: Option<StagingArea>,
+    snapshots: Vec<Snapshot>,
+    next_id: usize,
+}
+
+impl UndoStack {
+    #[cfg(test)]
+    fn with_area(area: StagingArea) -> Self {
+        Self {
+            area: Some(area),
+            ..Self::default()
+        }
+    }
+
+    /// 下一次撤销会撤销的操作
+    pub fn peek(&self) -> Option<&str> {
+        self.snapshots.last().map(|s| s.label.as_str())
+    }
+
+    /// 在操作 `label` 修改 `pck` 之前调用，保存当前内容；暂存区放不下时先丢弃最早的快照
+    pub fn record(&mut self, label: impl Into<String>, pck: &Path) -> Result<()> {
+        if self.area.is_none() {
+            self.area = Some(StagingArea::new("undo")?);
+        }
+        let name = format!("{}.pck", self.next_id);
+        self.next_id += 1;
+
+        if self.snapshots.len() >= MAX_DEPTH {
+            self.drop_oldest()?;
+        }
+        loop {
+            let area = self.area.as_mut().expect("暂存区已创建");
+            match area.copy_in(&name, pck) {
+                Ok(_) => break,
+                Err(err) if self.snapshots.is_empty() => {
+                    return Err(err).context("无法保存撤销快照");
+                }
+                Err(_) => self.drop_oldest()?,
+            }
+        }
+
+        self.snapshots.push(Snapshot {
+            label: label.into(),
+            pck: pck.to_path_buf(),
+            name,
+            after: None,
+        });
+        Ok(())
+    }
+
+    /// 操作成功后调用，记下 PCK 修改后的状态
+    pub fn finish(&mut self) {
+        if let Some(snapshot) = self.snapshots.last_mut() {
+            snapshot.after = file_state(&snapshot.pck);
+        }
+    }
+
+    /// 撤销最近一次操作，返回它的说明与被恢复的 PCK；栈为空时返回 None
+    pub fn undo(&mut self) -> Result<Option<(String, PathBuf)>> {
+        let Some(snapshot) = self.snapshots.last() else {
+            return Ok(None);
+        };
+        if let Some(after) = snapshot.after
+            && file_state(&snapshot.pck) != Some(after)
+        {
+            bail!(
+                "{} 在「{}」之后被其他程序改动过，为避免覆盖这些改动已停止撤销",
+                snapshot.pck.display(),
+                snapshot.label
+            );
+        }
+
+        let area = self
+            .area
+            .as_mut()
+            .ok_or_else(|| anyhow!("撤销快照已丢失"))?;
+        let source = area.path().join(&snapshot.name);
+        let mut partial = snapshot.pck.clone().into_os_string();
+        partial.push(".undo.partial");
+        let partial = PathBuf::from(partial);
+        fs::copy(&source, &partial)
+            .with_context(|| format!("无法恢复撤销快照到 {}", partial.display()))?;
+        fs::rename(&partial, &snapshot.pck).with_context(|| {
+            let _ = fs::remove_file(&partial);
+            format!("无法覆盖 {}，请确认游戏已关闭", snapshot.pck.display())
+        })?;
+
+        let snapshot = self.snapshots.pop().expect("栈非空");
+        let _ = area.remove(&snapshot.name);
+        Ok(Some((snapshot.label, snapshot.pck)))
+    }
+
+    fn drop_oldest(&mut self) -> Result<()> {
+        let snapshot = self.snapshots.remove(0);
+        if let Some(area) = &mut self.area {
+            area.remove(&snapshot.name)?;
+        }
+        Ok(())
+    }
+}
+
+fn file_state(path: &Path) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoes_in_reverse_order_within_the_cap() {
+        let dir = std::env::temp_dir().join(format!("bpb_undo_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pck = dir.join("Game.pck");
+        let area = StagingArea::create_in(&dir.join("staging"), "undo", 9).unwrap();
+        let mut stack = UndoStack::with_area(area);
+
+        fs::write(&pck, b"v1").unwrap();
+        stack.record("mod A", &pck).unwrap();
+        fs::write(&pck, b"v2 mod A").unwrap();
+        stack.finish();
+        // 两份快照放不下，最早的被丢弃
+        stack.record("mod B", &pck).unwrap();
+        fs::write(&pck, b"v3 mod B").unwrap();
+        stack.finish();
+
+        let (label, restored) = stack.undo().unwrap().unwrap();
+        assert_eq!((label.as_str(), restored), ("mod B", pck.clone()));
+        assert_eq!(fs::read(&pck).unwrap(), b"v2 mod A");
+        assert!(stack.undo().unwrap().is_none());
+
+        // 操作之后文件又被改动时拒绝撤销
+        stack.record("mod C", &pck).unwrap();
+        fs::write(&pck, b"v4").unwrap();
+        stack.finish();
+        fs::write(&pck, b"changed elsewhere").unwrap();
+        assert!(stack.undo().is_err());
+        assert_eq!(stack.peek(), Some("mod C"));
+
+        drop(stack);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,675 @@
+//! 最小的 ZIP 读写（写入只用 stored，读取支持 stored 与 deflate），以及 PCK 与 ZIP 之间的导出、导入
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::diff;
+use crate::merge::MergeSummary;
+use crate::pck::{self, IoOptions};
+use crate::report::EntryDigest;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const END_OF_CENTRAL_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+/// 压缩方式：不压缩、deflate
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+/// 通用标志位 0：条目已加密
+const FLAG_ENCRYPTED: u16 = 1;
+/// 2.0：不使用 ZIP64 的最低版本
+const VERSION: u16 = 20;
+/// 4.5：使用 ZIP64 扩展
+const VERSION_ZIP64: u16 = 45;
+/// 通用标志位 3：CRC 与大小写在数据之后的 data descriptor 中
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+/// 通用标志位 11：文件名为 UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+/// 流式写入条目时的缓冲区大小
+const COPY_BUF_SIZE: usize = 256 * 1024;
+/// 1980-01-01 00:00:00（DOS 日期时间的最小值）
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0u32, data)
+}
+
+/// 在未取反的中间值 `crc` 上继续计算，用于分块计算
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+struct CentralEntry {
+    name: String,
+    flags: u16,
+    crc: u32,
+    size: u32,
+    local_offset: u64,
+}
+
+/// 只写不压缩（stored）条目的最小 ZIP 写入器；单个条目不超过 4 GiB，
+/// 整个 ZIP 超过 4 GiB 或条目数超过 65535 时改用 ZIP64 的中央目录
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let size = u32::try_from(data.len()).map_err(|_| anyhow!("文件过大: {}", name))?;
+        let crc = crc32(data);
+        let header = local_header(name, FLAG_UTF8, crc, size);
+
+        self.out
+            .write_all(&header)
+            .and_then(|_| self.out.write_all(data))
+            .with_context(|| format!("无法写入 ZIP 条目: {}", name))?;
+        self.push(
+            name,
+            FLAG_UTF8,
+            crc,
+            size,
+            (header.len() + data.len()) as u64,
+        );
+        Ok(())
+    }
+
+    /// 从 `data` 读取 `size` 字节写成一个条目，不整个载入内存；CRC 写在数据之后的 data descriptor 中
+    pub fn add_reader(&mut self, name: &str, mut data: impl Read, size: u64) -> Result<()> {
+        let size = u32::try_from(size).map_err(|_| anyhow!("文件过大: {}", name))?;
+        let flags = FLAG_UTF8 | FLAG_DATA_DESCRIPTOR;
+        let header = local_header(name, flags, 0, 0);
+        self.out
+            .write_all(&header)
+            .with_context(|| format!("无法写入 ZIP 条目: {}", name))?;
+
+        let mut crc = !0u32;
+        let mut copied = 0u64;
+        let mut buf = vec![0u8; COPY_BUF_SIZE];
+        loop {
+            let n = data
+                .read(&mut buf)
+                .with_context(|| format!("无法读取: {}", name))?;
+            if n == 0 {
+                break;
+            }
+            crc = crc32_update(crc, &buf[..n]);
+            self.out
+                .write_all(&buf[..n])
+                .with_context(|| format!("无法写入 ZIP 条目: {}", name))?;
+            copied += n as u64;
+        }
+        if copied != size as u64 {
+            bail!(
+                "{} 的数据不完整：应有 {} 字节，只读到 {} 字节",
+                name,
+                size,
+                copied
+            );
+        }
+        let crc = !crc;
+
+        let mut descriptor = Vec::with_capacity(16);
+        descriptor.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        descriptor.extend_from_slice(&crc.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        self.out
+            .write_all(&descriptor)
+            .with_context(|| format!("无法写入 ZIP 条目: {}", name))?;
+        self.push(
+            name,
+            flags,
+            crc,
+            size,
+            (header.len() + descriptor.len()) as u64 + copied,
+        );
+        Ok(())
+    }
+
+    fn push(&mut self, name: &str, flags: u16, crc: u32, size: u32, written: u64) {
+        self.entries.push(CentralEntry {
+            name: name.to_string(),
+            flags,
+            crc,
+            size,
+            local_offset: self.offset,
+        });
+        self.offset += written;
+    }
+
+    /// 写出中央目录并返回底层写入器
+    pub fn finish(mut self) -> Result<W> {
+        let central_offset = self.offset;
+        let mut central = Vec::new();
+
+        for entry in &self.entries {
+            // 偏移超出 32 位时记在 ZIP64 扩展字段中
+            let zip64 = entry.local_offset > u32::MAX as u64;
+            let version = if zip64 { VERSION_ZIP64 } else { VERSION };
+            central.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend_from_slice(&version.to_le_bytes()); // 创建版本
+            central.extend_from_slice(&version.to_le_bytes()); // 解压所需版本
+            central.extend_from_slice(&entry.flags.to_le_bytes());
+            central.extend_from_slice(&0u16.to_le_bytes());
+            central.extend_from_slice(&0u16.to_le_bytes());
+            central.extend_from_slice(&DOS_DATE.to_le_bytes());
+            central.extend_from_slice(&entry.crc.to_le_bytes());
+            central.extend_from_slice(&entry.size.to_le_bytes());
+            central.extend_from_slice(&entry.size.to_le_bytes());
+            central.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&(if zip64 { 12u16 } else { 0 }).to_le_bytes()); // extra
+            central.extend_from_slice(&[0; 6]); // 注释长度、磁盘号、内部属性
+            central.extend_from_slice(&0u32.to_le_bytes()); // 外部属性
+            let local_offset = if zip64 {
+                u32::MAX
+            } else {
+                entry.local_offset as u32
+            };
+            central.extend_from_slice(&local_offset.to_le_bytes());
+            central.extend_from_slice(entry.name.as_bytes());
+            if zip64 {
+                central.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+                central.extend_from_slice(&8u16.to_le_bytes());
+                central.extend_from_slice(&entry.local_offset.to_le_bytes());
+            }
+        }
+
+        let count = self.entries.len() as u64;
+        let central_size = central.len() as u64;
+        let mut end = Vec::with_capacity(98);
+        let zip64 = count > u16::MAX as u64
+            || central_offset > u32::MAX as u64
+            || central_size > u32::MAX as u64;
+        if zip64 {
+            let record_offset = central_offset + central_size;
+            end.extend_from_slice(&ZIP64_END_OF_CENTRAL_SIGNATURE.to_le_bytes());
+            end.extend_from_slice(&44u64.to_le_bytes()); // 记录剩余长度
+            end.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            end.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            end.extend_from_slice(&[0; 8]); // 磁盘号
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&central_size.to_le_bytes());
+            end.extend_from_slice(&central_offset.to_le_bytes());
+
+            end.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&record_offset.to_le_bytes());
+            end.extend_from_slice(&1u32.to_le_bytes()); // 磁盘总数
+        }
+        // ZIP64 时以全 1 表示实际值在 ZIP64 记录中
+        let count16 = u16::try_from(count).unwrap_or(u16::MAX);
+        let central_size32 = u32::try_from(central_size).unwrap_or(u32::MAX);
+        let central_offset32 = u32::try_from(central_offset).unwrap_or(u32::MAX);
+        end.extend_from_slice(&END_OF_CENTRAL_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0; 4]); // 磁盘号
+        end.extend_from_slice(&count16.to_le_bytes());
+        end.extend_from_slice(&count16.to_le_bytes());
+        end.extend_from_slice(&central_size32.to_le_bytes());
+        end.extend_from_slice(&central_offset32.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // 注释长度
+
+        self.out
+            .write_all(&central)
+            .and_then(|_| self.out.write_all(&end))
+            .and_then(|_| self.out.flush())
+            .context("无法写入 ZIP 中央目录")?;
+        Ok(self.out)
+    }
+}
+
+fn local_header(name: &str, flags: u16, crc: u32, size: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(30 + name.len());
+    header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&flags.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes()); // stored
+    header.extend_from_slice(&0u16.to_le_bytes()); // 时间
+    header.extend_from_slice(&DOS_DATE.to_le_bytes());
+    header.extend_from_slice(&crc.to_le_bytes());
+    header.extend_from_slice(&size.to_le_bytes()); // 压缩后大小
+    header.extend_from_slice(&size.to_le_bytes());
+    header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes()); // extra
+    header.extend_from_slice(name.as_bytes());
+    header
+}
+
+/// 把 PCK 的全部 entry 按 `res://` 之后的路径写入 ZIP，返回写入的文件数。
+/// 按数据在 PCK 中的顺序逐个流式复制，加密的 entry 解密后写入
+pub fn export_pck(pck_path: &Path, out: &Path, limits: pck::TableLimits) -> Result<usize> {
+    let file =
+        File::open(pck_path).with_context(|| format!("无法打开文件: {}", pck_path.display()))?;
+    let mut reader = BufReader::new(file);
+    let (_, mut entries) = pck::read_entries(&mut reader, None, limits)
+        .with_context(|| format!("无法读取文件表: {}", pck_path.display()))?;
+    entries.sort_by_key(|(_, entry)| entry.offset);
+
+    let mut names = Vec::with_capacity(entries.len());
+    for (path, _) in &entries {
+        let name = path.strip_prefix("res://").unwrap_or(path);
+        if name.is_empty()
+            || Path::new(name)
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("无法导出到目录外的路径: {}", path);
+        }
+        names.push(name);
+    }
+
+    let written = File::create(out)
+        .with_context(|| format!("无法创建 ZIP: {}", out.display()))
+        .and_then(|zip_file| {
+            let mut zip = ZipWriter::new(BufWriter::new(zip_file));
+            for ((path, entry), name) in entries.iter().zip(&names) {
+                let data = pck::entry_reader(&mut reader, entry, path, None)?;
+                zip.add_reader(name, data, entry.size)?;
+            }
+            zip.finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())
+                .context("无法写入 ZIP")?
+                .sync_all()
+                .context("无法写入 ZIP")
+        });
+    if let Err(err) = written {
+        // 不留下写了一半的 ZIP
+        let _ = fs::remove_file(out);
+        return Err(err);
+    }
+    Ok(entries.len())
+}
+
+/// ZIP 中央目录记录的一个文件
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: u64,
+    pub size: u64,
+    local_offset: u64,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+/// 按中央目录读取 ZIP 的读取器
+pub struct ZipReader<R: Read + Seek> {
+    reader: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("无法打开 ZIP: {}", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("无法读取 ZIP: {}", path.display()))
+    }
+}
+
+impl<R: Read + Seek> ZipReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        // 目录结束记录在末尾，后面最多跟 65535 字节的注释
+        let tail_len = len.min(22 + u16::MAX as u64);
+        let mut tail = vec![0u8; tail_len as usize];
+        reader.seek(SeekFrom::Start(len - tail_len))?;
+        reader.read_exact(&mut tail)?;
+        let end = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|&i| tail[i..i + 4] == END_OF_CENTRAL_SIGNATURE.to_le_bytes())
+            .ok_or_else(|| anyhow!("不是 ZIP 文件（找不到中央目录）"))?;
+        let record = &tail[end..];
+        let mut count = u16_at(record, 10) as u64;
+        let mut central_size = u32_at(record, 12) as u64;
+        let mut central_offset = u32_at(record, 16) as u64;
+
+        let locator = end
+            .checked_sub(20)
+            .map(|at| &tail[at..end])
+            .filter(|locator| locator[..4] == ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+        if let Some(locator) = locator {
+            let mut record = [0u8; 56];
+            reader.seek(SeekFrom::Start(u64_at(locator, 8)))?;
+            reader.read_exact(&mut record)?;
+            if record[..4] != ZIP64_END_OF_CENTRAL_SIGNATURE.to_le_bytes() {
+                bail!("ZIP64 目录结束记录损坏");
+            }
+            count = u64_at(&record, 32);
+            central_size = u64_at(&record, 40);
+            central_offset = u64_at(&record, 48);
+        }
+        if central_offset
+            .checked_add(central_size)
+            .is_none_or(|end| end > len)
+        {
+            bail!("中央目录超出文件末尾");
+        }
+
+        let mut central = vec![0u8; central_size as usize];
+        reader.seek(SeekFrom::Start(central_offset))?;
+        reader.read_exact(&mut central)?;
+        let mut entries = Vec::new();
+        let mut rest = central.as_slice();
+        for _ in 0..count {
+            let (entry, tail) = parse_central_entry(rest)?;
+            entries.push(entry);
+            rest = tail;
+        }
+        Ok(Self { reader, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// 读取并解压一个文件，校验大小与 CRC
+    pub fn read(&mut self, entry: &ZipEntry) -> Result<Vec<u8>> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            bail!("不支持加密的 ZIP 条目: {}", entry.name);
+        }
+        let mut local = [0u8; 30];
+        self.reader.seek(SeekFrom::Start(entry.local_offset))?;
+        self.reader.read_exact(&mut local)?;
+        if local[..4] != LOCAL_HEADER_SIGNATURE.to_le_bytes() {
+            bail!("ZIP 条目的本地文件头损坏: {}", entry.name);
+        }
+        let skip = u16_at(&local, 26) as i64 + u16_at(&local, 28) as i64;
+        self.reader.seek(SeekFrom::Current(skip))?;
+        let mut stored = Vec::new();
+        (&mut self.reader)
+            .take(entry.compressed_size)
+            .read_to_end(&mut stored)?;
+        if stored.len() as u64 != entry.compressed_size {
+            bail!("ZIP 条目的数据不完整: {}", entry.name);
+        }
+
+        let size = usize::try_from(entry.size).map_err(|_| anyhow!("文件过大: {}", entry.name))?;
+        let data = match entry.method {
+            METHOD_STORED => stored,
+            METHOD_DEFLATE => miniz_oxide::inflate::decompress_to_vec_with_limit(&stored, size)
+                .map_err(|err| anyhow!("无法解压 {}: {}", entry.name, err))?,
+            method => bail!("不支持的压缩方式 {}: {}", method, entry.name),
+        };
+        if data.len() != size || crc32(&data) != entry.crc {
+            bail!("ZIP 条目校验失败: {}", entry.name);
+        }
+        Ok(data)
+    }
+}
+
+fn parse_central_entry(record: &[u8]) -> Result<(ZipEntry, &[u8])> {
+    if record.len() < 46 || record[..4] != CENTRAL_HEADER_SIGNATURE.to_le_bytes() {
+        bail!("中央目录损坏");
+    }
+    let name_len = u16_at(record, 28) as usize;
+    let extra_len = u16_at(record, 30) as usize;
+    let comment_len = u16_at(record, 32) as usize;
+    let total = 46 + name_len + extra_len + comment_len;
+    if record.len() < total {
+        bail!("中央目录损坏");
+    }
+    let name = String::from_utf8_lossy(&record[46..46 + name_len]).replace('\\', "/");
+    let mut entry = ZipEntry {
+        name,
+        method: u16_at(record, 10),
+        flags: u16_at(record, 8),
+        crc: u32_at(record, 16),
+        compressed_size: u32_at(record, 20) as u64,
+        size: u32_at(record, 24) as u64,
+        local_offset: u32_at(record, 42) as u64,
+    };
+
+    // ZIP64 扩展字段按顺序只包含值为全 1 的那几项
+    let mut extra = &record[46 + name_len..46 + name_len + extra_len];
+    while extra.len() >= 4 {
+        let (id, len) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+        let data = extra.get(4..4 + len).unwrap_or_default();
+        if id == ZIP64_EXTRA_ID {
+            let mut values = data.chunks_exact(8).map(|chunk| u64_at(chunk, 0));
+            for field in [
+                &mut entry.size,
+                &mut entry.compressed_size,
+                &mut entry.local_offset,
+            ] {
+                if *field == u32::MAX as u64 {
+                    *field = values.next().ok_or_else(|| anyhow!("ZIP64 扩展字段损坏"))?;
+                }
+            }
+        }
+        extra = extra.get(4 + len..).unwrap_or_default();
+    }
+    Ok((entry, &record[total..]))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// 把 ZIP 中的文件按 `res://<ZIP 内路径>` 一次写入 `target`：已有的路径替换，没有的新增，
+/// 与 PCK 中数据完全相同的文件跳过。目录条目忽略
+pub fn import_zip(
+    target: &Path,
+    zip_path: &Path,
+    io: &IoOptions,
+    limits: pck::TableLimits,
+) -> Result<MergeSummary> {
+    let mut zip = ZipReader::open(zip_path)?;
+    let entries: Vec<ZipEntry> = zip
+        .entries()
+        .iter()
+        .filter(|e| !e.is_dir())
+        .cloned()
+        .collect();
+    if entries.is_empty() {
+        bail!("ZIP 中没有任何文件: {}", zip_path.display());
+    }
+
+    let mut seen = HashSet::new();
+    let mut files = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        let name = entry.name.trim_start_matches("res://");
+        if name.is_empty()
+            || Path::new(name)
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            bail!("ZIP 中的路径不能映射到 res://: {}", entry.name);
+        }
+        let res_path = format!("res://{}", name);
+        if !seen.insert(res_path.clone()) {
+            bail!("ZIP 中有重复的路径: {}", res_path);
+        }
+        files.push((res_path, zip.read(entry)?));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let existing = diff::read_digests(target, limits)?;
+    let mut summary = MergeSummary::default();
+    files.retain(|(path, data)| {
+        let digest = EntryDigest {
+            size: data.len() as u64,
+            md5: md5::compute(data).0,
+        };
+        match existing.get(path) {
+            None => summary.added.push(path.clone()),
+            Some(current) if *current != digest => summary.replaced.push(path.clone()),
+            Some(_) => {
+                summary.unchanged += 1;
+                return false;
+            }
+        }
+        true
+    });
+    if files.is_empty() {
+        return Ok(summary);
+    }
+
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(target)
+        .with_context(|| format!("无法打开: {}", target.display()))?;
+    let mut file = pck::Keyed::new(file, None).with_limits(limits);
+    let (header, index) = pck::read_header_and_index(&mut file)?;
+    let files = files
+        .iter()
+        .map(|(path, data)| (path.as_str(), data.as_slice()))
+        .collect();
+    pck::replace_files_in_pck_with(&mut file, &header, &index, files, io)
+        .with_context(|| format!("写入文件失败: {}", target.display()))?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_stored_entries() {
+        assert_eq!(crc32(b"hello"), 0x3610_a686);
+
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("a.txt", b"hello").unwrap();
+        zip.add_file("目录/b.txt", b"").unwrap();
+        let bytes = zip.finish().unwrap();
+
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+        assert_eq!(&bytes[30..35], b"a.txt");
+        assert_eq!(&bytes[35..40], b"hello");
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+    }
+
+    #[test]
+    fn streams_entries_and_switches_to_zip64() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_reader("a.txt", &b"hello"[..], 5).unwrap();
+        assert!(zip.add_reader("short.txt", &b"abc"[..], 4).is_err());
+        let bytes = zip.finish().unwrap();
+        // data descriptor 中的 CRC 与一次性计算的相同
+        assert_eq!(&bytes[35..40], b"hello");
+        assert_eq!(&bytes[40..44], &DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        assert_eq!(&bytes[44..48], &crc32(b"hello").to_le_bytes());
+
+        // 模拟前面已写入 5 GiB 数据
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.offset = 5 << 30;
+        zip.add_file("big.txt", b"x").unwrap();
+        let bytes = zip.finish().unwrap();
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(&end[16..20], &u32::MAX.to_le_bytes());
+        let locator = &bytes[bytes.len() - 42..bytes.len() - 22];
+        assert_eq!(&locator[..4], &ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+        let central = bytes.len() - 42 - 56 - (46 + 7 + 12);
+        assert_eq!(&bytes[central + 42..central + 46], &u32::MAX.to_le_bytes());
+        assert_eq!(
+            &bytes[central + 57..central + 65],
+            &(5u64 << 30).to_le_bytes()
+        );
+    }
+
+    /// 只含一个 deflate 条目的 ZIP，模拟常见压缩工具的输出
+    fn deflated_zip(name: &str, data: &[u8]) -> Vec<u8> {
+        let deflated = miniz_oxide::deflate::compress_to_vec(data, 6);
+        let mut bytes = local_header(name, FLAG_UTF8, crc32(data), deflated.len() as u32);
+        bytes[8..10].copy_from_slice(&METHOD_DEFLATE.to_le_bytes());
+        bytes[22..26].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&deflated);
+        let central_offset = bytes.len();
+
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file(name, data).unwrap();
+        let stored = zip.finish().unwrap();
+        let mut central = stored[30 + name.len() + data.len()..].to_vec();
+        central[10..12].copy_from_slice(&METHOD_DEFLATE.to_le_bytes());
+        central[20..24].copy_from_slice(&(deflated.len() as u32).to_le_bytes());
+        let end = central.len() - 22;
+        central[end + 16..end + 20].copy_from_slice(&(central_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&central);
+        bytes
+    }
+
+    #[test]
+    fn reads_back_stored_and_deflated_entries() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("Core/", b"").unwrap();
+        zip.add_reader("Core/Game.gde", &b"extends Node"[..], 12)
+            .unwrap();
+        let bytes = zip.finish().unwrap();
+        let mut reader = ZipReader::new(std::io::Cursor::new(bytes)).unwrap();
+        let entries = reader.entries().to_vec();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Core/", "Core/Game.gde"]);
+        assert!(entries[0].is_dir());
+        assert_eq!(reader.read(&entries[1]).unwrap(), b"extends Node");
+
+        let data = b"hello ".repeat(100);
+        let mut bytes = deflated_zip("a.txt", &data);
+        let mut reader = ZipReader::new(std::io::Cursor::new(bytes.clone())).unwrap();
+        let entry = reader.entries()[0].clone();
+        assert!(entry.compressed_size < entry.size);
+        assert_eq!(reader.read(&entry).unwrap(), data);
+
+        // 数据损坏时校验失败
+        bytes[30 + 5 + 2] ^= 0x55;
+        let mut reader = ZipReader::new(std::io::Cursor::new(bytes)).unwrap();
+        assert!(reader.read(&entry).is_err());
+        assert!(ZipReader::new(std::io::Cursor::new(b"not a zip".to_vec())).is_err());
+    }
+}
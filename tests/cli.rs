@@ -0,0 +1,1491 @@
+//! 通过 CLI 驱动整个流程的集成测试：每个测试在临时目录里生成一个极小的 PCK，
+//! 再调用编译出的 bpb_enhance 可执行文件
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const ORIGINAL_GAME: &[u8] = b"extends Node\n# original\n";
+const MENU_SCENE: &[u8] = b"[gd_scene format=2]\n\n[node name=\"Menu\" type=\"Control\"]\n";
+
+/// 测试结束时自动删除的临时目录
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("bpb_enhance_it_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// 在内存中构造 v1 PCK：header、entry 表、数据区依次排列
+fn build_pck(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let padded = |path: &str| {
+        let mut bytes = path.as_bytes().to_vec();
+        while !bytes.len().is_multiple_of(4) {
+            bytes.push(0);
+        }
+        bytes
+    };
+
+    let mut out = b"GDPC".to_vec();
+    for value in [1u32, 3, 5, 1] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&[0; 64]);
+    out.extend_from_slice(&(files.len() as u32).to_le_bytes());
+
+    let table_size: usize = files.iter().map(|(p, _)| 4 + padded(p).len() + 32).sum();
+    let mut data_offset = (out.len() + table_size) as u64;
+    for (path, data) in files {
+        let path_bytes = padded(path);
+        out.extend_from_slice(&(path_bytes.len() as u32).to_le_bytes());
+        out.extend_from_slice(&path_bytes);
+        out.extend_from_slice(&data_offset.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&md5::compute(data).0);
+        data_offset += data.len() as u64;
+    }
+    for (_, data) in files {
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+fn mini_game(dir: &Path) -> PathBuf {
+    let pck = dir.join("BackpackBattles.pck");
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://Core/Game.gde", ORIGINAL_GAME),
+            ("res://UI/Menu.tscn", MENU_SCENE),
+            ("res://Other/obsolete.txt", b"old"),
+        ]),
+    )
+    .unwrap();
+    pck
+}
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bpb_enhance"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn run_ok(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(
+        output.status.success(),
+        "{:?} failed:\n{}{}",
+        args,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// `list --verbose` 的结果：路径 -> (大小, MD5)
+fn list_entries(pck: &Path) -> Vec<(String, u64, String)> {
+    run_ok(&["list", "-p", pck.to_str().unwrap(), "--verbose"])
+        .lines()
+        .filter(|line| line.starts_with("res://"))
+        .map(|line| {
+            let cols: Vec<&str> = line.split('\t').collect();
+            (
+                cols[0].to_string(),
+                cols[1].parse().unwrap(),
+                cols[2].to_string(),
+            )
+        })
+        .collect()
+}
+
+/// 把 mini_mod 复制到 `dir/mod`，便于测试修改 manifest
+fn copy_mini_mod(dir: &Path) -> PathBuf {
+    let mod_dir = dir.join("mod");
+    fs::create_dir_all(mod_dir.join("Core")).unwrap();
+    for file in ["replace.toml", "Core/Game.gde", "Core/New.gd"] {
+        fs::copy(fixture("mini_mod").join(file), mod_dir.join(file)).unwrap();
+    }
+    mod_dir
+}
+
+/// 在 mini_mod 原有的 manifest 之后追加内容
+fn extend_manifest(mod_dir: &Path, extra: &str) {
+    let manifest = fs::read_to_string(fixture("mini_mod/replace.toml")).unwrap();
+    fs::write(
+        mod_dir.join("replace.toml"),
+        format!("{}\n{}\n", manifest, extra),
+    )
+    .unwrap();
+}
+
+fn md5_hex(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}
+
+#[test]
+fn apply_replaces_adds_and_deletes_entries() {
+    let dir = TestDir::new("apply");
+    let pck = mini_game(dir.path());
+    let report = dir.path().join("report.md");
+    let mod_dir = fixture("mini_mod");
+
+    run_ok(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mod_dir.to_str().unwrap(),
+        "--report",
+        report.to_str().unwrap(),
+    ]);
+
+    let patched_game = fs::read(mod_dir.join("Core/Game.gde")).unwrap();
+    let entries = list_entries(&pck);
+    let paths: Vec<&str> = entries.iter().map(|(p, _, _)| p.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "res://Core/Game.gde",
+            "res://Core/New.gd",
+            "res://UI/Menu.tscn",
+            "res://plugin_version.txt"
+        ]
+    );
+    assert_eq!(entries[0].1, patched_game.len() as u64);
+    assert_eq!(entries[0].2, md5_hex(&patched_game));
+    assert_eq!(entries[2].2, md5_hex(MENU_SCENE));
+
+    let report = fs::read_to_string(report).unwrap();
+    assert!(report.contains("res://Other/obsolete.txt"));
+
+    // 已注入过的 PCK 通过 plugin_version.txt 校验版本，可以再次应用
+    run_ok(&[
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mod_dir.to_str().unwrap(),
+    ]);
+    assert_eq!(list_entries(&pck).len(), 4);
+    assert!(run_ok(&["repair", "-p", pck.to_str().unwrap()]).contains("No duplicate entries"));
+}
+
+#[test]
+fn optional_features_follow_enable_and_disable() {
+    let dir = TestDir::new("options");
+    let pck = mini_game(dir.path());
+    let mod_dir = copy_mini_mod(dir.path());
+    extend_manifest(
+        &mod_dir,
+        "[options.menu]\ntitle = \"菜单\"\ndefault = false\ninclude = [\"menu.toml\"]",
+    );
+    fs::write(
+        mod_dir.join("menu.toml"),
+        "[replace]\n\"res://UI/Menu.tscn\" = \"Menu.tscn\"\n",
+    )
+    .unwrap();
+    fs::write(mod_dir.join("Menu.tscn"), b"[gd_scene format=2]").unwrap();
+    let apply = |extra: &[&str]| {
+        let mut args = vec!["apply", "-p", pck.to_str().unwrap(), "-a", mod_dir.to_str().unwrap()];
+        args.extend_from_slice(extra);
+        run(&args)
+    };
+    let menu_md5 = || {
+        list_entries(&pck)
+            .into_iter()
+            .find(|(path, _, _)| path == "res://UI/Menu.tscn")
+            .unwrap()
+            .2
+    };
+
+    // 默认关闭的功能不应用
+    assert!(apply(&[]).status.success());
+    assert_eq!(menu_md5(), md5_hex(MENU_SCENE));
+
+    let typo = apply(&["--enable", "menus"]);
+    assert!(!typo.status.success());
+    assert!(String::from_utf8_lossy(&typo.stderr).contains("menus"));
+    assert!(!apply(&["--enable", "menu", "--disable", "menu"]).status.success());
+
+    let output = apply(&["--enable", "menu"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("已启用可选功能: menu"));
+    assert_eq!(menu_md5(), md5_hex(b"[gd_scene format=2]"));
+}
+
+#[test]
+fn progress_json_reports_apply_events() {
+    let dir = TestDir::new("progress");
+    let pck = mini_game(dir.path());
+    let output = run(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        fixture("mini_mod").to_str().unwrap(),
+        "--progress",
+        "json",
+    ]);
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let events: Vec<&str> = stderr.lines().filter(|l| l.starts_with('{')).collect();
+    assert_eq!(
+        events.first(),
+        Some(&r#"{"event":"stage","stage":"read_index","message":"正在读取 PCK 文件头与索引..."}"#)
+    );
+    assert!(
+        events
+            .iter()
+            .any(|e| e.starts_with(r#"{"event":"plan","replaced":1,"added":2,"#))
+    );
+    assert!(events.last().unwrap().starts_with(r#"{"event":"finished""#));
+    // 事件不混入标准输出
+    assert!(!String::from_utf8_lossy(&output.stdout).contains(r#""event""#));
+}
+
+#[test]
+fn apply_refuses_unknown_game_version() {
+    let dir = TestDir::new("version");
+    let pck = dir.path().join("BackpackBattles.pck");
+    let original = build_pck(&[("res://Core/Game.gde", b"some other build")]);
+    fs::write(&pck, &original).unwrap();
+
+    let output = run(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        fixture("mini_mod").to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert_eq!(fs::read(&pck).unwrap(), original);
+}
+
+#[test]
+fn atomic_apply_replaces_the_pck_in_one_rename() {
+    let dir = TestDir::new("atomic");
+    let pck = mini_game(dir.path());
+    let mod_dir = fixture("mini_mod");
+    let apply = |pck: &Path| {
+        run(&[
+            "apply",
+            "--atomic",
+            "--no-backup",
+            "--yes",
+            "--pck",
+            pck.to_str().unwrap(),
+            "--assets",
+            mod_dir.to_str().unwrap(),
+        ])
+    };
+
+    assert!(apply(&pck).status.success());
+    assert_eq!(list_entries(&pck).len(), 4);
+    // 只剩 PCK 与来源记录，临时副本已改名为 PCK
+    let mut names: Vec<String> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["BackpackBattles.pck", "BackpackBattles.pck.provenance.toml"]
+    );
+
+    // 失败时原 PCK 不变，临时副本被删除
+    let other = dir.path().join("Other.pck");
+    let original = build_pck(&[("res://Core/Game.gde", b"some other build")]);
+    fs::write(&other, &original).unwrap();
+    assert!(!apply(&other).status.success());
+    assert_eq!(fs::read(&other).unwrap(), original);
+    assert!(!dir.path().join("Other.pck.atomic.partial").exists());
+}
+
+#[test]
+fn staged_apply_changes_nothing_until_commit() {
+    let dir = TestDir::new("stage");
+    let pck = mini_game(dir.path());
+    let pck_str = pck.to_str().unwrap();
+    let original = fs::read(&pck).unwrap();
+    let original_entries = list_entries(&pck);
+    let mod_dir = fixture("mini_mod");
+    let args = [
+        "apply",
+        "--stage",
+        "--no-backup",
+        "--yes",
+        "--pck",
+        pck_str,
+        "--assets",
+        mod_dir.to_str().unwrap(),
+    ];
+
+    assert!(run_ok(&args).contains("Staged changes"));
+    assert_eq!(list_entries(&pck), original_entries);
+    assert_eq!(fs::read(&pck).unwrap()[..original.len()], original[..]);
+    // 未提交时拒绝再次修改
+    assert!(!run(&args).status.success());
+
+    assert!(run_ok(&["abort", "-p", pck_str]).contains("Discarded"));
+    assert_eq!(fs::read(&pck).unwrap(), original);
+    assert!(!run(&["commit", "-p", pck_str]).status.success());
+
+    run_ok(&args);
+    assert!(run_ok(&["commit", "-p", pck_str]).contains("Committed"));
+    assert_eq!(list_entries(&pck).len(), 4);
+    assert!(!dir.path().join("BackpackBattles.pck.pending").exists());
+    assert!(dir.path().join("BackpackBattles.pck.provenance.toml").exists());
+}
+
+#[test]
+fn pack_output_lists_back() {
+    let dir = TestDir::new("pack");
+    let pck = dir.path().join("packed.pck");
+
+    run_ok(&[
+        "pack",
+        fixture("mini_mod").to_str().unwrap(),
+        "-o",
+        pck.to_str().unwrap(),
+    ]);
+
+    let paths: Vec<String> = list_entries(&pck).into_iter().map(|(p, _, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "res://Core/Game.gde",
+            "res://Core/New.gd",
+            "res://replace.toml"
+        ]
+    );
+}
+
+#[test]
+fn export_zip_stores_every_entry_under_its_res_path() {
+    let dir = TestDir::new("export_zip");
+    let pck = mini_game(dir.path());
+    let zip = dir.path().join("game.zip");
+
+    let stdout = run_ok(&[
+        "export-zip",
+        "-p",
+        pck.to_str().unwrap(),
+        "-o",
+        zip.to_str().unwrap(),
+    ]);
+    assert!(stdout.contains("Exported 3 files"), "{}", stdout);
+
+    let bytes = fs::read(&zip).unwrap();
+    let find = |needle: &[u8]| bytes.windows(needle.len()).position(|w| w == needle);
+    // 本地文件头之后紧跟未压缩的数据
+    let name = find(b"Core/Game.gde").unwrap();
+    assert_eq!(&bytes[name - 30..name - 26], b"PK\x03\x04");
+    let data = name + "Core/Game.gde".len();
+    assert_eq!(&bytes[data..data + ORIGINAL_GAME.len()], ORIGINAL_GAME);
+    assert!(find(b"UI/Menu.tscn").is_some());
+    assert!(find(b"res://").is_none());
+    let end = &bytes[bytes.len() - 22..];
+    assert_eq!(&end[..4], b"PK\x05\x06");
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 3);
+}
+
+#[test]
+fn import_zip_writes_zip_files_into_the_pck() {
+    let dir = TestDir::new("import_zip");
+    let source = dir.path().join("source.pck");
+    fs::write(
+        &source,
+        build_pck(&[
+            ("res://Core/Game.gde", b"patched game"),
+            ("res://UI/Menu.tscn", MENU_SCENE),
+            ("res://New/extra.txt", b"extra"),
+        ]),
+    )
+    .unwrap();
+    let zip = dir.path().join("mod.zip");
+    run_ok(&["export-zip", "-p", source.to_str().unwrap(), "-o", zip.to_str().unwrap()]);
+
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let out = dir.path().join("out.pck");
+    let stdout = run_ok(&[
+        "import-zip",
+        "-p",
+        pck.to_str().unwrap(),
+        zip.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+    ]);
+    assert!(stdout.contains("+ res://New/extra.txt\n~ res://Core/Game.gde\n"), "{}", stdout);
+    assert!(stdout.contains("1 added, 1 replaced, 1 already identical"), "{}", stdout);
+    assert_eq!(fs::read(&pck).unwrap(), original);
+
+    let extracted = dir.path().join("extracted");
+    run_ok(&["extract", "-p", out.to_str().unwrap(), "-o", extracted.to_str().unwrap()]);
+    assert_eq!(fs::read(extracted.join("Core/Game.gde")).unwrap(), b"patched game");
+    assert_eq!(fs::read(extracted.join("New/extra.txt")).unwrap(), b"extra");
+    assert_eq!(fs::read(extracted.join("Other/obsolete.txt")).unwrap(), b"old");
+
+    let bad = run(&["import-zip", "-p", pck.to_str().unwrap(), pck.to_str().unwrap()]);
+    assert!(!bad.status.success());
+    assert_eq!(fs::read(&pck).unwrap(), original);
+}
+
+#[test]
+fn transfer_reassembles_only_verified_chunks() {
+    let dir = TestDir::new("transfer");
+    let pck = mini_game(dir.path());
+    let chunks = dir.path().join("usb");
+    let stdout = run_ok(&[
+        "export-transfer",
+        "-p",
+        pck.to_str().unwrap(),
+        "-o",
+        chunks.to_str().unwrap(),
+    ]);
+    assert!(stdout.contains("Wrote 1 chunk "), "{}", stdout);
+
+    let dest = dir.path().join("other/BackpackBattles.pck");
+    let part = chunks.join("BackpackBattles.pck.part0001");
+    let original = fs::read(&part).unwrap();
+    let mut damaged = original.clone();
+    damaged[100] ^= 0xff;
+    fs::write(&part, &damaged).unwrap();
+    let import = || {
+        run(&[
+            "import-transfer",
+            chunks.to_str().unwrap(),
+            "-p",
+            dest.to_str().unwrap(),
+        ])
+    };
+    let output = import();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("corrupted: BackpackBattles.pck.part0001")
+    );
+    assert!(!dest.exists());
+
+    fs::write(&part, &original).unwrap();
+    assert!(import().status.success());
+    assert_eq!(fs::read(&dest).unwrap(), fs::read(&pck).unwrap());
+    assert!(String::from_utf8_lossy(&import().stdout).contains("nothing written"));
+}
+
+#[test]
+fn extract_writes_entries_under_their_res_paths() {
+    let dir = TestDir::new("extract");
+    let pck = dir.path().join("Game.pck");
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://Core/Game.gde", ORIGINAL_GAME),
+            ("res://Scenes/Menu.tscn", MENU_SCENE),
+        ]),
+    )
+    .unwrap();
+    let out = dir.path().join("out");
+
+    let stdout = run_ok(&[
+        "extract",
+        "--pck",
+        pck.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+        "Core/Game.gde",
+    ]);
+    assert!(stdout.contains("Extracted 1 file"));
+    assert_eq!(fs::read(out.join("Core/Game.gde")).unwrap(), ORIGINAL_GAME);
+    assert!(!out.join("Scenes").exists());
+
+    run_ok(&[
+        "extract",
+        "--pck",
+        pck.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+    ]);
+    assert_eq!(fs::read(out.join("Scenes/Menu.tscn")).unwrap(), MENU_SCENE);
+
+    let missing = run(&[
+        "extract",
+        "--pck",
+        pck.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+        "res://missing.gd",
+    ]);
+    assert!(!missing.status.success());
+}
+
+#[test]
+fn glob_patterns_select_entries_to_list_and_delete() {
+    let dir = TestDir::new("glob");
+    let pck = mini_game(dir.path());
+    let pck_str = pck.to_str().unwrap();
+    assert_eq!(run_ok(&["list", "-p", pck_str, "**/*.tscn"]).trim(), "res://UI/Menu.tscn");
+    assert_eq!(
+        run_ok(&["list", "-p", pck_str, "res://Core/*", "Other/*.txt"])
+            .lines()
+            .collect::<Vec<_>>(),
+        ["res://Core/Game.gde", "res://Other/obsolete.txt"]
+    );
+
+    let mod_dir = copy_mini_mod(dir.path());
+    let manifest = fs::read_to_string(mod_dir.join("replace.toml")).unwrap();
+    let manifest = manifest.replace(
+        "\"res://Other/obsolete.txt\"",
+        "\"res://Other/*.txt\", \"**/*.tscn\"",
+    );
+    fs::write(mod_dir.join("replace.toml"), manifest).unwrap();
+    let args = ["apply", "--pck", pck_str, "--assets", mod_dir.to_str().unwrap()];
+    assert!(run_ok(&args).contains("**/*.tscn 匹配 1 个文件"));
+    let entries = list_entries(&pck);
+    let paths: Vec<&str> = entries.iter().map(|(p, _, _)| p.as_str()).collect();
+    assert_eq!(
+        paths,
+        ["res://Core/Game.gde", "res://Core/New.gd", "res://plugin_version.txt"]
+    );
+
+    // 再次应用时模式不再匹配任何文件，只给出提示
+    assert!(run_ok(&args).contains("没有匹配任何文件"));
+    assert_eq!(list_entries(&pck).len(), 3);
+}
+
+#[test]
+fn restore_refuses_a_truncated_backup() {
+    let dir = TestDir::new("restore");
+    let pck = dir.path().join("Game.pck");
+    let original = build_pck(&[("res://Core/Game.gde", ORIGINAL_GAME)]);
+    fs::write(dir.path().join("Game.pck.bak"), &original).unwrap();
+    fs::write(&pck, b"patched").unwrap();
+
+    run_ok(&["restore", "--pck", pck.to_str().unwrap()]);
+    assert_eq!(fs::read(&pck).unwrap(), original);
+
+    fs::write(&pck, b"patched").unwrap();
+    fs::write(
+        dir.path().join("Game.pck.bak"),
+        &original[..original.len() - 4],
+    )
+    .unwrap();
+    let output = run(&["restore", "--pck", pck.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("备份已损坏"));
+    assert_eq!(fs::read(&pck).unwrap(), b"patched");
+}
+
+#[test]
+fn apply_keeps_a_timestamped_backup_unless_disabled() {
+    let dir = TestDir::new("auto_backup");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let pck_str = pck.to_str().unwrap();
+    let mod_dir = fixture("mini_mod");
+    let backups = || {
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("BackpackBattles.pck.bak-") && !name.ends_with(".md5"))
+            .collect();
+        names.sort();
+        names
+    };
+
+    let out = run_ok(&["apply", "--pck", pck_str, "--assets", mod_dir.to_str().unwrap()]);
+    assert!(out.contains("Backed up"), "{}", out);
+    assert_eq!(backups().len(), 1);
+
+    let args = ["apply", "--pck", pck_str, "--assets", mod_dir.to_str().unwrap(), "--no-backup"];
+    assert!(!run_ok(&args).contains("Backed up"));
+    assert_eq!(backups().len(), 1);
+
+    let listed = run_ok(&["restore", "--pck", pck_str, "--list"]);
+    assert!(listed.contains(&backups()[0]), "{}", listed);
+    run_ok(&["restore", "--pck", pck_str]);
+    assert_eq!(fs::read(&pck).unwrap(), original);
+}
+
+#[test]
+fn apply_without_any_backup_needs_yes() {
+    let dir = TestDir::new("no_backup_yes");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let mod_dir = fixture("mini_mod");
+    let mut args = vec![
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mod_dir.to_str().unwrap(),
+        "--no-backup",
+    ];
+
+    let output = run(&args);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Verify integrity of game files"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+    assert_eq!(fs::read(&pck).unwrap(), original);
+
+    args.push("--yes");
+    assert!(run_ok(&args).contains("Successfully tweaked"));
+    assert_ne!(fs::read(&pck).unwrap(), original);
+}
+
+#[test]
+fn apply_labels_the_backup_of_a_pck_modified_elsewhere() {
+    let dir = TestDir::new("foreign");
+    let pck = mini_game(dir.path());
+    let pck_str = pck.to_str().unwrap();
+    fs::write(dir.path().join("override.cfg"), "[autoload]\n").unwrap();
+
+    let mod_dir = fixture("mini_mod");
+    let out = run_ok(&["apply", "--pck", pck_str, "--assets", mod_dir.to_str().unwrap()]);
+    assert!(out.contains("modified by another tool"), "{}", out);
+    assert!(out.contains("override.cfg"), "{}", out);
+    let listed = run_ok(&["restore", "--pck", pck_str, "--list"]);
+    assert!(listed.contains("(pre-existing modifications)"), "{}", listed);
+}
+
+#[test]
+fn tweak_pack_indexes_every_asset() {
+    let dir = TestDir::new("tweak_pack");
+    let mod_dir = copy_mini_mod(dir.path());
+
+    let out = run_ok(&["tweak-pack", mod_dir.to_str().unwrap(), "--version", "0.7.0"]);
+    assert!(out.contains("Indexed 3 files"), "{}", out);
+    let index = fs::read_to_string(mod_dir.join("tweak_pack.toml")).unwrap();
+    assert!(index.contains("version = \"0.7.0\""), "{}", index);
+    let game = fs::read(mod_dir.join("Core/Game.gde")).unwrap();
+    assert!(index.contains(&format!("\"Core/Game.gde\" = \"{}\"", md5_hex(&game))), "{}", index);
+
+    // 重新生成时不把索引自身收录进去
+    let out = run_ok(&["tweak-pack", mod_dir.to_str().unwrap(), "--version", "0.7.1"]);
+    assert!(out.contains("Indexed 3 files"), "{}", out);
+}
+
+#[test]
+fn merge_applies_a_mod_pck_on_top_of_the_game() {
+    let dir = TestDir::new("merge");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let overlay = dir.path().join("mod.pck");
+    fs::write(
+        &overlay,
+        build_pck(&[
+            ("res://UI/Menu.tscn", MENU_SCENE),
+            ("res://Core/Game.gde", b"modded"),
+            ("res://Mods/extra.gd", b"extends Node"),
+        ]),
+    )
+    .unwrap();
+    let merged = dir.path().join("merged.pck");
+
+    let out = run_ok(&[
+        "merge",
+        "-p",
+        pck.to_str().unwrap(),
+        overlay.to_str().unwrap(),
+        "-o",
+        merged.to_str().unwrap(),
+    ]);
+    assert!(out.contains("1 added, 1 replaced, 1 already identical"), "{}", out);
+    assert_eq!(fs::read(&pck).unwrap(), original);
+
+    let entries = list_entries(&merged);
+    let game = entries.iter().find(|(p, _, _)| p == "res://Core/Game.gde").unwrap();
+    assert_eq!(game.2, md5_hex(b"modded"));
+    assert!(entries.iter().any(|(p, _, _)| p == "res://Mods/extra.gd"));
+    assert!(entries.iter().any(|(p, _, _)| p == "res://Other/obsolete.txt"));
+    run_ok(&["verify", "-p", merged.to_str().unwrap()]);
+}
+
+#[test]
+fn repack_writes_a_clean_copy_with_the_same_contents() {
+    let dir = TestDir::new("repack");
+    let pck = mini_game(dir.path());
+    let mini_mod = fixture("mini_mod");
+    let apply = |output: &Path, extra: &[&str]| {
+        let mut args = vec![
+            "apply",
+            "--pck",
+            pck.to_str().unwrap(),
+            "--assets",
+            mini_mod.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        run_ok(&args)
+    };
+    let plain = dir.path().join("plain.pck");
+    let repacked = dir.path().join("repacked.pck");
+    apply(&plain, &[]);
+    let stdout = apply(&repacked, &["--repack"]);
+    assert!(stdout.contains("Repacked "), "{}", stdout);
+
+    assert_eq!(list_entries(&repacked), {
+        let mut entries = list_entries(&plain);
+        entries.sort();
+        entries
+    });
+    assert!(fs::metadata(&repacked).unwrap().len() < fs::metadata(&plain).unwrap().len());
+    run_ok(&["verify", "-p", repacked.to_str().unwrap()]);
+    assert!(!dir.path().join("repacked.pck.repack.partial").exists());
+
+    let in_place = run(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mini_mod.to_str().unwrap(),
+        "--repack",
+    ]);
+    assert!(!in_place.status.success());
+}
+
+/// The mini mod without additions or deletions, which override packs can't express.
+fn overlay_mod(dir: &Path) -> PathBuf {
+    let mod_dir = dir.join("overlay_mod");
+    fs::create_dir_all(mod_dir.join("Core")).unwrap();
+    fs::copy(
+        fixture("mini_mod/Core/Game.gde"),
+        mod_dir.join("Core/Game.gde"),
+    )
+    .unwrap();
+    fs::write(
+        mod_dir.join("replace.toml"),
+        fs::read_to_string(fixture("mini_mod/replace.toml"))
+            .unwrap()
+            .replace("\"res://Core/New.gd\" = \"Core/New.gd\"\n", "")
+            .replace("[delete]", "[stub]"),
+    )
+    .unwrap();
+    mod_dir
+}
+
+#[test]
+fn overlay_backend_leaves_game_pck_untouched() {
+    let dir = TestDir::new("overlay");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let mod_dir = overlay_mod(dir.path());
+
+    run_ok(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mod_dir.to_str().unwrap(),
+        "--backend",
+        "overlay",
+        "--priority",
+        "7",
+    ]);
+
+    assert_eq!(fs::read(&pck).unwrap(), original);
+    let overlay = dir.path().join("mods/00007_overlay_mod.pck");
+    let paths: Vec<String> = list_entries(&overlay)
+        .into_iter()
+        .map(|(p, _, _)| p)
+        .collect();
+    assert_eq!(
+        paths,
+        vec!["res://Core/Game.gde", "res://Other/obsolete.txt"]
+    );
+    assert!(
+        fs::read_to_string(dir.path().join("mods/load_order.cfg"))
+            .unwrap()
+            .contains("00007_overlay_mod.pck")
+    );
+}
+
+#[test]
+fn read_only_pck_falls_back_to_an_override_pack() {
+    let dir = TestDir::new("read_only");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let mod_dir = overlay_mod(dir.path());
+    let mut permissions = fs::metadata(&pck).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&pck, permissions.clone()).unwrap();
+
+    let apply = |extra: &[&str]| {
+        let mut args = vec![
+            "apply",
+            "--pck",
+            pck.to_str().unwrap(),
+            "--assets",
+            mod_dir.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        run(&args)
+    };
+    // Without a terminal to ask on, explain the problem and the ways out.
+    let refused = apply(&[]);
+    assert!(!refused.status.success());
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(
+        stderr.contains("the file is marked read-only"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--backend overlay"), "{}", stderr);
+    let safe_mode = apply(&["--safe-mode"]);
+    assert!(String::from_utf8_lossy(&safe_mode.stderr).contains("--safe-mode rules out"));
+
+    let output = apply(&["--yes"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read(&pck).unwrap(), original);
+    assert!(dir.path().join("mods/00100_overlay_mod.pck").is_file());
+
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    fs::set_permissions(&pck, permissions).unwrap();
+}
+
+#[test]
+fn lint_manifest_exit_codes() {
+    let dir = TestDir::new("lint");
+    run_ok(&["lint-manifest", fixture("mini_mod").to_str().unwrap()]);
+
+    let bad = dir.path().join("replace.toml");
+    fs::write(&bad, "[version]\nrequired-game-version = 1\n").unwrap();
+    let output = run(&["lint-manifest", bad.to_str().unwrap(), "--format", "json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"code\":\"invalid-type\""));
+}
+
+#[test]
+fn verify_detects_corrupted_entry() {
+    let dir = TestDir::new("verify");
+    let pck = mini_game(dir.path());
+    let pck_arg = pck.to_str().unwrap();
+    assert!(run_ok(&["verify", "-p", pck_arg, "--jobs", "4"]).contains("with 4 job(s)"));
+    assert!(run_ok(&["verify", "-p", pck_arg, "-j", "auto"]).contains("Checked 3 of 3"));
+    assert!(run_ok(&["verify", "-p", pck_arg, "--threads", "2"]).contains("with 2 job(s)"));
+    assert!(
+        !run(&["verify", "-p", pck_arg, "--jobs", "0"])
+            .status
+            .success()
+    );
+
+    let mut bytes = fs::read(&pck).unwrap();
+    let pos = bytes
+        .windows(ORIGINAL_GAME.len())
+        .position(|w| w == ORIGINAL_GAME)
+        .unwrap();
+    bytes[pos] ^= 0xff;
+    fs::write(&pck, bytes).unwrap();
+
+    let output = run(&["verify", "-p", pck_arg, "--fail-fast"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("FAILED res://Core/Game.gde"));
+
+    // 抽样时至少校验一个 entry
+    let output = run(&["verify", "-p", pck_arg, "--sample", "0.01%", "--seed", "1"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Checked 1 of 3"));
+}
+
+#[test]
+fn diff_export_round_trips_through_apply() {
+    let dir = TestDir::new("diff");
+    let old = mini_game(dir.path());
+    let new = dir.path().join("modded.pck");
+    fs::write(
+        &new,
+        build_pck(&[
+            ("res://Core/Game.gde", b"extends Node\n# modded\n"),
+            ("res://UI/Menu.tscn", MENU_SCENE),
+            ("res://UI/Copy.tscn", b"extends Node\n# modded\n"),
+        ]),
+    )
+    .unwrap();
+    let patchset = dir.path().join("patchset");
+
+    let stdout = run_ok(&[
+        "diff",
+        old.to_str().unwrap(),
+        new.to_str().unwrap(),
+        "--export",
+        patchset.to_str().unwrap(),
+        "--game-version",
+        "1.0.0",
+    ]);
+    assert!(stdout.contains("1 added, 1 changed, 1 removed"));
+    assert!(stdout.contains("1 deduplicated"));
+    assert!(!patchset.join("UI/Copy.tscn").exists());
+
+    run_ok(&[
+        "apply",
+        "--pck",
+        old.to_str().unwrap(),
+        "--assets",
+        patchset.to_str().unwrap(),
+    ]);
+    let stdout = run_ok(&["diff", old.to_str().unwrap(), new.to_str().unwrap()]);
+    assert!(stdout.contains("0 added, 0 changed, 1 removed"));
+    assert!(stdout.contains("- res://plugin_version.txt"));
+}
+
+#[test]
+fn rebase_relocates_moved_targets() {
+    let dir = TestDir::new("rebase");
+    let old = mini_game(dir.path());
+    let new = dir.path().join("new_build.pck");
+    fs::write(
+        &new,
+        build_pck(&[
+            ("res://Core/Game.gde", b"extends Node\n# new build\n"),
+            ("res://UI/Main/Menu.tscn", MENU_SCENE),
+            ("res://Other/obsolete.txt", b"old"),
+        ]),
+    )
+    .unwrap();
+    let mod_dir = dir.path().join("menu_mod");
+    fs::create_dir_all(&mod_dir).unwrap();
+    fs::write(
+        mod_dir.join("replace.toml"),
+        "[version]\nrequired-game-version = \"1.0.0\"\nplugin-version = \"0.1.0\"\n\n\
+         [version-hash]\n\"1.0.0\" = \"75715e94e1166e3e699e1b63f01b9488\"\n\n\
+         [replace]\n\"res://UI/Menu.tscn\" = \"Menu.tscn\"\n\"res://Core/Game.gde\" = \"Game.gde\"\n",
+    )
+    .unwrap();
+    fs::write(mod_dir.join("Menu.tscn"), b"[gd_scene format=2]\n").unwrap();
+    fs::write(mod_dir.join("Game.gde"), b"extends Node\n").unwrap();
+    let out = dir.path().join("rebased");
+
+    let stdout = run_ok(&[
+        "rebase",
+        mod_dir.to_str().unwrap(),
+        "--old",
+        old.to_str().unwrap(),
+        "--new",
+        new.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+        "--game-version",
+        "1.1.0",
+    ]);
+    assert!(stdout.contains("moved     res://UI/Menu.tscn -> res://UI/Main/Menu.tscn"));
+    assert!(stdout.contains("CHECK     res://Core/Game.gde"));
+
+    run_ok(&[
+        "apply",
+        "--pck",
+        new.to_str().unwrap(),
+        "--assets",
+        out.to_str().unwrap(),
+    ]);
+    let paths: Vec<String> = list_entries(&new).into_iter().map(|(p, _, _)| p).collect();
+    assert!(paths.contains(&"res://UI/Main/Menu.tscn".to_string()));
+    assert!(!paths.contains(&"res://UI/Menu.tscn".to_string()));
+}
+
+#[test]
+fn mapping_resolves_readable_manifest_paths() {
+    let dir = TestDir::new("mapping");
+    let pck = dir.path().join("BackpackBattles.pck");
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://x/3f9c2b1d7e.gde", ORIGINAL_GAME),
+            ("res://x/zq7w9k2vbn.tscn", MENU_SCENE),
+        ]),
+    )
+    .unwrap();
+    let map = dir.path().join("mapping.csv");
+    fs::write(
+        &map,
+        "obfuscated,logical\nres://x/3f9c2b1d7e.gde,res://Core/Game.gde\n",
+    )
+    .unwrap();
+    let mod_dir = fixture("mini_mod");
+
+    let output = run(&["list", "-p", pck.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("look obfuscated"));
+
+    run_ok(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mod_dir.to_str().unwrap(),
+        "--map",
+        map.to_str().unwrap(),
+    ]);
+
+    let listed = run_ok(&[
+        "list",
+        "-p",
+        pck.to_str().unwrap(),
+        "--map",
+        map.to_str().unwrap(),
+        "--filter",
+        "Core/",
+    ]);
+    assert_eq!(
+        listed
+            .lines()
+            .filter(|l| l.starts_with("res://"))
+            .collect::<Vec<_>>(),
+        vec!["res://Core/Game.gde", "res://Core/New.gd"]
+    );
+    let game = list_entries(&pck)
+        .into_iter()
+        .find(|(p, _, _)| p == "res://x/3f9c2b1d7e.gde")
+        .unwrap();
+    assert_eq!(
+        game.2,
+        md5_hex(&fs::read(mod_dir.join("Core/Game.gde")).unwrap())
+    );
+}
+
+#[test]
+fn remap_renames_entries_and_keeps_data() {
+    let dir = TestDir::new("remap");
+    let pck = dir.path().join("game.pck");
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://x/3f9c2b1d7e.gde", ORIGINAL_GAME),
+            ("res://x/zq7w9k2vbn.tscn", MENU_SCENE),
+        ]),
+    )
+    .unwrap();
+    let map = dir.path().join("mapping.csv");
+    fs::write(
+        &map,
+        "res://x/3f9c2b1d7e.gde,res://Core/Game.gde\n\
+         res://x/zq7w9k2vbn.tscn,res://UI/Menus/MainMenu.tscn\n",
+    )
+    .unwrap();
+    let pck_arg = pck.to_str().unwrap();
+
+    assert!(
+        run_ok(&["remap", "-p", pck_arg, "--map", map.to_str().unwrap()]).contains("Renamed 2")
+    );
+    assert_eq!(
+        list_entries(&pck),
+        vec![
+            (
+                "res://Core/Game.gde".to_string(),
+                ORIGINAL_GAME.len() as u64,
+                md5_hex(ORIGINAL_GAME)
+            ),
+            (
+                "res://UI/Menus/MainMenu.tscn".to_string(),
+                MENU_SCENE.len() as u64,
+                md5_hex(MENU_SCENE)
+            ),
+        ]
+    );
+    run_ok(&["verify", "-p", pck_arg]);
+
+    run_ok(&[
+        "remap",
+        "-p",
+        pck_arg,
+        "--map",
+        map.to_str().unwrap(),
+        "--reverse",
+    ]);
+    let paths: Vec<String> = list_entries(&pck).into_iter().map(|(p, _, _)| p).collect();
+    assert_eq!(
+        paths,
+        vec!["res://x/3f9c2b1d7e.gde", "res://x/zq7w9k2vbn.tscn"]
+    );
+}
+
+#[test]
+fn failed_apply_writes_explain_bundle() {
+    let dir = TestDir::new("explain");
+    let pck = dir.path().join("BackpackBattles.pck");
+    fs::write(
+        &pck,
+        build_pck(&[("res://Core/Game.gde", b"some other build")]),
+    )
+    .unwrap();
+    let bundle = dir.path().join("explain.zip");
+
+    let output = run(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        fixture("mini_mod").to_str().unwrap(),
+        "--explain",
+        bundle.to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    let zip = fs::read(&bundle).unwrap();
+    assert_eq!(&zip[..4], b"PK\x03\x04");
+    for name in [
+        "error.txt",
+        "environment.txt",
+        "layout.txt",
+        "table.hexdump.txt",
+        "replace.toml",
+    ] {
+        assert!(
+            zip.windows(name.len()).any(|w| w == name.as_bytes()),
+            "{} missing",
+            name
+        );
+    }
+}
+
+#[test]
+fn safe_mode_only_replaces_existing_entries() {
+    let dir = TestDir::new("safe_mode");
+    let pck = mini_game(dir.path());
+    let mod_dir = fixture("mini_mod");
+    let apply = |extra: &[&str]| {
+        let mut args = vec![
+            "apply",
+            "--pck",
+            pck.to_str().unwrap(),
+            "--assets",
+            mod_dir.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        run(&args)
+    };
+
+    // 首次应用需要新增 New.gd 和 plugin_version.txt、删除 obsolete.txt
+    let original = fs::read(&pck).unwrap();
+    let output = apply(&["--safe-mode"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("res://Core/New.gd"));
+    assert_eq!(fs::read(&pck).unwrap(), original);
+
+    // 注入过一次后，重新应用只会替换已有文件
+    assert!(apply(&[]).status.success());
+    let before = list_entries(&pck);
+    let output = apply(&["--safe-mode"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(list_entries(&pck), before);
+}
+
+#[test]
+fn apply_output_patches_a_copy() {
+    let dir = TestDir::new("apply_output");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let copy = dir.path().join("usb/Patched.pck");
+
+    run_ok(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        fixture("mini_mod").to_str().unwrap(),
+        "--output",
+        copy.to_str().unwrap(),
+    ]);
+
+    assert_eq!(fs::read(&pck).unwrap(), original);
+    let paths: Vec<String> = list_entries(&copy).into_iter().map(|(p, _, _)| p).collect();
+    assert!(paths.contains(&"res://plugin_version.txt".to_string()));
+
+    let output = run(&[
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        fixture("mini_mod").to_str().unwrap(),
+        "--output",
+        pck.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(fs::read(&pck).unwrap(), original);
+}
+
+#[test]
+fn identical_data_is_listed_and_shared() {
+    let dir = TestDir::new("identical");
+    let pck = dir.path().join("game.pck");
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://a/icon.png", b"same bytes"),
+            ("res://b/icon.png", b"same bytes"),
+            ("res://c.txt", b"different"),
+        ]),
+    )
+    .unwrap();
+    let pck_str = pck.to_str().unwrap();
+
+    let listed = run_ok(&["list", "-p", pck_str, "--duplicates"]);
+    assert!(listed.contains("res://b/icon.png\t[same data as res://a/icon.png]"));
+    assert!(listed.contains("res://c.txt\n"));
+
+    let before = list_entries(&pck);
+    let output = run_ok(&["repair", "-p", pck_str, "--share-identical-data"]);
+    assert!(output.contains("10 bytes of duplicate data"), "{}", output);
+    assert_eq!(list_entries(&pck), before);
+    run_ok(&["verify", "-p", pck_str]);
+    assert!(!run_ok(&["list", "-p", pck_str, "--duplicates"]).contains("same data"));
+}
+
+#[test]
+fn repair_drops_entries_cut_off_by_an_interrupted_write() {
+    let dir = TestDir::new("fix_table");
+    let pck = dir.path().join("game.pck");
+    let mut bytes = build_pck(&[("res://a.txt", b"kept"), ("res://b.txt", b"cut off")]);
+    bytes.truncate(bytes.len() - 3);
+    fs::write(&pck, &bytes).unwrap();
+    let pck_str = pck.to_str().unwrap();
+
+    let output = run_ok(&["repair", "-p", pck_str]);
+    assert!(output.contains("Unusable entry #1 \"res://b.txt\""), "{}", output);
+    assert!(output.contains("--fix-table"), "{}", output);
+    assert_eq!(fs::read(&pck).unwrap(), bytes);
+
+    let output = run_ok(&["repair", "-p", pck_str, "--fix-table"]);
+    assert!(output.contains("Rewrote the entry table with 1 of 2 entries"), "{}", output);
+    let entries = list_entries(&pck);
+    assert_eq!(entries.len(), 1);
+    assert_eq!((entries[0].0.as_str(), entries[0].1), ("res://a.txt", 4));
+    run_ok(&["verify", "-p", pck_str]);
+}
+
+#[test]
+fn list_shows_and_filters_content_types() {
+    let dir = TestDir::new("res_type");
+    let pck = dir.path().join("game.pck");
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://icon.bin", b"\x89PNG\r\n\x1a\n"),
+            ("res://music.ogg", b"OggS\0\x02"),
+            ("res://fr.translation", b"whatever"),
+            ("res://readme.txt", b"hello"),
+        ]),
+    )
+    .unwrap();
+    let pck_str = pck.to_str().unwrap();
+
+    let verbose = run_ok(&["list", "-p", pck_str, "--verbose"]);
+    let icon = verbose.lines().find(|l| l.starts_with("res://icon.bin")).unwrap();
+    assert_eq!(icon.split('\t').nth(3), Some("texture"), "{}", verbose);
+
+    let listed = run_ok(&["list", "-p", pck_str, "--type", "audio", "--type", "translation"]);
+    assert_eq!(listed, "res://fr.translation\nres://music.ogg\n");
+
+    let json = run_ok(&["list", "-p", pck_str, "--type", "other", "--format", "json"]);
+    assert!(json.starts_with("[{\"path\":\"res://readme.txt\""), "{}", json);
+    assert!(json.contains("\"type\":\"other\",\"size\":5,"), "{}", json);
+    assert!(!run(&["list", "-p", pck_str, "--type", "movie"]).status.success());
+}
+
+#[test]
+fn unmet_requirements_stop_apply_before_writing() {
+    let dir = TestDir::new("require");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+
+    let mod_dir = copy_mini_mod(dir.path());
+    let apply = |require: &str| {
+        extend_manifest(&mod_dir, &format!("[require]\n{}", require));
+        run(&[
+            "apply",
+            "--pck",
+            pck.to_str().unwrap(),
+            "--assets",
+            mod_dir.to_str().unwrap(),
+        ])
+    };
+
+    for unmet in [
+        "min_tool_version = \"99.0\"",
+        "free_space_mb = 999999999999",
+    ] {
+        let output = apply(unmet);
+        assert!(!output.status.success(), "{} should fail", unmet);
+        assert_eq!(fs::read(&pck).unwrap(), original);
+    }
+    let output = apply("platform = \"plan9\"");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("plan9"));
+
+    let met = format!(
+        "min_tool_version = \"0.1\"\nplatform = [\"{}\"]\nfree_space_mb = 1",
+        std::env::consts::OS
+    );
+    let output = apply(&met);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(list_entries(&pck).len(), 4);
+}
+
+#[test]
+fn portable_flag_is_accepted_by_subcommands() {
+    let dir = TestDir::new("portable");
+    let pck = mini_game(dir.path());
+    let pck = pck.to_str().unwrap();
+
+    let before = run_ok(&["list", "-p", pck]);
+    assert_eq!(run_ok(&["list", "--portable", "-p", pck]), before);
+}
+
+#[test]
+fn original_md5_guards_against_stacked_modifications() {
+    let dir = TestDir::new("original_md5");
+    let pck = mini_game(dir.path());
+    let mod_dir = copy_mini_mod(dir.path());
+    let apply = || {
+        run(&[
+            "apply",
+            "--pck",
+            pck.to_str().unwrap(),
+            "--assets",
+            mod_dir.to_str().unwrap(),
+        ])
+    };
+
+    // 其他工具改过的 Game.gde 与固定的 MD5 不符
+    let original = fs::read(&pck).unwrap();
+    extend_manifest(
+        &mod_dir,
+        &format!(
+            "[original-md5]\n\"res://Core/Game.gde\" = \"{}\"",
+            md5_hex(b"other")
+        ),
+    );
+    let output = apply();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&md5_hex(ORIGINAL_GAME)));
+    assert_eq!(fs::read(&pck).unwrap(), original);
+
+    // 固定为真正的原始 MD5 时可以应用，重复应用时当前内容已是本 MOD 写入的，同样通过
+    extend_manifest(
+        &mod_dir,
+        &format!(
+            "[original-md5]\n\"res://Core/Game.gde\" = \"{}\"",
+            md5_hex(ORIGINAL_GAME)
+        ),
+    );
+    for _ in 0..2 {
+        let output = apply();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn loose_backend_writes_only_changed_files_and_falls_back() {
+    let dir = TestDir::new("loose");
+    let pck = mini_game(dir.path());
+    let original = fs::read(&pck).unwrap();
+    let mod_dir = copy_mini_mod(dir.path());
+    let manifest = fs::read_to_string(mod_dir.join("replace.toml")).unwrap();
+    fs::write(
+        mod_dir.join("replace.toml"),
+        manifest.replace("[delete]", "[stub]"),
+    )
+    .unwrap();
+    let args = [
+        "apply",
+        "--pck",
+        pck.to_str().unwrap(),
+        "--assets",
+        mod_dir.to_str().unwrap(),
+        "--backend",
+        "loose",
+    ];
+
+    let loose = dir.path().join("res_override");
+    fs::create_dir(&loose).unwrap();
+    let stdout = run_ok(&args);
+    assert!(stdout.contains("3 changed resource(s)"), "{}", stdout);
+    assert_eq!(fs::read(&pck).unwrap(), original);
+    assert_eq!(
+        fs::read(loose.join("Core/New.gd")).unwrap(),
+        fs::read(mod_dir.join("Core/New.gd")).unwrap()
+    );
+    assert!(loose.join("Other/obsolete.txt").is_file());
+
+    fs::write(mod_dir.join("Core/New.gd"), "extends Node\n# edited\n").unwrap();
+    let stdout = run_ok(&args);
+    assert!(stdout.contains("updated   res://Core/New.gd"), "{}", stdout);
+    assert!(stdout.contains("1 changed resource(s)"), "{}", stdout);
+
+    // 没有 res_override 的发行版游戏退回到直接修改 PCK
+    fs::remove_dir_all(&loose).unwrap();
+    let with_watch: Vec<&str> = args.iter().copied().chain(["--watch"]).collect();
+    assert!(!run(&with_watch).status.success());
+    let stdout = run_ok(&args);
+    assert!(stdout.contains("falling back"), "{}", stdout);
+    assert_ne!(fs::read(&pck).unwrap(), original);
+}
+
+#[test]
+fn analyze_compression_ranks_uncompressed_entries() {
+    let dir = TestDir::new("analyze");
+    let pck = dir.path().join("big.pck");
+    let script = b"extends Node\nfunc _ready():\n\tprint(\"hello\")\n".repeat(4096);
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.resize(200_000, 7);
+    fs::write(
+        &pck,
+        build_pck(&[
+            ("res://Core/Big.gd", &script),
+            ("res://icon.png", &png),
+            ("res://small.txt", b"tiny"),
+        ]),
+    )
+    .unwrap();
+
+    let stdout = run_ok(&["analyze-compression", "-p", pck.to_str().unwrap()]);
+    let rows: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.ends_with("res://Core/Big.gd"))
+        .collect();
+    assert_eq!(rows.len(), 1, "{}", stdout);
+    assert!(rows[0].contains("\tscript\t"), "{}", stdout);
+    assert!(!stdout.contains("small.txt"), "{}", stdout);
+    assert!(
+        stdout.contains("1 already-compressed entry (0.2 MiB) skipped"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1 @@
+extends Reference